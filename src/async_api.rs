@@ -0,0 +1,391 @@
+//! Async install, update and delete built on tokio
+
+use crate::install::{
+    record_installation_results, split_satisfied, track_dependencies, TimedResult,
+};
+use crate::outcome::InstallReport;
+use crate::pip::{get_python_executable, pip_error, resolve_missing_versions};
+use crate::registry::PackageRegistry;
+use crate::remove::check_removal;
+use crate::reporter::success_mark;
+use crate::session::record_removal;
+use crate::spec::{parse_package_spec, prepare_package_specs, requirement_name};
+use crate::stats::{record_timing, Phase};
+use crate::update::record_update_results;
+use crate::{policy, PackageError, Result};
+use std::process::{Command, Output, Stdio};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Turns a panicked or cancelled tokio task into an error
+fn join_error(error: tokio::task::JoinError) -> PackageError {
+    PackageError::IoError(std::io::Error::other(error))
+}
+
+/// Runs `python -m pip` with the given arguments as a tokio child process
+///
+/// The child is killed if the returned future is dropped, so cancelling an
+/// operation does not leave pip running in the background.
+async fn run_pip_async(python: &str, label: &str, args: &[String], phase: Phase) -> Result<Output> {
+    let mut command = Command::new(python);
+    command.arg("-m").arg("pip").args(args);
+    policy::check_pip_command(&command)?;
+    debug_detail!("[{}] running {} -m pip {}", label, python, args.join(" "));
+    let started = Instant::now();
+    let output = tokio::process::Command::new(python)
+        .arg("-m")
+        .arg("pip")
+        .args(args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    record_timing(label, phase, started);
+    tracing::info!(
+        package = label,
+        status = %output.status,
+        duration_ms = started.elapsed().as_millis() as u64,
+        "pip finished"
+    );
+    Ok(output)
+}
+
+/// Runs a blocking registry operation, such as an interpreter lookup, on tokio's blocking pool
+///
+/// The operation works on a copy of the registry that replaces the original
+/// once it returns, so the caller's registry is untouched if it panics.
+async fn with_registry_blocking<T: Send + 'static>(
+    registry: &mut PackageRegistry,
+    operation: impl FnOnce(&mut PackageRegistry) -> T + Send + 'static,
+) -> Result<T> {
+    let mut copy = registry.clone();
+    let (copy, value) = tokio::task::spawn_blocking(move || {
+        let value = operation(&mut copy);
+        (copy, value)
+    })
+    .await
+    .map_err(join_error)?;
+    *registry = copy;
+    Ok(value)
+}
+
+/// Runs one pip invocation per item with at most `jobs` of them at a time
+///
+/// # Returns
+/// The output of each invocation, or the error starting it, in the order of `items`
+async fn run_pip_bounded(
+    python: &str,
+    items: Vec<(String, Vec<String>)>,
+    phase: Phase,
+    jobs: usize,
+) -> Result<Vec<(Result<Output>, Duration)>> {
+    let limit = Arc::new(tokio::sync::Semaphore::new(jobs.max(1)));
+    let mut tasks = tokio::task::JoinSet::new();
+    for (index, (label, args)) in items.into_iter().enumerate() {
+        let python = python.to_string();
+        let limit = Arc::clone(&limit);
+        tasks.spawn(async move {
+            let _permit = limit.acquire_owned().await;
+            let started = Instant::now();
+            let output = run_pip_async(&python, &label, &args, phase).await;
+            (index, output, started.elapsed())
+        });
+    }
+
+    let mut finished = Vec::with_capacity(tasks.len());
+    while let Some(joined) = tasks.join_next().await {
+        finished.push(joined.map_err(join_error)?);
+    }
+    finished.sort_by_key(|(index, _, _)| *index);
+    Ok(finished
+        .into_iter()
+        .map(|(_, output, elapsed)| (output, elapsed))
+        .collect())
+}
+
+/// Installs packages without blocking the calling thread
+///
+/// The asynchronous counterpart of [`install_packages_parallel`](crate::install_packages_parallel): packages the
+/// environment already satisfies are skipped, the rest are downloaded with at
+/// most `jobs` pip processes at a time and then installed one at a time from
+/// the downloads.
+///
+/// # Arguments
+/// * `packages` - Package specifications to install
+/// * `jobs` - Maximum number of concurrent downloads (at least one)
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if
+///   nothing could be attempted
+pub async fn install_packages_async(
+    packages: &[String],
+    jobs: usize,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let mut report = InstallReport::new("Installation summary", "install");
+    if packages.is_empty() {
+        return Ok(report);
+    }
+
+    let python = get_python_executable()?;
+    let specs = prepare_package_specs(packages)?;
+    let (satisfied, specs) = {
+        let python = python.clone();
+        with_registry_blocking(registry, move |registry| {
+            split_satisfied(&python, &specs, registry)
+        })
+        .await?
+    };
+
+    // Each call gets a private wheelhouse of its own
+    let wheelhouse = tempfile::Builder::new()
+        .prefix("ppm-wheelhouse-")
+        .tempdir()?;
+    let downloads: Vec<(String, Vec<String>)> = specs
+        .iter()
+        .enumerate()
+        .map(|(index, spec)| {
+            let dir = wheelhouse.path().join(index.to_string());
+            let dir = dir.display().to_string();
+            let args = ["download", "--dest", dir.as_str(), spec.as_str()];
+            (
+                requirement_name(spec).to_string(),
+                args.map(String::from).to_vec(),
+            )
+        })
+        .collect();
+    let downloaded = run_pip_bounded(&python, downloads, Phase::Download, jobs).await?;
+
+    // Install from the wheelhouse one package at a time
+    let mut results: Vec<TimedResult> = Vec::with_capacity(specs.len());
+    for (index, (spec, (download, download_time))) in specs.iter().zip(downloaded).enumerate() {
+        let (name, version) = parse_package_spec(spec)?;
+        let download = download.and_then(|output| {
+            if output.status.success() {
+                return Ok(());
+            }
+            Err(pip_error(&name, &output, |stderr| {
+                PackageError::InstallationFailed(format!("Failed to download {}: {}", name, stderr))
+            }))
+        });
+        let started = Instant::now();
+        let result = match download {
+            Ok(()) => {
+                let dir = wheelhouse.path().join(index.to_string());
+                let dir = dir.display().to_string();
+                let args = ["install", spec.as_str(), "--find-links", dir.as_str()];
+                let args: Vec<String> = args.map(String::from).to_vec();
+                run_pip_async(&python, &name, &args, Phase::Install)
+                    .await
+                    .and_then(|output| {
+                        if output.status.success() {
+                            return Ok((name.clone(), version.unwrap_or_default()));
+                        }
+                        Err(pip_error(&name, &output, |stderr| {
+                            PackageError::InstallationFailed(format!(
+                                "Failed to install {}: {}",
+                                name, stderr
+                            ))
+                        }))
+                    })
+            }
+            Err(e) => Err(e),
+        };
+        results.push((spec.clone(), result, download_time + started.elapsed()));
+    }
+    let _ = tokio::fs::remove_dir_all(wheelhouse.path()).await;
+
+    report.outcomes = satisfied;
+    with_registry_blocking(registry, move |registry| {
+        resolve_missing_versions(&python, &mut results);
+        report
+            .outcomes
+            .extend(record_installation_results(results, registry));
+        let installed: Vec<String> = report
+            .outcomes
+            .iter()
+            .filter(|o| o.new_version.is_some() && !o.satisfied)
+            .map(|o| o.name.clone())
+            .collect();
+        report
+            .warnings
+            .extend(track_dependencies(&python, &installed, registry));
+        report
+    })
+    .await
+}
+
+/// Updates packages to specific versions without blocking the calling thread
+///
+/// The asynchronous counterpart of [`update_packages_parallel`](crate::update_packages_parallel), running at
+/// most `jobs` pip upgrades at a time.
+///
+/// # Arguments
+/// * `updates` - Pairs of package name and target version
+/// * `jobs` - Maximum number of concurrent pip processes (at least one)
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every update, or an error if
+///   nothing could be attempted
+pub async fn update_packages_async(
+    updates: &[(String, String)],
+    jobs: usize,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let mut report = InstallReport::new("Update summary", "update");
+    if updates.is_empty() {
+        return Ok(report);
+    }
+
+    let python = get_python_executable()?;
+    let runs: Vec<(String, Vec<String>)> = updates
+        .iter()
+        .map(|(name, version)| {
+            let spec = format!("{}=={}", name, version);
+            let args = ["install".to_string(), "--upgrade".to_string(), spec];
+            (name.clone(), args.to_vec())
+        })
+        .collect();
+    let finished = run_pip_bounded(&python, runs, Phase::Update, jobs).await?;
+
+    let results: Vec<TimedResult> = updates
+        .iter()
+        .zip(finished)
+        .map(|((name, version), (output, elapsed))| {
+            let result = output.and_then(|output| {
+                if output.status.success() {
+                    // pip succeeded on an exact pin, so that is the installed version
+                    return Ok((name.clone(), version.clone()));
+                }
+                Err(pip_error(name, &output, |stderr| {
+                    PackageError::InstallationFailed(format!(
+                        "Failed to update {}: {}",
+                        name, stderr
+                    ))
+                }))
+            });
+            (format!("{}=={}", name, version), result, elapsed)
+        })
+        .collect();
+
+    let updates = updates.to_vec();
+    with_registry_blocking(registry, move |registry| {
+        report.outcomes = record_update_results(&updates, results, registry);
+        let updated: Vec<String> = report
+            .outcomes
+            .iter()
+            .filter(|o| o.new_version.is_some())
+            .map(|o| o.name.clone())
+            .collect();
+        report
+            .warnings
+            .extend(track_dependencies(&python, &updated, registry));
+        report
+    })
+    .await
+}
+
+/// Uninstalls packages with a single pip process without blocking the calling thread
+///
+/// Like [`delete_packages`](crate::delete_packages) with [`DependentsPolicy::Refuse`](crate::DependentsPolicy::Refuse), the deletion is
+/// refused while packages outside the batch depend on a member of it.
+///
+/// # Arguments
+/// * `names` - Names of the packages to delete
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, a [`PackageError::RequiredByOthers`] refusal, or deletion error
+pub async fn delete_packages_async(names: &[String], registry: &mut PackageRegistry) -> Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
+    if names.iter().any(|name| name.trim().is_empty()) {
+        return Err(PackageError::InvalidPackageSpec(
+            "Package name cannot be empty".to_string(),
+        ));
+    }
+    check_removal(names, registry)?;
+
+    let python = get_python_executable()?;
+    let label = names.join(" ");
+    let mut args = vec!["uninstall".to_string(), "-y".to_string()];
+    args.extend(names.iter().cloned());
+    let output = run_pip_async(&python, &label, &args, Phase::Uninstall).await?;
+    if !output.status.success() {
+        return Err(pip_error(
+            &label,
+            &output,
+            PackageError::UninstallationFailed,
+        ));
+    }
+
+    for name in names {
+        let removed = registry
+            .find_package_key(name)
+            .and_then(|key| registry.remove_package(&key));
+        record_removal(name, removed.map(|p| p.version));
+    }
+    status!("{} Successfully removed {}", success_mark(), label);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pip::{set_command_runner, ScriptedRunner, COMMAND_RUNNER};
+    use crate::registry::Package;
+    use crate::test_support::RUNNER_LOCK;
+
+    #[test]
+    fn test_async_api() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let mut registry = PackageRegistry::new();
+        let mut flask = Package::new("flask".to_string(), "3.0.2".to_string());
+        flask.dependencies = vec!["werkzeug".to_string()];
+        registry.add_package(flask);
+        registry.add_package(Package::new("werkzeug".to_string(), "3.0.1".to_string()));
+
+        // The scripted interpreter does not exist, so the pip processes fail to start
+        let runner =
+            ScriptedRunner::new().respond("print(sys.executable)", "/nonexistent/python\n");
+        let _guard = RUNNER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        set_command_runner(Arc::new(runner));
+        runtime.block_on(async {
+            let none = install_packages_async(&[], 4, &mut registry).await.unwrap();
+            assert!(none.outcomes.is_empty());
+
+            // Failures to run pip are reported per package
+            let specs = ["numpy==1.26.4".to_string(), "requests".to_string()];
+            let outcomes = install_packages_async(&specs, 4, &mut registry)
+                .await
+                .unwrap()
+                .outcomes;
+            let names: Vec<&str> = outcomes.iter().map(|o| o.name.as_str()).collect();
+            assert_eq!(names, ["numpy", "requests"]);
+            assert!(outcomes.iter().all(|o| o.status() == "failed"));
+
+            let updates = [("flask".to_string(), "3.1.0".to_string())];
+            let outcomes = update_packages_async(&updates, 2, &mut registry)
+                .await
+                .unwrap()
+                .outcomes;
+            assert_eq!(outcomes[0].spec, "flask==3.1.0");
+            assert_eq!(outcomes[0].previous_version.as_deref(), Some("3.0.2"));
+            assert!(outcomes[0].error.is_some());
+
+            // Dependents are checked before any process is started
+            let err = delete_packages_async(&["werkzeug".to_string()], &mut registry)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, PackageError::RequiredByOthers(_, ref d) if d == &["flask"]));
+        });
+        *COMMAND_RUNNER.lock().unwrap() = None;
+        assert_eq!(registry.packages.len(), 2);
+    }
+}
@@ -0,0 +1,547 @@
+//! Verification of the PEP 740 attestations PyPI publishes for distributions
+//!
+//! Files uploaded through Trusted Publishing come with provenance that PyPI
+//! serves from its integrity API: sigstore-signed attestations binding the
+//! file's sha256 to the workflow that built it. ppm fetches the provenance
+//! itself and has the `pypi-attestations` package in the target interpreter
+//! check the signatures, so nothing cryptographic is reimplemented here.
+
+use crate::http::{http_client, HttpRequest};
+use crate::install::install_hash_pinned;
+use crate::pip::{
+    get_python_executable, pip_error, request_pip_report, require_pip_feature, run_command,
+    run_pip, take_pip_report, PipFeature, ReportedInstall,
+};
+use crate::registry::PackageRegistry;
+use crate::spec::canonicalize_name;
+use crate::{success_mark, HttpFailure, InstallReport, PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::Write;
+use std::process::Command;
+
+/// Base URL of PyPI's integrity API
+pub const PYPI_INTEGRITY_URL: &str = "https://pypi.org/integrity";
+
+/// Host PyPI serves distribution files from
+const PYPI_FILES_HOST: &str = "files.pythonhosted.org";
+
+/// Python snippet that verifies provenance with the `pypi-attestations` package
+///
+/// Takes one JSON object per argument with the file name, its sha256 and the
+/// provenance document, and writes one JSON line per file with the publishers
+/// whose attestations all verified, or the error. Writes a single
+/// `unavailable` line when the package is not installed.
+const ATTESTATION_SCRIPT: &str = r#"
+import json, sys
+try:
+    from pypi_attestations import Distribution, Provenance
+except ImportError:
+    print(json.dumps({"unavailable": "pypi-attestations is not installed"}))
+    sys.exit(0)
+for argument in sys.argv[1:]:
+    artifact = json.loads(argument)
+    try:
+        provenance = Provenance.model_validate_json(artifact["provenance"])
+        dist = Distribution(name=artifact["filename"], digest=artifact["sha256"])
+        publishers = []
+        for bundle in provenance.attestation_bundles:
+            for attestation in bundle.attestations:
+                attestation.verify(bundle.publisher, dist)
+            publishers.append(bundle.publisher.model_dump(mode="json"))
+        if publishers:
+            print(json.dumps({"publishers": publishers}))
+        else:
+            print(json.dumps({"error": "the provenance holds no attestations"}))
+    except Exception as error:
+        print(json.dumps({"error": "%s: %s" % (type(error).__name__, error)}))
+"#;
+
+/// Identity of the CI workflow that published a distribution
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Publisher {
+    /// Trusted Publishing provider, e.g. "GitHub" or "GitLab"
+    pub kind: String,
+    /// Repository the workflow ran in, e.g. "pypa/sampleproject"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    /// Workflow file that built and uploaded the distribution
+    #[serde(
+        default,
+        alias = "workflow_filepath",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub workflow: Option<String>,
+    /// Deployment environment of the workflow, if it used one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<String>,
+}
+
+impl fmt::Display for Publisher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(repository) = &self.repository {
+            write!(f, " {}", repository)?;
+        }
+        if let Some(workflow) = &self.workflow {
+            write!(f, " ({})", workflow)?;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of verifying the attestations of one distribution file
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum AttestationStatus {
+    /// Every attestation verified against the identity of its publisher
+    Verified { publishers: Vec<Publisher> },
+    /// The file could not be verified, e.g. because it has no attestations
+    Unavailable { reason: String },
+    /// An attestation did not verify
+    Failed { reason: String },
+}
+
+/// The attestation check of a distribution pip would download
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AttestationCheck {
+    /// Package name
+    pub name: String,
+    /// Version pip resolved
+    pub version: String,
+    /// File name of the wheel or sdist
+    pub filename: String,
+    /// sha256 of the file, as reported by the index
+    #[serde(skip)]
+    pub sha256: Option<String>,
+    /// The outcome
+    #[serde(flatten)]
+    pub status: AttestationStatus,
+}
+
+impl AttestationCheck {
+    /// Whether the distribution's attestations verified
+    pub fn is_verified(&self) -> bool {
+        matches!(self.status, AttestationStatus::Verified { .. })
+    }
+}
+
+impl fmt::Display for AttestationCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} ({}): ", self.name, self.version, self.filename)?;
+        match &self.status {
+            AttestationStatus::Verified { publishers } => {
+                let publishers: Vec<String> = publishers.iter().map(|p| p.to_string()).collect();
+                write!(f, "published by {}", publishers.join(", "))
+            }
+            AttestationStatus::Unavailable { reason } => write!(f, "not verifiable, {}", reason),
+            AttestationStatus::Failed { reason } => write!(f, "verification failed, {}", reason),
+        }
+    }
+}
+
+/// URL of the provenance PyPI publishes for a distribution file
+///
+/// # Arguments
+/// * `name` - Package name
+/// * `version` - Release version
+/// * `filename` - File name of the wheel or sdist
+pub fn provenance_url(name: &str, version: &str, filename: &str) -> String {
+    format!(
+        "{}/{}/{}/{}/provenance",
+        PYPI_INTEGRITY_URL,
+        canonicalize_name(name),
+        version,
+        filename
+    )
+}
+
+/// Resolves packages and verifies the attestations of every file pip would download
+///
+/// Packages that are already installed are not downloaded and so not checked.
+///
+/// # Arguments
+/// * `specs` - Requirement specs to resolve
+/// * `pip_args` - Extra arguments for pip, such as `--index-url`
+///
+/// # Returns
+/// * `Result<Vec<AttestationCheck>>` - One check per file, or an error if pip
+///   could not resolve the packages
+pub fn check_attestations(specs: &[String], pip_args: &[String]) -> Result<Vec<AttestationCheck>> {
+    let python = get_python_executable()?;
+    require_pip_feature(&python, PipFeature::InstallReport, "Verifying attestations")?;
+    let mut command = Command::new(&python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("install")
+        .arg("--dry-run")
+        .arg("--quiet")
+        .args(specs)
+        .args(pip_args);
+    let report = request_pip_report(&python, &mut command);
+    let output = run_pip(command, "attestations", None)?;
+    let resolved = take_pip_report(report);
+    if !output.status.success() {
+        return Err(pip_error(
+            &specs.join(" "),
+            &output,
+            PackageError::InstallationFailed,
+        ));
+    }
+
+    let mut checks: Vec<AttestationCheck> = resolved.iter().map(unchecked).collect();
+    let mut requests: Vec<(usize, HttpRequest)> = Vec::new();
+    for (index, (check, install)) in checks.iter_mut().zip(&resolved).enumerate() {
+        let url = install
+            .origin
+            .as_ref()
+            .map_or("", |origin| origin.url.as_str());
+        let host = url
+            .split("://")
+            .nth(1)
+            .and_then(|rest| rest.split('/').next());
+        let reason = if host != Some(PYPI_FILES_HOST) {
+            let source = match host {
+                Some(host) if !host.is_empty() => host,
+                _ => url.rsplit_once('/').map_or(url, |(directory, _)| directory),
+            };
+            format!("it comes from {}, not PyPI", source)
+        } else if check.sha256.is_none() {
+            "the index reported no sha256 for it".to_string()
+        } else {
+            let url = provenance_url(&check.name, &check.version, &check.filename);
+            requests.push((index, HttpRequest::get(url)));
+            continue;
+        };
+        check.status = AttestationStatus::Unavailable { reason };
+    }
+    if requests.is_empty() {
+        return Ok(checks);
+    }
+
+    let batch: Vec<HttpRequest> = requests
+        .iter()
+        .map(|(_, request)| request.clone())
+        .collect();
+    let responses = http_client().get_all(&python, &batch)?;
+    let mut verify = Vec::new();
+    for ((index, _), response) in requests.iter().zip(responses) {
+        let check = &mut checks[*index];
+        match response {
+            Ok(response) => verify.push((*index, response.body)),
+            Err(PackageError::HttpStatus(HttpFailure { status: 404, .. })) => {
+                check.status = AttestationStatus::Unavailable {
+                    reason: "no attestations are published for it".to_string(),
+                };
+            }
+            Err(e) => {
+                check.status = AttestationStatus::Unavailable {
+                    reason: format!("its provenance could not be fetched: {}", e),
+                };
+            }
+        }
+    }
+    if verify.is_empty() {
+        return Ok(checks);
+    }
+
+    let mut command = Command::new(&python);
+    command.arg("-c").arg(ATTESTATION_SCRIPT);
+    for (index, provenance) in &verify {
+        let check = &checks[*index];
+        command.arg(serde_json::to_string(&serde_json::json!({
+            "filename": check.filename,
+            "sha256": check.sha256,
+            "provenance": provenance,
+        }))?);
+    }
+    let output = run_command(&mut command)?;
+    let statuses = verification_statuses(
+        output.status.success(),
+        &String::from_utf8_lossy(&output.stdout),
+        &String::from_utf8_lossy(&output.stderr),
+        verify.len(),
+        &python,
+    );
+    for ((index, _), status) in verify.iter().zip(statuses) {
+        checks[*index].status = status;
+    }
+    Ok(checks)
+}
+
+/// Turns the output of [`ATTESTATION_SCRIPT`] into one status per file
+///
+/// The verifier must exit successfully and write exactly one line per file,
+/// in order; the only exception is the single `unavailable` line written when
+/// `pypi-attestations` is missing, which applies to every file. Anything else
+/// fails every file, so a verifier that dies half-way never passes a file it
+/// did not check.
+///
+/// # Arguments
+/// * `success` - Whether the verifier exited successfully
+/// * `stdout` - What it wrote to stdout
+/// * `stderr` - What it wrote to stderr
+/// * `count` - Number of files it was given
+/// * `python` - The interpreter it ran in, named in the hint to install the package
+fn verification_statuses(
+    success: bool,
+    stdout: &str,
+    stderr: &str,
+    count: usize,
+    python: &str,
+) -> Vec<AttestationStatus> {
+    let lines: Vec<&str> = stdout.lines().filter(|l| !l.trim().is_empty()).collect();
+    if let [line] = lines.as_slice() {
+        let status = parse_verification(line, python);
+        if success && matches!(status, AttestationStatus::Unavailable { .. }) {
+            return vec![status; count];
+        }
+    }
+    let reason = if !success {
+        let detail = stderr.trim().lines().last().unwrap_or("no error output");
+        Some(format!("the verifier failed: {}", detail))
+    } else if lines.len() != count {
+        Some(format!(
+            "the verifier gave {} result(s) for {} file(s)",
+            lines.len(),
+            count
+        ))
+    } else {
+        None
+    };
+    match reason {
+        Some(reason) => vec![AttestationStatus::Failed { reason }; count],
+        None => lines
+            .iter()
+            .map(|line| parse_verification(line, python))
+            .collect(),
+    }
+}
+
+/// Fails unless every checked distribution verified
+///
+/// # Arguments
+/// * `checks` - The result of [`check_attestations`]
+///
+/// # Returns
+/// * `Result<()>` - Success, or [`PackageError::PolicyViolation`] listing the
+///   files that could not be verified
+pub fn require_attestations(checks: &[AttestationCheck]) -> Result<()> {
+    let unverified: Vec<String> = checks
+        .iter()
+        .filter(|check| !check.is_verified())
+        .map(|check| check.to_string())
+        .collect();
+    if unverified.is_empty() {
+        return Ok(());
+    }
+    Err(PackageError::PolicyViolation(format!(
+        "{} of {} distribution(s) could not be verified: {}",
+        unverified.len(),
+        checks.len(),
+        unverified.join("; ")
+    )))
+}
+
+/// Installs packages after verifying the attestations of every file pip downloads
+///
+/// The verified files are then installed in `--require-hashes` mode with their
+/// sha256, so pip cannot pick up an archive other than the one verified.
+///
+/// # Arguments
+/// * `packages` - Requirement specs to install
+/// * `pip_args` - Extra arguments for pip
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or
+///   [`PackageError::PolicyViolation`] if any file could not be verified
+pub(crate) fn install_attested(
+    packages: &[String],
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let checks = check_attestations(packages, pip_args)?;
+    report_attestations(&checks);
+    require_attestations(&checks)?;
+    if checks.is_empty() {
+        status!("Nothing to install; every requirement is already satisfied");
+        return Ok(InstallReport::new("Installation summary", "install"));
+    }
+
+    let mut requirements = String::new();
+    for check in &checks {
+        requirements.push_str(&format!(
+            "{}=={} --hash=sha256:{}\n",
+            check.name,
+            check.version,
+            check.sha256.as_deref().unwrap_or_default()
+        ));
+    }
+    // Created with O_EXCL and mode 0600, so nobody else can swap what pip installs
+    let mut file = tempfile::Builder::new()
+        .prefix("ppm-attested-")
+        .suffix(".txt")
+        .tempfile()?;
+    file.write_all(requirements.as_bytes())?;
+    file.flush()?;
+    let mut pip_args = pip_args.to_vec();
+    pip_args.push("--no-deps".to_string());
+    install_hash_pinned(file.path(), packages, &pip_args, registry)
+}
+
+/// Prints the outcome of every attestation check
+pub fn report_attestations(checks: &[AttestationCheck]) {
+    for check in checks {
+        if check.is_verified() {
+            status!("{} {}", success_mark(), check);
+        } else {
+            warning!("{}", check);
+        }
+    }
+}
+
+/// The check of a file from pip's report, before its provenance is fetched
+fn unchecked(install: &ReportedInstall) -> AttestationCheck {
+    let url = install
+        .origin
+        .as_ref()
+        .map_or("", |origin| origin.url.as_str());
+    let location = url.split(['#', '?']).next().unwrap_or(url);
+    let sha256 = install
+        .origin
+        .as_ref()
+        .and_then(|origin| origin.hash.as_deref())
+        .and_then(|hash| hash.strip_prefix("sha256="))
+        .map(str::to_string);
+    AttestationCheck {
+        name: install.name.clone(),
+        version: install.version.clone(),
+        filename: location.rsplit('/').next().unwrap_or(location).to_string(),
+        sha256,
+        status: AttestationStatus::Unavailable {
+            reason: "its provenance was not checked".to_string(),
+        },
+    }
+}
+
+/// One line written by [`ATTESTATION_SCRIPT`]
+#[derive(Debug, Deserialize)]
+struct VerificationLine {
+    #[serde(default)]
+    publishers: Option<Vec<Publisher>>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    unavailable: Option<String>,
+}
+
+/// Turns a line written by [`ATTESTATION_SCRIPT`] into the status of a file
+fn parse_verification(line: &str, python: &str) -> AttestationStatus {
+    match serde_json::from_str::<VerificationLine>(line) {
+        Ok(VerificationLine {
+            publishers: Some(publishers),
+            ..
+        }) => AttestationStatus::Verified { publishers },
+        Ok(VerificationLine {
+            error: Some(reason),
+            ..
+        }) => AttestationStatus::Failed { reason },
+        Ok(VerificationLine {
+            unavailable: Some(reason),
+            ..
+        }) => AttestationStatus::Unavailable {
+            reason: format!("{} for {} (pip install pypi-attestations)", reason, python),
+        },
+        _ => AttestationStatus::Unavailable {
+            reason: "the verifier gave no result".to_string(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_verification() {
+        let line = r#"{"publishers": [{"kind": "GitHub", "repository": "pypa/sampleproject",
+            "workflow": "release.yml", "environment": null, "claims": null}]}"#;
+        let check = AttestationCheck {
+            name: "sampleproject".to_string(),
+            version: "4.0.0".to_string(),
+            filename: "sampleproject-4.0.0-py3-none-any.whl".to_string(),
+            sha256: Some("abcd".to_string()),
+            status: parse_verification(&line.replace('\n', " "), "python3"),
+        };
+        assert!(check.is_verified());
+        assert_eq!(
+            check.to_string(),
+            "sampleproject 4.0.0 (sampleproject-4.0.0-py3-none-any.whl): \
+             published by GitHub pypa/sampleproject (release.yml)"
+        );
+        assert_eq!(serde_json::to_value(&check).unwrap()["status"], "verified");
+
+        let missing = AttestationCheck {
+            status: parse_verification(
+                r#"{"unavailable": "pypi-attestations is not installed"}"#,
+                "python3",
+            ),
+            ..check.clone()
+        };
+        let failed = AttestationCheck {
+            status: parse_verification(
+                r#"{"error": "VerificationError: bad signature"}"#,
+                "python3",
+            ),
+            ..check.clone()
+        };
+        assert!(matches!(failed.status, AttestationStatus::Failed { .. }));
+        assert!(require_attestations(std::slice::from_ref(&check)).is_ok());
+        let error = require_attestations(&[check, missing, failed]).unwrap_err();
+        assert_eq!(error.code(), "PPM0016");
+        assert!(error
+            .to_string()
+            .contains("2 of 3 distribution(s) could not be verified"));
+        assert!(error
+            .to_string()
+            .contains("pypi-attestations is not installed for python3"));
+    }
+
+    #[test]
+    fn test_verification_statuses() {
+        let verified = r#"{"publishers": [{"kind": "GitHub"}]}"#;
+        let failed = |statuses: &[AttestationStatus]| {
+            statuses
+                .iter()
+                .all(|status| matches!(status, AttestationStatus::Failed { .. }))
+        };
+
+        let statuses = verification_statuses(true, verified, "", 3, "python3");
+        assert_eq!(statuses.len(), 3);
+        assert!(failed(&statuses));
+        let both = format!("{}\n{}\n", verified, verified);
+        let statuses = verification_statuses(false, &both, "Killed", 2, "python3");
+        assert!(failed(&statuses));
+        let statuses = verification_statuses(true, &both, "", 2, "python3");
+        assert!(statuses
+            .iter()
+            .all(|status| matches!(status, AttestationStatus::Verified { .. })));
+
+        let missing = r#"{"unavailable": "pypi-attestations is not installed"}"#;
+        let statuses = verification_statuses(true, missing, "", 2, "python3");
+        assert_eq!(statuses.len(), 2);
+        assert!(statuses
+            .iter()
+            .all(|status| matches!(status, AttestationStatus::Unavailable { .. })));
+    }
+
+    #[test]
+    fn test_provenance_url() {
+        assert_eq!(
+            provenance_url("Sample_Project", "4.0.0", "sample_project-4.0.0.tar.gz"),
+            "https://pypi.org/integrity/sample-project/4.0.0/sample_project-4.0.0.tar.gz/provenance"
+        );
+    }
+}
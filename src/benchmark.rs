@@ -0,0 +1,341 @@
+//! Timing the install strategies against each other
+//!
+//! `ppm benchmark` installs the same packages once per strategy, each time
+//! into a fresh throwaway virtual environment, so users can pick the mode that
+//! suits their workload. An untimed install runs first to fill pip's cache;
+//! without it, whichever strategy went first would pay for every download.
+
+use crate::project::venv_python;
+use crate::registry::PackageRegistry;
+use crate::session::PYTHON_OVERRIDE;
+use crate::{
+    create_virtualenv, format_duration, install_with_options, set_python_override, InstallOptions,
+    InstallReport, Result,
+};
+use serde::Serialize;
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+
+/// A way of running pip over a list of packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InstallStrategy {
+    /// One pip process per package, one after another
+    Sequential,
+    /// One pip process per package, downloads running concurrently (`--parallel`)
+    Parallel,
+    /// A single pip process for every package, the default of `install`
+    Batched,
+}
+
+impl InstallStrategy {
+    /// Every strategy, in the order they are timed by default
+    pub const ALL: [InstallStrategy; 3] = [
+        InstallStrategy::Sequential,
+        InstallStrategy::Parallel,
+        InstallStrategy::Batched,
+    ];
+}
+
+impl fmt::Display for InstallStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InstallStrategy::Sequential => "sequential",
+            InstallStrategy::Parallel => "parallel",
+            InstallStrategy::Batched => "batched",
+        })
+    }
+}
+
+/// How long one package took under a strategy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageTiming {
+    /// Package name
+    pub name: String,
+    /// Duration in milliseconds; `None` when pip handled it together with the others
+    pub millis: Option<u64>,
+    /// Whether the package failed to install
+    pub failed: bool,
+}
+
+/// Timings of one strategy
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BenchmarkResult {
+    /// The strategy that was timed
+    pub strategy: InstallStrategy,
+    /// Total duration in milliseconds
+    pub millis: u64,
+    /// One timing per package
+    pub packages: Vec<PackageTiming>,
+    /// Why the strategy could not run at all, e.g. the virtual environment could not be created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl BenchmarkResult {
+    /// Whether every package was installed
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && self.packages.iter().all(|p| !p.failed)
+    }
+}
+
+/// Installs the packages once per strategy and times each run
+///
+/// Every run, and the untimed one that warms pip's cache, gets a fresh
+/// virtual environment in a temporary directory that is removed afterwards.
+/// The project's own environment and registry are left alone.
+///
+/// # Arguments
+/// * `packages` - Package specifications to install
+/// * `strategies` - The strategies to time, in order
+/// * `python` - Interpreter the virtual environments are created with, if not the default
+///
+/// # Returns
+/// * `Result<Vec<BenchmarkResult>>` - One result per strategy, or an error if
+///   no virtual environment could be created
+pub fn benchmark_install(
+    packages: &[String],
+    strategies: &[InstallStrategy],
+    python: Option<&str>,
+) -> Result<Vec<BenchmarkResult>> {
+    let root = std::env::temp_dir().join(format!("ppm-benchmark-{}", std::process::id()));
+    let results = run_benchmark(&root, packages, strategies, python);
+    let _ = std::fs::remove_dir_all(&root);
+    results
+}
+
+fn run_benchmark(
+    root: &Path,
+    packages: &[String],
+    strategies: &[InstallStrategy],
+    python: Option<&str>,
+) -> Result<Vec<BenchmarkResult>> {
+    status!("Warming pip's cache with an untimed install...");
+    let warm_up = time_strategy(
+        &root.join("warm-up"),
+        InstallStrategy::Batched,
+        packages,
+        python,
+    )?;
+    if !warm_up.is_success() {
+        warning!("The warm-up install failed; the first strategy may include download time");
+    }
+
+    let mut results = Vec::new();
+    for (index, &strategy) in strategies.iter().enumerate() {
+        status!("Timing the {} install...", strategy);
+        results.push(time_strategy(
+            &root.join(index.to_string()),
+            strategy,
+            packages,
+            python,
+        )?);
+    }
+    Ok(results)
+}
+
+/// Creates a virtual environment and times one strategy installing into it
+fn time_strategy(
+    venv: &Path,
+    strategy: InstallStrategy,
+    packages: &[String],
+    python: Option<&str>,
+) -> Result<BenchmarkResult> {
+    let previous = match python {
+        Some(python) => PYTHON_OVERRIDE.lock().unwrap().replace(python.to_string()),
+        None => PYTHON_OVERRIDE.lock().unwrap().clone(),
+    };
+    let created = create_virtualenv(venv);
+    set_python_override(previous);
+    created?;
+
+    let options = InstallOptions::new().python(venv_python(venv));
+    let mut registry = PackageRegistry::new();
+    let started = Instant::now();
+    let report = match strategy {
+        InstallStrategy::Sequential => {
+            let mut report = InstallReport::new("Installation summary", "install");
+            packages
+                .iter()
+                .try_for_each(|package| {
+                    let started = Instant::now();
+                    let single = install_with_options(
+                        std::slice::from_ref(package),
+                        &options,
+                        &mut registry,
+                    )?;
+                    let millis = started.elapsed().as_millis() as u64;
+                    report
+                        .outcomes
+                        .extend(single.outcomes.into_iter().map(|mut outcome| {
+                            outcome.millis.get_or_insert(millis);
+                            outcome
+                        }));
+                    Ok(())
+                })
+                .map(|()| report)
+        }
+        InstallStrategy::Parallel => {
+            install_with_options(packages, &options.parallel(true), &mut registry)
+        }
+        InstallStrategy::Batched => install_with_options(packages, &options, &mut registry),
+    };
+    let millis = started.elapsed().as_millis() as u64;
+
+    Ok(match report {
+        Ok(report) => BenchmarkResult {
+            strategy,
+            millis,
+            packages: report
+                .outcomes
+                .iter()
+                .map(|outcome| PackageTiming {
+                    name: outcome.name.clone(),
+                    millis: outcome.millis,
+                    failed: outcome.status() == "failed",
+                })
+                .collect(),
+            error: None,
+        },
+        Err(e) => BenchmarkResult {
+            strategy,
+            millis,
+            packages: Vec::new(),
+            error: Some(e.to_string()),
+        },
+    })
+}
+
+/// Renders the timings as a table of packages by strategy, with the fastest strategy
+///
+/// # Arguments
+/// * `results` - One result per strategy
+///
+/// # Returns
+/// * `String` - The table, the total of every strategy and a verdict
+pub fn render_benchmark(results: &[BenchmarkResult]) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for timing in results.iter().flat_map(|r| &r.packages) {
+        if !names.contains(&timing.name.as_str()) {
+            names.push(&timing.name);
+        }
+    }
+    let header: Vec<String> = std::iter::once("Package".to_string())
+        .chain(results.iter().map(|r| r.strategy.to_string()))
+        .collect();
+    let mut rows: Vec<Vec<String>> = names
+        .iter()
+        .map(|name| {
+            std::iter::once(name.to_string())
+                .chain(results.iter().map(|result| {
+                    match result.packages.iter().find(|t| t.name == *name) {
+                        Some(timing) if timing.failed => "failed".to_string(),
+                        Some(timing) => timing.millis.map_or("-".to_string(), format_duration),
+                        None => "-".to_string(),
+                    }
+                }))
+                .collect()
+        })
+        .collect();
+    rows.push(
+        std::iter::once("Total".to_string())
+            .chain(results.iter().map(|result| match result.error {
+                Some(_) => "failed".to_string(),
+                None => format_duration(result.millis),
+            }))
+            .collect(),
+    );
+    let mut widths: Vec<usize> = header.iter().map(String::len).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = format!("\nInstall benchmark ({} package(s)):\n", names.len());
+    for row in std::iter::once(&header).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell))
+            .collect();
+        out.push_str(&format!("  {}\n", cells.join("  ").trim_end()));
+    }
+
+    for result in results {
+        if let Some(error) = &result.error {
+            out.push_str(&format!("\n{} failed: {}\n", result.strategy, error));
+        }
+    }
+    let complete: Vec<&BenchmarkResult> = results.iter().filter(|r| r.is_success()).collect();
+    let fastest = complete.iter().min_by_key(|r| r.millis);
+    let slowest = complete.iter().max_by_key(|r| r.millis);
+    match (fastest, slowest) {
+        (Some(fastest), Some(slowest)) if complete.len() > 1 => {
+            out.push_str(&format!(
+                "\nFastest: {} ({}), {:.1}x the speed of {}\n",
+                fastest.strategy,
+                format_duration(fastest.millis),
+                slowest.millis as f64 / fastest.millis.max(1) as f64,
+                slowest.strategy
+            ));
+        }
+        _ if complete.len() < results.len() => {
+            out.push_str("\nOnly strategies that installed every package are compared\n");
+        }
+        _ => {}
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_benchmark() {
+        let timing = |name: &str, millis: Option<u64>, failed: bool| PackageTiming {
+            name: name.to_string(),
+            millis,
+            failed,
+        };
+        let results = [
+            BenchmarkResult {
+                strategy: InstallStrategy::Sequential,
+                millis: 4000,
+                packages: vec![
+                    timing("alpha", Some(2500), false),
+                    timing("beta", Some(1500), false),
+                ],
+                error: None,
+            },
+            BenchmarkResult {
+                strategy: InstallStrategy::Batched,
+                millis: 2000,
+                packages: vec![timing("alpha", None, false), timing("beta", None, false)],
+                error: None,
+            },
+            BenchmarkResult {
+                strategy: InstallStrategy::Parallel,
+                millis: 300,
+                packages: Vec::new(),
+                error: Some("Python executable not found".to_string()),
+            },
+        ];
+
+        assert_eq!(
+            render_benchmark(&results),
+            "\nInstall benchmark (2 package(s)):\n\
+             \x20 Package  sequential  batched  parallel\n\
+             \x20 alpha    2.5 s       -        -\n\
+             \x20 beta     1.5 s       -        -\n\
+             \x20 Total    4.0 s       2.0 s    failed\n\
+             \n\
+             parallel failed: Python executable not found\n\
+             \n\
+             Fastest: batched (2.0 s), 2.0x the speed of sequential\n"
+        );
+    }
+}
@@ -0,0 +1,244 @@
+//! Building sdists and wheels with the PEP 517 frontend `build`
+//!
+//! `ppm build` runs `python -m build` from a virtual environment of its own
+//! under `.ppm/tools`, created and given `build` the first time it is
+//! needed, so the project's environment does not have to contain it. Build
+//! failures are classified like pip's, so the backend's error and a hint
+//! show up through [`PackageError::diagnose`].
+
+use crate::pip::{get_python_executable, get_python_version, pip_error, run_command, run_pip};
+use crate::project::venv_python;
+use crate::state::STATE_DIR;
+use crate::{create_virtualenv, PackageError, PipFailure, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Which distributions to build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuildTarget {
+    /// An sdist, then a wheel built from it, like `python -m build`
+    Both,
+    /// Only the source distribution
+    Sdist,
+    /// Only the wheel, built straight from the source tree
+    Wheel,
+}
+
+/// Distributions written by [`build_project`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BuildSummary {
+    /// The project that was built
+    pub project: PathBuf,
+    /// Directory the distributions were written to
+    pub outdir: PathBuf,
+    /// The distributions, e.g. `dist/mypkg-1.0.tar.gz`
+    pub artifacts: Vec<PathBuf>,
+}
+
+/// Virtual environment holding `build` for an interpreter version
+///
+/// # Arguments
+/// * `python_version` - Version of the interpreter, e.g. "3.12.1"
+///
+/// # Returns
+/// * `PathBuf` - e.g. `.ppm/tools/build-3.12`
+pub fn build_tool_dir(python_version: &str) -> PathBuf {
+    let minor: Vec<&str> = python_version.split('.').take(2).collect();
+    Path::new(STATE_DIR)
+        .join("tools")
+        .join(format!("build-{}", minor.join(".")))
+}
+
+/// The interpreter of the `build` tool environment, set up if needed
+///
+/// # Returns
+/// * `Result<String>` - Path of the environment's interpreter, or an error if
+///   it could not be created or `build` could not be installed
+fn build_tool_python() -> Result<String> {
+    let python = get_python_executable()?;
+    let venv = std::path::absolute(build_tool_dir(&get_python_version(&python)?))?;
+    let tool_python = venv_python(&venv);
+    let has_build = Path::new(&tool_python).exists()
+        && run_command(Command::new(&tool_python).args(["-m", "build", "--version"]))
+            .is_ok_and(|output| output.status.success());
+    if has_build {
+        return Ok(tool_python);
+    }
+
+    status!("Installing build into {}...", venv.display());
+    if !Path::new(&tool_python).exists() {
+        create_virtualenv(&venv)?;
+    }
+    let mut command = Command::new(&tool_python);
+    command
+        .args(["-m", "pip", "install", "--upgrade", "build"])
+        // The tool environment is separate from the project; local packages must not redirect it
+        .env_remove("PIP_TARGET")
+        .env_remove("PIP_UPGRADE");
+    let output = run_pip(command, "build", None)?;
+    if !output.status.success() {
+        return Err(pip_error(
+            "build",
+            &output,
+            PackageError::InstallationFailed,
+        ));
+    }
+    Ok(tool_python)
+}
+
+/// Reads the distributions `build` reports in its last line
+///
+/// # Arguments
+/// * `stdout` - Output of `python -m build`
+///
+/// # Returns
+/// * `Vec<String>` - File names from "Successfully built a.tar.gz and a.whl"
+pub fn parse_built_artifacts(stdout: &str) -> Vec<String> {
+    stdout
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("Successfully built "))
+        .map(|built| {
+            built
+                .split(" and ")
+                .flat_map(|part| part.split(", "))
+                .map(|name| name.trim().to_string())
+                .filter(|name| !name.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Builds the distributions of a project with `python -m build`
+///
+/// # Arguments
+/// * `project` - The project directory, holding `pyproject.toml` or `setup.py`
+/// * `outdir` - Where to write the distributions, by default `<project>/dist`
+/// * `target` - Which distributions to build
+/// * `no_isolation` - Build in the tool environment instead of a fresh
+///   isolated one, so the build dependencies must already be there
+///
+/// # Returns
+/// * `Result<BuildSummary>` - The distributions, or an error if `build` could
+///   not be set up or the build backend failed
+pub fn build_project(
+    project: &Path,
+    outdir: Option<&Path>,
+    target: BuildTarget,
+    no_isolation: bool,
+) -> Result<BuildSummary> {
+    if !project.is_dir() {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "{} is not a directory",
+            project.display()
+        )));
+    }
+    let outdir = outdir.map_or_else(|| project.join("dist"), Path::to_path_buf);
+    let python = build_tool_python()?;
+
+    status!("Building {}...", project.display());
+    let mut command = Command::new(&python);
+    command.args(["-m", "build"]);
+    match target {
+        BuildTarget::Both => {}
+        BuildTarget::Sdist => {
+            command.arg("--sdist");
+        }
+        BuildTarget::Wheel => {
+            command.arg("--wheel");
+        }
+    }
+    if no_isolation {
+        command.arg("--no-isolation");
+    }
+    command
+        .arg("--outdir")
+        .arg(&outdir)
+        .arg(project)
+        .env("NO_COLOR", "1")
+        // Isolated build environments are installed with pip, which must not follow local packages
+        .env_remove("PIP_TARGET")
+        .env_remove("PIP_UPGRADE");
+    let output = run_command(&mut command)?;
+    if !output.status.success() {
+        return Err(build_error(project, &output));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(BuildSummary {
+        project: project.to_path_buf(),
+        artifacts: parse_built_artifacts(&stdout)
+            .into_iter()
+            .map(|name| outdir.join(name))
+            .collect(),
+        outdir,
+    })
+}
+
+/// Classifies a failed build from everything `build` and the backend printed
+///
+/// `build` reports its own errors on stdout, interleaved with the backend's
+/// output, so both streams are searched.
+fn build_error(project: &Path, output: &Output) -> PackageError {
+    let mut combined = output.stdout.clone();
+    combined.extend_from_slice(&output.stderr);
+    let output = Output {
+        status: output.status,
+        stdout: Vec::new(),
+        stderr: combined,
+    };
+    let package = project.display().to_string();
+    let exit_status = output.status.code();
+    pip_error(&package, &output, |stderr| {
+        PackageError::BuildFailed(PipFailure {
+            package: package.clone(),
+            exit_status,
+            stderr,
+        })
+    })
+}
+
+/// Renders the distributions a build wrote
+///
+/// # Arguments
+/// * `summary` - The build
+///
+/// # Returns
+/// * `String` - One line per distribution
+pub fn render_build_summary(summary: &BuildSummary) -> String {
+    if summary.artifacts.is_empty() {
+        return format!("Built into {}\n", summary.outdir.display());
+    }
+    summary
+        .artifacts
+        .iter()
+        .map(|artifact| format!("Built {}\n", artifact.display()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_built_artifacts() {
+        let stdout = "* Creating isolated environment: venv+pip...\n\
+            * Building wheel from sdist\n\
+            Successfully built mypkg-1.0.tar.gz and mypkg-1.0-py3-none-any.whl\n";
+        assert_eq!(
+            parse_built_artifacts(stdout),
+            vec!["mypkg-1.0.tar.gz", "mypkg-1.0-py3-none-any.whl"]
+        );
+        assert_eq!(
+            parse_built_artifacts("Successfully built mypkg-1.0-py3-none-any.whl\n"),
+            vec!["mypkg-1.0-py3-none-any.whl"]
+        );
+        assert!(parse_built_artifacts("ERROR Backend subprocess exited").is_empty());
+        assert_eq!(
+            build_tool_dir("3.12.1"),
+            Path::new(STATE_DIR).join("tools").join("build-3.12")
+        );
+    }
+}
@@ -0,0 +1,812 @@
+//! Single-file application bundles built from the tracked environment
+//!
+//! `ppm bundle app` installs the locked dependencies and the project into a
+//! staging `site-packages` with `pip install --target`, adds a generated
+//! `__main__.py` and zips the lot with Python's `zipapp`. Like shiv, the
+//! bundle extracts its `site-packages` to a cache directory on first run, so
+//! native extensions load normally, and reuses it on later runs.
+//!
+//! `ppm bundle create` packs the hash-pinned requirements, the lockfile and
+//! every archive they need into one zip for machines without network access,
+//! and `ppm bundle install` installs it from there with `--no-index`, checking
+//! the bundle against the sha256 given by the operator, then each archive
+//! against the bundle's manifest and its pinned hash.
+
+use crate::container::pinned_requirements;
+use crate::install::install_hash_pinned;
+use crate::lock::Lockfile;
+use crate::pip::{get_python_executable, get_python_version, pip_error, run_command, run_pip};
+use crate::registry::PackageRegistry;
+use crate::serve::file_sha256;
+use crate::spec::canonicalize_name;
+use crate::state::unix_now;
+use crate::vendor::vendored_distributions;
+use crate::{HashMismatch, InstallReport, PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tempfile::TempDir;
+
+/// Interpreter line written into bundles unless another one is given
+pub const DEFAULT_BUNDLE_SHEBANG: &str = "/usr/bin/env python3";
+
+/// Variable that overrides where bundles extract their packages
+pub const BUNDLE_ROOT_ENV: &str = "PPM_BUNDLE_ROOT";
+
+/// What went into a bundle
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BundleSummary {
+    /// The bundle that was written
+    pub output: PathBuf,
+    /// The entry point it runs, e.g. "mypkg.cli:main"
+    pub entry: String,
+    /// Digest of its packages, naming the directory they are extracted to
+    pub bundle_id: String,
+    /// Bundled distributions with their versions, the project included
+    pub packages: Vec<(String, String)>,
+    /// Whether the project itself was installed into the bundle
+    pub project: bool,
+}
+
+/// Splits an entry point into its module and attribute
+///
+/// # Arguments
+/// * `entry` - The entry point, e.g. "mypkg.cli:main" or "mypkg.app:App.run"
+///
+/// # Returns
+/// * `Result<(String, String)>` - The module and the attribute path, or an
+///   error if the entry point is not `module:attribute`
+pub fn parse_entry_point(entry: &str) -> Result<(String, String)> {
+    let is_dotted_name = |value: &str| {
+        !value.is_empty()
+            && value.split('.').all(|part| {
+                part.chars().next().is_some_and(|c| !c.is_ascii_digit())
+                    && part.chars().all(|c| c.is_alphanumeric() || c == '_')
+            })
+    };
+    match entry.trim().split_once(':') {
+        Some((module, attr)) if is_dotted_name(module) && is_dotted_name(attr) => {
+            Ok((module.to_string(), attr.to_string()))
+        }
+        _ => Err(PackageError::InvalidPackageSpec(format!(
+            "Invalid entry point '{}'; expected module:function, e.g. mypkg.cli:main",
+            entry
+        ))),
+    }
+}
+
+/// Renders the `__main__.py` that starts a bundle
+///
+/// # Arguments
+/// * `module` - Module of the entry point
+/// * `attr` - Attribute path of the entry point within the module
+/// * `bundle_id` - Digest naming the directory the packages are extracted to
+///
+/// # Returns
+/// * `String` - The script
+pub fn render_bundle_main(module: &str, attr: &str, bundle_id: &str) -> String {
+    format!(
+        r#"# Generated by ppm bundle app
+import importlib
+import os
+import shutil
+import site
+import sys
+import tempfile
+import zipfile
+
+BUNDLE_ID = "{bundle_id}"
+MODULE = "{module}"
+ATTR = "{attr}"
+
+
+def _site_packages():
+    archive = os.path.dirname(os.path.abspath(__file__))
+    root = os.environ.get("{root_env}") or os.path.join(
+        os.path.expanduser("~"), ".cache", "ppm-bundles"
+    )
+    target = os.path.join(root, BUNDLE_ID)
+    if os.path.isdir(target):
+        return target
+    os.makedirs(root, exist_ok=True)
+    staging = tempfile.mkdtemp(prefix=BUNDLE_ID + "-", dir=root)
+    try:
+        with zipfile.ZipFile(archive) as bundle:
+            for name in bundle.namelist():
+                if name.startswith("site-packages/"):
+                    bundle.extract(name, staging)
+        os.makedirs(os.path.join(staging, "site-packages"), exist_ok=True)
+        try:
+            os.rename(os.path.join(staging, "site-packages"), target)
+        except OSError:
+            # Another run extracted the same bundle first
+            if not os.path.isdir(target):
+                raise
+    finally:
+        shutil.rmtree(staging, ignore_errors=True)
+    return target
+
+
+def main():
+    packages = _site_packages()
+    sys.path.insert(0, packages)
+    site.addsitedir(packages)
+    target = importlib.import_module(MODULE)
+    for part in ATTR.split("."):
+        target = getattr(target, part)
+    return target()
+
+
+if __name__ == "__main__":
+    sys.exit(main())
+"#,
+        bundle_id = bundle_id,
+        module = module,
+        attr = attr,
+        root_env = BUNDLE_ROOT_ENV,
+    )
+}
+
+/// Every file below a directory, sorted, relative to it
+fn files_under(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for entry in std::fs::read_dir(&current)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                files.push(relative.to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Digest of a directory's file names and contents
+///
+/// # Arguments
+/// * `dir` - The directory
+///
+/// # Returns
+/// * `Result<String>` - The first 16 hex digits of the sha256, or an error if
+///   a file could not be read
+pub fn directory_digest(dir: &Path) -> Result<String> {
+    let mut hash = hmac_sha256::Hash::new();
+    for file in files_under(dir)? {
+        hash.update(file.to_string_lossy().replace('\\', "/").as_bytes());
+        hash.update([0]);
+        hash.update(std::fs::read(dir.join(&file))?);
+        hash.update([0]);
+    }
+    Ok(hash
+        .finalize()
+        .iter()
+        .take(8)
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Creates the temporary directory a bundle is staged in, removed when dropped
+///
+/// The directory gets a fresh random name and mode 0700, so no other user can
+/// plant or replace files between staging and installing them.
+fn staging_dir() -> Result<TempDir> {
+    Ok(tempfile::Builder::new().prefix("ppm-bundle-").tempdir()?)
+}
+
+/// Runs `pip install --target` into the staging `site-packages`
+fn pip_install_into(python: &str, site_packages: &Path, label: &str, args: &[&str]) -> Result<()> {
+    let mut command = Command::new(python);
+    command
+        .args(["-m", "pip", "install", "--target"])
+        .arg(site_packages)
+        .args(["--no-compile", "--no-warn-script-location"])
+        .args(args)
+        // The bundle is staged explicitly; local packages must not redirect it
+        .env_remove("PIP_TARGET");
+    let output = run_pip(command, label, None)?;
+    if !output.status.success() {
+        return Err(pip_error(label, &output, PackageError::InstallationFailed));
+    }
+    Ok(())
+}
+
+/// Builds a runnable zipapp of the project and its pinned dependencies
+///
+/// The dependencies come from the lockfile when there is one and from the
+/// registry otherwise, and are installed in pip's `--require-hashes` mode.
+/// The project is installed without dependencies when it has a
+/// `pyproject.toml` or `setup.py`.
+///
+/// # Arguments
+/// * `entry` - The entry point to run, e.g. "mypkg.cli:main"
+/// * `output` - The bundle to write, e.g. "app.pyz"
+/// * `shebang` - Interpreter line of the bundle, e.g. "/usr/bin/env python3"
+/// * `lockfile` - The lockfile to pin from, if the project has one
+/// * `registry` - Reference to the package registry, used without a lockfile
+/// * `project` - The project directory
+///
+/// # Returns
+/// * `Result<BundleSummary>` - What went into the bundle, or an error if the
+///   entry point is invalid or pip or zipapp failed
+pub fn bundle_app(
+    entry: &str,
+    output: &Path,
+    shebang: &str,
+    lockfile: Option<&Lockfile>,
+    registry: &PackageRegistry,
+    project: &Path,
+) -> Result<BundleSummary> {
+    let (module, attr) = parse_entry_point(entry)?;
+    let python = get_python_executable()?;
+    let staging = staging_dir()?;
+    let staging = staging.path();
+    let site_packages = staging.join("site-packages");
+    std::fs::create_dir_all(&site_packages)?;
+
+    let (requirements, unhashed, complete) = pinned_requirements(lockfile, registry)?;
+    let no_deps: &[&str] = if complete { &["--no-deps"] } else { &[] };
+    if !requirements.is_empty() {
+        status!("Installing the pinned dependencies, verifying every archive...");
+        let file = staging.join("requirements.txt");
+        std::fs::write(&file, &requirements)?;
+        let file = file.to_string_lossy().to_string();
+        let mut args = vec!["--require-hashes", "-r", file.as_str()];
+        args.extend(no_deps);
+        pip_install_into(&python, &site_packages, "bundle dependencies", &args)?;
+    }
+    for spec in &unhashed {
+        warning!("{} has no hashes; bundling it without verification", spec);
+        let mut args = vec![spec.as_str()];
+        args.extend(no_deps);
+        pip_install_into(&python, &site_packages, spec, &args)?;
+    }
+
+    let has_project =
+        project.join("pyproject.toml").is_file() || project.join("setup.py").is_file();
+    if has_project {
+        status!("Installing the project from {}...", project.display());
+        let path = project.to_string_lossy().to_string();
+        pip_install_into(&python, &site_packages, "project", &["--no-deps", &path])?;
+    } else {
+        notice!(
+            "{} has no pyproject.toml or setup.py; bundling only its dependencies",
+            project.display()
+        );
+    }
+
+    let bundle_id = directory_digest(&site_packages)?;
+    std::fs::write(
+        staging.join("__main__.py"),
+        render_bundle_main(&module, &attr, &bundle_id),
+    )?;
+    let _ = std::fs::remove_file(staging.join("requirements.txt"));
+    let mut packages: Vec<(String, String)> = vendored_distributions(&site_packages)?
+        .into_iter()
+        .map(|distribution| (distribution.name, distribution.version))
+        .collect();
+    packages.sort();
+
+    if let Some(parent) = output
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+    status!("Writing {}...", output.display());
+    let mut command = Command::new(&python);
+    command
+        .args(["-m", "zipapp"])
+        .arg(staging)
+        .arg("-o")
+        .arg(output)
+        .args(["-p", shebang, "-c"]);
+    let zipped = run_command(&mut command)?;
+    if !zipped.status.success() {
+        return Err(PackageError::InstallationFailed(format!(
+            "zipapp could not write {}: {}",
+            output.display(),
+            String::from_utf8_lossy(&zipped.stderr).trim()
+        )));
+    }
+
+    Ok(BundleSummary {
+        output: output.to_path_buf(),
+        entry: entry.to_string(),
+        bundle_id,
+        packages,
+        project: has_project,
+    })
+}
+
+/// Renders what went into a bundle
+///
+/// # Arguments
+/// * `summary` - The bundle
+///
+/// # Returns
+/// * `String` - One line per bundled package and a closing line
+pub fn render_bundle_summary(summary: &BundleSummary) -> String {
+    let mut out = String::new();
+    for (name, version) in &summary.packages {
+        out.push_str(&format!("  {} {}\n", name, version));
+    }
+    out.push_str(&format!(
+        "{}: {} packages, runs {} (bundle {})\n",
+        summary.output.display(),
+        summary.packages.len(),
+        summary.entry,
+        summary.bundle_id
+    ));
+    out
+}
+
+/// Layout version of offline bundles; newer bundles are refused
+pub const OFFLINE_BUNDLE_FORMAT: u32 = 1;
+
+/// Manifest of an offline bundle, stored as `manifest.json` at its root
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleManifest {
+    /// Layout version, see [`OFFLINE_BUNDLE_FORMAT`]
+    pub format: u32,
+    /// Tool that wrote the bundle, e.g. "ppm 0.1.0"
+    pub created_by: String,
+    /// Unix timestamp of when the bundle was written
+    pub created_at: u64,
+    /// Version of the interpreter the archives were downloaded for, e.g. "3.12.3"
+    pub python: String,
+    /// Whether the requirements pin every dependency, so pip installs them with `--no-deps`
+    pub complete: bool,
+    /// The bundled packages with their versions
+    pub packages: Vec<(String, String)>,
+    /// Every archive under `wheels/` with its sha256
+    pub files: Vec<BundledFile>,
+}
+
+/// An archive in an offline bundle
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundledFile {
+    /// File name below `wheels/`
+    pub name: String,
+    /// Hex sha256 of the file
+    pub sha256: String,
+}
+
+/// What went into an offline bundle
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OfflineBundleSummary {
+    /// The bundle that was written
+    pub output: PathBuf,
+    /// Its manifest
+    pub manifest: BundleManifest,
+    /// Size of the bundle in bytes
+    pub size: u64,
+    /// Hex sha256 of the bundle, to be passed to `bundle install --sha256` on the target
+    pub sha256: String,
+}
+
+/// Packages of a hash-pinned requirements file, as name and version
+fn pinned_packages(requirements: &str) -> Vec<(String, String)> {
+    requirements
+        .lines()
+        .filter(|line| !line.starts_with(char::is_whitespace))
+        .filter_map(|line| line.trim_end_matches('\\').trim().split_once("=="))
+        .map(|(name, version)| (name.trim().to_string(), version.trim().to_string()))
+        .collect()
+}
+
+/// Packs the locked packages and every archive they need into one file
+///
+/// The archives are downloaded in pip's `--require-hashes` mode for the
+/// active interpreter. The bundle holds them under `wheels/`, the
+/// hash-pinned `requirements.txt`, a copy of the lockfile and `manifest.json`.
+///
+/// # Arguments
+/// * `output` - The bundle to write, e.g. "env.bundle"
+/// * `lockfile` - The lockfile to pin from, if the project has one
+/// * `lock_path` - Where that lockfile is, copied into the bundle
+/// * `registry` - Reference to the package registry, used without a lockfile
+///
+/// # Returns
+/// * `Result<OfflineBundleSummary>` - What went into the bundle, or an error
+///   if a package has no hash or pip or zipfile failed
+pub fn create_offline_bundle(
+    output: &Path,
+    lockfile: Option<&Lockfile>,
+    lock_path: &Path,
+    registry: &PackageRegistry,
+) -> Result<OfflineBundleSummary> {
+    let (requirements, unhashed, complete) = pinned_requirements(lockfile, registry)?;
+    if !unhashed.is_empty() {
+        return Err(PackageError::MetadataError(format!(
+            "Cannot verify {} offline: no sha256 hash is pinned; run `ppm lock` first",
+            unhashed.join(", ")
+        )));
+    }
+    if requirements.is_empty() {
+        return Err(PackageError::MetadataError(
+            "Nothing to bundle: no packages are locked or registered".to_string(),
+        ));
+    }
+
+    let python = get_python_executable()?;
+    let staging = staging_dir()?;
+    let staging = staging.path();
+    let wheels = staging.join("wheels");
+    std::fs::create_dir_all(&wheels)?;
+    std::fs::write(staging.join("requirements.txt"), &requirements)?;
+
+    status!("Downloading the pinned archives, verifying every one...");
+    let mut command = Command::new(&python);
+    command
+        .args(["-m", "pip", "download", "--require-hashes", "-r"])
+        .arg(staging.join("requirements.txt"))
+        .arg("--dest")
+        .arg(&wheels);
+    if complete {
+        command.arg("--no-deps");
+    }
+    let downloaded = run_pip(command, "bundle download", None)?;
+    if !downloaded.status.success() {
+        return Err(pip_error(
+            "bundle download",
+            &downloaded,
+            PackageError::InstallationFailed,
+        ));
+    }
+
+    let mut files = Vec::new();
+    for file in files_under(&wheels)? {
+        files.push(BundledFile {
+            sha256: file_sha256(&wheels.join(&file))?,
+            name: file.to_string_lossy().replace('\\', "/"),
+        });
+    }
+    let manifest = BundleManifest {
+        format: OFFLINE_BUNDLE_FORMAT,
+        created_by: format!("ppm {}", env!("CARGO_PKG_VERSION")),
+        created_at: unix_now(),
+        python: get_python_version(&python)?,
+        complete,
+        packages: pinned_packages(&requirements),
+        files,
+    };
+    std::fs::write(
+        staging.join("manifest.json"),
+        serde_json::to_string_pretty(&manifest)?,
+    )?;
+    let mut entries = vec!["manifest.json", "requirements.txt", "wheels"];
+    if lockfile.is_some() {
+        std::fs::copy(lock_path, staging.join("pylock.toml"))?;
+        entries.push("pylock.toml");
+    }
+
+    let output = std::env::current_dir()?.join(output);
+    if let Some(parent) = output.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let _ = std::fs::remove_file(&output);
+    status!("Writing {}...", output.display());
+    let mut command = Command::new(&python);
+    command
+        .args(["-m", "zipfile", "-c"])
+        .arg(&output)
+        .args(&entries)
+        .current_dir(staging);
+    let zipped = run_command(&mut command)?;
+    if !zipped.status.success() {
+        return Err(PackageError::InstallationFailed(format!(
+            "zipfile could not write {}: {}",
+            output.display(),
+            String::from_utf8_lossy(&zipped.stderr).trim()
+        )));
+    }
+
+    Ok(OfflineBundleSummary {
+        size: std::fs::metadata(&output)?.len(),
+        sha256: file_sha256(&output)?,
+        output,
+        manifest,
+    })
+}
+
+/// Checks the archives of an unpacked bundle against its manifest
+///
+/// # Arguments
+/// * `manifest` - The bundle's manifest
+/// * `wheels` - The unpacked `wheels/` directory
+///
+/// # Returns
+/// * `Result<()>` - Success, or [`PackageError::HashMismatch`] listing every
+///   archive that is missing, altered or not in the manifest
+pub fn verify_bundle_files(manifest: &BundleManifest, wheels: &Path) -> Result<()> {
+    let mut mismatches = Vec::new();
+    for file in &manifest.files {
+        let path = wheels.join(&file.name);
+        let actual = match file_sha256(&path) {
+            Ok(actual) if actual == file.sha256 => continue,
+            Ok(actual) => actual,
+            Err(_) => "nothing (the file is missing)".to_string(),
+        };
+        mismatches.push(HashMismatch {
+            requirement: archive_requirement(manifest, &file.name),
+            url: path.display().to_string(),
+            expected: vec![file.sha256.clone()],
+            actual,
+        });
+    }
+    for file in files_under(wheels)? {
+        let name = file.to_string_lossy().replace('\\', "/");
+        if !manifest.files.iter().any(|listed| listed.name == name) {
+            mismatches.push(HashMismatch {
+                requirement: archive_requirement(manifest, &name),
+                url: wheels.join(&file).display().to_string(),
+                expected: vec!["none (the file is not in the manifest)".to_string()],
+                actual: file_sha256(&wheels.join(&file))?,
+            });
+        }
+    }
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(PackageError::HashMismatch(mismatches))
+    }
+}
+
+/// Bundled package an archive belongs to, e.g. "idna==3.7" for "idna-3.7-py3-none-any.whl"
+fn archive_requirement(manifest: &BundleManifest, file: &str) -> String {
+    let file = canonicalize_name(file);
+    manifest
+        .packages
+        .iter()
+        .map(|(name, version)| (canonicalize_name(name), name, version))
+        .filter(|(canonical, _, _)| file.starts_with(&format!("{}-", canonical)))
+        .max_by_key(|(canonical, _, _)| canonical.len())
+        .map_or_else(
+            || "no bundled package".to_string(),
+            |(_, name, version)| format!("{}=={}", name, version),
+        )
+}
+
+/// Installs an offline bundle without contacting any index
+///
+/// The bundle is copied into a private temporary directory and checked
+/// against its sha256 before anything is unpacked; the manifest inside a
+/// bundle cannot vouch for the bundle itself, so without a sha256 the install
+/// is refused unless `insecure_no_verify` is set. Every archive is then
+/// checked against the manifest before pip installs the requirements with
+/// `--no-index` in `--require-hashes` mode.
+///
+/// # Arguments
+/// * `bundle` - A bundle written by [`create_offline_bundle`]
+/// * `sha256` - The sha256 of the bundle, obtained from a trusted channel
+/// * `insecure_no_verify` - Whether to install without a sha256, trusting the
+///   bundle's own manifest
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if
+///   the bundle is unreadable, of a newer format, fails verification or
+///   comes without a sha256
+pub fn install_offline_bundle(
+    bundle: &Path,
+    sha256: Option<&str>,
+    insecure_no_verify: bool,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    if sha256.is_none() && !insecure_no_verify {
+        return Err(PackageError::PolicyViolation(format!(
+            "{} cannot be verified without its sha256; pass the sha256 printed by \
+             `ppm bundle create` with --sha256, or --insecure-no-verify to trust its own manifest",
+            bundle.display()
+        )));
+    }
+    let python = get_python_executable()?;
+    let staging = staging_dir()?;
+    let staging = staging.path();
+    // Checked and unpacked from a private copy, so the file cannot change in between
+    let copy = staging.join("bundle.zip");
+    std::fs::copy(bundle, &copy)?;
+    let actual = file_sha256(&copy)?;
+    match sha256 {
+        Some(expected) => {
+            let expected = expected.trim().trim_start_matches("sha256:").to_lowercase();
+            if actual != expected {
+                return Err(PackageError::HashMismatch(vec![HashMismatch {
+                    requirement: "bundle".to_string(),
+                    url: bundle.display().to_string(),
+                    expected: vec![expected],
+                    actual,
+                }]));
+            }
+        }
+        None => warning!(
+            "Installing {} without verifying it; its archives are only checked against its own manifest",
+            bundle.display()
+        ),
+    }
+    let unpacked = staging.join("bundle");
+    let mut command = Command::new(&python);
+    command
+        .args(["-m", "zipfile", "-e"])
+        .arg(&copy)
+        .arg(&unpacked);
+    let unzipped = run_command(&mut command)?;
+    if !unzipped.status.success() {
+        return Err(PackageError::InstallationFailed(format!(
+            "Could not unpack {}: {}",
+            bundle.display(),
+            String::from_utf8_lossy(&unzipped.stderr).trim()
+        )));
+    }
+    let staging = unpacked.as_path();
+
+    let manifest: BundleManifest = std::fs::read_to_string(staging.join("manifest.json"))
+        .map_err(|e| {
+            PackageError::MetadataError(format!("{} is not a ppm bundle: {}", bundle.display(), e))
+        })
+        .and_then(|contents| Ok(serde_json::from_str(&contents)?))?;
+    if manifest.format > OFFLINE_BUNDLE_FORMAT {
+        return Err(PackageError::MetadataError(format!(
+            "{} was written by a newer ppm ({}); upgrade ppm to install it",
+            bundle.display(),
+            manifest.created_by
+        )));
+    }
+
+    status!("Verifying {} archives...", manifest.files.len());
+    let wheels = staging.join("wheels");
+    verify_bundle_files(&manifest, &wheels)?;
+    let python_version = get_python_version(&python)?;
+    let minor = |version: &str| version.split('.').take(2).collect::<Vec<_>>().join(".");
+    if minor(&python_version) != minor(&manifest.python) {
+        warning!(
+            "{} was made for Python {}, not {}; its wheels may not install",
+            bundle.display(),
+            manifest.python,
+            python_version
+        );
+    }
+
+    status!(
+        "Installing {} packages from {} without network access",
+        manifest.packages.len(),
+        bundle.display()
+    );
+    let specs: Vec<String> = manifest
+        .packages
+        .iter()
+        .map(|(name, version)| format!("{}=={}", name, version))
+        .collect();
+    let mut pip_args = vec![
+        "--no-index".to_string(),
+        "--find-links".to_string(),
+        wheels.display().to_string(),
+    ];
+    if manifest.complete {
+        pip_args.push("--no-deps".to_string());
+    }
+    install_hash_pinned(
+        &staging.join("requirements.txt"),
+        &specs,
+        &pip_args,
+        registry,
+    )
+}
+
+/// Renders what went into an offline bundle
+///
+/// # Arguments
+/// * `summary` - The bundle
+///
+/// # Returns
+/// * `String` - One line per bundled package and a closing line
+pub fn render_offline_bundle_summary(summary: &OfflineBundleSummary) -> String {
+    let mut out = String::new();
+    for (name, version) in &summary.manifest.packages {
+        out.push_str(&format!("  {} {}\n", name, version));
+    }
+    out.push_str(&format!(
+        "{}: {} packages in {} archives, {:.1} MB, for Python {}\n",
+        summary.output.display(),
+        summary.manifest.packages.len(),
+        summary.manifest.files.len(),
+        summary.size as f64 / 1_000_000.0,
+        summary.manifest.python
+    ));
+    out.push_str(&format!("sha256: {}\n", summary.sha256));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entry_point() {
+        assert_eq!(
+            parse_entry_point("mypkg.cli:main").unwrap(),
+            ("mypkg.cli".to_string(), "main".to_string())
+        );
+        assert_eq!(
+            parse_entry_point("app:App.run").unwrap(),
+            ("app".to_string(), "App.run".to_string())
+        );
+        for invalid in [
+            "mypkg.cli",
+            "mypkg:",
+            ":main",
+            "my-pkg:main",
+            "1pkg:main",
+            "a..b:c",
+        ] {
+            assert!(parse_entry_point(invalid).is_err(), "{}", invalid);
+        }
+
+        let main = render_bundle_main("mypkg.cli", "main", "0123abcd");
+        assert!(main.contains("MODULE = \"mypkg.cli\""));
+        assert!(main.contains("BUNDLE_ID = \"0123abcd\""));
+        assert!(main.contains(BUNDLE_ROOT_ENV));
+    }
+
+    #[test]
+    fn test_verify_bundle_files() {
+        assert_eq!(
+            pinned_packages(
+                "requests==2.32.3 \\\n    --hash=sha256:abc\nidna==3.7 \\\n    --hash=sha256:def\n"
+            ),
+            [
+                ("requests".to_string(), "2.32.3".to_string()),
+                ("idna".to_string(), "3.7".to_string())
+            ]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("idna-3.7-py3-none-any.whl"), "wheel").unwrap();
+        let mut manifest = BundleManifest {
+            format: OFFLINE_BUNDLE_FORMAT,
+            created_by: "ppm test".to_string(),
+            created_at: 0,
+            python: "3.12.3".to_string(),
+            complete: true,
+            packages: vec![("idna".to_string(), "3.7".to_string())],
+            files: vec![BundledFile {
+                name: "idna-3.7-py3-none-any.whl".to_string(),
+                sha256: file_sha256(&dir.path().join("idna-3.7-py3-none-any.whl")).unwrap(),
+            }],
+        };
+        assert!(verify_bundle_files(&manifest, dir.path()).is_ok());
+
+        std::fs::write(dir.path().join("evil-1.0-py3-none-any.whl"), "extra").unwrap();
+        manifest.files[0].sha256 = "0".repeat(64);
+        match verify_bundle_files(&manifest, dir.path()) {
+            Err(PackageError::HashMismatch(mismatches)) => {
+                assert_eq!(mismatches.len(), 2);
+                assert_eq!(mismatches[0].requirement, "idna==3.7");
+                assert_eq!(mismatches[1].file_name(), "evil-1.0-py3-none-any.whl");
+            }
+            other => panic!("expected a hash mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_install_offline_bundle_requires_a_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("env.bundle");
+        std::fs::write(&bundle, "bundle").unwrap();
+        let mut registry = PackageRegistry::new();
+
+        // Refused before the bundle is even read
+        match install_offline_bundle(&bundle, None, false, &mut registry) {
+            Err(PackageError::PolicyViolation(message)) => {
+                assert!(message.contains("--sha256"));
+                assert!(message.contains("--insecure-no-verify"));
+            }
+            other => panic!("expected a policy violation, got {:?}", other),
+        }
+        assert!(registry.packages.is_empty());
+    }
+}
@@ -0,0 +1,1563 @@
+//! Command line definitions, plugins and shell integration
+
+use crate::pip::{get_python_executable, run_command};
+use crate::pypackages::{apply_local_packages, local_packages};
+use crate::registry::{Package, PackageRegistry};
+use crate::requirements::{install_from_requirements, install_from_requirements_parallel};
+use crate::state::STATE_DIR;
+use crate::suggest::POPULAR_PACKAGES;
+use crate::{
+    delete_package, install_packages, install_packages_parallel, list_packages, update_package,
+    update_packages_parallel, ColorChoice, DiffFormat, GraphFormat, HistoryFormat, InitFormat,
+    InstallReport, InstallStrategy, ListFormat, MetadataCache, PackageError, ProgressMode, Result,
+    Verbosity, DEFAULT_BASE_IMAGE, DEFAULT_BUNDLE_SHEBANG, DEFAULT_SERVE_PORT, LOCK_FILE,
+};
+use clap::Subcommand;
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// Command line interface structure
+#[derive(clap::Parser)]
+#[command(version, about, long_about = None)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Commands,
+    /// Show more detail: -v streams pip's output live, -vv also shows every command run
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+    /// Only print errors and a final machine-parsable result line
+    #[arg(short = 'q', long = "quiet", global = true, conflicts_with = "verbose")]
+    pub quiet: bool,
+    /// Print a JSON result document on stdout and human-readable output on stderr
+    #[arg(long = "json", global = true)]
+    pub json: bool,
+    /// When to use colors; `auto` honors NO_COLOR and disables colors when not writing to a terminal
+    #[arg(long = "color", value_enum, global = true, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Write a log of every pip invocation to a file (level set by PPM_LOG, default "info")
+    #[arg(long = "log-file", value_name = "PATH", global = true)]
+    pub log_file: Option<PathBuf>,
+    /// How to report progress: an interactive bar, or newline-delimited JSON events on stderr
+    #[arg(long = "progress", value_enum, global = true, default_value_t = ProgressMode::Bar)]
+    pub progress: ProgressMode,
+    /// Non-interactive mode for pipelines: no prompts or progress bars (also set by PPM_CI=1)
+    #[arg(long = "ci", global = true)]
+    pub ci: bool,
+    /// Fail with exit code 10 if any warning was emitted
+    #[arg(long = "deny-warnings", global = true)]
+    pub deny_warnings: bool,
+    /// Answer yes to confirmation prompts, e.g. in scripts
+    #[arg(short = 'y', long = "yes", global = true)]
+    pub yes: bool,
+    /// Maximum number of packages processed at once by parallel operations
+    /// (defaults to `jobs` in ppm.toml, then the number of CPUs)
+    #[arg(
+        short = 'j',
+        long = "jobs",
+        global = true,
+        value_name = "N",
+        value_parser = clap::value_parser!(u16).range(1..)
+    )]
+    pub jobs: Option<u16>,
+    /// Work on a named environment (see `ppm env list`) instead of the default one
+    #[arg(long = "env", value_name = "NAME", global = true)]
+    pub env: Option<String>,
+    /// Install packages into __pypackages__/<X.Y>/lib (PEP 582) instead of the interpreter's
+    /// environment; on by default when ppm.toml sets `pypackages = true` or the directory exists
+    #[arg(long = "pypackages", global = true, conflicts_with = "env")]
+    pub pypackages: bool,
+}
+
+impl Cli {
+    /// Output level selected by the `--quiet` and `--verbose` flags
+    pub fn verbosity(&self) -> Verbosity {
+        match (self.quiet, self.verbose) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        }
+    }
+
+    /// Whether CI mode was requested with `--ci` or the `PPM_CI` environment variable
+    pub fn ci_mode(&self) -> bool {
+        ci_requested(self.ci, std::env::var_os(CI_ENV).as_deref())
+    }
+}
+
+/// Environment variable enabling CI mode
+pub const CI_ENV: &str = "PPM_CI";
+
+/// Decides whether CI mode is on
+///
+/// `PPM_CI` counts as set unless it is empty, `0` or `false`.
+fn ci_requested(flag: bool, env: Option<&std::ffi::OsStr>) -> bool {
+    flag || env.is_some_and(|value| {
+        !matches!(
+            value.to_string_lossy().trim().to_lowercase().as_str(),
+            "" | "0" | "false"
+        )
+    })
+}
+
+/// Available CLI commands
+#[derive(Subcommand)]
+pub enum Commands {
+    /// Install Python packages
+    Install {
+        /// List of packages to install (can include version specs like "package==1.0.0");
+        /// "-" reads them from stdin
+        #[arg(add = ArgValueCandidates::new(install_name_candidates))]
+        packages: Vec<String>,
+        /// Install packages in parallel for faster execution
+        #[arg(short = 'p', long = "parallel", help = "Install packages in parallel")]
+        parallel: bool,
+        /// Install every package listed in a requirements file
+        #[arg(
+            short = 'r',
+            long = "requirements",
+            value_name = "FILE",
+            conflicts_with = "packages"
+        )]
+        requirements: Option<String>,
+        /// Install the requirements of a profile such as "dev" or "prod", following
+        /// its -r includes (requirements/<NAME>.txt unless ppm.toml maps it)
+        #[arg(
+            long = "profile",
+            value_name = "NAME",
+            conflicts_with_all = ["packages", "requirements"]
+        )]
+        profile: Option<String>,
+        /// Install the requirements of every workspace member listed in ppm.toml
+        #[arg(long = "workspace", conflicts_with_all = ["packages", "requirements", "profile"])]
+        workspace: bool,
+        /// Roll back every package of the batch if any of them fails
+        #[arg(long = "atomic", conflicts_with = "workspace")]
+        atomic: bool,
+        /// Continue an interrupted or failed install from a requirements file
+        #[arg(
+            long = "resume",
+            conflicts_with_all = ["packages", "requirements", "profile", "workspace"]
+        )]
+        resume: bool,
+        /// Install exactly the packages of a lockfile, verifying the hash of every archive
+        #[arg(
+            long = "lock",
+            value_name = "FILE",
+            num_args = 0..=1,
+            default_missing_value = LOCK_FILE,
+            conflicts_with_all = ["packages", "requirements", "profile", "workspace", "resume"]
+        )]
+        lock: Option<PathBuf>,
+        /// Verify the PyPI attestations (PEP 740) of every downloaded file and fail
+        /// unless all of them verify
+        #[arg(long = "verify-attestations", conflicts_with_all = ["workspace", "resume", "lock"])]
+        verify_attestations: bool,
+        /// Install packages whose names are one or two letters away from a popular
+        /// package without asking, e.g. when "pyaml" rather than "pyyaml" is meant
+        #[arg(long = "allow-similar-name")]
+        allow_similar_name: bool,
+        /// Install into several interpreters at once, e.g. "3.9,3.10,3.12" (versions are
+        /// looked up as python<VERSION> on PATH and, on Windows, in the registry; "3.12-32"
+        /// asks for a 32-bit install; paths are used as they are), and print a result
+        /// matrix; the registry is left alone
+        #[arg(
+            long = "python",
+            value_name = "VERSIONS",
+            value_delimiter = ',',
+            conflicts_with_all = ["parallel", "workspace", "atomic", "resume", "lock", "verify_attestations"]
+        )]
+        python: Vec<String>,
+        /// Arguments after `--` are passed to pip verbatim, e.g.
+        /// `ppm install requests -- --no-cache-dir --prefer-binary`
+        #[arg(
+            last = true,
+            value_name = "PIP_ARGS",
+            conflicts_with_all = ["workspace", "resume", "lock", "python"]
+        )]
+        pip_args: Vec<String>,
+    },
+    /// Make the environment match a requirements file: install what is missing and
+    /// remove explicitly installed packages that are no longer listed
+    Sync {
+        /// Requirements file (defaults to the one in ppm.toml, then requirements.txt)
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Sync to the requirements of a profile such as "dev" or "prod"
+        #[arg(long = "profile", value_name = "NAME", conflicts_with = "requirements")]
+        profile: Option<String>,
+        /// Sync every workspace member listed in ppm.toml
+        #[arg(long = "workspace", conflicts_with_all = ["requirements", "profile"])]
+        workspace: bool,
+        /// Install packages whose names are one or two letters away from a popular
+        /// package without asking
+        #[arg(long = "allow-similar-name", conflicts_with = "workspace")]
+        allow_similar_name: bool,
+    },
+    /// Resolve the requirements file into a pylock.toml lockfile with archive hashes
+    Lock {
+        /// Requirements file (defaults to the one in ppm.toml, then requirements.txt)
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Lockfile to write or check
+        #[arg(short = 'o', long = "output", value_name = "FILE", default_value = LOCK_FILE)]
+        output: PathBuf,
+        /// Only check that the lockfile matches a fresh resolution; exits non-zero if it is stale
+        #[arg(long = "check")]
+        check: bool,
+    },
+    /// Check that the installed packages, their archive hashes and files match the
+    /// lockfile; exits non-zero on any drift
+    Verify {
+        /// Lockfile to verify against
+        #[arg(
+            long = "lock",
+            value_name = "FILE",
+            num_args = 0..=1,
+            default_missing_value = LOCK_FILE,
+            required = true
+        )]
+        lock: PathBuf,
+    },
+    /// Pin every entry of a requirements file to the installed (or latest) version,
+    /// keeping comments, ordering and markers
+    PinFile {
+        /// Requirements file (defaults to the one in ppm.toml, then requirements.txt)
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Pin to the newest release supporting the interpreter instead of the installed version
+        #[arg(long = "latest")]
+        latest: bool,
+        /// Show the new pins without writing the file
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Declare dependencies in pyproject.toml (or the requirements file) and install them
+    Add {
+        /// Requirements to add, e.g. "requests>=2.31"
+        #[arg(required = true, add = ArgValueCandidates::new(install_name_candidates))]
+        specs: Vec<String>,
+        /// Declare them in this requirements file instead of pyproject.toml
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Declare them in this group of [project.optional-dependencies]
+        #[arg(
+            long = "optional",
+            value_name = "GROUP",
+            conflicts_with = "requirements"
+        )]
+        optional: Option<String>,
+    },
+    /// Remove dependencies from pyproject.toml and the requirements file and uninstall them
+    Remove {
+        /// Names of the packages to remove
+        #[arg(required = true, add = ArgValueCandidates::new(registry_name_candidates))]
+        names: Vec<String>,
+        /// Requirements file to remove them from (defaults to the one in ppm.toml, then
+        /// requirements.txt)
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Remove the packages even if other packages depend on them
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Delete Python packages
+    Delete {
+        /// Names or glob patterns (e.g. "boto3*") of the packages to delete; "-" reads
+        /// them from stdin
+        #[arg(required = true, add = ArgValueCandidates::new(registry_name_candidates))]
+        names: Vec<String>,
+        /// Also remove every package that depends on them
+        #[arg(long = "cascade", conflicts_with = "force")]
+        cascade: bool,
+        /// Remove the packages even if other packages depend on them
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Update Python packages to specific or latest versions
+    Update {
+        /// Package name and version or range ("numpy 1.24.0", "numpy '<2'"), or several
+        /// "name==version" pairs, ranges like "numpy~=1.26" and bare names (upgraded to
+        /// the latest release)
+        #[arg(add = ArgValueCandidates::new(registry_name_candidates))]
+        targets: Vec<String>,
+        /// Read "name==version" pairs from a file
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Allow pre-releases when resolving the latest version
+        #[arg(long = "pre")]
+        pre: bool,
+        /// Print the pending upgrades of the named packages (default: all registered ones)
+        /// as markdown, e.g. for a pull request description, without applying anything
+        #[arg(long = "plan", conflicts_with_all = ["requirements", "pre"])]
+        plan: bool,
+    },
+    /// Convert between requirements.txt, pyproject.toml and Pipfile, reporting whatever
+    /// the target format cannot express
+    Convert {
+        /// File to read; its format is taken from the name (pyproject.toml, Pipfile,
+        /// anything else is a requirements file)
+        #[arg(long = "from", value_name = "FILE")]
+        from: PathBuf,
+        /// File to write, in the format its name implies
+        #[arg(long = "to", value_name = "FILE")]
+        to: PathBuf,
+        /// Replace the target if it exists; a pyproject.toml keeps its other settings
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Format a requirements file: normalize names, spacing and specifiers and sort the entries
+    Fmt {
+        /// Requirements file (defaults to the one in ppm.toml, then requirements.txt)
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Only check the formatting; exits non-zero if the file would change
+        #[arg(long = "check")]
+        check: bool,
+    },
+    /// Move one dependency to a new version in the requirements files, pyproject.toml
+    /// and the environment at once
+    Bump {
+        /// The package to bump
+        #[arg(add = ArgValueCandidates::new(registry_name_candidates))]
+        name: String,
+        /// Newest release with the same major version
+        #[arg(long = "minor", conflicts_with_all = ["patch", "to"])]
+        minor: bool,
+        /// Newest release with the same major and minor version
+        #[arg(long = "patch", conflicts_with = "to")]
+        patch: bool,
+        /// Exact version to bump to (default: the newest release)
+        #[arg(long = "to", value_name = "VERSION")]
+        to: Option<String>,
+        /// Requirements files to edit (default: the one in ppm.toml, then requirements.txt);
+        /// pyproject.toml is edited too when it exists
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Vec<String>,
+        /// Allow pre-releases when resolving the version
+        #[arg(long = "pre")]
+        pre: bool,
+    },
+    /// Hold packages at their current version during upgrades
+    Pin {
+        /// Names of the packages to pin
+        #[arg(required = true, add = ArgValueCandidates::new(registry_name_candidates))]
+        names: Vec<String>,
+    },
+    /// Release previously pinned packages
+    Unpin {
+        /// Names of the packages to unpin
+        #[arg(required = true, add = ArgValueCandidates::new(registry_name_candidates))]
+        names: Vec<String>,
+    },
+    /// List all installed packages
+    List {
+        /// Annotate packages with the latest version known from the local metadata cache
+        #[arg(long = "outdated")]
+        outdated: bool,
+        /// Output format
+        #[arg(long = "format", value_enum, default_value_t = ListFormat::Table)]
+        format: ListFormat,
+        /// Show the installed size of each package, largest first
+        #[arg(long = "sizes")]
+        sizes: bool,
+        /// Add the sha256 hashes of each release to freeze and JSON output, for
+        /// pip's --require-hashes mode
+        #[arg(long = "hashes")]
+        hashes: bool,
+        /// Only list the packages a profile needs, e.g. `--format freeze --profile prod`
+        #[arg(long = "profile", value_name = "NAME")]
+        profile: Option<String>,
+    },
+    /// Show the installed disk usage of packages
+    Size {
+        /// Package to measure (defaults to every registered package)
+        #[arg(add = ArgValueCandidates::new(registry_name_candidates))]
+        package: Option<String>,
+    },
+    /// Create dependency files, a ppm.toml project config and optionally a virtual environment
+    Init {
+        /// Project name (defaults to the directory name)
+        #[arg(long = "name")]
+        name: Option<String>,
+        /// Supported Python versions, written as requires-python
+        #[arg(long = "python", value_name = "SPECIFIER")]
+        python: Option<String>,
+        /// Which dependency files to create
+        #[arg(long = "format", value_enum)]
+        format: Option<InitFormat>,
+        /// Create a virtual environment at this path (".venv" if no path is given)
+        #[arg(long = "venv", value_name = "PATH", num_args = 0..=1, default_missing_value = ".venv")]
+        venv: Option<PathBuf>,
+    },
+    /// Compare two package sets: registries (packages.json), requirements files,
+    /// lockfiles (poetry.lock, uv.lock, pylock.toml) or live environments
+    /// ("env" for the active one, "env:<python>" or a virtualenv directory)
+    Diff {
+        /// The baseline, e.g. "production/packages.json"
+        a: String,
+        /// The package set compared against the baseline, e.g. "env"
+        b: String,
+        /// Output format
+        #[arg(long = "format", value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+    },
+    /// Print the dependency graph of the registered packages for Graphviz or Mermaid
+    Graph {
+        /// Output format
+        #[arg(long = "format", value_enum, default_value_t = GraphFormat::Dot)]
+        format: GraphFormat,
+        /// Only show this package and what it depends on
+        #[arg(long = "root", add = ArgValueCandidates::new(registry_name_candidates))]
+        root: Option<String>,
+    },
+    /// Show what previous commands installed, changed and removed
+    History {
+        /// Only show the most recent N operations
+        #[arg(long = "limit", short = 'n')]
+        limit: Option<usize>,
+        /// Only show operations that touched this package
+        #[arg(long = "package", add = ArgValueCandidates::new(registry_name_candidates))]
+        package: Option<String>,
+        /// Output format
+        #[arg(long = "format", value_enum, default_value_t = HistoryFormat::Text)]
+        format: HistoryFormat,
+    },
+    /// Reverse the most recent install, update or delete recorded in the history
+    Undo {
+        /// Only show what would be reversed
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Save the installed environment as a restore point, or return to one
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// Manage named environments, each with its own virtual environment and packages
+    Env {
+        #[command(subcommand)]
+        action: EnvAction,
+    },
+    /// Check or renew the checksum that protects packages.json against tampering
+    Registry {
+        #[command(subcommand)]
+        action: RegistryAction,
+    },
+    /// List the Python interpreters installed on this machine, or install one with pyenv
+    Python {
+        #[command(subcommand)]
+        action: PythonAction,
+    },
+    /// Check whether the interpreter's pip supports the features ppm uses, or upgrade it
+    Pip {
+        #[command(subcommand)]
+        action: PipAction,
+    },
+    /// List the commands (console scripts) installed packages provide
+    Scripts {
+        /// Only show the commands of this package
+        #[arg(add = ArgValueCandidates::new(registry_name_candidates))]
+        package: Option<String>,
+    },
+    /// Remove dependencies that are no longer required by any explicit package
+    Autoremove,
+    /// Remove registry entries whose packages are no longer installed, e.g. after
+    /// `pip uninstall`; the environment itself is left alone
+    Prune {
+        /// Only list the stale entries
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// Show timings of the last run and historical averages
+    Stats,
+    /// Prefetch PyPI metadata for the registered packages, or those in a requirements file,
+    /// so later commands work quickly on slow networks or offline
+    WarmCache {
+        /// Prefetch the packages listed in this requirements file instead
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Also download the packages' wheels into pip's cache
+        #[arg(long = "wheels")]
+        wheels: bool,
+    },
+    /// Time sequential, parallel and batched installs of a requirements file, each into
+    /// a throwaway virtual environment, to pick the fastest mode for a workload
+    Benchmark {
+        /// Requirements file whose packages are installed
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: String,
+        /// Strategies to time, comma-separated; all of them by default
+        #[arg(long = "strategy", value_enum, value_delimiter = ',')]
+        strategies: Vec<InstallStrategy>,
+        /// Python version or interpreter path the environments are created with
+        #[arg(long = "python", value_name = "VERSION")]
+        python: Option<String>,
+    },
+    /// Write a Dockerfile, .dockerignore and hash-pinned requirements that build the
+    /// managed environment into an image
+    Containerize {
+        /// Base image of the build and final stages
+        #[arg(long = "base", value_name = "IMAGE", default_value = DEFAULT_BASE_IMAGE)]
+        base: String,
+        /// Lockfile to pin from; without it, the registry's packages are pinned
+        #[arg(long = "lock", value_name = "FILE", default_value = LOCK_FILE)]
+        lock: PathBuf,
+        /// Directory to write the files into
+        #[arg(short = 'o', long = "output", value_name = "DIR", default_value = ".")]
+        output: PathBuf,
+        /// Replace files that already exist
+        #[arg(long = "force")]
+        force: bool,
+    },
+    /// Build the sdist and wheel of a project with the PEP 517 frontend `build`, which is
+    /// installed into an environment of its own on first use
+    Build {
+        /// The project directory
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// Directory to write the distributions to (defaults to dist/ in the project)
+        #[arg(short = 'o', long = "outdir", value_name = "DIR")]
+        outdir: Option<PathBuf>,
+        /// Build only the source distribution
+        #[arg(long = "sdist", conflicts_with = "wheel")]
+        sdist: bool,
+        /// Build only the wheel
+        #[arg(long = "wheel")]
+        wheel: bool,
+        /// Use the build dependencies already in the tool environment instead of an
+        /// isolated environment
+        #[arg(long = "no-isolation")]
+        no_isolation: bool,
+    },
+    /// Pack the project and its locked dependencies into a single deployable file
+    Bundle {
+        #[command(subcommand)]
+        action: BundleAction,
+    },
+    /// Install a requirements file into a plain directory with pip --target, e.g. for a
+    /// zip deployment, removing what it no longer needs from earlier runs
+    VendorInstall {
+        /// Requirements file (defaults to the one the directory was last synced to)
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Directory to install into
+        #[arg(long = "into", value_name = "DIR", default_value = "vendor")]
+        into: PathBuf,
+        /// Remove every vendored package first; without -r, only clean the directory
+        /// and stop tracking it
+        #[arg(long = "clean")]
+        clean: bool,
+        /// Arguments after `--` are passed to pip verbatim, e.g.
+        /// `-- --platform manylinux2014_x86_64 --only-binary=:all:`
+        #[arg(last = true, value_name = "PIP_ARGS")]
+        pip_args: Vec<String>,
+    },
+    /// Sync the environment whenever the requirements file changes, printing what changed
+    Watch {
+        /// Requirements file to watch (defaults to the one in ppm.toml, then requirements.txt)
+        #[arg(short = 'r', long = "requirements", value_name = "FILE")]
+        requirements: Option<String>,
+        /// Also watch ppm.toml, following it when it names another requirements file
+        #[arg(long = "project", conflicts_with = "requirements")]
+        project: bool,
+    },
+    /// Run a local simple-index proxy that caches PyPI metadata and files,
+    /// for use with `pip install --index-url http://<host>:<port>/simple/`
+    Serve {
+        /// Address to listen on; use 0.0.0.0 to serve other machines
+        #[arg(long = "host", default_value = "127.0.0.1")]
+        host: String,
+        /// Port to listen on
+        #[arg(short = 'p', long = "port", default_value_t = DEFAULT_SERVE_PORT)]
+        port: u16,
+    },
+    /// Explain an error code such as PPM0003
+    Explain {
+        /// The error code shown in the error message
+        code: String,
+    },
+    /// Run a program inside the managed Python environment, e.g. `ppm run pytest -x`
+    Run {
+        /// The program followed by its arguments
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        command: Vec<String>,
+    },
+    /// Runs a `ppm-<name>` plugin found on PATH
+    #[command(external_subcommand)]
+    External(Vec<String>),
+    /// Write man pages for ppm and its subcommands into a directory
+    #[command(hide = true)]
+    GenerateManpages {
+        /// Directory to write the `.1` files into; created if missing
+        dir: PathBuf,
+    },
+    /// Upgrade packages to their latest published versions
+    Upgrade {
+        /// Names of the packages to upgrade
+        #[arg(add = ArgValueCandidates::new(registry_name_candidates))]
+        packages: Vec<String>,
+        /// Upgrade every package in the registry
+        #[arg(long = "all", help = "Upgrade all registered packages")]
+        all: bool,
+        /// Only show the planned upgrades without applying them
+        #[arg(long = "dry-run", help = "Show planned upgrades without applying them")]
+        dry_run: bool,
+        /// Write a change proposal report, e.g. "html=upgrade-report.html" or "md=upgrades.md"
+        #[arg(long = "report", value_name = "FORMAT=PATH")]
+        report: Option<String>,
+        /// Never upgrade beyond the ceilings listed in a constraints file
+        #[arg(short = 'c', long = "constraint", value_name = "FILE")]
+        constraint: Option<String>,
+        /// Choose which outdated packages to upgrade from a checklist
+        #[arg(short = 'i', long = "interactive", conflicts_with = "dry_run")]
+        interactive: bool,
+        /// Ignore the update policies of ppm.toml and allow new major versions
+        #[arg(long = "allow-major")]
+        allow_major: bool,
+    },
+    /// List registered packages with newer releases and the advisories an upgrade fixes,
+    /// posting a summary to the webhook of ppm.toml when any are found
+    Outdated {
+        /// Write the findings to a file, e.g. "report.json" or "html=report.html"
+        #[arg(long = "report", value_name = "PATH")]
+        report: Option<String>,
+        /// Do not post to the configured webhook
+        #[arg(long = "no-notify")]
+        no_notify: bool,
+    },
+    /// Summarize the releases between two versions of a package before upgrading
+    Changes {
+        /// The package, e.g. "requests"
+        #[arg(add = ArgValueCandidates::new(registry_name_candidates))]
+        package: String,
+        /// The versions, e.g. "2.25.0..2.32.3"; without a start the installed
+        /// version is used, without an end the latest release
+        #[arg(value_name = "FROM..TO", default_value = "..")]
+        range: String,
+    },
+    /// Show a package's latest release, release cadence and maintainers, and how far
+    /// the installed version is behind
+    Info {
+        /// The package, or "name==version" to compare that version instead of the installed one
+        #[arg(add = ArgValueCandidates::new(registry_name_candidates))]
+        package: String,
+    },
+}
+
+/// Prefix of the executables that provide plugin subcommands
+pub const PLUGIN_PREFIX: &str = "ppm-";
+
+/// Finds the executable providing a plugin subcommand
+///
+/// # Arguments
+/// * `name` - The subcommand name, e.g. "audit" for `ppm-audit`
+/// * `search_path` - A PATH-style list of directories
+///
+/// # Returns
+/// * `Option<PathBuf>` - The first matching executable file
+pub fn find_plugin(name: &str, search_path: &std::ffi::OsStr) -> Option<PathBuf> {
+    let file_name = format!("{}{}{}", PLUGIN_PREFIX, name, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(search_path)
+        .map(|dir| dir.join(&file_name))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// Whether a path is a file the current user may execute
+fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = path.metadata() else {
+        return false;
+    };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
+
+/// Runs a plugin subcommand, passing the remaining arguments through
+///
+/// The plugin inherits stdio and receives `PPM_PYTHON` (the active
+/// interpreter, when one is found), `PPM_REGISTRY` (absolute path of
+/// `packages.json`), `PPM_STATE_DIR` and `PPM_VERSION`.
+///
+/// # Arguments
+/// * `args` - Subcommand name followed by its arguments
+///
+/// # Returns
+/// * `Result<i32>` - The plugin's exit code, or an error if no plugin exists
+pub fn run_plugin(args: &[String]) -> Result<i32> {
+    let (name, rest) = args
+        .split_first()
+        .ok_or_else(|| PackageError::InvalidPackageSpec("No command given".to_string()))?;
+    let search_path = std::env::var_os("PATH").unwrap_or_default();
+    let plugin = find_plugin(name, &search_path).ok_or_else(|| {
+        PackageError::InvalidPackageSpec(format!(
+            "Unknown command `{}` (no {}{} executable on PATH)",
+            name, PLUGIN_PREFIX, name
+        ))
+    })?;
+
+    let cwd = std::env::current_dir()?;
+    let mut command = Command::new(&plugin);
+    command
+        .args(rest)
+        .env("PPM_REGISTRY", cwd.join("packages.json"))
+        .env("PPM_STATE_DIR", cwd.join(STATE_DIR))
+        .env("PPM_VERSION", env!("CARGO_PKG_VERSION"));
+    if let Ok(python) = get_python_executable() {
+        command.env("PPM_PYTHON", python);
+    }
+    apply_local_packages(&mut command);
+
+    debug_detail!("Running plugin {}", plugin.display());
+    let status = command.status()?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Script that prints the scripts directory, prefix and base prefix of the interpreter
+const ENVIRONMENT_SCRIPT: &str = "import sys, sysconfig\n\
+print(sysconfig.get_path('scripts'))\n\
+print(sys.prefix)\n\
+print(sys.base_prefix)";
+
+/// Locations of the Python environment ppm manages
+#[derive(Debug, Clone, PartialEq)]
+pub struct PythonEnvironment {
+    /// The interpreter executable
+    pub python: String,
+    /// Directory holding the environment's console scripts (`bin` or `Scripts`)
+    pub scripts_dir: PathBuf,
+    /// The environment prefix; a virtual environment's directory when one is active
+    pub prefix: PathBuf,
+    /// Whether the prefix is a virtual environment rather than a base installation
+    pub is_virtualenv: bool,
+}
+
+impl PythonEnvironment {
+    /// Inspects the environment of the selected interpreter
+    pub fn detect() -> Result<Self> {
+        let python = get_python_executable()?;
+        let output = run_command(Command::new(&python).arg("-c").arg(ENVIRONMENT_SCRIPT))?;
+        if !output.status.success() {
+            return Err(PackageError::PythonNotFound);
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines().map(str::trim);
+        let (Some(scripts_dir), Some(prefix), Some(base_prefix)) =
+            (lines.next(), lines.next(), lines.next())
+        else {
+            return Err(PackageError::PythonNotFound);
+        };
+
+        Ok(PythonEnvironment {
+            python,
+            scripts_dir: PathBuf::from(scripts_dir),
+            prefix: PathBuf::from(prefix),
+            is_virtualenv: prefix != base_prefix,
+        })
+    }
+
+    /// PATH with the environment's scripts directory placed first
+    ///
+    /// # Arguments
+    /// * `current` - The PATH to extend, if any
+    pub fn search_path(&self, current: Option<&std::ffi::OsStr>) -> Result<std::ffi::OsString> {
+        let mut dirs = vec![self.scripts_dir.clone()];
+        if let Some(current) = current {
+            dirs.extend(std::env::split_paths(current).filter(|dir| *dir != self.scripts_dir));
+        }
+        std::env::join_paths(dirs).map_err(|e| {
+            PackageError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+        })
+    }
+}
+
+/// Runs a program inside the managed Python environment
+///
+/// The environment's scripts directory goes first on PATH and, for a virtual
+/// environment, `VIRTUAL_ENV` is set as activation scripts do. A bare `python`
+/// resolves to the selected interpreter. With local packages (PEP 582) on,
+/// they are put on `PYTHONPATH` and their scripts go first on PATH.
+///
+/// # Arguments
+/// * `args` - Program followed by its arguments
+///
+/// # Returns
+/// * `Result<i32>` - The program's exit code
+pub fn run_in_environment(args: &[String]) -> Result<i32> {
+    let (program, rest) = args
+        .split_first()
+        .ok_or_else(|| PackageError::InvalidPackageSpec("No program given".to_string()))?;
+    let environment = PythonEnvironment::detect()?;
+
+    let program = if program == "python" {
+        environment.python.clone()
+    } else {
+        program.clone()
+    };
+    let mut search_path = environment.search_path(std::env::var_os("PATH").as_deref())?;
+    if let Some(lib) = local_packages() {
+        // pip --target puts console scripts in <target>/bin
+        let dirs = std::iter::once(lib.join("bin")).chain(std::env::split_paths(&search_path));
+        search_path = std::env::join_paths(dirs.collect::<Vec<_>>()).map_err(|e| {
+            PackageError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+        })?;
+    }
+
+    let mut command = Command::new(&program);
+    command
+        .args(rest)
+        .env("PATH", &search_path)
+        .env_remove("PYTHONHOME");
+    if environment.is_virtualenv {
+        command.env("VIRTUAL_ENV", &environment.prefix);
+    }
+    apply_local_packages(&mut command);
+
+    debug_detail!(
+        "Running {} with {} first on PATH",
+        program,
+        environment.scripts_dir.display()
+    );
+    let status = command.status().map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            PackageError::IoError(std::io::Error::new(
+                e.kind(),
+                format!(
+                    "Program not found in the environment or on PATH: {}",
+                    program
+                ),
+            ))
+        } else {
+            PackageError::IoError(e)
+        }
+    })?;
+    Ok(status.code().unwrap_or(1))
+}
+
+/// Renders man pages for the CLI and every visible subcommand
+///
+/// The top-level page is written as `ppm.1` and each subcommand as
+/// `ppm-<name>.1`, all generated from the clap argument definitions.
+///
+/// # Arguments
+/// * `dir` - Output directory, created if missing
+///
+/// # Returns
+/// * `Result<Vec<PathBuf>>` - Paths of the written pages
+pub fn generate_manpages(dir: &Path) -> Result<Vec<PathBuf>> {
+    std::fs::create_dir_all(dir)?;
+
+    let mut command = <Cli as clap::CommandFactory>::command()
+        .name("ppm")
+        .bin_name("ppm");
+    // Building propagates global arguments such as --verbose into the subcommands
+    // and names them "ppm-<name>"
+    command.build();
+
+    let mut written = Vec::new();
+    let mut pages = vec![command];
+    while let Some(command) = pages.pop() {
+        pages.extend(
+            command
+                .get_subcommands()
+                .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+                .cloned(),
+        );
+
+        let page_name = command
+            .get_display_name()
+            .unwrap_or_else(|| command.get_name())
+            .to_string();
+        let path = dir.join(format!("{}.1", page_name));
+        let mut buffer = Vec::new();
+        clap_mangen::Man::new(command).render(&mut buffer)?;
+        std::fs::write(&path, buffer)?;
+        written.push(path);
+    }
+
+    written.sort();
+    Ok(written)
+}
+
+/// Time changes are collected for after the first one, so that one save
+/// touching a file several times is handled once
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Watches files and calls `on_change` with those that changed, until the process is stopped
+///
+/// The directories holding the files are watched rather than the files
+/// themselves, so editors that save by replacing a file are noticed too, as
+/// are files created after the watch started.
+///
+/// # Arguments
+/// * `paths` - The files to watch; they need not exist yet
+/// * `on_change` - Called with the changed files; returns the files to watch from then on
+///
+/// # Returns
+/// * `Result<()>` - An error if a directory could not be watched or `on_change` failed
+pub fn watch_files(
+    paths: Vec<PathBuf>,
+    mut on_change: impl FnMut(&[PathBuf]) -> Result<Vec<PathBuf>>,
+) -> Result<()> {
+    let watch_error = |e: notify::Error| PackageError::IoError(std::io::Error::other(e));
+    let (sender, events) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(watch_error)?;
+    let mut directories: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut paths = paths;
+
+    loop {
+        let files: BTreeSet<PathBuf> = paths
+            .iter()
+            .map(std::path::absolute)
+            .collect::<std::io::Result<_>>()?;
+        let wanted: BTreeSet<PathBuf> = files
+            .iter()
+            .filter_map(|file| file.parent().map(Path::to_path_buf))
+            .collect();
+        for dir in directories.difference(&wanted) {
+            let _ = watcher.unwatch(dir);
+        }
+        for dir in wanted.difference(&directories) {
+            watcher
+                .watch(dir, RecursiveMode::NonRecursive)
+                .map_err(watch_error)?;
+        }
+        directories = wanted;
+
+        let relevant = |event: notify::Result<notify::Event>| -> Vec<PathBuf> {
+            match event {
+                Ok(event) if !matches!(event.kind, EventKind::Access(_)) => event
+                    .paths
+                    .into_iter()
+                    .filter(|p| files.contains(p))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+        let mut changed: BTreeSet<PathBuf> = BTreeSet::new();
+        while changed.is_empty() {
+            match events.recv() {
+                Ok(event) => changed.extend(relevant(event)),
+                Err(_) => return Ok(()),
+            }
+        }
+        let deadline = std::time::Instant::now() + WATCH_DEBOUNCE;
+        while let Ok(event) =
+            events.recv_timeout(deadline.saturating_duration_since(std::time::Instant::now()))
+        {
+            changed.extend(relevant(event));
+        }
+        paths = on_change(&changed.into_iter().collect::<Vec<_>>())?;
+    }
+}
+
+/// Completion candidates for commands that act on registered packages
+///
+/// Runs while the shell completes a command line, so it never prints and
+/// treats an unreadable registry as empty.
+pub fn registry_name_candidates() -> Vec<CompletionCandidate> {
+    registry_candidates(Path::new("packages.json"))
+}
+
+/// Names and versions of the packages in a registry file, sorted by name
+fn registry_candidates(path: &Path) -> Vec<CompletionCandidate> {
+    let registry: PackageRegistry = File::open(path)
+        .ok()
+        .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+        .unwrap_or_default();
+    let mut packages: Vec<&Package> = registry.packages.values().collect();
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+    packages
+        .into_iter()
+        .map(|package| {
+            CompletionCandidate::new(&package.name).help(Some(package.version.clone().into()))
+        })
+        .collect()
+}
+
+/// Completion candidates for `install`: popular PyPI packages and names in the metadata cache
+pub fn install_name_candidates() -> Vec<CompletionCandidate> {
+    let cache = MetadataCache::load();
+    let mut names: Vec<&str> = POPULAR_PACKAGES.to_vec();
+    names.extend(cache.entries.keys().map(String::as_str));
+    names.sort_unstable();
+    names.dedup();
+    names.into_iter().map(CompletionCandidate::new).collect()
+}
+
+/// Trait defining package management operations
+pub trait PackageManager {
+    /// Installs packages sequentially
+    fn install_packages(
+        &self,
+        packages: &[String],
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport>;
+
+    /// Installs packages in parallel
+    fn install_packages_parallel(
+        &self,
+        packages: &[String],
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport>;
+
+    /// Deletes a single package
+    fn delete_package(&self, name: &str, registry: &mut PackageRegistry) -> Result<()>;
+
+    /// Updates a package to a specific version
+    fn update_package(
+        &self,
+        name: &str,
+        version: &str,
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport>;
+
+    /// Updates several packages to specific versions in parallel
+    fn update_packages_parallel(
+        &self,
+        updates: &[(String, String)],
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport>;
+
+    /// Lists all packages in the registry
+    fn list_packages(&self, registry: &PackageRegistry);
+
+    /// Installs packages from a requirements file
+    fn install_from_requirements(
+        &self,
+        path: &str,
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport>;
+
+    /// Installs packages from a requirements file in parallel
+    fn install_from_requirements_parallel(
+        &self,
+        path: &str,
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport>;
+}
+
+impl PackageManager for Cli {
+    fn install_packages(
+        &self,
+        packages: &[String],
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport> {
+        install_packages(packages, registry)
+    }
+
+    fn install_packages_parallel(
+        &self,
+        packages: &[String],
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport> {
+        install_packages_parallel(packages, registry)
+    }
+
+    fn delete_package(&self, name: &str, registry: &mut PackageRegistry) -> Result<()> {
+        delete_package(name, registry)
+    }
+
+    fn update_package(
+        &self,
+        name: &str,
+        version: &str,
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport> {
+        update_package(name, version, registry)
+    }
+
+    fn update_packages_parallel(
+        &self,
+        updates: &[(String, String)],
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport> {
+        update_packages_parallel(updates, registry)
+    }
+
+    fn list_packages(&self, registry: &PackageRegistry) {
+        list_packages(registry)
+    }
+
+    fn install_from_requirements(
+        &self,
+        path: &str,
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport> {
+        install_from_requirements(path, registry)
+    }
+
+    fn install_from_requirements_parallel(
+        &self,
+        path: &str,
+        registry: &mut PackageRegistry,
+    ) -> Result<InstallReport> {
+        install_from_requirements_parallel(path, registry)
+    }
+}
+
+/// Actions of the snapshot command
+#[derive(Subcommand, Debug)]
+pub enum SnapshotAction {
+    /// Capture every installed distribution (with file hashes) and the registry
+    Create {
+        /// Snapshot name, e.g. "before-django-5"
+        name: String,
+    },
+    /// Install and uninstall the difference to return the environment to a snapshot
+    Restore {
+        /// Snapshot name
+        name: String,
+        /// Only show the changes
+        #[arg(long = "dry-run")]
+        dry_run: bool,
+    },
+    /// List saved snapshots
+    List,
+    /// Delete a snapshot
+    Delete {
+        /// Snapshot name
+        name: String,
+    },
+}
+
+/// Actions of the env command
+#[derive(Subcommand, Debug)]
+pub enum EnvAction {
+    /// List the default environment and every named one
+    List,
+    /// Create a named environment with its own virtual environment
+    Create {
+        /// Environment name, e.g. "api" or "worker"
+        name: String,
+        /// Virtual environment to use, created unless it exists (defaults to .ppm/envs/<NAME>)
+        #[arg(long = "venv", value_name = "PATH")]
+        venv: Option<PathBuf>,
+        /// Interpreter that creates the virtual environment (defaults to the one on PATH)
+        #[arg(long = "python", value_name = "INTERPRETER")]
+        python: Option<String>,
+    },
+    /// Create a named environment with the exact package set (versions and sources) of another
+    Clone {
+        /// Environment to copy ("default" for the default environment)
+        source: String,
+        /// Name of the new environment
+        target: String,
+        /// Virtual environment to create (defaults to .ppm/envs/<TARGET>)
+        #[arg(long = "venv", value_name = "PATH")]
+        venv: Option<PathBuf>,
+        /// Interpreter that creates the virtual environment (defaults to the source's)
+        #[arg(long = "python", value_name = "INTERPRETER")]
+        python: Option<String>,
+    },
+    /// Stop tracking a named environment
+    Remove {
+        /// Environment name
+        name: String,
+        /// Delete its virtual environment as well
+        #[arg(long = "delete-venv")]
+        delete_venv: bool,
+    },
+}
+
+/// Actions of the python command
+#[derive(Subcommand, Debug)]
+pub enum PythonAction {
+    /// List the interpreters on PATH, installed with pyenv and, on Windows, in the
+    /// registry (PEP 514)
+    List,
+    /// Install a Python version with pyenv, unless pyenv already has it
+    Install {
+        /// Version to install, e.g. "3.12" (defaults to the one in .python-version)
+        version: Option<String>,
+    },
+}
+
+/// Actions of the bundle command
+#[derive(Subcommand, Debug)]
+pub enum BundleAction {
+    /// Build a runnable zipapp of the project and its locked dependencies, which
+    /// extracts them to a cache directory on first run (like shiv)
+    App {
+        /// Entry point to run, as module:function
+        #[arg(long = "entry", value_name = "MODULE:FUNCTION")]
+        entry: String,
+        /// The bundle to write
+        #[arg(
+            short = 'o',
+            long = "output",
+            value_name = "FILE",
+            default_value = "app.pyz"
+        )]
+        output: PathBuf,
+        /// Interpreter line of the bundle
+        #[arg(long = "python-shebang", value_name = "SHEBANG", default_value = DEFAULT_BUNDLE_SHEBANG)]
+        shebang: String,
+        /// Lockfile to pin from; without it, the registry's packages are pinned
+        #[arg(long = "lock", value_name = "FILE", default_value = LOCK_FILE)]
+        lock: PathBuf,
+    },
+    /// Pack the lockfile and every archive it needs into one file, for installing
+    /// on a machine without network access
+    Create {
+        /// The bundle to write
+        #[arg(
+            short = 'o',
+            long = "output",
+            value_name = "FILE",
+            default_value = "env.bundle"
+        )]
+        output: PathBuf,
+        /// Lockfile to pin from; without it, the registry's packages are pinned
+        #[arg(long = "lock", value_name = "FILE", default_value = LOCK_FILE)]
+        lock: PathBuf,
+    },
+    /// Install a bundle written by `bundle create` without network access,
+    /// verifying the hash of every archive
+    Install {
+        /// The bundle to install
+        #[arg(value_name = "BUNDLE")]
+        bundle: PathBuf,
+        /// sha256 of the bundle as printed by `bundle create`, obtained from a trusted
+        /// channel; the install is refused if the bundle does not match it
+        #[arg(long = "sha256", value_name = "HEX")]
+        sha256: Option<String>,
+        /// Install without --sha256, trusting only the manifest inside the bundle
+        #[arg(long = "insecure-no-verify", conflicts_with = "sha256")]
+        insecure_no_verify: bool,
+    },
+}
+
+/// Actions of the pip command
+#[derive(Subcommand, Debug)]
+pub enum PipAction {
+    /// Show the pip version and which pip features it supports
+    Version,
+    /// Upgrade pip to its newest release
+    Upgrade,
+}
+
+/// Actions of the registry command
+#[derive(Subcommand, Debug)]
+pub enum RegistryAction {
+    /// Verify the checksum of packages.json (an HMAC if PPM_REGISTRY_KEY is set)
+    Verify,
+    /// Accept the current contents of packages.json and write a fresh checksum
+    Rehash,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::ProjectConfig;
+
+    #[test]
+    fn test_ci_requested() {
+        use std::ffi::OsStr;
+
+        assert!(ci_requested(true, None));
+        assert!(ci_requested(false, Some(OsStr::new("1"))));
+        assert!(ci_requested(false, Some(OsStr::new("true"))));
+        assert!(!ci_requested(false, Some(OsStr::new("0"))));
+        assert!(!ci_requested(false, Some(OsStr::new(""))));
+        assert!(!ci_requested(false, None));
+    }
+
+    #[test]
+    fn test_install_forwards_pip_args() {
+        use clap::Parser;
+
+        let parse = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Commands::Install {
+                packages,
+                parallel,
+                pip_args,
+                ..
+            } => (packages, parallel, pip_args),
+            _ => panic!("not an install"),
+        };
+
+        // Everything after `--` reaches pip as it was typed, even ppm's own flags
+        let (packages, parallel, pip_args) = parse(&[
+            "ppm",
+            "install",
+            "--parallel",
+            "requests",
+            "--",
+            "--no-cache-dir",
+            "--config-settings=--build-option=--x",
+            "-p",
+            "requests",
+        ]);
+        assert_eq!(packages, ["requests"]);
+        assert!(parallel);
+        assert_eq!(
+            pip_args,
+            [
+                "--no-cache-dir",
+                "--config-settings=--build-option=--x",
+                "-p",
+                "requests"
+            ]
+        );
+
+        // ppm flags before `--` are not forwarded
+        let (packages, parallel, pip_args) = parse(&["ppm", "install", "-p", "flask"]);
+        assert_eq!(packages, ["flask"]);
+        assert!(parallel);
+        assert!(pip_args.is_empty());
+
+        assert!(Cli::try_parse_from(["ppm", "install", "--lock", "--", "--no-deps"]).is_err());
+    }
+
+    #[test]
+    fn test_install_python_flags() {
+        use clap::Parser;
+
+        // --allow-similar-name is honored by the matrix install
+        match Cli::try_parse_from([
+            "ppm",
+            "install",
+            "--python",
+            "3.11,3.12",
+            "--allow-similar-name",
+            "pyaml",
+        ])
+        .unwrap()
+        .command
+        {
+            Commands::Install {
+                python,
+                allow_similar_name,
+                ..
+            } => {
+                assert_eq!(python, ["3.11", "3.12"]);
+                assert!(allow_similar_name);
+            }
+            _ => panic!("not an install"),
+        }
+
+        // Flags the matrix install would ignore are refused
+        for flag in [
+            "--parallel",
+            "--atomic",
+            "--resume",
+            "--verify-attestations",
+        ] {
+            assert!(
+                Cli::try_parse_from(["ppm", "install", "--python", "3.12", flag, "requests"])
+                    .is_err(),
+                "{}",
+                flag
+            );
+        }
+        assert!(
+            Cli::try_parse_from(["ppm", "install", "--python", "3.12", "rich", "--", "-q"])
+                .is_err()
+        );
+
+        // Lockfile, resumed and synced installs can let similar names through too
+        for args in [
+            &["ppm", "install", "--lock", "--allow-similar-name"][..],
+            &["ppm", "install", "--resume", "--allow-similar-name"],
+            &["ppm", "sync", "--allow-similar-name"],
+        ] {
+            match Cli::try_parse_from(args).unwrap().command {
+                Commands::Install {
+                    allow_similar_name, ..
+                }
+                | Commands::Sync {
+                    allow_similar_name, ..
+                } => assert!(allow_similar_name, "{:?}", args),
+                _ => panic!("not an install or sync"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_complete_registered_names() {
+        use clap::CommandFactory;
+        use std::ffi::OsString;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packages.json");
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("requests".to_string(), "2.32.3".to_string()));
+        registry.add_package(Package::new("flask".to_string(), "3.0.3".to_string()));
+        crate::write_registry(&path, &registry, None).unwrap();
+
+        // The real command line, reading the registry from the temporary directory
+        // instead of the working directory
+        let mut command = Cli::command().mut_subcommand("delete", |delete| {
+            delete.mut_arg("names", |names| {
+                names.add(ArgValueCandidates::new(move || registry_candidates(&path)))
+            })
+        });
+        let complete = |command: &mut clap::Command, words: &[&str]| {
+            let args: Vec<OsString> = words.iter().map(OsString::from).collect();
+            clap_complete::engine::complete(command, args, words.len() - 1, Some(dir.path()))
+                .unwrap()
+                .into_iter()
+                .filter(|candidate| !candidate.get_value().to_string_lossy().starts_with('-'))
+                .map(|candidate| {
+                    let help = candidate.get_help().map(|help| help.to_string());
+                    (candidate.get_value().to_string_lossy().into_owned(), help)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            complete(&mut command, &["ppm", "delete", ""]),
+            [
+                ("flask".to_string(), Some("3.0.3".to_string())),
+                ("requests".to_string(), Some("2.32.3".to_string()))
+            ]
+        );
+        assert_eq!(
+            complete(&mut command, &["ppm", "delete", "re"]),
+            [("requests".to_string(), Some("2.32.3".to_string()))]
+        );
+        assert!(registry_candidates(&dir.path().join("missing.json")).is_empty());
+    }
+
+    #[test]
+    fn test_watch_files_reports_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let watched = dir.path().join("requirements.txt");
+        let other = dir.path().join("notes.txt");
+        let done = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+
+        // Keep writing until the change is seen, since the watch starts asynchronously
+        let writer = {
+            let (watched, other, done) = (watched.clone(), other.clone(), done.clone());
+            std::thread::spawn(move || {
+                while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                    std::fs::write(&other, "ignored").unwrap();
+                    std::fs::write(&watched, "rich==13.7.0\n").unwrap();
+                    std::thread::sleep(Duration::from_millis(50));
+                }
+            })
+        };
+        let mut seen = Vec::new();
+        let result = watch_files(vec![watched.clone()], |changed| {
+            seen = changed.to_vec();
+            Err(PackageError::MetadataError("stop".to_string()))
+        });
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        writer.join().unwrap();
+
+        assert!(matches!(result, Err(PackageError::MetadataError(_))));
+        assert_eq!(seen, [std::path::absolute(&watched).unwrap()]);
+    }
+
+    #[test]
+    fn test_generate_manpages() {
+        let dir = tempfile::tempdir().unwrap();
+        let pages = generate_manpages(dir.path()).unwrap();
+        let names: Vec<String> = pages
+            .iter()
+            .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+
+        assert!(names.contains(&"ppm.1".to_string()));
+        assert!(names.contains(&"ppm-install.1".to_string()));
+        assert!(!names.iter().any(|n| n.contains("generate-manpages")));
+
+        let install = std::fs::read_to_string(dir.path().join("ppm-install.1")).unwrap();
+        assert!(install.contains("parallel"));
+        assert!(install.contains("verbose"));
+    }
+
+    #[test]
+    fn test_find_plugin() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let not_executable = first.path().join("ppm-audit");
+        std::fs::write(&not_executable, "#!/bin/sh\n").unwrap();
+        let plugin = second.path().join("ppm-audit");
+        std::fs::write(&plugin, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&plugin, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let search_path = std::env::join_paths([first.path(), second.path()]).unwrap();
+        assert_eq!(find_plugin("audit", &search_path), Some(plugin));
+        assert_eq!(find_plugin("missing", &search_path), None);
+    }
+
+    #[test]
+    fn test_environment_search_path() {
+        let environment = PythonEnvironment {
+            python: "/venv/bin/python".to_string(),
+            scripts_dir: PathBuf::from("/venv/bin"),
+            prefix: PathBuf::from("/venv"),
+            is_virtualenv: true,
+        };
+        let current = std::env::join_paths(["/usr/bin", "/venv/bin", "/bin"]).unwrap();
+        let path = environment.search_path(Some(&current)).unwrap();
+        let dirs: Vec<PathBuf> = std::env::split_paths(&path).collect();
+        assert_eq!(
+            dirs,
+            vec![
+                PathBuf::from("/venv/bin"),
+                PathBuf::from("/usr/bin"),
+                PathBuf::from("/bin")
+            ]
+        );
+        assert_eq!(
+            environment.search_path(None).unwrap(),
+            std::ffi::OsString::from("/venv/bin")
+        );
+    }
+
+    #[test]
+    fn test_cli_verbosity() {
+        use clap::Parser;
+
+        let verbosity = |args: &[&str]| Cli::try_parse_from(args).map(|cli| cli.verbosity());
+        assert_eq!(verbosity(&["ppm", "list"]).unwrap(), Verbosity::Normal);
+        assert_eq!(verbosity(&["ppm", "-q", "list"]).unwrap(), Verbosity::Quiet);
+        assert_eq!(
+            verbosity(&["ppm", "list", "-v"]).unwrap(),
+            Verbosity::Verbose
+        );
+        assert_eq!(
+            verbosity(&["ppm", "-vv", "list"]).unwrap(),
+            Verbosity::Debug
+        );
+        assert!(verbosity(&["ppm", "-q", "-v", "list"]).is_err());
+    }
+
+    #[test]
+    fn test_jobs_option_and_config() {
+        use clap::Parser;
+
+        let jobs = |args: &[&str]| Cli::try_parse_from(args).map(|cli| cli.jobs);
+        assert_eq!(jobs(&["ppm", "list"]).unwrap(), None);
+        assert_eq!(
+            jobs(&["ppm", "install", "-p", "-j", "4", "numpy"]).unwrap(),
+            Some(4)
+        );
+        assert_eq!(
+            jobs(&["ppm", "--jobs=2", "upgrade", "--all"]).unwrap(),
+            Some(2)
+        );
+        assert!(jobs(&["ppm", "-j", "0", "list"]).is_err());
+
+        let config: ProjectConfig = toml::from_str("name = \"ci\"\njobs = 3\n").unwrap();
+        assert_eq!(config.jobs, Some(3));
+    }
+}
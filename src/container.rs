@@ -0,0 +1,287 @@
+//! Docker build assets for shipping the managed environment as an image
+//!
+//! `ppm containerize` writes a requirements file with the sha256 of every
+//! archive, taken from the lockfile when there is one and from the registry
+//! otherwise, plus a multi-stage Dockerfile that installs it in pip's
+//! `--require-hashes` mode and a `.dockerignore`.
+
+use crate::lock::{hashed_requirements, LockedPackage, Lockfile};
+use crate::registry::PackageRegistry;
+use crate::{collect_package_hashes, PackageError, Result};
+use std::path::{Path, PathBuf};
+
+/// Base image used unless another one is given
+pub const DEFAULT_BASE_IMAGE: &str = "python:3.12-slim";
+
+/// Requirements file the Dockerfile installs from
+pub const CONTAINER_REQUIREMENTS_FILE: &str = "requirements.lock.txt";
+
+/// Files written by [`container_assets`], ready to be saved with [`write_container_assets`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContainerAssets {
+    /// Hash-pinned requirements, in pip's requirements file format
+    pub requirements: String,
+    /// Specs that have no hashes, e.g. packages installed from version control
+    pub unhashed: Vec<String>,
+    /// Whether the requirements list every dependency, so pip need not resolve any
+    pub complete: bool,
+    /// The Dockerfile
+    pub dockerfile: String,
+    /// The .dockerignore
+    pub dockerignore: String,
+}
+
+/// Builds the Docker assets for a lockfile, or for the registry without one
+///
+/// A lockfile pins the whole dependency tree, so its packages are installed
+/// with `--no-deps`. The registry records dependencies as they get installed;
+/// if it misses one, pip's hash-checking mode fails the image build rather
+/// than install it unverified.
+///
+/// # Arguments
+/// * `lockfile` - The lockfile to install, if the project has one
+/// * `registry` - Reference to the package registry, used without a lockfile
+/// * `base` - Base image of both stages, e.g. "python:3.12-slim"
+///
+/// # Returns
+/// * `Result<ContainerAssets>` - The files, or an error if the hashes could not be looked up
+pub fn container_assets(
+    lockfile: Option<&Lockfile>,
+    registry: &PackageRegistry,
+    base: &str,
+) -> Result<ContainerAssets> {
+    let (requirements, unhashed, complete) = pinned_requirements(lockfile, registry)?;
+
+    Ok(ContainerAssets {
+        dockerfile: render_dockerfile(base, !requirements.is_empty(), &unhashed, complete),
+        dockerignore: render_dockerignore(),
+        requirements,
+        unhashed,
+        complete,
+    })
+}
+
+/// Pins the packages of a lockfile, or of the registry without one
+///
+/// # Arguments
+/// * `lockfile` - The lockfile to pin, if the project has one
+/// * `registry` - Reference to the package registry, used without a lockfile
+///
+/// # Returns
+/// * `Result<(String, Vec<String>, bool)>` - The hash-pinned requirements, the
+///   specs that have no hashes, and whether the requirements list every
+///   dependency; or an error if the hashes could not be looked up
+pub(crate) fn pinned_requirements(
+    lockfile: Option<&Lockfile>,
+    registry: &PackageRegistry,
+) -> Result<(String, Vec<String>, bool)> {
+    Ok(match lockfile {
+        Some(lockfile) => {
+            let (hashed, unhashed): (Vec<&LockedPackage>, Vec<&LockedPackage>) = lockfile
+                .packages
+                .iter()
+                .partition(|package| package.is_hash_pinned());
+            let unhashed = unhashed.iter().map(|package| package.spec()).collect();
+            (hashed_requirements(&hashed), unhashed, true)
+        }
+        None => {
+            let hashes = collect_package_hashes(registry)?;
+            let mut packages: Vec<_> = registry.packages.values().collect();
+            packages.sort_by_key(|package| package.name.to_lowercase());
+            let mut requirements = String::new();
+            let mut unhashed = Vec::new();
+            for package in packages {
+                let spec = format!("{}=={}", package.name, package.version);
+                match hashes.get(&package.name) {
+                    _ if package.vcs_spec().is_some() => {
+                        unhashed.extend(package.vcs_spec());
+                    }
+                    Some(digests) => {
+                        requirements.push_str(&spec);
+                        for digest in digests {
+                            requirements.push_str(&format!(" \\\n    --hash={}", digest));
+                        }
+                        requirements.push('\n');
+                    }
+                    None => unhashed.push(spec),
+                }
+            }
+            (requirements, unhashed, false)
+        }
+    })
+}
+
+/// Renders a multi-stage Dockerfile that installs the requirements into a virtual environment
+///
+/// The build stage creates `/opt/venv` and installs into it with hash
+/// checking; the final stage copies only the environment and the project.
+///
+/// # Arguments
+/// * `base` - Base image of both stages
+/// * `hashed` - Whether the requirements file lists any package
+/// * `unhashed` - Specs installed after the hash-checked ones, without verification
+/// * `complete` - Whether the requirements pin every dependency
+pub fn render_dockerfile(base: &str, hashed: bool, unhashed: &[String], complete: bool) -> String {
+    let no_deps = if complete { " --no-deps" } else { "" };
+    let mut out = format!(
+        "# syntax=docker/dockerfile:1\n\
+         # Generated by ppm containerize\n\
+         \n\
+         FROM {} AS build\n\
+         ENV PIP_DISABLE_PIP_VERSION_CHECK=1 PIP_NO_CACHE_DIR=1\n\
+         RUN python -m venv /opt/venv\n\
+         ENV PATH=/opt/venv/bin:$PATH\n",
+        base
+    );
+    if hashed {
+        out.push_str(&format!(
+            "COPY {0} /tmp/{0}\n\
+             RUN pip install --require-hashes{1} -r /tmp/{0}\n",
+            CONTAINER_REQUIREMENTS_FILE, no_deps
+        ));
+    }
+    if !unhashed.is_empty() {
+        out.push_str("# These have no hashes to check\n");
+        out.push_str(&format!("RUN pip install{}", no_deps));
+        for spec in unhashed {
+            out.push_str(&format!(" \\\n    \"{}\"", spec.replace('"', "\\\"")));
+        }
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "\n\
+         FROM {}\n\
+         COPY --from=build /opt/venv /opt/venv\n\
+         ENV PATH=/opt/venv/bin:$PATH PYTHONUNBUFFERED=1\n\
+         WORKDIR /app\n\
+         COPY . .\n\
+         CMD [\"python\"]\n",
+        base
+    ));
+    out
+}
+
+/// Renders a .dockerignore that keeps local state, environments and VCS data out of the image
+pub fn render_dockerignore() -> String {
+    [
+        "# Generated by ppm containerize",
+        ".git",
+        ".ppm",
+        ".venv",
+        "venv",
+        "__pycache__",
+        "*.py[cod]",
+        ".pytest_cache",
+        ".mypy_cache",
+        "build",
+        "dist",
+        "*.egg-info",
+        "Dockerfile",
+        ".dockerignore",
+    ]
+    .join("\n")
+        + "\n"
+}
+
+/// Writes the Docker assets into a directory
+///
+/// # Arguments
+/// * `assets` - The files to write
+/// * `dir` - The build context, usually the project root
+/// * `force` - Whether to overwrite files that already exist
+///
+/// # Returns
+/// * `Result<Vec<PathBuf>>` - The files written, or an error naming an
+///   existing file when `force` is not set
+pub fn write_container_assets(
+    assets: &ContainerAssets,
+    dir: &Path,
+    force: bool,
+) -> Result<Vec<PathBuf>> {
+    let files = [
+        (CONTAINER_REQUIREMENTS_FILE, &assets.requirements),
+        ("Dockerfile", &assets.dockerfile),
+        (".dockerignore", &assets.dockerignore),
+    ];
+    if !force {
+        if let Some(existing) = files
+            .iter()
+            .map(|(name, _)| dir.join(name))
+            .find(|path| path.exists())
+        {
+            return Err(PackageError::IoError(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists; pass --force to replace it",
+                    existing.display()
+                ),
+            )));
+        }
+    }
+
+    let mut written = Vec::new();
+    for (name, contents) in files {
+        let path = dir.join(name);
+        std::fs::write(&path, contents)?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock::{LockedArchive, LockedVcs};
+
+    #[test]
+    fn test_container_assets_from_lockfile() {
+        let archive = LockedArchive {
+            url: "https://files.pythonhosted.org/rich-13.7.0-py3-none-any.whl".to_string(),
+            hashes: [("sha256".to_string(), "abc".to_string())].into(),
+        };
+        let lockfile = Lockfile {
+            lock_version: "1.0".to_string(),
+            created_by: "ppm".to_string(),
+            requires_python: None,
+            packages: vec![
+                LockedPackage {
+                    name: "rich".to_string(),
+                    version: "13.7.0".to_string(),
+                    sdist: None,
+                    wheels: vec![archive],
+                    vcs: None,
+                },
+                LockedPackage {
+                    name: "tool".to_string(),
+                    version: "0.1".to_string(),
+                    sdist: None,
+                    wheels: Vec::new(),
+                    vcs: Some(LockedVcs {
+                        kind: "git".to_string(),
+                        url: "https://example.com/tool.git".to_string(),
+                        commit_id: "deadbeef".to_string(),
+                    }),
+                },
+            ],
+        };
+        let assets =
+            container_assets(Some(&lockfile), &PackageRegistry::new(), "python:3.11").unwrap();
+
+        assert_eq!(
+            assets.requirements,
+            "rich==13.7.0 \\\n    --hash=sha256:abc\n"
+        );
+        assert_eq!(
+            assets.unhashed,
+            ["tool @ git+https://example.com/tool.git@deadbeef"]
+        );
+        assert!(assets.dockerfile.contains("FROM python:3.11 AS build\n"));
+        assert!(assets
+            .dockerfile
+            .contains("RUN pip install --require-hashes --no-deps -r /tmp/requirements.lock.txt"));
+        assert!(assets.dockerfile.contains(
+            "RUN pip install --no-deps \\\n    \"tool @ git+https://example.com/tool.git@deadbeef\"\n"
+        ));
+        assert!(assets.dockerignore.lines().any(|line| line == ".ppm"));
+    }
+}
@@ -0,0 +1,675 @@
+//! Conversion between requirements.txt, pyproject.toml and Pipfile
+//!
+//! Every format is read into a [`DependencySet`] of PEP 508 requirements and
+//! written back out from it. Whatever a format cannot express, such as the
+//! `-r` includes of a requirements file or the optional groups of a
+//! pyproject.toml converted to requirements.txt, is left out and reported.
+
+use crate::spec::{canonicalize_name, requirement_name, VcsRequirement};
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Markers Pipfile accepts as keys of a package table, e.g. `sys_platform = "== 'win32'"`
+const PIPFILE_MARKER_KEYS: [&str; 11] = [
+    "os_name",
+    "sys_platform",
+    "platform_machine",
+    "platform_python_implementation",
+    "platform_release",
+    "platform_system",
+    "platform_version",
+    "python_version",
+    "python_full_version",
+    "implementation_name",
+    "implementation_version",
+];
+
+/// The simple index of PyPI, which a Pipfile names as its default source
+const PYPI_SIMPLE_URL: &str = "https://pypi.org/simple";
+
+/// Dependency file formats `ppm convert` reads and writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DependencyFormat {
+    /// A pip requirements file
+    Requirements,
+    /// The `[project]` table of a pyproject.toml (PEP 621)
+    Pyproject,
+    /// A Pipfile as used by pipenv
+    Pipfile,
+}
+
+impl DependencyFormat {
+    /// Guesses the format from a file name: pyproject.toml, Pipfile, or else a requirements file
+    pub fn from_path(path: &Path) -> Self {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some("pyproject.toml") => DependencyFormat::Pyproject,
+            Some("Pipfile") => DependencyFormat::Pipfile,
+            _ => DependencyFormat::Requirements,
+        }
+    }
+}
+
+/// Dependencies read from any supported file
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DependencySet {
+    /// PEP 508 requirements installed by default
+    pub dependencies: Vec<String>,
+    /// Optional groups such as "dev", in file order
+    pub groups: Vec<(String, Vec<String>)>,
+    /// Supported Python versions, e.g. ">=3.11"
+    pub requires_python: Option<String>,
+    /// Package indexes, the default one first
+    pub indexes: Vec<String>,
+}
+
+/// Reads the dependencies of a file
+///
+/// # Arguments
+/// * `contents` - Contents of the file
+/// * `format` - Its format
+///
+/// # Returns
+/// * `Result<(DependencySet, Vec<String>)>` - The dependencies and a note for
+///   everything that was left out, or an error if the file is malformed
+pub fn read_dependencies(
+    contents: &str,
+    format: DependencyFormat,
+) -> Result<(DependencySet, Vec<String>)> {
+    match format {
+        DependencyFormat::Requirements => Ok(read_requirements(contents)),
+        DependencyFormat::Pyproject => read_pyproject(contents),
+        DependencyFormat::Pipfile => read_pipfile(contents),
+    }
+}
+
+/// Renders dependencies in a format
+///
+/// # Arguments
+/// * `set` - The dependencies
+/// * `format` - Format to write
+/// * `project` - Project name, used for a new pyproject.toml
+/// * `existing` - Current contents of the target; a pyproject.toml keeps everything
+///   but its dependency lists and `requires-python`
+///
+/// # Returns
+/// * `Result<(String, Vec<String>)>` - The file and a note for everything that
+///   could not be written, or an error if the existing file is malformed
+pub fn render_dependencies(
+    set: &DependencySet,
+    format: DependencyFormat,
+    project: &str,
+    existing: Option<&str>,
+) -> Result<(String, Vec<String>)> {
+    match format {
+        DependencyFormat::Requirements => Ok(render_requirements(set)),
+        DependencyFormat::Pyproject => render_pyproject_dependencies(set, project, existing),
+        DependencyFormat::Pipfile => Ok(render_pipfile(set)),
+    }
+}
+
+/// Converts one dependency file into another
+///
+/// # Arguments
+/// * `from` - File to read
+/// * `to` - File to write; its format is taken from its name
+/// * `project` - Project name, used for a new pyproject.toml
+/// * `force` - Whether to replace a file that already exists
+///
+/// # Returns
+/// * `Result<Vec<String>>` - A note for everything that could not be
+///   translated, or an error if reading or writing failed
+pub fn convert_dependency_file(
+    from: &Path,
+    to: &Path,
+    project: &str,
+    force: bool,
+) -> Result<Vec<String>> {
+    let contents = std::fs::read_to_string(from)?;
+    let (set, mut notes) = read_dependencies(&contents, DependencyFormat::from_path(from))?;
+
+    let existing = if to.exists() {
+        if !force {
+            return Err(PackageError::IoError(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} already exists; pass --force to replace it",
+                    to.display()
+                ),
+            )));
+        }
+        Some(std::fs::read_to_string(to)?)
+    } else {
+        None
+    };
+    let (rendered, render_notes) = render_dependencies(
+        &set,
+        DependencyFormat::from_path(to),
+        project,
+        existing.as_deref(),
+    )?;
+    notes.extend(render_notes);
+    std::fs::write(to, rendered)?;
+    Ok(notes)
+}
+
+/// Reads the requirements, indexes and left-out options of a requirements file
+fn read_requirements(contents: &str) -> (DependencySet, Vec<String>) {
+    let mut set = DependencySet::default();
+    let mut notes = Vec::new();
+    let mut continued = String::new();
+    let mut hashed = 0;
+
+    for line in contents.lines() {
+        if let Some(head) = line.trim_end().strip_suffix('\\') {
+            continued.push_str(head);
+            continued.push(' ');
+            continue;
+        }
+        continued.push_str(line);
+        let line = std::mem::take(&mut continued);
+        let code = line
+            .char_indices()
+            .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with(char::is_whitespace)))
+            .map_or(line.as_str(), |(i, _)| &line[..i])
+            .trim();
+        if code.is_empty() {
+            continue;
+        }
+
+        if code.starts_with('-') {
+            let (option, value) = code
+                .split_once(['=', ' '])
+                .map_or((code, ""), |(option, value)| (option, value.trim()));
+            match option {
+                "-i" | "--index-url" => set.indexes.insert(0, value.to_string()),
+                "--extra-index-url" => set.indexes.push(value.to_string()),
+                _ => notes.push(format!("`{}` has no equivalent; left out", code)),
+            }
+            continue;
+        }
+
+        let requirement = match code.find(" -") {
+            Some(index) => {
+                if code[index..].contains("--hash") {
+                    hashed += 1;
+                }
+                code[..index].trim()
+            }
+            None => code,
+        };
+        set.dependencies.push(requirement.to_string());
+    }
+
+    if hashed > 0 {
+        notes.push(format!(
+            "--hash options of {} requirement(s) were dropped; run `ppm lock` to pin hashes again",
+            hashed
+        ));
+    }
+    (set, notes)
+}
+
+/// Reads `[project]` dependencies, optional dependencies and PEP 735 dependency groups
+fn read_pyproject(contents: &str) -> Result<(DependencySet, Vec<String>)> {
+    let document: toml::Table = toml::from_str(contents)
+        .map_err(|e| PackageError::MetadataError(format!("Invalid pyproject.toml: {}", e)))?;
+    let mut set = DependencySet::default();
+    let mut notes = Vec::new();
+    let strings = |value: &toml::Value| -> Vec<String> {
+        value
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    if let Some(project) = document.get("project").and_then(|p| p.as_table()) {
+        if let Some(dependencies) = project.get("dependencies") {
+            set.dependencies = strings(dependencies);
+        }
+        set.requires_python = project
+            .get("requires-python")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        if let Some(optional) = project
+            .get("optional-dependencies")
+            .and_then(|v| v.as_table())
+        {
+            for (group, requirements) in optional {
+                set.groups.push((group.clone(), strings(requirements)));
+            }
+        }
+    }
+    if let Some(groups) = document.get("dependency-groups").and_then(|v| v.as_table()) {
+        for (group, requirements) in groups {
+            let includes = requirements.as_array().map_or(0, |items| {
+                items.iter().filter(|item| item.is_table()).count()
+            });
+            if includes > 0 {
+                notes.push(format!(
+                    "include-group entries of dependency group {} were left out",
+                    group
+                ));
+            }
+            set.groups.push((group.clone(), strings(requirements)));
+        }
+    }
+    Ok((set, notes))
+}
+
+/// Reads the sources, package categories and Python version of a Pipfile
+fn read_pipfile(contents: &str) -> Result<(DependencySet, Vec<String>)> {
+    let document: toml::Table = toml::from_str(contents)
+        .map_err(|e| PackageError::MetadataError(format!("Invalid Pipfile: {}", e)))?;
+    let mut set = DependencySet::default();
+    let mut notes = Vec::new();
+
+    for (key, value) in &document {
+        match key.as_str() {
+            "source" => {
+                for source in value.as_array().into_iter().flatten() {
+                    if let Some(url) = source.get("url").and_then(|url| url.as_str()) {
+                        set.indexes.push(url.to_string());
+                    }
+                }
+            }
+            "requires" => {
+                let requires = |key: &str| value.get(key).and_then(|v| v.as_str());
+                set.requires_python = requires("python_full_version")
+                    .map(|version| format!("=={}", version))
+                    .or_else(|| requires("python_version").map(|v| format!("=={}.*", v)));
+            }
+            "pipenv" | "scripts" => notes.push(format!(
+                "[{}] of the Pipfile has no equivalent; left out",
+                key
+            )),
+            category => {
+                let Some(packages) = value.as_table() else {
+                    continue;
+                };
+                let mut requirements = Vec::new();
+                for (name, entry) in packages {
+                    match pipfile_requirement(name, entry) {
+                        Ok(requirement) => requirements.push(requirement),
+                        Err(reason) => notes.push(format!("{}: {}; left out", name, reason)),
+                    }
+                }
+                match category {
+                    "packages" => set.dependencies = requirements,
+                    "dev-packages" => set.groups.push(("dev".to_string(), requirements)),
+                    _ => set.groups.push((category.to_string(), requirements)),
+                }
+            }
+        }
+    }
+    // A lone PyPI source is the default every format implies
+    if set.indexes == [PYPI_SIMPLE_URL] {
+        set.indexes.clear();
+    }
+    Ok((set, notes))
+}
+
+/// Translates a Pipfile package entry into a PEP 508 requirement
+fn pipfile_requirement(name: &str, entry: &toml::Value) -> std::result::Result<String, String> {
+    let table = match entry {
+        toml::Value::String(version) if version == "*" => return Ok(name.to_string()),
+        toml::Value::String(version) => return Ok(format!("{}{}", name, version)),
+        toml::Value::Table(table) => table,
+        _ => return Err("unsupported entry".to_string()),
+    };
+    let text = |key: &str| table.get(key).and_then(|v| v.as_str());
+
+    let mut requirement = name.to_string();
+    let extras: Vec<&str> = table
+        .get("extras")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|extra| extra.as_str())
+        .collect();
+    if !extras.is_empty() {
+        requirement.push_str(&format!("[{}]", extras.join(",")));
+    }
+
+    if let Some(url) = text("git") {
+        requirement.push_str(&format!(" @ git+{}", url.trim_start_matches("git+")));
+        if let Some(reference) = text("ref") {
+            requirement.push_str(&format!("@{}", reference));
+        }
+        if let Some(subdirectory) = text("subdirectory") {
+            requirement.push_str(&format!("#subdirectory={}", subdirectory));
+        }
+    } else if let Some(file) = text("file") {
+        requirement.push_str(&format!(" @ {}", file));
+    } else if text("path").is_some() {
+        return Err("local paths cannot be written as requirements".to_string());
+    } else if let Some(version) = text("version").filter(|version| *version != "*") {
+        requirement.push_str(version);
+    }
+
+    let mut markers: Vec<String> = text("markers").map(str::to_string).into_iter().collect();
+    for key in PIPFILE_MARKER_KEYS {
+        if let Some(condition) = text(key) {
+            markers.push(format!("{} {}", key, condition.trim()));
+        }
+    }
+    if !markers.is_empty() {
+        requirement.push_str(&format!("; {}", markers.join(" and ")));
+    }
+    Ok(requirement)
+}
+
+/// Renders a requirements file; optional groups and `requires-python` have no place in it
+fn render_requirements(set: &DependencySet) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut notes = Vec::new();
+    if let Some((first, extra)) = set.indexes.split_first() {
+        out.push_str(&format!("--index-url {}\n", first));
+        for index in extra {
+            out.push_str(&format!("--extra-index-url {}\n", index));
+        }
+    }
+    for requirement in &set.dependencies {
+        out.push_str(requirement);
+        out.push('\n');
+    }
+    for (group, requirements) in &set.groups {
+        notes.push(format!(
+            "group {} ({} requirement(s)) was left out; requirements files have no groups",
+            group,
+            requirements.len()
+        ));
+    }
+    if let Some(requires_python) = &set.requires_python {
+        notes.push(format!(
+            "requires-python {} was left out; requirements files cannot express it",
+            requires_python
+        ));
+    }
+    (out, notes)
+}
+
+/// Renders a pyproject.toml, or updates the dependency lists of an existing one
+fn render_pyproject_dependencies(
+    set: &DependencySet,
+    project: &str,
+    existing: Option<&str>,
+) -> Result<(String, Vec<String>)> {
+    #[derive(Serialize)]
+    struct Pyproject<'a> {
+        project: ProjectTable<'a>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct ProjectTable<'a> {
+        name: &'a str,
+        version: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requires_python: Option<&'a str>,
+        dependencies: &'a [String],
+        #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+        optional_dependencies: BTreeMap<&'a str, &'a [String]>,
+    }
+
+    let mut notes = Vec::new();
+    if !set.indexes.is_empty() {
+        notes.push(format!(
+            "package indexes ({}) were left out; pyproject.toml has no standard place for them",
+            set.indexes.join(", ")
+        ));
+    }
+    let optional: BTreeMap<&str, &[String]> = set
+        .groups
+        .iter()
+        .map(|(group, requirements)| (group.as_str(), requirements.as_slice()))
+        .collect();
+
+    let rendered = match existing {
+        None => toml::to_string_pretty(&Pyproject {
+            project: ProjectTable {
+                name: project,
+                version: "0.1.0",
+                requires_python: set.requires_python.as_deref(),
+                dependencies: &set.dependencies,
+                optional_dependencies: optional,
+            },
+        }),
+        Some(existing) => {
+            let mut document: toml::Table = toml::from_str(existing).map_err(|e| {
+                PackageError::MetadataError(format!("Invalid pyproject.toml: {}", e))
+            })?;
+            let table = document
+                .entry("project")
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            let Some(table) = table.as_table_mut() else {
+                return Err(PackageError::MetadataError(
+                    "[project] of pyproject.toml is not a table".to_string(),
+                ));
+            };
+            table.insert(
+                "dependencies".to_string(),
+                toml::Value::try_from(&set.dependencies)
+                    .expect("strings are always representable as TOML"),
+            );
+            if optional.is_empty() {
+                table.remove("optional-dependencies");
+            } else {
+                table.insert(
+                    "optional-dependencies".to_string(),
+                    toml::Value::try_from(&optional)
+                        .expect("string lists are always representable as TOML"),
+                );
+            }
+            if let Some(requires_python) = &set.requires_python {
+                table.insert(
+                    "requires-python".to_string(),
+                    toml::Value::String(requires_python.clone()),
+                );
+            }
+            notes.push("comments and key order of pyproject.toml were not kept".to_string());
+            toml::to_string_pretty(&document)
+        }
+    }
+    .map_err(|e| PackageError::MetadataError(format!("Could not write pyproject.toml: {}", e)))?;
+    Ok((rendered, notes))
+}
+
+/// Renders a Pipfile; the dev group becomes `[dev-packages]` and other groups custom categories
+fn render_pipfile(set: &DependencySet) -> (String, Vec<String>) {
+    let mut out = String::new();
+    let mut notes = Vec::new();
+    let quote = |text: &str| toml::Value::String(text.to_string()).to_string();
+
+    let default_index = [PYPI_SIMPLE_URL.to_string()];
+    let indexes = if set.indexes.is_empty() {
+        &default_index[..]
+    } else {
+        &set.indexes[..]
+    };
+    for (position, url) in indexes.iter().enumerate() {
+        let name = if position == 0 {
+            "pypi".to_string()
+        } else {
+            format!("index-{}", position)
+        };
+        out.push_str(&format!(
+            "[[source]]\nurl = {}\nverify_ssl = true\nname = {}\n\n",
+            quote(url),
+            quote(&name)
+        ));
+    }
+
+    let mut categories = vec![("packages", set.dependencies.as_slice())];
+    for (group, requirements) in &set.groups {
+        let category = if group == "dev" {
+            "dev-packages"
+        } else {
+            group.as_str()
+        };
+        categories.push((category, requirements.as_slice()));
+    }
+    for (category, requirements) in categories {
+        out.push_str(&format!("[{}]\n", category));
+        for requirement in requirements {
+            match pipfile_entry(requirement) {
+                Some((name, entry)) => out.push_str(&format!("{} = {}\n", name, entry)),
+                None => notes.push(format!(
+                    "{} cannot be written as a Pipfile entry; left out",
+                    requirement
+                )),
+            }
+        }
+        out.push('\n');
+    }
+
+    if let Some(requires_python) = &set.requires_python {
+        let version = requires_python
+            .trim_start_matches(['=', '>', '~'])
+            .trim_end_matches(".*");
+        if !requires_python.starts_with("==") {
+            notes.push(format!(
+                "requires-python {} was written as python_version {}",
+                requires_python, version
+            ));
+        }
+        out.push_str(&format!(
+            "[requires]\npython_version = {}\n",
+            quote(version)
+        ));
+    }
+    (out.trim_end().to_string() + "\n", notes)
+}
+
+/// Translates a PEP 508 requirement into a Pipfile key and value
+fn pipfile_entry(requirement: &str) -> Option<(String, String)> {
+    let quote = |text: &str| toml::Value::String(text.to_string()).to_string();
+    let (requirement, markers) = match requirement.split_once(';') {
+        Some((requirement, markers)) => (requirement.trim(), Some(markers.trim())),
+        None => (requirement.trim(), None),
+    };
+    let name = requirement_name(requirement);
+    if name.is_empty() || requirement.starts_with(|c: char| !c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    let rest = requirement[name.len()..].trim_start();
+    let (extras, rest) = match rest.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((extras, rest)) => (
+            extras
+                .split(',')
+                .map(|extra| quote(extra.trim()))
+                .collect::<Vec<_>>(),
+            rest.trim(),
+        ),
+        None => (Vec::new(), rest),
+    };
+
+    let mut fields = Vec::new();
+    match rest.strip_prefix('@').map(str::trim) {
+        Some(url) => match VcsRequirement::parse(url) {
+            Some(vcs) if vcs.kind == "git" => {
+                fields.push(format!("git = {}", quote(&vcs.url)));
+                if let Some(revision) = &vcs.revision {
+                    fields.push(format!("ref = {}", quote(revision)));
+                }
+            }
+            Some(_) => return None,
+            None => fields.push(format!("file = {}", quote(url))),
+        },
+        None if rest.is_empty() => fields.push("version = \"*\"".to_string()),
+        None => fields.push(format!("version = {}", quote(&rest.replace(' ', "")))),
+    }
+    if !extras.is_empty() {
+        fields.push(format!("extras = [{}]", extras.join(", ")));
+    }
+    if let Some(markers) = markers {
+        fields.push(format!("markers = {}", quote(markers)));
+    }
+
+    let key = if name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    {
+        name.to_string()
+    } else {
+        quote(&canonicalize_name(name))
+    };
+    let value = match fields.as_slice() {
+        [only] if only.starts_with("version = ") => only["version = ".len()..].to_string(),
+        _ => format!("{{{}}}", fields.join(", ")),
+    };
+    Some((key, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_convert_pipfile_to_pyproject_and_back() {
+        let pipfile = r#"
+[[source]]
+url = "https://pypi.example.org/simple"
+verify_ssl = true
+name = "internal"
+
+[packages]
+requests = "==2.31.0"
+rich = {version = ">=13", extras = ["jupyter"], markers = "python_version >= '3.9'"}
+tool = {git = "https://example.com/tool.git", ref = "v1.2"}
+local = {path = "./local", editable = true}
+pywin32 = {version = "*", sys_platform = "== 'win32'"}
+
+[dev-packages]
+pytest = "*"
+
+[requires]
+python_version = "3.11"
+"#;
+        let (set, notes) = read_dependencies(pipfile, DependencyFormat::Pipfile).unwrap();
+        assert_eq!(
+            set.dependencies,
+            [
+                "pywin32; sys_platform == 'win32'",
+                "requests==2.31.0",
+                "rich[jupyter]>=13; python_version >= '3.9'",
+                "tool @ git+https://example.com/tool.git@v1.2",
+            ]
+        );
+        assert_eq!(
+            set.groups,
+            [("dev".to_string(), vec!["pytest".to_string()])]
+        );
+        assert_eq!(set.requires_python.as_deref(), Some("==3.11.*"));
+        assert_eq!(notes.len(), 1);
+        assert!(notes[0].starts_with("local: local paths"));
+
+        let (requirements, notes) = render_requirements(&set);
+        assert!(requirements.starts_with("--index-url https://pypi.example.org/simple\n"));
+        assert_eq!(notes.len(), 2);
+
+        let (pyproject, _) =
+            render_dependencies(&set, DependencyFormat::Pyproject, "demo", None).unwrap();
+        let (reread, _) = read_dependencies(&pyproject, DependencyFormat::Pyproject).unwrap();
+        assert_eq!(reread.dependencies, set.dependencies);
+        assert_eq!(reread.groups, set.groups);
+
+        let (rendered, notes) = render_pipfile(&reread);
+        assert!(notes.is_empty());
+        assert!(rendered.contains("requests = \"==2.31.0\"\n"));
+        assert!(rendered.contains(
+            "rich = {version = \">=13\", extras = [\"jupyter\"], markers = \"python_version >= '3.9'\"}\n"
+        ));
+        assert!(
+            rendered.contains("tool = {git = \"https://example.com/tool.git\", ref = \"v1.2\"}\n")
+        );
+        assert!(rendered.contains("[dev-packages]\npytest = \"*\"\n"));
+        assert!(rendered.ends_with("[requires]\npython_version = \"3.11\"\n"));
+    }
+}
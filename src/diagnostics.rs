@@ -0,0 +1,278 @@
+//! Hints for common pip failures
+
+use crate::pip::NETWORK_ERROR_MARKERS;
+use crate::spec::parse_package_spec;
+use crate::suggest::{did_you_mean, suggest_package_names};
+
+/// A concise explanation of a recognized pip failure
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// One-line description of what went wrong
+    pub summary: String,
+    /// Relevant lines picked out of pip's output
+    pub details: Vec<String>,
+    /// What the user can do about it
+    pub suggestion: String,
+}
+
+impl Diagnostic {
+    /// Renders the diagnostic as indented lines below an error message
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for detail in &self.details {
+            out.push_str(&format!("  {}\n", detail));
+        }
+        out.push_str(&format!("  hint: {}\n", self.suggestion));
+        out
+    }
+}
+
+/// Diagnostic for a package index that could not be reached
+pub(crate) fn network_diagnostic() -> Diagnostic {
+    Diagnostic {
+        summary: "Could not reach the package index".to_string(),
+        details: Vec::new(),
+        suggestion: "Check your network connection and proxy settings, then retry".to_string(),
+    }
+}
+
+/// Recognizes common pip failure patterns in its stderr
+///
+/// Also recognizes the errors of the `build` frontend run by `ppm build`.
+///
+/// # Arguments
+/// * `stderr` - pip's stderr
+///
+/// # Returns
+/// * `Option<Diagnostic>` - Explanation and suggested fix, if the pattern is known
+pub fn diagnose_pip_output(stderr: &str) -> Option<Diagnostic> {
+    let requirement_after = |marker: &str| {
+        stderr.lines().find_map(|line| {
+            let rest = &line[line.find(marker)? + marker.len()..];
+            let requirement = rest.split_whitespace().next()?;
+            Some(requirement.trim_end_matches(['.', ',']).to_string())
+        })
+    };
+
+    if NETWORK_ERROR_MARKERS
+        .iter()
+        .any(|marker| stderr.contains(marker))
+    {
+        return Some(network_diagnostic());
+    }
+
+    if stderr.contains("externally-managed-environment") {
+        return Some(Diagnostic {
+            summary: "The Python environment is managed by the operating system".to_string(),
+            details: Vec::new(),
+            suggestion: "Create a virtual environment with `python3 -m venv .venv` and activate it"
+                .to_string(),
+        });
+    }
+
+    if stderr.contains("Permission denied") || stderr.contains("[Errno 13]") {
+        return Some(Diagnostic {
+            summary: "pip is not allowed to write to the Python environment".to_string(),
+            details: stderr
+                .lines()
+                .filter(|line| line.contains("Permission denied"))
+                .map(|line| line.trim().to_string())
+                .take(1)
+                .collect(),
+            suggestion: "Use a virtual environment, or fix the ownership of site-packages"
+                .to_string(),
+        });
+    }
+
+    if stderr.contains("Microsoft Visual C++") {
+        return Some(Diagnostic {
+            summary: "Building a C extension requires Microsoft Visual C++ 14.0 or greater"
+                .to_string(),
+            details: Vec::new(),
+            suggestion: "Install the Microsoft C++ Build Tools \
+                (https://visualstudio.microsoft.com/visual-cpp-build-tools/) \
+                or choose a version that ships wheels for your Python"
+                .to_string(),
+        });
+    }
+
+    if stderr.contains("ResolutionImpossible") || stderr.contains("conflicting dependencies") {
+        let details = stderr
+            .lines()
+            .skip_while(|line| !line.contains("The conflict is caused by:"))
+            .skip(1)
+            .take_while(|line| line.starts_with("    "))
+            .map(|line| line.trim().to_string())
+            .collect();
+        return Some(Diagnostic {
+            summary: "The requested packages have conflicting requirements".to_string(),
+            details,
+            suggestion: "Loosen one of the conflicting version pins, or install the packages \
+                one at a time to find the culprit"
+                .to_string(),
+        });
+    }
+
+    if let Some(requirement) = requirement_after("No matching distribution found for ") {
+        let name = parse_package_spec(&requirement)
+            .map(|(name, _)| name)
+            .unwrap_or_else(|_| requirement.clone());
+        let details = requirement_after("(from versions: ")
+            .map(|_| {
+                stderr
+                    .lines()
+                    .filter(|line| {
+                        line.contains("(from versions: ") && !line.contains("(from versions: none)")
+                    })
+                    .map(|line| {
+                        let start = line.find("(from versions: ").unwrap();
+                        format!("available {}", line[start + 1..].trim_end_matches(')'))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let summary = match did_you_mean(&suggest_package_names(&name, [])) {
+            Some(hint) => format!("No release matches {} ({})", requirement, hint),
+            None => format!("No release matches {}", requirement),
+        };
+        return Some(Diagnostic {
+            summary,
+            details,
+            suggestion: format!(
+                "Check the spelling and version of {}; it may also lack releases for this \
+                 Python version or platform",
+                name
+            ),
+        });
+    }
+
+    if let Some(backend) = requirement_after("ERROR Backend '") {
+        return Some(Diagnostic {
+            summary: format!(
+                "The build backend {} could not be imported",
+                backend.trim_end_matches(['\'', '.'])
+            ),
+            details: Vec::new(),
+            suggestion: "Check `build-backend` and `requires` in the [build-system] table of \
+                pyproject.toml"
+                .to_string(),
+        });
+    }
+
+    if stderr.contains("ERROR Missing dependencies:") {
+        let details = stderr
+            .lines()
+            .skip_while(|line| !line.contains("ERROR Missing dependencies:"))
+            .skip(1)
+            .take_while(|line| line.starts_with(['\t', ' ']))
+            .map(|line| line.trim().to_string())
+            .collect();
+        return Some(Diagnostic {
+            summary: "The build dependencies are not installed".to_string(),
+            details,
+            suggestion: "Build without --no-isolation, or install the build dependencies first"
+                .to_string(),
+        });
+    }
+
+    if stderr.contains("does not appear to be a Python project") {
+        return Some(Diagnostic {
+            summary: "The directory has no pyproject.toml or setup.py".to_string(),
+            details: Vec::new(),
+            suggestion: "Pass the project directory, e.g. `ppm build path/to/project`".to_string(),
+        });
+    }
+
+    if let Some(hook) = requirement_after("Backend subprocess exited when trying to invoke ") {
+        let details: Vec<String> = stderr
+            .lines()
+            .map(str::trim)
+            .filter(|line| {
+                let lower = line.to_lowercase();
+                (lower.starts_with("error:") || lower.contains("error: "))
+                    && !line.starts_with("ERROR ")
+            })
+            .map(str::to_string)
+            .collect();
+        return Some(Diagnostic {
+            summary: format!("The build backend failed in {}", hook),
+            details: details[details.len().saturating_sub(3)..].to_vec(),
+            suggestion: "Fix the error the build backend reported, e.g. in pyproject.toml or \
+                setup.py, then build again"
+                .to_string(),
+        });
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::PipFailure;
+    use crate::PackageError;
+
+    #[test]
+    fn test_diagnose_pip_output() {
+        let missing = "ERROR: Could not find a version that satisfies the requirement \
+            requests==99.0 (from versions: 2.31.0, 2.32.3)\n\
+            ERROR: No matching distribution found for requests==99.0\n";
+        let diagnostic = diagnose_pip_output(missing).unwrap();
+        assert_eq!(diagnostic.summary, "No release matches requests==99.0");
+        assert_eq!(
+            diagnostic.details,
+            vec!["available from versions: 2.31.0, 2.32.3"]
+        );
+        assert!(diagnostic
+            .suggestion
+            .contains("spelling and version of requests"));
+
+        let conflict = "ERROR: Cannot install a==1.0 and b==2.0 because these package \
+            versions have conflicting dependencies.\n\n\
+            The conflict is caused by:\n    \
+            a 1.0 depends on c<2\n    \
+            b 2.0 depends on c>=2\n\n\
+            To fix this you could try to:\n";
+        let diagnostic = diagnose_pip_output(conflict).unwrap();
+        assert_eq!(
+            diagnostic.details,
+            vec!["a 1.0 depends on c<2", "b 2.0 depends on c>=2"]
+        );
+        assert!(diagnostic
+            .render()
+            .ends_with(&format!("  hint: {}\n", diagnostic.suggestion)));
+
+        let error = PackageError::BuildFailed(PipFailure {
+            package: "pyyaml".to_string(),
+            exit_status: Some(1),
+            stderr: "error: Microsoft Visual C++ 14.0 or greater is required.".to_string(),
+        });
+        assert!(error.diagnose().unwrap().summary.contains("Visual C++"));
+
+        let backend = "* Getting build dependencies for wheel...\n\
+            error: Multiple top-level packages discovered in a flat-layout: ['a', 'b'].\n\
+            \n\
+            ERROR Backend subprocess exited when trying to invoke get_requires_for_build_wheel\n";
+        let diagnostic = diagnose_pip_output(backend).unwrap();
+        assert_eq!(
+            diagnostic.summary,
+            "The build backend failed in get_requires_for_build_wheel"
+        );
+        assert_eq!(
+            diagnostic.details,
+            vec!["error: Multiple top-level packages discovered in a flat-layout: ['a', 'b']."]
+        );
+        let missing = "ERROR Missing dependencies:\n\tsetuptools>=61\n\twheel\n";
+        assert_eq!(
+            diagnose_pip_output(missing).unwrap().details,
+            vec!["setuptools>=61", "wheel"]
+        );
+        assert_eq!(
+            diagnose_pip_output("ERROR Backend 'flit_core.buildapi' is not available.")
+                .unwrap()
+                .summary,
+            "The build backend flit_core.buildapi could not be imported"
+        );
+        assert!(diagnose_pip_output("ERROR: something unexpected").is_none());
+    }
+}
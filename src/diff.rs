@@ -0,0 +1,298 @@
+//! Comparing the packages of two environments, lockfiles or requirement files
+
+use crate::pip::{get_python_executable, run_command};
+use crate::project::venv_python;
+use crate::registry::PackageRegistry;
+use crate::requirements::parse_requirements_file;
+use crate::spec::{canonicalize_name, requirement_name};
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::process::Command;
+
+/// Output formats supported by the diff command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum DiffFormat {
+    /// `+`/`-`/`~` lines followed by a summary
+    Text,
+    /// JSON object with added, removed and changed packages
+    Json,
+}
+
+/// Package versions keyed by normalized name, keeping the original spelling
+pub type PackageSet = BTreeMap<String, (String, String)>;
+
+/// A package present on only one side of a diff
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiffEntry {
+    /// Package name
+    pub name: String,
+    /// Version, or the specifier for unpinned requirements
+    pub version: String,
+}
+
+/// A package whose version differs between both sides of a diff
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VersionChange {
+    /// Package name
+    pub name: String,
+    /// Version on the baseline side
+    pub from: String,
+    /// Version on the compared side
+    pub to: String,
+}
+
+/// Differences between two package sets
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct PackageDiff {
+    /// Packages only in the compared set
+    pub added: Vec<DiffEntry>,
+    /// Packages only in the baseline
+    pub removed: Vec<DiffEntry>,
+    /// Packages in both sets with different versions
+    pub changed: Vec<VersionChange>,
+}
+
+impl PackageDiff {
+    /// Whether both sets are identical
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Loads a package set from a registry, requirements file, lockfile or live environment
+///
+/// # Arguments
+/// * `source` - `env`, `env:<python>`, a virtualenv directory, or a file path;
+///   `.json` files are read as registries, `.lock`/`.toml` files as lockfiles with
+///   `[[package]]` tables and anything else as a requirements file
+///
+/// # Returns
+/// * `Result<PackageSet>` - Versions keyed by normalized name
+pub fn load_package_set(source: &str) -> Result<PackageSet> {
+    if source == "env" {
+        return environment_package_set(&get_python_executable()?);
+    }
+    if let Some(python) = source.strip_prefix("env:") {
+        return environment_package_set(python);
+    }
+
+    let path = Path::new(source);
+    if path.is_dir() {
+        if path.join("pyvenv.cfg").exists() {
+            return environment_package_set(&venv_python(path));
+        }
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "{} is a directory but not a virtual environment",
+            source
+        )));
+    }
+    if !path.exists() {
+        return Err(PackageError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Package source not found: {}", source),
+        )));
+    }
+
+    let mut set = PackageSet::new();
+    let mut add = |name: &str, version: &str| {
+        set.insert(
+            canonicalize_name(name),
+            (name.to_string(), version.to_string()),
+        );
+    };
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => {
+            let registry: PackageRegistry =
+                serde_json::from_reader(BufReader::new(File::open(path)?))?;
+            for package in registry.packages.values() {
+                add(&package.name, &package.version);
+            }
+        }
+        Some("lock") | Some("toml") => {
+            let document: toml::Table = toml::from_str(&std::fs::read_to_string(path)?)
+                .map_err(|e| PackageError::MetadataError(format!("Invalid {}: {}", source, e)))?;
+            let packages = document
+                .get("package")
+                .or_else(|| document.get("packages"))
+                .and_then(toml::Value::as_array)
+                .ok_or_else(|| {
+                    PackageError::MetadataError(format!("{} has no [[package]] entries", source))
+                })?;
+            for package in packages {
+                let field = |key: &str| package.get(key).and_then(toml::Value::as_str);
+                if let (Some(name), Some(version)) = (field("name"), field("version")) {
+                    add(name, version);
+                }
+            }
+        }
+        _ => {
+            for spec in parse_requirements_file(source)? {
+                let name = requirement_name(&spec);
+                let rest = spec[name.len()..].split(';').next().unwrap_or("").trim();
+                add(name, rest.strip_prefix("==").unwrap_or(rest).trim());
+            }
+        }
+    }
+    Ok(set)
+}
+
+/// Lists every distribution installed in the environment of an interpreter
+pub(crate) fn environment_package_set(python: &str) -> Result<PackageSet> {
+    let output = run_command(
+        Command::new(python)
+            .arg("-c")
+            .arg(INSTALLED_DISTRIBUTIONS_SCRIPT),
+    )
+    .map_err(|_| PackageError::PythonNotFound)?;
+    if !output.status.success() {
+        return Err(PackageError::MetadataError(format!(
+            "Failed to list packages of {}: {}",
+            python,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let installed: HashMap<String, String> = serde_json::from_slice(&output.stdout)?;
+    Ok(installed
+        .into_iter()
+        .map(|(name, version)| (canonicalize_name(&name), (name, version)))
+        .collect())
+}
+
+/// Compares two package sets
+///
+/// # Arguments
+/// * `a` - The baseline
+/// * `b` - The set compared against the baseline
+///
+/// # Returns
+/// * `PackageDiff` - Added, removed and changed packages, each sorted by name
+pub fn diff_package_sets(a: &PackageSet, b: &PackageSet) -> PackageDiff {
+    let mut diff = PackageDiff::default();
+    for (key, (name, version)) in a {
+        match b.get(key) {
+            None => diff.removed.push(DiffEntry {
+                name: name.clone(),
+                version: version.clone(),
+            }),
+            Some((_, other)) if other != version => diff.changed.push(VersionChange {
+                name: name.clone(),
+                from: version.clone(),
+                to: other.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    for (key, (name, version)) in b {
+        if !a.contains_key(key) {
+            diff.added.push(DiffEntry {
+                name: name.clone(),
+                version: version.clone(),
+            });
+        }
+    }
+    diff
+}
+
+/// Renders a diff as `+`/`-`/`~` lines followed by a summary
+///
+/// # Arguments
+/// * `diff` - The differences to render
+///
+/// # Returns
+/// Removed, changed and added packages, one per line
+pub fn render_package_diff(diff: &PackageDiff) -> String {
+    if diff.is_empty() {
+        return "No differences\n".to_string();
+    }
+
+    let mut out = String::new();
+    for entry in &diff.removed {
+        let line = format!("- {} {}", entry.name, entry.version);
+        out.push_str(&format!("{}\n", console::style(line).red()));
+    }
+    for change in &diff.changed {
+        let line = format!("~ {} {} -> {}", change.name, change.from, change.to);
+        out.push_str(&format!("{}\n", console::style(line).yellow()));
+    }
+    for entry in &diff.added {
+        let line = format!("+ {} {}", entry.name, entry.version);
+        out.push_str(&format!("{}\n", console::style(line).green()));
+    }
+    out.push_str(&format!(
+        "\n{} added, {} removed, {} changed\n",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len()
+    ));
+    out
+}
+
+/// Python snippet that prints a JSON object mapping every installed distribution to its version
+///
+/// Like the other listing snippets, it only looks in `PPM_LOCAL_PACKAGES` when that is set.
+pub(crate) const INSTALLED_DISTRIBUTIONS_SCRIPT: &str = r#"
+import json, os
+from importlib import metadata
+local = os.environ.get("PPM_LOCAL_PACKAGES")
+dists = metadata.distributions(**({"path": [local]} if local else {}))
+print(json.dumps({d.metadata["Name"]: d.version for d in dists}))
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_package_sets() {
+        let dir = tempfile::tempdir().unwrap();
+        let registry = dir.path().join("packages.json");
+        std::fs::write(
+            &registry,
+            r#"{"packages": {
+                "numpy": {"name": "numpy", "version": "1.24.0"},
+                "requests": {"name": "requests", "version": "2.31.0"},
+                "Flask": {"name": "Flask", "version": "3.0.0"}
+            }}"#,
+        )
+        .unwrap();
+        let lock = dir.path().join("poetry.lock");
+        std::fs::write(
+            &lock,
+            "[[package]]\nname = \"numpy\"\nversion = \"1.26.4\"\n\n\
+             [[package]]\nname = \"flask\"\nversion = \"3.0.0\"\n",
+        )
+        .unwrap();
+        let requirements = dir.path().join("requirements.txt");
+        std::fs::write(&requirements, "numpy==1.26.4\nclick>=8\n").unwrap();
+
+        let a = load_package_set(registry.to_str().unwrap()).unwrap();
+        let b = load_package_set(lock.to_str().unwrap()).unwrap();
+        let diff = diff_package_sets(&a, &b);
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].name, "requests");
+        assert_eq!(
+            diff.changed,
+            vec![VersionChange {
+                name: "numpy".to_string(),
+                from: "1.24.0".to_string(),
+                to: "1.26.4".to_string(),
+            }]
+        );
+
+        let c = load_package_set(requirements.to_str().unwrap()).unwrap();
+        let diff = diff_package_sets(&b, &c);
+        assert_eq!(diff.added[0].version, ">=8");
+        assert!(render_package_diff(&diff).ends_with("1 added, 1 removed, 0 changed\n"));
+        assert_eq!(
+            render_package_diff(&PackageDiff::default()),
+            "No differences\n"
+        );
+    }
+}
@@ -0,0 +1,418 @@
+//! Named environments tracked in one registry
+//!
+//! Besides the default environment, whose packages are the top-level entries
+//! of `packages.json`, a project can track environments such as `api` or
+//! `worker`, each bound to its own virtual environment. The global `--env`
+//! option selects one, after which every command reads and writes that
+//! environment's packages and runs pip in its interpreter.
+
+use crate::pip::get_python_executable;
+use crate::project::venv_python;
+use crate::registry::{
+    load_packages, set_active_environment, unknown_environment, NamedEnvironment, Package,
+    PackageRegistry,
+};
+use crate::session::PYTHON_OVERRIDE;
+use crate::spec::canonicalize_name;
+use crate::state::STATE_DIR;
+use crate::{
+    create_virtualenv, install_with_options, set_python_override, InstallOptions, InstallReport,
+    PackageError, Result,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Name that selects the default environment, i.e. the top-level packages
+pub const DEFAULT_ENVIRONMENT: &str = "default";
+
+/// Directory new environments are created in unless `--venv` says otherwise
+pub fn environments_dir() -> PathBuf {
+    Path::new(STATE_DIR).join("envs")
+}
+
+/// One row of `ppm env list`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnvironmentSummary {
+    /// Environment name
+    pub name: String,
+    /// Its virtual environment, or None for the default environment
+    pub venv: Option<PathBuf>,
+    /// Number of registered packages
+    pub packages: usize,
+    /// Whether the virtual environment has an interpreter
+    pub present: bool,
+}
+
+/// Checks that a name can be used for a new environment
+///
+/// # Arguments
+/// * `name` - The proposed name
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error explaining what is wrong with the name
+pub fn validate_environment_name(name: &str) -> Result<()> {
+    if name == DEFAULT_ENVIRONMENT {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "{} is the name of the default environment",
+            DEFAULT_ENVIRONMENT
+        )));
+    }
+    let valid = name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if name.is_empty() || !valid || name.starts_with('.') {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "Invalid environment name {:?}: use letters, digits, '-', '_' and '.'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Makes the registry functions and pip work on a named environment
+///
+/// # Arguments
+/// * `name` - Name of the environment; "default" keeps the default environment
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the environment is unknown or its
+///   virtual environment is gone
+pub fn select_environment(name: &str) -> Result<()> {
+    if name == DEFAULT_ENVIRONMENT {
+        return Ok(());
+    }
+    let registry = load_packages()?;
+    let environment = registry
+        .environments
+        .get(name)
+        .ok_or_else(|| unknown_environment(name))?;
+    let python = venv_python(&std::env::current_dir()?.join(&environment.venv));
+    if !Path::new(&python).exists() {
+        return Err(PackageError::MetadataError(format!(
+            "The virtual environment of {} is missing ({}); remove the environment and create it again",
+            name,
+            environment.venv.display()
+        )));
+    }
+    set_active_environment(Some(name.to_string()));
+    set_python_override(Some(python));
+    Ok(())
+}
+
+/// Summarizes the default environment and every named one
+///
+/// # Arguments
+/// * `registry` - Reference to the whole package registry
+///
+/// # Returns
+/// The default environment first, then the named ones by name
+pub fn environment_summaries(registry: &PackageRegistry) -> Vec<EnvironmentSummary> {
+    let default = EnvironmentSummary {
+        name: DEFAULT_ENVIRONMENT.to_string(),
+        venv: None,
+        packages: registry.packages.len(),
+        present: true,
+    };
+    std::iter::once(default)
+        .chain(
+            registry
+                .environments
+                .iter()
+                .map(|(name, environment)| EnvironmentSummary {
+                    name: name.clone(),
+                    venv: Some(environment.venv.clone()),
+                    packages: environment.packages.len(),
+                    present: Path::new(&venv_python(&environment.venv)).exists(),
+                }),
+        )
+        .collect()
+}
+
+/// Renders the environment table of `ppm env list`
+///
+/// # Arguments
+/// * `summaries` - Rows from [`environment_summaries`]
+pub fn render_environments(summaries: &[EnvironmentSummary]) -> String {
+    let name_width = summaries
+        .iter()
+        .map(|summary| summary.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let mut out = format!(
+        "  {:<name_width$}  {:>8}  Virtual environment\n",
+        "Name", "Packages"
+    );
+    for summary in summaries {
+        let venv = match &summary.venv {
+            None => "(interpreter on PATH)".to_string(),
+            Some(venv) if summary.present => venv.display().to_string(),
+            Some(venv) => format!("{} (missing)", venv.display()),
+        };
+        out.push_str(&format!(
+            "  {:<name_width$}  {:>8}  {}\n",
+            summary.name, summary.packages, venv
+        ));
+    }
+    out
+}
+
+/// Creates a named environment and its virtual environment
+///
+/// A directory that already holds a virtual environment is bound as it is.
+///
+/// # Arguments
+/// * `registry` - Mutable reference to the whole package registry
+/// * `name` - Name of the new environment
+/// * `venv` - Where its virtual environment lives (defaults to `.ppm/envs/<name>`)
+/// * `python` - Interpreter that creates the virtual environment (defaults to the one on PATH)
+///
+/// # Returns
+/// * `Result<PathBuf>` - The virtual environment, or an error if the name is
+///   taken or the virtual environment could not be created
+pub fn create_environment(
+    registry: &mut PackageRegistry,
+    name: &str,
+    venv: Option<&Path>,
+    python: Option<&str>,
+) -> Result<PathBuf> {
+    validate_environment_name(name)?;
+    if registry.environments.contains_key(name) {
+        return Err(PackageError::IoError(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("Environment {} already exists", name),
+        )));
+    }
+
+    let venv = venv.map_or_else(|| environments_dir().join(name), Path::to_path_buf);
+    if Path::new(&venv_python(&venv)).exists() {
+        status!(
+            "Using the existing virtual environment in {}",
+            venv.display()
+        );
+    } else {
+        status!("Creating virtual environment in {}...", venv.display());
+        if let Some(parent) = venv.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let previous = match python {
+            Some(python) => PYTHON_OVERRIDE.lock().unwrap().replace(python.to_string()),
+            None => PYTHON_OVERRIDE.lock().unwrap().clone(),
+        };
+        let created = create_virtualenv(&venv);
+        set_python_override(previous);
+        created?;
+    }
+
+    registry.environments.insert(
+        name.to_string(),
+        NamedEnvironment {
+            venv: venv.clone(),
+            ..NamedEnvironment::default()
+        },
+    );
+    Ok(venv)
+}
+
+/// Stops tracking a named environment
+///
+/// # Arguments
+/// * `registry` - Mutable reference to the whole package registry
+/// * `name` - Name of the environment
+/// * `delete_venv` - Whether to delete its virtual environment as well
+///
+/// # Returns
+/// * `Result<NamedEnvironment>` - The removed environment, or an error if it does not exist
+pub fn remove_environment(
+    registry: &mut PackageRegistry,
+    name: &str,
+    delete_venv: bool,
+) -> Result<NamedEnvironment> {
+    let environment = registry
+        .environments
+        .remove(name)
+        .ok_or_else(|| unknown_environment(name))?;
+    if delete_venv && environment.venv.exists() {
+        std::fs::remove_dir_all(&environment.venv)?;
+    }
+    Ok(environment)
+}
+
+/// Requirement spec that installs the same release from the same source as a registered package
+///
+/// # Arguments
+/// * `package` - The registered package
+///
+/// # Returns
+/// The commit of a git install, the URL of a direct reference, or `name==version`
+pub fn replica_spec(package: &Package) -> String {
+    if let Some(spec) = package.vcs_spec() {
+        return spec;
+    }
+    match &package.origin {
+        Some(origin) if origin.direct => format!("{} @ {}", package.name, origin.url),
+        _ => format!("{}=={}", package.name, package.version),
+    }
+}
+
+/// Creates a named environment holding the exact package set of another one
+///
+/// Every package of the source is installed without dependencies, at its
+/// registered version and from its recorded source, with one pip run per
+/// package so that a package that cannot be replicated fails on its own. The
+/// copies keep the source's explicit flags, dependencies and pins.
+///
+/// # Arguments
+/// * `registry` - Mutable reference to the whole package registry
+/// * `source` - Environment to copy, "default" for the top-level packages
+/// * `target` - Name of the new environment
+/// * `venv` - Its virtual environment (defaults to `.ppm/envs/<target>`)
+/// * `python` - Interpreter that creates it (defaults to the source's)
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if the
+///   environment could not be created or pip could not be run
+pub fn clone_environment(
+    registry: &mut PackageRegistry,
+    source: &str,
+    target: &str,
+    venv: Option<&Path>,
+    python: Option<&str>,
+) -> Result<InstallReport> {
+    let (packages, source_python) = if source == DEFAULT_ENVIRONMENT {
+        (registry.packages.clone(), get_python_executable()?)
+    } else {
+        let environment = registry
+            .environments
+            .get(source)
+            .ok_or_else(|| unknown_environment(source))?;
+        (environment.packages.clone(), venv_python(&environment.venv))
+    };
+
+    let venv = create_environment(
+        registry,
+        target,
+        venv,
+        Some(python.unwrap_or(&source_python)),
+    )?;
+    let mut specs: Vec<String> = packages.values().map(replica_spec).collect();
+    specs.sort_by_key(|spec| spec.to_lowercase());
+    status!(
+        "Replicating {} packages of {} into {}",
+        specs.len(),
+        source,
+        target
+    );
+
+    let options = InstallOptions::new()
+        .parallel(true)
+        .no_deps(true)
+        .python(venv_python(&std::env::current_dir()?.join(&venv)));
+    let mut replica = PackageRegistry::new();
+    let mut report = match install_with_options(&specs, &options, &mut replica) {
+        Ok(report) => report,
+        Err(e) => {
+            remove_environment(registry, target, true)?;
+            return Err(e);
+        }
+    };
+    report.title = "Clone summary".to_string();
+    report.verb = "clone".to_string();
+
+    let originals: HashMap<String, &Package> = packages
+        .values()
+        .map(|package| (canonicalize_name(&package.name), package))
+        .collect();
+    for package in replica.packages.values_mut() {
+        if let Some(original) = originals.get(&canonicalize_name(&package.name)) {
+            package.explicit = original.explicit;
+            package.dependencies = original.dependencies.clone();
+            package.pinned = original.pinned;
+        }
+    }
+    if let Some(environment) = registry.environments.get_mut(target) {
+        environment.packages = replica.packages;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::PackageOrigin;
+
+    #[test]
+    fn test_replica_spec() {
+        let mut package = Package::new("rich".to_string(), "13.7.0".to_string());
+        assert_eq!(replica_spec(&package), "rich==13.7.0");
+
+        package.origin = Some(PackageOrigin {
+            url: "https://files.pythonhosted.org/rich-13.7.0-py3-none-any.whl".to_string(),
+            hash: Some("sha256=abc".to_string()),
+            direct: false,
+            commit: None,
+        });
+        assert_eq!(replica_spec(&package), "rich==13.7.0");
+
+        package.origin = Some(PackageOrigin {
+            url: "file:///wheels/rich-13.7.0-py3-none-any.whl".to_string(),
+            hash: None,
+            direct: true,
+            commit: None,
+        });
+        assert_eq!(
+            replica_spec(&package),
+            "rich @ file:///wheels/rich-13.7.0-py3-none-any.whl"
+        );
+
+        package.origin = Some(PackageOrigin {
+            url: "https://github.com/Textualize/rich".to_string(),
+            hash: None,
+            direct: true,
+            commit: Some("a".repeat(40)),
+        });
+        assert_eq!(
+            replica_spec(&package),
+            format!(
+                "rich @ git+https://github.com/Textualize/rich@{}",
+                "a".repeat(40)
+            )
+        );
+    }
+
+    #[test]
+    fn test_environment_summaries() {
+        assert!(validate_environment_name("api").is_ok());
+        assert!(validate_environment_name("default").is_err());
+        assert!(validate_environment_name("../api").is_err());
+        assert!(validate_environment_name("").is_err());
+
+        let dir = tempfile::tempdir().unwrap();
+        let venv = dir.path().join("worker");
+        std::fs::create_dir_all(Path::new(&venv_python(&venv)).parent().unwrap()).unwrap();
+        std::fs::write(venv_python(&venv), "").unwrap();
+
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("rich".to_string(), "13.7.0".to_string()));
+        assert_eq!(
+            create_environment(&mut registry, "worker", Some(&venv), None).unwrap(),
+            venv
+        );
+        assert!(create_environment(&mut registry, "worker", Some(&venv), None).is_err());
+
+        let summaries = environment_summaries(&registry);
+        assert_eq!(
+            summaries
+                .iter()
+                .map(|s| (s.name.as_str(), s.packages, s.present))
+                .collect::<Vec<_>>(),
+            [("default", 1, true), ("worker", 0, true)]
+        );
+
+        remove_environment(&mut registry, "worker", true).unwrap();
+        assert!(registry.environments.is_empty());
+        assert!(!venv.exists());
+        assert!(remove_environment(&mut registry, "worker", false).is_err());
+    }
+}
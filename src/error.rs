@@ -0,0 +1,317 @@
+//! Errors returned by the library
+
+use crate::diagnostics::{diagnose_pip_output, network_diagnostic, Diagnostic};
+use crate::session::error_summary;
+use serde::Serialize;
+use std::{fmt, result};
+
+/// Custom error type for package management operations
+#[derive(Debug)]
+pub enum PackageError {
+    /// IO operation failed
+    IoError(std::io::Error),
+    /// Python executable not found
+    PythonNotFound,
+    /// Package installation failed
+    InstallationFailed(String),
+    /// Package uninstallation failed
+    UninstallationFailed(String),
+    /// Invalid package specification
+    InvalidPackageSpec(String),
+    /// JSON serialization/deserialization failed
+    JsonError(serde_json::Error),
+    /// Package not found in registry
+    PackageNotFound(String),
+    /// Package metadata could not be fetched or parsed
+    MetadataError(String),
+    /// A server answered a request with an error status, e.g. `404 Not Found`
+    HttpStatus(HttpFailure),
+    /// Package is still required by other registered packages
+    RequiredByOthers(String, Vec<String>),
+    /// pip could not reach the package index
+    NetworkError(PipFailure),
+    /// pip found no set of versions satisfying every requirement
+    ResolutionConflict(PipFailure),
+    /// Building a package from source failed
+    BuildFailed(PipFailure),
+    /// pip was not allowed to write to the environment
+    PermissionDenied(PipFailure),
+    /// A request to the package index timed out
+    Timeout(PipFailure),
+    /// Some items of a batch failed: what failed (e.g. "packages failed to install"),
+    /// failed count, total count
+    PartialFailure(String, usize, usize),
+    /// The run broke a rule it was asked to enforce, e.g. `--deny-warnings`
+    PolicyViolation(String),
+    /// Downloaded archives did not match the hashes they were pinned to
+    HashMismatch(Vec<HashMismatch>),
+    /// The registry file is corrupted or was changed outside ppm
+    RegistryIntegrity(String),
+    /// A package or package source is not allowed by the package policy of ppm.toml or
+    /// the organization policy
+    PackageDenied(String),
+}
+
+/// Details of a failed pip invocation
+#[derive(Debug, Clone, PartialEq)]
+pub struct PipFailure {
+    /// Package (or space-separated packages) pip was working on
+    pub package: String,
+    /// Exit code of pip, if it exited normally
+    pub exit_status: Option<i32>,
+    /// pip's stderr without boilerplate warnings, limited to its last lines
+    pub stderr: String,
+}
+
+impl PipFailure {
+    /// The line of stderr that best describes the failure
+    pub fn reason(&self) -> &str {
+        error_summary(&self.stderr)
+    }
+}
+
+impl fmt::Display for PipFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.exit_status {
+            Some(code) => write!(f, "pip exited with status {}: {}", code, self.reason()),
+            None => write!(f, "pip was terminated: {}", self.reason()),
+        }
+    }
+}
+
+impl std::error::Error for PipFailure {}
+
+/// Details of a request a server answered with an error status
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpFailure {
+    /// URL that was requested
+    pub url: String,
+    /// Status code of the answer, e.g. 404
+    pub status: u16,
+    /// Reason phrase of the answer, e.g. "Not Found"
+    pub reason: String,
+}
+
+impl fmt::Display for HttpFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to fetch {}: HTTP Error {}: {}",
+            self.url, self.status, self.reason
+        )
+    }
+}
+
+impl std::error::Error for HttpFailure {}
+
+/// A downloaded archive whose sha256 did not match the hashes it was pinned to
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HashMismatch {
+    /// Requirement the archive was downloaded for, e.g. "numpy==1.26.4"
+    pub requirement: String,
+    /// URL the archive was downloaded from
+    pub url: String,
+    /// Hashes the requirement allows
+    pub expected: Vec<String>,
+    /// Hash of the downloaded archive
+    pub actual: String,
+}
+
+impl HashMismatch {
+    /// URL without its `#sha256=...` fragment or query
+    fn location(&self) -> &str {
+        self.url.split(['#', '?']).next().unwrap_or(&self.url)
+    }
+
+    /// File name of the archive
+    pub fn file_name(&self) -> &str {
+        let location = self.location();
+        location.rsplit('/').next().unwrap_or(location)
+    }
+
+    /// Index or directory the archive came from: its URL without the file name
+    pub fn source(&self) -> &str {
+        let location = self.location();
+        location
+            .rsplit_once('/')
+            .map_or(location, |(source, _)| source)
+    }
+}
+
+impl fmt::Display for HashMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}) from {}: expected sha256 {}, got {}",
+            self.file_name(),
+            self.requirement,
+            self.source(),
+            self.expected.join(" or "),
+            self.actual
+        )
+    }
+}
+
+impl fmt::Display for PackageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageError::IoError(e) => write!(f, "IO error: {}", e),
+            PackageError::PythonNotFound => write!(f, "Python executable not found"),
+            PackageError::InstallationFailed(msg) => write!(f, "Installation failed: {}", msg),
+            PackageError::UninstallationFailed(msg) => write!(f, "Uninstallation failed: {}", msg),
+            PackageError::InvalidPackageSpec(spec) => write!(f, "Invalid package spec: {}", spec),
+            PackageError::JsonError(e) => write!(f, "JSON error: {}", e),
+            PackageError::PackageNotFound(name) => write!(f, "Package not found: {}", name),
+            PackageError::MetadataError(msg) => write!(f, "Metadata error: {}", msg),
+            PackageError::HttpStatus(failure) => write!(f, "Metadata error: {}", failure),
+            PackageError::RequiredByOthers(name, dependents) => write!(
+                f,
+                "Package {} is required by: {} (use --cascade to remove them too or --force to remove it anyway)",
+                name,
+                dependents.join(", ")
+            ),
+            PackageError::NetworkError(failure) => write!(
+                f,
+                "Network error while processing {}: {}",
+                failure.package,
+                failure.reason()
+            ),
+            PackageError::ResolutionConflict(failure) => write!(
+                f,
+                "Conflicting requirements for {}: {}",
+                failure.package,
+                failure.reason()
+            ),
+            PackageError::BuildFailed(failure) => write!(
+                f,
+                "Building {} failed: {}",
+                failure.package,
+                failure.reason()
+            ),
+            PackageError::PermissionDenied(failure) => write!(
+                f,
+                "Permission denied while processing {}: {}",
+                failure.package,
+                failure.reason()
+            ),
+            PackageError::Timeout(failure) => write!(
+                f,
+                "Timed out while processing {}: {}",
+                failure.package,
+                failure.reason()
+            ),
+            PackageError::PartialFailure(what, failed, total) => {
+                write!(f, "{} of {} {}", failed, total, what)
+            }
+            PackageError::PolicyViolation(msg) => write!(f, "Policy violation: {}", msg),
+            PackageError::HashMismatch(mismatches) => {
+                write!(f, "Hash verification failed for ")?;
+                let files: Vec<String> = mismatches.iter().map(|m| m.to_string()).collect();
+                write!(f, "{}", files.join("; "))
+            }
+            PackageError::RegistryIntegrity(msg) => {
+                write!(f, "Registry integrity check failed for {}", msg)
+            }
+            PackageError::PackageDenied(msg) => write!(f, "Denied by policy: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for PackageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PackageError::IoError(e) => Some(e),
+            PackageError::JsonError(e) => Some(e),
+            PackageError::HttpStatus(failure) => Some(failure),
+            PackageError::NetworkError(failure)
+            | PackageError::ResolutionConflict(failure)
+            | PackageError::BuildFailed(failure)
+            | PackageError::PermissionDenied(failure)
+            | PackageError::Timeout(failure) => Some(failure),
+            _ => None,
+        }
+    }
+}
+
+impl PackageError {
+    /// Stable code identifying the class of this error, e.g. "PPM0003"
+    ///
+    /// Codes never change meaning once assigned; `ppm explain <code>` prints
+    /// the causes and remediation steps for each of them.
+    pub fn code(&self) -> &'static str {
+        match self {
+            PackageError::IoError(_) => "PPM0001",
+            PackageError::JsonError(_) => "PPM0002",
+            PackageError::PythonNotFound => "PPM0003",
+            PackageError::InstallationFailed(_) => "PPM0004",
+            PackageError::UninstallationFailed(_) => "PPM0005",
+            PackageError::InvalidPackageSpec(_) => "PPM0006",
+            PackageError::PackageNotFound(_) => "PPM0007",
+            PackageError::MetadataError(_) | PackageError::HttpStatus(_) => "PPM0008",
+            PackageError::RequiredByOthers(_, _) => "PPM0009",
+            PackageError::NetworkError(_) => "PPM0010",
+            PackageError::ResolutionConflict(_) => "PPM0011",
+            PackageError::BuildFailed(_) => "PPM0012",
+            PackageError::PermissionDenied(_) => "PPM0013",
+            PackageError::Timeout(_) => "PPM0014",
+            PackageError::PartialFailure(_, _, _) => "PPM0015",
+            PackageError::PolicyViolation(_) => "PPM0016",
+            PackageError::HashMismatch(_) => "PPM0017",
+            PackageError::RegistryIntegrity(_) => "PPM0018",
+            PackageError::PackageDenied(_) => "PPM0019",
+        }
+    }
+
+    /// Details of the failed pip invocation, for errors classified from pip's output
+    pub fn pip_failure(&self) -> Option<&PipFailure> {
+        match self {
+            PackageError::NetworkError(failure)
+            | PackageError::ResolutionConflict(failure)
+            | PackageError::BuildFailed(failure)
+            | PackageError::PermissionDenied(failure)
+            | PackageError::Timeout(failure) => Some(failure),
+            _ => None,
+        }
+    }
+
+    /// Recognizes a known pip failure pattern behind this error
+    ///
+    /// # Returns
+    /// * `Option<Diagnostic>` - Explanation and suggested fix, if the pattern is known
+    pub fn diagnose(&self) -> Option<Diagnostic> {
+        match self {
+            PackageError::InstallationFailed(stderr)
+            | PackageError::UninstallationFailed(stderr) => diagnose_pip_output(stderr),
+            PackageError::NetworkError(_) => Some(network_diagnostic()),
+            PackageError::HashMismatch(mismatches) => Some(Diagnostic {
+                summary: format!(
+                    "{} downloaded archive(s) did not match their pinned hashes; nothing was installed",
+                    mismatches.len()
+                ),
+                details: mismatches.iter().map(|m| m.to_string()).collect(),
+                suggestion: "If the versions were changed on purpose, regenerate the hashes \
+                    (e.g. with `ppm lock`); otherwise check the index, the archive may have been \
+                    tampered with"
+                    .to_string(),
+            }),
+            _ => self
+                .pip_failure()
+                .and_then(|failure| diagnose_pip_output(&failure.stderr)),
+        }
+    }
+}
+
+impl From<std::io::Error> for PackageError {
+    fn from(error: std::io::Error) -> Self {
+        PackageError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for PackageError {
+    fn from(error: serde_json::Error) -> Self {
+        PackageError::JsonError(error)
+    }
+}
+
+/// Custom Result type for package operations
+pub type Result<T> = result::Result<T, PackageError>;
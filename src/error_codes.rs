@@ -0,0 +1,315 @@
+//! The `PPM` error codes and their explanations, see `ppm explain`
+
+/// Documentation of a stable error code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErrorCodeInfo {
+    /// The code, e.g. "PPM0003"
+    pub code: &'static str,
+    /// One-line description of the failure class
+    pub title: &'static str,
+    /// Common causes of the failure
+    pub causes: &'static [&'static str],
+    /// Steps that usually resolve it
+    pub remediation: &'static [&'static str],
+}
+
+/// Every stable error code, in numeric order
+pub const ERROR_CODES: &[ErrorCodeInfo] = &[
+    ErrorCodeInfo {
+        code: "PPM0001",
+        title: "I/O error",
+        causes: &[
+            "A file such as packages.json, a requirements file or a report path could not be read or written",
+            "The current directory is not writable",
+        ],
+        remediation: &[
+            "Check that the path exists and that you have permission to access it",
+            "Run the command from a writable project directory",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0002",
+        title: "JSON error",
+        causes: &[
+            "A JSON document produced by Python or PyPI could not be parsed",
+            "A state file under .ppm/ was edited by hand and is no longer valid JSON",
+        ],
+        remediation: &[
+            "Delete the damaged file under .ppm/; it is recreated automatically",
+            "Re-run with -vv to see the command whose output could not be parsed",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0003",
+        title: "Python not found",
+        causes: &[
+            "None of python3, python or py is on PATH",
+            "The interpreter found on PATH fails to start",
+        ],
+        remediation: &[
+            "Install Python 3 and make sure `python3 --version` works in this shell",
+            "Activate the virtual environment you want to manage before running ppm",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0004",
+        title: "Installation failed",
+        causes: &[
+            "No release matches the requested version, or the package name is misspelled",
+            "PyPI or the configured index could not be reached",
+            "Building a source distribution failed because of missing compilers or headers",
+        ],
+        remediation: &[
+            "Check the failure reason in the summary table and the name on https://pypi.org",
+            "Re-run with -v to stream pip's output, or with --log-file to keep it",
+            "Retry only the failed packages with the printed \"Failed specs\" line",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0005",
+        title: "Uninstallation failed",
+        causes: &[
+            "pip could not remove the package files, usually because of missing permissions",
+            "The package is not installed in the active environment",
+        ],
+        remediation: &[
+            "Check that the environment is writable or use a virtual environment",
+            "Run `ppm list` and compare it with `python -m pip list`",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0006",
+        title: "Invalid package specification",
+        causes: &[
+            "A package argument is malformed, e.g. an empty name or version",
+            "Conflicting or missing command-line arguments",
+        ],
+        remediation: &[
+            "Use `name`, `name==version` or `name<range>` forms",
+            "Run the command with --help to see the accepted arguments",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0007",
+        title: "Package not found",
+        causes: &[
+            "The package is not recorded in packages.json",
+            "The package is not installed in the active environment",
+        ],
+        remediation: &[
+            "Run `ppm list` to see the registered packages",
+            "Install the package first with `ppm install <name>`",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0008",
+        title: "Metadata error",
+        causes: &[
+            "PyPI could not be reached or returned an unexpected document",
+            "The index answered with an error status, e.g. 404 for a package it does not know",
+            "No release of the package satisfies the requested range",
+        ],
+        remediation: &[
+            "Check your network connection and proxy settings",
+            "Loosen the requested version range or pass --pre to allow pre-releases",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0009",
+        title: "Package required by others",
+        causes: &["Other registered packages depend on the package being removed"],
+        remediation: &[
+            "Pass --cascade to remove the dependents as well",
+            "Pass --force to remove the package anyway",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0010",
+        title: "Network error",
+        causes: &[
+            "There is no network connection or DNS does not resolve the index host",
+            "A proxy or firewall blocks access to PyPI",
+            "TLS verification failed, e.g. behind an intercepting proxy",
+        ],
+        remediation: &[
+            "Check that https://pypi.org is reachable from this machine",
+            "Configure HTTPS_PROXY or pip's proxy and certificate settings",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0011",
+        title: "Resolution conflict",
+        causes: &[
+            "Two requirements ask for incompatible versions of the same package",
+            "A constraints file caps a package below what another package needs",
+        ],
+        remediation: &[
+            "Read the conflict report with -v to see which requirements disagree",
+            "Loosen one of the conflicting version pins or constraints",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0012",
+        title: "Build failed",
+        causes: &[
+            "No wheel is available for this platform, so pip built from source and the build failed",
+            "A compiler, system library or header file needed by the build is missing",
+            "The build backend of the project given to `ppm build` reported an error",
+        ],
+        remediation: &[
+            "Install the build dependencies listed in the package documentation",
+            "For `ppm build`, fix the error shown in the hint, e.g. in pyproject.toml",
+            "Pick a version that ships wheels for your Python version and platform",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0013",
+        title: "Permission denied",
+        causes: &["The Python environment is owned by another user, e.g. the system Python"],
+        remediation: &[
+            "Use a virtual environment (`python3 -m venv .venv`) and activate it",
+            "Fix the ownership of the site-packages directory",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0014",
+        title: "Timeout",
+        causes: &["The package index answered too slowly or the connection stalled"],
+        remediation: &[
+            "Retry the command; transient timeouts are common on busy networks",
+            "Raise pip's timeout with PIP_DEFAULT_TIMEOUT=60",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0015",
+        title: "Partial failure",
+        causes: &["Some packages of a batch operation failed while the others succeeded"],
+        remediation: &[
+            "Check the Failures section of the summary for the reason of each package",
+            "Re-run the command with the specs listed under \"Failed specs\"",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0016",
+        title: "Policy violation",
+        causes: &[
+            "Warnings were emitted while --deny-warnings was given",
+            "A confirmation prompt was needed in CI mode or without a terminal on stdin",
+            "A package to install is named like a popular package, a common typosquatting trick",
+        ],
+        remediation: &[
+            "Fix the cause of the warnings, or drop --deny-warnings",
+            "Pass --yes to confirm destructive operations ahead of time",
+            "Check the spelling; pass --allow-similar-name if the name is the one you meant",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0017",
+        title: "Hash mismatch",
+        causes: &[
+            "A package was re-released or changed since its hashes were recorded",
+            "The index or a proxy in between served a different or tampered archive",
+        ],
+        remediation: &[
+            "Compare the file and index in the error with the ones the hashes were taken from",
+            "Regenerate the lockfile or the hashes only once the new archive is trusted",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0018",
+        title: "Registry integrity check failed",
+        causes: &[
+            "packages.json was edited by hand, by a plugin or by another tool",
+            "packages.json has no checksum, e.g. because it was written by an older ppm",
+            "A write to packages.json was cut short, e.g. by a full disk",
+            "PPM_REGISTRY_KEY is missing or differs from the key the registry was signed with",
+        ],
+        remediation: &[
+            "Inspect the file, e.g. with `git diff packages.json`",
+            "Run `ppm registry verify` to see what failed",
+            "Run `ppm registry rehash` to accept the current contents once they are trusted",
+        ],
+    },
+    ErrorCodeInfo {
+        code: "PPM0019",
+        title: "Denied by policy",
+        causes: &[
+            "The package matches a `deny` pattern of the [policy] table in ppm.toml",
+            "[policy] has an `allow` list and the package is not on it",
+            "pip would download from an index or URL missing from the policy's `indexes`",
+            "The license of a package or one of its dependencies is not in `allowed-licenses`, \
+             or could not be looked up",
+            "A requirement, or the resolved version of a dependency, is below `minimum-versions`",
+            "The policy sets `require-hashes` and pip would run without pinned hashes",
+            "The organization policy has no pinned sha256, could not be fetched, or does not \
+             match its sha256",
+        ],
+        remediation: &[
+            "Pick an approved package, or ask the owners of the policy to allow this one",
+            "Point pip at an approved index, e.g. with `indexes` in the [http] table of ppm.toml",
+            "Install from a lockfile with `ppm install --lock` when hashes are required",
+            "Check the `url` and `sha256` of [policy], or PPM_POLICY_URL and PPM_POLICY_SHA256",
+        ],
+    },
+];
+
+/// Looks up the documentation of an error code
+///
+/// Accepts the code in any case and with or without the `PPM` prefix, so
+/// "PPM0003", "ppm0003" and "3" are equivalent.
+///
+/// # Arguments
+/// * `code` - The code to look up
+///
+/// # Returns
+/// The documentation, or `None` for an unknown code
+pub fn explain_error_code(code: &str) -> Option<&'static ErrorCodeInfo> {
+    let code = code.trim().to_ascii_uppercase();
+    let digits = code.strip_prefix("PPM").unwrap_or(&code);
+    let number: u32 = digits.parse().ok()?;
+    let wanted = format!("PPM{:04}", number);
+    ERROR_CODES.iter().find(|info| info.code == wanted)
+}
+
+/// Renders the explanation of an error code
+///
+/// # Arguments
+/// * `info` - The error code documentation
+///
+/// # Returns
+/// The rendered explanation, terminated by a newline
+pub fn render_error_explanation(info: &ErrorCodeInfo) -> String {
+    let mut out = format!("{}: {}\n\nCommon causes:\n", info.code, info.title);
+    for cause in info.causes {
+        out.push_str(&format!("  - {}\n", cause));
+    }
+    out.push_str("\nHow to fix it:\n");
+    for step in info.remediation {
+        out.push_str(&format!("  - {}\n", step));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PackageError;
+
+    #[test]
+    fn test_error_codes() {
+        assert_eq!(PackageError::PythonNotFound.code(), "PPM0003");
+        assert_eq!(
+            explain_error_code("PPM0003").unwrap().title,
+            "Python not found"
+        );
+        assert_eq!(explain_error_code("ppm0003").unwrap().code, "PPM0003");
+        assert_eq!(explain_error_code("3").unwrap().code, "PPM0003");
+        assert!(explain_error_code("PPM9999").is_none());
+        assert!(explain_error_code("python").is_none());
+
+        // Codes are unique and listed in order
+        for pair in ERROR_CODES.windows(2) {
+            assert!(pair[0].code < pair[1].code);
+        }
+    }
+}
@@ -0,0 +1,587 @@
+//! The dependency graph between packages, built from recorded or installed metadata
+
+use crate::pip::run_command;
+use crate::registry::PackageRegistry;
+use crate::spec::canonicalize_name;
+use crate::{PackageError, Result};
+use petgraph::algo::toposort;
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::process::Command;
+
+/// A package in a [`DependencyGraph`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct DependencyNode {
+    /// Registry key or distribution name of the package
+    pub name: String,
+    /// Installed version, or `None` for a dependency that is not present
+    pub version: Option<String>,
+    /// Whether the package was requested explicitly rather than pulled in as a dependency
+    pub explicit: bool,
+}
+
+/// Directed graph with an edge from every package to each of its dependencies
+///
+/// Packages are looked up by name, ignoring case and separator differences.
+/// Dependencies that are named by a package but not present get a node
+/// without a version, so their dependents can still be found; they are left
+/// out of [`DependencyGraph::packages`], the orderings and the orphans.
+///
+/// ```ignore
+/// let graph = DependencyGraph::from_registry(&registry);
+/// for name in graph.dependents("urllib3") {
+///     println!("{} needs urllib3", name);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct DependencyGraph {
+    graph: DiGraph<DependencyNode, ()>,
+    nodes: HashMap<String, NodeIndex>,
+}
+
+/// One distribution printed by [`INSTALLED_REQUIREMENTS_SCRIPT`]
+#[derive(Debug, Deserialize)]
+struct InstalledDistribution {
+    name: String,
+    version: String,
+    requires: Vec<String>,
+}
+
+/// Python snippet that prints every installed distribution with the names of its requirements
+///
+/// Requirements that only apply to an extra are left out, like `pip show` does.
+const INSTALLED_REQUIREMENTS_SCRIPT: &str = r#"
+import json, os, re
+from importlib import metadata
+local = os.environ.get("PPM_LOCAL_PACKAGES")
+print(json.dumps([
+    {"name": d.metadata["Name"], "version": d.version,
+     "requires": [re.split(r"[\s;<>=!~\[(]", r, maxsplit=1)[0] for r in d.requires or [] if "extra ==" not in r]}
+    for d in metadata.distributions(**({"path": [local]} if local else {}))
+]))
+"#;
+
+impl DependencyGraph {
+    /// Creates an empty graph
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the graph from the dependencies recorded in the registry
+    ///
+    /// # Arguments
+    /// * `registry` - The registry whose packages become the nodes
+    ///
+    /// # Returns
+    /// A graph whose package names are the registry keys
+    pub fn from_registry(registry: &PackageRegistry) -> Self {
+        let mut graph = Self::new();
+        let mut keys: Vec<&String> = registry.packages.keys().collect();
+        keys.sort();
+        for key in &keys {
+            let package = &registry.packages[*key];
+            graph.add_package(key, &package.version, package.explicit);
+        }
+        for key in keys {
+            for dependency in &registry.packages[key].dependencies {
+                graph.add_dependency(key, dependency);
+            }
+        }
+        graph
+    }
+
+    /// Builds the graph from the distributions installed in an environment
+    ///
+    /// The environment does not record which packages were requested, so the
+    /// ones no other package requires count as explicit.
+    ///
+    /// # Arguments
+    /// * `python` - Interpreter of the environment
+    ///
+    /// # Returns
+    /// * `Result<Self>` - The graph, or an error if the interpreter could not list its packages
+    pub fn from_installed(python: &str) -> Result<Self> {
+        let output = run_command(
+            Command::new(python)
+                .arg("-c")
+                .arg(INSTALLED_REQUIREMENTS_SCRIPT),
+        )?;
+        if !output.status.success() {
+            return Err(PackageError::MetadataError(format!(
+                "Failed to read installed metadata of {}: {}",
+                python,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let mut installed: Vec<InstalledDistribution> = serde_json::from_slice(&output.stdout)?;
+        installed.sort_by(|a, b| a.name.cmp(&b.name));
+        let required: HashSet<String> = installed
+            .iter()
+            .flat_map(|d| d.requires.iter().map(|r| canonicalize_name(r)))
+            .collect();
+
+        let mut graph = Self::new();
+        for distribution in &installed {
+            let explicit = !required.contains(&canonicalize_name(&distribution.name));
+            graph.add_package(&distribution.name, &distribution.version, explicit);
+        }
+        for distribution in &installed {
+            for requirement in &distribution.requires {
+                graph.add_dependency(&distribution.name, requirement);
+            }
+        }
+        Ok(graph)
+    }
+
+    /// Adds a package, or fills in the version of a dependency added before it
+    ///
+    /// # Arguments
+    /// * `name` - The package name
+    /// * `version` - The installed version
+    /// * `explicit` - Whether the package was requested explicitly
+    pub fn add_package(&mut self, name: &str, version: &str, explicit: bool) {
+        let index = self.node(name);
+        let node = &mut self.graph[index];
+        node.name = name.to_string();
+        node.version = Some(version.to_string());
+        node.explicit = explicit;
+    }
+
+    /// Records that `package` depends on `dependency`, adding missing nodes
+    pub fn add_dependency(&mut self, package: &str, dependency: &str) {
+        let from = self.node(package);
+        let to = self.node(dependency);
+        self.graph.update_edge(from, to, ());
+    }
+
+    /// Gets a package by name
+    pub fn get(&self, name: &str) -> Option<&DependencyNode> {
+        self.index(name).map(|index| &self.graph[index])
+    }
+
+    /// Names of the packages present, sorted
+    pub fn packages(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .graph
+            .node_weights()
+            .filter(|node| node.version.is_some())
+            .map(|node| node.name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Finds the direct dependencies of a package
+    ///
+    /// # Returns
+    /// Sorted names of the packages `name` depends on, including missing ones
+    pub fn dependencies(&self, name: &str) -> Vec<String> {
+        self.neighbors(name, Direction::Outgoing)
+    }
+
+    /// Finds the packages that directly depend on a package
+    ///
+    /// # Returns
+    /// Sorted names of the direct dependents
+    pub fn dependents(&self, name: &str) -> Vec<String> {
+        self.neighbors(name, Direction::Incoming)
+    }
+
+    /// Finds every package that directly or transitively depends on a package
+    ///
+    /// Dependents on a cycle have no valid order; they are then sorted by name.
+    ///
+    /// # Returns
+    /// Names of the dependents, ordered so that each package comes before
+    /// the packages it depends on
+    pub fn transitive_dependents(&self, name: &str) -> Vec<String> {
+        let Some(start) = self.index(name) else {
+            return Vec::new();
+        };
+        let mut chain: HashSet<NodeIndex> = HashSet::new();
+        let mut frontier = vec![start];
+        while let Some(current) = frontier.pop() {
+            for dependent in self.graph.neighbors_directed(current, Direction::Incoming) {
+                if dependent != start && chain.insert(dependent) {
+                    frontier.push(dependent);
+                }
+            }
+        }
+
+        // Edges point at dependencies, so toposort puts every dependent before
+        // the packages it depends on, also where two paths meet again
+        let dependents = self.graph.filter_map(
+            |index, _| chain.contains(&index).then_some(index),
+            |_, _| Some(()),
+        );
+        match toposort(&dependents, None) {
+            Ok(order) => order
+                .into_iter()
+                .map(|index| self.graph[dependents[index]].name.clone())
+                .collect(),
+            Err(_) => {
+                let mut names: Vec<String> = chain
+                    .into_iter()
+                    .map(|index| self.graph[index].name.clone())
+                    .collect();
+                names.sort();
+                names
+            }
+        }
+    }
+
+    /// Orders the packages so that each one comes after its dependencies
+    ///
+    /// # Returns
+    /// * `Result<Vec<String>>` - The names in install order, or an error naming
+    ///   a package on a dependency cycle
+    pub fn topological_order(&self) -> Result<Vec<String>> {
+        let mut order = toposort(&self.graph, None).map_err(|cycle| {
+            PackageError::MetadataError(format!(
+                "Dependency cycle involving {}",
+                self.graph[cycle.node_id()].name
+            ))
+        })?;
+        // toposort puts dependents first, since edges point at dependencies
+        order.reverse();
+        Ok(order
+            .into_iter()
+            .map(|index| &self.graph[index])
+            .filter(|node| node.version.is_some())
+            .map(|node| node.name.clone())
+            .collect())
+    }
+
+    /// Orders a batch of packages so that dependents are removed before their dependencies
+    ///
+    /// A dependency cycle inside the batch has no valid order; its packages
+    /// are then taken in the order given.
+    pub fn removal_order(&self, names: &[String]) -> Vec<String> {
+        let mut remaining: Vec<String> = names.to_vec();
+        let mut ordered = Vec::with_capacity(names.len());
+
+        while !remaining.is_empty() {
+            let pending: HashSet<String> = remaining.iter().map(|n| canonicalize_name(n)).collect();
+            let position = remaining.iter().position(|name| {
+                self.dependents(name)
+                    .iter()
+                    .all(|d| !pending.contains(&canonicalize_name(d)))
+            });
+            ordered.push(remaining.remove(position.unwrap_or(0)));
+        }
+
+        ordered
+    }
+
+    /// Finds packages that were pulled in as dependencies but no explicit package needs anymore
+    ///
+    /// # Returns
+    /// Sorted names of the orphaned packages
+    pub fn orphans(&self) -> Vec<String> {
+        let mut required: HashSet<NodeIndex> = HashSet::new();
+        let mut pending: Vec<NodeIndex> = self
+            .graph
+            .node_indices()
+            .filter(|&index| self.graph[index].explicit)
+            .collect();
+        while let Some(index) = pending.pop() {
+            if required.insert(index) {
+                pending.extend(self.graph.neighbors_directed(index, Direction::Outgoing));
+            }
+        }
+
+        let mut orphans: Vec<String> = self
+            .graph
+            .node_indices()
+            .filter(|index| !required.contains(index))
+            .map(|index| &self.graph[index])
+            .filter(|node| node.version.is_some())
+            .map(|node| node.name.clone())
+            .collect();
+        orphans.sort();
+        orphans
+    }
+
+    /// Dependency edges as `(package, dependency)` pairs, sorted
+    pub fn edges(&self) -> Vec<(String, String)> {
+        let mut edges: Vec<(String, String)> = self
+            .graph
+            .edge_indices()
+            .filter_map(|edge| self.graph.edge_endpoints(edge))
+            .map(|(from, to)| (self.graph[from].name.clone(), self.graph[to].name.clone()))
+            .collect();
+        edges.sort();
+        edges
+    }
+
+    /// Takes the part of the graph a package depends on, directly or transitively
+    ///
+    /// # Arguments
+    /// * `root` - The package to start from
+    ///
+    /// # Returns
+    /// The root and everything it depends on, or `None` if `root` is not in the graph
+    pub fn rooted_at(&self, root: &str) -> Option<Self> {
+        let start = self.index(root)?;
+        let mut reachable: HashSet<NodeIndex> = HashSet::new();
+        let mut pending = vec![start];
+        while let Some(index) = pending.pop() {
+            if reachable.insert(index) {
+                pending.extend(self.graph.neighbors_directed(index, Direction::Outgoing));
+            }
+        }
+
+        let mut subgraph = Self::new();
+        let mut indices: Vec<NodeIndex> = reachable.iter().copied().collect();
+        indices.sort_by(|a, b| self.graph[*a].name.cmp(&self.graph[*b].name));
+        for &index in &indices {
+            let node = &self.graph[index];
+            let added = subgraph.node(&node.name);
+            subgraph.graph[added] = node.clone();
+        }
+        for &index in &indices {
+            for dependency in self.graph.neighbors_directed(index, Direction::Outgoing) {
+                subgraph.add_dependency(&self.graph[index].name, &self.graph[dependency].name);
+            }
+        }
+        Some(subgraph)
+    }
+
+    /// Renders the graph in the Graphviz DOT language
+    ///
+    /// Explicitly requested packages are drawn bold and missing dependencies
+    /// dashed, e.g. for `ppm graph --format dot | dot -Tsvg > deps.svg`.
+    pub fn to_dot(&self) -> String {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut out =
+            String::from("digraph dependencies {\n    rankdir=LR;\n    node [shape=box];\n");
+        for node in self.sorted_nodes() {
+            let (label, style) = match &node.version {
+                Some(version) => (
+                    format!("{}\\n{}", escape(&node.name), escape(version)),
+                    if node.explicit { "bold" } else { "solid" },
+                ),
+                None => (escape(&node.name), "dashed"),
+            };
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{}\", style={}];\n",
+                escape(&node.name),
+                label,
+                style
+            ));
+        }
+        for (from, to) in self.edges() {
+            out.push_str(&format!(
+                "    \"{}\" -> \"{}\";\n",
+                escape(&from),
+                escape(&to)
+            ));
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as a Mermaid flowchart
+    ///
+    /// The text goes inside a ` ```mermaid ` block, which GitHub, GitLab and
+    /// most documentation sites draw without extra tooling.
+    pub fn to_mermaid(&self) -> String {
+        let nodes = self.sorted_nodes();
+        let ids: HashMap<&str, String> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.name.as_str(), format!("n{}", i)))
+            .collect();
+        let mut out = String::from("graph LR\n");
+        for node in &nodes {
+            let label = match &node.version {
+                Some(version) => format!("{} {}", node.name, version),
+                None => node.name.clone(),
+            };
+            out.push_str(&format!(
+                "    {}[\"{}\"]\n",
+                ids[node.name.as_str()],
+                label.replace('"', "#quot;")
+            ));
+        }
+        for (from, to) in self.edges() {
+            out.push_str(&format!(
+                "    {} --> {}\n",
+                ids[from.as_str()],
+                ids[to.as_str()]
+            ));
+        }
+        let members = |wanted: fn(&DependencyNode) -> bool| -> Vec<String> {
+            nodes
+                .iter()
+                .filter(|node| wanted(node))
+                .map(|node| ids[node.name.as_str()].clone())
+                .collect()
+        };
+        for (class, style, members) in [
+            (
+                "explicit",
+                "font-weight:bold",
+                members(|n| n.explicit && n.version.is_some()),
+            ),
+            (
+                "missing",
+                "stroke-dasharray:4",
+                members(|n| n.version.is_none()),
+            ),
+        ] {
+            if !members.is_empty() {
+                out.push_str(&format!("    classDef {} {}\n", class, style));
+                out.push_str(&format!("    class {} {}\n", members.join(","), class));
+            }
+        }
+        out
+    }
+
+    /// Every node, including missing dependencies, sorted by name
+    fn sorted_nodes(&self) -> Vec<&DependencyNode> {
+        let mut nodes: Vec<&DependencyNode> = self.graph.node_weights().collect();
+        nodes.sort_by(|a, b| a.name.cmp(&b.name));
+        nodes
+    }
+
+    /// Index of a package, ignoring case and separator differences
+    fn index(&self, name: &str) -> Option<NodeIndex> {
+        self.nodes.get(&canonicalize_name(name)).copied()
+    }
+
+    /// Index of a package, adding a node for a missing one
+    fn node(&mut self, name: &str) -> NodeIndex {
+        let graph = &mut self.graph;
+        *self
+            .nodes
+            .entry(canonicalize_name(name))
+            .or_insert_with(|| {
+                graph.add_node(DependencyNode {
+                    name: name.to_string(),
+                    version: None,
+                    explicit: false,
+                })
+            })
+    }
+
+    /// Sorted names of the neighbors of a package in one direction
+    fn neighbors(&self, name: &str, direction: Direction) -> Vec<String> {
+        let Some(index) = self.index(name) else {
+            return Vec::new();
+        };
+        let mut names: Vec<String> = self
+            .graph
+            .neighbors_directed(index, direction)
+            .map(|neighbor| self.graph[neighbor].name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::Package;
+
+    fn registry() -> PackageRegistry {
+        let mut registry = PackageRegistry::new();
+        let mut flask = Package::new("Flask".to_string(), "3.0.2".to_string());
+        flask.dependencies = vec!["jinja2".to_string(), "werkzeug".to_string()];
+        registry.add_package(flask);
+        let mut jinja = Package::new("Jinja2".to_string(), "3.1.4".to_string());
+        jinja.explicit = false;
+        jinja.dependencies = vec!["markupsafe".to_string()];
+        registry.add_package(jinja);
+        let mut werkzeug = Package::new("werkzeug".to_string(), "3.0.1".to_string());
+        werkzeug.explicit = false;
+        werkzeug.dependencies = vec!["MarkupSafe".to_string()];
+        registry.add_package(werkzeug);
+        let mut leftover = Package::new("idna".to_string(), "3.7".to_string());
+        leftover.explicit = false;
+        registry.add_package(leftover);
+        registry
+    }
+
+    #[test]
+    fn test_dependency_graph_queries() {
+        let graph = DependencyGraph::from_registry(&registry());
+
+        assert_eq!(graph.dependencies("flask"), ["Jinja2", "werkzeug"]);
+        assert_eq!(graph.dependents("markupsafe"), ["Jinja2", "werkzeug"]);
+        // MarkupSafe is required but not registered
+        assert_eq!(graph.get("markupsafe").unwrap().version, None);
+        assert_eq!(graph.packages(), ["Flask", "Jinja2", "idna", "werkzeug"]);
+        assert_eq!(graph.orphans(), ["idna"]);
+
+        let order = graph.topological_order().unwrap();
+        let position = |name: &str| order.iter().position(|n| n == name).unwrap();
+        assert!(position("Jinja2") < position("Flask"));
+        assert!(position("werkzeug") < position("Flask"));
+    }
+
+    #[test]
+    fn test_transitive_dependents_of_a_diamond() {
+        // app needs web and base, web needs base: app must come before web
+        let mut graph = DependencyGraph::new();
+        for name in ["app", "web", "base", "cli"] {
+            graph.add_package(name, "1.0", true);
+        }
+        graph.add_dependency("app", "base");
+        graph.add_dependency("app", "web");
+        graph.add_dependency("web", "base");
+        graph.add_dependency("cli", "web");
+
+        let chain = graph.transitive_dependents("base");
+        assert_eq!(chain.len(), 3);
+        let position = |name: &str| chain.iter().position(|n| n == name).unwrap();
+        assert!(position("app") < position("web"));
+        assert!(position("cli") < position("web"));
+        assert_eq!(graph.transitive_dependents("web").len(), 2);
+        assert!(graph.transitive_dependents("app").is_empty());
+        assert!(graph.transitive_dependents("unknown").is_empty());
+    }
+
+    #[test]
+    fn test_dependency_graph_reports_cycles() {
+        let mut graph = DependencyGraph::new();
+        graph.add_package("a", "1.0", true);
+        graph.add_package("b", "1.0", false);
+        graph.add_dependency("a", "b");
+        graph.add_dependency("b", "a");
+
+        assert!(matches!(
+            graph.topological_order(),
+            Err(PackageError::MetadataError(e)) if e.contains("cycle")
+        ));
+        assert_eq!(
+            graph.removal_order(&["b".to_string(), "a".to_string()]),
+            ["b", "a"]
+        );
+    }
+
+    #[test]
+    fn test_render_rooted_graph() {
+        let graph = DependencyGraph::from_registry(&registry())
+            .rooted_at("jinja2")
+            .unwrap();
+        assert_eq!(graph.packages(), ["Jinja2"]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph dependencies {"));
+        assert!(dot.contains(r#""Jinja2" [label="Jinja2\n3.1.4", style=solid];"#));
+        assert!(dot.contains(r#""markupsafe" [label="markupsafe", style=dashed];"#));
+        assert!(dot.contains(r#""Jinja2" -> "markupsafe";"#));
+
+        assert_eq!(
+            graph.to_mermaid(),
+            "graph LR\n    n0[\"Jinja2 3.1.4\"]\n    n1[\"markupsafe\"]\n    n0 --> n1\n    \
+             classDef missing stroke-dasharray:4\n    class n1 missing\n"
+        );
+        assert!(DependencyGraph::new().rooted_at("flask").is_none());
+    }
+}
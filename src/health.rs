@@ -0,0 +1,540 @@
+//! Maintenance and release-cadence signals for a package
+
+use crate::history::parse_utc_date;
+use crate::http::http_client;
+use crate::install::map_bounded;
+use crate::metadata::fetch_project_json;
+use crate::pip::{get_python_executable, get_python_version};
+use crate::releases::release_date;
+use crate::spec::{compare_versions, is_prerelease};
+use crate::state::unix_now;
+use crate::Result;
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::fmt;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering as AtomicOrdering;
+
+/// Days without a release after which a package looks unmaintained, unless configured
+pub const DEFAULT_UNMAINTAINED_AFTER_DAYS: u64 = 3 * 365;
+
+/// Days without a release after which a package looks unmaintained; 0 disables the check
+static UNMAINTAINED_AFTER_DAYS: AtomicU64 = AtomicU64::new(DEFAULT_UNMAINTAINED_AFTER_DAYS);
+
+/// Sets after how many days without a release a package is flagged as unmaintained
+///
+/// # Arguments
+/// * `days` - The threshold; 0 turns the check off
+pub fn set_unmaintained_after(days: u64) {
+    UNMAINTAINED_AFTER_DAYS.store(days, AtomicOrdering::Relaxed);
+}
+
+/// Gets after how many days without a release a package is flagged as unmaintained
+pub fn unmaintained_after() -> u64 {
+    UNMAINTAINED_AFTER_DAYS.load(AtomicOrdering::Relaxed)
+}
+
+/// How many releases [`package_health`] measures the release cadence over
+const CADENCE_RELEASES: usize = 10;
+
+/// Health indicators of a package on PyPI, see [`package_info`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageHealth {
+    /// Package name
+    pub name: String,
+    /// One-line description of the package
+    pub summary: String,
+    /// Newest release
+    pub latest_version: String,
+    /// Day the newest release was published
+    pub latest_release_date: Option<String>,
+    /// Days since the newest release was published
+    pub days_since_release: Option<u64>,
+    /// Releases published in the last 365 days
+    pub releases_last_year: usize,
+    /// Median number of days between the most recent releases
+    pub release_interval_days: Option<u64>,
+    /// People named as author or maintainer in the package metadata
+    pub maintainers: Vec<String>,
+    /// The version compared against the newest release, e.g. the installed one
+    pub version: Option<String>,
+    /// Releases published after `version`
+    pub releases_behind: Option<usize>,
+    /// Days between the release of `version` and the newest release
+    pub days_behind: Option<u64>,
+    /// Whether `version` is a major version or more than a year behind
+    pub far_behind: bool,
+}
+
+/// Names the people listed as author or maintainer of a package
+///
+/// PyPI's JSON API does not expose the accounts that can publish a project,
+/// so the core metadata fields stand in for them. Names win over addresses;
+/// an address is used for an entry without a name.
+fn metadata_maintainers(info: &serde_json::Value) -> Vec<String> {
+    let mut people: Vec<String> = Vec::new();
+    let mut add = |person: &str| {
+        let person = person.trim().trim_matches('"').trim();
+        let known = people.iter().any(|p| p.eq_ignore_ascii_case(person));
+        if !person.is_empty() && !known {
+            people.push(person.to_string());
+        }
+    };
+    for (name_field, email_field) in [
+        ("author", "author_email"),
+        ("maintainer", "maintainer_email"),
+    ] {
+        let names = info[name_field].as_str().unwrap_or("");
+        for name in names.split(',') {
+            add(name);
+        }
+        if names.trim().is_empty() {
+            // "Jane Doe <jane@example.com>, ops@example.com" as written by modern build backends
+            for entry in info[email_field].as_str().unwrap_or("").split(',') {
+                match entry.split_once('<') {
+                    Some((name, _)) if !name.trim().is_empty() => add(name),
+                    _ => {
+                        add(entry.trim_matches(|c: char| c == '<' || c == '>' || c.is_whitespace()))
+                    }
+                }
+            }
+        }
+    }
+    people
+}
+
+/// Works out the health indicators of a package from its PyPI project document
+///
+/// Only final releases with files that are not all yanked count; the cadence
+/// is the median gap between the last ten of them.
+///
+/// # Arguments
+/// * `project` - The PyPI project document
+/// * `version` - The version to compare against the newest release, if any
+/// * `today` - The current day, in days since the Unix epoch
+///
+/// # Returns
+/// * `PackageHealth` - The indicators; dates are missing when PyPI has no upload times
+pub fn package_health(
+    project: &serde_json::Value,
+    version: Option<&str>,
+    today: u64,
+) -> PackageHealth {
+    let info = &project["info"];
+    let mut releases: Vec<(&String, Option<u64>)> = project["releases"]
+        .as_object()
+        .map(|releases| {
+            releases
+                .iter()
+                .filter(|(version, _)| !is_prerelease(version))
+                .filter(|(_, files)| {
+                    files.as_array().is_some_and(|files| {
+                        files
+                            .iter()
+                            .any(|file| !file["yanked"].as_bool().unwrap_or(false))
+                    })
+                })
+                .map(|(version, files)| {
+                    (
+                        version,
+                        release_date(files).as_deref().and_then(parse_utc_date),
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    releases.sort_by(|a, b| compare_versions(a.0, b.0));
+
+    let latest_version = info["version"]
+        .as_str()
+        .map(str::to_string)
+        .or_else(|| releases.last().map(|(version, _)| version.to_string()))
+        .unwrap_or_default();
+    let day_of = |wanted: &str| {
+        project["releases"]
+            .get(wanted)
+            .and_then(release_date)
+            .and_then(|date| parse_utc_date(&date).map(|day| (date, day)))
+    };
+    let latest = day_of(&latest_version);
+
+    let mut days: Vec<u64> = releases.iter().filter_map(|(_, day)| *day).collect();
+    days.sort_unstable();
+    let releases_last_year = days
+        .iter()
+        .filter(|day| today.saturating_sub(**day) < 365)
+        .count();
+    let recent = &days[days.len().saturating_sub(CADENCE_RELEASES)..];
+    let mut gaps: Vec<u64> = recent.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    gaps.sort_unstable();
+    let release_interval_days = gaps.get(gaps.len() / 2).copied();
+
+    let (releases_behind, days_behind, far_behind) = match version {
+        Some(version) => {
+            let behind = releases
+                .iter()
+                .filter(|(release, _)| {
+                    compare_versions(release, version) == Ordering::Greater
+                        && compare_versions(release, &latest_version) != Ordering::Greater
+                })
+                .count();
+            let days_behind = day_of(version)
+                .zip(latest.as_ref())
+                .map(|((_, from), (_, to))| to.saturating_sub(from));
+            let major = |v: &str| v.split('.').next().unwrap_or("").to_string();
+            let far = behind > 0
+                && (major(version) != major(&latest_version) || days_behind.unwrap_or(0) > 365);
+            (Some(behind), days_behind, far)
+        }
+        None => (None, None, false),
+    };
+
+    PackageHealth {
+        name: info["name"].as_str().unwrap_or("").to_string(),
+        summary: info["summary"].as_str().unwrap_or("").trim().to_string(),
+        latest_version,
+        days_since_release: latest.as_ref().map(|(_, day)| today.saturating_sub(*day)),
+        latest_release_date: latest.map(|(date, _)| date),
+        releases_last_year,
+        release_interval_days,
+        maintainers: metadata_maintainers(info),
+        version: version.map(str::to_string),
+        releases_behind,
+        days_behind,
+        far_behind,
+    }
+}
+
+/// Looks up a package on PyPI with its health indicators
+///
+/// The project document comes from the metadata cache while it is fresh.
+///
+/// # Arguments
+/// * `name` - The package name
+/// * `version` - The version to compare against the newest release, if any
+///
+/// # Returns
+/// * `Result<PackageHealth>` - The indicators, or an error if PyPI could not be reached
+pub fn package_info(name: &str, version: Option<&str>) -> Result<PackageHealth> {
+    let python = get_python_executable()?;
+    let project = fetch_project_json(&python, name)?;
+    let mut health = package_health(&project, version, unix_now() / 86_400);
+    if health.name.is_empty() {
+        health.name = name.to_string();
+    }
+    Ok(health)
+}
+
+/// A package that looks abandoned, see [`check_maintenance`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MaintenanceWarning {
+    /// Package name
+    pub name: String,
+    /// What makes it look unmaintained, e.g. "no release in 4 years"
+    pub reasons: Vec<String>,
+}
+
+impl fmt::Display for MaintenanceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} looks unmaintained: {}",
+            self.name,
+            self.reasons.join("; ")
+        )
+    }
+}
+
+/// Python minor versions behind the interpreter a classifier may name and still count as recent
+const RECENT_PYTHON_MINORS: u64 = 3;
+
+/// Finds the signs of abandonment in a PyPI project document
+///
+/// A package looks unmaintained when its latest release is older than the
+/// threshold, or when that release ships only an sdist and its classifiers
+/// name no Python version from the last few before the interpreter's.
+///
+/// # Arguments
+/// * `project` - The PyPI project document
+/// * `today` - The current day, in days since the Unix epoch
+/// * `threshold_days` - Days without a release that count as unmaintained
+/// * `python_version` - Version of the interpreter, e.g. "3.12.1"
+///
+/// # Returns
+/// * `Vec<String>` - One reason per sign found, empty for a healthy package
+pub fn unmaintained_reasons(
+    project: &serde_json::Value,
+    today: u64,
+    threshold_days: u64,
+    python_version: &str,
+) -> Vec<String> {
+    let health = package_health(project, None, today);
+    let mut reasons = Vec::new();
+    if let (Some(days), Some(date)) = (health.days_since_release, &health.latest_release_date) {
+        if days >= threshold_days {
+            reasons.push(format!(
+                "no release in {} (latest {} on {})",
+                format_days(days),
+                health.latest_version,
+                date
+            ));
+        }
+    }
+
+    let files = project["releases"][&health.latest_version]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let sdist_only = !files.is_empty()
+        && files
+            .iter()
+            .all(|file| file["packagetype"].as_str() == Some("sdist"));
+    let minor = |version: &str| {
+        let mut parts = version.split('.');
+        (parts.next() == Some("3"))
+            .then(|| parts.next()?.parse::<u64>().ok())
+            .flatten()
+    };
+    let recent_floor = minor(python_version).map(|m| m.saturating_sub(RECENT_PYTHON_MINORS));
+    let recent_classifier = project["info"]["classifiers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|c| {
+            c.as_str()?
+                .strip_prefix("Programming Language :: Python :: ")
+        })
+        .filter_map(minor)
+        .any(|m| recent_floor.is_none_or(|floor| m >= floor));
+    if sdist_only && !recent_classifier {
+        reasons.push(format!(
+            "{} ships only an sdist and names no recent Python version",
+            health.latest_version
+        ));
+    }
+    reasons
+}
+
+/// Checks packages for signs of abandonment, see [`unmaintained_reasons`]
+///
+/// The lookups share the metadata cache. Packages that cannot be looked up,
+/// e.g. when offline or installed from elsewhere, are skipped, and nothing is
+/// checked when the threshold is 0.
+///
+/// # Arguments
+/// * `names` - The packages to check
+///
+/// # Returns
+/// * `Vec<MaintenanceWarning>` - The packages that look unmaintained, in the order given
+pub fn check_maintenance(names: &[String]) -> Vec<MaintenanceWarning> {
+    let threshold = unmaintained_after();
+    if threshold == 0 || names.is_empty() {
+        return Vec::new();
+    }
+    let Ok(python) = get_python_executable() else {
+        return Vec::new();
+    };
+    let python_version = get_python_version(&python).unwrap_or_default();
+    let today = unix_now() / 86_400;
+
+    let checked =
+        map_bounded(
+            names,
+            http_client().concurrency_limit(),
+            |_, name| match fetch_project_json(&python, name) {
+                Ok(project) => unmaintained_reasons(&project, today, threshold, &python_version),
+                Err(e) => {
+                    debug_detail!("Skipping the maintenance check of {}: {}", name, e);
+                    Vec::new()
+                }
+            },
+        );
+    names
+        .iter()
+        .zip(checked)
+        .filter(|(_, reasons)| !reasons.is_empty())
+        .map(|(name, reasons)| MaintenanceWarning {
+            name: name.clone(),
+            reasons,
+        })
+        .collect()
+}
+
+/// Formats a number of days the way people say it, e.g. "3 months"
+fn format_days(days: u64) -> String {
+    let (count, unit) = match days {
+        0..=59 => (days, "day"),
+        60..=729 => (days / 30, "month"),
+        _ => (days / 365, "year"),
+    };
+    format!("{} {}{}", count, unit, if count == 1 { "" } else { "s" })
+}
+
+/// Renders a package with its health indicators
+///
+/// # Arguments
+/// * `health` - The package, see [`package_info`]
+///
+/// # Returns
+/// * `String` - One labelled line per indicator
+pub fn render_package_info(health: &PackageHealth) -> String {
+    let mut out = health.name.clone();
+    if !health.summary.is_empty() {
+        out.push_str(&format!(": {}", health.summary));
+    }
+    out.push('\n');
+
+    let mut latest = format!("Latest:      {}", health.latest_version);
+    if let (Some(date), Some(age)) = (&health.latest_release_date, health.days_since_release) {
+        latest.push_str(&format!(" ({}, {} ago)", date, format_days(age)));
+    }
+    out.push_str(&latest);
+    out.push('\n');
+
+    let cadence = match health.release_interval_days {
+        Some(days) => format!("every {} typically", format_days(days.max(1))),
+        None => "a single release".to_string(),
+    };
+    out.push_str(&format!(
+        "Releases:    {}, {} in the last year\n",
+        cadence, health.releases_last_year
+    ));
+
+    let maintainers = if health.maintainers.is_empty() {
+        "none listed".to_string()
+    } else {
+        format!(
+            "{} ({})",
+            health.maintainers.len(),
+            health.maintainers.join(", ")
+        )
+    };
+    out.push_str(&format!("Maintainers: {}\n", maintainers));
+
+    if let (Some(version), Some(behind)) = (&health.version, health.releases_behind) {
+        let standing = if behind == 0 {
+            "up to date".to_string()
+        } else {
+            let mut standing = format!(
+                "{} release{} behind",
+                behind,
+                if behind == 1 { "" } else { "s" }
+            );
+            if let Some(days) = health.days_behind {
+                standing.push_str(&format!(", released {} earlier", format_days(days)));
+            }
+            if health.far_behind {
+                standing.push_str(" - far behind the latest release");
+            }
+            standing
+        };
+        out.push_str(&format!("Version:     {} ({})\n", version, standing));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_package_health() {
+        assert_eq!(parse_utc_date("1970-01-02T00:00:00Z"), Some(1));
+        assert_eq!(parse_utc_date("2024-03-01"), Some(19_783));
+        assert_eq!(parse_utc_date("2024-13-01"), None);
+
+        let release = |time: &str| serde_json::json!([{ "upload_time_iso_8601": time }]);
+        let project = serde_json::json!({
+            "info": {
+                "name": "demo",
+                "summary": "A demo package ",
+                "version": "2.1.0",
+                "author": "",
+                "author_email": "Jane Doe <jane@example.com>, ops@example.com",
+                "maintainer": "jane doe",
+            },
+            "releases": {
+                "1.0.0": release("2022-01-01T00:00:00Z"),
+                "1.1.0": release("2022-03-02T00:00:00Z"),
+                "2.0.0": release("2024-01-01T00:00:00Z"),
+                "2.1.0b1": release("2024-02-01T00:00:00Z"),
+                "2.1.0": release("2024-03-01T00:00:00Z"),
+            },
+        });
+        let today = parse_utc_date("2024-04-10").unwrap();
+
+        let health = package_health(&project, Some("1.1.0"), today);
+        assert_eq!(health.maintainers, ["Jane Doe", "ops@example.com"]);
+        assert_eq!(health.days_since_release, Some(40));
+        assert_eq!(health.releases_last_year, 2);
+        assert_eq!(health.release_interval_days, Some(60));
+        assert_eq!(health.releases_behind, Some(2));
+        assert!(health.far_behind);
+        assert_eq!(
+            render_package_info(&health),
+            "demo: A demo package\n\
+             Latest:      2.1.0 (2024-03-01, 40 days ago)\n\
+             Releases:    every 2 months typically, 2 in the last year\n\
+             Maintainers: 2 (Jane Doe, ops@example.com)\n\
+             Version:     1.1.0 (2 releases behind, released 2 years earlier - far behind the latest release)\n"
+        );
+
+        let current = package_health(&project, Some("2.1.0"), today);
+        assert_eq!(current.releases_behind, Some(0));
+        assert!(!current.far_behind);
+    }
+
+    #[test]
+    fn test_unmaintained_reasons() {
+        let project = |wheel: bool, classifier: &str| {
+            let mut files = vec![serde_json::json!({
+                "packagetype": "sdist",
+                "upload_time_iso_8601": "2019-06-01T00:00:00Z",
+            })];
+            if wheel {
+                files.push(serde_json::json!({
+                    "packagetype": "bdist_wheel",
+                    "upload_time_iso_8601": "2019-06-01T00:05:00Z",
+                }));
+            }
+            serde_json::json!({
+                "info": { "version": "0.4.2", "classifiers": [classifier] },
+                "releases": { "0.4.2": files },
+            })
+        };
+        let today = parse_utc_date("2024-06-10").unwrap();
+
+        assert_eq!(
+            unmaintained_reasons(
+                &project(false, "Programming Language :: Python :: 3.6"),
+                today,
+                DEFAULT_UNMAINTAINED_AFTER_DAYS,
+                "3.12.1"
+            ),
+            [
+                "no release in 5 years (latest 0.4.2 on 2019-06-01)",
+                "0.4.2 ships only an sdist and names no recent Python version",
+            ]
+        );
+        let recent = project(false, "Programming Language :: Python :: 3.10");
+        assert_eq!(
+            unmaintained_reasons(&recent, today, 10 * 365, "3.12.1"),
+            Vec::<String>::new()
+        );
+        assert!(unmaintained_reasons(
+            &project(true, "Programming Language :: Python :: 3 :: Only"),
+            today,
+            10 * 365,
+            "3.12.1"
+        )
+        .is_empty());
+        let warning = MaintenanceWarning {
+            name: "oldpkg".to_string(),
+            reasons: vec!["no release in 5 years".to_string()],
+        };
+        assert_eq!(
+            warning.to_string(),
+            "oldpkg looks unmaintained: no release in 5 years"
+        );
+    }
+}
@@ -0,0 +1,603 @@
+//! The operation history, transactions and `ppm undo`
+
+use crate::install::install_packages;
+use crate::outcome::InstallReport;
+use crate::registry::PackageRegistry;
+use crate::remove::{delete_packages, DependentsPolicy};
+use crate::session::RUN_REPORT;
+use crate::snapshot::{apply_restore, installed_snapshot_packages, plan_restore, Snapshot};
+use crate::spec::canonicalize_name;
+use crate::state::{operation_history_path, unix_now};
+use crate::{PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+
+/// Output formats supported by the history command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum HistoryFormat {
+    /// One line per operation followed by its package changes
+    Text,
+    /// JSON array of history entries, newest first
+    Json,
+}
+
+/// A change made to one package, as recorded in the operation history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryChange {
+    /// Package name
+    pub name: String,
+    /// One of "installed", "upgraded", "downgraded", "unchanged", "satisfied", "removed" or "failed"
+    pub action: String,
+    /// Version before the operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<String>,
+    /// Version after the operation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+}
+
+/// One invocation that changed (or tried to change) the environment
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Number of the entry, one higher than the entry before it; entries
+    /// recorded before entries had numbers get theirs when they are loaded
+    #[serde(default)]
+    pub id: u64,
+    /// Unix timestamp of the end of the run
+    pub timestamp: u64,
+    /// Command that was run, e.g. "install --parallel"
+    pub command: String,
+    /// Exit code of the run
+    pub exit_code: i32,
+    /// Error that ended the run, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Id of the entry this run reversed, for `ppm undo`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<u64>,
+    /// Per-package changes
+    pub packages: Vec<HistoryChange>,
+}
+
+impl HistoryEntry {
+    /// "success", "partial" (exit code 9) or "failed"
+    pub fn outcome(&self) -> &'static str {
+        match self.exit_code {
+            0 => "success",
+            9 => "partial",
+            _ => "failed",
+        }
+    }
+}
+
+/// Appends the package changes of this invocation to `.ppm/history.jsonl`
+///
+/// Runs that touched no package (such as `list`) are not recorded.
+///
+/// # Arguments
+/// * `command` - Label of the command that was run, e.g. "install --parallel"
+/// * `exit_code` - Exit code of the process
+/// * `error` - Error that ended the command, if any
+///
+/// # Returns
+/// * `Result<()>` - Success or error while appending to the history
+pub fn record_history(command: &str, exit_code: i32, error: Option<&PackageError>) -> Result<()> {
+    let (packages, undoes): (Vec<HistoryChange>, Option<u64>) = {
+        let report = RUN_REPORT.lock().unwrap();
+        let packages = report
+            .outcomes
+            .iter()
+            .map(|outcome| HistoryChange {
+                name: outcome.name.clone(),
+                action: outcome.status().to_string(),
+                from: outcome.previous_version.clone(),
+                to: outcome.new_version.clone(),
+            })
+            .chain(report.removed.iter().map(|(name, version)| HistoryChange {
+                name: name.clone(),
+                action: "removed".to_string(),
+                from: version.clone(),
+                to: None,
+            }))
+            .collect();
+        (packages, report.undoes)
+    };
+    if packages.is_empty() {
+        return Ok(());
+    }
+
+    let entry = HistoryEntry {
+        id: load_history()?.last().map_or(1, |last| last.id + 1),
+        timestamp: unix_now(),
+        command: command.to_string(),
+        exit_code,
+        error: error.map(|e| e.to_string()),
+        undoes: undoes.filter(|_| exit_code == 0),
+        packages,
+    };
+    let path = operation_history_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+    Ok(())
+}
+
+/// Loads the operation history, oldest entry first
+///
+/// Entries recorded before entries had ids are numbered in order, and the
+/// timestamp they name in `undoes` is turned into the id of the entry with it.
+///
+/// # Returns
+/// * `Result<Vec<HistoryEntry>>` - Recorded entries; malformed lines are skipped with a warning
+pub fn load_history() -> Result<Vec<HistoryEntry>> {
+    let file = match File::open(operation_history_path()) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    read_history(BufReader::new(file))
+}
+
+/// Reads history lines, see [`load_history`]
+fn read_history(reader: impl BufRead) -> Result<Vec<HistoryEntry>> {
+    let mut entries: Vec<HistoryEntry> = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<HistoryEntry>(&line) {
+            Ok(mut entry) => {
+                if entry.id == 0 {
+                    entry.id = entries.last().map_or(1, |last| last.id + 1);
+                    entry.undoes = entry.undoes.and_then(|timestamp| {
+                        entries
+                            .iter()
+                            .rev()
+                            .find(|undone| undone.timestamp == timestamp)
+                            .map(|undone| undone.id)
+                    });
+                }
+                entries.push(entry)
+            }
+            Err(e) => warning!("Skipping malformed history line {}: {}", index + 1, e),
+        }
+    }
+    Ok(entries)
+}
+
+/// Selects the most recent history entries, optionally only those touching a package
+///
+/// # Arguments
+/// * `entries` - The history, oldest entry first
+/// * `package` - Only keep entries (and changes within them) for this package
+/// * `limit` - Maximum number of entries to keep
+///
+/// # Returns
+/// * `Vec<HistoryEntry>` - Matching entries, newest first
+pub fn filter_history(
+    entries: Vec<HistoryEntry>,
+    package: Option<&str>,
+    limit: Option<usize>,
+) -> Vec<HistoryEntry> {
+    let wanted = package.map(canonicalize_name);
+    entries
+        .into_iter()
+        .rev()
+        .filter_map(|mut entry| {
+            if let Some(wanted) = &wanted {
+                entry
+                    .packages
+                    .retain(|change| canonicalize_name(&change.name) == *wanted);
+                if entry.packages.is_empty() {
+                    return None;
+                }
+            }
+            Some(entry)
+        })
+        .take(limit.unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Renders history entries with one line per entry and one per package change
+///
+/// # Arguments
+/// * `entries` - Entries to render, in display order
+///
+/// # Returns
+/// The rendered history, terminated by a newline
+pub fn render_history(entries: &[HistoryEntry]) -> String {
+    if entries.is_empty() {
+        return "No operations recorded yet\n".to_string();
+    }
+
+    let mut out = String::new();
+    for entry in entries {
+        let outcome = match entry.outcome() {
+            "success" => console::style(entry.outcome()).green(),
+            "partial" => console::style(entry.outcome()).yellow(),
+            other => console::style(other).red(),
+        };
+        out.push_str(&format!(
+            "{}  {}  {}\n",
+            format_utc_timestamp(entry.timestamp),
+            entry.command,
+            outcome
+        ));
+        for change in &entry.packages {
+            let versions = match (&change.from, &change.to) {
+                (Some(from), Some(to)) if from != to => format!("{} -> {}", from, to),
+                (_, Some(version)) | (Some(version), None) => version.clone(),
+                (None, None) => String::new(),
+            };
+            let line = format!("    {:<10} {} {}", change.action, change.name, versions);
+            out.push_str(&format!("{}\n", line.trim_end()));
+        }
+    }
+    out
+}
+
+/// Runs an operation as a transaction over the environment and the registry
+///
+/// The installed distributions and the registry are captured first; if the
+/// operation fails, every change it made is reverted with [`plan_restore`] and
+/// [`apply_restore`], as if restoring a snapshot taken just before.
+///
+/// # Arguments
+/// * `registry` - Mutable reference to the package registry
+/// * `operation` - The operation to run
+///
+/// # Returns
+/// * `Result<()>` - Success, or the operation's error after the rollback
+pub fn run_transaction(
+    registry: &mut PackageRegistry,
+    operation: impl FnOnce(&mut PackageRegistry) -> Result<()>,
+) -> Result<()> {
+    let before = Snapshot {
+        name: "transaction".to_string(),
+        created_at: unix_now(),
+        python_version: String::new(),
+        packages: installed_snapshot_packages()?,
+        registry: registry.clone(),
+    };
+
+    let error = match operation(registry) {
+        Ok(()) => return Ok(()),
+        Err(e) => e,
+    };
+
+    let rollback = installed_snapshot_packages().and_then(|current| {
+        let plan = plan_restore(&before, &current);
+        if !plan.is_empty() {
+            status!(
+                "Rolling back: {} to remove, {} to reinstall",
+                plan.uninstall.len(),
+                plan.install.len() + plan.reinstall.len()
+            );
+        }
+        apply_restore(&before, &plan, registry)
+    });
+    match rollback {
+        Ok(()) => status!("Rolled back; the environment is back to its prior state"),
+        Err(e) => warning!(
+            "Rollback failed, the environment may be partially modified: {}",
+            e
+        ),
+    }
+    Err(error)
+}
+
+/// Changes that reverse a recorded operation
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UndoPlan {
+    /// The operation being reversed
+    pub entry: HistoryEntry,
+    /// `name==version` specs putting back removed packages and previous versions
+    pub reinstall: Vec<String>,
+    /// Packages the operation freshly installed
+    pub uninstall: Vec<String>,
+    /// Changes that cannot be reversed, with the reason
+    pub skipped: Vec<String>,
+}
+
+/// Finds the most recent operation that has not been undone and plans its reversal
+///
+/// Runs of `ppm undo` are themselves skipped, so repeated undos walk further back.
+///
+/// # Arguments
+/// * `history` - The history, oldest entry first
+///
+/// # Returns
+/// * `Option<UndoPlan>` - The plan, or `None` if nothing is left to undo
+pub fn plan_undo(history: &[HistoryEntry]) -> Option<UndoPlan> {
+    let undone: HashSet<u64> = history.iter().filter_map(|e| e.undoes).collect();
+
+    history
+        .iter()
+        .rev()
+        .filter(|entry| entry.undoes.is_none() && !undone.contains(&entry.id))
+        .find_map(|entry| {
+            let mut plan = UndoPlan {
+                entry: entry.clone(),
+                reinstall: Vec::new(),
+                uninstall: Vec::new(),
+                skipped: Vec::new(),
+            };
+            for change in &entry.packages {
+                match (change.action.as_str(), &change.from, &change.to) {
+                    ("installed", None, Some(_)) => plan.uninstall.push(change.name.clone()),
+                    ("upgraded" | "downgraded" | "removed", Some(from), _) => {
+                        plan.reinstall.push(format!("{}=={}", change.name, from))
+                    }
+                    ("removed", None, _) => plan.skipped.push(format!(
+                        "{} was removed without a recorded version",
+                        change.name
+                    )),
+                    _ => {}
+                }
+            }
+            (!plan.reinstall.is_empty() || !plan.uninstall.is_empty()).then_some(plan)
+        })
+}
+
+/// Renders an undo plan as a preview
+///
+/// # Arguments
+/// * `plan` - The plan to render
+///
+/// # Returns
+/// The operation being reversed followed by one line per change
+pub fn render_undo_plan(plan: &UndoPlan) -> String {
+    let mut out = format!(
+        "Undoing `{}` from {}\n",
+        plan.entry.command,
+        format_utc_timestamp(plan.entry.timestamp)
+    );
+    for spec in &plan.reinstall {
+        out.push_str(&format!(
+            "{}\n",
+            console::style(format!("+ {}", spec)).green()
+        ));
+    }
+    for name in &plan.uninstall {
+        out.push_str(&format!(
+            "{}\n",
+            console::style(format!("- {}", name)).red()
+        ));
+    }
+    for reason in &plan.skipped {
+        out.push_str(&format!("  skipped: {}\n", reason));
+    }
+    out
+}
+
+/// Reverses an operation: reinstalls previous versions, then removes fresh installs
+///
+/// Dependencies pulled in by a reversed install stay installed; `ppm autoremove`
+/// removes them.
+///
+/// # Arguments
+/// * `plan` - Changes computed by [`plan_undo`]
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of the reinstalls, or the removal error;
+///   nothing is removed if a reinstall failed
+pub fn apply_undo(plan: &UndoPlan, registry: &mut PackageRegistry) -> Result<InstallReport> {
+    RUN_REPORT.lock().unwrap().undoes = Some(plan.entry.id);
+    let report = install_packages(&plan.reinstall, registry)?;
+    if report.is_success() && !plan.uninstall.is_empty() {
+        delete_packages(&plan.uninstall, DependentsPolicy::Refuse, registry)?;
+    }
+    Ok(report)
+}
+
+/// Formats a Unix timestamp as "YYYY-MM-DD HH:MM:SS UTC"
+fn format_utc_timestamp(secs: u64) -> String {
+    // Civil-from-days conversion for the proleptic Gregorian calendar
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    let time = secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// Parses the "YYYY-MM-DD" start of a date into days since the Unix epoch
+pub(crate) fn parse_utc_date(date: &str) -> Option<u64> {
+    let mut parts = date.get(..10)?.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days-from-civil, the inverse of the conversion in format_utc_timestamp
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    u64::try_from(era * 146_097 + day_of_era - 719_468).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pip::ScriptedRunner;
+    use crate::test_support::with_runner;
+
+    #[test]
+    fn test_filter_and_render_history() {
+        let change =
+            |name: &str, action: &str, from: Option<&str>, to: Option<&str>| HistoryChange {
+                name: name.to_string(),
+                action: action.to_string(),
+                from: from.map(str::to_string),
+                to: to.map(str::to_string),
+            };
+        let entries = vec![
+            HistoryEntry {
+                id: 1,
+                timestamp: 1_700_000_000,
+                command: "install".to_string(),
+                exit_code: 0,
+                error: None,
+                undoes: None,
+                packages: vec![
+                    change("requests", "installed", None, Some("2.31.0")),
+                    change("numpy", "installed", None, Some("1.26.4")),
+                ],
+            },
+            HistoryEntry {
+                id: 2,
+                timestamp: 1_700_086_400,
+                command: "update".to_string(),
+                exit_code: 9,
+                error: None,
+                undoes: None,
+                packages: vec![change("numpy", "upgraded", Some("1.26.4"), Some("2.0.0"))],
+            },
+            HistoryEntry {
+                id: 3,
+                timestamp: 1_700_172_800,
+                command: "delete".to_string(),
+                exit_code: 0,
+                error: None,
+                undoes: None,
+                packages: vec![change("requests", "removed", Some("2.31.0"), None)],
+            },
+        ];
+
+        let numpy = filter_history(entries.clone(), Some("NumPy"), None);
+        assert_eq!(numpy.len(), 2);
+        assert_eq!(numpy[0].command, "update");
+        assert_eq!(numpy[1].packages.len(), 1);
+
+        let latest = filter_history(entries, None, Some(1));
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].command, "delete");
+
+        let rendered = console::strip_ansi_codes(&render_history(&numpy)).to_string();
+        assert_eq!(
+            rendered,
+            "2023-11-15 22:13:20 UTC  update  partial\n    upgraded   numpy 1.26.4 -> 2.0.0\n\
+             2023-11-14 22:13:20 UTC  install  success\n    installed  numpy 1.26.4\n"
+        );
+    }
+
+    #[test]
+    fn test_plan_undo() {
+        let change =
+            |name: &str, action: &str, from: Option<&str>, to: Option<&str>| HistoryChange {
+                name: name.to_string(),
+                action: action.to_string(),
+                from: from.map(str::to_string),
+                to: to.map(str::to_string),
+            };
+        // Runs within the same second share a timestamp, but not an id
+        let entry = |id: u64, command: &str, undoes, packages| HistoryEntry {
+            id,
+            timestamp: 1_700_000_000,
+            command: command.to_string(),
+            exit_code: 0,
+            error: None,
+            undoes,
+            packages,
+        };
+        let mut history = vec![
+            entry(
+                1,
+                "install",
+                None,
+                vec![
+                    change("requests", "installed", None, Some("2.31.0")),
+                    change("numpy", "upgraded", Some("1.26.4"), Some("2.0.0")),
+                    change("broken", "failed", None, None),
+                ],
+            ),
+            entry(
+                2,
+                "delete",
+                None,
+                vec![change("six", "removed", Some("1.16.0"), None)],
+            ),
+        ];
+
+        let plan = plan_undo(&history).unwrap();
+        assert_eq!(plan.entry.command, "delete");
+        assert_eq!(plan.reinstall, vec!["six==1.16.0"]);
+        assert!(plan.uninstall.is_empty());
+
+        // Once the delete is undone, the next undo reaches the install
+        history.push(entry(
+            3,
+            "undo",
+            Some(2),
+            vec![change("six", "installed", None, Some("1.16.0"))],
+        ));
+        let plan = plan_undo(&history).unwrap();
+        assert_eq!(plan.entry.command, "install");
+        assert_eq!(plan.reinstall, vec!["numpy==1.26.4"]);
+        assert_eq!(plan.uninstall, vec!["requests"]);
+
+        history.push(entry(4, "undo", Some(1), Vec::new()));
+        assert!(plan_undo(&history).is_none());
+    }
+
+    #[test]
+    fn test_read_history_numbers_entries_without_ids() {
+        let lines = r#"{"timestamp":100,"command":"install","exit_code":0,"packages":[]}
+{"timestamp":200,"command":"delete","exit_code":0,"packages":[]}
+{"timestamp":300,"command":"undo","exit_code":0,"undoes":200,"packages":[]}
+{"id":4,"timestamp":300,"command":"undo","exit_code":0,"undoes":1,"packages":[]}
+"#;
+        let entries = read_history(lines.as_bytes()).unwrap();
+        let ids: Vec<u64> = entries.iter().map(|entry| entry.id).collect();
+        assert_eq!(ids, [1, 2, 3, 4]);
+        // The timestamp an old undo recorded becomes the id of the entry it reversed
+        assert_eq!(entries[2].undoes, Some(2));
+        assert_eq!(entries[3].undoes, Some(1));
+    }
+
+    #[test]
+    fn test_transaction_requires_prior_state() {
+        // Without a readable environment there is nothing to roll back to, so the
+        // operation must not run unprotected
+        let mut registry = PackageRegistry::new();
+        let mut ran = false;
+        let result = with_runner(ScriptedRunner::new(), |_| {
+            run_transaction(&mut registry, |_| {
+                ran = true;
+                Ok(())
+            })
+        });
+        assert!(matches!(result, Err(PackageError::PythonNotFound)));
+        assert!(!ran);
+    }
+}
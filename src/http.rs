@@ -0,0 +1,529 @@
+//! HTTP access shared by every feature that talks to the network
+//!
+//! Requests are made by the Python interpreter, so they honour its trust
+//! store and the usual `HTTPS_PROXY`/`NO_PROXY` variables. The timeout, retry,
+//! proxy and user agent settings of the [`HttpClient`] apply to every request,
+//! and [`HttpClient::pip_env`] hands the same settings to pip.
+
+use crate::pip::run_command_with_input;
+use crate::{HttpFailure, PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Timeout of a single request unless configured
+pub const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of times a failed request is retried unless configured
+pub const DEFAULT_HTTP_RETRIES: u32 = 2;
+
+/// Number of packages looked up at once unless configured
+pub const DEFAULT_HTTP_CONCURRENCY: usize = 8;
+
+/// Python snippet that performs a batch of GET requests
+///
+/// Reads the client settings as JSON from the first line of stdin and one
+/// request per following line, so neither the proxy credentials nor a long
+/// batch end up on the command line. Connections are kept alive and shared per host
+/// for the whole batch; a kept-alive connection the server closed is reopened
+/// without counting as a retry. Connection errors, `429` and `5xx` answers are
+/// retried with exponential backoff, and redirects are followed. Writes one
+/// JSON line per request, in order, with the status, the `ETag` and
+/// `Last-Modified` validators and the body, or the error; an error status
+/// comes with its code and reason. A request with a
+/// `save_to` path has its body written to that file instead.
+const HTTP_GET_SCRIPT: &str = r#"
+import http.client, json, sys, time, urllib.parse, urllib.request
+lines = sys.stdin.buffer.read().decode().splitlines()
+config = json.loads(lines[0])
+pool = {}
+def proxy_for(scheme, host):
+    proxy = config.get("proxy") or urllib.request.getproxies().get(scheme)
+    if not proxy or urllib.request.proxy_bypass(host):
+        return None
+    return urllib.parse.urlsplit(proxy if "://" in proxy else "http://" + proxy)
+def connection(parts):
+    key = (parts.scheme, parts.netloc)
+    if key not in pool:
+        proxy = proxy_for(parts.scheme, parts.hostname)
+        secure = parts.scheme == "https"
+        cls = http.client.HTTPSConnection if secure else http.client.HTTPConnection
+        if proxy is None:
+            conn = cls(parts.hostname, parts.port, timeout=config["timeout"])
+        elif secure:
+            conn = cls(proxy.hostname, proxy.port or 8080, timeout=config["timeout"])
+            conn.set_tunnel(parts.hostname, parts.port or 443)
+        else:
+            conn = cls(proxy.hostname, proxy.port or 8080, timeout=config["timeout"])
+        pool[key] = (conn, proxy is not None and not secure)
+    return pool[key]
+def get(request):
+    url = request["url"]
+    headers = {"User-Agent": config["user_agent"], "Accept-Encoding": "identity"}
+    if request.get("etag"):
+        headers["If-None-Match"] = request["etag"]
+    if request.get("last_modified"):
+        headers["If-Modified-Since"] = request["last_modified"]
+    for _ in range(6):
+        parts = urllib.parse.urlsplit(url)
+        attempt = 0
+        while True:
+            reused = (parts.scheme, parts.netloc) in pool
+            conn, absolute = connection(parts)
+            target = url if absolute else (parts.path or "/") + ("?" + parts.query if parts.query else "")
+            try:
+                conn.request("GET", target, headers=headers)
+                response = conn.getresponse()
+                body = response.read()
+            except (OSError, http.client.HTTPException) as error:
+                conn.close()
+                del pool[(parts.scheme, parts.netloc)]
+                if reused and isinstance(error, (http.client.RemoteDisconnected, ConnectionResetError, BrokenPipeError)):
+                    continue
+                if attempt == config["retries"]:
+                    return {"url": request["url"], "error": "%s: %s" % (type(error).__name__, error)}
+            else:
+                if response.status not in (429, 500, 502, 503, 504) or attempt == config["retries"]:
+                    break
+            time.sleep(0.5 * 2 ** attempt)
+            attempt += 1
+        if response.status in (301, 302, 303, 307, 308) and response.getheader("Location"):
+            url = urllib.parse.urljoin(url, response.getheader("Location"))
+            continue
+        if response.status >= 400:
+            return {"url": request["url"], "status": response.status, "error": response.reason}
+        if request.get("save_to") and response.status == 200:
+            with open(request["save_to"], "wb") as target:
+                target.write(body)
+            body = b""
+        return {"url": request["url"], "status": response.status, "etag": response.getheader("ETag"),
+                "last_modified": response.getheader("Last-Modified"), "body": body.decode(errors="replace")}
+    return {"url": request["url"], "error": "Too many redirects"}
+for line in lines[1:]:
+    print(json.dumps(get(json.loads(line))), flush=True)
+"#;
+
+/// Python snippet that POSTs a JSON document
+///
+/// Reads the client settings as JSON from the first line of stdin, the URL
+/// from the second and the body from the rest. Connection errors, `429` and `5xx`
+/// answers are retried with exponential backoff like GET requests. Writes
+/// one JSON line with the status, or the error.
+const HTTP_POST_SCRIPT: &str = r#"
+import json, sys, time, urllib.error, urllib.request
+config, url, body = sys.stdin.buffer.read().split(b"\n", 2)
+config, url = json.loads(config), url.decode()
+handlers = []
+if config.get("proxy"):
+    handlers.append(urllib.request.ProxyHandler({"http": config["proxy"], "https": config["proxy"]}))
+opener = urllib.request.build_opener(*handlers)
+headers = {"User-Agent": config["user_agent"], "Content-Type": "application/json"}
+attempt = 0
+while True:
+    try:
+        with opener.open(urllib.request.Request(url, body, headers), timeout=config["timeout"]) as response:
+            result = {"url": url, "status": response.status}
+    except urllib.error.HTTPError as error:
+        result = {"url": url, "error": "HTTP Error %d: %s" % (error.code, error.reason)}
+        retry = error.code in (429, 500, 502, 503, 504)
+    except OSError as error:
+        result = {"url": url, "error": "%s: %s" % (type(error).__name__, error)}
+        retry = True
+    else:
+        break
+    if not retry or attempt == config["retries"]:
+        break
+    time.sleep(0.5 * 2 ** attempt)
+    attempt += 1
+print(json.dumps(result), flush=True)
+"#;
+
+/// Settings shared by every HTTP request ppm makes
+///
+/// Start from [`HttpClient::new`] and chain the setters, then install the
+/// client with [`set_http_client`]:
+///
+/// ```ignore
+/// set_http_client(
+///     HttpClient::new()
+///         .timeout(Duration::from_secs(10))
+///         .proxy("http://proxy.internal:3128"),
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HttpClient {
+    #[serde(serialize_with = "serialize_secs")]
+    timeout: Duration,
+    retries: u32,
+    proxy: Option<String>,
+    user_agent: String,
+    #[serde(skip)]
+    concurrency: usize,
+    #[serde(skip)]
+    indexes: Vec<String>,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            timeout: DEFAULT_HTTP_TIMEOUT,
+            retries: DEFAULT_HTTP_RETRIES,
+            proxy: None,
+            user_agent: format!("ppm/{}", env!("CARGO_PKG_VERSION")),
+            concurrency: DEFAULT_HTTP_CONCURRENCY,
+            indexes: Vec::new(),
+        }
+    }
+}
+
+/// A GET request, optionally conditional on a previous response
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HttpRequest {
+    /// The URL to fetch
+    pub url: String,
+    /// Sent as `If-None-Match`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Sent as `If-Modified-Since`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// File the body is written to, e.g. for a binary download; the response body is then empty
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub save_to: Option<PathBuf>,
+}
+
+impl HttpRequest {
+    /// An unconditional request for `url`
+    pub fn get(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Self::default()
+        }
+    }
+}
+
+/// A successful answer to an [`HttpRequest`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    /// The status code; `304` means the validators of the request are still current
+    pub status: u16,
+    /// `ETag` header of the response
+    pub etag: Option<String>,
+    /// `Last-Modified` header of the response
+    pub last_modified: Option<String>,
+    /// The body, empty for `304 Not Modified`
+    pub body: String,
+}
+
+impl HttpResponse {
+    /// Whether the server answered `304 Not Modified`
+    pub fn not_modified(&self) -> bool {
+        self.status == 304
+    }
+}
+
+/// One line written by [`HTTP_GET_SCRIPT`]
+#[derive(Debug, Deserialize)]
+struct ScriptResult {
+    url: String,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    status: Option<u16>,
+    #[serde(default)]
+    etag: Option<String>,
+    #[serde(default)]
+    last_modified: Option<String>,
+    #[serde(default)]
+    body: String,
+}
+
+impl HttpClient {
+    /// A client with the default timeout and retries that uses the proxy from the environment
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives up on a request that stalls for longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retries failed connections and `429`/`5xx` answers up to `retries` times
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sends requests through this proxy instead of the one from `HTTPS_PROXY`/`HTTP_PROXY`
+    pub fn proxy(mut self, proxy: impl Into<String>) -> Self {
+        self.proxy = Some(proxy.into());
+        self
+    }
+
+    /// Identifies requests with this `User-Agent` header
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Looks up at most `concurrency` packages at once in commands that check many of them
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Number of packages looked up at once, e.g. by `upgrade --all`
+    pub fn concurrency_limit(&self) -> usize {
+        self.concurrency
+    }
+
+    /// Resolves and downloads packages from these indexes, in order of preference
+    ///
+    /// When pip cannot reach an index, the same command is retried against the
+    /// next one. An empty list leaves the index to pip's own configuration.
+    pub fn indexes<I, S>(mut self, indexes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.indexes = indexes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// The configured package indexes, in order of preference
+    pub fn index_urls(&self) -> &[String] {
+        &self.indexes
+    }
+
+    /// Performs a single GET request
+    ///
+    /// # Arguments
+    /// * `python` - Interpreter used for the request
+    /// * `request` - The URL and validators to send
+    ///
+    /// # Returns
+    /// * `Result<HttpResponse>` - The response, or an error for failed requests and error statuses
+    pub fn get(&self, python: &str, request: &HttpRequest) -> Result<HttpResponse> {
+        self.get_all(python, std::slice::from_ref(request))?
+            .pop()
+            .unwrap_or_else(|| Err(fetch_error(&request.url, "no response")))
+    }
+
+    /// Downloads a file, such as a wheel, with the retries and proxy of the client
+    ///
+    /// # Arguments
+    /// * `python` - Interpreter used for the request
+    /// * `url` - The file to download
+    /// * `path` - Where to write it; parent directories must exist
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or an error for failed requests and error statuses
+    pub fn download(&self, python: &str, url: &str, path: &Path) -> Result<()> {
+        let request = HttpRequest {
+            save_to: Some(path.to_path_buf()),
+            ..HttpRequest::get(url)
+        };
+        self.get(python, &request).map(|_| ())
+    }
+
+    /// Performs several GET requests over shared keep-alive connections
+    ///
+    /// # Arguments
+    /// * `python` - Interpreter used for the requests
+    /// * `requests` - The URLs and validators to send
+    ///
+    /// # Returns
+    /// * `Result<Vec<Result<HttpResponse>>>` - One result per request, in order,
+    ///   or an error if the interpreter could not run the batch
+    pub fn get_all(
+        &self,
+        python: &str,
+        requests: &[HttpRequest],
+    ) -> Result<Vec<Result<HttpResponse>>> {
+        let mut input = serde_json::to_string(self)?;
+        for request in requests {
+            debug_detail!("Fetching {}", request.url);
+            input.push('\n');
+            input.push_str(&serde_json::to_string(request)?);
+        }
+        let mut command = Command::new(python);
+        command.arg("-c").arg(HTTP_GET_SCRIPT);
+        let output = run_command_with_input(&mut command, input.as_bytes())?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            let reason = error.lines().last().unwrap_or("unknown error");
+            let url = requests.first().map_or("", |r| r.url.as_str());
+            return Err(fetch_error(url, reason));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut results = stdout
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let result: ScriptResult = serde_json::from_str(line)?;
+                match (result.error, result.status) {
+                    (Some(reason), Some(status)) => Err(PackageError::HttpStatus(HttpFailure {
+                        url: result.url,
+                        status,
+                        reason,
+                    })),
+                    (Some(error), None) => Err(fetch_error(&result.url, &error)),
+                    (None, Some(status)) => Ok(HttpResponse {
+                        status,
+                        etag: result.etag,
+                        last_modified: result.last_modified,
+                        body: result.body,
+                    }),
+                    (None, None) => Err(fetch_error(&result.url, "malformed response")),
+                }
+            })
+            .collect::<Vec<_>>();
+        results.resize_with(requests.len(), || {
+            Err(PackageError::MetadataError(
+                "Missing HTTP response".to_string(),
+            ))
+        });
+        Ok(results)
+    }
+
+    /// Sends a JSON document with a POST request, e.g. to a chat webhook
+    ///
+    /// # Arguments
+    /// * `python` - Interpreter used for the request
+    /// * `url` - Where to send the document
+    /// * `body` - The document
+    ///
+    /// # Returns
+    /// * `Result<u16>` - The status code, or an error for failed requests and error statuses
+    pub fn post_json(&self, python: &str, url: &str, body: &serde_json::Value) -> Result<u16> {
+        debug_detail!("Posting to {}", url);
+        let post_error = |reason: &str| {
+            PackageError::MetadataError(format!("Failed to post to {}: {}", url, reason))
+        };
+        let input = format!("{}\n{}\n{}", serde_json::to_string(self)?, url, body);
+        let mut command = Command::new(python);
+        command.arg("-c").arg(HTTP_POST_SCRIPT);
+        let output = run_command_with_input(&mut command, input.as_bytes())?;
+
+        if !output.status.success() {
+            let error = String::from_utf8_lossy(&output.stderr);
+            return Err(post_error(error.lines().last().unwrap_or("unknown error")));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let result: ScriptResult = serde_json::from_str(stdout.trim())?;
+        match (result.error, result.status) {
+            (Some(error), _) => Err(post_error(&error)),
+            (None, Some(status)) => Ok(status),
+            (None, None) => Err(post_error("malformed response")),
+        }
+    }
+
+    /// Environment variables that give pip the same timeout, retries and proxy
+    ///
+    /// Settings left at their defaults are not passed on, so pip's own
+    /// configuration still applies to them.
+    ///
+    /// # Returns
+    /// `PIP_TIMEOUT`, `PIP_RETRIES` and `PIP_PROXY` for the configured settings
+    pub fn pip_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = Vec::new();
+        if self.timeout != DEFAULT_HTTP_TIMEOUT {
+            env.push(("PIP_TIMEOUT", self.timeout.as_secs_f64().to_string()));
+        }
+        if self.retries != DEFAULT_HTTP_RETRIES {
+            env.push(("PIP_RETRIES", self.retries.to_string()));
+        }
+        if let Some(proxy) = &self.proxy {
+            env.push(("PIP_PROXY", proxy.clone()));
+        }
+        env
+    }
+}
+
+/// The client used by every network request, when one was installed
+static HTTP_CLIENT: Mutex<Option<Arc<HttpClient>>> = Mutex::new(None);
+
+/// Installs the client used by every network request
+///
+/// # Arguments
+/// * `client` - The settings to use from now on
+pub fn set_http_client(client: HttpClient) {
+    *HTTP_CLIENT.lock().unwrap() = Some(Arc::new(client));
+}
+
+/// Gets the client used by every network request
+///
+/// # Returns
+/// The client installed with [`set_http_client`], or one with the default settings
+pub fn http_client() -> Arc<HttpClient> {
+    HTTP_CLIENT
+        .lock()
+        .unwrap()
+        .get_or_insert_with(|| Arc::new(HttpClient::default()))
+        .clone()
+}
+
+/// Error for a request that did not produce a response
+fn fetch_error(url: &str, reason: &str) -> PackageError {
+    PackageError::MetadataError(format!("Failed to fetch {}: {}", url, reason))
+}
+
+fn serialize_secs<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pip::ScriptedRunner;
+    use crate::test_support::with_runner;
+
+    #[test]
+    fn test_http_client_batches_requests() {
+        let runner = ScriptedRunner::new().respond(
+            "https://pypi.org/pypi/rich/json",
+            concat!(
+                r#"{"url": "https://pypi.org/pypi/rich/json", "status": 200, "etag": "\"r1\"", "body": "{}"}"#,
+                "\n",
+                r#"{"url": "https://pypi.org/pypi/nope/json", "status": 404, "error": "Not Found"}"#,
+                "\n",
+            ),
+        );
+        let requests = [
+            HttpRequest::get("https://pypi.org/pypi/rich/json"),
+            HttpRequest::get("https://pypi.org/pypi/nope/json"),
+        ];
+        let client = HttpClient::new().retries(0).proxy("http://proxy:3128");
+        let (results, calls) = with_runner(runner, |runner| {
+            (client.get_all("python", &requests).unwrap(), runner.calls())
+        });
+
+        assert_eq!(calls.len(), 1, "both requests share one interpreter");
+        let (_, input) = calls[0].split_once(" <<< ").expect("settings go to stdin");
+        assert!(input.contains(r#""retries":0"#));
+        assert!(input.contains(r#""proxy":"http://proxy:3128""#));
+        assert!(input.contains("https://pypi.org/pypi/nope/json"));
+        assert_eq!(results[0].as_ref().unwrap().etag.as_deref(), Some("\"r1\""));
+        assert!(matches!(
+            &results[1],
+            Err(PackageError::HttpStatus(HttpFailure { status: 404, .. }))
+        ));
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            "Metadata error: Failed to fetch https://pypi.org/pypi/nope/json: HTTP Error 404: Not Found"
+        );
+        assert!(client
+            .pip_env()
+            .contains(&("PIP_PROXY", "http://proxy:3128".to_string())));
+    }
+}
@@ -0,0 +1,739 @@
+//! Listing installed packages, their sizes, hashes and console scripts
+
+use crate::http::http_client;
+use crate::install::map_bounded;
+use crate::metadata::{fetch_pypi_json, MetadataCache};
+use crate::pip::{get_python_executable, run_command};
+use crate::registry::{Package, PackageRegistry};
+use crate::reporter::{json_output, reporter};
+use crate::session::set_report_data;
+use crate::spec::{canonicalize_name, compare_versions};
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::process::Command;
+
+/// Lists all packages in the registry
+///
+/// Displays all installed packages with their versions in a formatted list.
+///
+/// # Arguments
+/// * `registry` - Reference to the package registry
+pub fn list_packages(registry: &PackageRegistry) {
+    reporter().output(&render_package_list(
+        registry,
+        ListFormat::Table,
+        &ListAnnotations::default(),
+    ));
+}
+
+/// Lists all packages in the requested output format
+///
+/// With `outdated`, packages are annotated with the latest version known
+/// from the local metadata cache filled by online operations such as
+/// `upgrade` and `update`, so no network access is needed. Outdated entries
+/// are highlighted when colors are enabled.
+///
+/// With `sizes`, the installed footprint of every package is measured and
+/// the listing is sorted from largest to smallest.
+///
+/// With `hashes`, the archive digests of every release are looked up, and
+/// the freeze and JSON formats include them.
+///
+/// # Arguments
+/// * `registry` - Reference to the package registry
+/// * `format` - Output format
+/// * `outdated` - Whether to annotate packages with cached latest versions
+/// * `sizes` - Whether to annotate packages with their installed size
+/// * `hashes` - Whether to annotate packages with the digests of their archives
+///
+/// # Returns
+/// * `Result<()>` - Success or error while measuring sizes or looking up hashes
+pub fn list_packages_formatted(
+    registry: &PackageRegistry,
+    format: ListFormat,
+    outdated: bool,
+    sizes: bool,
+    hashes: bool,
+) -> Result<()> {
+    let cache = outdated.then(MetadataCache::load);
+    let sizes = if sizes {
+        let names: Vec<String> = registry.packages.keys().cloned().collect();
+        Some(
+            measure_package_sizes(&names)?
+                .into_iter()
+                .map(|size| (size.name, size.bytes))
+                .collect::<HashMap<String, u64>>(),
+        )
+    } else {
+        None
+    };
+
+    let hashes = if hashes {
+        Some(collect_package_hashes(registry)?)
+    } else {
+        None
+    };
+
+    let annotations = ListAnnotations {
+        cache: cache.as_ref(),
+        sizes: sizes.as_ref(),
+        highlight: format == ListFormat::Table && console::colors_enabled(),
+        hashes: hashes.as_ref(),
+    };
+    if json_output() {
+        let listing = render_package_list(registry, ListFormat::Json, &annotations);
+        set_report_data(serde_json::from_str(&listing)?);
+    } else {
+        reporter().output(&render_package_list(registry, format, &annotations));
+    }
+    Ok(())
+}
+
+/// Looks up the sha256 digests of the archives of every registered release
+///
+/// The digests come from the PyPI release document, which lists every wheel
+/// and sdist of a release, so requirements carrying them install on any
+/// platform in pip's `--require-hashes` mode. When a lookup fails, the hash
+/// pip recorded for the archive it installed is used instead. Packages
+/// without either are left out with a warning.
+///
+/// # Arguments
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<HashMap<String, Vec<String>>>` - Sorted "sha256:…" digests keyed by package name,
+///   or an error if no interpreter was found
+pub fn collect_package_hashes(registry: &PackageRegistry) -> Result<HashMap<String, Vec<String>>> {
+    let python = get_python_executable()?;
+    let packages: Vec<&Package> = registry
+        .packages
+        .values()
+        .filter(|p| p.version != "unknown")
+        .collect();
+    let looked_up = map_bounded(
+        &packages,
+        http_client().concurrency_limit(),
+        |_, package| {
+            fetch_pypi_json(&python, &package.name, Some(&package.version))
+                .map(|release| release_digests(&release["urls"]))
+        },
+    );
+
+    let mut hashes = HashMap::new();
+    let mut missing = 0;
+    for (package, digests) in packages.iter().zip(looked_up) {
+        let recorded = package
+            .origin
+            .as_ref()
+            .and_then(|origin| origin.hash.as_deref())
+            .and_then(|hash| hash.strip_prefix("sha256="))
+            .map(|digest| vec![format!("sha256:{}", digest)]);
+        match (digests, recorded) {
+            (Ok(digests), _) if !digests.is_empty() => {
+                hashes.insert(package.name.clone(), digests);
+            }
+            (_, Some(recorded)) => {
+                detail!("Using the recorded archive hash of {}", package.name);
+                hashes.insert(package.name.clone(), recorded);
+            }
+            (result, None) => {
+                missing += 1;
+                match result {
+                    Err(e) => warning!("No hashes for {}: {}", package.name, e),
+                    Ok(_) => warning!("No hashes for {}: PyPI lists no files", package.name),
+                }
+            }
+        }
+    }
+    if missing > 0 {
+        warning!(
+            "{} package(s) have no hashes; pip's hash-checking mode rejects requirements without them",
+            missing
+        );
+    }
+    Ok(hashes)
+}
+
+/// Collects the sha256 digests from the `urls` array of a PyPI release document
+fn release_digests(files: &serde_json::Value) -> Vec<String> {
+    let mut digests: Vec<String> = files
+        .as_array()
+        .map(|files| {
+            files
+                .iter()
+                .filter_map(|file| file["digests"]["sha256"].as_str())
+                .map(|digest| format!("sha256:{}", digest))
+                .collect()
+        })
+        .unwrap_or_default();
+    digests.sort();
+    digests.dedup();
+    digests
+}
+
+/// Installed footprint of a single package
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PackageSize {
+    /// Package name
+    pub name: String,
+    /// Total size of the files listed in the package's RECORD
+    pub bytes: u64,
+    /// Number of files that exist on disk
+    pub files: u64,
+}
+
+/// Measures the installed footprint of packages from their dist-info RECORD files
+///
+/// Packages that are not installed in the active environment are left out.
+///
+/// # Arguments
+/// * `names` - Names of the packages to measure
+///
+/// # Returns
+/// * `Result<Vec<PackageSize>>` - Sizes sorted from largest to smallest
+pub fn measure_package_sizes(names: &[String]) -> Result<Vec<PackageSize>> {
+    if names.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let python = get_python_executable()?;
+    let output = run_command(
+        Command::new(&python)
+            .arg("-c")
+            .arg(PACKAGE_SIZE_SCRIPT)
+            .args(names),
+    )?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(PackageError::MetadataError(format!(
+            "Failed to measure package sizes: {}",
+            error.trim()
+        )));
+    }
+
+    let measured: HashMap<String, (u64, u64)> = serde_json::from_slice(&output.stdout)?;
+    let mut sizes: Vec<PackageSize> = measured
+        .into_iter()
+        .map(|(name, (bytes, files))| PackageSize { name, bytes, files })
+        .collect();
+    sizes.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.name.cmp(&b.name)));
+    Ok(sizes)
+}
+
+/// Prints package sizes as a table with a total
+///
+/// # Arguments
+/// * `sizes` - Sizes to print, in display order
+pub fn print_package_sizes(sizes: &[PackageSize]) {
+    if sizes.is_empty() {
+        reporter().output("No installed packages found\n");
+        return;
+    }
+
+    let name_width = sizes.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    let mut out = format!("Installed size ({} packages):\n", sizes.len());
+    for size in sizes {
+        out.push_str(&format!(
+            "  {:<name_width$}  {:>10}  {} files\n",
+            size.name,
+            format_size(size.bytes),
+            size.files
+        ));
+    }
+
+    let total: u64 = sizes.iter().map(|s| s.bytes).sum();
+    out.push_str(&format!("\nTotal: {}\n", format_size(total)));
+    reporter().output(&out);
+}
+
+/// A command installed by a package's `console_scripts` or `gui_scripts` entry point
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConsoleScript {
+    /// Name of the command, e.g. "pytest"
+    pub name: String,
+    /// Package that declares the entry point
+    pub package: String,
+    /// Object the command runs, e.g. "pytest:console_main"
+    pub entry_point: String,
+    /// Whether this is a GUI script rather than a console script
+    pub gui: bool,
+}
+
+/// Enumerates the console and GUI scripts declared by installed distributions
+///
+/// Every distribution in the active environment is inspected, not only
+/// registered ones, so commands colliding with unmanaged packages show up too.
+///
+/// # Arguments
+/// * `package` - Only return the scripts of this package
+///
+/// # Returns
+/// * `Result<Vec<ConsoleScript>>` - Scripts sorted by command name, then package
+pub fn list_console_scripts(package: Option<&str>) -> Result<Vec<ConsoleScript>> {
+    let python = get_python_executable()?;
+    let output = run_command(Command::new(&python).arg("-c").arg(CONSOLE_SCRIPTS_SCRIPT))?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        return Err(PackageError::MetadataError(format!(
+            "Failed to read entry points: {}",
+            error.trim()
+        )));
+    }
+
+    let entries: Vec<(String, String, String, String)> = serde_json::from_slice(&output.stdout)?;
+    let wanted = package.map(canonicalize_name);
+    let mut scripts: Vec<ConsoleScript> = entries
+        .into_iter()
+        .filter(|(_, dist, _, _)| {
+            wanted
+                .as_ref()
+                .is_none_or(|w| *w == canonicalize_name(dist))
+        })
+        .map(|(name, package, entry_point, group)| ConsoleScript {
+            name,
+            package,
+            entry_point,
+            gui: group == "gui_scripts",
+        })
+        .collect();
+    scripts.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.package.cmp(&b.package)));
+    scripts.dedup();
+    Ok(scripts)
+}
+
+/// Renders console scripts as a table, flagging commands provided by several packages
+///
+/// # Arguments
+/// * `scripts` - Scripts sorted by command name
+///
+/// # Returns
+/// A table with one row per script, followed by a note about collisions
+pub fn render_console_scripts(scripts: &[ConsoleScript]) -> String {
+    if scripts.is_empty() {
+        return "No console scripts found\n".to_string();
+    }
+
+    let mut providers: HashMap<&str, usize> = HashMap::new();
+    for script in scripts {
+        *providers.entry(script.name.as_str()).or_default() += 1;
+    }
+
+    let name_width = scripts
+        .iter()
+        .map(|s| s.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    let package_width = scripts
+        .iter()
+        .map(|s| s.package.len())
+        .max()
+        .unwrap_or(0)
+        .max(7);
+    let mut out = format!(
+        "{:<name_width$}  {:<package_width$}  Entry point\n",
+        "Command", "Package"
+    );
+    for script in scripts {
+        let mut notes = Vec::new();
+        if script.gui {
+            notes.push("gui");
+        }
+        if providers[script.name.as_str()] > 1 {
+            notes.push("conflict");
+        }
+        let notes = if notes.is_empty() {
+            String::new()
+        } else {
+            format!("  ({})", notes.join(", "))
+        };
+        out.push_str(&format!(
+            "{:<name_width$}  {:<package_width$}  {}{}\n",
+            script.name, script.package, script.entry_point, notes
+        ));
+    }
+
+    let conflicts = providers.values().filter(|&&count| count > 1).count();
+    if conflicts > 0 {
+        out.push_str(&format!(
+            "\n{} command(s) are provided by more than one package; \
+             the one installed last wins\n",
+            conflicts
+        ));
+    }
+    out
+}
+
+/// Formats a byte count using binary units
+///
+/// # Arguments
+/// * `bytes` - Number of bytes
+///
+/// # Returns
+/// A human-readable size such as "1.5 MiB"
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Optional extra information shown in package listings
+#[derive(Debug, Default)]
+pub struct ListAnnotations<'a> {
+    /// Metadata cache used to annotate latest versions
+    pub cache: Option<&'a MetadataCache>,
+    /// Installed sizes in bytes keyed by registry name; also sorts the listing by size
+    pub sizes: Option<&'a HashMap<String, u64>>,
+    /// Whether to color outdated table rows
+    pub highlight: bool,
+    /// Archive digests such as "sha256:…" keyed by package name, added to
+    /// freeze output as `--hash` options
+    pub hashes: Option<&'a HashMap<String, Vec<String>>>,
+}
+
+/// Output formats supported by the list command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ListFormat {
+    /// Human-readable aligned table
+    Table,
+    /// JSON array for scripts
+    Json,
+    /// `name==version` pins usable as a requirements file
+    Freeze,
+}
+
+/// Output formats supported by the graph command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum GraphFormat {
+    /// Graphviz DOT, e.g. for `dot -Tsvg`
+    Dot,
+    /// Mermaid flowchart for Markdown documents
+    Mermaid,
+}
+
+/// A single package as emitted by the JSON list format
+#[derive(Debug, Serialize)]
+struct ListEntry<'a> {
+    name: &'a str,
+    version: &'a str,
+    explicit: bool,
+    pinned: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latest_version: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outdated: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size_bytes: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hashes: Option<&'a [String]>,
+}
+
+/// Renders the registry contents in the requested format
+///
+/// # Arguments
+/// * `registry` - Reference to the package registry
+/// * `format` - Output format
+/// * `annotations` - Extra information to include
+///
+/// # Returns
+/// The rendered listing, terminated by a newline
+pub fn render_package_list(
+    registry: &PackageRegistry,
+    format: ListFormat,
+    annotations: &ListAnnotations,
+) -> String {
+    let cache = annotations.cache;
+    let size_of = |package: &Package| -> Option<u64> {
+        annotations
+            .sizes
+            .map(|sizes| sizes.get(&package.name).copied().unwrap_or(0))
+    };
+
+    let mut packages: Vec<&Package> = registry.packages.values().collect();
+    packages.sort_by(|a, b| {
+        size_of(b)
+            .cmp(&size_of(a))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+
+    let latest_of =
+        |package: &Package| -> Option<&str> { cache.and_then(|c| c.latest_version(&package.name)) };
+    let hashes_of = |package: &Package| -> Option<&[String]> {
+        annotations
+            .hashes
+            .and_then(|hashes| hashes.get(&package.name))
+            .map(Vec::as_slice)
+    };
+    let is_outdated = |package: &Package, latest: &str| {
+        compare_versions(latest, &package.version) == Ordering::Greater
+    };
+
+    match format {
+        ListFormat::Json => {
+            let entries: Vec<ListEntry> = packages
+                .iter()
+                .map(|p| {
+                    let latest = latest_of(p);
+                    ListEntry {
+                        name: &p.name,
+                        version: &p.version,
+                        explicit: p.explicit,
+                        pinned: p.pinned,
+                        latest_version: latest,
+                        outdated: latest.map(|l| is_outdated(p, l)),
+                        size_bytes: size_of(p),
+                        hashes: hashes_of(p),
+                    }
+                })
+                .collect();
+            let mut json = serde_json::to_string_pretty(&entries).unwrap_or_else(|_| "[]".into());
+            json.push('\n');
+            json
+        }
+        ListFormat::Freeze => packages
+            .iter()
+            .map(|p| {
+                let mut line = if let Some(spec) = p.vcs_spec() {
+                    spec
+                } else if p.version == "unknown" {
+                    p.name.clone()
+                } else {
+                    format!("{}=={}", p.name, p.version)
+                };
+                for hash in hashes_of(p).unwrap_or_default() {
+                    line.push_str(&format!(" \\\n    --hash={}", hash));
+                }
+                line.push('\n');
+                line
+            })
+            .collect(),
+        ListFormat::Table => {
+            if packages.is_empty() {
+                return "No packages installed\n".to_string();
+            }
+
+            let name_width = packages.iter().map(|p| p.name.len()).max().unwrap_or(0);
+            let version_width = packages.iter().map(|p| p.version.len()).max().unwrap_or(0);
+            let mut out = format!("Installed packages ({} total):\n", packages.len());
+            let mut outdated = 0;
+            let mut uncached = 0;
+
+            for package in &packages {
+                let mut row = format!(
+                    "  {:<name_width$}  {:<version_width$}",
+                    package.name, package.version
+                );
+                if let Some(bytes) = size_of(package) {
+                    row.push_str(&format!("  {:>10}", format_size(bytes)));
+                }
+                match (cache.is_some(), latest_of(package)) {
+                    (false, _) => out.push_str(row.trim_end()),
+                    (true, Some(latest)) if is_outdated(package, latest) => {
+                        outdated += 1;
+                        let row = format!("{}  -> {} available", row, latest);
+                        if annotations.highlight {
+                            out.push_str(&console::style(row).yellow().to_string());
+                        } else {
+                            out.push_str(&row);
+                        }
+                    }
+                    (true, Some(_)) => out.push_str(&format!("{}  (up to date)", row)),
+                    (true, None) => {
+                        uncached += 1;
+                        out.push_str(row.trim_end());
+                    }
+                }
+                out.push('\n');
+            }
+
+            if let Some(sizes) = annotations.sizes {
+                let total: u64 = sizes.values().sum();
+                out.push_str(&format!("\nTotal size: {}\n", format_size(total)));
+            }
+            if cache.is_some() {
+                out.push_str(&format!("\n{} outdated package(s)\n", outdated));
+                if uncached > 0 {
+                    out.push_str(&format!(
+                        "{} package(s) have no cached metadata; run `upgrade --all --dry-run` to refresh\n",
+                        uncached
+                    ));
+                }
+            }
+            out
+        }
+    }
+}
+
+/// Python snippet that sums the on-disk size of every file in each package's RECORD
+const PACKAGE_SIZE_SCRIPT: &str = r#"
+import json, os, sys
+from importlib import metadata
+sizes = {}
+for name in sys.argv[1:]:
+    try:
+        dist = metadata.distribution(name)
+    except metadata.PackageNotFoundError:
+        continue
+    total = files = 0
+    for entry in dist.files or []:
+        try:
+            total += os.path.getsize(dist.locate_file(entry))
+            files += 1
+        except OSError:
+            pass
+    sizes[name] = [total, files]
+print(json.dumps(sizes))
+"#;
+
+/// Python snippet that prints `[command, package, entry point, group]` for
+/// every console and GUI script of every installed distribution
+const CONSOLE_SCRIPTS_SCRIPT: &str = r#"
+import json, os
+from importlib import metadata
+local = os.environ.get("PPM_LOCAL_PACKAGES")
+scripts = []
+for dist in metadata.distributions(**({"path": [local]} if local else {})):
+    name = dist.metadata["Name"]
+    for ep in dist.entry_points:
+        if ep.group in ("console_scripts", "gui_scripts"):
+            scripts.append([ep.name, name, ep.value, ep.group])
+print(json.dumps(scripts))
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_package_list_formats() {
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("requests".to_string(), "2.31.0".to_string()));
+        registry.add_package(Package::new("flask".to_string(), "3.0.2".to_string()));
+
+        let freeze =
+            render_package_list(&registry, ListFormat::Freeze, &ListAnnotations::default());
+        assert_eq!(freeze, "flask==3.0.2\nrequests==2.31.0\n");
+
+        let mut cache = MetadataCache::default();
+        cache.record("requests", "2.32.3");
+        let annotations = ListAnnotations {
+            cache: Some(&cache),
+            ..Default::default()
+        };
+        let json = render_package_list(&registry, ListFormat::Json, &annotations);
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[1]["name"], "requests");
+        assert_eq!(parsed[1]["outdated"], true);
+        assert!(parsed[0].get("latest_version").is_none());
+
+        let table = render_package_list(&registry, ListFormat::Table, &annotations);
+        assert!(table.contains("  requests  2.31.0  -> 2.32.3 available\n"));
+        assert!(table.contains("1 outdated package(s)"));
+    }
+
+    #[test]
+    fn test_list_sorted_by_size() {
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("six".to_string(), "1.16.0".to_string()));
+        registry.add_package(Package::new("numpy".to_string(), "1.26.4".to_string()));
+
+        let sizes = HashMap::from([
+            ("six".to_string(), 34_000),
+            ("numpy".to_string(), 61_000_000),
+        ]);
+        let annotations = ListAnnotations {
+            sizes: Some(&sizes),
+            ..Default::default()
+        };
+
+        let freeze = render_package_list(&registry, ListFormat::Freeze, &annotations);
+        assert_eq!(freeze, "numpy==1.26.4\nsix==1.16.0\n");
+
+        let table = render_package_list(&registry, ListFormat::Table, &annotations);
+        assert!(table.contains("58.2 MiB"));
+        assert!(table.contains("Total size: 58.2 MiB"));
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(5 * 1024 * 1024 * 1024), "5.0 GiB");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_render_console_scripts() {
+        let script = |name: &str, package: &str, entry_point: &str, gui: bool| ConsoleScript {
+            name: name.to_string(),
+            package: package.to_string(),
+            entry_point: entry_point.to_string(),
+            gui,
+        };
+        let scripts = [
+            script("black", "black", "black:patched_main", false),
+            script("idle", "idlelib", "idlelib.idle:main", true),
+            script(
+                "jupyter",
+                "jupyter-core",
+                "jupyter_core.command:main",
+                false,
+            ),
+            script(
+                "jupyter",
+                "jupyter_client",
+                "jupyter_client.main:main",
+                false,
+            ),
+        ];
+
+        assert_eq!(
+            render_console_scripts(&scripts),
+            "Command  Package         Entry point\n\
+             black    black           black:patched_main\n\
+             idle     idlelib         idlelib.idle:main  (gui)\n\
+             jupyter  jupyter-core    jupyter_core.command:main  (conflict)\n\
+             jupyter  jupyter_client  jupyter_client.main:main  (conflict)\n\
+             \n\
+             1 command(s) are provided by more than one package; the one installed last wins\n"
+        );
+        assert_eq!(render_console_scripts(&[]), "No console scripts found\n");
+    }
+
+    #[test]
+    fn test_freeze_with_hashes() {
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("six".to_string(), "1.16.0".to_string()));
+        registry.add_package(Package::new("idna".to_string(), "3.7".to_string()));
+
+        let release = serde_json::json!([
+            {"filename": "six-1.16.0.tar.gz", "digests": {"sha256": "1e61"}},
+            {"filename": "six-1.16.0-py2.py3-none-any.whl", "digests": {"sha256": "8abb"}},
+        ]);
+        let hashes = HashMap::from([("six".to_string(), release_digests(&release))]);
+        let annotations = ListAnnotations {
+            hashes: Some(&hashes),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            render_package_list(&registry, ListFormat::Freeze, &annotations),
+            "idna==3.7\nsix==1.16.0 \\\n    --hash=sha256:1e61 \\\n    --hash=sha256:8abb\n"
+        );
+    }
+}
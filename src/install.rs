@@ -0,0 +1,1868 @@
+//! Installing packages, in sequence, in parallel and across interpreters
+
+use crate::attest::{check_attestations, report_attestations, require_attestations};
+use crate::diff::environment_package_set;
+use crate::http::http_client;
+use crate::interpreters::{
+    discover_interpreters, probe_interpreter, pyenv_interpreter, Interpreter,
+};
+use crate::metadata::fetch_project_json;
+use crate::observer::{install_observer, observe_package_done, InstallObserver};
+use crate::outcome::{registered_version, InstallReport, OperationOutcome};
+use crate::pip::{
+    apply_pip_report, get_installed_versions, get_python_executable, pip_error, reported_version,
+    request_pip_report, require_pip_feature, resolve_missing_versions, run_command, run_pip,
+    run_pip_with_index, show_packages, take_pip_report, PipFeature, ReportedInstall,
+};
+use crate::registry::{Package, PackageOrigin, PackageRegistry};
+use crate::reporter::success_mark;
+use crate::session::{
+    emit_event, error_summary, package_finished_event, package_started_event, set_python_override,
+    PYTHON_OVERRIDE, THREAD_PYTHON,
+};
+use crate::snapshot::installed_snapshot_packages;
+use crate::spec::{
+    canonicalize_name, parse_package_spec, prepare_package_specs, requirement_name,
+    spec_is_satisfied, VcsRequirement,
+};
+use crate::state::STATE_DIR;
+use crate::stats::{format_duration, record_timing, Phase};
+use crate::{attest, PackageError, Result};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Limits how many packages parallel operations process at once
+///
+/// Parallel downloads, installs and updates all run on the global rayon pool,
+/// so this must be called before the first parallel operation.
+///
+/// # Arguments
+/// * `jobs` - Number of worker threads
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the pool was already started
+#[cfg(feature = "parallel")]
+pub fn configure_jobs(jobs: usize) -> Result<()> {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build_global()
+        .map_err(|e| {
+            PackageError::IoError(std::io::Error::other(format!(
+                "Could not limit parallelism to {} jobs: {}",
+                jobs, e
+            )))
+        })
+}
+
+/// Iterates over `items` on the rayon pool
+#[cfg(feature = "parallel")]
+pub(crate) fn par_items<T: Sync>(items: &[T]) -> rayon::slice::Iter<'_, T> {
+    items.par_iter()
+}
+
+/// Iterates over `items` one after another, as the `parallel` feature is disabled
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn par_items<T>(items: &[T]) -> std::slice::Iter<'_, T> {
+    items.iter()
+}
+
+/// Runs `check` on every item with at most `limit` of them in flight
+///
+/// Meant for network lookups, which wait on I/O rather than the CPU, so they
+/// run on their own threads instead of the rayon pool. `check` receives the
+/// index of the item as well, and the results come back in the order of `items`.
+pub(crate) fn map_bounded<T: Sync, R: Send>(
+    items: &[T],
+    limit: usize,
+    check: impl Fn(usize, &T) -> R + Sync,
+) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..limit.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, AtomicOrdering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = check(index, item);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item is checked"))
+        .collect()
+}
+
+/// Installs packages sequentially using pip
+///
+/// Installs the specified packages one by one using a single pip command.
+/// Updates the registry with the installed packages and their versions.
+///
+/// # Arguments
+/// * `packages` - Slice of package specifications to install
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if
+///   pip could not be run
+pub fn install_packages(
+    packages: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    install_packages_with_args(packages, &[], registry)
+}
+
+/// Installs packages with a single pip command, passing extra arguments to pip
+pub(crate) fn install_packages_with_args(
+    packages: &[String],
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    install_batch(packages, None, pip_args, registry)
+}
+
+/// Installs the packages of a hash-pinned requirements file with a single pip command
+///
+/// pip runs in `--require-hashes` mode on the file itself, so every archive it
+/// downloads is rejected unless its sha256 matches one of the hashes listed for it,
+/// and nothing is installed when any archive fails the check.
+///
+/// # Arguments
+/// * `path` - The requirements file, with `--hash` options on every line
+/// * `packages` - Its requirement specs, without the hash options
+/// * `pip_args` - Extra arguments for pip, such as `--no-deps`
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package; a mismatch fails the
+///   whole batch with [`PackageError::HashMismatch`]
+pub(crate) fn install_hash_pinned(
+    path: &Path,
+    packages: &[String],
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    install_batch(packages, Some(path), pip_args, registry)
+}
+
+/// Installs packages with a single pip command, from a hash-pinned requirements
+/// file instead of the specs when one is given
+fn install_batch(
+    packages: &[String],
+    hashed_requirements: Option<&Path>,
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let mut report = InstallReport::new("Installation summary", "install");
+    if packages.is_empty() {
+        return Ok(report);
+    }
+
+    let python = get_python_executable()?;
+    let (satisfied, remaining) = split_satisfied(&python, packages, registry);
+    report.outcomes = satisfied;
+    let packages = remaining.as_slice();
+    if packages.is_empty() {
+        return Ok(report);
+    }
+    let package_specs: Vec<String> = prepare_package_specs(packages)?
+        .iter()
+        .map(|spec| pin_vcs_revision(spec))
+        .collect();
+
+    status!("Installing packages: {}", package_specs.join(", "));
+
+    let mut names = packages
+        .iter()
+        .map(|spec| parse_package_spec(spec).map(|(name, _)| requirement_name(&name).to_string()))
+        .collect::<Result<Vec<_>>>()?;
+
+    for name in &names {
+        emit_event(package_started_event(name, "install"));
+    }
+
+    let started = Instant::now();
+    let mut command = Command::new(&python);
+    command.arg("-m").arg("pip").arg("install");
+    match hashed_requirements {
+        Some(path) => command.arg("--require-hashes").arg("-r").arg(path),
+        None => command.args(&package_specs),
+    };
+    command.args(pip_args);
+    let pip_report = request_pip_report(&python, &mut command);
+    let (output, index) = run_pip_with_index(command, "pip", None)?;
+    record_timing(&package_specs.join(" "), Phase::Install, started);
+    let mut reported = take_pip_report(pip_report);
+    if hashed_requirements.is_some() {
+        // pip counts every line of the file as requested; only the given specs are explicit
+        for install in &mut reported {
+            let key = canonicalize_name(&install.name);
+            install.requested = names.iter().any(|name| canonicalize_name(name) == key);
+        }
+    }
+
+    // A single pip call installs the whole batch, so only a lone package gets its own duration
+    let millis = (packages.len() == 1).then(|| started.elapsed().as_millis() as u64);
+
+    if !output.status.success() {
+        let error_msg = String::from_utf8_lossy(&output.stderr);
+        let failure = pip_error(&names.join(" "), &output, PackageError::InstallationFailed);
+        // Point each package at its own rejected archive rather than at pip's last line
+        let package_error = |name: &str| match &failure {
+            PackageError::HashMismatch(mismatches) => mismatches
+                .iter()
+                .find(|m| {
+                    canonicalize_name(requirement_name(&m.requirement)) == canonicalize_name(name)
+                })
+                .map_or_else(
+                    || "Not installed: another archive failed hash verification".to_string(),
+                    |m| format!("Hash mismatch: {}", m),
+                ),
+            _ => error_msg.to_string(),
+        };
+        for name in &names {
+            emit_event(package_finished_event(
+                name,
+                "install",
+                Err(&package_error(name)),
+                started.elapsed(),
+            ));
+        }
+        report
+            .outcomes
+            .extend(
+                names
+                    .iter()
+                    .zip(&package_specs)
+                    .map(|(name, spec)| OperationOutcome {
+                        name: name.clone(),
+                        spec: spec.clone(),
+                        previous_version: registered_version(registry, name),
+                        new_version: None,
+                        error: Some(package_error(name)),
+                        millis,
+                        satisfied: false,
+                        index: index.clone(),
+                    }),
+            );
+        report.failure = Some(failure);
+        return Ok(report);
+    }
+
+    // A repository URL only hints at the name of the distribution it contains
+    for (name, spec) in names.iter_mut().zip(&package_specs) {
+        if let Some(install) = direct_vcs_install(&reported, spec) {
+            *name = install.name.clone();
+        }
+    }
+
+    // Take the versions pip picked for unpinned specs from its report, or else look
+    // them up in a single call
+    let unpinned: Vec<String> = names
+        .iter()
+        .zip(&package_specs)
+        .filter(|(name, spec)| !spec.contains("==") && reported_version(&reported, name).is_none())
+        .map(|(name, _)| name.clone())
+        .collect();
+    let resolved = get_installed_versions(&python, &unpinned);
+
+    // Update registry with installed packages
+    for (spec, name) in package_specs.iter().zip(&names) {
+        let (_, version_option) = parse_package_spec(spec)?;
+        let version = reported_version(&reported, name)
+            .or(version_option)
+            .or_else(|| resolved.get(name).cloned())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        emit_event(package_finished_event(
+            name,
+            "install",
+            Ok(&version),
+            started.elapsed(),
+        ));
+        report.outcomes.push(OperationOutcome {
+            name: name.clone(),
+            spec: spec.clone(),
+            previous_version: registered_version(registry, name),
+            new_version: Some(version.clone()),
+            error: None,
+            millis,
+            satisfied: false,
+            index: index.clone(),
+        });
+        let mut package = Package::new(name.clone(), version);
+        package.origin = vcs_origin(spec);
+        registry.add_package(package);
+    }
+    apply_pip_report(&reported, registry);
+
+    report
+        .warnings
+        .extend(track_dependencies(&python, &names, registry));
+    Ok(report)
+}
+
+/// Installs packages in parallel using rayon
+///
+/// Each package and its dependencies are downloaded concurrently into a
+/// temporary wheelhouse; the installs themselves then run one at a time from
+/// it, so concurrent pip processes never write to the environment at once.
+/// Provides a progress bar to show installation progress.
+///
+/// # Arguments
+/// * `packages` - Slice of package specifications to install
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if
+///   pip could not be run
+pub fn install_packages_parallel(
+    packages: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    install_packages_parallel_impl(packages, &[], registry, None)
+}
+
+/// Installs packages in parallel, recording each success in an optional checkpoint
+fn install_packages_parallel_impl(
+    packages: &[String],
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+    checkpoint: Option<&Mutex<InstallCheckpoint>>,
+) -> Result<InstallReport> {
+    let mut report = InstallReport::new("Installation summary", "install");
+    if packages.is_empty() {
+        return Ok(report);
+    }
+
+    let python = get_python_executable()?;
+    let (satisfied, remaining) = split_satisfied(&python, packages, registry);
+    report.outcomes = satisfied;
+    let packages = remaining.as_slice();
+    if packages.is_empty() {
+        return Ok(report);
+    }
+
+    let observer = install_observer();
+    observer.on_start(packages.len(), "install");
+
+    // Thread-safe registry wrapper
+    let registry_mutex = Arc::new(Mutex::new(&mut *registry));
+
+    // Download packages in parallel, each into its own directory of a private
+    // wheelhouse that nobody else can plant files in
+    let wheelhouse = tempfile::Builder::new()
+        .prefix("ppm-wheelhouse-")
+        .tempdir()?;
+    let downloads: Vec<_> = par_items(packages)
+        .enumerate()
+        .map(|(index, pkg)| {
+            let started = Instant::now();
+            let name = parse_package_spec(pkg).map_or_else(|_| pkg.clone(), |(name, _)| name);
+            observer.on_progress(&name, "starting");
+            let dir = wheelhouse.path().join(index.to_string());
+            let spec = pin_vcs_revision(pkg);
+            let result = download_single_package(&python, &spec, pip_args, &dir, observer.as_ref())
+                .map(|index| (dir, spec, index));
+            let state = if result.is_ok() {
+                "downloaded, waiting to install"
+            } else {
+                "download failed"
+            };
+            observer.on_progress(&name, state);
+            (pkg, name, started.elapsed(), result)
+        })
+        .collect();
+
+    // Install from the wheelhouse one package at a time
+    let mut reported: Vec<ReportedInstall> = Vec::new();
+    let mut results: Vec<TimedResult> = Vec::with_capacity(downloads.len());
+    let mut served: HashMap<String, String> = HashMap::new();
+    for (pkg, name, download_time, downloaded) in downloads {
+        let (dir, spec) = match downloaded {
+            Ok((dir, spec, index)) => {
+                if let Some(index) = index {
+                    served.insert(pkg.clone(), index);
+                }
+                (dir, spec)
+            }
+            Err(e) => {
+                let result = Err(e);
+                observe_package_done(observer.as_ref(), &name, &result, download_time);
+                results.push((pkg.clone(), result, download_time));
+                continue;
+            }
+        };
+        let started = Instant::now();
+        let result = install_single_package(
+            &python,
+            &spec,
+            pip_args,
+            Some(&dir),
+            observer.as_ref(),
+            &mut reported,
+        );
+        let elapsed = download_time + started.elapsed();
+        if let (Some(checkpoint), Ok(_)) = (checkpoint, &result) {
+            checkpoint.lock().unwrap().complete(pkg);
+        }
+        observe_package_done(observer.as_ref(), &name, &result, elapsed);
+        results.push((pkg.clone(), result, elapsed));
+    }
+
+    observer.on_finished();
+    drop(wheelhouse);
+    resolve_missing_versions(&python, &mut results);
+
+    let installed: Vec<String> = results
+        .iter()
+        .filter_map(|(_, r, _)| r.as_ref().ok().map(|(name, _)| name.clone()))
+        .collect();
+
+    // Process results and update registry
+    let mut outcomes = record_installation_results(results, &mut registry_mutex.lock().unwrap());
+    for outcome in &mut outcomes {
+        outcome.index = served.remove(&outcome.spec);
+    }
+    report.outcomes.extend(outcomes);
+    apply_pip_report(&reported, registry);
+    report
+        .warnings
+        .extend(track_dependencies(&python, &installed, registry));
+    Ok(report)
+}
+
+/// Settings accepted by [`install_with_options`] and [`update_with_options`](crate::update_with_options)
+///
+/// Start from [`InstallOptions::new`] and chain the setters, so settings can
+/// be added without changing the functions that take them:
+///
+/// ```ignore
+/// let options = InstallOptions::new()
+///     .parallel(true)
+///     .index_url("https://pypi.example.org/simple")
+///     .timeout(Duration::from_secs(30));
+/// install_with_options(&packages, &options, &mut registry)?;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstallOptions {
+    pub(crate) parallel: bool,
+    index_url: Option<String>,
+    no_deps: bool,
+    pre: bool,
+    pub(crate) dry_run: bool,
+    python: Option<String>,
+    timeout: Option<Duration>,
+    pub(crate) verify_attestations: bool,
+    extra_args: Vec<String>,
+}
+
+impl InstallOptions {
+    /// Options for a sequential install from the default index with the interpreter found on PATH
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs one pip process per package on the rayon thread pool
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Takes packages from this index instead of PyPI
+    pub fn index_url(mut self, url: impl Into<String>) -> Self {
+        self.index_url = Some(url.into());
+        self
+    }
+
+    /// Installs only the named packages, without their dependencies
+    pub fn no_deps(mut self, no_deps: bool) -> Self {
+        self.no_deps = no_deps;
+        self
+    }
+
+    /// Lets pip pick pre-releases
+    pub fn pre(mut self, pre: bool) -> Self {
+        self.pre = pre;
+        self
+    }
+
+    /// Only reports what pip would install, leaving the environment and registry alone
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Installs into the environment of this interpreter instead of the one found on PATH
+    pub fn python(mut self, python: impl Into<String>) -> Self {
+        self.python = Some(python.into());
+        self
+    }
+
+    /// Gives up on network operations that stall for longer than `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Requires verified PyPI attestations for every downloaded file, see [`check_attestations`]
+    pub fn verify_attestations(mut self, verify: bool) -> Self {
+        self.verify_attestations = verify;
+        self
+    }
+
+    /// Passes arguments to `pip install` verbatim, after the ones ppm adds itself
+    pub fn extra_pip_args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extra_args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Extra arguments these options add to `pip install`
+    ///
+    /// The dry run, the interpreter and attestation checks are not pip arguments
+    /// and are left out.
+    pub fn pip_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(url) = &self.index_url {
+            args.extend(["--index-url".to_string(), url.clone()]);
+        }
+        if self.no_deps {
+            args.push("--no-deps".to_string());
+        }
+        if self.pre {
+            args.push("--pre".to_string());
+        }
+        if let Some(timeout) = self.timeout {
+            args.extend(["--timeout".to_string(), timeout.as_secs_f64().to_string()]);
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+
+    /// Runs `operation` with the chosen interpreter in place of the default lookup
+    pub(crate) fn with_python<T>(&self, operation: impl FnOnce() -> T) -> T {
+        let Some(python) = &self.python else {
+            return operation();
+        };
+        let previous = PYTHON_OVERRIDE.lock().unwrap().replace(python.clone());
+        let result = operation();
+        set_python_override(previous);
+        result
+    }
+}
+
+/// Installs packages as described by an [`InstallOptions`]
+///
+/// # Arguments
+/// * `packages` - Slice of package specifications to install
+/// * `options` - How to install them
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package (none for a dry
+///   run), or an error if pip could not be run
+pub fn install_with_options(
+    packages: &[String],
+    options: &InstallOptions,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    options.with_python(|| {
+        let pip_args = options.pip_args();
+        if options.dry_run {
+            let specs = prepare_package_specs(packages)?;
+            if options.verify_attestations {
+                let checks = check_attestations(&specs, &pip_args)?;
+                report_attestations(&checks);
+                require_attestations(&checks)?;
+            }
+            dry_run_install(&specs, &pip_args)?;
+            return Ok(InstallReport::new("Installation summary", "install"));
+        }
+        if options.verify_attestations {
+            attest::install_attested(packages, &pip_args, registry)
+        } else if options.parallel {
+            install_packages_parallel_impl(packages, &pip_args, registry, None)
+        } else {
+            install_packages_with_args(packages, &pip_args, registry)
+        }
+    })
+}
+
+/// Finds the interpreter of a Python version, e.g. "3.10", or checks an interpreter path
+///
+/// Versions are looked up as `python<version>` on PATH, among the versions
+/// installed with pyenv, and through the `py` launcher and the PEP 514
+/// registry entries on Windows. A "-32" or "-64" suffix, e.g. "3.12-32", asks
+/// for that pointer width.
+///
+/// # Arguments
+/// * `request` - A version such as "3.10" or "3.12.1", or the path of an interpreter
+///
+/// # Returns
+/// * `Result<(String, String)>` - The interpreter executable and its full version
+pub fn find_interpreter(request: &str) -> Result<(String, String)> {
+    let is_path = request.contains(['/', '\\']);
+    let mut candidates = vec![vec![if is_path {
+        request.to_string()
+    } else {
+        format!("python{}", request)
+    }]];
+    if cfg!(windows) && !is_path {
+        candidates.insert(0, vec!["py".to_string(), format!("-{}", request)]);
+    }
+    if let Some(python) = (!is_path).then(|| pyenv_interpreter(request)).flatten() {
+        candidates.push(vec![python.to_string_lossy().into_owned()]);
+    }
+
+    for candidate in candidates {
+        let Some((executable, version, architecture)) = probe_interpreter(&candidate) else {
+            continue;
+        };
+        let interpreter = Interpreter {
+            executable,
+            version,
+            architecture: architecture.unwrap_or_default(),
+            sources: Vec::new(),
+        };
+        if is_path || interpreter.matches(request) {
+            return Ok((interpreter.executable, interpreter.version));
+        }
+    }
+    if cfg!(windows) && !is_path {
+        if let Some(interpreter) = discover_interpreters()
+            .into_iter()
+            .find(|interpreter| interpreter.matches(request))
+        {
+            return Ok((interpreter.executable, interpreter.version));
+        }
+    }
+    Err(PackageError::InvalidPackageSpec(format!(
+        "No Python {} interpreter found{}",
+        request,
+        match (is_path, cfg!(windows)) {
+            (true, _) => "",
+            (false, true) => " on PATH or in the registry",
+            (false, false) => " on PATH",
+        }
+    )))
+}
+
+/// Outcome of installing the same packages into one interpreter of a matrix
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatrixResult {
+    /// Version or path as requested, e.g. "3.10"
+    pub python: String,
+    /// The interpreter that was found, with its full version
+    pub interpreter: Option<(String, String)>,
+    /// One outcome per package
+    pub outcomes: Vec<OperationOutcome>,
+    /// Why the interpreter could not be used, or why its pip run failed as a whole
+    pub error: Option<String>,
+    /// Duration in milliseconds
+    pub millis: u64,
+}
+
+impl MatrixResult {
+    /// Whether every package was installed into this interpreter
+    pub fn is_success(&self) -> bool {
+        self.error.is_none() && self.outcomes.iter().all(|o| o.status() != "failed")
+    }
+}
+
+/// Installs the same packages into several interpreters at once
+///
+/// Each interpreter is handled on its own thread, with one pip run per
+/// package so that a package failing on one interpreter fails on its own. The
+/// registry tracks a single environment, so it is left alone.
+///
+/// # Arguments
+/// * `pythons` - Versions such as "3.9" or interpreter paths, see [`find_interpreter`]
+/// * `packages` - Package specifications to install into each of them
+///
+/// # Returns
+/// One result per interpreter, in the order given
+pub fn install_matrix(pythons: &[String], packages: &[String]) -> Vec<MatrixResult> {
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = pythons
+            .iter()
+            .map(|python| scope.spawn(move || install_into_interpreter(python, packages)))
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("matrix install thread panicked"))
+            .collect()
+    })
+}
+
+/// Installs packages into one interpreter of a matrix
+fn install_into_interpreter(python: &str, packages: &[String]) -> MatrixResult {
+    let started = Instant::now();
+    let mut result = MatrixResult {
+        python: python.to_string(),
+        interpreter: None,
+        outcomes: Vec::new(),
+        error: None,
+        millis: 0,
+    };
+    let report = find_interpreter(python).and_then(|(executable, version)| {
+        status!("Python {}: using {} ({})", python, executable, version);
+        result.interpreter = Some((executable.clone(), version));
+        THREAD_PYTHON.with(|current| *current.borrow_mut() = Some(executable));
+        let options = InstallOptions::new().parallel(true);
+        let report = install_with_options(packages, &options, &mut PackageRegistry::new());
+        THREAD_PYTHON.with(|current| *current.borrow_mut() = None);
+        report
+    });
+    match report {
+        Ok(report) => {
+            result.outcomes = report.outcomes.clone();
+            if report.failed() == 0 {
+                result.error = report.into_result().err().map(|e| e.to_string());
+            }
+        }
+        Err(PackageError::InvalidPackageSpec(message)) => result.error = Some(message),
+        Err(e) => result.error = Some(e.to_string()),
+    }
+    result.millis = started.elapsed().as_millis() as u64;
+    result
+}
+
+/// Renders the result matrix of [`install_matrix`]: one row per package, one
+/// column per interpreter, then a line per interpreter and the failures
+///
+/// # Arguments
+/// * `results` - One result per interpreter
+pub fn render_matrix(results: &[MatrixResult]) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for outcome in results.iter().flat_map(|r| &r.outcomes) {
+        if !names.contains(&outcome.name.as_str()) {
+            names.push(&outcome.name);
+        }
+    }
+    let header: Vec<String> = std::iter::once("Package".to_string())
+        .chain(results.iter().map(|r| r.python.clone()))
+        .collect();
+    let rows: Vec<Vec<String>> = names
+        .iter()
+        .map(|name| {
+            std::iter::once(name.to_string())
+                .chain(results.iter().map(|result| {
+                    match result.outcomes.iter().find(|o| o.name == *name) {
+                        Some(o) if o.status() == "failed" => "failed".to_string(),
+                        Some(o) => o.new_version.clone().unwrap_or_else(|| "-".to_string()),
+                        None => "-".to_string(),
+                    }
+                }))
+                .collect()
+        })
+        .collect();
+    let mut widths: Vec<usize> = header.iter().map(String::len).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = "\nInstall matrix:\n".to_string();
+    for row in std::iter::once(&header).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell))
+            .collect();
+        out.push_str(&format!("  {}\n", cells.join("  ").trim_end()));
+    }
+
+    out.push('\n');
+    for result in results {
+        let status = match (&result.interpreter, &result.error) {
+            (Some((executable, version)), _) => format!(
+                "{} ({}), {} installed, {} failed in {}",
+                executable,
+                version,
+                result
+                    .outcomes
+                    .iter()
+                    .filter(|o| o.status() != "failed")
+                    .count(),
+                result
+                    .outcomes
+                    .iter()
+                    .filter(|o| o.status() == "failed")
+                    .count(),
+                format_duration(result.millis)
+            ),
+            (None, Some(error)) => error.clone(),
+            (None, None) => "-".to_string(),
+        };
+        out.push_str(&format!("  Python {}: {}\n", result.python, status));
+    }
+
+    let failures: Vec<String> = results
+        .iter()
+        .flat_map(|result| {
+            let batch = result
+                .interpreter
+                .as_ref()
+                .and(result.error.as_ref())
+                .map(|error| format!("  Python {}: {}", result.python, error));
+            batch.into_iter().chain(
+                result
+                    .outcomes
+                    .iter()
+                    .filter(|o| o.status() == "failed")
+                    .map(move |o| {
+                        format!(
+                            "  {} on Python {}: {}",
+                            o.name,
+                            result.python,
+                            o.error
+                                .as_deref()
+                                .and_then(|error| error.lines().find(|l| !l.trim().is_empty()))
+                                .unwrap_or("failed")
+                                .trim()
+                        )
+                    }),
+            )
+        })
+        .collect();
+    if !failures.is_empty() {
+        out.push_str("\nFailures:\n");
+        out.push_str(&failures.join("\n"));
+        out.push('\n');
+    }
+    out
+}
+
+/// Shows what `pip install --dry-run` would do with the given specs
+pub(crate) fn dry_run_install(specs: &[String], pip_args: &[String]) -> Result<()> {
+    if specs.is_empty() {
+        return Ok(());
+    }
+
+    let python = get_python_executable()?;
+    require_pip_feature(&python, PipFeature::DryRun, "A dry run")?;
+    let mut command = Command::new(&python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("install")
+        .arg("--dry-run")
+        .args(specs)
+        .args(pip_args);
+    let output = run_pip(command, "pip", None)?;
+    if !output.status.success() {
+        return Err(pip_error(
+            &specs.join(" "),
+            &output,
+            PackageError::InstallationFailed,
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Would install "))
+    {
+        Some(planned) => status!("Would install: {}", planned.trim()),
+        None => status!("Nothing to install; every requirement is already satisfied"),
+    }
+    Ok(())
+}
+
+/// Result of prefetching one package with [`warm_cache`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WarmedPackage {
+    /// Package name
+    pub name: String,
+    /// Latest version published on PyPI, when the metadata could be fetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latest_version: Option<String>,
+    /// Why the package could not be prefetched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Prefetches PyPI metadata, and optionally distributions, for packages in parallel
+///
+/// Metadata goes into the local response cache used by `update` and
+/// `upgrade`. Distributions are downloaded into a scratch directory that is
+/// removed afterwards, which leaves them in pip's own cache for later installs.
+///
+/// # Arguments
+/// * `specs` - Names or requirement specifiers of the packages
+/// * `wheels` - Whether to download the distributions as well
+///
+/// # Returns
+/// * `Result<Vec<WarmedPackage>>` - One result per package, or an error if no interpreter was found
+pub fn warm_cache(specs: &[String], wheels: bool) -> Result<Vec<WarmedPackage>> {
+    let python = get_python_executable()?;
+    let scratch = std::env::temp_dir().join(format!("ppm-warm-cache-{}", std::process::id()));
+    let observer = install_observer();
+    observer.on_start(specs.len(), "prefetch");
+
+    let warmed = map_bounded(specs, http_client().concurrency_limit(), |index, spec| {
+        let started = Instant::now();
+        let name = requirement_name(spec).to_string();
+        observer.on_progress(&name, "starting");
+        let result = fetch_project_json(&python, &name).and_then(|metadata| {
+            if wheels {
+                let dir = scratch.join(index.to_string());
+                prefetch_distributions(&python, spec, &name, &dir, observer.as_ref())?;
+            }
+            Ok(metadata["info"]["version"].as_str().map(str::to_string))
+        });
+
+        let (latest_version, error) = match result {
+            Ok(latest) => (latest, None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let done = match (&latest_version, &error) {
+            (_, Some(error)) => Err(error.as_str()),
+            (latest, None) => Ok(latest.as_deref().unwrap_or_default()),
+        };
+        observer.on_package_done(&name, done, started.elapsed());
+        WarmedPackage {
+            name,
+            latest_version,
+            error,
+        }
+    });
+
+    observer.on_finished();
+    let _ = std::fs::remove_dir_all(&scratch);
+    Ok(warmed)
+}
+
+/// Downloads a package and its dependencies so pip caches them
+fn prefetch_distributions(
+    python: &str,
+    spec: &str,
+    name: &str,
+    dir: &Path,
+    observer: &dyn InstallObserver,
+) -> Result<()> {
+    let started = Instant::now();
+    let mut command = Command::new(python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("download")
+        .arg("--dest")
+        .arg(dir)
+        .arg(spec);
+    let output = run_pip(command, name, Some(observer))?;
+    record_timing(name, Phase::Download, started);
+
+    if !output.status.success() {
+        return Err(pip_error(name, &output, |stderr| {
+            PackageError::InstallationFailed(format!("Failed to download {}: {}", name, stderr))
+        }));
+    }
+    Ok(())
+}
+
+/// Downloads a package and its dependencies into a directory without installing them
+fn download_single_package(
+    python: &str,
+    pkg: &str,
+    pip_args: &[String],
+    dir: &Path,
+    observer: &dyn InstallObserver,
+) -> Result<Option<String>> {
+    let (name, version) = parse_package_spec(pkg)?;
+    let package_spec = version
+        .as_ref()
+        .map_or(name.clone(), |v| format!("{}=={}", name, v));
+
+    let started = Instant::now();
+    let mut command = Command::new(python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("download")
+        .arg("--dest")
+        .arg(dir)
+        .arg(&package_spec)
+        .args(pip_args);
+    let (output, index) = run_pip_with_index(command, &name, Some(observer))?;
+    record_timing(&name, Phase::Download, started);
+
+    if !output.status.success() {
+        return Err(pip_error(&name, &output, |stderr| {
+            PackageError::InstallationFailed(format!("Failed to download {}: {}", name, stderr))
+        }));
+    }
+    Ok(index)
+}
+
+/// Installs a single package and returns the result
+///
+/// With a `find_links` directory, pip takes the files downloaded there before
+/// going to the index.
+fn install_single_package(
+    python: &str,
+    pkg: &str,
+    pip_args: &[String],
+    find_links: Option<&Path>,
+    observer: &dyn InstallObserver,
+    reported: &mut Vec<ReportedInstall>,
+) -> Result<(String, String)> {
+    let (spec, version) = parse_package_spec(pkg)?;
+    let package_spec = version
+        .as_ref()
+        .map_or(spec.clone(), |v| format!("{}=={}", spec, v));
+    let name = if VcsRequirement::parse(&spec).is_some() {
+        requirement_name(&spec).to_string()
+    } else {
+        spec
+    };
+
+    let started = Instant::now();
+    let mut command = Command::new(python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("install")
+        .arg(&package_spec)
+        .args(pip_args);
+    if let Some(dir) = find_links {
+        command.arg("--find-links").arg(dir);
+    }
+    let report = request_pip_report(python, &mut command);
+    let output = run_pip(command, &name, Some(observer))?;
+    record_timing(&name, Phase::Install, started);
+    let installs = take_pip_report(report);
+
+    if !output.status.success() {
+        return Err(pip_error(&name, &output, |stderr| {
+            PackageError::InstallationFailed(format!("Failed to install {}: {}", name, stderr))
+        }));
+    }
+
+    // Unpinned versions missing from the report are looked up for the whole batch by
+    // `resolve_missing_versions`
+    let name =
+        direct_vcs_install(&installs, &package_spec).map_or(name, |install| install.name.clone());
+    let version = reported_version(&installs, &name)
+        .or(version)
+        .unwrap_or_default();
+    reported.extend(installs);
+    Ok((name, version))
+}
+
+/// Pins a git requirement to the commit its branch, tag or default branch points at
+///
+/// Other specs, and git specs that already name a full commit, are returned
+/// unchanged. So is a ref `git ls-remote` cannot resolve, such as an
+/// abbreviated commit, which pip then checks out itself.
+fn pin_vcs_revision(spec: &str) -> String {
+    let Some(vcs) = VcsRequirement::parse(spec) else {
+        return spec.to_string();
+    };
+    if vcs.kind != "git" || vcs.is_pinned() {
+        return spec.to_string();
+    }
+    let reference = vcs.revision.as_deref().unwrap_or("HEAD");
+    match resolve_git_ref(&vcs.url, reference) {
+        Ok(commit) => {
+            detail!("{} resolves to commit {}", spec, commit);
+            vcs.at_commit(&commit)
+        }
+        Err(e) => {
+            warning!("Could not pin {} to a commit: {}", spec, e);
+            spec.to_string()
+        }
+    }
+}
+
+/// Looks up the commit a git branch or tag points at
+///
+/// # Arguments
+/// * `url` - Repository URL, without the `git+` prefix
+/// * `reference` - Branch, tag or "HEAD"
+///
+/// # Returns
+/// * `Result<String>` - The full commit hash, or an error if git failed or
+///   the repository has no such ref
+pub(crate) fn resolve_git_ref(url: &str, reference: &str) -> Result<String> {
+    let output = run_command(
+        Command::new("git")
+            .arg("ls-remote")
+            .arg(url)
+            .arg(reference)
+            .arg(format!("{}^{{}}", reference)),
+    )?;
+    if !output.status.success() {
+        return Err(PackageError::MetadataError(format!(
+            "git ls-remote {} failed: {}",
+            url,
+            error_summary(&String::from_utf8_lossy(&output.stderr))
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let refs: Vec<(&str, &str)> = stdout
+        .lines()
+        .filter_map(|line| line.split_once('\t'))
+        .collect();
+    // An annotated tag is listed twice; the peeled `^{}` entry names the commit
+    refs.iter()
+        .find(|(_, name)| name.ends_with("^{}"))
+        .or_else(|| refs.first())
+        .map(|(commit, _)| commit.to_string())
+        .ok_or_else(|| {
+            PackageError::MetadataError(format!("{} has no ref named {}", url, reference))
+        })
+}
+
+/// Report entry of a package installed from the repository a git spec points at
+fn direct_vcs_install<'a>(
+    installs: &'a [ReportedInstall],
+    spec: &str,
+) -> Option<&'a ReportedInstall> {
+    let vcs = VcsRequirement::parse(spec)?;
+    installs.iter().find(|install| {
+        install
+            .origin
+            .as_ref()
+            .is_some_and(|origin| origin.direct && origin.url == vcs.url)
+    })
+}
+
+/// Origin recorded for a spec pinned to a commit, for when pip writes no report
+fn vcs_origin(spec: &str) -> Option<PackageOrigin> {
+    let vcs = VcsRequirement::parse(spec)?;
+    vcs.is_pinned().then(|| PackageOrigin {
+        url: vcs.url.clone(),
+        hash: None,
+        direct: true,
+        commit: vcs.revision.clone(),
+    })
+}
+
+/// Requested specification, its installation result and how long it took
+pub(crate) type TimedResult = (String, Result<(String, String)>, Duration);
+
+/// Registers the successful installations and turns every result into an outcome
+pub(crate) fn record_installation_results(
+    results: Vec<TimedResult>,
+    registry: &mut PackageRegistry,
+) -> Vec<OperationOutcome> {
+    let mut outcomes = Vec::with_capacity(results.len());
+
+    for (spec, result, elapsed) in results {
+        let name = parse_package_spec(&spec).map_or(spec.clone(), |(name, _)| name);
+        let mut outcome = OperationOutcome {
+            previous_version: registered_version(registry, &name),
+            name,
+            spec,
+            new_version: None,
+            error: None,
+            millis: Some(elapsed.as_millis() as u64),
+            satisfied: false,
+            index: None,
+        };
+        match result {
+            Ok((name, version)) => {
+                registry.add_package(Package::new(name, version.clone()));
+                outcome.new_version = Some(version);
+            }
+            Err(error) => outcome.error = Some(error.to_string()),
+        }
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+/// Separates the specs the environment already satisfies from those pip has to install
+///
+/// Satisfied packages are registered as explicitly requested without running pip.
+/// If the environment cannot be listed, every spec is left to pip.
+///
+/// # Arguments
+/// * `python` - The interpreter whose environment is checked
+/// * `packages` - Package specifications to install
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// Outcomes of the satisfied specs and the remaining specs
+pub(crate) fn split_satisfied(
+    python: &str,
+    packages: &[String],
+    registry: &mut PackageRegistry,
+) -> (Vec<OperationOutcome>, Vec<String>) {
+    let installed: HashMap<String, String> = match environment_package_set(python) {
+        Ok(set) => set
+            .into_iter()
+            .map(|(key, (_, version))| (key, version))
+            .collect(),
+        Err(e) => {
+            debug_detail!("Could not list installed packages: {}", e);
+            return (Vec::new(), packages.to_vec());
+        }
+    };
+
+    let mut satisfied = Vec::new();
+    let mut remaining = Vec::new();
+    for spec in packages {
+        if !spec_is_satisfied(spec, &installed) {
+            remaining.push(spec.clone());
+            continue;
+        }
+
+        let name = requirement_name(spec).to_string();
+        let version = installed[&canonicalize_name(&name)].clone();
+        status!(
+            "{} {} is already satisfied ({})",
+            success_mark(),
+            spec,
+            version
+        );
+        satisfied.push(OperationOutcome {
+            previous_version: registered_version(registry, &name).or(Some(version.clone())),
+            name: name.clone(),
+            spec: spec.clone(),
+            new_version: Some(version.clone()),
+            error: None,
+            millis: None,
+            satisfied: true,
+            index: None,
+        });
+        match registry.find_package_key(&name) {
+            Some(key) => {
+                if let Some(package) = registry.packages.get_mut(&key) {
+                    package.version = version;
+                    package.explicit = true;
+                }
+            }
+            None => registry.add_package(Package::new(name, version)),
+        }
+    }
+    (satisfied, remaining)
+}
+
+/// Installs the requirements of an interrupted or failed install that are still missing
+///
+/// Specs recorded as completed in the checkpoint, and specs the environment
+/// already satisfies, are skipped.
+///
+/// # Arguments
+/// * `registry` - Mutable reference to the package registry
+/// * `parallel` - Whether to install the remaining packages in parallel
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every remaining package, or an
+///   error if pip could not be run; the checkpoint is kept on failure
+pub fn resume_install(registry: &mut PackageRegistry, parallel: bool) -> Result<InstallReport> {
+    let checkpoint = InstallCheckpoint::load().ok_or_else(|| {
+        PackageError::InvalidPackageSpec(
+            "No interrupted install to resume; install from a requirements file first".to_string(),
+        )
+    })?;
+
+    let installed: HashMap<String, String> = installed_snapshot_packages()?
+        .into_iter()
+        .map(|p| (canonicalize_name(&p.name), p.version))
+        .collect();
+    let remaining = checkpoint.remaining(&installed);
+    status!(
+        "Resuming install from {}: {} of {} entries already done",
+        checkpoint.requirements,
+        checkpoint.specs.len() - remaining.len(),
+        checkpoint.specs.len()
+    );
+    if remaining.is_empty() {
+        InstallCheckpoint::clear()?;
+        return Ok(InstallReport::new("Installation summary", "install"));
+    }
+    run_checkpointed(checkpoint, &remaining, registry, parallel)
+}
+
+/// Installs packages while keeping the checkpoint on disk up to date
+pub(crate) fn run_checkpointed(
+    checkpoint: InstallCheckpoint,
+    packages: &[String],
+    registry: &mut PackageRegistry,
+    parallel: bool,
+) -> Result<InstallReport> {
+    checkpoint.save()?;
+    let checkpoint = Mutex::new(checkpoint);
+
+    let pip_args = checkpoint.lock().unwrap().pip_args.clone();
+    let result = if parallel {
+        install_packages_parallel_impl(packages, &pip_args, registry, Some(&checkpoint))
+    } else {
+        // A single pip call installs all of them or none
+        install_packages_with_args(packages, &pip_args, registry)
+    };
+
+    if result.as_ref().is_ok_and(InstallReport::is_success) {
+        InstallCheckpoint::clear()?;
+    } else {
+        notice!("Run `ppm install --resume` to continue where this install stopped");
+    }
+    result
+}
+
+/// Progress of an install from a requirements file, kept in `.ppm/install-checkpoint.json`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstallCheckpoint {
+    /// Requirements file being installed
+    pub requirements: String,
+    /// Unix timestamp of the first attempt
+    pub started_at: u64,
+    /// Every spec of the requirements file
+    pub specs: Vec<String>,
+    /// Specs installed so far
+    pub completed: Vec<String>,
+    /// Arguments passed to pip verbatim, used again when the install is resumed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pip_args: Vec<String>,
+}
+
+impl InstallCheckpoint {
+    /// Loads the checkpoint of the last unfinished install, if any
+    pub fn load() -> Option<Self> {
+        File::open(install_checkpoint_path())
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+    }
+
+    /// Saves the checkpoint
+    pub fn save(&self) -> Result<()> {
+        let path = install_checkpoint_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Write then rename so an interruption never leaves a truncated checkpoint
+        let partial = path.with_extension("json.tmp");
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&partial)?), self)?;
+        std::fs::rename(partial, path)?;
+        Ok(())
+    }
+
+    /// Deletes the checkpoint once the install has finished
+    pub fn clear() -> Result<()> {
+        match std::fs::remove_file(install_checkpoint_path()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records a completed spec and saves the checkpoint
+    ///
+    /// # Arguments
+    /// * `spec` - The spec that was installed
+    pub fn complete(&mut self, spec: &str) {
+        self.completed.push(spec.to_string());
+        if let Err(e) = self.save() {
+            warning!("Could not update the install checkpoint: {}", e);
+        }
+    }
+
+    /// Specs that are neither completed nor already satisfied by the environment
+    ///
+    /// # Arguments
+    /// * `installed` - Installed versions keyed by normalized name
+    ///
+    /// # Returns
+    /// * `Vec<String>` - Remaining specs in requirements file order
+    pub fn remaining(&self, installed: &HashMap<String, String>) -> Vec<String> {
+        let completed: HashSet<&str> = self.completed.iter().map(String::as_str).collect();
+        self.specs
+            .iter()
+            .filter(|spec| {
+                !completed.contains(spec.as_str()) && !spec_is_satisfied(spec, installed)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// Path of the checkpoint of the last unfinished install
+fn install_checkpoint_path() -> PathBuf {
+    Path::new(STATE_DIR).join("install-checkpoint.json")
+}
+
+/// Records the dependency closure of freshly installed packages in the registry
+///
+/// Dependencies that are not yet registered are added as implicit packages so
+/// `autoremove` can later clean them up. Failures only produce a warning since
+/// the installation itself has already succeeded.
+///
+/// # Returns
+/// The warning to report if the dependencies could not be recorded
+pub(crate) fn track_dependencies(
+    python: &str,
+    names: &[String],
+    registry: &mut PackageRegistry,
+) -> Option<String> {
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut pending: Vec<String> = names.to_vec();
+
+    while !pending.is_empty() {
+        let batch: Vec<String> = pending
+            .drain(..)
+            .filter(|name| seen.insert(canonicalize_name(name)))
+            .collect();
+        if batch.is_empty() {
+            break;
+        }
+
+        let shown = match show_packages(python, &batch) {
+            Ok(shown) => shown,
+            Err(e) => return Some(format!("Could not record dependencies: {}", e)),
+        };
+
+        for info in shown {
+            let key = registry
+                .find_package_key(&info.name)
+                .unwrap_or_else(|| canonicalize_name(&info.name));
+            let existing = registry.get_package(&key);
+            let explicit = existing.is_some_and(|p| p.explicit);
+            let pinned = existing.is_some_and(|p| p.pinned);
+            let origin = existing.and_then(|p| p.origin.clone());
+
+            let mut package = Package::new(key, info.version);
+            package.explicit = explicit;
+            package.pinned = pinned;
+            package.origin = origin;
+            package.dependencies = info.requires.iter().map(|r| canonicalize_name(r)).collect();
+            registry.add_package(package);
+
+            pending.extend(info.requires);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::INSTALLED_DISTRIBUTIONS_SCRIPT;
+    use crate::http::{set_http_client, HttpClient};
+    use crate::inspect::{render_package_list, ListAnnotations, ListFormat};
+    use crate::outcome::render_summary_table;
+    use crate::pip::{ScriptedRunner, INSTALLED_VERSIONS_SCRIPT};
+    use crate::project::HttpConfig;
+    use crate::reporter::{set_reporter, REPORTER};
+    use crate::test_support::{python_runner, with_runner, RecordingReporter};
+    use crate::update::update_package;
+
+    #[test]
+    fn test_install_checkpoint_remaining() {
+        let checkpoint = InstallCheckpoint {
+            requirements: "requirements.txt".to_string(),
+            started_at: 0,
+            specs: vec![
+                "requests==2.31.0".to_string(),
+                "numpy>=1.26".to_string(),
+                "Django[argon2]~=4.2".to_string(),
+                "pandas==2.2.1".to_string(),
+                "uvloop; sys_platform != 'win32'".to_string(),
+                "rich".to_string(),
+            ],
+            completed: vec!["requests==2.31.0".to_string()],
+            pip_args: Vec::new(),
+        };
+        let installed: HashMap<String, String> = [
+            ("numpy", "1.26.4"),
+            ("django", "4.2.11"),
+            ("pandas", "2.1.0"),
+            ("uvloop", "0.19.0"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+        .collect();
+
+        assert_eq!(
+            checkpoint.remaining(&installed),
+            vec!["pandas==2.2.1", "uvloop; sys_platform != 'win32'", "rich"]
+        );
+    }
+
+    #[test]
+    fn test_install_packages_with_scripted_runner() {
+        let runner = python_runner()
+            .respond(
+                "pip --version",
+                "pip 22.3.1 from /venv/lib/pip (python 3.11)\n",
+            )
+            .respond("pip install", "Successfully installed requests-2.31.0\n")
+            .respond(INSTALLED_VERSIONS_SCRIPT, r#"{"requests": "2.31.0"}"#)
+            .respond(
+                "pip show",
+                "Name: requests\nVersion: 2.31.0\nRequires: urllib3\n---\n\
+                 Name: urllib3\nVersion: 2.2.1\nRequires: \n",
+            );
+        let mut registry = PackageRegistry::new();
+        let (report, calls) = with_runner(runner, |runner| {
+            let report = install_packages(&["requests".to_string()], &mut registry).unwrap();
+            (report, runner.calls())
+        });
+        assert!(report.is_success());
+        assert_eq!(report.outcomes[0].new_version.as_deref(), Some("2.31.0"));
+        assert!(report.warnings.is_empty());
+
+        assert!(calls
+            .iter()
+            .any(|call| call == "/venv/bin/python -m pip install requests"));
+        let requests = registry.get_package("requests").unwrap();
+        assert_eq!(requests.version, "2.31.0");
+        assert!(requests.explicit);
+        assert_eq!(requests.dependencies, ["urllib3"]);
+        assert!(!registry.get_package("urllib3").unwrap().explicit);
+
+        // Dependencies that cannot be read only produce a warning
+        let runner = python_runner().respond("pip install", "");
+        let mut registry = PackageRegistry::new();
+        let report = with_runner(runner, |_| {
+            install_packages(&["rich==13.7.0".to_string()], &mut registry).unwrap()
+        });
+        assert!(report.is_success());
+        assert_eq!(report.warnings.len(), 1);
+        assert!(report.warnings[0].starts_with("Could not record dependencies"));
+
+        // pip's failure is classified and nothing is registered
+        let runner = python_runner().fail(
+            "pip install",
+            1,
+            "ERROR: Could not find a version that satisfies the requirement nosuchpkg\n\
+             ERROR: No matching distribution found for nosuchpkg\n",
+        );
+        let mut registry = PackageRegistry::new();
+        let result = with_runner(runner, |_| {
+            install_packages(&["nosuchpkg".to_string()], &mut registry)
+        });
+        let report = result.unwrap();
+        assert_eq!(report.failed(), 1);
+        let error = report.into_result().unwrap_err();
+        assert!(
+            matches!(error, PackageError::InstallationFailed(ref m) if m.contains("nosuchpkg"))
+        );
+        assert!(registry.packages.is_empty());
+    }
+
+    #[test]
+    fn test_install_options() {
+        assert!(InstallOptions::new().pip_args().is_empty());
+        let options = InstallOptions::new()
+            .parallel(true)
+            .index_url("https://pypi.example.org/simple")
+            .no_deps(true)
+            .pre(true)
+            .timeout(Duration::from_millis(2500))
+            .extra_pip_args(["--no-cache-dir", "--prefer-binary"]);
+        assert_eq!(
+            options.pip_args(),
+            [
+                "--index-url",
+                "https://pypi.example.org/simple",
+                "--no-deps",
+                "--pre",
+                "--timeout",
+                "2.5",
+                "--no-cache-dir",
+                "--prefer-binary"
+            ]
+        );
+
+        // A dry run against another interpreter neither looks for one on PATH nor
+        // touches the registry
+        let runner = ScriptedRunner::new()
+            .respond(
+                "pip --version",
+                "pip 24.0 from /opt/py311/lib/pip (python 3.11)\n",
+            )
+            .respond("--dry-run", "Would install requests-2.31.0 urllib3-2.2.1\n");
+        let options = InstallOptions::new()
+            .dry_run(true)
+            .no_deps(true)
+            .python("/opt/py311/bin/python");
+        let mut registry = PackageRegistry::new();
+        let calls = with_runner(runner, |runner| {
+            install_with_options(&["requests".to_string()], &options, &mut registry).unwrap();
+            runner.calls()
+        });
+        assert_eq!(
+            calls,
+            [
+                "/opt/py311/bin/python -m pip --version",
+                "/opt/py311/bin/python -m pip install --dry-run requests --no-deps"
+            ]
+        );
+        assert!(registry.packages.is_empty());
+        assert!(PYTHON_OVERRIDE.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_warm_cache_reports_failed_lookups() {
+        let runner = python_runner().fail(
+            "https://pypi.org/pypi/no-such-package/json",
+            1,
+            "urllib.error.HTTPError: HTTP Error 404: Not Found",
+        );
+        let (warmed, calls) = with_runner(runner, |runner| {
+            let warmed = warm_cache(&["no-such-package>=1.0".to_string()], true).unwrap();
+            (warmed, runner.calls())
+        });
+
+        assert_eq!(warmed.len(), 1);
+        assert_eq!(warmed[0].name, "no-such-package");
+        assert_eq!(warmed[0].latest_version, None);
+        assert!(warmed[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("HTTP Error 404"));
+        // Nothing is downloaded for a package whose metadata is unavailable
+        assert!(!calls.iter().any(|call| call.contains("pip download")));
+    }
+
+    #[test]
+    fn test_map_bounded_limits_concurrency() {
+        let in_flight = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let items: Vec<usize> = (0..20).collect();
+        let doubled = map_bounded(&items, 3, |index, &item| {
+            let now = in_flight.fetch_add(1, AtomicOrdering::SeqCst) + 1;
+            peak.fetch_max(now, AtomicOrdering::SeqCst);
+            std::thread::sleep(Duration::from_millis(5));
+            in_flight.fetch_sub(1, AtomicOrdering::SeqCst);
+            assert_eq!(index, item);
+            item * 2
+        });
+
+        assert_eq!(doubled, items.iter().map(|i| i * 2).collect::<Vec<_>>());
+        assert!(peak.load(AtomicOrdering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn test_install_falls_back_to_mirror_index() {
+        let runner = python_runner()
+            .respond(
+                "pip --version",
+                "pip 22.3.1 from /venv/lib/pip (python 3.11)\n",
+            )
+            .fail(
+                "--index-url https://pypi.example.org/simple",
+                1,
+                "WARNING: Retrying after connection broken by 'NewConnectionError(\
+                 Failed to establish a new connection: [Errno 111] Connection refused)'\n\
+                 ERROR: No matching distribution found for rich\n",
+            )
+            .respond("--index-url https://mirror.example.org/simple", "")
+            .respond(INSTALLED_VERSIONS_SCRIPT, r#"{"rich": "13.7.0"}"#);
+        let http: HttpConfig = toml::from_str(
+            r#"indexes = ["https://pypi.example.org/simple", "https://mirror.example.org/simple"]"#,
+        )
+        .unwrap();
+        let mut registry = PackageRegistry::new();
+        let (report, calls) = with_runner(runner, |runner| {
+            set_http_client(http.client());
+            let report = install_packages(&["rich".to_string()], &mut registry);
+            set_http_client(HttpClient::default());
+            (report.unwrap(), runner.calls())
+        });
+
+        assert!(report.is_success());
+        let served = Some("https://mirror.example.org/simple");
+        assert_eq!(report.outcomes[0].index.as_deref(), served);
+        let installs: Vec<&String> = calls.iter().filter(|c| c.contains("pip install")).collect();
+        assert_eq!(installs.len(), 2);
+        assert!(
+            console::strip_ansi_codes(&render_summary_table("Summary", &report.outcomes))
+                .contains("Served by https://mirror.example.org/simple: rich\n")
+        );
+    }
+
+    #[test]
+    fn test_install_pins_git_ref() {
+        let commit = "3f786850e387550fdab836ed7e6dc881de23001b";
+        let runner = python_runner()
+            .respond(
+                "pip --version",
+                "pip 22.3.1 from /venv/lib/pip (python 3.11)\n",
+            )
+            .respond(
+                "git ls-remote https://github.com/org/tool main",
+                &format!("{}\trefs/heads/main\n", commit),
+            )
+            .respond("pip install", "")
+            .respond(INSTALLED_VERSIONS_SCRIPT, r#"{"tool": "0.3.dev0"}"#);
+        let mut registry = PackageRegistry::new();
+        let spec = "git+https://github.com/org/tool@main".to_string();
+        let calls = with_runner(runner, |runner| {
+            install_packages(&[spec], &mut registry).unwrap();
+            runner.calls()
+        });
+
+        let pinned = format!("git+https://github.com/org/tool@{}", commit);
+        assert!(calls.iter().any(|call| call.contains(&pinned)));
+        let package = registry.get_package("tool").unwrap();
+        assert_eq!(package.version, "0.3.dev0");
+        assert_eq!(
+            package.origin.as_ref().unwrap().commit.as_deref(),
+            Some(commit)
+        );
+        assert_eq!(
+            render_package_list(&registry, ListFormat::Freeze, &ListAnnotations::default()),
+            format!("tool @ {}\n", pinned)
+        );
+    }
+
+    #[test]
+    fn test_install_matrix_reports_each_interpreter() {
+        let runner = ScriptedRunner::new()
+            .respond(
+                "python3.12 -c import platform",
+                "/usr/bin/python3.12\n3.12.1\n",
+            )
+            .respond(
+                "python3.9 -c import platform",
+                "/usr/bin/python3.9\n3.10.2\n",
+            )
+            .respond(INSTALLED_DISTRIBUTIONS_SCRIPT, r#"{"rich": "13.7.0"}"#);
+        let results = with_runner(runner, |_| {
+            assert_eq!(
+                find_interpreter("3.12").unwrap(),
+                ("/usr/bin/python3.12".to_string(), "3.12.1".to_string())
+            );
+            install_matrix(
+                &["3.12".to_string(), "3.9".to_string()],
+                &["rich".to_string()],
+            )
+        });
+
+        assert!(results[0].is_success());
+        assert_eq!(results[0].outcomes[0].status(), "satisfied");
+        // python3.9 turned out to be another version
+        assert_eq!(results[1].interpreter, None);
+        assert_eq!(
+            results[1].error.as_deref(),
+            Some("No Python 3.9 interpreter found on PATH")
+        );
+
+        let matrix = render_matrix(&results);
+        assert!(
+            matrix.contains("  Package  3.12    3.9\n  rich     13.7.0  -\n"),
+            "{}",
+            matrix
+        );
+        assert!(matrix.contains("  Python 3.9: No Python 3.9 interpreter found on PATH\n"));
+    }
+
+    #[test]
+    fn test_parallel_install_downloads_before_installing() {
+        let runner = python_runner()
+            .respond("pip download", "Saved ./pkg-1.0-py3-none-any.whl\n")
+            .respond("pip install", "Successfully installed pkg-1.0\n");
+        let mut registry = PackageRegistry::new();
+        let packages = ["alpha==1.0".to_string(), "beta==2.0".to_string()];
+        let calls = with_runner(runner, |runner| {
+            install_packages_parallel(&packages, &mut registry).unwrap();
+            runner.calls()
+        });
+
+        // Every download finishes before the first install starts
+        let pip_calls: Vec<&String> = calls
+            .iter()
+            .filter(|call| call.contains("pip download") || call.contains("pip install"))
+            .collect();
+        assert_eq!(pip_calls.len(), 4, "{:?}", pip_calls);
+        assert!(pip_calls[..2]
+            .iter()
+            .all(|call| call.contains("pip download")));
+
+        // Each package installs from the directory it was downloaded into
+        let value_after = |call: &str, option: &str| {
+            let mut words = call.split_whitespace();
+            words.find(|word| *word == option);
+            words.next().unwrap().to_string()
+        };
+        for spec in &packages {
+            let download = pip_calls
+                .iter()
+                .find(|call| call.contains("pip download") && call.contains(spec.as_str()))
+                .unwrap();
+            let install = pip_calls
+                .iter()
+                .find(|call| call.contains("pip install") && call.contains(spec.as_str()))
+                .unwrap();
+            let dir = value_after(download, "--dest");
+            assert!(dir.contains("ppm-wheelhouse-"));
+            assert_eq!(value_after(install, "--find-links"), dir);
+            assert!(!Path::new(&dir).exists(), "the wheelhouse is removed");
+        }
+        assert_eq!(registry.packages.len(), 2);
+    }
+
+    #[test]
+    fn test_install_and_update_return_reports() {
+        let runner = python_runner()
+            .respond(INSTALLED_VERSIONS_SCRIPT, r#"{"flask": "3.0.3"}"#)
+            .fail(
+                "pip install alpha",
+                1,
+                "ERROR: No matching distribution found for alpha\n",
+            )
+            .respond(
+                "pip install --upgrade flask==3.0.3",
+                "Successfully installed flask-3.0.3\n",
+            );
+        let recorder = Arc::new(RecordingReporter::default());
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("flask".to_string(), "2.3.3".to_string()));
+        let (installed, updated) = with_runner(runner, |_| {
+            set_reporter(recorder.clone());
+            let installed = install_packages(&["alpha".to_string()], &mut registry);
+            let updated = update_package("flask", "3.0.3", &mut registry);
+            *REPORTER.lock().unwrap() = None;
+            (installed.unwrap(), updated.unwrap())
+        });
+
+        // A failed package is recorded in the report instead of ending the call
+        assert_eq!(installed.title, "Installation summary");
+        assert_eq!(installed.failed(), 1);
+        assert_eq!(installed.outcomes[0].name, "alpha");
+        assert!(installed.outcomes[0]
+            .error
+            .as_deref()
+            .unwrap()
+            .contains("No matching distribution"));
+        assert!(installed.into_result().is_err());
+
+        assert!(updated.is_success());
+        assert_eq!(updated.outcomes[0].status(), "upgraded");
+        assert_eq!(
+            updated.outcomes[0].previous_version.as_deref(),
+            Some("2.3.3")
+        );
+        assert_eq!(updated.outcomes[0].new_version.as_deref(), Some("3.0.3"));
+        assert_eq!(registry.get_package("flask").unwrap().version, "3.0.3");
+
+        // Presenting the reports is left to the caller
+        let shown = recorder.shown.lock().unwrap();
+        assert!(!shown
+            .iter()
+            .any(|(kind, text)| kind.is_none() && text.contains(" summary")));
+    }
+}
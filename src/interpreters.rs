@@ -0,0 +1,738 @@
+//! Finding the Python interpreters installed on the machine
+//!
+//! Interpreters are looked up on PATH and, on Windows, in the registry as
+//! PEP 514 describes. python.org, Microsoft Store and Anaconda installs
+//! register themselves there whether or not they are on PATH, and 32-bit and
+//! 64-bit installs of one version get separate entries, e.g. the tags "3.12"
+//! and "3.12-32".
+//!
+//! Versions installed with pyenv are found in its `versions` directory, and
+//! the version a project pins in `.python-version` is used like pyenv's shims
+//! would use it, whether or not the shims are on PATH.
+
+use crate::pip::{get_python_executable, run_command};
+use crate::spec::compare_versions;
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Script printing the executable, full version and pointer width of an interpreter
+pub(crate) const INTERPRETER_SCRIPT: &str = "import platform, sys; print(sys.executable); \
+print(platform.python_version()); print(platform.architecture()[0])";
+
+/// Registry keys PEP 514 installs are listed under, with the architecture
+/// assumed for entries that do not record one
+const PEP514_ROOTS: [(&str, &str, Option<&str>); 3] = [
+    ("HKEY_CURRENT_USER\\Software\\Python", "/reg:64", None),
+    (
+        "HKEY_LOCAL_MACHINE\\Software\\Python",
+        "/reg:64",
+        Some("64bit"),
+    ),
+    (
+        "HKEY_LOCAL_MACHINE\\Software\\Python",
+        "/reg:32",
+        Some("32bit"),
+    ),
+];
+
+/// Company key of the `py` launcher, which PEP 514 reserves and lists no interpreter
+const PY_LAUNCHER_COMPANY: &str = "PyLauncher";
+
+/// An interpreter entry of the Windows registry (PEP 514)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RegisteredInterpreter {
+    /// Distributor, e.g. "PythonCore" for python.org and Store installs or "ContinuumAnalytics"
+    pub company: String,
+    /// Tag of the install, e.g. "3.12" or "3.12-32"
+    pub tag: String,
+    /// Human-readable name, e.g. "Python 3.12 (64-bit)"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    /// Version the entry declares
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// "32bit" or "64bit", declared or implied by the registry view
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub architecture: Option<String>,
+    /// The interpreter, from `ExecutablePath` or `python.exe` in the `InstallPath`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub executable: Option<String>,
+}
+
+/// Parses `reg query <root> /s` output of a PEP 514 root key
+///
+/// # Arguments
+/// * `output` - What `reg query` printed
+/// * `default_architecture` - Architecture of entries that do not declare one,
+///   e.g. "32bit" for the 32-bit registry view
+///
+/// # Returns
+/// * `Vec<RegisteredInterpreter>` - One entry per company and tag, in the order listed
+pub fn parse_pep514_registry(
+    output: &str,
+    default_architecture: Option<&str>,
+) -> Vec<RegisteredInterpreter> {
+    let mut entries: Vec<RegisteredInterpreter> = Vec::new();
+    let mut install_dirs: Vec<Option<String>> = Vec::new();
+    let mut current: Option<(usize, Option<String>)> = None;
+
+    for line in output.lines() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !line.starts_with(' ') {
+            // A key: HKEY_...\Software\[WOW6432Node\]Python\<company>\<tag>[\<subkey>]
+            let lower = line.to_lowercase();
+            let Some(start) = lower.find("\\python\\") else {
+                current = None;
+                continue;
+            };
+            let mut parts = line[start + "\\python\\".len()..].split('\\');
+            let (Some(company), Some(tag)) = (parts.next(), parts.next()) else {
+                current = None;
+                continue;
+            };
+            if company.eq_ignore_ascii_case(PY_LAUNCHER_COMPANY) {
+                current = None;
+                continue;
+            }
+            let index = match entries
+                .iter()
+                .position(|e| e.company == company && e.tag == tag)
+            {
+                Some(index) => index,
+                None => {
+                    entries.push(RegisteredInterpreter {
+                        company: company.to_string(),
+                        tag: tag.to_string(),
+                        ..RegisteredInterpreter::default()
+                    });
+                    install_dirs.push(None);
+                    entries.len() - 1
+                }
+            };
+            current = Some((index, parts.next().map(str::to_lowercase)));
+            continue;
+        }
+
+        // A value: <name>    <type>    <data>
+        let Some((index, subkey)) = &current else {
+            continue;
+        };
+        let mut fields = line.trim().splitn(3, "    ");
+        let (Some(name), Some(_), data) = (fields.next(), fields.next(), fields.next()) else {
+            continue;
+        };
+        let data = data.unwrap_or_default().trim().to_string();
+        let entry = &mut entries[*index];
+        match (subkey.as_deref(), name) {
+            (None, "DisplayName") => entry.display_name = Some(data),
+            (None, "Version") => entry.version = Some(data),
+            (None, "SysVersion") if entry.version.is_none() => entry.version = Some(data),
+            (None, "SysArchitecture") => entry.architecture = Some(data),
+            (Some("installpath"), "ExecutablePath") => entry.executable = Some(data),
+            (Some("installpath"), "(Default)") => install_dirs[*index] = Some(data),
+            _ => {}
+        }
+    }
+
+    for (entry, install_dir) in entries.iter_mut().zip(install_dirs) {
+        if entry.executable.is_none() {
+            entry.executable = install_dir.map(|dir| {
+                Path::new(&dir)
+                    .join("python.exe")
+                    .to_string_lossy()
+                    .into_owned()
+            });
+        }
+        if entry.architecture.is_none() {
+            entry.architecture = default_architecture.map(str::to_string);
+        }
+        // PythonCore tags are versions, optionally with a "-32" suffix
+        if entry.version.is_none() && entry.company == "PythonCore" {
+            entry.version = entry.tag.split('-').next().map(str::to_string);
+        }
+    }
+    entries
+}
+
+/// Reads the interpreters registered in the Windows registry
+///
+/// # Returns
+/// * `Vec<RegisteredInterpreter>` - The entries of the current user and the
+///   machine, 64-bit view first; empty on other systems
+pub fn registered_interpreters() -> Vec<RegisteredInterpreter> {
+    if !cfg!(windows) {
+        return Vec::new();
+    }
+    let mut entries = Vec::new();
+    for (root, view, architecture) in PEP514_ROOTS {
+        let Ok(output) = run_command(Command::new("reg").args(["query", root, "/s", view])) else {
+            continue;
+        };
+        if output.status.success() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            entries.extend(parse_pep514_registry(&stdout, architecture));
+        }
+    }
+    entries
+}
+
+/// File pyenv reads the Python version of a directory (and its subdirectories) from
+pub const PYTHON_VERSION_FILE: &str = ".python-version";
+
+/// Whether the missing pinned version was already warned about
+static PYENV_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Finds the pyenv installation
+///
+/// `PYENV_ROOT` is used when set, then `~/.pyenv`, then whatever `pyenv root`
+/// answers, so installs in other places are found as long as pyenv is on PATH.
+///
+/// # Returns
+/// * `Option<PathBuf>` - The pyenv root directory, or None without pyenv
+pub fn pyenv_root() -> Option<PathBuf> {
+    if let Some(root) = std::env::var_os("PYENV_ROOT").filter(|root| !root.is_empty()) {
+        return Some(PathBuf::from(root));
+    }
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"));
+    if let Some(root) = home.map(|home| Path::new(&home).join(".pyenv")) {
+        if root.is_dir() {
+            return Some(root);
+        }
+    }
+    let output = run_command(Command::new("pyenv").arg("root")).ok()?;
+    let root = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !root.is_empty()).then(|| PathBuf::from(root))
+}
+
+/// Lists the versions installed with pyenv
+///
+/// # Arguments
+/// * `root` - The pyenv root; pyenv-win keeps its versions under `pyenv-win`
+///
+/// # Returns
+/// * `Vec<(String, PathBuf)>` - Version names, e.g. "3.12.1" or "pypy3.10-7.3.15",
+///   with their interpreters, sorted by name
+pub fn pyenv_versions(root: &Path) -> Vec<(String, PathBuf)> {
+    let windows_versions = root.join("pyenv-win").join("versions");
+    let dir = if windows_versions.is_dir() {
+        windows_versions
+    } else {
+        root.join("versions")
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return Vec::new();
+    };
+    let mut versions: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let path = entry.path();
+            let executable = [path.join("bin").join("python"), path.join("python.exe")]
+                .into_iter()
+                .find(|executable| executable.is_file())?;
+            Some((entry.file_name().to_string_lossy().into_owned(), executable))
+        })
+        .collect();
+    versions.sort();
+    versions
+}
+
+/// Picks the pyenv version a request such as "3.12" or "3.12.1" means
+///
+/// An exact name wins; otherwise the newest version with the request as a
+/// prefix, e.g. "3.12.4" for "3.12", like pyenv resolves prefixes itself.
+///
+/// # Arguments
+/// * `versions` - Installed versions, see [`pyenv_versions`]
+/// * `request` - The version asked for
+///
+/// # Returns
+/// * `Option<&(String, PathBuf)>` - The version and its interpreter, if one is installed
+pub fn find_pyenv_version<'a>(
+    versions: &'a [(String, PathBuf)],
+    request: &str,
+) -> Option<&'a (String, PathBuf)> {
+    versions
+        .iter()
+        .find(|(name, _)| name == request)
+        .or_else(|| {
+            versions
+                .iter()
+                .filter(|(name, _)| name.starts_with(&format!("{}.", request)))
+                .max_by(|(a, _), (b, _)| compare_versions(a, b))
+        })
+}
+
+/// Parses a `.python-version` file
+///
+/// # Arguments
+/// * `contents` - The file; versions are separated by whitespace or lines, and
+///   lines starting with `#` are comments
+///
+/// # Returns
+/// * `Vec<String>` - The versions in order; the first one provides `python`
+pub fn parse_python_version_file(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.starts_with('#'))
+        .flat_map(str::split_whitespace)
+        .map(str::to_string)
+        .collect()
+}
+
+/// The Python version pinned for a directory, as pyenv would pick it
+///
+/// `PYENV_VERSION` wins, then the nearest `.python-version` in the directory
+/// or its parents.
+///
+/// # Arguments
+/// * `dir` - The directory to start from, usually the current one
+///
+/// # Returns
+/// * `Option<String>` - The pinned version, e.g. "3.12" or "system", or None
+///   if nothing pins one
+pub fn pinned_python_version(dir: &Path) -> Option<String> {
+    if let Some(version) = std::env::var("PYENV_VERSION")
+        .ok()
+        .and_then(|versions| parse_python_version_file(&versions).into_iter().next())
+    {
+        return Some(version);
+    }
+    dir.ancestors()
+        .map(|dir| dir.join(PYTHON_VERSION_FILE))
+        .find_map(|file| std::fs::read_to_string(file).ok())
+        .and_then(|contents| parse_python_version_file(&contents).into_iter().next())
+}
+
+/// The pyenv interpreter of a version, if pyenv is installed and has it
+///
+/// # Arguments
+/// * `request` - A version such as "3.12" or "3.12.1"
+///
+/// # Returns
+/// * `Option<PathBuf>` - The interpreter
+pub fn pyenv_interpreter(request: &str) -> Option<PathBuf> {
+    let versions = pyenv_versions(&pyenv_root()?);
+    find_pyenv_version(&versions, request).map(|(_, executable)| executable.clone())
+}
+
+/// The interpreter of the version pinned in `.python-version`, when pyenv manages it
+///
+/// A pinned version pyenv does not have is reported once, with the command
+/// that installs it; ppm then falls back to the interpreter on PATH.
+pub(crate) fn pinned_pyenv_interpreter() -> Option<String> {
+    let root = pyenv_root()?;
+    let version = pinned_python_version(&std::env::current_dir().ok()?)?;
+    if version == "system" {
+        return None;
+    }
+    let versions = pyenv_versions(&root);
+    match find_pyenv_version(&versions, &version) {
+        Some((_, executable)) => Some(executable.to_string_lossy().into_owned()),
+        None => {
+            if !PYENV_WARNED.swap(true, Ordering::Relaxed) {
+                warning!(
+                    "Python {} is pinned in {} but not installed with pyenv; \
+                     install it with `ppm python install`",
+                    version,
+                    PYTHON_VERSION_FILE
+                );
+            }
+            None
+        }
+    }
+}
+
+/// Installs a Python version with `pyenv install`
+///
+/// # Arguments
+/// * `version` - The version to install, e.g. "3.12" (pyenv picks the newest 3.12.x)
+///
+/// # Returns
+/// * `Result<(String, PathBuf, bool)>` - The version name, its interpreter and
+///   whether it was installed now rather than already present
+pub fn install_pyenv_version(version: &str) -> Result<(String, PathBuf, bool)> {
+    let root = pyenv_root().ok_or_else(|| {
+        PackageError::InstallationFailed(
+            "pyenv was not found; see https://github.com/pyenv/pyenv#installation".to_string(),
+        )
+    })?;
+    if let Some((name, executable)) = find_pyenv_version(&pyenv_versions(&root), version) {
+        return Ok((name.clone(), executable.clone(), false));
+    }
+
+    let output = run_command(Command::new("pyenv").env("PYENV_ROOT", &root).args([
+        "install",
+        "--skip-existing",
+        version,
+    ]))
+    .map_err(|e| PackageError::InstallationFailed(format!("Could not run pyenv: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .map(str::trim)
+            .rfind(|line| !line.is_empty())
+            .unwrap_or("pyenv install failed");
+        return Err(PackageError::InstallationFailed(format!(
+            "pyenv could not install Python {}: {}",
+            version, reason
+        )));
+    }
+    let (name, executable) = find_pyenv_version(&pyenv_versions(&root), version)
+        .cloned()
+        .ok_or_else(|| {
+            PackageError::InstallationFailed(format!(
+                "pyenv install finished but Python {} is not in {}",
+                version,
+                root.display()
+            ))
+        })?;
+    Ok((name, executable, true))
+}
+
+/// Where an interpreter was found
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum InterpreterSource {
+    /// A `python`, `python3` or `python3.X` executable in a PATH directory
+    Path,
+    /// A PEP 514 registry entry
+    Registry {
+        /// Distributor, e.g. "PythonCore"
+        company: String,
+        /// Tag of the install, e.g. "3.12-32"
+        tag: String,
+    },
+    /// A version installed with pyenv
+    Pyenv {
+        /// The version name, e.g. "3.12.1"
+        version: String,
+    },
+}
+
+impl std::fmt::Display for InterpreterSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpreterSource::Path => f.write_str("PATH"),
+            InterpreterSource::Registry { company, tag } => {
+                write!(f, "registry ({}\\{})", company, tag)
+            }
+            InterpreterSource::Pyenv { version } => write!(f, "pyenv ({})", version),
+        }
+    }
+}
+
+/// An interpreter that was found and answered when run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Interpreter {
+    /// The executable, as the interpreter reports it
+    pub executable: String,
+    /// Full version, e.g. "3.12.1"
+    pub version: String,
+    /// "32bit" or "64bit"
+    pub architecture: String,
+    /// Every place it was found, PATH first
+    pub sources: Vec<InterpreterSource>,
+}
+
+impl Interpreter {
+    /// Whether this interpreter satisfies a request such as "3.12", "3.12.1" or "3.12-32"
+    ///
+    /// A "-32" or "-64" suffix, as in PEP 514 tags and the `py` launcher, also
+    /// requires that pointer width.
+    pub fn matches(&self, request: &str) -> bool {
+        let (version, bits) = match request.rsplit_once('-') {
+            Some((version, bits @ ("32" | "64"))) => (version, Some(bits)),
+            _ => (request, None),
+        };
+        let version_matches =
+            self.version == version || self.version.starts_with(&format!("{}.", version));
+        version_matches && bits.is_none_or(|bits| self.architecture == format!("{}bit", bits))
+    }
+}
+
+/// Runs an interpreter to learn its executable, version and pointer width
+///
+/// # Arguments
+/// * `command` - Program and leading arguments, e.g. `["py", "-3.12"]`
+///
+/// # Returns
+/// * `Option<(String, String, Option<String>)>` - Executable, version and
+///   architecture, or None if it could not be run
+pub(crate) fn probe_interpreter(command: &[String]) -> Option<(String, String, Option<String>)> {
+    let (program, args) = command.split_first()?;
+    let output = run_command(
+        Command::new(program)
+            .args(args)
+            .arg("-c")
+            .arg(INTERPRETER_SCRIPT),
+    )
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut lines = stdout.lines().map(str::trim);
+    let (Some(executable), Some(version)) = (lines.next(), lines.next()) else {
+        return None;
+    };
+    Some((
+        executable.to_string(),
+        version.to_string(),
+        lines.next().map(str::to_string),
+    ))
+}
+
+/// Whether a file name is one of the names interpreters are installed under
+fn is_interpreter_name(name: &str) -> bool {
+    let name = name.strip_suffix(".exe").unwrap_or(name);
+    let Some(version) = name.strip_prefix("python") else {
+        return false;
+    };
+    version.is_empty()
+        || version
+            .split('.')
+            .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// The path with symbolic links resolved, so the aliases of one interpreter compare equal
+fn canonical_path(path: &str) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// Finds every interpreter on PATH, installed with pyenv and, on Windows, in the registry
+///
+/// Each candidate is run once; candidates that turn out to be the same
+/// executable are merged. The `WindowsApps` directory is skipped, as its
+/// `python.exe` may only be a stub that opens the Microsoft Store; Store
+/// installs are found through the registry instead.
+///
+/// # Returns
+/// * `Vec<Interpreter>` - The interpreters, newest version first and 64-bit before 32-bit
+pub fn discover_interpreters() -> Vec<Interpreter> {
+    let mut candidates: Vec<(String, InterpreterSource)> = Vec::new();
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    for dir in std::env::split_paths(&path) {
+        if dir.ends_with("WindowsApps") {
+            continue;
+        }
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| is_interpreter_name(name))
+            .collect();
+        names.sort();
+        for name in names {
+            candidates.push((
+                dir.join(name).to_string_lossy().into_owned(),
+                InterpreterSource::Path,
+            ));
+        }
+    }
+    if let Some(root) = pyenv_root() {
+        for (version, executable) in pyenv_versions(&root) {
+            candidates.push((
+                executable.to_string_lossy().into_owned(),
+                InterpreterSource::Pyenv { version },
+            ));
+        }
+    }
+    for entry in registered_interpreters() {
+        if let Some(executable) = entry.executable {
+            if Path::new(&executable).exists() {
+                candidates.push((
+                    executable,
+                    InterpreterSource::Registry {
+                        company: entry.company,
+                        tag: entry.tag,
+                    },
+                ));
+            }
+        }
+    }
+
+    let mut interpreters: Vec<Interpreter> = Vec::new();
+    for (candidate, source) in candidates {
+        let Some((executable, version, architecture)) = probe_interpreter(&[candidate]) else {
+            continue;
+        };
+        let key = canonical_path(&executable);
+        let existing = interpreters
+            .iter_mut()
+            .find(|interpreter| canonical_path(&interpreter.executable) == key);
+        match existing {
+            Some(interpreter) if !interpreter.sources.contains(&source) => {
+                interpreter.sources.push(source)
+            }
+            Some(_) => {}
+            None => interpreters.push(Interpreter {
+                executable,
+                version,
+                architecture: architecture.unwrap_or_default(),
+                sources: vec![source],
+            }),
+        }
+    }
+    interpreters.sort_by(|a, b| {
+        compare_versions(&b.version, &a.version).then_with(|| b.architecture.cmp(&a.architecture))
+    });
+    interpreters
+}
+
+/// Renders interpreters as a table, marking the one commands run with
+///
+/// # Arguments
+/// * `interpreters` - The interpreters to list
+///
+/// # Returns
+/// * `String` - The table, or a note when none were found
+pub fn render_interpreters(interpreters: &[Interpreter]) -> String {
+    if interpreters.is_empty() {
+        return "No Python interpreters found\n".to_string();
+    }
+    let selected = get_python_executable()
+        .ok()
+        .map(|python| canonical_path(&python));
+
+    let header = ["", "Version", "Arch", "Executable", "Found in"].map(String::from);
+    let rows: Vec<[String; 5]> = interpreters
+        .iter()
+        .map(|interpreter| {
+            let sources: Vec<String> = interpreter.sources.iter().map(|s| s.to_string()).collect();
+            [
+                if selected.as_ref() == Some(&canonical_path(&interpreter.executable)) {
+                    "*".to_string()
+                } else {
+                    String::new()
+                },
+                interpreter.version.clone(),
+                interpreter.architecture.clone(),
+                interpreter.executable.clone(),
+                sources.join(", "),
+            ]
+        })
+        .collect();
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell))
+            .collect();
+        out.push_str(&format!("{}\n", cells.join("  ").trim_end()));
+    }
+    if selected.is_some() {
+        out.push_str("\n* used by ppm\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pep514_registry() {
+        let output = "\r
+HKEY_LOCAL_MACHINE\\Software\\WOW6432Node\\Python\\PythonCore\\3.11-32\r
+    DisplayName    REG_SZ    Python 3.11 (32-bit)\r
+    SysVersion    REG_SZ    3.11\r
+\r
+HKEY_LOCAL_MACHINE\\Software\\WOW6432Node\\Python\\PythonCore\\3.11-32\\InstallPath\r
+    (Default)    REG_SZ    C:\\Program Files (x86)\\Python311-32\\\r
+\r
+HKEY_LOCAL_MACHINE\\Software\\WOW6432Node\\Python\\ContinuumAnalytics\\Anaconda311-32\r
+    SysArchitecture    REG_SZ    32bit\r
+    Version    REG_SZ    2024.02\r
+\r
+HKEY_LOCAL_MACHINE\\Software\\WOW6432Node\\Python\\ContinuumAnalytics\\Anaconda311-32\\InstallPath\r
+    ExecutablePath    REG_SZ    C:\\ProgramData\\Anaconda3\\python.exe\r
+\r
+HKEY_LOCAL_MACHINE\\Software\\WOW6432Node\\Python\\PyLauncher\r
+    (Default)    REG_SZ    Python Launcher\r
+";
+        let entries = parse_pep514_registry(output, Some("32bit"));
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].company, "PythonCore");
+        assert_eq!(entries[0].tag, "3.11-32");
+        assert_eq!(
+            entries[0].display_name.as_deref(),
+            Some("Python 3.11 (32-bit)")
+        );
+        assert_eq!(entries[0].version.as_deref(), Some("3.11"));
+        assert_eq!(entries[0].architecture.as_deref(), Some("32bit"));
+        assert!(entries[0]
+            .executable
+            .as_deref()
+            .unwrap()
+            .starts_with("C:\\Program Files (x86)\\Python311-32\\"));
+        assert_eq!(entries[1].company, "ContinuumAnalytics");
+        assert_eq!(
+            entries[1].executable.as_deref(),
+            Some("C:\\ProgramData\\Anaconda3\\python.exe")
+        );
+
+        let interpreter = Interpreter {
+            executable: "C:\\Python312-32\\python.exe".to_string(),
+            version: "3.12.1".to_string(),
+            architecture: "32bit".to_string(),
+            sources: vec![InterpreterSource::Path],
+        };
+        assert!(interpreter.matches("3.12"));
+        assert!(interpreter.matches("3.12-32"));
+        assert!(!interpreter.matches("3.12-64"));
+        assert!(!interpreter.matches("3.1"));
+        assert!(is_interpreter_name("python3.12"));
+        assert!(is_interpreter_name("python.exe"));
+        assert!(!is_interpreter_name("python3-config"));
+    }
+
+    #[test]
+    fn test_pyenv_versions() {
+        let root = tempfile::tempdir().unwrap();
+        for version in ["3.11.9", "3.12.1", "3.12.10", "broken"] {
+            let bin = root.path().join("versions").join(version).join("bin");
+            std::fs::create_dir_all(&bin).unwrap();
+            if version != "broken" {
+                std::fs::write(bin.join("python"), "").unwrap();
+            }
+        }
+        let versions = pyenv_versions(root.path());
+        let names: Vec<&str> = versions.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names, ["3.11.9", "3.12.1", "3.12.10"]);
+
+        assert_eq!(find_pyenv_version(&versions, "3.12").unwrap().0, "3.12.10");
+        assert_eq!(find_pyenv_version(&versions, "3.12.1").unwrap().0, "3.12.1");
+        assert!(find_pyenv_version(&versions, "3.1").is_none());
+        assert!(find_pyenv_version(&versions, "3.13").is_none());
+
+        assert_eq!(
+            parse_python_version_file("# pinned for CI\n3.12.1 3.11\n\n"),
+            ["3.12.1", "3.11"]
+        );
+        let project = tempfile::tempdir().unwrap();
+        let nested = project.path().join("src").join("app");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(project.path().join(PYTHON_VERSION_FILE), "3.11\n").unwrap();
+        if std::env::var_os("PYENV_VERSION").is_none() {
+            assert_eq!(pinned_python_version(&nested).as_deref(), Some("3.11"));
+        }
+    }
+}
@@ -3,835 +3,285 @@
 //! This library provides a command-line interface for managing Python packages
 //! with support for parallel installation, requirements file processing, and
 //! package registry management.
-
-use clap::Subcommand;
-use indicatif::{ProgressBar, ProgressStyle};
-use rayon::prelude::*;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter};
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::{fmt, result};
-
-/// Custom error type for package management operations
-#[derive(Debug)]
-pub enum PackageError {
-    /// IO operation failed
-    IoError(std::io::Error),
-    /// Python executable not found
-    PythonNotFound,
-    /// Package installation failed
-    InstallationFailed(String),
-    /// Package uninstallation failed
-    UninstallationFailed(String),
-    /// Invalid package specification
-    InvalidPackageSpec(String),
-    /// JSON serialization/deserialization failed
-    JsonError(serde_json::Error),
-    /// Package not found in registry
-    PackageNotFound(String),
-}
-
-impl fmt::Display for PackageError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            PackageError::IoError(e) => write!(f, "IO error: {}", e),
-            PackageError::PythonNotFound => write!(f, "Python executable not found"),
-            PackageError::InstallationFailed(msg) => write!(f, "Installation failed: {}", msg),
-            PackageError::UninstallationFailed(msg) => write!(f, "Uninstallation failed: {}", msg),
-            PackageError::InvalidPackageSpec(spec) => write!(f, "Invalid package spec: {}", spec),
-            PackageError::JsonError(e) => write!(f, "JSON error: {}", e),
-            PackageError::PackageNotFound(name) => write!(f, "Package not found: {}", name),
-        }
-    }
-}
-
-impl std::error::Error for PackageError {}
-
-impl From<std::io::Error> for PackageError {
-    fn from(error: std::io::Error) -> Self {
-        PackageError::IoError(error)
-    }
-}
-
-impl From<serde_json::Error> for PackageError {
-    fn from(error: serde_json::Error) -> Self {
-        PackageError::JsonError(error)
-    }
-}
-
-/// Custom Result type for package operations
-pub type Result<T> = result::Result<T, PackageError>;
-
-/// Represents a Python package with its name and version
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
-pub struct Package {
-    /// Package name as it appears in PyPI
-    pub name: String,
-    /// Installed version of the package
-    pub version: String,
-}
-
-impl Package {
-    /// Creates a new Package instance
-    ///
-    /// # Arguments
-    /// * `name` - The package name
-    /// * `version` - The package version
-    ///
-    /// # Returns
-    /// A new Package instance
-    pub fn new(name: String, version: String) -> Self {
-        Self { name, version }
-    }
-}
-
-/// Registry for tracking installed packages
-#[derive(Debug, Serialize, Deserialize, Default)]
-pub struct PackageRegistry {
-    /// Map of package names to Package instances
-    pub packages: HashMap<String, Package>,
-}
-
-impl PackageRegistry {
-    /// Creates a new empty registry
-    pub fn new() -> Self {
-        Self {
-            packages: HashMap::new(),
-        }
-    }
-
-    /// Adds a package to the registry
-    ///
-    /// # Arguments
-    /// * `package` - The package to add
-    pub fn add_package(&mut self, package: Package) {
-        self.packages.insert(package.name.clone(), package);
-    }
-
-    /// Removes a package from the registry
-    ///
-    /// # Arguments
-    /// * `name` - The name of the package to remove
-    ///
-    /// # Returns
-    /// The removed package if it existed
-    pub fn remove_package(&mut self, name: &str) -> Option<Package> {
-        self.packages.remove(name)
-    }
-
-    /// Gets a package from the registry
-    ///
-    /// # Arguments
-    /// * `name` - The name of the package to retrieve
-    ///
-    /// # Returns
-    /// Reference to the package if it exists
-    pub fn get_package(&self, name: &str) -> Option<&Package> {
-        self.packages.get(name)
-    }
-
-    /// Checks if the registry is empty
-    pub fn is_empty(&self) -> bool {
-        self.packages.is_empty()
-    }
-}
-
-/// Command line interface structure
-#[derive(clap::Parser)]
-#[command(version, about, long_about = None)]
-pub struct Cli {
-    #[command(subcommand)]
-    pub command: Commands,
-}
-
-/// Available CLI commands
-#[derive(Subcommand)]
-pub enum Commands {
-    /// Install Python packages
-    Install {
-        /// List of packages to install (can include version specs like "package==1.0.0")
-        packages: Vec<String>,
-        /// Install packages in parallel for faster execution
-        #[arg(short = 'p', long = "parallel", help = "Install packages in parallel")]
-        parallel: bool,
-    },
-    /// Delete a Python package
-    Delete {
-        /// Name of the package to delete
-        name: String,
-    },
-    /// Update a Python package to a specific version
-    Update {
-        /// Name of the package to update
-        name: String,
-        /// Target version for the update
-        version: String,
-    },
-    /// List all installed packages
-    List,
-}
-
-/// Trait defining package management operations
-pub trait PackageManager {
-    /// Installs packages sequentially
-    fn install_packages(&self, packages: &[String], registry: &mut PackageRegistry) -> Result<()>;
-
-    /// Installs packages in parallel
-    fn install_packages_parallel(
-        &self,
-        packages: &[String],
-        registry: &mut PackageRegistry,
-    ) -> Result<()>;
-
-    /// Deletes a single package
-    fn delete_package(&self, name: &str, registry: &mut PackageRegistry) -> Result<()>;
-
-    /// Updates a package to a specific version
-    fn update_package(
-        &self,
-        name: &str,
-        version: &str,
-        registry: &mut PackageRegistry,
-    ) -> Result<()>;
-
-    /// Lists all packages in the registry
-    fn list_packages(&self, registry: &PackageRegistry);
-
-    /// Installs packages from a requirements file
-    fn install_from_requirements(&self, path: &str, registry: &mut PackageRegistry) -> Result<()>;
-
-    /// Installs packages from a requirements file in parallel
-    fn install_from_requirements_parallel(
-        &self,
-        path: &str,
-        registry: &mut PackageRegistry,
-    ) -> Result<()>;
-}
-
-impl PackageManager for Cli {
-    fn install_packages(&self, packages: &[String], registry: &mut PackageRegistry) -> Result<()> {
-        install_packages(packages, registry)
-    }
-
-    fn install_packages_parallel(
-        &self,
-        packages: &[String],
-        registry: &mut PackageRegistry,
-    ) -> Result<()> {
-        install_packages_parallel(packages, registry)
-    }
-
-    fn delete_package(&self, name: &str, registry: &mut PackageRegistry) -> Result<()> {
-        delete_package(name, registry)
-    }
-
-    fn update_package(
-        &self,
-        name: &str,
-        version: &str,
-        registry: &mut PackageRegistry,
-    ) -> Result<()> {
-        update_package(name, version, registry)
-    }
-
-    fn list_packages(&self, registry: &PackageRegistry) {
-        list_packages(registry)
-    }
-
-    fn install_from_requirements(&self, path: &str, registry: &mut PackageRegistry) -> Result<()> {
-        install_from_requirements(path, registry)
-    }
-
-    fn install_from_requirements_parallel(
-        &self,
-        path: &str,
-        registry: &mut PackageRegistry,
-    ) -> Result<()> {
-        install_from_requirements_parallel(path, registry)
-    }
-}
-
-/// Locates the Python executable on the system
-///
-/// This function attempts to find a valid Python executable by trying
-/// common command names in order of preference. It validates that the
-/// found executable is actually working by running a simple command.
-///
-/// # Returns
-/// * `Result<String>` - Path to the Python executable or error if not found
-///
-/// # Examples
-/// ```
-/// let python_path = get_python_executable().unwrap();
-/// println!("Using Python: {}", python_path);
-/// ```
-fn get_python_executable() -> Result<String> {
-    if cfg!(test) {
-        return Ok("mock_python".to_string());
-    }
-
-    let candidates = ["python3", "python", "py"];
-
-    for cmd in &candidates {
-        if let Ok(output) = Command::new(cmd)
-            .arg("-c")
-            .arg("import sys; print(sys.executable)")
-            .output()
-        {
-            if output.status.success() {
-                if let Ok(path) = String::from_utf8(output.stdout) {
-                    return Ok(path.trim().to_string());
-                }
-            }
-        }
-    }
-
-    Err(PackageError::PythonNotFound)
-}
-
-/// Retrieves the installed version of a specific package
-///
-/// Uses `pip show` command to query the installed version of a package.
-/// Returns "unknown" if the package is not installed or version cannot be determined.
-///
-/// # Arguments
-/// * `python` - Path to the Python executable
-/// * `name` - Name of the package to check
-///
-/// # Returns
-/// * `Result<String>` - Version string or "unknown" if not found
-fn get_installed_version(python: &str, name: &str) -> Result<String> {
-    let output = Command::new(python)
-        .arg("-m")
-        .arg("pip")
-        .arg("show")
-        .arg(name)
-        .output()?;
-
-    if output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let version = stdout
-            .lines()
-            .find(|line| line.starts_with("Version: "))
-            .and_then(|line| line.strip_prefix("Version: "))
-            .map(|v| v.trim().to_string())
-            .unwrap_or_else(|| "unknown".to_string());
-        Ok(version)
-    } else {
-        Ok("unknown".to_string())
-    }
-}
-
-/// Loads the package registry from the JSON file
-///
-/// Attempts to load the package registry from `packages.json` in the current directory.
-/// If the file doesn't exist or is corrupted, returns an empty registry.
-///
-/// # Returns
-/// * `Result<PackageRegistry>` - Loaded registry or empty registry on error
-pub fn load_packages() -> Result<PackageRegistry> {
-    let path = PathBuf::from("packages.json");
-
-    if !path.exists() {
-        return Ok(PackageRegistry::new());
-    }
-
-    let file = File::open(&path)?;
-    let reader = BufReader::new(file);
-
-    match serde_json::from_reader(reader) {
-        Ok(registry) => Ok(registry),
-        Err(_) => {
-            eprintln!("Warning: Corrupted packages.json file, starting with empty registry");
-            Ok(PackageRegistry::new())
-        }
-    }
-}
-
-/// Saves the package registry to the JSON file
-///
-/// Serializes the current package registry to `packages.json` in the current directory.
-/// Uses pretty printing for better readability.
-///
-/// # Arguments
-/// * `registry` - The registry to save
-///
-/// # Returns
-/// * `Result<()>` - Success or IO error
-pub fn save_packages(registry: &PackageRegistry) -> Result<()> {
-    let path = PathBuf::from("packages.json");
-    let file = File::create(&path)?;
-    let writer = BufWriter::new(file);
-
-    serde_json::to_writer_pretty(writer, registry)?;
-    Ok(())
-}
-
-/// Installs packages sequentially using pip
-///
-/// Installs the specified packages one by one using a single pip command.
-/// Updates the registry with the installed packages and their versions.
-///
-/// # Arguments
-/// * `packages` - Slice of package specifications to install
-/// * `registry` - Mutable reference to the package registry
-///
-/// # Returns
-/// * `Result<()>` - Success or installation error
-pub fn install_packages(packages: &[String], registry: &mut PackageRegistry) -> Result<()> {
-    if packages.is_empty() {
-        return Ok(());
-    }
-
-    let python = get_python_executable()?;
-    let package_specs = prepare_package_specs(packages)?;
-
-    println!("Installing packages: {}", package_specs.join(", "));
-
-    let output = Command::new(&python)
-        .arg("-m")
-        .arg("pip")
-        .arg("install")
-        .args(&package_specs)
-        .output()?;
-
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(PackageError::InstallationFailed(error_msg.to_string()));
-    }
-
-    // Update registry with installed packages
-    for spec in packages {
-        let (name, version_option) = parse_package_spec(spec)?;
-        let version = match version_option {
-            Some(v) => v,
-            None => get_installed_version(&python, &name)?,
-        };
-
-        let package = Package::new(name.clone(), version.clone());
-        registry.add_package(package);
-        println!("✓ Successfully installed {} {}", name, version);
-    }
-
-    Ok(())
-}
-
-/// Installs packages in parallel using rayon
-///
-/// Installs each package in a separate thread for faster execution.
-/// Provides a progress bar to show installation progress.
-///
-/// # Arguments
-/// * `packages` - Slice of package specifications to install
-/// * `registry` - Mutable reference to the package registry
-///
-/// # Returns
-/// * `Result<()>` - Success or installation error
-pub fn install_packages_parallel(
-    packages: &[String],
-    registry: &mut PackageRegistry,
-) -> Result<()> {
-    if packages.is_empty() {
-        return Ok(());
-    }
-
-    let python = get_python_executable()?;
-
-    // Create and configure progress bar
-    let pb = create_progress_bar(packages.len());
-
-    // Thread-safe registry wrapper
-    let registry_mutex = Arc::new(Mutex::new(registry));
-
-    // Install packages in parallel
-    let results: Vec<Result<(String, String)>> = packages
-        .par_iter()
-        .map(|pkg| {
-            let result = install_single_package(&python, pkg, &pb);
-            pb.inc(1);
-            result
-        })
-        .collect();
-
-    pb.finish_with_message("Installation complete");
-
-    // Process results and update registry
-    process_installation_results(results, registry_mutex)?;
-
-    Ok(())
-}
-
-/// Deletes a package using pip uninstall
-///
-/// Removes the specified package from the system and updates the registry.
-///
-/// # Arguments
-/// * `name` - Name of the package to delete
-/// * `registry` - Mutable reference to the package registry
-///
-/// # Returns
-/// * `Result<()>` - Success or deletion error
-pub fn delete_package(name: &str, registry: &mut PackageRegistry) -> Result<()> {
-    if name.trim().is_empty() {
-        return Err(PackageError::InvalidPackageSpec(
-            "Package name cannot be empty".to_string(),
-        ));
-    }
-
-    let python = get_python_executable()?;
-
-    let output = Command::new(&python)
-        .arg("-m")
-        .arg("pip")
-        .arg("uninstall")
-        .arg(name)
-        .arg("-y")
-        .output()?;
-
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(PackageError::UninstallationFailed(error_msg.to_string()));
-    }
-
-    registry.remove_package(name);
-    println!("✓ Successfully removed package {}", name);
-    Ok(())
-}
-
-/// Updates a package to a specific version
-///
-/// Uses pip install --upgrade to update the package to the specified version.
-///
-/// # Arguments
-/// * `name` - Name of the package to update
-/// * `version` - Target version for the update
-/// * `registry` - Mutable reference to the package registry
-///
-/// # Returns
-/// * `Result<()>` - Success or update error
-pub fn update_package(name: &str, version: &str, registry: &mut PackageRegistry) -> Result<()> {
-    if name.trim().is_empty() || version.trim().is_empty() {
-        return Err(PackageError::InvalidPackageSpec(
-            "Package name and version cannot be empty".to_string(),
-        ));
-    }
-
-    let python = get_python_executable()?;
-    let package_spec = format!("{}=={}", name, version);
-
-    let output = Command::new(&python)
-        .arg("-m")
-        .arg("pip")
-        .arg("install")
-        .arg("--upgrade")
-        .arg(&package_spec)
-        .output()?;
-
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        return Err(PackageError::InstallationFailed(error_msg.to_string()));
-    }
-
-    let installed_version = get_installed_version(&python, name)?;
-    let package = Package::new(name.to_string(), installed_version.clone());
-    registry.add_package(package);
-
-    println!(
-        "✓ Successfully updated {} to version {}",
-        name, installed_version
-    );
-    Ok(())
-}
-
-/// Lists all packages in the registry
-///
-/// Displays all installed packages with their versions in a formatted list.
-///
-/// # Arguments
-/// * `registry` - Reference to the package registry
-pub fn list_packages(registry: &PackageRegistry) {
-    if registry.is_empty() {
-        println!("No packages installed");
-        return;
-    }
-
-    println!("Installed packages ({} total):", registry.packages.len());
-    let mut packages: Vec<_> = registry.packages.values().collect();
-    packages.sort_by(|a, b| a.name.cmp(&b.name));
-
-    for package in packages {
-        println!("  {} @ {}", package.name, package.version);
-    }
-}
-
-/// Installs packages from a requirements file
-///
-/// Reads a requirements.txt file and installs all specified packages.
-///
-/// # Arguments
-/// * `path` - Path to the requirements file
-/// * `registry` - Mutable reference to the package registry
-///
-/// # Returns
-/// * `Result<()>` - Success or installation error
-pub fn install_from_requirements(path: &str, registry: &mut PackageRegistry) -> Result<()> {
-    install_from_requirements_impl(path, registry, false)
-}
-
-/// Installs packages from a requirements file in parallel
-///
-/// Reads a requirements.txt file and installs all specified packages in parallel.
-///
-/// # Arguments
-/// * `path` - Path to the requirements file
-/// * `registry` - Mutable reference to the package registry
-///
-/// # Returns
-/// * `Result<()>` - Success or installation error
-pub fn install_from_requirements_parallel(
-    path: &str,
-    registry: &mut PackageRegistry,
-) -> Result<()> {
-    install_from_requirements_impl(path, registry, true)
-}
-
-// Helper functions
-
-/// Creates a configured progress bar for package installation
-fn create_progress_bar(len: usize) -> ProgressBar {
-    let pb = ProgressBar::new(len as u64);
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
-            .unwrap()
-            .progress_chars("#>-"),
-    );
-    pb
+//!
+//! The building blocks live in public modules: [`registry`] for `packages.json`,
+//! [`spec`] for package names, specifiers and versions, [`requirements`] for
+//! requirements and constraints files, [`pip`] for running pip and the
+//! interpreter, and [`cli`] for the command line definitions. Operations live
+//! next to them: [`install`], [`update`] and [`remove`] change the environment,
+//! [`upgrade`] plans upgrades, [`snapshot`], [`history`] and [`stats`] keep
+//! local state, [`metadata`] caches PyPI lookups, and [`error`],
+//! [`error_codes`], [`diagnostics`] and [`reporter`] cover failures and output.
+//! The items most callers need are re-exported at the crate root.
+
+/// Reports a status message, shown unless `--quiet` was given
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        $crate::report($crate::MessageKind::Status, format_args!($($arg)*))
+    };
 }
 
-/// Installs a single package and returns the result
-fn install_single_package(python: &str, pkg: &str, pb: &ProgressBar) -> Result<(String, String)> {
-    let (name, version) = parse_package_spec(pkg)?;
-    let package_spec = version
-        .as_ref()
-        .map_or(name.clone(), |v| format!("{}=={}", name, v));
-
-    pb.set_message(format!("Installing {}", name));
-
-    let output = Command::new(python)
-        .arg("-m")
-        .arg("pip")
-        .arg("install")
-        .arg(&package_spec)
-        .output()?;
-
-    if !output.status.success() {
-        let error = String::from_utf8_lossy(&output.stderr);
-        return Err(PackageError::InstallationFailed(format!(
-            "Failed to install {}: {}",
-            name, error
-        )));
-    }
-
-    let installed_version = version.unwrap_or_else(|| {
-        get_installed_version(python, &name).unwrap_or_else(|_| "unknown".to_string())
-    });
-
-    Ok((name, installed_version))
+/// Reports a message shown regardless of verbosity, such as the context of a confirmation prompt
+#[macro_export]
+macro_rules! notice {
+    ($($arg:tt)*) => {
+        $crate::report($crate::MessageKind::Notice, format_args!($($arg)*))
+    };
 }
 
-/// Processes installation results and updates the registry
-fn process_installation_results(
-    results: Vec<Result<(String, String)>>,
-    registry_mutex: Arc<Mutex<&mut PackageRegistry>>,
-) -> Result<()> {
-    let mut success_count = 0;
-    let mut failure_count = 0;
-
-    for result in results {
-        match result {
-            Ok((name, version)) => {
-                let mut reg = registry_mutex.lock().unwrap();
-                let package = Package::new(name.clone(), version.clone());
-                reg.add_package(package);
-                println!("✓ Successfully installed {} {}", name, version);
-                success_count += 1;
-            }
-            Err(error) => {
-                eprintln!("✗ {}", error);
-                failure_count += 1;
-            }
-        }
-    }
-
-    println!(
-        "\nInstallation summary: {} succeeded, {} failed",
-        success_count, failure_count
-    );
-
-    if failure_count > 0 {
-        Err(PackageError::InstallationFailed(format!(
-            "{} packages failed to install",
-            failure_count
-        )))
-    } else {
-        Ok(())
-    }
+/// Reports a warning, shown unless `--quiet` was given
+#[macro_export]
+macro_rules! warning {
+    ($($arg:tt)*) => {{
+        $crate::tracing::warn!($($arg)*);
+        $crate::record_warning();
+        $crate::report($crate::MessageKind::Warning, format_args!($($arg)*));
+    }};
 }
 
-/// Prepares package specifications for pip installation
-fn prepare_package_specs(packages: &[String]) -> Result<Vec<String>> {
-    packages
-        .iter()
-        .map(|pkg| {
-            let (name, version) = parse_package_spec(pkg)?;
-            Ok(version.map_or(name.clone(), |v| format!("{}=={}", name, v)))
-        })
-        .collect()
+/// Reports extra detail, shown when `-v` was given
+#[macro_export]
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        $crate::report($crate::MessageKind::Detail, format_args!($($arg)*))
+    };
 }
 
-/// Implementation for installing from requirements files
-fn install_from_requirements_impl(
-    path: &str,
-    registry: &mut PackageRegistry,
-    parallel: bool,
-) -> Result<()> {
-    if !Path::new(path).exists() {
-        return Err(PackageError::IoError(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("Requirements file not found: {}", path),
-        )));
-    }
-
-    let packages = parse_requirements_file(path)?;
-
-    if packages.is_empty() {
-        println!("No packages found in requirements file");
-        return Ok(());
-    }
-
-    println!("Installing {} packages from {}", packages.len(), path);
-
-    if parallel {
-        install_packages_parallel(&packages, registry)
-    } else {
-        install_packages(&packages, registry)
-    }
-}
-
-/// Parses a requirements file and returns package specifications
-fn parse_requirements_file(path: &str) -> Result<Vec<String>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mut packages = Vec::new();
-
-    for line in reader.lines() {
-        let line = line?;
-        let line = line.trim();
-
-        // Skip empty lines and comments
-        if line.is_empty() || line.starts_with('#') {
-            continue;
-        }
-
-        // Basic validation of package specification
-        if line.contains(' ') && !line.contains("==") {
-            eprintln!("Warning: Skipping potentially invalid line: {}", line);
-            continue;
-        }
-
-        packages.push(line.to_string());
-    }
-
-    Ok(packages)
-}
-
-/// Parses a package specification into name and optional version
-///
-/// Supports formats like "package" or "package==1.0.0"
-///
-/// # Arguments
-/// * `spec` - Package specification string
-///
-/// # Returns
-/// * `Result<(String, Option<String>)>` - Package name and optional version
-fn parse_package_spec(spec: &str) -> Result<(String, Option<String>)> {
-    let spec = spec.trim();
-
-    if spec.is_empty() {
-        return Err(PackageError::InvalidPackageSpec(
-            "Empty package specification".to_string(),
-        ));
-    }
-
-    let parts: Vec<&str> = spec.splitn(2, "==").collect();
-    match parts.as_slice() {
-        [name, version] => {
-            let name = name.trim();
-            let version = version.trim();
-
-            if name.is_empty() || version.is_empty() {
-                return Err(PackageError::InvalidPackageSpec(format!(
-                    "Invalid package specification: {}",
-                    spec
-                )));
-            }
-
-            Ok((name.to_string(), Some(version.to_string())))
-        }
-        [name] => {
-            let name = name.trim();
-            if name.is_empty() {
-                return Err(PackageError::InvalidPackageSpec(
-                    "Empty package name".to_string(),
-                ));
-            }
-            Ok((name.to_string(), None))
-        }
-        _ => Err(PackageError::InvalidPackageSpec(format!(
-            "Invalid package specification: {}",
-            spec
-        ))),
-    }
+/// Reports debugging detail, shown when `-vv` was given
+#[macro_export]
+macro_rules! debug_detail {
+    ($($arg:tt)*) => {{
+        $crate::tracing::debug!($($arg)*);
+        $crate::report($crate::MessageKind::Debug, format_args!($($arg)*));
+    }};
 }
 
+#[cfg(feature = "async")]
+pub mod async_api;
+pub mod attest;
+pub mod benchmark;
+pub mod build;
+pub mod bundle;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod container;
+pub mod convert;
+pub mod diagnostics;
+pub mod diff;
+pub mod environments;
+pub mod error;
+pub mod error_codes;
+pub mod graph;
+pub mod health;
+pub mod history;
+pub mod http;
+pub mod inspect;
+pub mod install;
+pub mod interpreters;
+pub mod lock;
+pub mod metadata;
+pub mod observer;
+pub mod outcome;
+pub mod pip;
+pub mod policy;
+#[cfg(feature = "progress")]
+pub mod progress;
+pub mod project;
+pub mod pypackages;
+pub mod registry;
+pub mod releases;
+pub mod remove;
+pub mod reporter;
+pub mod requirements;
+pub mod serve;
+pub mod session;
+pub mod snapshot;
+pub mod spec;
+pub mod state;
+pub mod stats;
+pub mod suggest;
 #[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_parse_package_spec_with_version() {
-        let result = parse_package_spec("numpy==1.21.0").unwrap();
-        assert_eq!(result, ("numpy".to_string(), Some("1.21.0".to_string())));
-    }
-
-    #[test]
-    fn test_parse_package_spec_without_version() {
-        let result = parse_package_spec("requests").unwrap();
-        assert_eq!(result, ("requests".to_string(), None));
-    }
-
-    #[test]
-    fn test_parse_package_spec_invalid() {
-        assert!(parse_package_spec("").is_err());
-        assert!(parse_package_spec("==1.0.0").is_err());
-        assert!(parse_package_spec("package==").is_err());
-    }
-
-    #[test]
-    fn test_package_registry_operations() {
-        let mut registry = PackageRegistry::new();
-        let package = Package::new("test".to_string(), "1.0.0".to_string());
-
-        registry.add_package(package.clone());
-        assert_eq!(registry.get_package("test"), Some(&package));
-
-        let removed = registry.remove_package("test");
-        assert_eq!(removed, Some(package));
-        assert!(registry.is_empty());
-    }
-}
+mod test_support;
+pub mod update;
+pub mod upgrade;
+pub mod vendor;
+pub mod webhook;
+pub mod workspace;
+
+#[cfg(feature = "async")]
+pub use async_api::{delete_packages_async, install_packages_async, update_packages_async};
+pub use attest::{
+    check_attestations, provenance_url, report_attestations, require_attestations,
+    AttestationCheck, AttestationStatus, Publisher, PYPI_INTEGRITY_URL,
+};
+pub use benchmark::{
+    benchmark_install, render_benchmark, BenchmarkResult, InstallStrategy, PackageTiming,
+};
+pub use build::{
+    build_project, build_tool_dir, parse_built_artifacts, render_build_summary, BuildSummary,
+    BuildTarget,
+};
+pub use bundle::{
+    bundle_app, create_offline_bundle, directory_digest, install_offline_bundle, parse_entry_point,
+    render_bundle_main, render_bundle_summary, render_offline_bundle_summary, verify_bundle_files,
+    BundleManifest, BundleSummary, BundledFile, OfflineBundleSummary, BUNDLE_ROOT_ENV,
+    DEFAULT_BUNDLE_SHEBANG, OFFLINE_BUNDLE_FORMAT,
+};
+#[cfg(feature = "cli")]
+pub use cli::{
+    find_plugin, generate_manpages, install_name_candidates, registry_name_candidates,
+    run_in_environment, run_plugin, watch_files, BundleAction, Cli, Commands, EnvAction,
+    PackageManager, PipAction, PythonAction, PythonEnvironment, RegistryAction, SnapshotAction,
+    CI_ENV, PLUGIN_PREFIX, WATCH_DEBOUNCE,
+};
+pub use container::{
+    container_assets, render_dockerfile, render_dockerignore, write_container_assets,
+    ContainerAssets, CONTAINER_REQUIREMENTS_FILE, DEFAULT_BASE_IMAGE,
+};
+pub use convert::{
+    convert_dependency_file, read_dependencies, render_dependencies, DependencyFormat,
+    DependencySet,
+};
+pub use diagnostics::{diagnose_pip_output, Diagnostic};
+pub use diff::{
+    diff_package_sets, load_package_set, render_package_diff, DiffEntry, DiffFormat, PackageDiff,
+    PackageSet, VersionChange,
+};
+pub use environments::{
+    clone_environment, create_environment, environment_summaries, environments_dir,
+    remove_environment, render_environments, replica_spec, select_environment,
+    validate_environment_name, EnvironmentSummary, DEFAULT_ENVIRONMENT,
+};
+pub use error::{HashMismatch, HttpFailure, PackageError, PipFailure, Result};
+pub use error_codes::{explain_error_code, render_error_explanation, ErrorCodeInfo, ERROR_CODES};
+pub use graph::{DependencyGraph, DependencyNode};
+pub use health::{
+    check_maintenance, package_health, package_info, render_package_info, set_unmaintained_after,
+    unmaintained_after, unmaintained_reasons, MaintenanceWarning, PackageHealth,
+    DEFAULT_UNMAINTAINED_AFTER_DAYS,
+};
+pub use history::{
+    apply_undo, filter_history, load_history, plan_undo, record_history, render_history,
+    render_undo_plan, run_transaction, HistoryChange, HistoryEntry, HistoryFormat, UndoPlan,
+};
+pub use http::{
+    http_client, set_http_client, HttpClient, HttpRequest, HttpResponse, DEFAULT_HTTP_CONCURRENCY,
+    DEFAULT_HTTP_RETRIES, DEFAULT_HTTP_TIMEOUT,
+};
+pub use inspect::{
+    collect_package_hashes, format_size, list_console_scripts, list_packages,
+    list_packages_formatted, measure_package_sizes, print_package_sizes, render_console_scripts,
+    render_package_list, ConsoleScript, GraphFormat, ListAnnotations, ListFormat, PackageSize,
+};
+#[cfg(feature = "parallel")]
+pub use install::configure_jobs;
+pub use install::{
+    find_interpreter, install_matrix, install_packages, install_packages_parallel,
+    install_with_options, render_matrix, resume_install, warm_cache, InstallCheckpoint,
+    InstallOptions, MatrixResult, WarmedPackage,
+};
+pub use interpreters::{
+    discover_interpreters, find_pyenv_version, install_pyenv_version, parse_pep514_registry,
+    parse_python_version_file, pinned_python_version, pyenv_interpreter, pyenv_root,
+    pyenv_versions, registered_interpreters, render_interpreters, Interpreter, InterpreterSource,
+    RegisteredInterpreter, PYTHON_VERSION_FILE,
+};
+pub use lock::{
+    check_lockfile, install_from_lockfile, resolve_lockfile, verify_environment, verify_lockfile,
+    EnvironmentDrift, InstalledDistribution, LockDrift, LockVerification, LockedArchive,
+    LockedPackage, LockedVcs, Lockfile, LOCK_FILE,
+};
+pub use metadata::{
+    metadata_ttl, set_metadata_ttl, CachedMetadata, MetadataCache, DEFAULT_METADATA_TTL,
+};
+pub use observer::{install_observer, set_install_observer, InstallObserver};
+pub use outcome::{print_install_report, render_summary_table, InstallReport, OperationOutcome};
+pub use pip::{
+    command_runner, pip_supports, pip_version, set_command_runner, upgrade_pip, CommandRunner,
+    PipFeature, PipStatus, ScriptedRunner, SystemRunner,
+};
+pub use policy::{
+    load_organization_policy, organization_policy, package_policy, set_package_policy,
+    PackagePolicy, DEFAULT_INDEX_URL, POLICY_SHA256_ENV, POLICY_URL_ENV,
+};
+#[cfg(feature = "progress")]
+pub use progress::ProgressBarObserver;
+pub use project::{
+    create_virtualenv, extraneous_packages, init_project, packages_required_by,
+    profile_requirements, HttpConfig, InitFormat, InitOptions, ProjectConfig, WorkspaceConfig,
+    PROFILES_DIR, PROJECT_CONFIG_FILE,
+};
+pub use pypackages::{
+    enable_local_packages, local_packages, local_packages_env, local_packages_requested,
+    pypackages_lib, set_local_packages, PYPACKAGES_DIR,
+};
+pub use registry::{
+    active_environment, load_packages, load_packages_unverified, read_registry, registry_checksum,
+    registry_key, save_packages, set_active_environment, write_registry, NamedEnvironment, Package,
+    PackageOrigin, PackageRegistry, VendoredSet, REGISTRY_FILE, REGISTRY_KEY_ENV,
+};
+pub use releases::{
+    parse_change_range, release_changes, render_release_changes, summarize_releases,
+    ReleaseChanges, ReleaseNote,
+};
+pub use remove::{
+    check_removal, delete_package, delete_package_with_dependents, delete_packages, plan_removal,
+    prune_packages, remove_orphaned_packages, resolve_package_patterns, stale_packages,
+    DependentsPolicy, RemovalPlan,
+};
+pub use reporter::{
+    error_label, init_logging, json_output, report, reporter, set_color_choice, set_json_output,
+    set_reporter, set_verbosity, success_mark, verbosity, warning_label, ColorChoice,
+    HumanReporter, JsonReporter, MessageKind, QuietReporter, Reporter, Verbosity, LOG_ENV,
+};
+pub use requirements::{
+    add_pyproject_dependency, add_requirement, bump_pyproject, bump_requirements,
+    format_requirements, install_from_requirements, install_from_requirements_parallel,
+    install_from_requirements_with_options, parse_requirements_file, pin_requirements,
+    pin_requirements_file, pinnable_requirements, read_constraints_file, read_package_list,
+    read_update_file, remove_pyproject_dependency, remove_requirement, PinChange,
+    PinnedRequirements, SpecEdit,
+};
+pub use serve::{render_project_page, serve, IndexProxy, DEFAULT_SERVE_PORT};
+pub use session::{
+    assume_yes, ci_mode, emit_run_summary, progress_mode, prompt_answer, record_warning,
+    render_json_result, set_assume_yes, set_ci_mode, set_progress_mode, set_python_override,
+    set_report_data, warnings_emitted, ProgressMode, PromptAnswer,
+};
+pub use snapshot::{
+    apply_restore, create_snapshot, delete_snapshot, installed_snapshot_packages, list_snapshots,
+    load_snapshot, plan_restore, render_restore_plan, RestorePlan, Snapshot, SnapshotPackage,
+};
+pub use spec::{canonicalize_name, VcsRequirement};
+pub use stats::{
+    format_duration, record_run, render_stats, OperationTiming, Phase, RunStats, StatsHistory,
+    MAX_RECORDED_RUNS,
+};
+pub use suggest::{
+    did_you_mean, find_similar_names, similar_popular_package, suggest_package_names,
+};
+pub use update::{
+    add_dependencies, apply_upgrade_plan_parallel, bump_package, parse_update_targets,
+    remove_dependencies, resolve_update_versions, set_pinned, update_package,
+    update_packages_parallel, update_with_options, BumpReport, BumpTarget, DeclarationReport,
+    UpdatePolicies, UpdatePolicy,
+};
+pub use upgrade::{
+    apply_upgrade_plan, parse_report_path, parse_report_spec, plan_upgrades, print_upgrade_plan,
+    render_outdated, render_upgrade_plan_markdown, select_upgrades, upgrade_choice_labels,
+    write_upgrade_report, HeldPackage, ReportFormat, UncheckedPackage, UpgradePlan,
+    UpgradePlanEntry, Vulnerability,
+};
+pub use vendor::{
+    parse_record, remove_vendored, render_vendor_summary, vendor_install, vendor_key,
+    vendored_distributions, VendorSummary, VendoredDistribution,
+};
+pub use webhook::{send_webhook, webhook_payload, OutdatedSummary, WebhookConfig, WebhookFormat};
+pub use workspace::{
+    render_workspace_report, run_in_workspace, summarize_workspace, workspace_members,
+    WorkspaceMember, WorkspaceMemberReport,
+};
+
+#[doc(hidden)]
+pub use tracing;
@@ -0,0 +1,888 @@
+//! Lockfiles pinning every resolved package together with the hash of its archive
+//!
+//! The lockfile follows the layout of PEP 751 (`pylock.toml`), so other
+//! tools and `ppm diff` can read it.
+
+use crate::install::{install_hash_pinned, install_packages_with_args};
+use crate::pip::{
+    get_python_executable, pip_error, request_pip_report, require_pip_feature, run_command,
+    run_pip, take_pip_report, PipFeature, ReportedInstall,
+};
+use crate::registry::PackageRegistry;
+use crate::spec::{canonicalize_name, compare_versions};
+use crate::{InstallReport, PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+/// Default lockfile name
+pub const LOCK_FILE: &str = "pylock.toml";
+
+/// A `pylock.toml` lockfile
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Lockfile {
+    /// Version of the lockfile format
+    pub lock_version: String,
+    /// Tool that wrote the lockfile
+    pub created_by: String,
+    /// Supported Python versions, e.g. ">=3.9"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires_python: Option<String>,
+    /// Every resolved package, sorted by name
+    #[serde(default)]
+    pub packages: Vec<LockedPackage>,
+}
+
+/// A package pinned in a [`Lockfile`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedPackage {
+    /// Package name
+    pub name: String,
+    /// Resolved version
+    pub version: String,
+    /// The source distribution that was resolved, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sdist: Option<LockedArchive>,
+    /// The wheels that were resolved
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wheels: Vec<LockedArchive>,
+    /// The repository commit, for packages installed from version control
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub vcs: Option<LockedVcs>,
+}
+
+/// A downloadable archive with its hashes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockedArchive {
+    /// Where the archive is downloaded from
+    pub url: String,
+    /// Digests keyed by algorithm, e.g. `sha256`
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+}
+
+/// A version control checkout
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct LockedVcs {
+    /// Version control system, e.g. "git"
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Repository URL
+    pub url: String,
+    /// The exact commit
+    pub commit_id: String,
+}
+
+impl LockedPackage {
+    /// Every archive of the package with its hashes
+    fn archives(&self) -> impl Iterator<Item = &LockedArchive> {
+        self.sdist.iter().chain(&self.wheels)
+    }
+
+    /// The sha256 digests of its archives
+    pub(crate) fn sha256_hashes(&self) -> Vec<&str> {
+        self.archives()
+            .filter_map(|archive| archive.hashes.get("sha256"))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Whether pip can check its archives in `--require-hashes` mode
+    pub(crate) fn is_hash_pinned(&self) -> bool {
+        self.vcs.is_none() && !self.sha256_hashes().is_empty()
+    }
+
+    /// Requirement spec installing exactly the locked version or commit
+    pub(crate) fn spec(&self) -> String {
+        match &self.vcs {
+            Some(vcs) => format!("{} @ {}+{}@{}", self.name, vcs.kind, vcs.url, vcs.commit_id),
+            None => format!("{}=={}", self.name, self.version),
+        }
+    }
+}
+
+impl Lockfile {
+    /// Loads a lockfile
+    ///
+    /// # Arguments
+    /// * `path` - The lockfile to read
+    ///
+    /// # Returns
+    /// * `Result<Self>` - The lockfile, or an error if it is missing or malformed
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| {
+            PackageError::MetadataError(format!("Invalid lockfile {}: {}", path.display(), e))
+        })
+    }
+
+    /// Writes the lockfile
+    ///
+    /// # Arguments
+    /// * `path` - Where to write it
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let contents = toml::to_string(self).map_err(|e| {
+            PackageError::MetadataError(format!("Failed to serialize lockfile: {}", e))
+        })?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Resolves a requirements file into a lockfile without installing anything
+///
+/// pip resolves the requirements from scratch, ignoring what is installed,
+/// and reports the archive and hash of every package it would install.
+///
+/// # Arguments
+/// * `requirements` - The requirements file declaring the dependencies
+/// * `requires_python` - Supported Python versions recorded in the lockfile
+///
+/// # Returns
+/// * `Result<Lockfile>` - The resolved lockfile, or an error if pip could not resolve it
+pub fn resolve_lockfile(requirements: &str, requires_python: Option<&str>) -> Result<Lockfile> {
+    let python = get_python_executable()?;
+    require_pip_feature(&python, PipFeature::InstallReport, "Locking")?;
+    let mut command = Command::new(&python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("install")
+        .arg("--dry-run")
+        .arg("--ignore-installed")
+        .arg("--quiet")
+        .arg("-r")
+        .arg(requirements);
+    let report = request_pip_report(&python, &mut command);
+    let output = run_pip(command, "lock", None)?;
+    let resolved = take_pip_report(report);
+    if !output.status.success() {
+        return Err(pip_error(requirements, &output, |stderr| {
+            PackageError::InstallationFailed(format!(
+                "Failed to resolve {}: {}",
+                requirements, stderr
+            ))
+        }));
+    }
+
+    let mut packages: Vec<LockedPackage> = resolved.iter().map(locked_package).collect();
+    packages.sort_by_key(|package| canonicalize_name(&package.name));
+    Ok(Lockfile {
+        lock_version: "1.0".to_string(),
+        created_by: "ppm".to_string(),
+        requires_python: requires_python.map(str::to_string),
+        packages,
+    })
+}
+
+/// Turns a distribution from pip's report into a lockfile entry
+fn locked_package(install: &ReportedInstall) -> LockedPackage {
+    let mut package = LockedPackage {
+        name: install.name.clone(),
+        version: install.version.clone(),
+        sdist: None,
+        wheels: Vec::new(),
+        vcs: None,
+    };
+    let Some(origin) = &install.origin else {
+        return package;
+    };
+    if let Some(commit) = &origin.commit {
+        package.vcs = Some(LockedVcs {
+            kind: "git".to_string(),
+            url: origin.url.trim_start_matches("git+").to_string(),
+            commit_id: commit.clone(),
+        });
+        return package;
+    }
+
+    let hashes = origin
+        .hash
+        .as_deref()
+        .and_then(|hash| hash.split_once('='))
+        .map(|(algorithm, digest)| BTreeMap::from([(algorithm.to_string(), digest.to_string())]))
+        .unwrap_or_default();
+    let archive = LockedArchive {
+        url: origin.url.clone(),
+        hashes,
+    };
+    if origin.url.ends_with(".whl") {
+        package.wheels.push(archive);
+    } else {
+        package.sdist = Some(archive);
+    }
+    package
+}
+
+/// Installs exactly the packages of a lockfile, verifying the hash of every archive
+///
+/// Packages with sha256 hashes are installed in a single `pip install
+/// --require-hashes --no-deps` run, so an archive that does not match its
+/// hash fails the whole install with [`PackageError::HashMismatch`] before
+/// anything is installed. Packages locked without a hash, such as version
+/// control checkouts, are installed afterwards with a warning.
+///
+/// # Arguments
+/// * `path` - The lockfile to install
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if the
+///   lockfile could not be read or pip could not be run
+pub fn install_from_lockfile(path: &Path, registry: &mut PackageRegistry) -> Result<InstallReport> {
+    let lockfile = Lockfile::load(path)?;
+    let (hashed, unhashed): (Vec<&LockedPackage>, Vec<&LockedPackage>) = lockfile
+        .packages
+        .iter()
+        .partition(|package| package.is_hash_pinned());
+    let no_deps = ["--no-deps".to_string()];
+
+    let mut report = InstallReport::new("Installation summary", "install");
+    if !hashed.is_empty() {
+        status!(
+            "Installing {} locked packages from {}, verifying every archive",
+            hashed.len(),
+            path.display()
+        );
+        // Created with O_EXCL and mode 0600, so nobody else can swap the pinned hashes
+        let mut requirements = tempfile::Builder::new()
+            .prefix("ppm-lock-")
+            .suffix(".txt")
+            .tempfile()?;
+        requirements.write_all(hashed_requirements(&hashed).as_bytes())?;
+        requirements.flush()?;
+        let specs: Vec<String> = hashed.iter().map(|package| package.spec()).collect();
+        report = install_hash_pinned(requirements.path(), &specs, &no_deps, registry)?;
+        if !report.is_success() {
+            return Ok(report);
+        }
+    }
+
+    if !unhashed.is_empty() {
+        for package in &unhashed {
+            warning!(
+                "{} {} has no sha256 hash in {}; installing it without verification",
+                package.name,
+                package.version,
+                path.display()
+            );
+        }
+        let specs: Vec<String> = unhashed.iter().map(|package| package.spec()).collect();
+        let rest = install_packages_with_args(&specs, &no_deps, registry)?;
+        report.outcomes.extend(rest.outcomes);
+        report.warnings.extend(rest.warnings);
+        report.failure = rest.failure;
+    }
+    Ok(report)
+}
+
+/// Renders locked packages as a requirements file with `--hash` options
+pub(crate) fn hashed_requirements(packages: &[&LockedPackage]) -> String {
+    let mut out = String::new();
+    for package in packages {
+        out.push_str(&package.spec());
+        for hash in package.sha256_hashes() {
+            out.push_str(&format!(" \\\n    --hash=sha256:{}", hash));
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// A way in which a lockfile no longer matches a fresh resolution
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LockDrift {
+    /// The resolution needs a package the lockfile does not list
+    Missing { name: String, version: String },
+    /// The lockfile lists a package the resolution no longer needs
+    Extraneous { name: String, version: String },
+    /// The resolution picked another version than the lockfile
+    VersionChanged {
+        name: String,
+        locked: String,
+        resolved: String,
+    },
+    /// The same archive now has another hash than the one locked
+    HashChanged { name: String, url: String },
+    /// The lockfile lists a package more than once
+    Duplicate { name: String },
+}
+
+impl fmt::Display for LockDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockDrift::Missing { name, version } => {
+                write!(f, "+ {} {} is not locked", name, version)
+            }
+            LockDrift::Extraneous { name, version } => {
+                write!(f, "- {} {} is locked but no longer required", name, version)
+            }
+            LockDrift::VersionChanged {
+                name,
+                locked,
+                resolved,
+            } => write!(
+                f,
+                "~ {} is locked at {} but resolves to {}",
+                name, locked, resolved
+            ),
+            LockDrift::HashChanged { name, url } => {
+                write!(f, "! {}: hash of {} changed", name, url)
+            }
+            LockDrift::Duplicate { name } => write!(f, "! {} is locked more than once", name),
+        }
+    }
+}
+
+/// Compares a lockfile with a fresh resolution of the same requirements
+///
+/// Hashes are only compared for archives with the same URL, as pip picks
+/// the wheel matching the platform it runs on.
+///
+/// # Arguments
+/// * `locked` - The committed lockfile
+/// * `resolved` - The result of [`resolve_lockfile`]
+///
+/// # Returns
+/// Every difference, sorted by package name; empty when the lockfile is current
+pub fn check_lockfile(locked: &Lockfile, resolved: &Lockfile) -> Vec<LockDrift> {
+    let mut drift = Vec::new();
+    let mut locked_by_name: HashMap<String, &LockedPackage> = HashMap::new();
+    for package in &locked.packages {
+        if locked_by_name
+            .insert(canonicalize_name(&package.name), package)
+            .is_some()
+        {
+            drift.push(LockDrift::Duplicate {
+                name: package.name.clone(),
+            });
+        }
+    }
+    let resolved_by_name: HashMap<String, &LockedPackage> = resolved
+        .packages
+        .iter()
+        .map(|package| (canonicalize_name(&package.name), package))
+        .collect();
+
+    for (key, package) in &resolved_by_name {
+        let Some(lock) = locked_by_name.get(key) else {
+            drift.push(LockDrift::Missing {
+                name: package.name.clone(),
+                version: package.version.clone(),
+            });
+            continue;
+        };
+        if lock.version != package.version {
+            drift.push(LockDrift::VersionChanged {
+                name: package.name.clone(),
+                locked: lock.version.clone(),
+                resolved: package.version.clone(),
+            });
+            continue;
+        }
+        for archive in package.archives() {
+            let changed = lock.archives().any(|locked| {
+                locked.url == archive.url
+                    && archive.hashes.iter().any(|(algorithm, digest)| {
+                        locked.hashes.get(algorithm).is_some_and(|d| d != digest)
+                    })
+            });
+            if changed {
+                drift.push(LockDrift::HashChanged {
+                    name: package.name.clone(),
+                    url: archive.url.clone(),
+                });
+            }
+        }
+    }
+    for (key, lock) in &locked_by_name {
+        if !resolved_by_name.contains_key(key) {
+            drift.push(LockDrift::Extraneous {
+                name: lock.name.clone(),
+                version: lock.version.clone(),
+            });
+        }
+    }
+
+    drift.sort_by_key(|d| canonicalize_name(drift_name(d)));
+    drift
+}
+
+/// Package a drift entry is about
+fn drift_name(drift: &LockDrift) -> &str {
+    match drift {
+        LockDrift::Missing { name, .. }
+        | LockDrift::Extraneous { name, .. }
+        | LockDrift::VersionChanged { name, .. }
+        | LockDrift::HashChanged { name, .. }
+        | LockDrift::Duplicate { name } => name,
+    }
+}
+
+/// Installer tools virtual environments are seeded with, not reported as unlocked
+const SEED_PACKAGES: [&str; 3] = ["pip", "setuptools", "wheel"];
+
+/// Python snippet that prints every installed distribution with the archive
+/// hash and commit of its `direct_url.json`, and, for the distributions named
+/// on the command line, the installed files that no longer match their RECORD
+const INSTALLED_LOCK_STATE_SCRIPT: &str = r#"
+import base64, hashlib, json, os, re, sys
+from importlib import metadata
+local = os.environ.get("PPM_LOCAL_PACKAGES")
+checked = set(sys.argv[1:])
+rows = []
+for dist in metadata.distributions(**({"path": [local]} if local else {})):
+    name = dist.metadata["Name"]
+    try:
+        direct = json.loads(dist.read_text("direct_url.json") or "{}")
+    except ValueError:
+        direct = {}
+    archive = direct.get("archive_info") or {}
+    hashes = archive.get("hashes") or {}
+    if not hashes and "=" in archive.get("hash", ""):
+        algorithm, _, digest = archive["hash"].partition("=")
+        hashes = {algorithm: digest}
+    modified = []
+    if re.sub(r"[-_.]+", "-", name).lower() in checked:
+        for entry in dist.files or []:
+            if entry.hash is None or entry.hash.mode != "sha256":
+                continue
+            try:
+                with open(dist.locate_file(entry), "rb") as handle:
+                    digest = hashlib.sha256(handle.read()).digest()
+                digest = base64.urlsafe_b64encode(digest).rstrip(b"=").decode()
+            except OSError:
+                digest = None
+            if digest != entry.hash.value:
+                modified.append(str(entry))
+    commit = (direct.get("vcs_info") or {}).get("commit_id")
+    rows.append({"name": name, "version": dist.version, "hashes": hashes,
+                 "commit": commit, "modified": modified})
+print(json.dumps(rows))
+"#;
+
+/// An installed distribution as [`verify_environment`] sees it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InstalledDistribution {
+    /// Distribution name
+    pub name: String,
+    /// Installed version
+    pub version: String,
+    /// Hashes of the archive it was installed from, keyed by algorithm, when
+    /// pip recorded them in `direct_url.json`
+    #[serde(default)]
+    pub hashes: BTreeMap<String, String>,
+    /// Commit it was installed from, for version control checkouts
+    #[serde(default)]
+    pub commit: Option<String>,
+    /// Installed files whose contents no longer match the hash in its RECORD
+    #[serde(default)]
+    pub modified: Vec<String>,
+}
+
+/// A way in which the installed environment no longer matches the lockfile
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum EnvironmentDrift {
+    /// A locked package is not installed
+    NotInstalled { name: String, version: String },
+    /// A package is installed that the lockfile does not list
+    NotLocked { name: String, version: String },
+    /// Another version is installed than the one locked
+    VersionDiffers {
+        name: String,
+        locked: String,
+        installed: String,
+    },
+    /// The archive it was installed from has none of the locked hashes
+    HashDiffers {
+        name: String,
+        version: String,
+        recorded: String,
+    },
+    /// Another commit is checked out than the one locked
+    CommitDiffers {
+        name: String,
+        locked: String,
+        installed: String,
+    },
+    /// Installed files were changed after the install
+    FilesModified {
+        name: String,
+        version: String,
+        files: Vec<String>,
+    },
+}
+
+impl fmt::Display for EnvironmentDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnvironmentDrift::NotInstalled { name, version } => {
+                write!(f, "- {} {} is locked but not installed", name, version)
+            }
+            EnvironmentDrift::NotLocked { name, version } => {
+                write!(f, "+ {} {} is installed but not locked", name, version)
+            }
+            EnvironmentDrift::VersionDiffers {
+                name,
+                locked,
+                installed,
+            } => write!(
+                f,
+                "~ {} is locked at {} but {} is installed",
+                name, locked, installed
+            ),
+            EnvironmentDrift::HashDiffers {
+                name,
+                version,
+                recorded,
+            } => write!(
+                f,
+                "! {} {} was installed from an archive with hash {}, which is not locked",
+                name, version, recorded
+            ),
+            EnvironmentDrift::CommitDiffers {
+                name,
+                locked,
+                installed,
+            } => write!(
+                f,
+                "! {} is locked at commit {} but {} is installed",
+                name, locked, installed
+            ),
+            EnvironmentDrift::FilesModified {
+                name,
+                version,
+                files,
+            } => write!(
+                f,
+                "! {} {} has {} file(s) changed since it was installed: {}",
+                name,
+                version,
+                files.len(),
+                files.join(", ")
+            ),
+        }
+    }
+}
+
+/// How the installed environment compares with a lockfile
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LockVerification {
+    /// Number of locked packages that were checked
+    pub checked: usize,
+    /// Every difference, sorted by package name; empty when the environment matches
+    pub drift: Vec<EnvironmentDrift>,
+    /// Locked packages installed at the locked version whose archive hash was
+    /// never recorded, so only their version and files could be checked
+    pub unverified: Vec<String>,
+}
+
+/// Compares the installed environment with a lockfile
+///
+/// The hash of the archive a package was installed from is taken from its
+/// origin in the registry, recorded from pip's installation report, or else
+/// from its `direct_url.json`. Installer tools such as pip are only reported
+/// as unlocked when the lockfile lists them.
+///
+/// # Arguments
+/// * `locked` - The lockfile
+/// * `installed` - Every installed distribution
+/// * `registry` - Reference to the package registry, holding the recorded archive hashes
+///
+/// # Returns
+/// * `LockVerification` - The differences and the packages whose hash could not be checked
+pub fn verify_environment(
+    locked: &Lockfile,
+    installed: &[InstalledDistribution],
+    registry: &PackageRegistry,
+) -> LockVerification {
+    let mut verification = LockVerification {
+        checked: locked.packages.len(),
+        ..LockVerification::default()
+    };
+    let installed_by_name: HashMap<String, &InstalledDistribution> = installed
+        .iter()
+        .map(|dist| (canonicalize_name(&dist.name), dist))
+        .collect();
+    let drift = &mut verification.drift;
+
+    for package in &locked.packages {
+        let Some(dist) = installed_by_name.get(&canonicalize_name(&package.name)) else {
+            drift.push(EnvironmentDrift::NotInstalled {
+                name: package.name.clone(),
+                version: package.version.clone(),
+            });
+            continue;
+        };
+        if compare_versions(&dist.version, &package.version) != Ordering::Equal {
+            drift.push(EnvironmentDrift::VersionDiffers {
+                name: package.name.clone(),
+                locked: package.version.clone(),
+                installed: dist.version.clone(),
+            });
+            continue;
+        }
+
+        match (&package.vcs, &dist.commit) {
+            (Some(vcs), Some(commit)) if *commit != vcs.commit_id => {
+                drift.push(EnvironmentDrift::CommitDiffers {
+                    name: package.name.clone(),
+                    locked: vcs.commit_id.clone(),
+                    installed: commit.clone(),
+                })
+            }
+            (Some(_), Some(_)) => {}
+            (Some(_), None) => verification.unverified.push(package.name.clone()),
+            (None, _) => {
+                let origin = registry
+                    .find_package_key(&package.name)
+                    .and_then(|key| registry.packages.get(&key))
+                    .filter(|registered| registered.version == dist.version)
+                    .and_then(|registered| registered.origin.as_ref()?.hash.clone())
+                    .and_then(|hash| {
+                        let (algorithm, digest) = hash.split_once(['=', ':'])?;
+                        (algorithm == "sha256").then(|| digest.to_string())
+                    });
+                match origin.or_else(|| dist.hashes.get("sha256").cloned()) {
+                    Some(recorded) if !package.sha256_hashes().contains(&recorded.as_str()) => {
+                        drift.push(EnvironmentDrift::HashDiffers {
+                            name: package.name.clone(),
+                            version: package.version.clone(),
+                            recorded: format!("sha256:{}", recorded),
+                        })
+                    }
+                    Some(_) => {}
+                    None => verification.unverified.push(package.name.clone()),
+                }
+            }
+        }
+
+        if !dist.modified.is_empty() {
+            drift.push(EnvironmentDrift::FilesModified {
+                name: package.name.clone(),
+                version: package.version.clone(),
+                files: dist.modified.clone(),
+            });
+        }
+    }
+
+    let locked_names: Vec<String> = locked
+        .packages
+        .iter()
+        .map(|package| canonicalize_name(&package.name))
+        .collect();
+    for (key, dist) in &installed_by_name {
+        if !locked_names.contains(key) && !SEED_PACKAGES.contains(&key.as_str()) {
+            drift.push(EnvironmentDrift::NotLocked {
+                name: dist.name.clone(),
+                version: dist.version.clone(),
+            });
+        }
+    }
+
+    drift.sort_by_key(|d| canonicalize_name(environment_drift_name(d)));
+    verification.unverified.sort();
+    verification
+}
+
+/// Package an environment drift entry is about
+fn environment_drift_name(drift: &EnvironmentDrift) -> &str {
+    match drift {
+        EnvironmentDrift::NotInstalled { name, .. }
+        | EnvironmentDrift::NotLocked { name, .. }
+        | EnvironmentDrift::VersionDiffers { name, .. }
+        | EnvironmentDrift::HashDiffers { name, .. }
+        | EnvironmentDrift::CommitDiffers { name, .. }
+        | EnvironmentDrift::FilesModified { name, .. } => name,
+    }
+}
+
+/// Checks the installed environment against a lockfile
+///
+/// Every installed file of a locked package is hashed and compared with its
+/// RECORD, see [`verify_environment`] for the rest.
+///
+/// # Arguments
+/// * `path` - The lockfile
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<LockVerification>` - How the environment compares, or an error if
+///   the lockfile could not be read or the environment could not be listed
+pub fn verify_lockfile(path: &Path, registry: &PackageRegistry) -> Result<LockVerification> {
+    let locked = Lockfile::load(path)?;
+    let python = get_python_executable()?;
+    let mut command = Command::new(&python);
+    command.arg("-c").arg(INSTALLED_LOCK_STATE_SCRIPT).args(
+        locked
+            .packages
+            .iter()
+            .map(|package| canonicalize_name(&package.name)),
+    );
+    let output = run_command(&mut command).map_err(|_| PackageError::PythonNotFound)?;
+    if !output.status.success() {
+        return Err(PackageError::MetadataError(format!(
+            "Failed to inspect the installed packages: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    let installed: Vec<InstalledDistribution> = serde_json::from_slice(&output.stdout)?;
+    Ok(verify_environment(&locked, &installed, registry))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package(name: &str, version: &str, url: &str, sha256: &str) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            sdist: None,
+            wheels: vec![LockedArchive {
+                url: url.to_string(),
+                hashes: BTreeMap::from([("sha256".to_string(), sha256.to_string())]),
+            }],
+            vcs: None,
+        }
+    }
+
+    fn lockfile(packages: Vec<LockedPackage>) -> Lockfile {
+        Lockfile {
+            lock_version: "1.0".to_string(),
+            created_by: "ppm".to_string(),
+            requires_python: None,
+            packages,
+        }
+    }
+
+    #[test]
+    fn test_check_lockfile_reports_drift() {
+        let locked = lockfile(vec![
+            package("click", "8.1.7", "https://files/click.whl", "aa"),
+            package("Flask", "3.0.2", "https://files/flask.whl", "bb"),
+            package("six", "1.16.0", "https://files/six.whl", "cc"),
+        ]);
+        let resolved = lockfile(vec![
+            package("click", "8.1.7", "https://files/click.whl", "a2"),
+            package("flask", "3.0.3", "https://files/flask-3.0.3.whl", "bd"),
+            package("idna", "3.7", "https://files/idna.whl", "dd"),
+        ]);
+
+        let drift: Vec<String> = check_lockfile(&locked, &resolved)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            drift,
+            [
+                "! click: hash of https://files/click.whl changed",
+                "~ flask is locked at 3.0.2 but resolves to 3.0.3",
+                "+ idna 3.7 is not locked",
+                "- six 1.16.0 is locked but no longer required",
+            ]
+        );
+        assert!(check_lockfile(&resolved, &resolved).is_empty());
+
+        // Another platform's wheel of the same release is not drift
+        let other_platform = lockfile(vec![package(
+            "click",
+            "8.1.7",
+            "https://files/click-win.whl",
+            "ff",
+        )]);
+        let locked = lockfile(vec![package(
+            "click",
+            "8.1.7",
+            "https://files/click.whl",
+            "aa",
+        )]);
+        assert!(check_lockfile(&locked, &other_platform).is_empty());
+    }
+
+    #[test]
+    fn test_lockfile_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(LOCK_FILE);
+        let lock = lockfile(vec![package(
+            "six",
+            "1.16.0",
+            "https://files/six.whl",
+            "cc",
+        )]);
+        lock.save(&path).unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(text.contains("lock-version = \"1.0\""));
+        assert!(text.contains("[[packages]]"));
+        assert_eq!(Lockfile::load(&path).unwrap(), lock);
+    }
+
+    #[test]
+    fn test_verify_environment() {
+        use crate::registry::{Package, PackageOrigin};
+
+        let locked = lockfile(vec![
+            package("click", "8.1.7", "https://files/click.whl", "aa"),
+            package("Flask", "3.0.2", "https://files/flask.whl", "bb"),
+            package("idna", "3.7", "https://files/idna.whl", "cc"),
+            package("six", "1.16.0", "https://files/six.whl", "dd"),
+            package("urllib3", "2.2.2", "https://files/urllib3.whl", "ee"),
+        ]);
+        let dist = |name: &str, version: &str, sha256: Option<&str>, modified: &[&str]| {
+            InstalledDistribution {
+                name: name.to_string(),
+                version: version.to_string(),
+                hashes: sha256
+                    .map(|digest| BTreeMap::from([("sha256".to_string(), digest.to_string())]))
+                    .unwrap_or_default(),
+                commit: None,
+                modified: modified.iter().map(|file| file.to_string()).collect(),
+            }
+        };
+        let installed = [
+            dist("click", "8.1.7", None, &[]),
+            dist("flask", "3.0.2", Some("bb"), &["flask/app.py"]),
+            dist("idna", "3.7", Some("c0"), &[]),
+            dist("urllib3", "2.2.3", None, &[]),
+            dist("pip", "24.0", None, &[]),
+            dist("rich", "13.7.1", None, &[]),
+        ];
+        let mut registry = PackageRegistry::new();
+        let mut click = Package::new("click".to_string(), "8.1.7".to_string());
+        click.origin = Some(PackageOrigin {
+            url: "https://files/click.whl".to_string(),
+            hash: Some("sha256=aa".to_string()),
+            direct: false,
+            commit: None,
+        });
+        registry.add_package(click);
+
+        let verification = verify_environment(&locked, &installed, &registry);
+        let drift: Vec<String> = verification.drift.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            drift,
+            [
+                "! Flask 3.0.2 has 1 file(s) changed since it was installed: flask/app.py",
+                "! idna 3.7 was installed from an archive with hash sha256:c0, which is not locked",
+                "+ rich 13.7.1 is installed but not locked",
+                "- six 1.16.0 is locked but not installed",
+                "~ urllib3 is locked at 2.2.2 but 2.2.3 is installed",
+            ]
+        );
+        assert_eq!(verification.checked, 5);
+        assert!(verification.unverified.is_empty());
+
+        registry.packages.clear();
+        let verification = verify_environment(&locked, &installed, &registry);
+        assert_eq!(verification.unverified, ["click"]);
+    }
+}
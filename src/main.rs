@@ -1,49 +1,510 @@
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use clap_complete::CompleteEnv;
+use console::Term;
+use dialoguer::{Confirm, Input, MultiSelect, Select};
+#[cfg(feature = "parallel")]
+use python_package_manager::configure_jobs;
 use python_package_manager::{
-    delete_package, install_from_requirements, install_from_requirements_parallel,
-    install_packages, install_packages_parallel, list_packages, load_packages, save_packages,
-    update_package, Cli, Commands, PackageError,
+    add_dependencies, apply_restore, apply_undo, apply_upgrade_plan, apply_upgrade_plan_parallel,
+    assume_yes, benchmark_install, build_project, bump_package, bundle_app, canonicalize_name,
+    check_lockfile, check_maintenance, ci_mode, clone_environment, container_assets,
+    convert_dependency_file, create_environment, create_offline_bundle, create_snapshot,
+    create_virtualenv, delete_packages, delete_snapshot, detail, did_you_mean, diff_package_sets,
+    discover_interpreters, emit_run_summary, enable_local_packages, environment_summaries,
+    error_label, explain_error_code, extraneous_packages, filter_history, find_interpreter,
+    find_similar_names, format_duration, format_requirements, generate_manpages, init_logging,
+    init_project, install_from_lockfile, install_from_requirements,
+    install_from_requirements_parallel, install_from_requirements_with_options, install_matrix,
+    install_offline_bundle, install_pyenv_version, install_with_options,
+    installed_snapshot_packages, json_output, list_console_scripts, list_packages_formatted,
+    list_snapshots, load_history, load_package_set, load_packages, load_packages_unverified,
+    load_snapshot, local_packages_requested, measure_package_sizes, notice, package_info,
+    packages_required_by, parse_change_range, parse_report_path, parse_report_spec,
+    parse_requirements_file, parse_update_targets, pin_requirements_file, pinned_python_version,
+    plan_removal, plan_restore, plan_undo, plan_upgrades, print_install_report,
+    print_package_sizes, print_upgrade_plan, profile_requirements, prompt_answer, prune_packages,
+    read_constraints_file, read_package_list, read_update_file, record_history, record_run,
+    registry_checksum, registry_key, release_changes, remove_dependencies, remove_environment,
+    remove_orphaned_packages, render_benchmark, render_build_summary, render_bundle_summary,
+    render_console_scripts, render_environments, render_error_explanation, render_history,
+    render_interpreters, render_json_result, render_matrix, render_offline_bundle_summary,
+    render_outdated, render_package_diff, render_package_info, render_release_changes,
+    render_restore_plan, render_stats, render_undo_plan, render_upgrade_plan_markdown,
+    render_vendor_summary, reporter, resolve_lockfile, resolve_package_patterns,
+    resolve_update_versions, resume_install, run_in_environment, run_in_workspace, run_plugin,
+    run_transaction, save_packages, select_environment, select_upgrades, send_webhook, serve,
+    set_assume_yes, set_ci_mode, set_color_choice, set_http_client, set_json_output,
+    set_metadata_ttl, set_package_policy, set_pinned, set_progress_mode, set_report_data,
+    set_reporter, set_unmaintained_after, set_verbosity, stale_packages, status, success_mark,
+    suggest_package_names, summarize_workspace, tracing, update_package, update_packages_parallel,
+    upgrade_choice_labels, upgrade_pip, vendor_install, verbosity, verify_lockfile, warm_cache,
+    warning, warnings_emitted, watch_files, write_container_assets, write_upgrade_report,
+    BuildTarget, BumpTarget, BundleAction, Cli, Commands, DependentsPolicy, DiffFormat, EnvAction,
+    GraphFormat, HistoryFormat, HumanReporter, InitFormat, InitOptions, InstallCheckpoint,
+    InstallOptions, InstallReport, InstallStrategy, JsonReporter, ListFormat, Lockfile,
+    MessageKind, OutdatedSummary, PackageError, PipAction, PipFeature, PipStatus, ProjectConfig,
+    PromptAnswer, PythonAction, QuietReporter, RegistryAction, SnapshotAction, SpecEdit,
+    StatsHistory, UpdatePolicies, UpgradePlan, Verbosity, PROJECT_CONFIG_FILE, PYTHON_VERSION_FILE,
+    REGISTRY_FILE,
 };
+#[cfg(feature = "progress")]
+use python_package_manager::{set_install_observer, ProgressBarObserver};
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 /// Main entry point for the Python Package Manager CLI
 ///
 /// Handles command parsing, package registry management, and error handling.
 /// Provides appropriate exit codes for different error conditions.
 fn main() {
+    // Answers shell completion requests (COMPLETE=bash ppm) before any other output
+    CompleteEnv::with_factory(Cli::command).complete();
+
     let args = Cli::parse();
+    set_color_choice(args.color);
+    set_verbosity(args.verbosity());
+    set_json_output(args.json);
+    set_progress_mode(args.progress);
+    set_ci_mode(args.ci_mode());
+    set_assume_yes(args.yes);
+    set_reporter(match (args.json, args.verbosity()) {
+        (true, verbosity) => Arc::new(JsonReporter::new(verbosity)),
+        (false, Verbosity::Quiet) => Arc::new(QuietReporter),
+        (false, verbosity) => Arc::new(HumanReporter::new(verbosity)),
+    });
+    #[cfg(feature = "progress")]
+    set_install_observer(Arc::new(ProgressBarObserver::new()));
+    apply_project_settings(args.jobs);
+    if let Err(e) = init_logging(args.log_file.as_deref()) {
+        report_error(&e);
+        process::exit(get_exit_code(&e));
+    }
+    if let Some(env) = &args.env {
+        let selected = match &args.command {
+            Commands::Env { .. } | Commands::Registry { .. } | Commands::VendorInstall { .. } => {
+                Err(PackageError::InvalidPackageSpec(format!(
+                    "--env does not apply to `ppm {}`, which works on every environment",
+                    command_label(&args.command)
+                )))
+            }
+            _ => select_environment(env),
+        };
+        if let Err(e) = selected {
+            report_error(&e);
+            process::exit(get_exit_code(&e));
+        }
+    }
+
+    if args.pypackages || (args.env.is_none() && local_packages_requested(Path::new("."))) {
+        match enable_local_packages(Path::new(".")) {
+            Ok(lib) => detail!("Using local packages in {}", lib.display()),
+            Err(e) => {
+                report_error(&e);
+                process::exit(get_exit_code(&e));
+            }
+        }
+    }
 
-    // Load package registry with error handling
-    let mut package_registry = match load_packages() {
+    // Plugins and programs started by `run` may change packages.json themselves,
+    // so they run before the registry is loaded and never see it overwritten
+    let passthrough = match &args.command {
+        Commands::External(plugin_args) => Some(run_plugin(plugin_args)),
+        Commands::Run { command } => Some(run_in_environment(command)),
+        _ => None,
+    };
+    if let Some(result) = passthrough {
+        match result {
+            Ok(code) => process::exit(code),
+            Err(e) => {
+                report_error(&e);
+                process::exit(get_exit_code(&e));
+            }
+        }
+    }
+
+    let started = Instant::now();
+    let command_label = command_label(&args.command);
+    tracing::info!(command = %command_label, "starting");
+
+    // Load package registry with error handling; rehashing is the way to accept
+    // contents that fail the integrity check, so it skips it
+    let loaded = match &args.command {
+        Commands::Registry {
+            action: RegistryAction::Rehash,
+        } => load_packages_unverified(),
+        _ => load_packages(),
+    };
+    let mut package_registry = match loaded {
         Ok(registry) => registry,
+        Err(e @ PackageError::RegistryIntegrity(_)) => {
+            report_error(&e);
+            process::exit(get_exit_code(&e));
+        }
         Err(e) => {
-            eprintln!("Error loading package registry: {}", e);
+            reporter().message(
+                MessageKind::Error,
+                &format!(
+                    "{} [{}] Failed to load package registry: {}",
+                    error_label(),
+                    e.code(),
+                    e
+                ),
+            );
             process::exit(1);
         }
     };
 
     // Execute the requested command
     let result = match args.command {
-        Commands::Install { packages, parallel } => {
-            handle_install_command(packages, parallel, &mut package_registry)
+        Commands::Install {
+            workspace: true,
+            parallel,
+            ..
+        } => handle_workspace_install(parallel),
+        Commands::Install {
+            packages,
+            requirements,
+            profile,
+            allow_similar_name,
+            python,
+            ..
+        } if !python.is_empty() => {
+            profile_or_requirements(profile, requirements).and_then(|requirements| {
+                handle_matrix_install(
+                    &python,
+                    packages,
+                    requirements,
+                    allow_similar_name,
+                    &package_registry,
+                )
+            })
+        }
+        Commands::Install {
+            packages,
+            requirements,
+            profile,
+            parallel,
+            atomic: true,
+            resume,
+            lock,
+            verify_attestations,
+            allow_similar_name,
+            pip_args,
+            ..
+        } => run_transaction(&mut package_registry, |registry| {
+            handle_install_command(
+                packages,
+                profile_or_requirements(profile, requirements)?,
+                parallel,
+                resume,
+                lock,
+                verify_attestations,
+                allow_similar_name,
+                pip_args,
+                registry,
+            )
+        }),
+        Commands::Install {
+            packages,
+            requirements,
+            profile,
+            parallel,
+            resume,
+            lock,
+            verify_attestations,
+            allow_similar_name,
+            pip_args,
+            ..
+        } => profile_or_requirements(profile, requirements).and_then(|requirements| {
+            handle_install_command(
+                packages,
+                requirements,
+                parallel,
+                resume,
+                lock,
+                verify_attestations,
+                allow_similar_name,
+                pip_args,
+                &mut package_registry,
+            )
+        }),
+        Commands::Sync {
+            workspace: true, ..
+        } => handle_workspace_sync(),
+        Commands::Sync {
+            requirements,
+            profile,
+            allow_similar_name,
+            ..
+        } => profile_or_requirements(profile, requirements).and_then(|requirements| {
+            handle_sync_command(
+                requirements.as_deref(),
+                allow_similar_name,
+                &mut package_registry,
+            )
+        }),
+        Commands::Watch {
+            requirements,
+            project,
+        } => handle_watch_command(requirements.as_deref(), project, &mut package_registry),
+        Commands::PinFile {
+            requirements,
+            latest,
+            dry_run,
+        } => handle_pin_file_command(requirements.as_deref(), latest, dry_run),
+        Commands::Add {
+            specs,
+            requirements,
+            optional,
+        } => handle_add_command(
+            &specs,
+            requirements.as_deref(),
+            optional.as_deref(),
+            &mut package_registry,
+        ),
+        Commands::Remove {
+            names,
+            requirements,
+            force,
+        } => handle_remove_command(
+            &names,
+            requirements.as_deref(),
+            force,
+            &mut package_registry,
+        ),
+        Commands::Delete {
+            names,
+            cascade,
+            force,
+        } => handle_delete_command(&names, cascade, force, &mut package_registry),
+        Commands::Update {
+            targets,
+            plan: true,
+            ..
+        } => handle_update_plan_command(&targets, &package_registry),
+        Commands::Update {
+            targets,
+            requirements,
+            pre,
+            plan: false,
+        } => handle_update_command(
+            &targets,
+            requirements.as_deref(),
+            pre,
+            &mut package_registry,
+        ),
+        Commands::Convert { from, to, force } => handle_convert_command(&from, &to, force),
+        Commands::Fmt {
+            requirements,
+            check,
+        } => handle_fmt_command(requirements.as_deref(), check),
+        Commands::Bump {
+            name,
+            minor,
+            patch,
+            to,
+            requirements,
+            pre,
+        } => {
+            let target = match (to, minor, patch) {
+                (Some(version), _, _) => BumpTarget::Version(version),
+                (None, true, _) => BumpTarget::Minor,
+                (None, _, true) => BumpTarget::Patch,
+                (None, false, false) => BumpTarget::Latest,
+            };
+            handle_bump_command(&name, &target, &requirements, pre, &mut package_registry)
         }
-        Commands::Delete { name } => handle_delete_command(&name, &mut package_registry),
-        Commands::Update { name, version } => {
-            handle_update_command(&name, &version, &mut package_registry)
+        Commands::Pin { names } => set_pinned(&names, true, &mut package_registry),
+        Commands::Unpin { names } => set_pinned(&names, false, &mut package_registry),
+        Commands::List {
+            outdated,
+            format,
+            sizes,
+            hashes,
+            profile,
+        } => handle_list_command(
+            outdated,
+            format,
+            sizes,
+            hashes,
+            profile.as_deref(),
+            &package_registry,
+        ),
+        Commands::Size { package } => handle_size_command(package, &package_registry),
+        Commands::Scripts { package } => handle_scripts_command(package.as_deref()),
+        Commands::Diff { a, b, format } => handle_diff_command(&a, &b, format),
+        Commands::Lock {
+            requirements,
+            output,
+            check,
+        } => handle_lock_command(requirements.as_deref(), &output, check),
+        Commands::Verify { lock } => handle_verify_command(&lock, &package_registry),
+        Commands::Graph { format, root } => {
+            handle_graph_command(format, root.as_deref(), &package_registry)
         }
-        Commands::List => handle_list_command(&package_registry),
+        Commands::History {
+            limit,
+            package,
+            format,
+        } => handle_history_command(limit, package.as_deref(), format),
+        Commands::Undo { dry_run } => handle_undo_command(dry_run, &mut package_registry),
+        Commands::Snapshot { action } => handle_snapshot_command(action, &mut package_registry),
+        Commands::Registry { action } => handle_registry_command(action, &package_registry),
+        Commands::Python { action } => handle_python_command(action),
+        Commands::Pip { action } => handle_pip_command(action),
+        Commands::Env { action } => handle_env_command(action, &mut package_registry),
+        Commands::Init {
+            name,
+            python,
+            format,
+            venv,
+        } => handle_init_command(name, python, format, venv),
+        Commands::Autoremove => handle_autoremove_command(&mut package_registry),
+        Commands::Prune { dry_run } => handle_prune_command(dry_run, &mut package_registry),
+        Commands::Explain { code } => handle_explain_command(&code),
+        Commands::WarmCache {
+            requirements,
+            wheels,
+        } => handle_warm_cache_command(requirements.as_deref(), wheels, &package_registry),
+        Commands::Benchmark {
+            requirements,
+            strategies,
+            python,
+        } => handle_benchmark_command(&requirements, strategies, python.as_deref()),
+        Commands::Containerize {
+            base,
+            lock,
+            output,
+            force,
+        } => handle_containerize_command(&base, &lock, &output, force, &package_registry),
+        Commands::Build {
+            path,
+            outdir,
+            sdist,
+            wheel,
+            no_isolation,
+        } => handle_build_command(&path, outdir.as_deref(), sdist, wheel, no_isolation),
+        Commands::Bundle { action } => handle_bundle_command(action, &mut package_registry),
+        Commands::VendorInstall {
+            requirements,
+            into,
+            clean,
+            pip_args,
+        } => handle_vendor_install_command(
+            requirements.as_deref(),
+            &into,
+            clean,
+            &pip_args,
+            &mut package_registry,
+        ),
+        Commands::Serve { host, port } => serve(&host, port),
+        Commands::External(_) | Commands::Run { .. } => {
+            unreachable!("passthrough commands run before the registry is loaded")
+        }
+        Commands::GenerateManpages { dir } => generate_manpages(&dir).map(|pages| {
+            for page in pages {
+                status!("Wrote {}", page.display());
+            }
+        }),
+        Commands::Stats => {
+            let history = StatsHistory::load();
+            if json_output() {
+                serde_json::to_value(&history)
+                    .map(set_report_data)
+                    .map_err(PackageError::from)
+            } else {
+                reporter().output(&render_stats(&history, unix_timestamp()));
+                Ok(())
+            }
+        }
+        Commands::Upgrade {
+            packages,
+            all,
+            dry_run,
+            report,
+            constraint,
+            interactive,
+            allow_major,
+        } => handle_upgrade_command(
+            packages,
+            all,
+            dry_run,
+            report,
+            constraint,
+            interactive,
+            allow_major,
+            &mut package_registry,
+        ),
+        Commands::Outdated { report, no_notify } => {
+            handle_outdated_command(report, no_notify, &package_registry)
+        }
+        Commands::Changes { package, range } => {
+            handle_changes_command(&package, &range, &package_registry)
+        }
+        Commands::Info { package } => handle_info_command(&package, &package_registry),
     };
 
-    // Handle command execution results
-    if let Err(e) = result {
-        eprintln!("Error: {}", e);
-        process::exit(get_exit_code(&e));
+    // Record timings even for failed runs so slow failures show up too
+    if let Err(e) = record_run(&command_label, started.elapsed()) {
+        warning!("Failed to record operation stats: {}", e);
+    }
+
+    // Handle command execution results, saving the registry only on success
+    let (mut exit_code, mut error) = match result {
+        Err(e) => {
+            report_error(&e);
+            (get_exit_code(&e), Some(e))
+        }
+        Ok(()) => match save_packages(&package_registry) {
+            Ok(()) => (0, None),
+            Err(e) => {
+                warning!("Failed to save package registry: {}", e);
+                (2, Some(e))
+            }
+        },
+    };
+
+    if let Err(e) = record_history(&command_label, exit_code, error.as_ref()) {
+        warning!("Failed to record operation history: {}", e);
+    }
+
+    if exit_code == 0 && args.deny_warnings && warnings_emitted() > 0 {
+        let e = PackageError::PolicyViolation(format!(
+            "{} warning(s) emitted with --deny-warnings",
+            warnings_emitted()
+        ));
+        report_error(&e);
+        exit_code = get_exit_code(&e);
+        error = Some(e);
     }
 
-    // Save package registry with error handling
-    if let Err(e) = save_packages(&package_registry) {
-        eprintln!("Warning: Failed to save package registry: {}", e);
-        process::exit(2);
+    let command_name = command_label.split(' ').next().unwrap_or_default();
+    tracing::info!(
+        command = %command_label,
+        exit_code,
+        duration_ms = started.elapsed().as_millis() as u64,
+        "finished"
+    );
+    emit_run_summary(command_name, exit_code, started.elapsed());
+    if json_output() {
+        println!(
+            "{}",
+            render_json_result(command_name, exit_code, error.as_ref())
+        );
+    } else if verbosity() == Verbosity::Quiet {
+        println!(
+            "status={} command={} exit_code={}",
+            if exit_code == 0 { "ok" } else { "error" },
+            command_name,
+            exit_code
+        );
+    }
+    if exit_code != 0 {
+        process::exit(exit_code);
     }
 }
 
@@ -51,122 +512,2549 @@ fn main() {
 ///
 /// # Arguments
 /// * `packages` - List of package specifications or requirements file
+/// * `requirements` - Requirements file given with `-r`
 /// * `parallel` - Whether to install packages in parallel
+/// * `resume` - Continue the last unfinished requirements file install instead
+/// * `lock` - Install exactly the packages of this lockfile instead, verifying their hashes
+/// * `verify_attestations` - Require verified PyPI attestations for every downloaded file
+/// * `allow_similar_name` - Install names resembling popular packages without asking
+/// * `pip_args` - Arguments given after `--`, passed to pip verbatim
 /// * `package_registry` - Mutable reference to the package registry
 ///
 /// # Returns
 /// * `Result<()>` - Success or error from installation
+#[allow(clippy::too_many_arguments)]
 fn handle_install_command(
     packages: Vec<String>,
+    requirements: Option<String>,
     parallel: bool,
+    resume: bool,
+    lock: Option<PathBuf>,
+    verify_attestations: bool,
+    allow_similar_name: bool,
+    mut pip_args: Vec<String>,
     package_registry: &mut python_package_manager::PackageRegistry,
 ) -> Result<(), PackageError> {
-    if packages.is_empty() {
-        eprintln!("Error: No packages specified for installation");
+    // A lockfile or a resumed install is checked for similar names like any other
+    if resume {
+        let specs = InstallCheckpoint::load().map_or_else(Vec::new, |c| c.specs);
+        if !confirm_similar_names(&specs, allow_similar_name, package_registry)? {
+            status!("Aborted: no packages were installed");
+            return Ok(());
+        }
+        return resume_install(package_registry, parallel).and_then(finish_install);
+    }
+    if let Some(lock) = lock {
+        let specs: Vec<String> = Lockfile::load(&lock)?
+            .packages
+            .iter()
+            .map(|package| format!("{}=={}", package.name, package.version))
+            .collect();
+        if !confirm_similar_names(&specs, allow_similar_name, package_registry)? {
+            status!("Aborted: no packages were installed");
+            return Ok(());
+        }
+        return install_from_lockfile(&lock, package_registry).and_then(finish_install);
+    }
+    let packages = expand_stdin_arg(packages)?;
+
+    // Check if this is a requirements file installation, also accepting "-- -r=FILE"
+    let requirements = match (requirements, packages.as_slice(), pip_args.as_slice()) {
+        (None, [], [only]) if only.starts_with("-r=") => {
+            let path = only["-r=".len()..].to_string();
+            pip_args.clear();
+            Some(path)
+        }
+        (requirements, _, _) => requirements,
+    };
+    if requirements.is_none() && packages.is_empty() {
         return Err(PackageError::InvalidPackageSpec(
             "No packages specified".to_string(),
         ));
     }
 
-    // Check if this is a requirements file installation
-    if packages.len() == 1 && packages[0].starts_with("-r=") {
-        let requirements_path = &packages[0][3..];
+    if let Some(requirements_path) = requirements.as_deref() {
         if requirements_path.is_empty() {
             return Err(PackageError::InvalidPackageSpec(
                 "Empty requirements file path".to_string(),
             ));
         }
 
-        println!("Installing from requirements file: {}", requirements_path);
-        if parallel {
-            install_from_requirements_parallel(requirements_path, package_registry)
-        } else {
-            install_from_requirements(requirements_path, package_registry)
+        let specs = parse_requirements_file(requirements_path)?;
+        if !confirm_similar_names(&specs, allow_similar_name, package_registry)? {
+            status!("Aborted: no packages were installed");
+            return Ok(());
         }
+
+        status!("Installing from requirements file: {}", requirements_path);
+        let options = InstallOptions::new()
+            .parallel(parallel)
+            .verify_attestations(verify_attestations)
+            .extra_pip_args(pip_args);
+        install_from_requirements_with_options(requirements_path, &options, package_registry)
+            .and_then(finish_install)
     } else {
         // Install individual packages
-        println!("Installing {} package(s)...", packages.len());
-        if parallel {
-            install_packages_parallel(&packages, package_registry)
+        if !confirm_similar_names(&packages, allow_similar_name, package_registry)? {
+            status!("Aborted: no packages were installed");
+            return Ok(());
+        }
+        status!("Installing {} package(s)...", packages.len());
+        let options = InstallOptions::new()
+            .parallel(parallel)
+            .verify_attestations(verify_attestations)
+            .extra_pip_args(pip_args);
+        install_with_options(&packages, &options, package_registry).and_then(finish_install)
+    }
+}
+
+/// Asks before installing packages named like popular ones (typosquatting)
+///
+/// The question is never answered by `--yes`: without a terminal, in CI mode
+/// or with `--yes`, only `--allow-similar-name` lets such a package through.
+///
+/// # Arguments
+/// * `specs` - The requirements about to be installed
+/// * `allow` - Whether `--allow-similar-name` was given
+/// * `package_registry` - Reference to the package registry, whose packages are not checked
+///
+/// # Returns
+/// * `Result<bool>` - Whether to go ahead, or a policy error when nobody can be asked
+fn confirm_similar_names(
+    specs: &[String],
+    allow: bool,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<bool, PackageError> {
+    let similar = find_similar_names(specs, package_registry);
+    if allow || similar.is_empty() {
+        return Ok(true);
+    }
+
+    for (name, popular) in &similar {
+        warning!("{} is named like the popular package {}", name, popular);
+    }
+    let names: Vec<&str> = similar.iter().map(|(name, _)| name.as_str()).collect();
+    // --yes does not vouch for a name nobody looked at
+    if prompt_answer(assume_yes(), ci_mode(), io::stdin().is_terminal()) != PromptAnswer::Ask {
+        return Err(PackageError::PolicyViolation(format!(
+            "{} may be typosquatting a popular package; check the spelling, or pass \
+             --allow-similar-name to install it anyway",
+            names.join(", ")
+        )));
+    }
+    confirm(&format!("Install {} anyway?", names.join(", ")))
+}
+
+/// Handles `install --python`, installing into several interpreters at once
+///
+/// # Arguments
+/// * `pythons` - Versions or interpreter paths
+/// * `packages` - Packages to install, unless a requirements file is given
+/// * `requirements` - Requirements file to install
+/// * `allow_similar_name` - Install names resembling popular packages without asking
+/// * `package_registry` - Reference to the package registry, which is left alone
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if any interpreter failed
+fn handle_matrix_install(
+    pythons: &[String],
+    packages: Vec<String>,
+    requirements: Option<String>,
+    allow_similar_name: bool,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let packages = match requirements {
+        Some(path) => parse_requirements_file(&path)?,
+        None => expand_stdin_arg(packages)?,
+    };
+    if packages.is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "No packages specified".to_string(),
+        ));
+    }
+    if !confirm_similar_names(&packages, allow_similar_name, package_registry)? {
+        status!("Aborted: no packages were installed");
+        return Ok(());
+    }
+
+    status!(
+        "Installing {} package(s) into Python {}...",
+        packages.len(),
+        pythons.join(", ")
+    );
+    let results = install_matrix(pythons, &packages);
+    if json_output() {
+        set_report_data(serde_json::Value::Array(
+            results
+                .iter()
+                .map(|result| {
+                    serde_json::json!({
+                        "python": result.python,
+                        "interpreter": result.interpreter.as_ref().map(|(path, _)| path),
+                        "version": result.interpreter.as_ref().map(|(_, version)| version),
+                        "packages": result.outcomes.iter().map(|o| serde_json::json!({
+                            "name": o.name,
+                            "version": o.new_version,
+                            "status": o.status(),
+                            "error": o.error,
+                        })).collect::<Vec<_>>(),
+                        "error": result.error,
+                        "millis": result.millis,
+                    })
+                })
+                .collect(),
+        ));
+    }
+    reporter().output(&render_matrix(&results));
+
+    let failed = results.iter().filter(|result| !result.is_success()).count();
+    if failed == 0 {
+        Ok(())
+    } else if failed < results.len() {
+        Err(PackageError::PartialFailure(
+            "interpreters failed to install the packages".to_string(),
+            failed,
+            results.len(),
+        ))
+    } else {
+        Err(PackageError::InstallationFailed(format!(
+            "{} interpreters failed to install the packages",
+            failed
+        )))
+    }
+}
+
+/// Presents the report of an install, update or upgrade and turns its failures into an error
+///
+/// Packages the operation brought in are checked for signs of abandonment,
+/// which are presented as warnings of the report.
+///
+/// # Arguments
+/// * `report` - The report returned by the library
+///
+/// # Returns
+/// * `Result<()>` - Success, or the error for the packages that failed
+fn finish_install(mut report: InstallReport) -> Result<(), PackageError> {
+    // Flag abandonware as it enters the environment, not packages that were already there
+    let added: Vec<String> = report
+        .outcomes
+        .iter()
+        .filter(|outcome| matches!(outcome.status(), "installed" | "upgraded" | "downgraded"))
+        .map(|outcome| outcome.name.clone())
+        .collect();
+    report
+        .warnings
+        .extend(check_maintenance(&added).iter().map(ToString::to_string));
+    print_install_report(&report);
+    report.into_result()
+}
+
+/// Installs the requirements of every workspace member
+///
+/// # Arguments
+/// * `parallel` - Whether each member installs its packages in parallel
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if any member failed
+fn handle_workspace_install(parallel: bool) -> Result<(), PackageError> {
+    let root = std::env::current_dir()?;
+    let reports = run_in_workspace(&root, |member, registry| {
+        let requirements = member.requirements();
+        if !Path::new(requirements).exists() {
+            status!("No {}; nothing to install", requirements);
+            return Ok(());
+        }
+        let report = if parallel {
+            install_from_requirements_parallel(requirements, registry)
         } else {
-            install_packages(&packages, package_registry)
+            install_from_requirements(requirements, registry)
+        };
+        report.and_then(finish_install)
+    })?;
+    summarize_workspace(&reports, "install")
+}
+
+/// Handles the benchmark command
+///
+/// # Arguments
+/// * `requirements` - Requirements file whose packages are installed
+/// * `strategies` - Strategies to time; every one when empty
+/// * `python` - Python version or interpreter the environments are created with
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the file could not be read or no
+///   environment could be created
+fn handle_benchmark_command(
+    requirements: &str,
+    mut strategies: Vec<InstallStrategy>,
+    python: Option<&str>,
+) -> Result<(), PackageError> {
+    let packages = parse_requirements_file(requirements)?;
+    if packages.is_empty() {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "No packages listed in {}",
+            requirements
+        )));
+    }
+    let python = python
+        .map(find_interpreter)
+        .transpose()?
+        .map(|(executable, _)| executable);
+    if strategies.is_empty() {
+        strategies = InstallStrategy::ALL.to_vec();
+    }
+
+    let results = benchmark_install(&packages, &strategies, python.as_deref())?;
+    reporter().output(&render_benchmark(&results));
+    set_report_data(serde_json::to_value(&results)?);
+    Ok(())
+}
+
+/// Handles the containerize command
+///
+/// Pins the lockfile's packages when it exists, and the registry's otherwise.
+///
+/// # Arguments
+/// * `base` - Base image of the Dockerfile
+/// * `lock` - The lockfile to pin from, if it exists
+/// * `output` - Directory to write the files into
+/// * `force` - Whether to replace existing files
+/// * `package_registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if hashes could not be looked up or a file exists
+fn handle_containerize_command(
+    base: &str,
+    lock: &Path,
+    output: &Path,
+    force: bool,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let lockfile = if lock.exists() {
+        status!("Pinning the packages of {}...", lock.display());
+        Some(Lockfile::load(lock)?)
+    } else {
+        status!(
+            "No {}; pinning the registered packages and looking up their hashes...",
+            lock.display()
+        );
+        None
+    };
+    let assets = container_assets(lockfile.as_ref(), package_registry, base)?;
+    for spec in &assets.unhashed {
+        warning!(
+            "{} has no hashes; the image installs it without verification",
+            spec
+        );
+    }
+
+    let written = write_container_assets(&assets, output, force)?;
+    set_report_data(serde_json::to_value(&written)?);
+    for path in &written {
+        status!("{} Wrote {}", success_mark(), path.display());
+    }
+    notice!(
+        "Build the image with `docker build -t <name> {}`",
+        output.display()
+    );
+    Ok(())
+}
+
+/// Handles the build command
+///
+/// # Arguments
+/// * `path` - The project directory
+/// * `outdir` - Where to write the distributions, if not the project's dist/
+/// * `sdist` - Whether to build only the source distribution
+/// * `wheel` - Whether to build only the wheel
+/// * `no_isolation` - Whether to skip the isolated build environment
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the build failed
+fn handle_build_command(
+    path: &Path,
+    outdir: Option<&Path>,
+    sdist: bool,
+    wheel: bool,
+    no_isolation: bool,
+) -> Result<(), PackageError> {
+    let target = match (sdist, wheel) {
+        (true, _) => BuildTarget::Sdist,
+        (_, true) => BuildTarget::Wheel,
+        _ => BuildTarget::Both,
+    };
+    let summary = build_project(path, outdir, target, no_isolation)?;
+    set_report_data(serde_json::to_value(&summary)?);
+    reporter().output(&render_build_summary(&summary));
+    Ok(())
+}
+
+/// Handles the bundle command
+///
+/// # Arguments
+/// * `action` - The bundle action to perform
+/// * `package_registry` - Mutable reference to the package registry, pinned from
+///   without a lockfile and updated by `bundle install`
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the bundle could not be built or installed
+fn handle_bundle_command(
+    action: BundleAction,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    match action {
+        BundleAction::App {
+            entry,
+            output,
+            shebang,
+            lock,
+        } => {
+            let lockfile = if lock.exists() {
+                status!("Pinning the packages of {}...", lock.display());
+                Some(Lockfile::load(&lock)?)
+            } else {
+                status!(
+                    "No {}; pinning the registered packages and looking up their hashes...",
+                    lock.display()
+                );
+                None
+            };
+            let summary = bundle_app(
+                &entry,
+                &output,
+                &shebang,
+                lockfile.as_ref(),
+                package_registry,
+                Path::new("."),
+            )?;
+            set_report_data(serde_json::to_value(&summary)?);
+            reporter().output(&render_bundle_summary(&summary));
+            notice!("Run it with `python {}`", output.display());
+            Ok(())
         }
+        BundleAction::Create { output, lock } => {
+            let lockfile = if lock.exists() {
+                status!("Bundling the packages of {}...", lock.display());
+                Some(Lockfile::load(&lock)?)
+            } else {
+                status!(
+                    "No {}; bundling the registered packages and looking up their hashes...",
+                    lock.display()
+                );
+                None
+            };
+            let summary =
+                create_offline_bundle(&output, lockfile.as_ref(), &lock, package_registry)?;
+            set_report_data(serde_json::to_value(&summary)?);
+            reporter().output(&render_offline_bundle_summary(&summary));
+            notice!(
+                "Install it offline with `ppm bundle install {} --sha256 {}`",
+                output.display(),
+                summary.sha256
+            );
+            Ok(())
+        }
+        BundleAction::Install {
+            bundle,
+            sha256,
+            insecure_no_verify,
+        } => install_offline_bundle(
+            &bundle,
+            sha256.as_deref(),
+            insecure_no_verify,
+            package_registry,
+        )
+        .and_then(finish_install),
     }
 }
 
-/// Handles the delete command
+/// Handles the vendor-install command
 ///
 /// # Arguments
-/// * `name` - Name of the package to delete
+/// * `requirements` - Requirements file given on the command line
+/// * `into` - The vendored directory
+/// * `clean` - Whether to remove every vendored package first
+/// * `pip_args` - Arguments passed to pip verbatim
 /// * `package_registry` - Mutable reference to the package registry
 ///
 /// # Returns
-/// * `Result<()>` - Success or error from deletion
-fn handle_delete_command(
-    name: &str,
+/// * `Result<()>` - Success, or an error if pip failed or files could not be removed
+fn handle_vendor_install_command(
+    requirements: Option<&str>,
+    into: &Path,
+    clean: bool,
+    pip_args: &[String],
     package_registry: &mut python_package_manager::PackageRegistry,
 ) -> Result<(), PackageError> {
-    if name.trim().is_empty() {
-        return Err(PackageError::InvalidPackageSpec(
-            "Package name cannot be empty".to_string(),
-        ));
+    let summary = vendor_install(requirements, into, clean, pip_args, package_registry)?;
+    set_report_data(serde_json::to_value(&summary)?);
+    reporter().output(&render_vendor_summary(&summary));
+    Ok(())
+}
+
+/// Handles the lock command
+///
+/// Resolves the project's requirements from scratch and writes the result,
+/// or with `check` compares it against the existing lockfile and fails when
+/// they differ, leaving every file untouched.
+///
+/// # Arguments
+/// * `requirements` - Requirements file given on the command line
+/// * `output` - The lockfile to write or check
+/// * `check` - Whether to only check the lockfile
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if resolving failed or the lockfile is stale
+fn handle_lock_command(
+    requirements: Option<&str>,
+    output: &Path,
+    check: bool,
+) -> Result<(), PackageError> {
+    let config = ProjectConfig::load(Path::new("."))?.unwrap_or_default();
+    let requirements = requirements
+        .map(str::to_string)
+        .or(config.requirements)
+        .unwrap_or_else(|| "requirements.txt".to_string());
+
+    status!("Resolving {}...", requirements);
+    let resolved = resolve_lockfile(&requirements, config.requires_python.as_deref())?;
+    if !check {
+        resolved.save(output)?;
+        set_report_data(serde_json::to_value(&resolved.packages)?);
+        status!(
+            "{} Locked {} package(s) in {}",
+            success_mark(),
+            resolved.packages.len(),
+            output.display()
+        );
+        return Ok(());
     }
 
-    println!("Deleting package: {}", name);
-    delete_package(name, package_registry)
+    let locked = Lockfile::load(output)?;
+    let drift = check_lockfile(&locked, &resolved);
+    set_report_data(serde_json::to_value(&drift)?);
+    if drift.is_empty() {
+        status!("{} {} is up to date", success_mark(), output.display());
+        return Ok(());
+    }
+    for entry in &drift {
+        notice!("  {}", entry);
+    }
+    Err(PackageError::PolicyViolation(format!(
+        "{} is out of date with {} ({} difference(s)); run `ppm lock` to update it",
+        output.display(),
+        requirements,
+        drift.len()
+    )))
 }
 
-/// Handles the update command
+/// Handles the verify command
+///
+/// # Arguments
+/// * `lock` - The lockfile the environment must match
+/// * `package_registry` - Reference to the package registry, holding the recorded archive hashes
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the environment has drifted from the lockfile
+fn handle_verify_command(
+    lock: &Path,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    status!("Verifying the environment against {}...", lock.display());
+    let verification = verify_lockfile(lock, package_registry)?;
+    set_report_data(serde_json::to_value(&verification)?);
+    if !verification.unverified.is_empty() {
+        notice!(
+            "No archive hash was recorded for {}; only their versions and files were checked",
+            verification.unverified.join(", ")
+        );
+    }
+    if verification.drift.is_empty() {
+        status!(
+            "{} The environment matches {} ({} package(s))",
+            success_mark(),
+            lock.display(),
+            verification.checked
+        );
+        return Ok(());
+    }
+    for entry in &verification.drift {
+        notice!("  {}", entry);
+    }
+    Err(PackageError::PolicyViolation(format!(
+        "The environment has drifted from {} ({} difference(s)); run `ppm install --lock` to restore it",
+        lock.display(),
+        verification.drift.len()
+    )))
+}
+
+/// Handles the convert command
+///
+/// # Arguments
+/// * `from` - File to read
+/// * `to` - File to write
+/// * `force` - Whether to replace an existing target
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if a file could not be read, parsed or written
+fn handle_convert_command(from: &Path, to: &Path, force: bool) -> Result<(), PackageError> {
+    let project = match ProjectConfig::load(Path::new("."))? {
+        Some(config) if !config.name.is_empty() => config.name,
+        _ => std::env::current_dir()?
+            .file_name()
+            .map(|name| canonicalize_name(&name.to_string_lossy()))
+            .unwrap_or_else(|| "project".to_string()),
+    };
+    let notes = convert_dependency_file(from, to, &project, force)?;
+    for note in &notes {
+        warning!("{}", note);
+    }
+    set_report_data(serde_json::json!({
+        "from": from,
+        "to": to,
+        "untranslated": notes,
+    }));
+    status!(
+        "{} Converted {} to {}",
+        success_mark(),
+        from.display(),
+        to.display()
+    );
+    Ok(())
+}
+
+/// Handles the fmt command
 ///
 /// # Arguments
-/// * `name` - Name of the package to update
-/// * `version` - Target version for the update
+/// * `requirements` - Requirements file given on the command line
+/// * `check` - Whether to only check the formatting, leaving the file untouched
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the file could not be read or
+///   written, or is not formatted in check mode
+fn handle_fmt_command(requirements: Option<&str>, check: bool) -> Result<(), PackageError> {
+    let path = requirements_path(requirements)?;
+    let contents = std::fs::read_to_string(&path)?;
+    let formatted = format_requirements(&contents);
+    let changed = formatted != contents;
+    set_report_data(serde_json::json!({ "file": path, "changed": changed }));
+
+    if !changed {
+        status!("{} {} is formatted", success_mark(), path);
+        return Ok(());
+    }
+    if check {
+        return Err(PackageError::PolicyViolation(format!(
+            "{} is not formatted; run `ppm fmt` to fix it",
+            path
+        )));
+    }
+    std::fs::write(&path, formatted)?;
+    status!("{} Formatted {}", success_mark(), path);
+    Ok(())
+}
+
+/// Handles the bump command
+///
+/// # Arguments
+/// * `name` - The package to bump
+/// * `target` - How far to move it
+/// * `requirements` - Requirements files given on the command line
+/// * `pre` - Whether pre-releases may be selected
 /// * `package_registry` - Mutable reference to the package registry
 ///
 /// # Returns
-/// * `Result<()>` - Success or error from update
-fn handle_update_command(
+/// * `Result<()>` - Success, or an error if resolving, installing or editing failed
+fn handle_bump_command(
     name: &str,
-    version: &str,
+    target: &BumpTarget,
+    requirements: &[String],
+    pre: bool,
     package_registry: &mut python_package_manager::PackageRegistry,
 ) -> Result<(), PackageError> {
-    if name.trim().is_empty() || version.trim().is_empty() {
-        return Err(PackageError::InvalidPackageSpec(
-            "Package name and version cannot be empty".to_string(),
-        ));
+    let mut files: Vec<PathBuf> = if requirements.is_empty() {
+        vec![PathBuf::from(requirements_path(None)?)]
+    } else {
+        requirements.iter().map(PathBuf::from).collect()
+    };
+    files.push(PathBuf::from("pyproject.toml"));
+    files.retain(|file| file.exists());
+
+    let bump = bump_package(name, target, &files, pre, package_registry)?;
+    if !bump.install.outcomes.is_empty() {
+        print_install_report(&bump.install);
     }
+    bump.install.into_result()?;
 
-    println!("Updating package {} to version {}", name, version);
-    update_package(name, version, package_registry)
+    match &bump.previous {
+        Some(previous) if *previous == bump.version => {
+            status!(
+                "{} {} is already at {}",
+                success_mark(),
+                bump.name,
+                bump.version
+            )
+        }
+        Some(previous) => status!(
+            "{} Bumped {} from {} to {}",
+            success_mark(),
+            bump.name,
+            previous,
+            bump.version
+        ),
+        None => status!(
+            "{} Installed {} {}",
+            success_mark(),
+            bump.name,
+            bump.version
+        ),
+    }
+    for (path, edit) in &bump.edits {
+        notice!(
+            "  {}:{}: {} -> {}",
+            path.display(),
+            edit.line,
+            edit.before,
+            edit.after
+        );
+    }
+    if bump.edits.is_empty() {
+        status!("No requirement of {} needed changing", bump.name);
+    }
+    set_report_data(serde_json::json!({
+        "name": bump.name,
+        "previous": bump.previous,
+        "version": bump.version,
+        "edits": bump
+            .edits
+            .iter()
+            .map(|(path, edit)| serde_json::json!({
+                "file": path,
+                "line": edit.line,
+                "before": edit.before,
+                "after": edit.after,
+            }))
+            .collect::<Vec<_>>(),
+    }));
+    Ok(())
 }
 
-/// Handles the list command
+/// Handles the add command
+///
+/// Declares the requirements in pyproject.toml when it has a `[project]`
+/// table, and in the requirements file otherwise or when one is given.
 ///
 /// # Arguments
-/// * `package_registry` - Reference to the package registry
+/// * `specs` - The requirements to add
+/// * `requirements` - Requirements file given on the command line
+/// * `optional` - Group of optional dependencies to declare them in
+/// * `package_registry` - Mutable reference to the package registry
 ///
 /// # Returns
-/// * `Result<()>` - Always succeeds for list command
-fn handle_list_command(
-    package_registry: &python_package_manager::PackageRegistry,
+/// * `Result<()>` - Success, or an error if the install failed or a file could not be written
+fn handle_add_command(
+    specs: &[String],
+    requirements: Option<&str>,
+    optional: Option<&str>,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let pyproject = Path::new("pyproject.toml");
+    let declares_project = std::fs::read_to_string(pyproject)
+        .is_ok_and(|contents| contents.lines().any(|line| line.trim() == "[project]"));
+    let file = if optional.is_some() || (requirements.is_none() && declares_project) {
+        pyproject.to_path_buf()
+    } else {
+        PathBuf::from(requirements_path(requirements)?)
+    };
+
+    let report = add_dependencies(specs, &file, optional, package_registry)?;
+    if !report.install.outcomes.is_empty() {
+        print_install_report(&report.install);
+    }
+    report.install.into_result()?;
+    report_declaration_edits(&report.edits);
+    if report.edits.is_empty() {
+        status!("{} already declares every requirement", file.display());
+    }
+    Ok(())
+}
+
+/// Handles the remove command
+///
+/// # Arguments
+/// * `names` - The packages to remove
+/// * `requirements` - Requirements file given on the command line
+/// * `force` - Whether to remove packages other packages depend on
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if uninstalling failed or a file could not be written
+fn handle_remove_command(
+    names: &[String],
+    requirements: Option<&str>,
+    force: bool,
+    package_registry: &mut python_package_manager::PackageRegistry,
 ) -> Result<(), PackageError> {
-    list_packages(package_registry);
+    let mut files = vec![PathBuf::from("pyproject.toml")];
+    files.push(PathBuf::from(requirements_path(requirements)?));
+    files.retain(|file| file.exists());
+    let policy = if force {
+        DependentsPolicy::Force
+    } else {
+        DependentsPolicy::Refuse
+    };
+
+    let report = remove_dependencies(names, &files, policy, package_registry)?;
+    report_declaration_edits(&report.edits);
     Ok(())
 }
 
-/// Maps package errors to appropriate exit codes
+/// Prints the requirements the add and remove commands wrote and records them for --json
+fn report_declaration_edits(edits: &[(PathBuf, SpecEdit)]) {
+    for (path, edit) in edits {
+        let change = match (edit.before.is_empty(), edit.after.is_empty()) {
+            (true, _) => format!("+ {}", edit.after),
+            (_, true) => format!("- {}", edit.before),
+            _ => format!("{} -> {}", edit.before, edit.after),
+        };
+        notice!("  {}:{}: {}", path.display(), edit.line, change);
+    }
+    set_report_data(serde_json::json!({
+        "edits": edits
+            .iter()
+            .map(|(path, edit)| serde_json::json!({
+                "file": path,
+                "line": edit.line,
+                "before": edit.before,
+                "after": edit.after,
+            }))
+            .collect::<Vec<_>>(),
+    }));
+}
+
+/// Handles the pin-file command
+///
+/// # Arguments
+/// * `requirements` - Requirements file given on the command line
+/// * `latest` - Whether to pin to the latest releases instead of the installed versions
+/// * `dry_run` - Whether to only show the new pins
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the file could not be read or written
+fn handle_pin_file_command(
+    requirements: Option<&str>,
+    latest: bool,
+    dry_run: bool,
+) -> Result<(), PackageError> {
+    let path = requirements_path(requirements)?;
+    let pinned = pin_requirements_file(&path, latest, !dry_run)?;
+
+    for change in &pinned.changes {
+        let previous = if change.previous.is_empty() {
+            "(unpinned)"
+        } else {
+            change.previous.as_str()
+        };
+        notice!("  {}: {} -> =={}", change.name, previous, change.version);
+    }
+    for (name, reason) in &pinned.skipped {
+        warning!("Left {} as it is: {}", name, reason);
+    }
+    set_report_data(serde_json::json!({
+        "file": path,
+        "changes": pinned
+            .changes
+            .iter()
+            .map(|change| serde_json::json!({
+                "name": change.name,
+                "previous": change.previous,
+                "version": change.version,
+            }))
+            .collect::<Vec<_>>(),
+        "skipped": pinned.skipped.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        "written": !dry_run && !pinned.changes.is_empty(),
+    }));
+
+    if pinned.changes.is_empty() {
+        status!("{} No pins to change in {}", success_mark(), path);
+    } else if dry_run {
+        status!(
+            "Would pin {} requirement(s) in {} (dry run)",
+            pinned.changes.len(),
+            path
+        );
+    } else {
+        status!(
+            "{} Pinned {} requirement(s) in {}",
+            success_mark(),
+            pinned.changes.len(),
+            path
+        );
+    }
+    Ok(())
+}
+
+/// Handles the sync command for the current project
+///
+/// # Arguments
+/// * `requirements` - Requirements file given on the command line
+/// * `allow_similar_name` - Install names resembling popular packages without asking
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error from installing or removing packages
+fn handle_sync_command(
+    requirements: Option<&str>,
+    allow_similar_name: bool,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let requirements = requirements_path(requirements)?;
+    let specs = parse_requirements_file(&requirements)?;
+    if !confirm_similar_names(&specs, allow_similar_name, package_registry)? {
+        status!("Aborted: the environment was not synced");
+        return Ok(());
+    }
+    sync_with_requirements(&requirements, package_registry)
+}
+
+/// The requirements file to sync: the given one, else the one in ppm.toml, else requirements.txt
+fn requirements_path(requirements: Option<&str>) -> Result<String, PackageError> {
+    let configured = ProjectConfig::load(Path::new("."))?.and_then(|c| c.requirements);
+    Ok(requirements
+        .map(str::to_string)
+        .or(configured)
+        .unwrap_or_else(|| "requirements.txt".to_string()))
+}
+
+/// Handles the watch command
+///
+/// Syncs once, then again whenever the requirements file changes. With
+/// `project`, ppm.toml is watched too and the requirements file it names is
+/// followed. A failed sync is reported and the watch goes on.
+///
+/// # Arguments
+/// * `requirements` - Requirements file given on the command line
+/// * `project` - Whether to watch ppm.toml as well
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - An error if the files could not be watched
+fn handle_watch_command(
+    requirements: Option<&str>,
+    project: bool,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let mut requirements = requirements_path(requirements)?;
+    let watched = |requirements: &str| {
+        let mut paths = vec![PathBuf::from(requirements)];
+        if project {
+            paths.push(PathBuf::from(PROJECT_CONFIG_FILE));
+        }
+        paths
+    };
+    let announce = |paths: &[PathBuf]| {
+        let names: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+        status!(
+            "Watching {} for changes (Ctrl-C to stop)",
+            names.join(" and ")
+        );
+    };
+
+    sync_and_show_changes(&requirements, package_registry);
+    announce(&watched(&requirements));
+    watch_files(watched(&requirements), |changed| {
+        for path in changed {
+            status!("{} changed", path.display());
+        }
+        if project {
+            match requirements_path(None) {
+                Ok(path) => requirements = path,
+                Err(e) => report_error(&e),
+            }
+        }
+        sync_and_show_changes(&requirements, package_registry);
+        let paths = watched(&requirements);
+        announce(&paths);
+        Ok(paths)
+    })
+}
+
+/// Syncs with a requirements file, saves the registry and prints how the environment changed
+fn sync_and_show_changes(
+    requirements: &str,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) {
+    let before = load_package_set("env");
+    let synced = sync_with_requirements(requirements, package_registry)
+        .and_then(|()| save_packages(package_registry));
+    if let Err(e) = synced {
+        report_error(&e);
+        return;
+    }
+    if let (Ok(before), Ok(after)) = (before, load_package_set("env")) {
+        reporter().output(&render_package_diff(&diff_package_sets(&before, &after)));
+    }
+}
+
+/// Syncs every workspace member with its requirements file
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if any member failed
+fn handle_workspace_sync() -> Result<(), PackageError> {
+    let root = std::env::current_dir()?;
+    let reports = run_in_workspace(&root, |member, registry| {
+        sync_with_requirements(member.requirements(), registry)
+    })?;
+    summarize_workspace(&reports, "sync")
+}
+
+/// Installs a requirements file, then removes explicit packages it no longer lists
+///
+/// Removals are confirmed first and take the dependencies nothing else needs with them.
+///
+/// # Arguments
+/// * `requirements` - Path to the requirements file
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error from installing or removing packages
+fn sync_with_requirements(
+    requirements: &str,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    finish_install(install_from_requirements(requirements, package_registry)?)?;
+
+    let extras = extraneous_packages(requirements, package_registry)?;
+    if extras.is_empty() {
+        status!("{} Environment matches {}", success_mark(), requirements);
+        return Ok(());
+    }
+
+    notice!("The following packages are not listed in {}:", requirements);
+    for name in &extras {
+        let version = package_registry
+            .get_package(name)
+            .map_or("unknown", |p| p.version.as_str());
+        notice!("  {} @ {}", name, version);
+    }
+    if !confirm(&format!("Remove {} package(s)?", extras.len()))? {
+        status!("Aborted: no packages were removed");
+        return Ok(());
+    }
+
+    delete_packages(&extras, DependentsPolicy::Force, package_registry)?;
+    let orphans = package_registry.orphaned_packages();
+    remove_orphaned_packages(&orphans, package_registry)
+}
+
+/// Handles the delete command
+///
+/// # Arguments
+/// * `names` - Names or glob patterns of the packages to delete
+/// * `cascade` - Whether to also delete packages that depend on them
+/// * `force` - Whether to delete them even if other packages depend on them
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error from deletion
+fn handle_delete_command(
+    names: &[String],
+    cascade: bool,
+    force: bool,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let names = &expand_stdin_arg(names.to_vec())?;
+    if names.is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "No packages specified".to_string(),
+        ));
+    }
+    let resolved = resolve_package_patterns(names, package_registry)?;
+
+    let policy = if cascade {
+        DependentsPolicy::Cascade
+    } else if force {
+        DependentsPolicy::Force
+    } else {
+        DependentsPolicy::Refuse
+    };
+
+    // Refuses a doomed batch before anyone is asked to confirm it
+    let plan = plan_removal(&resolved, policy, package_registry)?;
+
+    for name in names.iter().filter(|n| !n.contains(['*', '?', '['])) {
+        if package_registry.find_package_key(name).is_some() {
+            continue;
+        }
+        let registered = package_registry.packages.keys().map(String::as_str);
+        if let Some(hint) = did_you_mean(&suggest_package_names(name, registered)) {
+            warning!("{} is not in the registry; {}", name, hint);
+        }
+    }
+
+    if plan.needs_confirmation() {
+        notice!("The following packages will be removed:");
+        let version = |name: &str| {
+            package_registry
+                .get_package(name)
+                .map_or("unknown", |p| p.version.as_str())
+        };
+        for name in &plan.packages {
+            notice!("  {} @ {}", name, version(name));
+        }
+        for name in &plan.dependents {
+            notice!(
+                "  {} @ {} (depends on a removed package)",
+                name,
+                version(name)
+            );
+        }
+
+        let count = plan.packages.len() + plan.dependents.len();
+        if !confirm(&format!("Remove {} package(s)?", count))? {
+            status!("Aborted: no packages were removed");
+            return Ok(());
+        }
+    }
+
+    delete_packages(&plan.packages, policy, package_registry)
+}
+
+/// Replaces a `-` argument with the package specs piped to stdin
+///
+/// # Arguments
+/// * `args` - Package arguments as given on the command line
+///
+/// # Returns
+/// * `Result<Vec<String>>` - The arguments with the piped specs in place of `-`,
+///   or an error if stdin is a terminal or could not be read
+fn expand_stdin_arg(args: Vec<String>) -> Result<Vec<String>, PackageError> {
+    if !args.iter().any(|arg| arg == "-") {
+        return Ok(args);
+    }
+    if io::stdin().is_terminal() {
+        return Err(PackageError::InvalidPackageSpec(
+            "\"-\" reads packages from stdin, but stdin is a terminal".to_string(),
+        ));
+    }
+    let piped = read_package_list(io::stdin().lock())?;
+    let mut expanded = Vec::with_capacity(args.len() + piped.len());
+    for arg in args {
+        if arg == "-" {
+            expanded.extend(piped.iter().cloned());
+        } else {
+            expanded.push(arg);
+        }
+    }
+    Ok(expanded)
+}
+
+/// Handles the update command
+///
+/// # Arguments
+/// * `targets` - Package name and version, or several `name==version` pairs
+/// * `requirements` - Optional file with additional `name==version` pairs
+/// * `pre` - Whether pre-releases may be selected for packages without a version
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error from update
+fn handle_update_command(
+    targets: &[String],
+    requirements: Option<&str>,
+    pre: bool,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let mut targets = parse_update_targets(targets)?;
+    if let Some(path) = requirements {
+        targets.extend(
+            read_update_file(path)?
+                .into_iter()
+                .map(|(name, version)| (name, Some(version))),
+        );
+    }
+    let updates = resolve_update_versions(targets, pre, package_registry)?;
+
+    match updates.as_slice() {
+        [] => Err(PackageError::InvalidPackageSpec(
+            "No packages specified for update".to_string(),
+        )),
+        [(name, version)] => {
+            status!("Updating package {} to version {}", name, version);
+            update_package(name, version, package_registry).and_then(finish_install)
+        }
+        _ => {
+            status!("Updating {} package(s) in parallel...", updates.len());
+            update_packages_parallel(&updates, package_registry).and_then(finish_install)
+        }
+    }
+}
+
+/// Handles the update command with --plan
+///
+/// Computes the pending upgrades like `ppm upgrade --dry-run`, honoring the
+/// update policies of ppm.toml, and prints them as markdown.
+///
+/// # Arguments
+/// * `names` - Packages to check, or none for every registered package
+/// * `package_registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if a target is not a package name
+fn handle_update_plan_command(
+    names: &[String],
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let mut names = match parse_update_targets(names)?.as_slice() {
+        [] => package_registry.packages.keys().cloned().collect(),
+        targets => targets
+            .iter()
+            .map(|(name, version)| match version {
+                None => Ok(name.clone()),
+                Some(version) => Err(PackageError::InvalidPackageSpec(format!(
+                    "--plan takes package names, not {} {}",
+                    name, version
+                ))),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    names.sort();
+
+    status!("Checking {} package(s) for upgrades...", names.len());
+    let policies = ProjectConfig::load(Path::new("."))?
+        .unwrap_or_default()
+        .update_policies();
+    let plan = plan_upgrades(&names, &HashMap::new(), &policies, package_registry)?;
+    set_report_data(serde_json::to_value(&plan)?);
+    reporter().output(&render_upgrade_plan_markdown(&plan));
+    status!("Plan only: no packages were changed");
+    Ok(())
+}
+
+/// Handles the list command
+///
+/// # Arguments
+/// * `outdated` - Whether to annotate packages with cached latest versions
+/// * `format` - Output format of the listing
+/// * `sizes` - Whether to annotate packages with their installed size
+/// * `hashes` - Whether to add the archive hashes of every release
+/// * `package_registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error while measuring sizes or looking up hashes
+fn handle_list_command(
+    outdated: bool,
+    format: ListFormat,
+    sizes: bool,
+    hashes: bool,
+    profile: Option<&str>,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    if hashes && format == ListFormat::Table && !json_output() {
+        return Err(PackageError::InvalidPackageSpec(
+            "--hashes applies to --format freeze and --format json".to_string(),
+        ));
+    }
+    match profile {
+        Some(profile) => {
+            let path = profile_requirements(Path::new("."), profile)?;
+            let required = packages_required_by(&path, package_registry)?;
+            list_packages_formatted(&required, format, outdated, sizes, hashes)
+        }
+        None => list_packages_formatted(package_registry, format, outdated, sizes, hashes),
+    }
+}
+
+/// Picks the requirements file of `--profile` when it is given, else the `-r` file
+///
+/// # Arguments
+/// * `profile` - The `--profile` option
+/// * `requirements` - The `-r` option
+///
+/// # Returns
+/// * `Result<Option<String>>` - The requirements file to use, or an error if the
+///   profile does not exist
+fn profile_or_requirements(
+    profile: Option<String>,
+    requirements: Option<String>,
+) -> Result<Option<String>, PackageError> {
+    match profile {
+        Some(profile) => profile_requirements(Path::new("."), &profile).map(Some),
+        None => Ok(requirements),
+    }
+}
+
+/// Handles the size command
+///
+/// # Arguments
+/// * `package` - Package to measure, or every registered package when omitted
+/// * `package_registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error while measuring sizes
+fn handle_size_command(
+    package: Option<String>,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let names = match package {
+        Some(name) => vec![name],
+        None => package_registry.packages.keys().cloned().collect(),
+    };
+
+    let sizes = measure_package_sizes(&names)?;
+    if sizes.is_empty() && names.len() == 1 {
+        return Err(PackageError::PackageNotFound(names[0].clone()));
+    }
+    if json_output() {
+        set_report_data(serde_json::to_value(&sizes)?);
+    } else {
+        print_package_sizes(&sizes);
+    }
+    Ok(())
+}
+
+/// Applies the ppm.toml settings that hold for the whole run
+///
+/// # Arguments
+/// * `jobs` - The `--jobs` option, which takes precedence over `jobs` in ppm.toml
+#[cfg_attr(not(feature = "parallel"), allow(unused_variables))]
+fn apply_project_settings(jobs: Option<u16>) {
+    // A broken ppm.toml is reported by the commands that read it
+    let config = ProjectConfig::load(Path::new("."))
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    if let Some(ttl) = config.metadata_ttl {
+        set_metadata_ttl(Duration::from_secs(ttl));
+    }
+    if let Some(days) = config.unmaintained_after_days {
+        set_unmaintained_after(days);
+    }
+    set_package_policy(config.policy);
+    if let Some(http) = &config.http {
+        set_http_client(http.client());
+    }
+    #[cfg(feature = "parallel")]
+    if let Some(jobs) = jobs.map(usize::from).or(config.jobs) {
+        if let Err(e) = configure_jobs(jobs) {
+            warning!("{}", e);
+        }
+    }
+}
+
+/// Handles the init command
+///
+/// Values not given as flags are asked for on a terminal; with `--yes` or
+/// without a terminal the defaults are used.
+///
+/// # Arguments
+/// * `name` - Project name
+/// * `python` - Supported Python versions
+/// * `format` - Which dependency files to create
+/// * `venv` - Where to create a virtual environment
+///
+/// # Returns
+/// * `Result<()>` - Success or error while writing files or creating the environment
+fn handle_init_command(
+    name: Option<String>,
+    python: Option<String>,
+    format: Option<InitFormat>,
+    venv: Option<PathBuf>,
+) -> Result<(), PackageError> {
+    let cwd = std::env::current_dir()?;
+    let default_name = cwd
+        .file_name()
+        .map_or("project".to_string(), |n| n.to_string_lossy().into_owned());
+    let interactive =
+        prompt_answer(assume_yes(), ci_mode(), io::stdin().is_terminal()) == PromptAnswer::Ask;
+    let dialog_error = |e: dialoguer::Error| match e {
+        dialoguer::Error::IO(e) => PackageError::IoError(e),
+    };
+
+    let options = if interactive {
+        let term = Term::stderr();
+        let name = match name {
+            Some(name) => name,
+            None => Input::new()
+                .with_prompt("Project name")
+                .default(default_name)
+                .interact_text_on(&term)
+                .map_err(dialog_error)?,
+        };
+        let requires_python = match python {
+            Some(python) => python,
+            None => Input::new()
+                .with_prompt("Supported Python versions")
+                .default(">=3.8".to_string())
+                .interact_text_on(&term)
+                .map_err(dialog_error)?,
+        };
+        let format = match format {
+            Some(format) => format,
+            None => {
+                let choices = ["requirements.txt", "pyproject.toml", "both"];
+                match Select::new()
+                    .with_prompt("Dependency file")
+                    .items(&choices)
+                    .default(0)
+                    .interact_on(&term)
+                    .map_err(dialog_error)?
+                {
+                    0 => InitFormat::Requirements,
+                    1 => InitFormat::Pyproject,
+                    _ => InitFormat::Both,
+                }
+            }
+        };
+        let venv = match venv {
+            Some(venv) => Some(venv),
+            None => Confirm::new()
+                .with_prompt("Create a virtual environment in .venv?")
+                .default(true)
+                .interact_on(&term)
+                .map_err(dialog_error)?
+                .then(|| PathBuf::from(".venv")),
+        };
+        InitOptions {
+            name,
+            requires_python: Some(requires_python).filter(|p| !p.trim().is_empty()),
+            format,
+            venv,
+        }
+    } else {
+        InitOptions {
+            name: name.unwrap_or(default_name),
+            requires_python: python,
+            format: format.unwrap_or_default(),
+            venv,
+        }
+    };
+
+    for path in init_project(&cwd, &options)? {
+        status!("{} Created {}", success_mark(), path.display());
+    }
+    if let Some(venv) = &options.venv {
+        if venv.exists() {
+            status!("Virtual environment {} already exists", venv.display());
+        } else {
+            status!("Creating virtual environment in {}...", venv.display());
+            create_virtualenv(venv)?;
+            status!(
+                "{} Created virtual environment {}",
+                success_mark(),
+                venv.display()
+            );
+        }
+    }
+    status!("Initialized project {}", options.name);
+    Ok(())
+}
+
+/// Handles the diff command
+///
+/// # Arguments
+/// * `a` - The baseline package source
+/// * `b` - The package source compared against the baseline
+/// * `format` - Output format
+///
+/// # Returns
+/// * `Result<()>` - Success or error while loading either side
+fn handle_diff_command(a: &str, b: &str, format: DiffFormat) -> Result<(), PackageError> {
+    let diff = diff_package_sets(&load_package_set(a)?, &load_package_set(b)?);
+    if json_output() {
+        set_report_data(serde_json::to_value(&diff)?);
+    } else if format == DiffFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+    } else {
+        status!("--- {}\n+++ {}", a, b);
+        reporter().output(&render_package_diff(&diff));
+    }
+    Ok(())
+}
+
+/// Handles the graph command
+///
+/// # Arguments
+/// * `format` - Output format
+/// * `root` - Only show this package and its dependencies
+/// * `package_registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error if the root package is not registered
+fn handle_graph_command(
+    format: GraphFormat,
+    root: Option<&str>,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let mut graph = package_registry.dependency_graph();
+    if let Some(root) = root {
+        graph = graph
+            .rooted_at(root)
+            .ok_or_else(|| PackageError::PackageNotFound(root.to_string()))?;
+    }
+
+    if json_output() {
+        set_report_data(serde_json::json!({
+            "packages": graph.packages(),
+            "edges": graph.edges(),
+        }));
+        return Ok(());
+    }
+    let rendered = match format {
+        GraphFormat::Dot => graph.to_dot(),
+        GraphFormat::Mermaid => graph.to_mermaid(),
+    };
+    reporter().output(&rendered);
+    Ok(())
+}
+
+/// Handles the history command
+///
+/// # Arguments
+/// * `limit` - Maximum number of operations to show
+/// * `package` - Only show operations that touched this package
+/// * `format` - Output format
+///
+/// # Returns
+/// * `Result<()>` - Success or error while reading the history
+fn handle_history_command(
+    limit: Option<usize>,
+    package: Option<&str>,
+    format: HistoryFormat,
+) -> Result<(), PackageError> {
+    let entries = filter_history(load_history()?, package, limit);
+    if json_output() {
+        set_report_data(serde_json::to_value(&entries)?);
+    } else if format == HistoryFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&entries)?);
+    } else {
+        reporter().output(&render_history(&entries));
+    }
+    Ok(())
+}
+
+/// Handles the undo command
+///
+/// # Arguments
+/// * `dry_run` - Only show the preview
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error while reversing the operation
+fn handle_undo_command(
+    dry_run: bool,
+    registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let Some(plan) = plan_undo(&load_history()?) else {
+        status!("Nothing to undo");
+        return Ok(());
+    };
+    if json_output() {
+        set_report_data(serde_json::to_value(&plan)?);
+    } else {
+        reporter().output(&render_undo_plan(&plan));
+    }
+    if dry_run {
+        return Ok(());
+    }
+    if !confirm(&format!("Undo `{}`?", plan.entry.command))? {
+        status!("Undo cancelled");
+        return Ok(());
+    }
+
+    finish_install(apply_undo(&plan, registry)?)?;
+    if !plan.uninstall.is_empty() {
+        notice!("Dependencies they pulled in are still installed; `ppm autoremove` removes them");
+    }
+    status!("{} Undid `{}`", success_mark(), plan.entry.command);
+    Ok(())
+}
+
+/// Handles the env command
+///
+/// # Arguments
+/// * `action` - The env action to run
+/// * `registry` - Mutable reference to the whole package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the environment could not be created or found
+fn handle_env_command(
+    action: EnvAction,
+    registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    match action {
+        EnvAction::List => {
+            let summaries = environment_summaries(registry);
+            if json_output() {
+                set_report_data(serde_json::to_value(&summaries)?);
+            } else {
+                print!("{}", render_environments(&summaries));
+            }
+        }
+        EnvAction::Create { name, venv, python } => {
+            let venv = create_environment(registry, &name, venv.as_deref(), python.as_deref())?;
+            status!(
+                "{} Created environment {} ({}); use it with `ppm --env {} install ...`",
+                success_mark(),
+                name,
+                venv.display(),
+                name
+            );
+        }
+        EnvAction::Clone {
+            source,
+            target,
+            venv,
+            python,
+        } => {
+            let report = clone_environment(
+                registry,
+                &source,
+                &target,
+                venv.as_deref(),
+                python.as_deref(),
+            )?;
+            // The summary names every package that could not be replicated
+            print_install_report(&report);
+            if report.is_success() {
+                status!(
+                    "{} Cloned {} into {} ({} packages)",
+                    success_mark(),
+                    source,
+                    target,
+                    report.outcomes.len()
+                );
+            }
+            // The new environment and the packages that made it are kept either way
+            save_packages(registry)?;
+            return report.into_result();
+        }
+        EnvAction::Remove { name, delete_venv } => {
+            let environment = remove_environment(registry, &name, delete_venv)?;
+            status!(
+                "{} Removed environment {} ({} packages){}",
+                success_mark(),
+                name,
+                environment.packages.len(),
+                if delete_venv {
+                    format!(" and deleted {}", environment.venv.display())
+                } else {
+                    String::new()
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Handles the registry command
+///
+/// The registry was already verified when it was loaded (or, for `rehash`,
+/// loaded without verification); saving it at the end of the run writes the
+/// fresh checksum.
+///
+/// # Arguments
+/// * `action` - The registry action to run
+/// * `registry` - Reference to the loaded package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error computing the checksum
+fn handle_registry_command(
+    action: RegistryAction,
+    registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let stored = std::fs::read_to_string(REGISTRY_FILE)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .and_then(|document| document["checksum"].as_str().map(str::to_string));
+    let checksum = registry_checksum(registry, registry_key().as_deref())?;
+    if json_output() {
+        set_report_data(serde_json::json!({
+            "file": REGISTRY_FILE,
+            "packages": registry.packages.len(),
+            "previous_checksum": stored,
+            "checksum": checksum,
+        }));
+    }
+
+    match action {
+        RegistryAction::Verify => match stored {
+            Some(_) => status!(
+                "{} {} matches its {} checksum ({} packages)",
+                success_mark(),
+                REGISTRY_FILE,
+                checksum.split(':').next().unwrap_or_default(),
+                registry.packages.len()
+            ),
+            None => status!("No {} yet; nothing to verify", REGISTRY_FILE),
+        },
+        RegistryAction::Rehash => {
+            if stored.as_deref() == Some(checksum.as_str()) {
+                status!("{} already matches its checksum", REGISTRY_FILE);
+            } else {
+                status!(
+                    "{} Accepted the current contents of {} ({} packages)",
+                    success_mark(),
+                    REGISTRY_FILE,
+                    registry.packages.len()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handles the pip command
+///
+/// # Arguments
+/// * `action` - The pip action to run
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if pip could not be run or upgraded
+fn handle_pip_command(action: PipAction) -> Result<(), PackageError> {
+    let pip = PipStatus::current()?;
+    match action {
+        PipAction::Version => {
+            if json_output() {
+                set_report_data(serde_json::json!({
+                    "python": pip.python,
+                    "version": pip.version,
+                    "features": PipFeature::ALL.iter().map(|feature| serde_json::json!({
+                        "feature": feature.description(),
+                        "minimum_version": feature.minimum_version(),
+                        "supported": pip.supports(*feature),
+                    })).collect::<Vec<_>>(),
+                }));
+            }
+            let Some(version) = &pip.version else {
+                return Err(PackageError::InstallationFailed(format!(
+                    "pip is not available in {0}; install it with `{0} -m ensurepip`",
+                    pip.python
+                )));
+            };
+            let mut out = format!("pip {} ({})\n", version, pip.python);
+            let width = PipFeature::ALL
+                .iter()
+                .map(|feature| feature.description().len())
+                .max()
+                .unwrap_or_default();
+            for feature in PipFeature::ALL {
+                let state = if pip.supports(feature) {
+                    "supported"
+                } else {
+                    "needs an upgrade"
+                };
+                out.push_str(&format!(
+                    "  {:<width$}  {} (pip {}+)\n",
+                    feature.description(),
+                    state,
+                    feature.minimum_version()
+                ));
+            }
+            reporter().output(&out);
+            if !PipFeature::ALL.iter().all(|feature| pip.supports(*feature)) {
+                notice!("Run `ppm pip upgrade` to update pip");
+            }
+        }
+        PipAction::Upgrade => {
+            status!("Upgrading pip in {}...", pip.python);
+            let (before, after) = upgrade_pip()?;
+            set_report_data(serde_json::json!({
+                "python": before.python,
+                "previous_version": before.version,
+                "version": after,
+            }));
+            match before.version {
+                Some(previous) if previous == after => {
+                    status!(
+                        "{} pip {} is already the newest release",
+                        success_mark(),
+                        after
+                    )
+                }
+                Some(previous) => {
+                    status!("{} Upgraded pip {} -> {}", success_mark(), previous, after)
+                }
+                None => status!("{} Installed pip {}", success_mark(), after),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Handles the python command
+///
+/// # Arguments
+/// * `action` - The python action to run
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if pyenv could not install the version;
+///   finding no interpreter is not an error
+fn handle_python_command(action: PythonAction) -> Result<(), PackageError> {
+    match action {
+        PythonAction::List => {
+            status!("Looking for Python interpreters...");
+            let interpreters = discover_interpreters();
+            set_report_data(serde_json::json!(interpreters));
+            reporter().output(&render_interpreters(&interpreters));
+        }
+        PythonAction::Install { version } => {
+            let version = match version {
+                Some(version) => version,
+                None => std::env::current_dir()
+                    .ok()
+                    .and_then(|dir| pinned_python_version(&dir))
+                    .filter(|version| version != "system")
+                    .ok_or_else(|| {
+                        PackageError::InvalidPackageSpec(format!(
+                            "No version given and no {} pins one",
+                            PYTHON_VERSION_FILE
+                        ))
+                    })?,
+            };
+            let (name, executable, installed) = install_pyenv_version(&version)?;
+            set_report_data(serde_json::json!({
+                "version": name,
+                "executable": executable,
+                "installed": installed,
+            }));
+            status!(
+                "{} Python {} {} ({})",
+                success_mark(),
+                name,
+                if installed {
+                    "installed"
+                } else {
+                    "is already installed"
+                },
+                executable.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Handles the snapshot command
+///
+/// # Arguments
+/// * `action` - The snapshot action to run
+/// * `registry` - Mutable reference to the package registry, replaced on restore
+///
+/// # Returns
+/// * `Result<()>` - Success or error while capturing or restoring
+fn handle_snapshot_command(
+    action: SnapshotAction,
+    registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    match action {
+        SnapshotAction::Create { name } => {
+            let snapshot = create_snapshot(&name, registry)?;
+            status!(
+                "{} Saved snapshot {} ({} packages, Python {})",
+                success_mark(),
+                snapshot.name,
+                snapshot.packages.len(),
+                snapshot.python_version
+            );
+        }
+        SnapshotAction::Restore { name, dry_run } => {
+            let snapshot = load_snapshot(&name)?;
+            let plan = plan_restore(&snapshot, &installed_snapshot_packages()?);
+            if json_output() {
+                set_report_data(serde_json::to_value(&plan)?);
+            } else {
+                reporter().output(&render_restore_plan(&plan));
+            }
+            if dry_run {
+                return Ok(());
+            }
+            if !plan.is_empty() && !confirm(&format!("Restore snapshot {}?", name))? {
+                status!("Restore cancelled");
+                return Ok(());
+            }
+            apply_restore(&snapshot, &plan, registry)?;
+            status!("{} Restored snapshot {}", success_mark(), name);
+        }
+        SnapshotAction::List => {
+            let snapshots = list_snapshots()?;
+            if json_output() {
+                let summary: Vec<serde_json::Value> = snapshots
+                    .iter()
+                    .map(|s| {
+                        serde_json::json!({
+                            "name": s.name,
+                            "created_at": s.created_at,
+                            "python_version": s.python_version,
+                            "packages": s.packages.len(),
+                        })
+                    })
+                    .collect();
+                set_report_data(serde_json::Value::Array(summary));
+            } else if snapshots.is_empty() {
+                println!("No snapshots yet; create one with `ppm snapshot create <name>`");
+            } else {
+                let now = unix_timestamp();
+                for snapshot in &snapshots {
+                    println!(
+                        "{:<24} {:>4} packages  Python {:<8} {} ago",
+                        snapshot.name,
+                        snapshot.packages.len(),
+                        snapshot.python_version,
+                        format_duration(now.saturating_sub(snapshot.created_at) * 1000)
+                    );
+                }
+            }
+        }
+        SnapshotAction::Delete { name } => {
+            delete_snapshot(&name)?;
+            status!("{} Deleted snapshot {}", success_mark(), name);
+        }
+    }
+    Ok(())
+}
+
+/// Handles the scripts command
+///
+/// # Arguments
+/// * `package` - Optional package whose commands to list
+///
+/// # Returns
+/// * `Result<()>` - Success or error while reading entry points
+fn handle_scripts_command(package: Option<&str>) -> Result<(), PackageError> {
+    let scripts = list_console_scripts(package)?;
+    if json_output() {
+        set_report_data(serde_json::to_value(&scripts)?);
+    } else if scripts.is_empty() && package.is_some() {
+        println!(
+            "{} provides no console scripts",
+            package.unwrap_or_default()
+        );
+    } else {
+        reporter().output(&render_console_scripts(&scripts));
+    }
+    Ok(())
+}
+
+/// Handles the autoremove command
+///
+/// Shows the orphaned dependencies and asks for confirmation before removing them.
+///
+/// # Arguments
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error from uninstallation
+fn handle_autoremove_command(
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let orphans = package_registry.orphaned_packages();
+    if orphans.is_empty() {
+        status!("No orphaned dependencies to remove");
+        return Ok(());
+    }
+
+    notice!("The following dependencies are no longer required:");
+    for name in &orphans {
+        let version = package_registry
+            .get_package(name)
+            .map_or("unknown", |p| p.version.as_str());
+        notice!("  {} @ {}", name, version);
+    }
+
+    if !confirm(&format!("Remove {} package(s)?", orphans.len()))? {
+        status!("Aborted: no packages were removed");
+        return Ok(());
+    }
+
+    remove_orphaned_packages(&orphans, package_registry)
+}
+
+/// Handles the prune command
+///
+/// # Arguments
+/// * `dry_run` - Whether to only list the stale entries
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the installed packages could not be listed
+fn handle_prune_command(
+    dry_run: bool,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let stale = stale_packages(package_registry)?;
+    if json_output() {
+        set_report_data(serde_json::json!({ "pruned": stale, "dry_run": dry_run }));
+    }
+    if stale.is_empty() {
+        status!("{} Every registry entry is installed", success_mark());
+        return Ok(());
+    }
+
+    notice!("The following registry entries are no longer installed:");
+    for name in &stale {
+        let version = package_registry
+            .get_package(name)
+            .map_or("unknown", |p| p.version.as_str());
+        notice!("  {} @ {}", name, version);
+    }
+    if dry_run {
+        status!("Dry run: nothing was pruned");
+        return Ok(());
+    }
+
+    let pruned = prune_packages(&stale, package_registry);
+    status!(
+        "{} Pruned {} registry entr{}",
+        success_mark(),
+        pruned.len(),
+        if pruned.len() == 1 { "y" } else { "ies" }
+    );
+    Ok(())
+}
+
+/// Handles the outdated command
+///
+/// # Arguments
+/// * `report` - Optional report destination, `PATH` or `FORMAT=PATH`
+/// * `no_notify` - Whether to skip the webhook of ppm.toml
+/// * `package_registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the report or the webhook could not be written
+fn handle_outdated_command(
+    report: Option<String>,
+    no_notify: bool,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    // Validate the report destination before doing any network work
+    let report = report.as_deref().map(parse_report_path).transpose()?;
+    let config = ProjectConfig::load(Path::new("."))?.unwrap_or_default();
+
+    let mut names: Vec<String> = package_registry.packages.keys().cloned().collect();
+    names.sort();
+    status!("Checking {} package(s) for newer releases...", names.len());
+    let plan = plan_upgrades(
+        &names,
+        &HashMap::new(),
+        &UpdatePolicies::default(),
+        package_registry,
+    )?;
+    reporter().output(&render_outdated(&plan));
+    set_report_data(serde_json::to_value(&plan)?);
+    for warning in check_maintenance(&names) {
+        warning!("{}", warning);
+    }
+
+    if let Some((format, path)) = report {
+        write_upgrade_report(&plan, format, &path)?;
+    }
+
+    let Some(webhook) = config.webhook.filter(|_| !no_notify) else {
+        return Ok(());
+    };
+    let project = if config.name.is_empty() {
+        std::env::current_dir()?
+            .file_name()
+            .map_or("project".to_string(), |n| n.to_string_lossy().into_owned())
+    } else {
+        config.name
+    };
+    if let Some(summary) = OutdatedSummary::from_plan(&project, &plan) {
+        send_webhook(&webhook, &summary)?;
+        status!("{} Posted the summary to the webhook", success_mark());
+    }
+    Ok(())
+}
+
+/// Handles the changes command
+///
+/// # Arguments
+/// * `package` - The package to look up
+/// * `range` - The versions, `FROM..TO`
+/// * `package_registry` - Reference to the package registry, for the installed version
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the range is invalid or PyPI could not be reached
+fn handle_changes_command(
+    package: &str,
+    range: &str,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let (from, to) = parse_change_range(range)?;
+    let from = match from {
+        Some(from) => from,
+        None => package_registry
+            .get_package(package)
+            .map(|p| p.version.clone())
+            .filter(|version| version != "unknown")
+            .ok_or_else(|| {
+                PackageError::InvalidPackageSpec(format!(
+                    "{} is not installed; give the version to start from, e.g. 1.0..",
+                    package
+                ))
+            })?,
+    };
+
+    status!("Fetching the releases of {}...", package);
+    let changes = release_changes(package, &from, to.as_deref())?;
+    reporter().output(&render_release_changes(&changes));
+    set_report_data(serde_json::to_value(&changes)?);
+    Ok(())
+}
+
+/// Handles the info command
+///
+/// # Arguments
+/// * `package` - The package, optionally with the version to compare
+/// * `package_registry` - Reference to the package registry, for the installed version
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the spec is invalid or PyPI could not be reached
+fn handle_info_command(
+    package: &str,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let (name, version) = python_package_manager::spec::parse_package_spec(package)?;
+    let version = version.or_else(|| {
+        package_registry
+            .get_package(&name)
+            .map(|p| p.version.clone())
+            .filter(|version| version != "unknown")
+    });
+
+    status!("Fetching {} from PyPI...", name);
+    let health = package_info(&name, version.as_deref())?;
+    reporter().output(&render_package_info(&health));
+    set_report_data(serde_json::to_value(&health)?);
+    Ok(())
+}
+
+/// Asks the user a yes/no question on the terminal
+///
+/// `--yes` answers the question in advance. Without it, CI mode and a stdin
+/// that is not a terminal refuse instead of waiting for an answer that never comes.
+///
+/// # Arguments
+/// * `question` - The question to display
+///
+/// # Returns
+/// * `Result<bool>` - Whether the user answered yes; end of input counts as no
+fn confirm(question: &str) -> Result<bool, PackageError> {
+    match prompt_answer(assume_yes(), ci_mode(), io::stdin().is_terminal()) {
+        PromptAnswer::Yes => {
+            status!("{} yes (--yes)", question);
+            return Ok(true);
+        }
+        PromptAnswer::Refuse(reason) => {
+            return Err(PackageError::PolicyViolation(format!(
+                "\"{}\" needs confirmation, but {}; pass --yes to proceed",
+                question, reason
+            )));
+        }
+        PromptAnswer::Ask => {}
+    }
+
+    // Keep stdout clean for the JSON result document
+    if json_output() {
+        eprint!("{} [y/N] ", question);
+    } else {
+        print!("{} [y/N] ", question);
+        io::stdout().flush()?;
+    }
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// Handles the upgrade command
+///
+/// # Arguments
+/// * `packages` - Names of the packages to upgrade
+/// * `all` - Whether to upgrade every registered package
+/// * `dry_run` - Whether to only show the plan without applying it
+/// * `report` - Optional `FORMAT=PATH` report specification
+/// * `constraint` - Optional constraints file capping the upgrades
+/// * `interactive` - Whether to pick the upgrades from a checklist and apply them in parallel
+/// * `allow_major` - Whether to ignore the update policies of ppm.toml
+/// * `package_registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error from planning or upgrading
+#[allow(clippy::too_many_arguments)]
+fn handle_upgrade_command(
+    packages: Vec<String>,
+    all: bool,
+    dry_run: bool,
+    report: Option<String>,
+    constraint: Option<String>,
+    interactive: bool,
+    allow_major: bool,
+    package_registry: &mut python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    if all && !packages.is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "Cannot combine --all with explicit package names".to_string(),
+        ));
+    }
+    if interactive && (ci_mode() || !io::stdin().is_terminal()) {
+        return Err(PackageError::PolicyViolation(
+            "--interactive needs a terminal; pass package names or --all instead".to_string(),
+        ));
+    }
+    // Interactive mode offers every outdated package unless names narrow it down
+    let all = all || (interactive && packages.is_empty());
+    if !all && packages.is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "Specify packages to upgrade or use --all".to_string(),
+        ));
+    }
+
+    // Validate the report destination before doing any network work
+    let report = report.as_deref().map(parse_report_spec).transpose()?;
+    let constraints = match constraint.as_deref() {
+        Some(path) => read_constraints_file(path)?,
+        None => HashMap::new(),
+    };
+
+    let names = if all {
+        let mut names: Vec<String> = package_registry.packages.keys().cloned().collect();
+        names.sort();
+        names
+    } else {
+        packages
+    };
+
+    status!("Checking {} package(s) for upgrades...", names.len());
+    let policies = if allow_major {
+        UpdatePolicies::default()
+    } else {
+        ProjectConfig::load(Path::new("."))?
+            .unwrap_or_default()
+            .update_policies()
+    };
+    let plan = plan_upgrades(&names, &constraints, &policies, package_registry)?;
+    if interactive && !plan.upgrades.is_empty() {
+        // The checklist shows the planned upgrades itself
+        print_upgrade_plan(&UpgradePlan {
+            upgrades: Vec::new(),
+            ..plan.clone()
+        });
+    } else {
+        print_upgrade_plan(&plan);
+    }
+    set_report_data(serde_json::to_value(&plan)?);
+
+    if let Some((format, path)) = report {
+        write_upgrade_report(&plan, format, &path)?;
+    }
+
+    if dry_run {
+        status!("Dry run: no packages were changed");
+        return Ok(());
+    }
+
+    if interactive {
+        if plan.upgrades.is_empty() {
+            return Ok(());
+        }
+        let Some(chosen) = MultiSelect::new()
+            .with_prompt("Select packages to upgrade (space to toggle, enter to confirm)")
+            .items(&upgrade_choice_labels(&plan))
+            .interact_on_opt(&Term::stderr())
+            .map_err(|e| match e {
+                dialoguer::Error::IO(e) => PackageError::IoError(e),
+            })?
+        else {
+            status!("Aborted: no packages were upgraded");
+            return Ok(());
+        };
+        if chosen.is_empty() {
+            status!("No packages selected");
+            return Ok(());
+        }
+        return apply_upgrade_plan_parallel(
+            &select_upgrades(&plan, &chosen),
+            constraint.as_deref().map(Path::new),
+            package_registry,
+        )
+        .and_then(finish_install);
+    }
+
+    if plan.needs_confirmation(all)
+        && !confirm(&format!("Upgrade {} package(s)?", plan.upgrades.len()))?
+    {
+        status!("Aborted: no packages were upgraded");
+        return Ok(());
+    }
+
+    apply_upgrade_plan(
+        &plan,
+        constraint.as_deref().map(Path::new),
+        package_registry,
+    )
+    .and_then(finish_install)
+}
+
+/// Handles the warm-cache command
+///
+/// # Arguments
+/// * `requirements` - Requirements file listing the packages, instead of the registry
+/// * `wheels` - Whether to download the packages' wheels as well
+/// * `package_registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error reading the requirements file or finding Python
+fn handle_warm_cache_command(
+    requirements: Option<&str>,
+    wheels: bool,
+    package_registry: &python_package_manager::PackageRegistry,
+) -> Result<(), PackageError> {
+    let specs = match requirements {
+        Some(path) => parse_requirements_file(path)?,
+        None => {
+            let mut specs: Vec<String> = package_registry
+                .packages
+                .values()
+                .map(|package| format!("{}=={}", package.name, package.version))
+                .collect();
+            specs.sort();
+            specs
+        }
+    };
+    if specs.is_empty() {
+        status!("No packages to prefetch");
+        return Ok(());
+    }
+
+    status!("Prefetching {} package(s)...", specs.len());
+    let warmed = warm_cache(&specs, wheels)?;
+    for package in &warmed {
+        if let Some(error) = &package.error {
+            warning!("Could not prefetch {}: {}", package.name, error);
+        }
+    }
+    if json_output() {
+        set_report_data(serde_json::to_value(&warmed)?);
+    }
+    let cached = warmed.iter().filter(|p| p.error.is_none()).count();
+    status!(
+        "{} Cached metadata{} for {} of {} package(s)",
+        success_mark(),
+        if wheels { " and wheels" } else { "" },
+        cached,
+        warmed.len()
+    );
+    Ok(())
+}
+
+/// Handles the explain command
+///
+/// # Arguments
+/// * `code` - The error code to explain
+///
+/// # Returns
+/// * `Result<()>` - Success or error for an unknown code
+fn handle_explain_command(code: &str) -> Result<(), PackageError> {
+    let info = explain_error_code(code).ok_or_else(|| {
+        PackageError::InvalidPackageSpec(format!(
+            "Unknown error code {} (codes look like PPM0003)",
+            code
+        ))
+    })?;
+
+    if json_output() {
+        set_report_data(serde_json::json!({
+            "code": info.code,
+            "title": info.title,
+            "causes": info.causes,
+            "remediation": info.remediation,
+        }));
+    } else {
+        reporter().output(&render_error_explanation(info));
+    }
+    Ok(())
+}
+
+/// Prints an error with its stable code and a pointer to its explanation
+///
+/// # Arguments
+/// * `error` - The error to print
+fn report_error(error: &PackageError) {
+    let Some(diagnostic) = error.diagnose() else {
+        eprintln!("{} [{}] {}", error_label(), error.code(), error);
+        if verbosity() > Verbosity::Quiet {
+            eprintln!(
+                "  (run `ppm explain {}` for causes and fixes)",
+                error.code()
+            );
+        }
+        return;
+    };
+
+    eprintln!(
+        "{} [{}] {}",
+        error_label(),
+        error.code(),
+        diagnostic.summary
+    );
+    eprint!("{}", diagnostic.render());
+    if verbosity() >= Verbosity::Verbose {
+        eprintln!("  pip output:\n{}", error);
+    }
+    if verbosity() > Verbosity::Quiet {
+        eprintln!(
+            "  (run `ppm explain {}` for causes and fixes)",
+            error.code()
+        );
+    }
+}
+
+/// Builds the label under which a run is recorded in the stats history
+///
+/// # Arguments
+/// * `command` - The parsed command
+///
+/// # Returns
+/// * `String` - Command name plus the flags that change how it performs
+fn command_label(command: &Commands) -> String {
+    match command {
+        Commands::Install { workspace, .. } if *workspace => "install --workspace".to_string(),
+        Commands::Install { parallel, .. } if *parallel => "install --parallel".to_string(),
+        Commands::Sync { workspace, .. } if *workspace => "sync --workspace".to_string(),
+        Commands::Sync { .. } => "sync".to_string(),
+        Commands::Watch { .. } => "watch".to_string(),
+        Commands::Install { .. } => "install".to_string(),
+        Commands::Add { .. } => "add".to_string(),
+        Commands::Remove { .. } => "remove".to_string(),
+        Commands::Delete { .. } => "delete".to_string(),
+        Commands::Update { .. } => "update".to_string(),
+        Commands::Upgrade { .. } => "upgrade".to_string(),
+        Commands::Outdated { .. } => "outdated".to_string(),
+        Commands::Changes { .. } => "changes".to_string(),
+        Commands::Info { .. } => "info".to_string(),
+        Commands::Autoremove => "autoremove".to_string(),
+        Commands::Prune { .. } => "prune".to_string(),
+        Commands::Pin { .. } => "pin".to_string(),
+        Commands::Unpin { .. } => "unpin".to_string(),
+        Commands::List { .. } => "list".to_string(),
+        Commands::Size { .. } => "size".to_string(),
+        Commands::Scripts { .. } => "scripts".to_string(),
+        Commands::Diff { .. } => "diff".to_string(),
+        Commands::Lock { check: true, .. } => "lock --check".to_string(),
+        Commands::Lock { .. } => "lock".to_string(),
+        Commands::Verify { .. } => "verify --lock".to_string(),
+        Commands::PinFile { .. } => "pin-file".to_string(),
+        Commands::Bump { .. } => "bump".to_string(),
+        Commands::Convert { .. } => "convert".to_string(),
+        Commands::Fmt { check: true, .. } => "fmt --check".to_string(),
+        Commands::Fmt { .. } => "fmt".to_string(),
+        Commands::Graph { .. } => "graph".to_string(),
+        Commands::Env { action } => match action {
+            EnvAction::List => "env list",
+            EnvAction::Create { .. } => "env create",
+            EnvAction::Clone { .. } => "env clone",
+            EnvAction::Remove { .. } => "env remove",
+        }
+        .to_string(),
+        Commands::Registry { action } => match action {
+            RegistryAction::Verify => "registry verify",
+            RegistryAction::Rehash => "registry rehash",
+        }
+        .to_string(),
+        Commands::Python { action } => match action {
+            PythonAction::List => "python list",
+            PythonAction::Install { .. } => "python install",
+        }
+        .to_string(),
+        Commands::Pip { action } => match action {
+            PipAction::Version => "pip version",
+            PipAction::Upgrade => "pip upgrade",
+        }
+        .to_string(),
+        Commands::Snapshot { action } => match action {
+            SnapshotAction::Create { .. } => "snapshot create",
+            SnapshotAction::Restore { .. } => "snapshot restore",
+            SnapshotAction::List => "snapshot list",
+            SnapshotAction::Delete { .. } => "snapshot delete",
+        }
+        .to_string(),
+        Commands::History { .. } => "history".to_string(),
+        Commands::Undo { .. } => "undo".to_string(),
+        Commands::Init { .. } => "init".to_string(),
+        Commands::Stats => "stats".to_string(),
+        Commands::Explain { .. } => "explain".to_string(),
+        Commands::WarmCache { .. } => "warm-cache".to_string(),
+        Commands::Benchmark { .. } => "benchmark".to_string(),
+        Commands::Containerize { .. } => "containerize".to_string(),
+        Commands::Build { .. } => "build".to_string(),
+        Commands::Bundle { action } => match action {
+            BundleAction::App { .. } => "bundle app",
+            BundleAction::Create { .. } => "bundle create",
+            BundleAction::Install { .. } => "bundle install",
+        }
+        .to_string(),
+        Commands::VendorInstall { .. } => "vendor-install".to_string(),
+        Commands::Serve { .. } => "serve".to_string(),
+        Commands::GenerateManpages { .. } => "generate-manpages".to_string(),
+        Commands::External(args) => args.first().cloned().unwrap_or_default(),
+        Commands::Run { .. } => "run".to_string(),
+    }
+}
+
+/// Current time as a Unix timestamp in seconds
+fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Maps package errors to appropriate exit codes
+///
+/// This mapping is a stable contract documented in the readme; pipelines branch on it.
+///
+/// | Code | Meaning |
+/// |------|---------|
+/// | 0 | Success |
+/// | 1 | General error |
+/// | 2 | The command succeeded but the registry could not be saved |
+/// | 3 | Python not found |
+/// | 4 | Total failure: every package of the operation failed |
+/// | 5 | Invalid package specification |
+/// | 6 | Package not found |
+/// | 7 | Metadata error |
+/// | 8 | Removal refused because other packages depend on the package |
+/// | 9 | Partial failure: some packages failed, the others succeeded |
+/// | 10 | Policy violation, e.g. warnings under `--deny-warnings` |
+/// | 11 | A downloaded archive did not match its pinned hash |
+/// | 12 | packages.json failed its integrity check |
+/// | 13 | A package or package source is denied by the package policy |
 ///
 /// # Arguments
 /// * `error` - The error to map
 ///
 /// # Returns
-/// * `i32` - Exit code (1 for general errors, 3 for Python not found, 4 for installation failures)
+/// * `i32` - Exit code from the table above
 fn get_exit_code(error: &PackageError) -> i32 {
     match error {
         PackageError::PythonNotFound => 3,
-        PackageError::InstallationFailed(_) | PackageError::UninstallationFailed(_) => 4,
+        PackageError::InstallationFailed(_)
+        | PackageError::UninstallationFailed(_)
+        | PackageError::NetworkError(_)
+        | PackageError::ResolutionConflict(_)
+        | PackageError::BuildFailed(_)
+        | PackageError::PermissionDenied(_)
+        | PackageError::Timeout(_) => 4,
         PackageError::InvalidPackageSpec(_) => 5,
         PackageError::PackageNotFound(_) => 6,
+        PackageError::MetadataError(_) | PackageError::HttpStatus(_) => 7,
+        PackageError::RequiredByOthers(_, _) => 8,
+        PackageError::PartialFailure(_, _, _) => 9,
+        PackageError::PolicyViolation(_) => 10,
+        PackageError::HashMismatch(_) => 11,
+        PackageError::RegistryIntegrity(_) => 12,
+        PackageError::PackageDenied(_) => 13,
         _ => 1,
     }
 }
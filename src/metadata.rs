@@ -0,0 +1,325 @@
+//! PyPI metadata lookups and the caches in front of them
+
+use crate::http::{http_client, HttpRequest};
+use crate::spec::canonicalize_name;
+use crate::state::{metadata_cache_path, response_cache_dir, unix_now};
+use crate::stats::format_duration;
+use crate::{PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::time::Duration;
+
+/// Latest release information remembered from a previous PyPI query
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CachedMetadata {
+    /// Newest version published at the time of the query
+    pub latest_version: String,
+    /// Unix timestamp of the query
+    pub fetched_at: u64,
+}
+
+/// On-disk cache of PyPI metadata keyed by normalized package name
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct MetadataCache {
+    /// Cached entries keyed by normalized package name
+    pub entries: HashMap<String, CachedMetadata>,
+}
+
+impl MetadataCache {
+    /// Loads the cache from `.ppm/metadata.json`, returning an empty cache if it is missing or unreadable
+    pub fn load() -> Self {
+        File::open(metadata_cache_path())
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves the cache to `.ppm/metadata.json`
+    pub fn save(&self) -> Result<()> {
+        let path = metadata_cache_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Gets the cached latest version of a package
+    ///
+    /// # Arguments
+    /// * `name` - The package name
+    ///
+    /// # Returns
+    /// The latest version if the package has been queried before
+    pub fn latest_version(&self, name: &str) -> Option<&str> {
+        self.entries
+            .get(&canonicalize_name(name))
+            .map(|entry| entry.latest_version.as_str())
+    }
+
+    /// Records the latest version of a package
+    ///
+    /// # Arguments
+    /// * `name` - The package name
+    /// * `latest_version` - The newest published version
+    pub fn record(&mut self, name: &str, latest_version: &str) {
+        let fetched_at = unix_now();
+        self.entries.insert(
+            canonicalize_name(name),
+            CachedMetadata {
+                latest_version: latest_version.to_string(),
+                fetched_at,
+            },
+        );
+    }
+}
+
+/// Base URL of the PyPI JSON API
+const PYPI_JSON_URL: &str = "https://pypi.org/pypi";
+
+/// Time a cached PyPI response is used before it is revalidated, unless configured
+pub const DEFAULT_METADATA_TTL: Duration = Duration::from_secs(3600);
+
+/// Seconds a cached PyPI response stays fresh
+static METADATA_TTL_SECS: AtomicU64 = AtomicU64::new(DEFAULT_METADATA_TTL.as_secs());
+
+/// Sets how long cached PyPI responses are used before they are revalidated
+///
+/// A zero TTL revalidates on every lookup, which still avoids downloading
+/// documents that did not change.
+///
+/// # Arguments
+/// * `ttl` - Time a response stays fresh after it was fetched or revalidated
+pub fn set_metadata_ttl(ttl: Duration) {
+    METADATA_TTL_SECS.store(ttl.as_secs(), AtomicOrdering::Relaxed);
+}
+
+/// Gets how long cached PyPI responses are used before they are revalidated
+pub fn metadata_ttl() -> Duration {
+    Duration::from_secs(METADATA_TTL_SECS.load(AtomicOrdering::Relaxed))
+}
+
+/// Fetches the PyPI project document of a package and remembers its latest version
+pub(crate) fn fetch_project_json(python: &str, name: &str) -> Result<serde_json::Value> {
+    let metadata = fetch_pypi_json(python, name, None)?;
+
+    if let Some(latest) = metadata["info"]["version"].as_str() {
+        let mut cache = MetadataCache::load();
+        cache.record(name, latest);
+        if let Err(e) = cache.save() {
+            warning!("Failed to update metadata cache: {}", e);
+        }
+    }
+
+    Ok(metadata)
+}
+
+/// A PyPI response kept on disk with the validators needed to revalidate it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CachedResponse {
+    /// `ETag` header of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    /// `Last-Modified` header of the response
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+    /// Unix timestamp of the last fetch or revalidation
+    fetched_at: u64,
+    /// The JSON document
+    body: serde_json::Value,
+}
+
+/// Fetches the PyPI JSON document of a project, or of one of its releases
+///
+/// # Arguments
+/// * `python` - Interpreter used for the request
+/// * `name` - The package name
+/// * `version` - The release, or `None` for the project document
+///
+/// # Returns
+/// * `Result<serde_json::Value>` - The document, possibly from the local cache
+pub(crate) fn fetch_pypi_json(
+    python: &str,
+    name: &str,
+    version: Option<&str>,
+) -> Result<serde_json::Value> {
+    let (url, key) = match version {
+        Some(version) => (
+            format!("{}/{}/{}/json", PYPI_JSON_URL, name, version),
+            format!("{}@{}", canonicalize_name(name), version),
+        ),
+        None => (
+            format!("{}/{}/json", PYPI_JSON_URL, name),
+            canonicalize_name(name),
+        ),
+    };
+    let path = response_cache_dir().join(format!("{}.json", key));
+    fetch_cached_json(python, &url, &path, metadata_ttl())
+}
+
+/// Fetches a JSON document, answering from the cache file at `path` while it is fresh
+///
+/// A stale entry is revalidated with its `ETag` and `Last-Modified`
+/// validators, so an unchanged document is not downloaded again. When the
+/// request fails, a stale entry is used with a warning, which keeps lookups
+/// working offline.
+fn fetch_cached_json(
+    python: &str,
+    url: &str,
+    path: &Path,
+    ttl: Duration,
+) -> Result<serde_json::Value> {
+    let cached: Option<CachedResponse> = File::open(path)
+        .ok()
+        .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok());
+    let now = unix_now();
+    if let Some(cached) = &cached {
+        if now.saturating_sub(cached.fetched_at) < ttl.as_secs() {
+            debug_detail!("Using cached {}", url);
+            return Ok(cached.body.clone());
+        }
+    }
+
+    let entry = match (fetch_json(python, url, cached.as_ref()), cached) {
+        (Ok(Some(fresh)), _) => fresh,
+        (Ok(None), Some(mut cached)) => {
+            debug_detail!("{} is unchanged", url);
+            cached.fetched_at = now;
+            cached
+        }
+        (Ok(None), None) => {
+            return Err(PackageError::MetadataError(format!(
+                "Empty response for {}",
+                url
+            )))
+        }
+        (Err(e), Some(cached)) => {
+            warning!(
+                "{}; using metadata cached {} ago",
+                e,
+                format_duration(now.saturating_sub(cached.fetched_at) * 1000)
+            );
+            return Ok(cached.body);
+        }
+        (Err(e), None) => return Err(e),
+    };
+
+    // Written next to the entry and renamed over it, so concurrent lookups of
+    // the same document never read a half-written file
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let saved = std::fs::create_dir_all(dir)
+        .and_then(|()| tempfile::NamedTempFile::new_in(dir))
+        .map_err(PackageError::from)
+        .and_then(|partial| {
+            serde_json::to_writer(BufWriter::new(partial.as_file()), &entry)?;
+            partial
+                .persist(path)
+                .map_err(|e| PackageError::from(e.error))?;
+            Ok(())
+        });
+    if let Err(e) = saved {
+        warning!("Failed to update metadata cache: {}", e);
+    }
+    Ok(entry.body)
+}
+
+/// Fetches a JSON document with the shared [`HttpClient`](crate::HttpClient)
+///
+/// # Arguments
+/// * `python` - Interpreter used for the request
+/// * `url` - The document to fetch
+/// * `cached` - A previous response whose validators are sent along
+///
+/// # Returns
+/// * `Result<Option<CachedResponse>>` - The response, or `None` if `cached` is still current
+fn fetch_json(
+    python: &str,
+    url: &str,
+    cached: Option<&CachedResponse>,
+) -> Result<Option<CachedResponse>> {
+    let request = HttpRequest {
+        url: url.to_string(),
+        etag: cached.and_then(|c| c.etag.clone()),
+        last_modified: cached.and_then(|c| c.last_modified.clone()),
+        save_to: None,
+    };
+    let response = http_client().get(python, &request)?;
+    if response.not_modified() {
+        return Ok(None);
+    }
+    Ok(Some(CachedResponse {
+        etag: response.etag,
+        last_modified: response.last_modified,
+        fetched_at: unix_now(),
+        body: serde_json::from_str(&response.body)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pip::ScriptedRunner;
+    use crate::test_support::with_runner;
+
+    #[test]
+    fn test_metadata_cache_lookup_is_normalized() {
+        let mut cache = MetadataCache::default();
+        cache.record("Charset_Normalizer", "3.3.2");
+
+        assert_eq!(cache.latest_version("charset-normalizer"), Some("3.3.2"));
+        assert!(cache.entries["charset-normalizer"].fetched_at > 0);
+        assert_eq!(cache.latest_version("requests"), None);
+    }
+
+    #[test]
+    fn test_fetch_cached_json_revalidates_with_etag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("requests.json");
+        let url = "https://pypi.org/pypi/requests/json";
+        let fetch = |runner: ScriptedRunner, ttl: u64| {
+            with_runner(runner, |runner| {
+                let body = fetch_cached_json("python", url, &path, Duration::from_secs(ttl));
+                (body, runner.calls())
+            })
+        };
+
+        let (body, calls) = fetch(
+            ScriptedRunner::new().respond(
+                url,
+                r#"{"url": "", "status": 200, "etag": "\"v1\"", "body": "{\"info\": {\"version\": \"2.31.0\"}}"}"#,
+            ),
+            3600,
+        );
+        assert_eq!(body.unwrap()["info"]["version"], "2.31.0");
+        assert_eq!(calls.len(), 1);
+
+        // Fresh entries are answered without a request
+        let (body, calls) = fetch(ScriptedRunner::new(), 3600);
+        assert_eq!(body.unwrap()["info"]["version"], "2.31.0");
+        assert!(calls.is_empty());
+
+        // Stale entries are revalidated with their ETag
+        let (body, calls) = fetch(
+            ScriptedRunner::new().respond(url, r#"{"url": "", "status": 304}"#),
+            0,
+        );
+        assert_eq!(body.unwrap()["info"]["version"], "2.31.0");
+        assert!(calls[0].contains(r#""etag":"\"v1\"""#));
+
+        // A failed request falls back to the stale entry
+        let (body, _) = fetch(
+            ScriptedRunner::new().fail(url, 1, "urllib.error.URLError: <urlopen error timed out>"),
+            0,
+        );
+        assert_eq!(body.unwrap()["info"]["version"], "2.31.0");
+
+        std::fs::remove_file(&path).unwrap();
+        let (body, _) = fetch(ScriptedRunner::new().fail(url, 1, "timed out"), 0);
+        assert!(matches!(body, Err(PackageError::MetadataError(_))));
+    }
+}
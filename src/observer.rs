@@ -0,0 +1,183 @@
+//! Progress callbacks for batch installs and updates
+
+use crate::pip::echo_pip_line;
+use crate::Result;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Receives the progress of parallel installs and updates, e.g. to drive a UI or a log
+///
+/// Every method does nothing by default, so an observer implements only what
+/// it needs. Calls arrive from the worker threads. The observer in use is
+/// chosen with [`set_install_observer`]; the CLI uses a [`ProgressBarObserver`](crate::ProgressBarObserver).
+pub trait InstallObserver: Send + Sync {
+    /// Called once before any package starts
+    ///
+    /// # Arguments
+    /// * `total` - Number of packages in the operation
+    /// * `operation` - What is done to them: "install", "update" or "upgrade"
+    fn on_start(&self, _total: usize, _operation: &str) {}
+
+    /// Called when a package starts ("starting") and whenever pip reports a new state for it
+    ///
+    /// # Arguments
+    /// * `name` - Package name
+    /// * `state` - Short description such as "downloading" or "installing"
+    fn on_progress(&self, _name: &str, _state: &str) {}
+
+    /// Called when a package is done
+    ///
+    /// # Arguments
+    /// * `name` - Package name
+    /// * `result` - The installed version, or the error
+    /// * `elapsed` - How long the package took
+    fn on_package_done(
+        &self,
+        _name: &str,
+        _result: std::result::Result<&str, &str>,
+        _elapsed: Duration,
+    ) {
+    }
+
+    /// Called once after every package is done
+    fn on_finished(&self) {}
+
+    /// Called with each line pip prints for a package in verbose mode
+    ///
+    /// The default prints the line prefixed with the package name.
+    fn on_output(&self, name: &str, line: &str, is_stderr: bool) {
+        echo_pip_line(name, line, is_stderr);
+    }
+}
+
+/// Observer that ignores every event, used unless another one is set
+struct SilentObserver;
+
+impl InstallObserver for SilentObserver {}
+
+/// Observer used for parallel installs and updates; `None` means [`SilentObserver`]
+static INSTALL_OBSERVER: Mutex<Option<Arc<dyn InstallObserver>>> = Mutex::new(None);
+
+/// Reports the progress of every later parallel install and update to `observer`
+///
+/// # Arguments
+/// * `observer` - The observer to use from now on
+pub fn set_install_observer(observer: Arc<dyn InstallObserver>) {
+    *INSTALL_OBSERVER.lock().unwrap() = Some(observer);
+}
+
+/// Returns the observer of parallel installs and updates
+pub fn install_observer() -> Arc<dyn InstallObserver> {
+    INSTALL_OBSERVER
+        .lock()
+        .unwrap()
+        .clone()
+        .unwrap_or_else(|| Arc::new(SilentObserver))
+}
+
+/// Reports a finished package to an observer
+pub(crate) fn observe_package_done(
+    observer: &dyn InstallObserver,
+    name: &str,
+    result: &Result<(String, String)>,
+    elapsed: Duration,
+) {
+    let error;
+    let result = match result {
+        Ok((_, version)) => Ok(version.as_str()),
+        Err(e) => {
+            error = e.to_string();
+            Err(error.as_str())
+        }
+    };
+    observer.on_package_done(name, result, elapsed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::install::install_packages_parallel;
+    use crate::registry::PackageRegistry;
+    use crate::test_support::{python_runner, with_runner};
+
+    /// Observer that records every event as a line of text
+    #[derive(Default)]
+    struct RecordingObserver {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl InstallObserver for RecordingObserver {
+        fn on_start(&self, total: usize, operation: &str) {
+            self.record(format!("start {} {}", total, operation));
+        }
+
+        fn on_progress(&self, name: &str, state: &str) {
+            self.record(format!("progress {} {}", name, state));
+        }
+
+        fn on_package_done(
+            &self,
+            name: &str,
+            result: std::result::Result<&str, &str>,
+            _elapsed: Duration,
+        ) {
+            match result {
+                Ok(version) => self.record(format!("done {} {}", name, version)),
+                Err(_) => self.record(format!("failed {}", name)),
+            }
+        }
+
+        fn on_finished(&self) {
+            self.record("finished".to_string());
+        }
+    }
+
+    impl RecordingObserver {
+        fn record(&self, event: String) {
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[test]
+    fn test_install_observer_receives_parallel_progress() {
+        let runner = python_runner()
+            .fail(
+                " beta",
+                1,
+                "ERROR: No matching distribution found for beta\n",
+            )
+            .respond("pip download", "Saved ./alpha-1.0-py3-none-any.whl\n")
+            .respond("pip install", "Successfully installed alpha-1.0\n");
+        let observer = Arc::new(RecordingObserver::default());
+        let mut registry = PackageRegistry::new();
+        let packages = ["alpha==1.0".to_string(), "beta".to_string()];
+        // Set while the runner lock is held so no other test reports to it
+        let report = with_runner(runner, |_| {
+            set_install_observer(observer.clone());
+            let report = install_packages_parallel(&packages, &mut registry);
+            *INSTALL_OBSERVER.lock().unwrap() = None;
+            report
+        });
+        let report = report.unwrap();
+        assert_eq!(report.failed(), 1);
+
+        let events = observer.events.lock().unwrap();
+        assert_eq!(events.first().map(String::as_str), Some("start 2 install"));
+        assert_eq!(events.last().map(String::as_str), Some("finished"));
+        for event in [
+            "progress alpha starting",
+            "progress alpha downloaded, waiting to install",
+            "done alpha 1.0",
+            "progress beta starting",
+            "progress beta download failed",
+            "failed beta",
+        ] {
+            assert!(
+                events.iter().any(|e| e == event),
+                "missing {:?} in {:?}",
+                event,
+                events
+            );
+        }
+    }
+}
@@ -0,0 +1,438 @@
+//! Per-package outcomes and the summary printed after a batch
+
+use crate::diagnostics::diagnose_pip_output;
+use crate::registry::PackageRegistry;
+use crate::reporter::{reporter, verbosity, MessageKind, Verbosity};
+use crate::session::{error_summary, RUN_REPORT};
+use crate::spec::compare_versions;
+use crate::stats::format_duration;
+use crate::{PackageError, Result};
+use std::cmp::Ordering;
+
+/// Result of installing or updating a single package
+#[derive(Debug, Clone, PartialEq)]
+pub struct OperationOutcome {
+    /// Package name
+    pub name: String,
+    /// Specification that was requested, e.g. "numpy==1.26.4"
+    pub spec: String,
+    /// Version registered before the operation, if any
+    pub previous_version: Option<String>,
+    /// Version installed by the operation; `None` if it failed
+    pub new_version: Option<String>,
+    /// Error reported by pip if the operation failed
+    pub error: Option<String>,
+    /// Duration in milliseconds, if the package was handled on its own
+    pub millis: Option<u64>,
+    /// Whether the installed version already satisfied the spec, so pip was not run
+    pub satisfied: bool,
+    /// Index that served the package, when several indexes are configured
+    pub index: Option<String>,
+}
+
+impl OperationOutcome {
+    /// Describes what the operation did to the package
+    ///
+    /// # Returns
+    /// One of "failed", "installed", "upgraded", "downgraded", "unchanged" or "satisfied"
+    pub fn status(&self) -> &'static str {
+        if self.satisfied {
+            return "satisfied";
+        }
+        match (&self.error, &self.previous_version, &self.new_version) {
+            (Some(_), _, _) | (None, _, None) => "failed",
+            (None, None, Some(_)) => "installed",
+            (None, Some(previous), Some(new)) => match compare_versions(new, previous) {
+                Ordering::Greater => "upgraded",
+                Ordering::Less => "downgraded",
+                Ordering::Equal => "unchanged",
+            },
+        }
+    }
+}
+
+/// Renders the outcomes of an install or update as an aligned table
+///
+/// Successful packages are listed first and failures are grouped at the
+/// bottom, followed by each failure's error and the failed specifications
+/// on a single line so they can be retried.
+///
+/// # Arguments
+/// * `title` - Heading of the table, e.g. "Installation summary"
+/// * `outcomes` - Outcomes in the order the packages were requested
+///
+/// # Returns
+/// The rendered table, terminated by a newline
+pub fn render_summary_table(title: &str, outcomes: &[OperationOutcome]) -> String {
+    let (succeeded, failed): (Vec<&OperationOutcome>, Vec<&OperationOutcome>) =
+        outcomes.iter().partition(|o| o.status() != "failed");
+
+    let header = ["Package", "Previous", "New", "Status", "Duration"].map(String::from);
+    let rows: Vec<[String; 5]> = succeeded
+        .iter()
+        .chain(&failed)
+        .map(|o| {
+            [
+                o.name.clone(),
+                o.previous_version
+                    .clone()
+                    .unwrap_or_else(|| "-".to_string()),
+                o.new_version.clone().unwrap_or_else(|| "-".to_string()),
+                o.status().to_string(),
+                o.millis.map_or_else(|| "-".to_string(), format_duration),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = format!("\n{}:\n", title);
+    for (index, row) in std::iter::once(&header).chain(&rows).enumerate() {
+        // Pad before styling so escape codes do not break the alignment
+        let status = format!("{:<w$}", row[3], w = widths[3]);
+        let status = match row[3].as_str() {
+            _ if index == 0 => status,
+            "failed" => console::style(status).red().to_string(),
+            "unchanged" | "satisfied" => status,
+            _ => console::style(status).green().to_string(),
+        };
+        let line = format!(
+            "  {:<w0$}  {:<w1$}  {:<w2$}  {}  {:>w4$}",
+            row[0],
+            row[1],
+            row[2],
+            status,
+            row[4],
+            w0 = widths[0],
+            w1 = widths[1],
+            w2 = widths[2],
+            w4 = widths[4]
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    let skipped = succeeded.iter().filter(|o| o.satisfied).count();
+    let skipped = if skipped > 0 {
+        format!(" ({} already satisfied)", skipped)
+    } else {
+        String::new()
+    };
+    out.push_str(&format!(
+        "\n{} succeeded{}, {} failed\n",
+        succeeded.len(),
+        skipped,
+        failed.len()
+    ));
+
+    // With mirrors configured, say which index each package came from
+    let mut served: Vec<(&str, Vec<&str>)> = Vec::new();
+    for outcome in &succeeded {
+        let Some(index) = outcome.index.as_deref() else {
+            continue;
+        };
+        match served.iter_mut().find(|(served_by, _)| *served_by == index) {
+            Some((_, names)) => names.push(&outcome.name),
+            None => served.push((index, vec![&outcome.name])),
+        }
+    }
+    for (index, names) in served {
+        out.push_str(&format!("Served by {}: {}\n", index, names.join(", ")));
+    }
+
+    if !failed.is_empty() {
+        out.push('\n');
+        out.push_str(&render_failures(&failed));
+    }
+
+    out
+}
+
+/// Renders the errors of failed operations and the failed specifications
+fn render_failures(failed: &[&OperationOutcome]) -> String {
+    let mut out = String::from("Failures:\n");
+    for outcome in failed {
+        let diagnostic = outcome.error.as_deref().and_then(diagnose_pip_output);
+        let reason = match (&diagnostic, outcome.error.as_deref()) {
+            (Some(diagnostic), _) => diagnostic.summary.as_str(),
+            (None, Some(error)) => error_summary(error),
+            (None, None) => "unknown error",
+        };
+        out.push_str(&format!("  {}: {}\n", outcome.name, reason));
+    }
+    let specs: Vec<&str> = failed.iter().map(|o| o.spec.as_str()).collect();
+    out.push_str(&format!("Failed specs: {}\n", specs.join(" ")));
+    out
+}
+
+/// What an install, update or upgrade did to each package, returned instead of printed
+///
+/// Packages that fail are recorded here rather than ending the operation, so
+/// callers see every outcome; [`InstallReport::into_result`] turns the
+/// failures into the error the command exits with.
+#[derive(Debug, Default)]
+pub struct InstallReport {
+    /// Heading the report is presented under, e.g. "Installation summary"
+    pub title: String,
+    /// What was done to the packages: "install", "update" or "upgrade"
+    pub verb: String,
+    /// One outcome per package, with the resolved version, duration and any error
+    pub outcomes: Vec<OperationOutcome>,
+    /// Problems that did not fail any package, such as untracked dependencies
+    pub warnings: Vec<String>,
+    /// Classified error of a pip run that failed for the whole batch
+    pub(crate) failure: Option<PackageError>,
+}
+
+impl InstallReport {
+    /// Creates an empty report
+    ///
+    /// # Arguments
+    /// * `title` - Heading the report is presented under
+    /// * `verb` - What is done to the packages, used in error messages
+    pub fn new(title: &str, verb: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            verb: verb.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// Number of packages that failed
+    pub fn failed(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|o| o.status() == "failed")
+            .count()
+    }
+
+    /// Whether every package succeeded
+    pub fn is_success(&self) -> bool {
+        self.failure.is_none() && self.failed() == 0
+    }
+
+    /// Turns the failures into an error
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success if nothing failed, the classified pip error of a
+    ///   failed batch, a [`PackageError::PartialFailure`] if only some packages
+    ///   failed, or [`PackageError::InstallationFailed`] if all of them did
+    pub fn into_result(self) -> Result<()> {
+        if let Some(failure) = self.failure {
+            return Err(failure);
+        }
+
+        let failure_count = self.failed();
+        if failure_count == 0 {
+            Ok(())
+        } else if failure_count < self.outcomes.len() {
+            Err(PackageError::PartialFailure(
+                format!("packages failed to {}", self.verb),
+                failure_count,
+                self.outcomes.len(),
+            ))
+        } else {
+            Err(PackageError::InstallationFailed(format!(
+                "{} packages failed to {}",
+                failure_count, self.verb
+            )))
+        }
+    }
+}
+
+/// Presents a report: its warnings, then a summary table, or only the failures in quiet mode
+///
+/// The outcomes are also recorded for the JSON result document and the history.
+///
+/// # Arguments
+/// * `report` - The report to present
+pub fn print_install_report(report: &InstallReport) {
+    RUN_REPORT
+        .lock()
+        .unwrap()
+        .outcomes
+        .extend_from_slice(&report.outcomes);
+
+    for message in &report.warnings {
+        warning!("{}", message);
+    }
+    if report.outcomes.is_empty() {
+        return;
+    }
+
+    if verbosity() > Verbosity::Quiet {
+        let table = render_summary_table(&report.title, &report.outcomes);
+        reporter().output(&table);
+        return;
+    }
+
+    let failed: Vec<&OperationOutcome> = report
+        .outcomes
+        .iter()
+        .filter(|o| o.status() == "failed")
+        .collect();
+    if !failed.is_empty() {
+        let failures = render_failures(&failed);
+        reporter().message(MessageKind::Error, failures.trim_end());
+    }
+}
+
+/// Version of a package as recorded in the registry before an operation
+pub(crate) fn registered_version(registry: &PackageRegistry, name: &str) -> Option<String> {
+    registry
+        .find_package_key(name)
+        .and_then(|key| registry.get_package(&key))
+        .map(|package| package.version.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spec::spec_is_satisfied;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_outcome_status() {
+        let outcome = |previous: Option<&str>, new: Option<&str>| OperationOutcome {
+            name: "numpy".to_string(),
+            spec: "numpy".to_string(),
+            previous_version: previous.map(String::from),
+            new_version: new.map(String::from),
+            error: None,
+            millis: None,
+            satisfied: false,
+            index: None,
+        };
+        assert_eq!(outcome(None, Some("1.26.4")).status(), "installed");
+        assert_eq!(outcome(Some("1.24.0"), Some("1.26.4")).status(), "upgraded");
+        assert_eq!(
+            outcome(Some("2.0.0"), Some("1.26.4")).status(),
+            "downgraded"
+        );
+        assert_eq!(
+            outcome(Some("1.26.4"), Some("1.26.4")).status(),
+            "unchanged"
+        );
+        assert_eq!(outcome(Some("1.26.4"), None).status(), "failed");
+    }
+
+    #[test]
+    fn test_render_summary_table() {
+        let outcomes = vec![
+            OperationOutcome {
+                name: "nosuchpkg".to_string(),
+                spec: "nosuchpkg==1.0".to_string(),
+                previous_version: None,
+                new_version: None,
+                error: Some(
+                    "ERROR: No matching distribution found for nosuchpkg==1.0\n".to_string(),
+                ),
+                millis: Some(800),
+                satisfied: false,
+                index: None,
+            },
+            OperationOutcome {
+                name: "click".to_string(),
+                spec: "click".to_string(),
+                previous_version: Some("8.1.6".to_string()),
+                new_version: Some("8.1.7".to_string()),
+                error: None,
+                millis: Some(1_200),
+                satisfied: false,
+                index: None,
+            },
+        ];
+
+        let table = render_summary_table("Installation summary", &outcomes);
+        let expected = "
+Installation summary:
+  Package    Previous  New    Status    Duration
+  click      8.1.6     8.1.7  upgraded     1.2 s
+  nosuchpkg  -         -      failed      800 ms
+
+1 succeeded, 1 failed
+
+Failures:
+  nosuchpkg: No release matches nosuchpkg==1.0
+Failed specs: nosuchpkg==1.0
+";
+        assert_eq!(table, expected);
+    }
+
+    #[test]
+    fn test_partial_failures() {
+        let outcome = |name: &str, error: Option<&str>| OperationOutcome {
+            name: name.to_string(),
+            spec: name.to_string(),
+            previous_version: None,
+            new_version: error.is_none().then(|| "1.0".to_string()),
+            error: error.map(str::to_string),
+            millis: Some(1),
+            satisfied: false,
+            index: None,
+        };
+        let report = |outcomes: Vec<OperationOutcome>| InstallReport {
+            outcomes,
+            ..InstallReport::new("Installation summary", "install")
+        };
+        let mixed = report(vec![outcome("a", None), outcome("b", Some("boom"))]);
+        assert!(!mixed.is_success());
+        match mixed.into_result() {
+            Err(e @ PackageError::PartialFailure(_, 1, 2)) => {
+                assert_eq!(e.to_string(), "1 of 2 packages failed to install");
+                assert_eq!(e.code(), "PPM0015");
+            }
+            other => panic!("expected a partial failure, got {:?}", other),
+        }
+        let failed = report(vec![outcome("b", Some("boom"))]);
+        assert!(matches!(
+            failed.into_result(),
+            Err(PackageError::InstallationFailed(_))
+        ));
+        assert!(report(Vec::new()).into_result().is_ok());
+    }
+
+    #[test]
+    fn test_satisfied_outcomes_in_summary() {
+        let outcomes = vec![
+            OperationOutcome {
+                name: "requests".to_string(),
+                spec: "requests>=2.28".to_string(),
+                previous_version: Some("2.31.0".to_string()),
+                new_version: Some("2.31.0".to_string()),
+                error: None,
+                millis: None,
+                satisfied: true,
+                index: None,
+            },
+            OperationOutcome {
+                name: "rich".to_string(),
+                spec: "rich".to_string(),
+                previous_version: None,
+                new_version: Some("13.7.1".to_string()),
+                error: None,
+                millis: Some(900),
+                satisfied: false,
+                index: None,
+            },
+        ];
+        assert_eq!(outcomes[0].status(), "satisfied");
+
+        let table =
+            console::strip_ansi_codes(&render_summary_table("Installation summary", &outcomes))
+                .to_string();
+        assert!(table.contains("  requests  2.31.0    2.31.0  satisfied"));
+        assert!(table.ends_with("2 succeeded (1 already satisfied), 0 failed\n"));
+
+        let installed: HashMap<String, String> =
+            [("requests".to_string(), "2.31.0".to_string())].into();
+        assert!(spec_is_satisfied("Requests>=2.28", &installed));
+        assert!(!spec_is_satisfied("requests==2.32.0", &installed));
+        assert!(!spec_is_satisfied("rich", &installed));
+    }
+}
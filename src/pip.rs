@@ -0,0 +1,1598 @@
+//! Running pip and the Python interpreter, and reading what they report
+
+use crate::http::http_client;
+use crate::install::TimedResult;
+use crate::interpreters::pinned_pyenv_interpreter;
+use crate::policy;
+use crate::pypackages::apply_local_packages;
+use crate::registry::{Package, PackageOrigin, PackageRegistry};
+use crate::reporter::is_verbose;
+use crate::session::{PYTHON_OVERRIDE, THREAD_PYTHON};
+use crate::spec::{canonicalize_name, compare_versions, requirement_name};
+use crate::{report, HashMismatch, InstallObserver, MessageKind, PackageError, PipFailure, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Command, Output, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tempfile::TempDir;
+
+/// Locates the Python executable on the system
+///
+/// This function attempts to find a valid Python executable by trying
+/// common command names in order of preference. It validates that the
+/// found executable is actually working by running a simple command.
+/// A version pinned in `.python-version` is used first when pyenv has it.
+///
+/// # Returns
+/// * `Result<String>` - Path to the Python executable or error if not found
+///
+/// # Examples
+/// ```ignore
+/// let python_path = get_python_executable().unwrap();
+/// println!("Using Python: {}", python_path);
+/// ```
+pub(crate) fn get_python_executable() -> Result<String> {
+    if let Some(python) = THREAD_PYTHON.with(|python| python.borrow().clone()) {
+        return Ok(python);
+    }
+    if let Some(python) = PYTHON_OVERRIDE.lock().unwrap().clone() {
+        return Ok(python);
+    }
+    if let Some(python) = pinned_pyenv_interpreter() {
+        detail!("Using Python interpreter {} (pinned by pyenv)", python);
+        return Ok(python);
+    }
+
+    let candidates = ["python3", "python", "py"];
+
+    for cmd in &candidates {
+        if let Ok(output) = run_command(
+            Command::new(cmd)
+                .arg("-c")
+                .arg("import sys; print(sys.executable)"),
+        ) {
+            if output.status.success() {
+                if let Ok(path) = String::from_utf8(output.stdout) {
+                    detail!("Using Python interpreter {}", path.trim());
+                    return Ok(path.trim().to_string());
+                }
+            }
+        }
+    }
+
+    Err(PackageError::PythonNotFound)
+}
+
+/// Retrieves the installed version of a specific package
+///
+/// Returns "unknown" if the package is not installed or version cannot be determined.
+///
+/// # Arguments
+/// * `python` - Path to the Python executable
+/// * `name` - Name of the package to check
+///
+/// # Returns
+/// * `Result<String>` - Version string or "unknown" if not found
+pub(crate) fn get_installed_version(python: &str, name: &str) -> Result<String> {
+    Ok(get_installed_versions(python, &[name.to_string()])
+        .remove(name)
+        .unwrap_or_else(|| "unknown".to_string()))
+}
+
+/// Retrieves the installed versions of several packages with a single interpreter launch
+///
+/// # Arguments
+/// * `python` - Path to the Python executable
+/// * `names` - Names of the packages to check
+///
+/// # Returns
+/// * `HashMap<String, String>` - Versions keyed by the names as given; packages
+///   that are not installed, or all of them if the lookup fails, are missing
+pub(crate) fn get_installed_versions(python: &str, names: &[String]) -> HashMap<String, String> {
+    if names.is_empty() {
+        return HashMap::new();
+    }
+
+    // Extras such as "requests[socks]" are not part of the distribution name
+    let output = run_command(
+        Command::new(python)
+            .arg("-c")
+            .arg(INSTALLED_VERSIONS_SCRIPT)
+            .args(names.iter().map(|name| requirement_name(name))),
+    );
+    match output {
+        Ok(output) if output.status.success() => {
+            let found: HashMap<String, String> =
+                serde_json::from_slice(&output.stdout).unwrap_or_default();
+            names
+                .iter()
+                .filter_map(|name| {
+                    let version = found.get(requirement_name(name))?;
+                    Some((name.clone(), version.clone()))
+                })
+                .collect()
+        }
+        Ok(output) => {
+            debug_detail!(
+                "Version lookup failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            HashMap::new()
+        }
+        Err(e) => {
+            debug_detail!("Version lookup failed: {}", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Fills in the versions that parallel workers left empty, using a single lookup
+pub(crate) fn resolve_missing_versions(python: &str, results: &mut [TimedResult]) {
+    let missing: Vec<String> = results
+        .iter()
+        .filter_map(|(_, result, _)| match result {
+            Ok((name, version)) if version.is_empty() => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+
+    let versions = get_installed_versions(python, &missing);
+    for (_, result, _) in results.iter_mut() {
+        if let Ok((name, version)) = result {
+            if version.is_empty() {
+                *version = versions
+                    .get(name.as_str())
+                    .cloned()
+                    .unwrap_or_else(|| "unknown".to_string());
+            }
+        }
+    }
+}
+
+/// Runs the external processes the package manager needs, such as pip and its helper scripts
+///
+/// [`SystemRunner`] starts real processes; [`ScriptedRunner`] answers from a
+/// script so the surrounding logic can be tested without a Python installation.
+/// The runner in use is chosen with [`set_command_runner`]; the async API
+/// starts its pip processes with tokio instead.
+pub trait CommandRunner: Send + Sync {
+    /// Runs a command to completion and captures its output
+    ///
+    /// # Arguments
+    /// * `command` - The command to run
+    ///
+    /// # Returns
+    /// * `std::io::Result<Output>` - The captured output, or the error starting the command
+    fn output(&self, command: &mut Command) -> std::io::Result<Output>;
+
+    /// Runs a command to completion, passing each line of output to `on_line` as it arrives
+    ///
+    /// `on_line` receives the line and whether it was written to stderr. The
+    /// default implementation replays the output of [`CommandRunner::output`]
+    /// once the command has finished.
+    ///
+    /// # Arguments
+    /// * `command` - The command to run
+    /// * `on_line` - Called for every line of stdout and stderr
+    ///
+    /// # Returns
+    /// * `std::io::Result<Output>` - The captured output, or the error starting the command
+    fn output_streaming(
+        &self,
+        command: &mut Command,
+        on_line: &(dyn Fn(&str, bool) + Sync),
+    ) -> std::io::Result<Output> {
+        let output = self.output(command)?;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            on_line(line, false);
+        }
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            on_line(line, true);
+        }
+        Ok(output)
+    }
+
+    /// Runs a command to completion with `input` written to its stdin
+    ///
+    /// # Arguments
+    /// * `command` - The command to run
+    /// * `input` - What the command reads from stdin
+    ///
+    /// # Returns
+    /// * `std::io::Result<Output>` - The captured output, or the error starting the command
+    fn output_with_input(&self, command: &mut Command, input: &[u8]) -> std::io::Result<Output>;
+}
+
+/// Runs commands as real child processes
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn output(&self, command: &mut Command) -> std::io::Result<Output> {
+        command.output()
+    }
+
+    fn output_streaming(
+        &self,
+        command: &mut Command,
+        on_line: &(dyn Fn(&str, bool) + Sync),
+    ) -> std::io::Result<Output> {
+        let mut child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout_pipe = child.stdout.take().expect("stdout is piped");
+        let stderr_pipe = child.stderr.take().expect("stderr is piped");
+
+        // Read both pipes at once so a chatty child cannot block on a full pipe
+        let read_lines = |pipe: &mut dyn Read, is_stderr: bool| -> std::io::Result<Vec<u8>> {
+            let mut captured = Vec::new();
+            for line in BufReader::new(pipe).split(b'\n') {
+                let line = line?;
+                on_line(&String::from_utf8_lossy(&line), is_stderr);
+                captured.extend_from_slice(&line);
+                captured.push(b'\n');
+            }
+            Ok(captured)
+        };
+        let (stdout, stderr) = std::thread::scope(|scope| {
+            let stderr = scope.spawn(|| {
+                let mut stderr_pipe = stderr_pipe;
+                read_lines(&mut stderr_pipe, true).unwrap_or_default()
+            });
+            let mut stdout_pipe = stdout_pipe;
+            let stdout = read_lines(&mut stdout_pipe, false);
+            (stdout, stderr.join().unwrap_or_default())
+        });
+
+        let status = child.wait()?;
+        Ok(Output {
+            status,
+            stdout: stdout?,
+            stderr,
+        })
+    }
+
+    fn output_with_input(&self, command: &mut Command, input: &[u8]) -> std::io::Result<Output> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+
+        // Write from another thread so a child answering before it has read
+        // everything cannot block on a full stdout pipe
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(move || stdin.write_all(input));
+            let output = child.wait_with_output();
+            match writer.join() {
+                // A child that exits without reading all of its input is not an error
+                Ok(Err(error)) if error.kind() != std::io::ErrorKind::BrokenPipe => Err(error),
+                _ => output,
+            }
+        })
+    }
+}
+
+/// One rule of a [`ScriptedRunner`]
+#[derive(Debug, Clone)]
+struct ScriptedResponse {
+    pattern: String,
+    exit_code: i32,
+    stdout: String,
+    stderr: String,
+    /// Installation report written to the path given with `--report`
+    report: Option<String>,
+}
+
+/// Answers commands from a script instead of starting processes
+///
+/// Each command is rendered as its program followed by its arguments,
+/// separated by spaces, and answered by the first rule whose pattern occurs in
+/// that line. Input given on stdin is appended to the line after ` <<< `. Commands no rule matches fail to start, like a missing program.
+/// Every command line is recorded and available from [`ScriptedRunner::calls`].
+#[derive(Debug, Default)]
+pub struct ScriptedRunner {
+    responses: Vec<ScriptedResponse>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl ScriptedRunner {
+    /// Creates a runner with an empty script, so every command fails to start
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a rule answering matching commands with success and the given stdout
+    ///
+    /// # Arguments
+    /// * `pattern` - Text the command line must contain
+    /// * `stdout` - What the command prints
+    pub fn respond(self, pattern: &str, stdout: &str) -> Self {
+        self.respond_with(pattern, 0, stdout, "")
+    }
+
+    /// Adds a rule answering matching commands with a failure
+    ///
+    /// # Arguments
+    /// * `pattern` - Text the command line must contain
+    /// * `exit_code` - Exit code of the command
+    /// * `stderr` - What the command prints to stderr
+    pub fn fail(self, pattern: &str, exit_code: i32, stderr: &str) -> Self {
+        self.respond_with(pattern, exit_code, "", stderr)
+    }
+
+    /// Adds a rule answering matching commands with success, writing an
+    /// installation report to the path the command gives with `--report`
+    ///
+    /// # Arguments
+    /// * `pattern` - Text the command line must contain
+    /// * `report` - The JSON installation report, as pip writes it
+    pub fn report(self, pattern: &str, report: &str) -> Self {
+        let mut runner = self.respond(pattern, "");
+        if let Some(response) = runner.responses.last_mut() {
+            response.report = Some(report.to_string());
+        }
+        runner
+    }
+
+    fn respond_with(mut self, pattern: &str, exit_code: i32, stdout: &str, stderr: &str) -> Self {
+        self.responses.push(ScriptedResponse {
+            pattern: pattern.to_string(),
+            exit_code,
+            stdout: stdout.to_string(),
+            stderr: stderr.to_string(),
+            report: None,
+        });
+        self
+    }
+
+    /// Command lines run so far, in order
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl CommandRunner for ScriptedRunner {
+    fn output(&self, command: &mut Command) -> std::io::Result<Output> {
+        self.answer(command, command_line(command))
+    }
+
+    fn output_with_input(&self, command: &mut Command, input: &[u8]) -> std::io::Result<Output> {
+        let line = format!(
+            "{} <<< {}",
+            command_line(command),
+            String::from_utf8_lossy(input)
+        );
+        self.answer(command, line)
+    }
+}
+
+impl ScriptedRunner {
+    /// Records `line` and answers it with the first matching rule
+    fn answer(&self, command: &Command, line: String) -> std::io::Result<Output> {
+        self.calls.lock().unwrap().push(line.clone());
+
+        let response = self
+            .responses
+            .iter()
+            .find(|response| line.contains(&response.pattern))
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("no scripted response for `{}`", line),
+                )
+            })?;
+        if let Some(report) = &response.report {
+            let path = command
+                .get_args()
+                .skip_while(|arg| *arg != "--report")
+                .nth(1)
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidInput,
+                        format!("`{}` does not ask for a report", line),
+                    )
+                })?;
+            std::fs::write(path, report)?;
+        }
+        Ok(Output {
+            status: exit_status(response.exit_code),
+            stdout: response.stdout.clone().into_bytes(),
+            stderr: response.stderr.clone().into_bytes(),
+        })
+    }
+}
+
+/// Renders a command as its program followed by its arguments, separated by spaces
+fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| part.to_string_lossy())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Builds the exit status of a process that exited with `code`
+#[cfg(unix)]
+fn exit_status(code: i32) -> std::process::ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    std::process::ExitStatus::from_raw(code << 8)
+}
+
+/// Builds the exit status of a process that exited with `code`
+#[cfg(windows)]
+fn exit_status(code: i32) -> std::process::ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    std::process::ExitStatus::from_raw(code as u32)
+}
+
+/// Runner used for every pip and interpreter invocation; `None` means the default
+pub(crate) static COMMAND_RUNNER: Mutex<Option<Arc<dyn CommandRunner>>> = Mutex::new(None);
+
+/// Makes every later pip and interpreter invocation go through `runner`
+///
+/// # Arguments
+/// * `runner` - The runner to use from now on
+pub fn set_command_runner(runner: Arc<dyn CommandRunner>) {
+    *COMMAND_RUNNER.lock().unwrap() = Some(runner);
+}
+
+/// Returns the runner for pip and interpreter invocations
+///
+/// Unless one was set with [`set_command_runner`], this is a [`SystemRunner`];
+/// the unit tests instead default to an empty [`ScriptedRunner`] so they never
+/// touch the machine's Python.
+pub fn command_runner() -> Arc<dyn CommandRunner> {
+    if let Some(runner) = COMMAND_RUNNER.lock().unwrap().as_ref() {
+        return Arc::clone(runner);
+    }
+    if cfg!(test) {
+        Arc::new(ScriptedRunner::new())
+    } else {
+        Arc::new(SystemRunner)
+    }
+}
+
+/// Runs a command to completion through the current [`CommandRunner`]
+///
+/// With local packages (PEP 582) on, the command is pointed at them first.
+pub(crate) fn run_command(command: &mut Command) -> std::io::Result<Output> {
+    apply_local_packages(command);
+    command_runner().output(command)
+}
+
+/// Runs a command like [`run_command`], writing `input` to its stdin
+pub(crate) fn run_command_with_input(
+    command: &mut Command,
+    input: &[u8],
+) -> std::io::Result<Output> {
+    apply_local_packages(command);
+    command_runner().output_with_input(command, input)
+}
+
+/// Runs pip while reading its output line by line
+///
+/// Behaves like [`Command::output`], so the output is still captured for
+/// error reporting. With an observer, the state parsed from pip's output is
+/// reported to it as the progress of `label`. In verbose mode every line is
+/// echoed as it arrives, prefixed with `label`.
+///
+/// With package indexes configured on the HTTP client, commands that resolve
+/// or download packages fail over to the next index when one cannot be reached.
+pub(crate) fn run_pip(
+    command: Command,
+    label: &str,
+    observer: Option<&dyn InstallObserver>,
+) -> Result<Output> {
+    run_pip_with_index(command, label, observer).map(|(output, _)| output)
+}
+
+/// Runs pip like [`run_pip`] and also returns the configured index that served it
+///
+/// The index is `None` when no indexes are configured or the command does not
+/// use one, e.g. because it passes `--index-url` or `--no-index` itself.
+pub(crate) fn run_pip_with_index(
+    command: Command,
+    label: &str,
+    observer: Option<&dyn InstallObserver>,
+) -> Result<(Output, Option<String>)> {
+    policy::check_pip_command(&command)?;
+    let indexes = http_client().index_urls().to_vec();
+    let args: Vec<String> = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let resolves = args
+        .iter()
+        .any(|arg| matches!(arg.as_str(), "install" | "download" | "wheel" | "index"));
+    let chooses_index = args.iter().any(|arg| {
+        matches!(arg.as_str(), "-i" | "--index-url" | "--no-index")
+            || arg.starts_with("--index-url=")
+    });
+    let Some((last, preferred)) = indexes.split_last().filter(|_| resolves && !chooses_index)
+    else {
+        return run_pip_once(command, label, observer).map(|output| (output, None));
+    };
+
+    let on_index = |index: &str| {
+        let mut attempt = Command::new(command.get_program());
+        attempt
+            .args(command.get_args())
+            .arg("--index-url")
+            .arg(index);
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => attempt.env(key, value),
+                None => attempt.env_remove(key),
+            };
+        }
+        if let Some(dir) = command.get_current_dir() {
+            attempt.current_dir(dir);
+        }
+        run_pip_once(attempt, label, observer)
+    };
+    for (position, index) in preferred.iter().enumerate() {
+        let output = on_index(index)?;
+        if output.status.success() || !is_index_failure(&String::from_utf8_lossy(&output.stderr)) {
+            return Ok((output, Some(index.clone())));
+        }
+        warning!(
+            "[{}] Index {} could not be reached; falling back to {}",
+            label,
+            index,
+            indexes[position + 1]
+        );
+    }
+    Ok((on_index(last)?, Some(last.clone())))
+}
+
+/// Checks pip's stderr for an index that could not be reached or answered with a server error
+fn is_index_failure(stderr: &str) -> bool {
+    NETWORK_ERROR_MARKERS
+        .iter()
+        .chain(&[
+            "Read timed out",
+            "ConnectTimeoutError",
+            "Could not fetch URL",
+            "HTTP error 5",
+        ])
+        .any(|marker| stderr.contains(marker))
+}
+
+/// Runs a single pip command, see [`run_pip`]
+fn run_pip_once(
+    mut command: Command,
+    label: &str,
+    observer: Option<&dyn InstallObserver>,
+) -> Result<Output> {
+    let args: Vec<String> = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect();
+    let span = tracing::info_span!("pip", package = label, args = %args.join(" "));
+    let _entered = span.enter();
+
+    command.envs(http_client().pip_env());
+    apply_local_packages(&mut command);
+    debug_detail!("[{}] running {:?}", label, command);
+    let started = Instant::now();
+    let on_line = |line: &str, is_stderr: bool| {
+        if let (false, Some(observer), Some(state)) = (is_stderr, observer, pip_state(line)) {
+            observer.on_progress(label, state);
+        }
+        match (is_verbose(), observer) {
+            (false, _) => {}
+            (true, Some(observer)) => observer.on_output(label, line, is_stderr),
+            (true, None) => echo_pip_line(label, line, is_stderr),
+        }
+    };
+    let output = command_runner().output_streaming(&mut command, &on_line)?;
+
+    tracing::info!(
+        status = %output.status,
+        duration_ms = started.elapsed().as_millis() as u64,
+        "pip finished"
+    );
+    tracing::debug!(
+        stdout = %String::from_utf8_lossy(&output.stdout),
+        stderr = %String::from_utf8_lossy(&output.stderr),
+        "pip output"
+    );
+
+    Ok(output)
+}
+
+/// Reports a line of pip output prefixed with the package it belongs to
+pub(crate) fn echo_pip_line(label: &str, line: &str, is_stderr: bool) {
+    let kind = if is_stderr {
+        MessageKind::Diagnostic
+    } else {
+        MessageKind::Detail
+    };
+    report(kind, format_args!("[{}] {}", label, line));
+}
+
+/// Turns a failed pip invocation into the most specific error
+///
+/// # Arguments
+/// * `package` - Package (or packages) pip was working on
+/// * `output` - Captured output of pip
+/// * `fallback` - Builds the error for failures that match no known class
+pub(crate) fn pip_error(
+    package: &str,
+    output: &Output,
+    fallback: impl FnOnce(String) -> PackageError,
+) -> PackageError {
+    classify_pip_failure(
+        package,
+        output.status.code(),
+        &String::from_utf8_lossy(&output.stderr),
+        fallback,
+    )
+}
+
+/// Classifies pip's stderr into a failure class
+fn classify_pip_failure(
+    package: &str,
+    exit_status: Option<i32>,
+    stderr: &str,
+    fallback: impl FnOnce(String) -> PackageError,
+) -> PackageError {
+    let failure = PipFailure {
+        package: package.to_string(),
+        exit_status,
+        stderr: trim_pip_stderr(stderr),
+    };
+    let mentions = |needles: &[&str]| needles.iter().any(|needle| stderr.contains(needle));
+
+    let mismatches = parse_hash_mismatches(stderr);
+    if !mismatches.is_empty() {
+        PackageError::HashMismatch(mismatches)
+    } else if mentions(&["Permission denied", "[Errno 13]"]) {
+        PackageError::PermissionDenied(failure)
+    } else if mentions(&["Read timed out", "ConnectTimeoutError", "TimeoutError"]) {
+        PackageError::Timeout(failure)
+    } else if mentions(NETWORK_ERROR_MARKERS) {
+        PackageError::NetworkError(failure)
+    } else if mentions(&["ResolutionImpossible", "conflicting dependencies"]) {
+        PackageError::ResolutionConflict(failure)
+    } else if mentions(&[
+        "Failed building wheel",
+        "Failed to build",
+        "subprocess-exited-with-error",
+        "legacy-install-failure",
+    ]) {
+        PackageError::BuildFailed(failure)
+    } else {
+        fallback(failure.stderr)
+    }
+}
+
+/// Fragments of pip's stderr showing that the package index could not be reached
+pub(crate) const NETWORK_ERROR_MARKERS: &[&str] = &[
+    "NewConnectionError",
+    "Failed to establish a new connection",
+    "Name or service not known",
+    "Temporary failure in name resolution",
+    "ProxyError",
+    "SSLError",
+];
+
+/// Reads the archives pip rejected in `--require-hashes` mode from its stderr
+///
+/// pip lists each of them under "THESE PACKAGES DO NOT MATCH THE HASHES" as
+/// `<requirement> from <url> (from ...):`, followed by the expected hashes and
+/// the one it got.
+pub(crate) fn parse_hash_mismatches(stderr: &str) -> Vec<HashMismatch> {
+    let Some(start) = stderr.find("DO NOT MATCH THE HASHES") else {
+        return Vec::new();
+    };
+    let mut mismatches = Vec::new();
+    let mut current: Option<HashMismatch> = None;
+    for line in stderr[start..].lines().skip(1) {
+        let line = line.trim();
+        if let Some(hash) = line
+            .strip_prefix("Expected sha256 ")
+            .or_else(|| line.strip_prefix("or sha256 "))
+        {
+            if let Some(mismatch) = current.as_mut() {
+                mismatch.expected.push(hash.trim().to_string());
+            }
+        } else if let Some(hash) = line.strip_prefix("Got ") {
+            if let Some(mut mismatch) = current.take() {
+                mismatch.actual = hash.trim().to_string();
+                mismatches.push(mismatch);
+            }
+        } else if let Some((requirement, rest)) = line.split_once(" from ") {
+            let url = rest.split(" (from ").next().unwrap_or(rest);
+            current = Some(HashMismatch {
+                requirement: requirement.to_string(),
+                url: url.trim_end_matches(':').to_string(),
+                expected: Vec::new(),
+                actual: String::new(),
+            });
+        }
+    }
+    mismatches
+}
+
+/// Strips pip's boilerplate warnings and keeps the last lines of its stderr
+fn trim_pip_stderr(stderr: &str) -> String {
+    const MAX_LINES: usize = 20;
+    let lines: Vec<&str> = stderr
+        .lines()
+        .filter(|line| {
+            !line.trim().is_empty()
+                && !line.starts_with("WARNING: Running pip as the 'root' user")
+                && !line.starts_with("[notice]")
+        })
+        .collect();
+    lines[lines.len().saturating_sub(MAX_LINES)..].join("\n")
+}
+
+/// Maps a line of pip output to the state of the package being processed
+fn pip_state(line: &str) -> Option<&'static str> {
+    let line = line.trim_start();
+    if line.starts_with("Collecting ") {
+        Some("resolving")
+    } else if line.starts_with("Downloading ") || line.starts_with("Using cached ") {
+        Some("downloading")
+    } else if line.starts_with("Building wheel") || line.starts_with("Preparing metadata") {
+        Some("building")
+    } else if line.starts_with("Installing collected packages") {
+        Some("installing")
+    } else {
+        None
+    }
+}
+
+/// Installed distribution details reported by `pip show`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ShownPackage {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    pub(crate) requires: Vec<String>,
+}
+
+/// A distribution pip installed, as listed in its `--report` JSON
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ReportedInstall {
+    pub(crate) name: String,
+    pub(crate) version: String,
+    pub(crate) requested: bool,
+    pub(crate) origin: Option<PackageOrigin>,
+    /// The `License` field of the distribution's metadata
+    pub(crate) license: Option<String>,
+    /// The SPDX `License-Expression` field of the distribution's metadata
+    pub(crate) license_expression: Option<String>,
+    /// The distribution's classifiers, e.g. "License :: OSI Approved :: MIT License"
+    pub(crate) classifiers: Vec<String>,
+}
+
+/// Top level of pip's installation report
+#[derive(Debug, Deserialize)]
+struct PipReport {
+    #[serde(default)]
+    install: Vec<PipReportItem>,
+}
+
+/// One entry of the `install` array of pip's installation report
+#[derive(Debug, Deserialize)]
+struct PipReportItem {
+    metadata: PipReportMetadata,
+    #[serde(default)]
+    is_direct: bool,
+    #[serde(default)]
+    requested: bool,
+    download_info: Option<PipDownloadInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipReportMetadata {
+    name: String,
+    version: String,
+    #[serde(default)]
+    license: Option<String>,
+    #[serde(default)]
+    license_expression: Option<String>,
+    #[serde(default, rename = "classifier")]
+    classifiers: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipDownloadInfo {
+    url: String,
+    archive_info: Option<PipArchiveInfo>,
+    vcs_info: Option<PipVcsInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipArchiveInfo {
+    hash: Option<String>,
+    #[serde(default)]
+    hashes: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PipVcsInfo {
+    commit_id: String,
+}
+
+/// Parses pip's `--report` JSON into the distributions it installed
+fn parse_pip_report(json: &str) -> Result<Vec<ReportedInstall>> {
+    let report: PipReport = serde_json::from_str(json)?;
+    Ok(report
+        .install
+        .into_iter()
+        .map(|item| ReportedInstall {
+            name: item.metadata.name,
+            version: item.metadata.version,
+            requested: item.requested,
+            origin: item.download_info.map(|info| PackageOrigin {
+                hash: info.archive_info.and_then(|archive| {
+                    archive
+                        .hashes
+                        .get("sha256")
+                        .map_or(archive.hash, |digest| Some(format!("sha256={}", digest)))
+                }),
+                commit: info.vcs_info.map(|vcs| vcs.commit_id),
+                direct: item.is_direct,
+                url: info.url,
+            }),
+            license: item.metadata.license,
+            license_expression: item.metadata.license_expression,
+            classifiers: item.metadata.classifiers,
+        })
+        .collect())
+}
+
+/// A pip capability ppm relies on, available from a certain pip release
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PipFeature {
+    /// Installation reports (`--report`), which record the exact versions and
+    /// archive hashes of an install; locking and attestation checks need them
+    InstallReport,
+    /// Resolving without installing (`install --dry-run`)
+    DryRun,
+}
+
+impl PipFeature {
+    /// Every feature, in the order `ppm pip version` lists them
+    pub const ALL: [PipFeature; 2] = [PipFeature::InstallReport, PipFeature::DryRun];
+
+    /// First pip release with a stable version of the feature
+    pub fn minimum_version(self) -> &'static str {
+        match self {
+            PipFeature::InstallReport => "23.0",
+            PipFeature::DryRun => "22.2",
+        }
+    }
+
+    /// Short description, e.g. "installation reports (--report)"
+    pub fn description(self) -> &'static str {
+        match self {
+            PipFeature::InstallReport => "installation reports (--report)",
+            PipFeature::DryRun => "dry runs (install --dry-run)",
+        }
+    }
+}
+
+/// The pip of the selected interpreter
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PipStatus {
+    /// The interpreter running pip
+    pub python: String,
+    /// pip's version, or None if pip could not be run
+    pub version: Option<String>,
+}
+
+impl PipStatus {
+    /// Looks up the pip of the interpreter commands run with
+    ///
+    /// # Returns
+    /// * `Result<PipStatus>` - The interpreter and its pip version, or an error if no interpreter was found
+    pub fn current() -> Result<Self> {
+        let python = get_python_executable()?;
+        let version = pip_version(&python);
+        Ok(Self { python, version })
+    }
+
+    /// Whether this pip is new enough for a feature
+    pub fn supports(&self, feature: PipFeature) -> bool {
+        self.version.as_deref().is_some_and(|version| {
+            compare_versions(version, feature.minimum_version()) != Ordering::Less
+        })
+    }
+}
+
+/// pip version of each interpreter, `None` when it has no working pip
+static PIP_VERSIONS: Mutex<Option<HashMap<String, Option<String>>>> = Mutex::new(None);
+
+/// Interpreters and features already warned about, so each warning shows once
+static PIP_WARNINGS: Mutex<Vec<(String, PipFeature)>> = Mutex::new(Vec::new());
+
+/// Name of the installation report inside the directory of [`request_pip_report`]
+const PIP_REPORT_FILE: &str = "report.json";
+
+/// Gets the version of an interpreter's pip, asking it once per run
+///
+/// # Arguments
+/// * `python` - The interpreter running pip
+///
+/// # Returns
+/// * `Option<String>` - The version, e.g. "24.0", or None if pip could not be run
+pub fn pip_version(python: &str) -> Option<String> {
+    let mut cache = PIP_VERSIONS.lock().unwrap();
+    cache
+        .get_or_insert_with(HashMap::new)
+        .entry(python.to_string())
+        .or_insert_with(|| {
+            run_command(Command::new(python).args(["-m", "pip", "--version"]))
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| {
+                    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+                    stdout.split_whitespace().nth(1).map(str::to_string)
+                })
+        })
+        .clone()
+}
+
+/// Checks whether an interpreter's pip is new enough for a feature
+///
+/// # Arguments
+/// * `python` - The interpreter running pip
+/// * `feature` - The capability to check
+///
+/// # Returns
+/// `true` if pip is at least [`PipFeature::minimum_version`]
+pub fn pip_supports(python: &str, feature: PipFeature) -> bool {
+    PipStatus {
+        python: python.to_string(),
+        version: pip_version(python),
+    }
+    .supports(feature)
+}
+
+/// Explains why a feature is unavailable and how to get it
+fn old_pip_message(python: &str, feature: PipFeature) -> String {
+    let found = match pip_version(python) {
+        Some(version) => format!("pip {}", version),
+        None => "no working pip".to_string(),
+    };
+    format!(
+        "pip {} or newer is needed for {}, but {} has {}; run `ppm pip upgrade` to update it",
+        feature.minimum_version(),
+        feature.description(),
+        python,
+        found
+    )
+}
+
+/// Fails with upgrade advice unless an interpreter's pip supports a feature
+///
+/// # Arguments
+/// * `python` - The interpreter running pip
+/// * `feature` - The capability the operation cannot do without
+/// * `operation` - What needs it, e.g. "Locking"
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error naming the pip version found
+pub(crate) fn require_pip_feature(
+    python: &str,
+    feature: PipFeature,
+    operation: &str,
+) -> Result<()> {
+    if pip_supports(python, feature) {
+        return Ok(());
+    }
+    Err(PackageError::MetadataError(format!(
+        "{} is not possible: {}",
+        operation,
+        old_pip_message(python, feature)
+    )))
+}
+
+/// Warns once per interpreter when its pip lacks a feature ppm can do without
+fn warn_old_pip(python: &str, feature: PipFeature) {
+    let mut warned = PIP_WARNINGS.lock().unwrap();
+    if warned.iter().any(|(p, f)| p == python && *f == feature) {
+        return;
+    }
+    warned.push((python.to_string(), feature));
+    warning!(
+        "{}. Installs still work, but the registry cannot record where packages came from",
+        old_pip_message(python, feature)
+    );
+}
+
+/// Upgrades the pip of the selected interpreter to the newest release
+///
+/// # Returns
+/// * `Result<(PipStatus, String)>` - The pip before the upgrade and the version
+///   installed, or an error if pip could not be upgraded
+pub fn upgrade_pip() -> Result<(PipStatus, String)> {
+    let before = PipStatus::current()?;
+    let python = before.python.as_str();
+    let mut command = Command::new(python);
+    // pip belongs to the interpreter, not to the project's local packages
+    command
+        .args(["-m", "pip", "install", "--upgrade", "pip"])
+        .env_remove("PIP_TARGET");
+    let output = run_pip(command, "pip", None)?;
+    if !output.status.success() {
+        return Err(pip_error("pip", &output, PackageError::InstallationFailed));
+    }
+
+    if let Some(cache) = PIP_VERSIONS.lock().unwrap().as_mut() {
+        cache.remove(python);
+    }
+    PIP_WARNINGS.lock().unwrap().retain(|(p, _)| p != python);
+    let after = pip_version(python).ok_or_else(|| {
+        PackageError::InstallationFailed(format!("pip no longer runs in {}", python))
+    })?;
+    Ok((before, after))
+}
+
+/// Asks pip for an installation report when it supports one
+///
+/// pip writes the report into a fresh directory only the current user can
+/// open, so nobody can plant or swap the report the install is judged by.
+///
+/// # Arguments
+/// * `python` - The interpreter running pip
+/// * `command` - The `pip install` command to extend
+///
+/// # Returns
+/// The directory pip will write the report to, for [`take_pip_report`]
+pub(crate) fn request_pip_report(python: &str, command: &mut Command) -> Option<TempDir> {
+    if !pip_supports(python, PipFeature::InstallReport) {
+        warn_old_pip(python, PipFeature::InstallReport);
+        return None;
+    }
+    let dir = match tempfile::Builder::new().prefix("ppm-report-").tempdir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            debug_detail!(
+                "Could not create a directory for pip's installation report: {}",
+                e
+            );
+            return None;
+        }
+    };
+    command
+        .arg("--report")
+        .arg(dir.path().join(PIP_REPORT_FILE));
+    Some(dir)
+}
+
+/// Reads and removes an installation report requested with [`request_pip_report`]
+pub(crate) fn take_pip_report(dir: Option<TempDir>) -> Vec<ReportedInstall> {
+    let Some(dir) = dir else {
+        return Vec::new();
+    };
+    match std::fs::read_to_string(dir.path().join(PIP_REPORT_FILE))
+        .map_err(PackageError::from)
+        .and_then(|json| parse_pip_report(&json))
+    {
+        Ok(installs) => installs,
+        Err(e) => {
+            debug_detail!("Could not read pip's installation report: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Resolves a `pip install` command without installing anything
+///
+/// Runs the command again with `--dry-run` and reads the installation
+/// report, which lists every distribution it would install, dependencies
+/// included, with their metadata. The dry run is not checked against the
+/// package policy, so the policy can use it.
+///
+/// # Arguments
+/// * `command` - The pip command, e.g. `python -m pip install requests`
+///
+/// # Returns
+/// * `Result<Vec<ReportedInstall>>` - The distributions pip would install, or
+///   an error if pip is too old or could not resolve the command
+pub(crate) fn resolve_pip_install(command: &Command) -> Result<Vec<ReportedInstall>> {
+    let python = command.get_program().to_string_lossy().into_owned();
+    let operation = "Checking the dependencies against the package policy";
+    require_pip_feature(&python, PipFeature::DryRun, operation)?;
+    require_pip_feature(&python, PipFeature::InstallReport, operation)?;
+    let dir = tempfile::Builder::new().prefix("ppm-report-").tempdir()?;
+    let report = dir.path().join(PIP_REPORT_FILE);
+
+    let mut dry_run = Command::new(command.get_program());
+    dry_run
+        .args(command.get_args())
+        .args(["--dry-run", "--quiet", "--report"])
+        .arg(&report);
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => dry_run.env(key, value),
+            None => dry_run.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        dry_run.current_dir(dir);
+    }
+    let output = run_command(&mut dry_run)?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(PackageError::PackageDenied(format!(
+            "could not resolve the dependencies to check them against the package policy: {}",
+            stderr
+                .lines()
+                .rev()
+                .find(|line| !line.trim().is_empty())
+                .unwrap_or("pip failed")
+                .trim()
+        )));
+    }
+    parse_pip_report(&std::fs::read_to_string(&report)?)
+}
+
+/// Records the exact versions and origins from an installation report in the registry
+///
+/// Packages pip installed as dependencies are registered as not explicitly requested.
+pub(crate) fn apply_pip_report(installs: &[ReportedInstall], registry: &mut PackageRegistry) {
+    for install in installs {
+        let key = registry
+            .find_package_key(&install.name)
+            .unwrap_or_else(|| canonicalize_name(&install.name));
+        let mut package = registry.get_package(&key).cloned().unwrap_or_else(|| {
+            let mut package = Package::new(key.clone(), install.version.clone());
+            package.explicit = install.requested;
+            package
+        });
+        package.version = install.version.clone();
+        package.explicit |= install.requested;
+        if install.origin.is_some() {
+            package.origin = install.origin.clone();
+        }
+        registry.add_package(package);
+    }
+}
+
+/// Version pip reported for a package, if it appears in the report
+pub(crate) fn reported_version(installs: &[ReportedInstall], name: &str) -> Option<String> {
+    let wanted = canonicalize_name(requirement_name(name));
+    installs
+        .iter()
+        .find(|install| canonicalize_name(&install.name) == wanted)
+        .map(|install| install.version.clone())
+}
+
+/// Runs `pip show` for several packages at once
+///
+/// Packages that are not installed are silently left out of the result.
+pub(crate) fn show_packages(python: &str, names: &[String]) -> Result<Vec<ShownPackage>> {
+    let output = run_command(
+        Command::new(python)
+            .arg("-m")
+            .arg("pip")
+            .arg("show")
+            .args(names),
+    )?;
+
+    // pip exits non-zero when any package is missing but still reports the others
+    Ok(parse_pip_show_output(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
+
+/// Parses the `---`-separated records printed by `pip show`
+fn parse_pip_show_output(stdout: &str) -> Vec<ShownPackage> {
+    stdout
+        .split("\n---")
+        .filter_map(|record| {
+            let mut name = None;
+            let mut version = None;
+            let mut requires = Vec::new();
+            for line in record.lines() {
+                if let Some(value) = line.strip_prefix("Name: ") {
+                    name = Some(value.trim().to_string());
+                } else if let Some(value) = line.strip_prefix("Version: ") {
+                    version = Some(value.trim().to_string());
+                } else if let Some(value) = line.strip_prefix("Requires:") {
+                    requires = value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|r| !r.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+            }
+            Some(ShownPackage {
+                name: name?,
+                version: version.unwrap_or_else(|| "unknown".to_string()),
+                requires,
+            })
+        })
+        .collect()
+}
+
+/// Python snippet that prints a JSON object mapping each installed package named on the
+/// command line to its version, looking only in `PPM_LOCAL_PACKAGES` when that is set
+pub(crate) const INSTALLED_VERSIONS_SCRIPT: &str = r#"
+import json, os, sys
+from importlib import metadata
+local = os.environ.get("PPM_LOCAL_PACKAGES")
+where = {"path": [local]} if local else {}
+versions = {}
+for name in sys.argv[1:]:
+    found = next(iter(metadata.distributions(name=name, **where)), None)
+    if found is not None:
+        versions[name] = found.version
+print(json.dumps(versions))
+"#;
+
+/// Python snippet that prints the interpreter version
+const PYTHON_VERSION_SCRIPT: &str = "import platform; print(platform.python_version())";
+
+/// Queries the version of a Python interpreter
+pub(crate) fn get_python_version(python: &str) -> Result<String> {
+    let output = run_command(Command::new(python).arg("-c").arg(PYTHON_VERSION_SCRIPT))?;
+
+    if !output.status.success() {
+        return Err(PackageError::PythonNotFound);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reporter::{set_reporter, set_verbosity, Verbosity, REPORTER};
+    use crate::test_support::{with_runner, RecordingReporter};
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_pip_show_output() {
+        let stdout = "Name: requests\nVersion: 2.32.3\nSummary: HTTP\nRequires: certifi, charset-normalizer, idna, urllib3\nRequired-by: \n---\nName: idna\nVersion: 3.7\nRequires: \nRequired-by: requests\n";
+        let shown = parse_pip_show_output(stdout);
+
+        assert_eq!(shown.len(), 2);
+        assert_eq!(shown[0].name, "requests");
+        assert_eq!(shown[0].version, "2.32.3");
+        assert_eq!(
+            shown[0].requires,
+            vec!["certifi", "charset-normalizer", "idna", "urllib3"]
+        );
+        assert!(shown[1].requires.is_empty());
+    }
+
+    #[test]
+    fn test_pip_state() {
+        assert_eq!(pip_state("Collecting requests==2.31.0"), Some("resolving"));
+        assert_eq!(
+            pip_state("  Downloading requests-2.31.0-py3-none-any.whl (62 kB)"),
+            Some("downloading")
+        );
+        assert_eq!(
+            pip_state("  Building wheel for pyyaml (pyproject.toml): started"),
+            Some("building")
+        );
+        assert_eq!(
+            pip_state("Installing collected packages: urllib3, requests"),
+            Some("installing")
+        );
+        assert_eq!(pip_state("Successfully installed requests-2.31.0"), None);
+    }
+
+    #[test]
+    fn test_classify_pip_failure() {
+        use std::error::Error;
+
+        let classify = |stderr: &str| {
+            classify_pip_failure("numpy", Some(1), stderr, PackageError::InstallationFailed)
+        };
+
+        let offline = "WARNING: Retrying (Retry(total=0)) after connection broken by \
+            'NewConnectionError(... Name or service not known)': /simple/numpy/\n\
+            ERROR: No matching distribution found for numpy\n";
+        let error = classify(offline);
+        assert!(matches!(error, PackageError::NetworkError(_)));
+        assert_eq!(error.code(), "PPM0010");
+        let failure = error.pip_failure().unwrap();
+        assert_eq!(failure.package, "numpy");
+        assert_eq!(failure.exit_status, Some(1));
+        assert_eq!(
+            failure.reason(),
+            "ERROR: No matching distribution found for numpy"
+        );
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            "pip exited with status 1: ERROR: No matching distribution found for numpy"
+        );
+
+        assert!(matches!(
+            classify("ERROR: ResolutionImpossible: for help visit ..."),
+            PackageError::ResolutionConflict(_)
+        ));
+        assert!(matches!(
+            classify(
+                "  error: subprocess-exited-with-error\nERROR: Failed building wheel for numpy"
+            ),
+            PackageError::BuildFailed(_)
+        ));
+        assert!(matches!(
+            classify(
+                "ERROR: Could not install packages due to an OSError: [Errno 13] Permission denied"
+            ),
+            PackageError::PermissionDenied(_)
+        ));
+        assert!(matches!(
+            classify("pip._vendor.urllib3.exceptions.ReadTimeoutError: Read timed out."),
+            PackageError::Timeout(_)
+        ));
+        assert!(matches!(
+            classify("WARNING: Running pip as the 'root' user can result in ...\nERROR: oops"),
+            PackageError::InstallationFailed(msg) if msg == "ERROR: oops"
+        ));
+    }
+
+    #[test]
+    fn test_parse_hash_mismatches() {
+        let stderr = "Processing /tmp/wheels/alpha-1.0-py3-none-any.whl (from -r r.txt (line 1))\n\
+            ERROR: THESE PACKAGES DO NOT MATCH THE HASHES FROM THE REQUIREMENTS FILE. If you \
+            have updated the package versions, please update the hashes.\n    \
+            alpha==1.0 from https://files.example.org/packages/ab/alpha-1.0-py3-none-any.whl#sha256=26e1 \
+            (from -r r.txt (line 1)):\n        \
+            Expected sha256 0000\n             \
+            or sha256 1111\n             \
+            Got        26e1\n";
+        let error =
+            classify_pip_failure("alpha", Some(1), stderr, PackageError::InstallationFailed);
+        assert_eq!(error.code(), "PPM0017");
+        let PackageError::HashMismatch(mismatches) = &error else {
+            panic!("expected a hash mismatch, got {:?}", error);
+        };
+        assert_eq!(mismatches.len(), 1);
+        let mismatch = &mismatches[0];
+        assert_eq!(mismatch.requirement, "alpha==1.0");
+        assert_eq!(mismatch.expected, vec!["0000", "1111"]);
+        assert_eq!(mismatch.actual, "26e1");
+        assert_eq!(mismatch.file_name(), "alpha-1.0-py3-none-any.whl");
+        assert_eq!(mismatch.source(), "https://files.example.org/packages/ab");
+        assert_eq!(
+            error.diagnose().unwrap().details,
+            vec![
+                "alpha-1.0-py3-none-any.whl (alpha==1.0) from https://files.example.org/packages/ab: \
+                 expected sha256 0000 or 1111, got 26e1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_pip_report() {
+        let report = r#"{
+            "version": "1",
+            "pip_version": "24.0",
+            "install": [
+                {
+                    "download_info": {
+                        "url": "https://files.pythonhosted.org/packages/requests-2.31.0-py3-none-any.whl",
+                        "archive_info": {"hash": "sha256=58cd", "hashes": {"sha256": "58cd"}}
+                    },
+                    "is_direct": false,
+                    "requested": true,
+                    "metadata": {"name": "requests", "version": "2.31.0"}
+                },
+                {
+                    "download_info": {
+                        "url": "https://github.com/pallets/click",
+                        "vcs_info": {"vcs": "git", "commit_id": "8f5e2c1"}
+                    },
+                    "is_direct": true,
+                    "requested": false,
+                    "metadata": {"name": "Click", "version": "8.2.0.dev0"}
+                }
+            ]
+        }"#;
+
+        let installs = parse_pip_report(report).unwrap();
+        assert_eq!(
+            reported_version(&installs, "requests[socks]").as_deref(),
+            Some("2.31.0")
+        );
+        assert_eq!(
+            installs[0].origin.as_ref().unwrap().hash.as_deref(),
+            Some("sha256=58cd")
+        );
+
+        let mut registry = PackageRegistry::new();
+        let mut pinned = Package::new("requests".to_string(), "2.28.0".to_string());
+        pinned.pinned = true;
+        registry.add_package(pinned);
+        apply_pip_report(&installs, &mut registry);
+
+        let requests = registry.get_package("requests").unwrap();
+        assert_eq!(requests.version, "2.31.0");
+        assert!(requests.pinned && requests.explicit);
+        let click = registry.get_package("click").unwrap();
+        assert!(!click.explicit);
+        let origin = click.origin.as_ref().unwrap();
+        assert!(origin.direct);
+        assert_eq!(origin.commit.as_deref(), Some("8f5e2c1"));
+    }
+
+    #[test]
+    fn test_resolve_missing_versions_keeps_known_versions() {
+        let mut results: Vec<TimedResult> = vec![
+            (
+                "numpy==1.26.4".to_string(),
+                Ok(("numpy".to_string(), "1.26.4".to_string())),
+                Duration::ZERO,
+            ),
+            (
+                "rich".to_string(),
+                Ok(("rich".to_string(), String::new())),
+                Duration::ZERO,
+            ),
+            (
+                "nosuchpkg".to_string(),
+                Err(PackageError::PackageNotFound("nosuchpkg".to_string())),
+                Duration::ZERO,
+            ),
+        ];
+
+        // Only the package without a version is looked up
+        let runner =
+            ScriptedRunner::new().respond(INSTALLED_VERSIONS_SCRIPT, r#"{"rich": "13.7.0"}"#);
+        let calls = with_runner(runner, |runner| {
+            resolve_missing_versions("/venv/bin/python", &mut results);
+            runner.calls()
+        });
+        let versions: Vec<Option<&str>> = results
+            .iter()
+            .map(|(_, result, _)| result.as_ref().ok().map(|(_, v)| v.as_str()))
+            .collect();
+        assert_eq!(versions, vec![Some("1.26.4"), Some("13.7.0"), None]);
+        assert_eq!(calls.len(), 1);
+        assert!(calls[0].ends_with(" rich"));
+
+        // A package the lookup does not find is recorded as unknown
+        results[1].1 = Ok(("rich".to_string(), String::new()));
+        with_runner(ScriptedRunner::new(), |_| {
+            resolve_missing_versions("/venv/bin/python", &mut results)
+        });
+        assert_eq!(results[1].1.as_ref().unwrap().1, "unknown");
+    }
+
+    #[test]
+    fn test_pip_feature_support() {
+        let runner = ScriptedRunner::new().respond(
+            "/opt/pip22/bin/python -m pip --version",
+            "pip 22.3.1 from /opt/pip22/lib/pip (python 3.10)\n",
+        );
+        with_runner(runner, |_| {
+            let python = "/opt/pip22/bin/python";
+            assert_eq!(pip_version(python).as_deref(), Some("22.3.1"));
+            assert!(pip_supports(python, PipFeature::DryRun));
+            assert!(!pip_supports(python, PipFeature::InstallReport));
+
+            let error = require_pip_feature(python, PipFeature::InstallReport, "Locking")
+                .unwrap_err()
+                .to_string();
+            assert!(error.contains("Locking is not possible: pip 23.0 or newer is needed"));
+            assert!(error.contains("has pip 22.3.1; run `ppm pip upgrade`"));
+        });
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_system_runner_writes_input_to_stdin() {
+        // More than a pipe buffer, so writing and reading must overlap
+        let input = "line\n".repeat(100_000);
+        let output = SystemRunner
+            .output_with_input(&mut Command::new("cat"), input.as_bytes())
+            .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, input.as_bytes());
+
+        let scripted = ScriptedRunner::new().respond("cat <<< hello", "hello");
+        let output = scripted
+            .output_with_input(&mut Command::new("cat"), b"hello")
+            .unwrap();
+        assert_eq!(output.stdout, b"hello");
+        assert_eq!(scripted.calls(), ["cat <<< hello"]);
+    }
+
+    #[test]
+    fn test_verbose_streams_pip_output() {
+        let runner = ScriptedRunner::new()
+            .respond(
+                "install alpha",
+                "Collecting alpha\nSuccessfully installed alpha-1.0\n",
+            )
+            .fail(
+                "install beta",
+                1,
+                "ERROR: No matching distribution found for beta\n",
+            );
+        let recorder = Arc::new(RecordingReporter::default());
+        let pip = |package: &str| {
+            let mut command = Command::new("python");
+            command.args(["-m", "pip", "install", package]);
+            run_pip(command, package, None).unwrap()
+        };
+        with_runner(runner, |_| {
+            set_reporter(recorder.clone());
+            set_verbosity(Verbosity::Verbose);
+            pip("alpha");
+            pip("beta");
+            set_verbosity(Verbosity::Normal);
+            pip("alpha");
+            *REPORTER.lock().unwrap() = None;
+        });
+
+        // Lines are labelled with their package, stderr is reported as a
+        // diagnostic, and nothing is echoed once verbose mode is off
+        let shown = recorder.shown.lock().unwrap();
+        let echoed: Vec<&(Option<MessageKind>, String)> = shown
+            .iter()
+            .filter(|(kind, text)| {
+                *kind != Some(MessageKind::Debug)
+                    && (text.starts_with("[alpha]") || text.starts_with("[beta]"))
+            })
+            .collect();
+        assert_eq!(
+            echoed,
+            [
+                &(
+                    Some(MessageKind::Detail),
+                    "[alpha] Collecting alpha".to_string()
+                ),
+                &(
+                    Some(MessageKind::Detail),
+                    "[alpha] Successfully installed alpha-1.0".to_string()
+                ),
+                &(
+                    Some(MessageKind::Diagnostic),
+                    "[beta] ERROR: No matching distribution found for beta".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pip_invocations_are_traced() {
+        let log = tempfile::NamedTempFile::new().unwrap();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(Mutex::new(log.reopen().unwrap()))
+            .finish();
+        let runner = ScriptedRunner::new().fail("install gamma", 1, "ERROR: gamma is broken\n");
+        with_runner(runner, |_| {
+            tracing::subscriber::with_default(subscriber, || {
+                let mut command = Command::new("python");
+                command.args(["-m", "pip", "install", "gamma"]);
+                run_pip(command, "gamma", None).unwrap();
+            })
+        });
+
+        // The span names the package and arguments, and the debug event carries pip's output
+        let log = std::fs::read_to_string(log.path()).unwrap();
+        let finished = log
+            .lines()
+            .find(|line| line.contains("pip finished"))
+            .unwrap_or_else(|| panic!("no pip finished event in {}", log));
+        assert!(finished.contains("pip{package=\"gamma\" args=-m pip install gamma}"));
+        assert!(finished.contains("status=exit status: 1"));
+        assert!(finished.contains("duration_ms="));
+        assert!(log.contains("ERROR: gamma is broken"));
+    }
+}
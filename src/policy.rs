@@ -0,0 +1,1116 @@
+//! Which packages may be installed, and where they may come from
+//!
+//! The `[policy]` table of `ppm.toml` lists the packages a project or an
+//! organization allows and denies, as exact names or glob patterns, and the
+//! indexes packages may be downloaded from. Every pip command that installs or
+//! downloads packages is checked before it runs, whichever command started it,
+//! and a violation fails with [`PackageError::PackageDenied`].
+//!
+//! An organization can also publish one policy document for all its machines:
+//! `url` in `[policy]`, or the `PPM_POLICY_URL` environment variable, points at
+//! a TOML document with the same keys, which is fetched, checked against the
+//! sha256 pinned by `sha256` or `PPM_POLICY_SHA256` and enforced on top of the
+//! local policy.
+
+use crate::http::HttpRequest;
+use crate::metadata::fetch_project_json;
+use crate::pip::{get_python_executable, resolve_pip_install, ReportedInstall};
+use crate::spec::{canonicalize_name, compare_versions, glob_match, requirement_name};
+use crate::state::policy_cache_dir;
+use crate::{http_client, metadata_ttl, PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// Index pip uses when none is configured
+pub const DEFAULT_INDEX_URL: &str = "https://pypi.org/simple";
+
+/// The `[policy]` table of `ppm.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct PackagePolicy {
+    /// Packages that may be installed, e.g. "requests" or "mycorp-*"; when
+    /// empty, every package that is not denied may be
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// Packages that must not be installed; a denied package stays denied
+    /// even when it is also allowed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+    /// Index URLs packages may be downloaded from, also the URL prefixes
+    /// direct references may point to; when empty, any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexes: Vec<String>,
+    /// Licenses packages must be published under, e.g. "MIT" or "Apache-2.0";
+    /// when empty, any
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_licenses: Vec<String>,
+    /// Lowest release of a package that may be installed, e.g. `urllib3 = "2.2.2"`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub minimum_versions: BTreeMap<String, String>,
+    /// Whether pip must run in hash-checking mode, installing only archives
+    /// whose hashes are pinned in a requirements file or lockfile
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub require_hashes: bool,
+    /// URL of an organization policy document enforced on top of this one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// sha256 the organization policy document must have; required with `url`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+/// Environment variable pointing at the organization policy, overriding `url`
+pub const POLICY_URL_ENV: &str = "PPM_POLICY_URL";
+
+/// Environment variable pinning the sha256 of the organization policy, overriding `sha256`
+pub const POLICY_SHA256_ENV: &str = "PPM_POLICY_SHA256";
+
+/// The policy of ppm.toml, when one is configured
+static PACKAGE_POLICY: Mutex<Option<PackagePolicy>> = Mutex::new(None);
+
+/// Where the organization policy is published, and the policy once it was loaded
+static ORGANIZATION_POLICY: Mutex<Option<OrganizationPolicy>> = Mutex::new(None);
+
+/// An organization policy document and its checksum
+#[derive(Debug, Clone, PartialEq)]
+struct OrganizationPolicy {
+    url: String,
+    sha256: Option<String>,
+    /// The document, loaded by the first pip command that is checked
+    loaded: Option<PackagePolicy>,
+}
+
+/// Sets the policy pip commands are checked against, or turns checking off
+///
+/// The organization policy named by `url`, or by `PPM_POLICY_URL`, is not
+/// fetched until a pip command is checked against it.
+///
+/// # Arguments
+/// * `policy` - The policy, or None to allow everything
+pub fn set_package_policy(policy: Option<PackagePolicy>) {
+    let mut policy = policy.unwrap_or_default();
+    let env = |key: &str| {
+        std::env::var(key)
+            .ok()
+            .filter(|value| !value.trim().is_empty())
+    };
+    let url = env(POLICY_URL_ENV).or(policy.url.take());
+    let sha256 = env(POLICY_SHA256_ENV).or(policy.sha256.take());
+    *ORGANIZATION_POLICY.lock().unwrap() = url.map(|url| OrganizationPolicy {
+        url,
+        sha256,
+        loaded: None,
+    });
+    *PACKAGE_POLICY.lock().unwrap() = Some(policy).filter(|policy| !policy.is_empty());
+}
+
+/// The policy of ppm.toml in force, if any
+pub fn package_policy() -> Option<PackagePolicy> {
+    PACKAGE_POLICY.lock().unwrap().clone()
+}
+
+/// The organization policy in force, if any, fetched on first use
+///
+/// # Returns
+/// * `Result<Option<PackagePolicy>>` - The policy, or a policy error if it is
+///   configured but could not be loaded, in which case nothing may be installed
+pub fn organization_policy() -> Result<Option<PackagePolicy>> {
+    let mut organization = ORGANIZATION_POLICY.lock().unwrap();
+    let Some(organization) = organization.as_mut() else {
+        return Ok(None);
+    };
+    if organization.loaded.is_none() {
+        organization.loaded = Some(load_organization_policy(
+            &organization.url,
+            organization.sha256.as_deref(),
+        )?);
+    }
+    Ok(organization.loaded.clone())
+}
+
+/// Checks a pip command against the policy of ppm.toml and the organization policy
+///
+/// When a policy lists allowed or denied packages, minimum versions or
+/// allowed licenses, `pip install` is first resolved with a dry run, and every
+/// distribution it would install, dependencies included, is checked against
+/// them.
+///
+/// # Arguments
+/// * `command` - The pip command, e.g. `python -m pip install requests`
+///
+/// # Returns
+/// * `Result<()>` - Success, or the first rule the command breaks
+pub(crate) fn check_pip_command(command: &Command) -> Result<()> {
+    if !is_checked(command.get_args().map(|arg| arg.to_string_lossy())) {
+        return Ok(());
+    }
+    let policies: Vec<PackagePolicy> = package_policy()
+        .into_iter()
+        .chain(organization_policy()?)
+        .collect();
+    for policy in &policies {
+        policy.check_pip_command(command)?;
+    }
+    let checks_dependencies = policies.iter().any(|policy| {
+        !policy.allow.is_empty()
+            || !policy.deny.is_empty()
+            || !policy.minimum_versions.is_empty()
+            || !policy.allowed_licenses.is_empty()
+    });
+    let subcommand = command
+        .get_args()
+        .map(|arg| arg.to_string_lossy())
+        .find(|arg| is_checked(std::iter::once(arg)));
+    if checks_dependencies && subcommand.as_deref() == Some("install") {
+        for install in resolve_pip_install(command)? {
+            for policy in &policies {
+                policy.check_resolved(&install)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether pip arguments run a subcommand the policies apply to
+fn is_checked<S: AsRef<str>>(mut args: impl Iterator<Item = S>) -> bool {
+    args.any(|arg| matches!(arg.as_ref(), "install" | "download" | "wheel" | "index"))
+}
+
+/// Fetches the organization policy, checks its sha256 and parses it
+///
+/// The document must match a pinned sha256, which is the only thing that
+/// vouches for it: without one nothing may be installed. The verified
+/// document is cached for the metadata TTL. When it cannot be fetched, the
+/// cached copy is used with a warning, as long as it still matches the pin.
+///
+/// # Arguments
+/// * `url` - Where the TOML document is published
+/// * `sha256` - The sha256 the document must have
+///
+/// # Returns
+/// * `Result<PackagePolicy>` - The policy, or a policy error
+pub fn load_organization_policy(url: &str, sha256: Option<&str>) -> Result<PackagePolicy> {
+    let Some(sha256) = sha256.filter(|sum| !sum.trim().is_empty()) else {
+        return Err(PackageError::PackageDenied(format!(
+            "the organization policy at {} has no pinned sha256; set `sha256` in [policy] or {}",
+            url, POLICY_SHA256_ENV
+        )));
+    };
+    let path = organization_policy_cache_path(url);
+    let cached = std::fs::read_to_string(&path)
+        .ok()
+        .filter(|body| sha256_matches(body, sha256));
+    let age = std::fs::metadata(&path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or(Duration::MAX);
+
+    let body = match cached {
+        Some(body) if age < metadata_ttl() => body,
+        cached => match fetch_organization_policy(url, sha256) {
+            Ok(body) => {
+                let saved = std::fs::create_dir_all(policy_cache_dir())
+                    .and_then(|_| std::fs::write(&path, &body));
+                if let Err(e) = saved {
+                    warning!("Failed to cache the organization policy: {}", e);
+                }
+                body
+            }
+            Err(e) => match cached {
+                Some(body) => {
+                    warning!("{}; using the copy fetched before", e);
+                    body
+                }
+                None => return Err(e),
+            },
+        },
+    };
+    let mut policy: PackagePolicy = toml::from_str(&body).map_err(|e| {
+        PackageError::PackageDenied(format!(
+            "the organization policy at {} is invalid: {}",
+            url, e
+        ))
+    })?;
+    // A policy document cannot chain to another one
+    policy.url = None;
+    policy.sha256 = None;
+    Ok(policy)
+}
+
+/// Downloads the organization policy document and checks it against the pinned sha256
+fn fetch_organization_policy(url: &str, sha256: &str) -> Result<String> {
+    let python = get_python_executable()?;
+    let body = http_client()
+        .get(&python, &HttpRequest::get(url))
+        .map_err(|e| {
+            PackageError::PackageDenied(format!(
+                "could not fetch the organization policy at {}: {}",
+                url, e
+            ))
+        })?
+        .body;
+    if !sha256_matches(&body, sha256) {
+        return Err(PackageError::PackageDenied(format!(
+            "the organization policy at {} does not match its sha256 {}",
+            url,
+            sha256.trim()
+        )));
+    }
+    Ok(body)
+}
+
+/// Whether a document has the given sha256, written as hex with an optional `sha256:` prefix
+fn sha256_matches(body: &str, sha256: &str) -> bool {
+    let expected = sha256.trim().trim_start_matches("sha256:").to_lowercase();
+    let actual: String = hmac_sha256::Hash::hash(body.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    !expected.is_empty() && actual == expected
+}
+
+/// File the organization policy published at `url` is cached in
+fn organization_policy_cache_path(url: &str) -> PathBuf {
+    let key: String = hmac_sha256::Hash::hash(url.as_bytes())[..8]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    policy_cache_dir().join(format!("{}.toml", key))
+}
+
+/// Options of `pip install`, `download` and `wheel` that take a value
+const VALUE_OPTIONS: &[&str] = &[
+    "-r",
+    "--requirement",
+    "-c",
+    "--constraint",
+    "-e",
+    "--editable",
+    "-t",
+    "--target",
+    "-d",
+    "--dest",
+    "-w",
+    "--wheel-dir",
+    "-i",
+    "--index-url",
+    "--extra-index-url",
+    "-f",
+    "--find-links",
+    "-C",
+    "--config-settings",
+    "--platform",
+    "--python-version",
+    "--implementation",
+    "--abi",
+    "--root",
+    "--prefix",
+    "--src",
+    "--upgrade-strategy",
+    "--progress-bar",
+    "--report",
+    "--no-binary",
+    "--only-binary",
+    "--cache-dir",
+    "--log",
+    "--log-file",
+    "--proxy",
+    "--retries",
+    "--resume-retries",
+    "--timeout",
+    "--exists-action",
+    "--trusted-host",
+    "--cert",
+    "--client-cert",
+    "--python",
+    "--root-user-action",
+    "--global-option",
+    "--build-option",
+    "--keyring-provider",
+    "--use-feature",
+    "--use-deprecated",
+    "--group",
+];
+
+/// What a pip command installs and which indexes it reads, see [`pip_sources`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PipSources {
+    /// Requirements given on the command line or in `-r` files
+    pub requirements: Vec<String>,
+    /// Index URLs given with `--index-url`, `--extra-index-url` or `-i`
+    pub indexes: Vec<String>,
+    /// Whether `--no-index` was given
+    pub no_index: bool,
+    /// Whether a requirements file pins hashes, which puts pip in hash-checking mode
+    pub hashed: bool,
+}
+
+impl PackagePolicy {
+    /// Whether the policy restricts nothing
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty()
+            && self.deny.is_empty()
+            && self.indexes.is_empty()
+            && self.allowed_licenses.is_empty()
+            && self.minimum_versions.is_empty()
+            && !self.require_hashes
+    }
+
+    /// Checks that a package may be installed
+    ///
+    /// # Arguments
+    /// * `name` - The package name
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or a policy error naming the rule it breaks
+    pub fn check_package(&self, name: &str) -> Result<()> {
+        let canonical = canonicalize_name(name);
+        let matches = |pattern: &String| glob_match(&canonicalize_name(pattern), &canonical);
+        if let Some(pattern) = self.deny.iter().find(|pattern| matches(pattern)) {
+            return Err(PackageError::PackageDenied(format!(
+                "{} is denied by the package policy ({})",
+                name, pattern
+            )));
+        }
+        if !self.allow.is_empty() && !self.allow.iter().any(matches) {
+            return Err(PackageError::PackageDenied(format!(
+                "{} is not on the package policy's allow list",
+                name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that packages may be downloaded from an index or URL
+    ///
+    /// A URL is approved when it is one of the allowed indexes or lies below one.
+    ///
+    /// # Arguments
+    /// * `url` - The index or direct reference URL
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or a policy error if the source is not approved
+    pub fn check_source(&self, url: &str) -> Result<()> {
+        if self.indexes.is_empty() {
+            return Ok(());
+        }
+        let url = url.trim().trim_end_matches('/').to_lowercase();
+        let approved = self.indexes.iter().any(|allowed| {
+            let allowed = allowed.trim().trim_end_matches('/').to_lowercase();
+            url.strip_prefix(&allowed)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '@', '#']))
+        });
+        if approved {
+            return Ok(());
+        }
+        Err(PackageError::PackageDenied(format!(
+            "{} is not an approved package source",
+            url
+        )))
+    }
+
+    /// Checks one requirement: its package and version and, for a direct
+    /// reference, its URL
+    ///
+    /// Local paths are not checked against the allowed indexes, nor against
+    /// the minimum versions, and a local directory, whose name pip only
+    /// learns by building it, not against the lists. Licenses are checked on
+    /// what pip resolves, see [`check_pip_command`], or with
+    /// [`PackagePolicy::check_license`] for downloads.
+    ///
+    /// # Arguments
+    /// * `spec` - A requirement, e.g. "requests>=2", "pkg @ https://…" or "./dist/pkg-1.0.whl"
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or the first rule the requirement breaks
+    pub fn check_requirement(&self, spec: &str) -> Result<()> {
+        let spec = spec.trim();
+        let url = match spec.split_once(" @ ").or_else(|| spec.split_once('@')) {
+            Some((_, url)) if url.contains("://") => Some(url.trim()),
+            _ if spec.contains("://") => Some(spec),
+            _ => None,
+        };
+        if let Some(url) = url.filter(|url| !url.starts_with("file:")) {
+            let url = url.split_once('+').map_or(url, |(kind, rest)| {
+                if rest.contains("://") && !kind.contains(':') {
+                    rest
+                } else {
+                    url
+                }
+            });
+            self.check_source(url)?;
+        }
+
+        if is_local_path(spec) {
+            return match local_archive_name(spec) {
+                Some(name) => self.check_package(&name),
+                None => Ok(()),
+            };
+        }
+        let name = requirement_name(spec);
+        self.check_package(name)?;
+        if url.is_none() {
+            self.check_minimum_version(spec)?;
+        }
+        Ok(())
+    }
+
+    /// The lowest release of a package the policy allows, if it sets one
+    fn minimum_version(&self, name: &str) -> Option<&str> {
+        let canonical = canonicalize_name(name);
+        self.minimum_versions
+            .iter()
+            .find(|(package, _)| canonicalize_name(package) == canonical)
+            .map(|(_, minimum)| minimum.as_str())
+    }
+
+    /// Checks that a requirement does not ask for a release below the policy's minimum
+    ///
+    /// Only versions the specifiers rule out are caught: an unpinned
+    /// requirement passes, even if pip might settle on an older release.
+    ///
+    /// # Arguments
+    /// * `spec` - A requirement, e.g. "urllib3==1.26.5" or "urllib3<2"
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or a policy error naming the minimum
+    pub fn check_minimum_version(&self, spec: &str) -> Result<()> {
+        let spec = spec.split(';').next().unwrap_or(spec).trim();
+        let name = requirement_name(spec);
+        let Some(minimum) = self.minimum_version(name) else {
+            return Ok(());
+        };
+        let mut specifiers = spec[name.len()..].trim_start();
+        if specifiers.starts_with('[') {
+            specifiers = specifiers.split_once(']').map_or("", |(_, rest)| rest);
+        }
+        let specifiers =
+            specifiers.trim_matches(|c: char| c.is_whitespace() || c == '(' || c == ')');
+        let too_old = specifiers.split(',').map(str::trim).any(|clause| {
+            let operator_len = clause
+                .find(|c: char| !matches!(c, '=' | '!' | '<' | '>' | '~'))
+                .unwrap_or(clause.len());
+            let (operator, version) = clause.split_at(operator_len);
+            let version = version.trim();
+            if let Some(prefix) = version.strip_suffix(".*") {
+                let length = prefix.split('.').count();
+                let minimum_prefix: Vec<&str> = minimum.split('.').take(length).collect();
+                return operator == "=="
+                    && compare_versions(prefix, &minimum_prefix.join(".")) == Ordering::Less;
+            }
+            let ordering = compare_versions(version, minimum);
+            match operator {
+                "==" | "===" | "<=" => ordering == Ordering::Less,
+                "<" => ordering != Ordering::Greater,
+                _ => false,
+            }
+        });
+        if too_old {
+            return Err(PackageError::PackageDenied(format!(
+                "{} asks for a release of {} older than the minimum {} set by the package policy",
+                spec, name, minimum
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks a distribution pip resolved against the package lists, the
+    /// minimum versions and the licenses
+    ///
+    /// # Arguments
+    /// * `install` - A distribution from pip's installation report
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or a policy error naming the rule it breaks
+    pub(crate) fn check_resolved(&self, install: &ReportedInstall) -> Result<()> {
+        self.check_package(&install.name)?;
+        if let Some(minimum) = self.minimum_version(&install.name) {
+            if compare_versions(&install.version, minimum) == Ordering::Less {
+                return Err(PackageError::PackageDenied(format!(
+                    "{} {} would be installed, older than the minimum {} set by the package policy",
+                    install.name, install.version, minimum
+                )));
+            }
+        }
+        self.check_license_metadata(
+            &install.name,
+            &serde_json::json!({
+                "license": install.license,
+                "license_expression": install.license_expression,
+                "classifiers": install.classifiers,
+            }),
+        )
+    }
+
+    /// Checks on PyPI that a package is published under an allowed license
+    ///
+    /// A package whose license cannot be looked up is denied.
+    pub fn check_license(&self, name: &str) -> Result<()> {
+        if self.allowed_licenses.is_empty() {
+            return Ok(());
+        }
+        let project = get_python_executable()
+            .and_then(|python| fetch_project_json(&python, name))
+            .map_err(|e| {
+                PackageError::PackageDenied(format!(
+                    "could not look up the license of {}: {}",
+                    name, e
+                ))
+            })?;
+        self.check_license_metadata(name, &project["info"])
+    }
+
+    /// Checks the license a package declares in its PyPI metadata
+    ///
+    /// An SPDX `license_expression` is allowed when one of its `OR`
+    /// alternatives only uses allowed licenses. Otherwise the `license` field
+    /// and the `License ::` classifiers are compared with the allowed
+    /// licenses, ignoring case, `-`, `_` and a trailing "License". A package
+    /// declaring no license is denied.
+    ///
+    /// # Arguments
+    /// * `name` - The package name
+    /// * `info` - The `info` object of the package's PyPI JSON document
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or a policy error naming the declared licenses
+    pub fn check_license_metadata(&self, name: &str, info: &serde_json::Value) -> Result<()> {
+        if self.allowed_licenses.is_empty() {
+            return Ok(());
+        }
+        let allowed: Vec<String> = self
+            .allowed_licenses
+            .iter()
+            .map(|license| normalize_license(license))
+            .collect();
+        let is_allowed = |license: &str| allowed.contains(&normalize_license(license));
+        let expression_allowed = |expression: &str| {
+            expression.split(" OR ").any(|alternative| {
+                alternative.split(" AND ").all(|license| {
+                    let license =
+                        license.trim_matches(|c: char| c.is_whitespace() || c == '(' || c == ')');
+                    is_allowed(license.split(" WITH ").next().unwrap_or(license))
+                })
+            })
+        };
+
+        let declared: Vec<String> = match info["license_expression"].as_str() {
+            Some(expression) if !expression.trim().is_empty() => {
+                if expression_allowed(expression) {
+                    return Ok(());
+                }
+                vec![expression.trim().to_string()]
+            }
+            _ => {
+                let mut declared: Vec<String> = info["classifiers"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|classifier| classifier.as_str()?.strip_prefix("License :: "))
+                    .filter_map(|classifier| classifier.rsplit(" :: ").next())
+                    .map(str::to_string)
+                    .collect();
+                // The field sometimes holds the whole license text, which cannot be matched
+                if let Some(license) = info["license"].as_str().map(str::trim).filter(|license| {
+                    !license.is_empty() && !license.contains('\n') && license.len() <= 80
+                }) {
+                    declared.push(license.to_string());
+                }
+                if declared.iter().any(|license| expression_allowed(license)) {
+                    return Ok(());
+                }
+                declared
+            }
+        };
+        Err(PackageError::PackageDenied(if declared.is_empty() {
+            format!(
+                "{} declares no license, and the package policy only allows {}",
+                name,
+                self.allowed_licenses.join(", ")
+            )
+        } else {
+            format!(
+                "{} is published under {}, which the package policy does not allow",
+                name,
+                declared.join(", ")
+            )
+        }))
+    }
+
+    /// Checks a pip command before it runs
+    ///
+    /// Commands other than `install`, `download`, `wheel` and `index` are not
+    /// checked. The indexes are the ones the command names, or else
+    /// `PIP_INDEX_URL` or the indexes of `ppm.toml`, or else PyPI, plus any
+    /// `PIP_EXTRA_INDEX_URL`; with `--no-index` or `PIP_NO_INDEX` there are none.
+    /// Indexes set in pip's own configuration files are not seen.
+    ///
+    /// # Arguments
+    /// * `command` - The pip command, e.g. `python -m pip install requests`
+    ///
+    /// # Returns
+    /// * `Result<()>` - Success, or the first rule the command breaks
+    pub fn check_pip_command(&self, command: &Command) -> Result<()> {
+        let args: Vec<String> = command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let env = |key: &str| {
+            command
+                .get_envs()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.map(|v| v.to_string_lossy().into_owned()))
+                .unwrap_or_else(|| std::env::var(key).ok())
+        };
+        self.check_pip_args(&args, env)
+    }
+
+    /// Checks the arguments of a pip command, see [`PackagePolicy::check_pip_command`]
+    ///
+    /// # Arguments
+    /// * `args` - The arguments after the interpreter, e.g. `-m pip install requests`
+    /// * `env` - Looks up an environment variable of the command
+    pub(crate) fn check_pip_args(
+        &self,
+        args: &[String],
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<()> {
+        let Some(position) = args
+            .iter()
+            .position(|arg| matches!(arg.as_str(), "install" | "download" | "wheel" | "index"))
+        else {
+            return Ok(());
+        };
+        let env = |key: &str| env(key).filter(|value| !value.trim().is_empty());
+        let is_set = |key: &str| {
+            env(key).is_some_and(|value| {
+                !matches!(value.to_lowercase().as_str(), "0" | "false" | "no" | "off")
+            })
+        };
+        let sources = pip_sources(&args[position + 1..])?;
+        if args[position] != "index" {
+            let hash_checking = sources.hashed
+                || args.iter().any(|arg| arg == "--require-hashes")
+                || is_set("PIP_REQUIRE_HASHES");
+            if self.require_hashes && !hash_checking {
+                return Err(PackageError::PackageDenied(
+                    "the package policy requires pinned hashes; install from a lockfile \
+                     with `ppm install --lock`, or from a requirements file with --hash options"
+                        .to_string(),
+                ));
+            }
+            for requirement in &sources.requirements {
+                self.check_requirement(requirement)?;
+                // What `install` resolves is checked as a whole, see [`check_pip_command`]
+                if args[position] != "install" && !is_local_path(requirement) {
+                    self.check_license(requirement_name(requirement))?;
+                }
+            }
+        }
+        if sources.no_index || is_set("PIP_NO_INDEX") {
+            return Ok(());
+        }
+
+        let mut indexes = sources.indexes;
+        if !args.iter().any(|arg| {
+            matches!(arg.as_str(), "-i" | "--index-url") || arg.starts_with("--index-url=")
+        }) {
+            match env("PIP_INDEX_URL") {
+                Some(index) => indexes.push(index),
+                None if http_client().index_urls().is_empty() => {
+                    indexes.push(DEFAULT_INDEX_URL.to_string())
+                }
+                None => indexes.extend(http_client().index_urls().iter().cloned()),
+            }
+        }
+        if let Some(extra) = env("PIP_EXTRA_INDEX_URL") {
+            indexes.extend(extra.split_whitespace().map(str::to_string));
+        }
+        indexes
+            .iter()
+            .try_for_each(|index| self.check_source(index))
+    }
+}
+
+/// Finds what a pip command installs and which indexes it names
+///
+/// Requirements files given with `-r` are read, with their own options and
+/// the files they include.
+///
+/// # Arguments
+/// * `args` - The arguments after the pip subcommand
+///
+/// # Returns
+/// * `Result<PipSources>` - The requirements and indexes, or an error if a
+///   requirements file could not be read
+pub fn pip_sources(args: &[String]) -> Result<PipSources> {
+    let mut sources = PipSources::default();
+    collect_sources(args, Path::new(""), &mut sources, 0)?;
+    Ok(sources)
+}
+
+/// Adds the requirements and indexes of pip arguments to `sources`
+///
+/// # Arguments
+/// * `args` - pip arguments or the words of a requirements file line
+/// * `base` - Directory `-r` paths are relative to
+/// * `sources` - What was found so far
+/// * `depth` - How many `-r` files deep this is, to stop include cycles
+fn collect_sources(
+    args: &[String],
+    base: &Path,
+    sources: &mut PipSources,
+    depth: usize,
+) -> Result<()> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--no-index" {
+            sources.no_index = true;
+            continue;
+        }
+        if arg == "-" {
+            continue;
+        }
+        if !arg.starts_with('-') {
+            sources.requirements.push(arg.clone());
+            continue;
+        }
+        let (option, value) = match arg.split_once('=') {
+            Some((option, value)) if option.starts_with("--") => (option, Some(value.to_string())),
+            _ if VALUE_OPTIONS.contains(&arg.as_str()) => (arg.as_str(), args.next().cloned()),
+            _ => continue,
+        };
+        let Some(value) = value else {
+            continue;
+        };
+        match option {
+            "-i" | "--index-url" | "--extra-index-url" => sources.indexes.push(value),
+            "-e" | "--editable" => sources.requirements.push(value),
+            "-r" | "--requirement" if depth < 8 => {
+                collect_file_sources(&base.join(value), sources, depth + 1)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Adds the requirements and indexes of a requirements file to `sources`
+fn collect_file_sources(path: &Path, sources: &mut PipSources, depth: usize) -> Result<()> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        PackageError::IoError(std::io::Error::new(
+            e.kind(),
+            format!("Could not read requirements file {}: {}", path.display(), e),
+        ))
+    })?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    for line in contents.replace("\\\n", " ").lines() {
+        let line = match line.find(" #") {
+            Some(comment) => &line[..comment],
+            None => line,
+        }
+        .trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with('-') {
+            let words: Vec<String> = line.split_whitespace().map(str::to_string).collect();
+            collect_sources(&words, base, sources, depth)?;
+        } else {
+            // Per-requirement options such as --hash follow the requirement
+            let requirement = line.split(" --").next().unwrap_or(line).trim();
+            sources.hashed |= line.contains("--hash");
+            sources.requirements.push(requirement.to_string());
+        }
+    }
+    Ok(())
+}
+
+/// A license name in a comparable form, e.g. "apache 2.0" for "Apache-2.0"
+/// and "mit" for "MIT License"
+fn normalize_license(license: &str) -> String {
+    let words = license.to_lowercase().replace(['-', '_'], " ");
+    let words: Vec<&str> = words.split_whitespace().collect();
+    match words.split_last() {
+        Some((&"license", rest)) if !rest.is_empty() => rest.join(" "),
+        _ => words.join(" "),
+    }
+}
+
+/// Whether a requirement is a local path rather than a name or a URL
+fn is_local_path(spec: &str) -> bool {
+    let spec = spec.trim();
+    !spec.contains("://")
+        && (spec.starts_with('.') || spec.contains(['/', '\\']) || Path::new(spec).exists())
+}
+
+/// Name of the package in a local wheel or sdist, e.g. "pkg" for "dist/pkg-1.0.tar.gz"
+fn local_archive_name(path: &str) -> Option<String> {
+    let file = Path::new(path).file_name()?.to_str()?;
+    if let Some(stem) = file.strip_suffix(".whl") {
+        return stem.split('-').next().map(str::to_string);
+    }
+    let stem = [".tar.gz", ".zip", ".tar.bz2"]
+        .iter()
+        .find_map(|ext| file.strip_suffix(ext))?;
+    stem.rsplit_once('-').map(|(name, _)| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pip::ScriptedRunner;
+    use crate::test_support::with_runner;
+
+    #[test]
+    fn test_package_policy() {
+        let policy = PackagePolicy {
+            allow: vec![
+                "requests".to_string(),
+                "MyCorp_*".to_string(),
+                "pip".to_string(),
+            ],
+            deny: vec!["mycorp-legacy".to_string()],
+            indexes: vec!["https://pypi.mycorp.example/simple/".to_string()],
+            ..PackagePolicy::default()
+        };
+        assert!(policy.check_package("Requests").is_ok());
+        assert!(policy.check_package("mycorp.tools").is_ok());
+        assert!(policy.check_package("mycorp-legacy").is_err());
+        assert!(policy.check_package("flask").is_err());
+        assert!(policy
+            .check_requirement("mycorp-tools @ git+https://pypi.mycorp.example/simple/x.git")
+            .is_ok());
+        assert!(policy
+            .check_requirement("requests @ https://evil.example/requests.whl")
+            .is_err());
+        assert!(policy
+            .check_requirement("./dist/requests-2.0-py3-none-any.whl")
+            .is_ok());
+        assert!(policy.check_requirement("./dist/flask-3.0.tar.gz").is_err());
+
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+        let no_env = |_: &str| None;
+        let install = args(&["-m", "pip", "install", "--target", "vendor", "requests"]);
+        assert!(matches!(
+            policy.check_pip_args(&install, no_env),
+            Err(PackageError::PackageDenied(message)) if message.contains("pypi.org")
+        ));
+        let mirrored = args(&[
+            "-m",
+            "pip",
+            "install",
+            "--index-url=https://pypi.mycorp.example/simple",
+            "requests",
+        ]);
+        assert!(policy.check_pip_args(&mirrored, no_env).is_ok());
+        let extra = |key: &str| {
+            (key == "PIP_EXTRA_INDEX_URL").then(|| "https://other.example/simple".to_string())
+        };
+        assert!(policy.check_pip_args(&mirrored, extra).is_err());
+        let offline = args(&[
+            "-m",
+            "pip",
+            "install",
+            "--no-index",
+            "-f",
+            "wheels",
+            "flask",
+        ]);
+        assert!(policy.check_pip_args(&offline, no_env).is_err());
+        let upgrade = args(&["-m", "pip", "install", "--no-index", "--upgrade", "pip"]);
+        assert!(policy.check_pip_args(&upgrade, no_env).is_ok());
+        assert!(policy
+            .check_pip_args(&args(&["-m", "pip", "uninstall", "flask"]), no_env)
+            .is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        let requirements = dir.path().join("requirements.txt");
+        std::fs::write(
+            &requirements,
+            "--extra-index-url https://pypi.mycorp.example/simple\n\
+             requests==2.32.3 --hash=sha256:abc  # pinned\n\
+             flask>=3; python_version >= \"3.9\"\n",
+        )
+        .unwrap();
+        let sources = pip_sources(&args(&["-r", requirements.to_str().unwrap()])).unwrap();
+        assert_eq!(
+            sources.requirements,
+            ["requests==2.32.3", "flask>=3; python_version >= \"3.9\""]
+        );
+        assert_eq!(sources.indexes, ["https://pypi.mycorp.example/simple"]);
+    }
+
+    #[test]
+    fn test_organization_policy_rules() {
+        let policy: PackagePolicy = toml::from_str(
+            "allowed-licenses = [\"MIT\", \"Apache-2.0\", \"BSD License\"]\n\
+             require-hashes = true\n\
+             [minimum-versions]\n\
+             urllib3 = \"2.2.2\"\n",
+        )
+        .unwrap();
+        assert!(policy.check_minimum_version("urllib3==1.26.5").is_err());
+        assert!(policy
+            .check_minimum_version("URLLib3[socks]<2.2.2")
+            .is_err());
+        assert!(policy.check_minimum_version("urllib3==1.*").is_err());
+        assert!(policy.check_minimum_version("urllib3<=2.2.2").is_ok());
+        assert!(policy.check_minimum_version("urllib3>=1.26,<3").is_ok());
+        assert!(policy.check_minimum_version("requests==1.0").is_ok());
+
+        let allowed = [
+            serde_json::json!({"license_expression": "MIT OR GPL-3.0-only"}),
+            serde_json::json!({"license": "Apache 2.0 License"}),
+            serde_json::json!({
+                "license": "",
+                "classifiers": ["License :: OSI Approved :: BSD License"]
+            }),
+        ];
+        for info in &allowed {
+            assert!(
+                policy.check_license_metadata("pkg", info).is_ok(),
+                "{}",
+                info
+            );
+        }
+        let denied = [
+            serde_json::json!({"license_expression": "MIT AND GPL-3.0-only"}),
+            serde_json::json!({"license": "GPL"}),
+            serde_json::json!({"license": null, "classifiers": []}),
+        ];
+        for info in &denied {
+            assert!(
+                policy.check_license_metadata("pkg", info).is_err(),
+                "{}",
+                info
+            );
+        }
+
+        let args = |args: &[&str]| args.iter().map(|a| a.to_string()).collect::<Vec<String>>();
+        let unhashed = args(&["-m", "pip", "install", "--no-index", "--no-deps", "./x"]);
+        assert!(matches!(
+            policy.check_pip_args(&unhashed, |_| None),
+            Err(PackageError::PackageDenied(message)) if message.contains("hashes")
+        ));
+        let env = |key: &str| (key == "PIP_REQUIRE_HASHES").then(|| "1".to_string());
+        assert!(policy.check_pip_args(&unhashed, env).is_ok());
+
+        assert!(sha256_matches(
+            "abc",
+            "sha256:BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD"
+        ));
+        assert!(!sha256_matches("abc", ""));
+        assert!(matches!(
+            load_organization_policy("https://policy.mycorp.example/ppm.toml", None),
+            Err(PackageError::PackageDenied(message)) if message.contains("no pinned sha256")
+        ));
+
+        let resolved = |name: &str, version: &str, license: &str| ReportedInstall {
+            name: name.to_string(),
+            version: version.to_string(),
+            requested: false,
+            origin: None,
+            license: Some(license.to_string()),
+            license_expression: None,
+            classifiers: Vec::new(),
+        };
+        assert!(policy
+            .check_resolved(&resolved("urllib3", "2.2.3", "MIT"))
+            .is_ok());
+        assert!(matches!(
+            policy.check_resolved(&resolved("urllib3", "1.26.5", "MIT")),
+            Err(PackageError::PackageDenied(message)) if message.contains("minimum 2.2.2")
+        ));
+        assert!(policy
+            .check_resolved(&resolved("certifi", "2024.8.30", "MPL-2.0"))
+            .is_err());
+        let classified = ReportedInstall {
+            license: None,
+            classifiers: vec!["License :: OSI Approved :: BSD License".to_string()],
+            ..resolved("idna", "3.10", "")
+        };
+        assert!(policy.check_resolved(&classified).is_ok());
+    }
+
+    #[test]
+    fn test_denied_dependency_is_caught_in_the_resolution() {
+        let report = r#"{
+            "version": "1",
+            "install": [
+                {"requested": true, "metadata": {"name": "webapp", "version": "1.0"}},
+                {"requested": false, "metadata": {"name": "PyCrypto", "version": "2.6.1"}}
+            ]
+        }"#;
+        let runner = ScriptedRunner::new()
+            .respond(
+                "/opt/policy/bin/python -m pip --version",
+                "pip 24.0 from /opt/policy/lib/pip (python 3.12)\n",
+            )
+            .report("install webapp --dry-run", report);
+        let mut command = Command::new("/opt/policy/bin/python");
+        command.args(["-m", "pip", "install", "webapp"]);
+        let result = with_runner(runner, |_| {
+            set_package_policy(Some(PackagePolicy {
+                deny: vec!["pycrypto".to_string()],
+                ..PackagePolicy::default()
+            }));
+            let result = check_pip_command(&command);
+            set_package_policy(None);
+            result
+        });
+
+        // The requested package passes; the dependency pip resolved for it does not
+        assert!(matches!(
+            result,
+            Err(PackageError::PackageDenied(message))
+                if message == "PyCrypto is denied by the package policy (pycrypto)"
+        ));
+    }
+
+    #[test]
+    fn test_organization_deny_list_covers_dependencies() {
+        let report = r#"{
+            "version": "1",
+            "install": [
+                {"requested": true, "metadata": {"name": "mycorp-app", "version": "2.0"}},
+                {"requested": false, "metadata": {"name": "mycorp-legacy", "version": "0.9"}}
+            ]
+        }"#;
+        let runner = ScriptedRunner::new()
+            .respond(
+                "/opt/org/bin/python -m pip --version",
+                "pip 24.0 from /opt/org/lib/pip (python 3.12)\n",
+            )
+            .report("install mycorp-app --dry-run", report);
+        let mut command = Command::new("/opt/org/bin/python");
+        command.args(["-m", "pip", "install", "mycorp-app"]);
+        let result = with_runner(runner, |_| {
+            // A loaded organization policy that only denies, with no local policy
+            set_package_policy(None);
+            *ORGANIZATION_POLICY.lock().unwrap() = Some(OrganizationPolicy {
+                url: "https://policy.mycorp.example/ppm.toml".to_string(),
+                sha256: Some("9f2c".to_string()),
+                loaded: Some(PackagePolicy {
+                    deny: vec!["mycorp-legacy".to_string()],
+                    ..PackagePolicy::default()
+                }),
+            });
+            let result = check_pip_command(&command);
+            *ORGANIZATION_POLICY.lock().unwrap() = None;
+            result
+        });
+
+        assert!(matches!(
+            result,
+            Err(PackageError::PackageDenied(message))
+                if message == "mycorp-legacy is denied by the package policy (mycorp-legacy)"
+        ));
+    }
+}
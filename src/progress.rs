@@ -0,0 +1,133 @@
+//! The progress bars shown during parallel operations
+
+use crate::observer::InstallObserver;
+use crate::pip::echo_pip_line;
+use crate::reporter::{verbosity, Verbosity};
+use crate::session::{
+    ci_mode, emit_event, package_finished_event, package_started_event, progress_mode, ProgressMode,
+};
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Creates a configured progress bar for package installation
+fn create_progress_bar(len: usize) -> ProgressBar {
+    let pb = ProgressBar::new(len as u64);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} {msg}")
+            .unwrap()
+            .progress_chars("#>-"),
+    );
+    pb
+}
+
+/// Progress display of the CLI for parallel operations
+///
+/// Shows one spinner per in-flight package, with its elapsed time and the
+/// state parsed from pip's output, above an overall bar counting completions.
+/// Everything is cleared once the operation finishes so only the summary
+/// table remains. The bars are hidden in quiet, CI and `--progress json`
+/// modes; the latter emits the package events instead.
+#[derive(Default)]
+pub struct ProgressBarObserver {
+    bars: Mutex<Option<ProgressBars>>,
+}
+
+/// Bars of the operation in progress
+struct ProgressBars {
+    multi: MultiProgress,
+    overall: ProgressBar,
+    operation: String,
+    spinners: HashMap<String, ProgressBar>,
+}
+
+impl ProgressBarObserver {
+    /// Creates an observer with nothing on display
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl InstallObserver for ProgressBarObserver {
+    fn on_start(&self, total: usize, operation: &str) {
+        let hidden =
+            verbosity() == Verbosity::Quiet || progress_mode() == ProgressMode::Json || ci_mode();
+        let multi = if hidden {
+            MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+        } else {
+            MultiProgress::new()
+        };
+        let overall = multi.add(create_progress_bar(total));
+        *self.bars.lock().unwrap() = Some(ProgressBars {
+            multi,
+            overall,
+            operation: operation.to_string(),
+            spinners: HashMap::new(),
+        });
+    }
+
+    fn on_progress(&self, name: &str, state: &str) {
+        let mut bars = self.bars.lock().unwrap();
+        let Some(bars) = bars.as_mut() else {
+            return;
+        };
+        if let Some(spinner) = bars.spinners.get(name) {
+            spinner.set_message(state.to_string());
+            return;
+        }
+
+        emit_event(package_started_event(name, &bars.operation));
+        let spinner = bars
+            .multi
+            .insert_before(&bars.overall, ProgressBar::new_spinner());
+        spinner.set_style(
+            ProgressStyle::default_spinner()
+                .template("  {spinner:.green} {prefix:.bold} [{elapsed}] {msg}")
+                .unwrap(),
+        );
+        spinner.set_prefix(name.to_string());
+        spinner.set_message(state.to_string());
+        spinner.enable_steady_tick(Duration::from_millis(100));
+        bars.spinners.insert(name.to_string(), spinner);
+    }
+
+    fn on_package_done(
+        &self,
+        name: &str,
+        result: std::result::Result<&str, &str>,
+        elapsed: Duration,
+    ) {
+        let mut bars = self.bars.lock().unwrap();
+        let Some(bars) = bars.as_mut() else {
+            return;
+        };
+        if let Some(spinner) = bars.spinners.remove(name) {
+            spinner.finish_and_clear();
+        }
+        bars.overall.inc(1);
+        emit_event(package_finished_event(
+            name,
+            &bars.operation,
+            result,
+            elapsed,
+        ));
+    }
+
+    fn on_finished(&self) {
+        if let Some(bars) = self.bars.lock().unwrap().take() {
+            bars.overall.finish_and_clear();
+        }
+    }
+
+    fn on_output(&self, name: &str, line: &str, is_stderr: bool) {
+        match self.bars.lock().unwrap().as_ref() {
+            // Print above the bars so they are not torn apart
+            Some(bars) if !bars.overall.is_hidden() => {
+                bars.multi.suspend(|| echo_pip_line(name, line, is_stderr))
+            }
+            _ => echo_pip_line(name, line, is_stderr),
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! The `ppm.toml` project configuration, `ppm init` and requirement profiles
+
+use crate::http::HttpClient;
+use crate::pip::{get_python_executable, run_command};
+use crate::policy::PackagePolicy;
+use crate::registry::PackageRegistry;
+use crate::requirements::parse_requirements_file;
+use crate::spec::{canonicalize_name, requirement_name};
+use crate::update::{UpdatePolicies, UpdatePolicy};
+use crate::webhook::WebhookConfig;
+use crate::{PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// Name of the per-project configuration file
+pub const PROJECT_CONFIG_FILE: &str = "ppm.toml";
+
+/// Project settings stored in `ppm.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProjectConfig {
+    /// Project name
+    #[serde(default)]
+    pub name: String,
+    /// Supported Python versions, e.g. ">=3.9"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires_python: Option<String>,
+    /// Requirements file installed by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requirements: Option<String>,
+    /// Virtual environment of the project, relative to the config file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub venv: Option<PathBuf>,
+    /// Install packages into `__pypackages__` (PEP 582) instead of the interpreter's environment
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pypackages: bool,
+    /// Maximum number of packages processed at once, like `--jobs`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jobs: Option<usize>,
+    /// Seconds a cached PyPI response is used before it is revalidated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_ttl: Option<u64>,
+    /// Days without a release after which a package is flagged as apparently
+    /// unmaintained; 0 turns the warning off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unmaintained_after_days: Option<u64>,
+    /// Settings of the HTTP client used for every network request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpConfig>,
+    /// Sub-projects managed together, when this is a workspace root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<WorkspaceConfig>,
+    /// Requirements file of each profile that is not at `requirements/<profile>.txt`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub profiles: BTreeMap<String, String>,
+    /// How far `upgrade` may move packages without `--allow-major`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub update_policy: Option<UpdatePolicy>,
+    /// Update policies of single packages, overriding `update-policy`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub update_policies: BTreeMap<String, UpdatePolicy>,
+    /// Where `outdated` posts a summary when it finds outdated or vulnerable packages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub webhook: Option<WebhookConfig>,
+    /// Which packages may be installed, and from which indexes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub policy: Option<PackagePolicy>,
+}
+
+/// The `[http]` table of `ppm.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct HttpConfig {
+    /// Seconds before a stalled request is abandoned
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<f64>,
+    /// Number of times a failed request is retried
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retries: Option<u32>,
+    /// Proxy URL used instead of `HTTPS_PROXY`/`HTTP_PROXY`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    /// `User-Agent` header sent with every request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// Maximum number of packages looked up at once
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub concurrency: Option<usize>,
+    /// Package indexes tried in order, falling back to the next one on network errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub indexes: Vec<String>,
+}
+
+impl HttpConfig {
+    /// Builds a client from these settings, keeping the defaults for the ones left out
+    pub fn client(&self) -> HttpClient {
+        let mut client = HttpClient::new();
+        if let Some(timeout) = self.timeout {
+            client = client.timeout(Duration::from_secs_f64(timeout));
+        }
+        if let Some(retries) = self.retries {
+            client = client.retries(retries);
+        }
+        if let Some(proxy) = &self.proxy {
+            client = client.proxy(proxy);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            client = client.user_agent(user_agent);
+        }
+        if let Some(concurrency) = self.concurrency {
+            client = client.concurrency(concurrency);
+        }
+        client.indexes(self.indexes.iter().cloned())
+    }
+}
+
+/// The `[workspace]` table of a root `ppm.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorkspaceConfig {
+    /// Member directories relative to the root; a trailing `/*` includes every subdirectory
+    pub members: Vec<String>,
+}
+
+impl ProjectConfig {
+    /// The `update-policy` and `[update-policies]` settings
+    pub fn update_policies(&self) -> UpdatePolicies {
+        UpdatePolicies {
+            default: self.update_policy.unwrap_or_default(),
+            packages: self
+                .update_policies
+                .iter()
+                .map(|(name, policy)| (canonicalize_name(name), *policy))
+                .collect(),
+        }
+    }
+
+    /// Loads `ppm.toml` from a directory
+    ///
+    /// # Returns
+    /// * `Result<Option<Self>>` - The config, or None if the directory has no `ppm.toml`
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join(PROJECT_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let text = std::fs::read_to_string(&path)?;
+        toml::from_str(&text)
+            .map(Some)
+            .map_err(|e| PackageError::MetadataError(format!("Invalid {}: {}", path.display(), e)))
+    }
+
+    /// Renders the config as TOML
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("project config is always representable as TOML")
+    }
+}
+
+/// Dependency files created by `ppm init`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum InitFormat {
+    /// A pyproject.toml with a [project] table
+    Pyproject,
+    /// A requirements.txt
+    #[default]
+    Requirements,
+    /// Both files
+    Both,
+}
+
+/// Settings for scaffolding a project
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitOptions {
+    /// Project name
+    pub name: String,
+    /// Supported Python versions, e.g. ">=3.9"
+    pub requires_python: Option<String>,
+    /// Which dependency files to create
+    pub format: InitFormat,
+    /// Where to create a virtual environment, if at all
+    pub venv: Option<PathBuf>,
+}
+
+/// Creates the dependency files and `ppm.toml` of a new project
+///
+/// Existing files are left untouched so `init` is safe to run in an existing project.
+///
+/// # Arguments
+/// * `dir` - Project directory
+/// * `options` - What to create
+///
+/// # Returns
+/// * `Result<Vec<PathBuf>>` - Files that were created
+pub fn init_project(dir: &Path, options: &InitOptions) -> Result<Vec<PathBuf>> {
+    let requirements = matches!(options.format, InitFormat::Requirements | InitFormat::Both)
+        .then(|| "requirements.txt".to_string());
+    let config = ProjectConfig {
+        name: options.name.clone(),
+        requires_python: options.requires_python.clone(),
+        requirements: requirements.clone(),
+        venv: options.venv.clone(),
+        ..ProjectConfig::default()
+    };
+
+    let mut files = vec![(PROJECT_CONFIG_FILE.to_string(), config.to_toml())];
+    if matches!(options.format, InitFormat::Pyproject | InitFormat::Both) {
+        files.push(("pyproject.toml".to_string(), render_pyproject(options)));
+    }
+    if let Some(requirements) = requirements {
+        files.push((
+            requirements,
+            format!(
+                "# Dependencies of {}, one specifier per line\n",
+                options.name
+            ),
+        ));
+    }
+
+    let mut created = Vec::new();
+    for (file_name, contents) in files {
+        let path = dir.join(file_name);
+        if path.exists() {
+            warning!("{} already exists; leaving it unchanged", path.display());
+            continue;
+        }
+        std::fs::write(&path, contents)?;
+        created.push(path);
+    }
+    Ok(created)
+}
+
+/// Renders a minimal PEP 621 pyproject.toml
+fn render_pyproject(options: &InitOptions) -> String {
+    #[derive(Serialize)]
+    struct Pyproject<'a> {
+        project: ProjectTable<'a>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "kebab-case")]
+    struct ProjectTable<'a> {
+        name: &'a str,
+        version: &'a str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        requires_python: Option<&'a str>,
+        dependencies: Vec<String>,
+    }
+
+    let document = Pyproject {
+        project: ProjectTable {
+            name: &options.name,
+            version: "0.1.0",
+            requires_python: options.requires_python.as_deref(),
+            dependencies: Vec::new(),
+        },
+    };
+    toml::to_string(&document).expect("pyproject table is always representable as TOML")
+}
+
+/// Creates a virtual environment with the selected interpreter's venv module
+///
+/// # Arguments
+/// * `path` - Directory of the new environment
+///
+/// # Returns
+/// * `Result<()>` - Success or error if `python -m venv` failed
+pub fn create_virtualenv(path: &Path) -> Result<()> {
+    let python = get_python_executable()?;
+    let output = run_command(Command::new(&python).arg("-m").arg("venv").arg(path))?;
+    if !output.status.success() {
+        return Err(PackageError::InstallationFailed(format!(
+            "Failed to create virtual environment at {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// Directory holding one requirements file per profile, e.g. `requirements/dev.txt`
+pub const PROFILES_DIR: &str = "requirements";
+
+/// Finds the requirements file of a profile such as "dev" or "prod"
+///
+/// The `[profiles]` table of ppm.toml takes precedence over the
+/// `requirements/<profile>.txt` layout. Profiles usually build on each other
+/// with `-r base.txt` lines, which are followed when the file is read.
+///
+/// # Arguments
+/// * `root` - The project directory
+/// * `profile` - Name of the profile
+///
+/// # Returns
+/// * `Result<String>` - Path of the requirements file, or an error naming the
+///   profiles that exist
+pub fn profile_requirements(root: &Path, profile: &str) -> Result<String> {
+    let config = ProjectConfig::load(root)?.unwrap_or_default();
+    if let Some(path) = config.profiles.get(profile) {
+        return Ok(root.join(path).display().to_string());
+    }
+    let path = root.join(PROFILES_DIR).join(format!("{}.txt", profile));
+    if path.exists() {
+        return Ok(path.display().to_string());
+    }
+
+    let mut profiles: Vec<String> = config.profiles.into_keys().collect();
+    if let Ok(entries) = std::fs::read_dir(root.join(PROFILES_DIR)) {
+        profiles.extend(entries.filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "txt" {
+                return None;
+            }
+            Some(path.file_stem()?.to_string_lossy().to_string())
+        }));
+    }
+    profiles.sort();
+    profiles.dedup();
+    let available = if profiles.is_empty() {
+        "no profiles are defined".to_string()
+    } else {
+        format!("available profiles: {}", profiles.join(", "))
+    };
+    Err(PackageError::IoError(std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!(
+            "No requirements file for profile {} (looked for {}); {}",
+            profile,
+            path.display(),
+            available
+        ),
+    )))
+}
+
+/// The registered packages a requirements file needs: those it lists, and
+/// everything they depend on
+///
+/// # Arguments
+/// * `path` - Path to the requirements file, whose includes are followed
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<PackageRegistry>` - A registry holding only those packages
+pub fn packages_required_by(path: &str, registry: &PackageRegistry) -> Result<PackageRegistry> {
+    let graph = registry.dependency_graph();
+    let mut required = PackageRegistry::new();
+    for spec in parse_requirements_file(path)? {
+        let Some(key) = registry.find_package_key(requirement_name(&spec)) else {
+            continue;
+        };
+        for name in graph
+            .rooted_at(&key)
+            .map(|g| g.packages())
+            .unwrap_or_default()
+        {
+            if let Some(package) = registry.get_package(&name) {
+                required.add_package(package.clone());
+            }
+        }
+    }
+    Ok(required)
+}
+
+/// Explicitly installed packages that a requirements file no longer lists
+///
+/// # Arguments
+/// * `path` - Path to the requirements file
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<Vec<String>>` - Registry keys of the extraneous packages, sorted
+pub fn extraneous_packages(path: &str, registry: &PackageRegistry) -> Result<Vec<String>> {
+    let wanted: HashSet<String> = parse_requirements_file(path)?
+        .iter()
+        .map(|spec| canonicalize_name(requirement_name(spec)))
+        .collect();
+    let mut extras: Vec<String> = registry
+        .packages
+        .iter()
+        .filter(|(_, package)| package.explicit)
+        .filter(|(key, _)| !wanted.contains(&canonicalize_name(key)))
+        .map(|(key, _)| key.clone())
+        .collect();
+    extras.sort();
+    Ok(extras)
+}
+
+/// Interpreter inside a virtual environment
+pub(crate) fn venv_python(venv: &Path) -> String {
+    let python = if cfg!(windows) {
+        venv.join("Scripts").join("python.exe")
+    } else {
+        venv.join("bin").join("python")
+    };
+    python.display().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::Package;
+
+    #[test]
+    fn test_init_project() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("requirements.txt"), "requests\n").unwrap();
+        let options = InitOptions {
+            name: "demo".to_string(),
+            requires_python: Some(">=3.9".to_string()),
+            format: InitFormat::Both,
+            venv: Some(PathBuf::from(".venv")),
+        };
+
+        let created = init_project(dir.path(), &options).unwrap();
+        assert_eq!(
+            created,
+            vec![
+                dir.path().join("ppm.toml"),
+                dir.path().join("pyproject.toml")
+            ]
+        );
+        // Existing files are kept as they are
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("requirements.txt")).unwrap(),
+            "requests\n"
+        );
+
+        let config = ProjectConfig::load(dir.path()).unwrap().unwrap();
+        assert_eq!(config.name, "demo");
+        assert_eq!(config.requirements.as_deref(), Some("requirements.txt"));
+        assert_eq!(config.venv, Some(PathBuf::from(".venv")));
+
+        let pyproject = std::fs::read_to_string(dir.path().join("pyproject.toml")).unwrap();
+        assert!(pyproject.contains("[project]"));
+        assert!(pyproject.contains("requires-python = \">=3.9\""));
+    }
+
+    #[test]
+    fn test_extraneous_packages() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut file,
+            b"numpy>=1.26\nRequests[socks]==2.32.3\n# comment\n",
+        )
+        .unwrap();
+        let mut registry = PackageRegistry::new();
+        for (name, explicit) in [
+            ("numpy", true),
+            ("requests", true),
+            ("flask", true),
+            ("idna", false),
+        ] {
+            let mut package = Package::new(name.to_string(), "1.0".to_string());
+            package.explicit = explicit;
+            registry.add_package(package);
+        }
+
+        let extras = extraneous_packages(file.path().to_str().unwrap(), &registry).unwrap();
+        assert_eq!(extras, vec!["flask"]);
+    }
+
+    #[test]
+    fn test_profile_requirements_follow_includes() {
+        let root = tempfile::tempdir().unwrap();
+        let profiles = root.path().join(PROFILES_DIR);
+        std::fs::create_dir(&profiles).unwrap();
+        std::fs::write(profiles.join("base.txt"), "requests>=2.0\nrich\n").unwrap();
+        std::fs::write(profiles.join("dev.txt"), "-r base.txt\npytest\n").unwrap();
+        std::fs::write(
+            profiles.join("ci.txt"),
+            "--requirement=dev.txt\n-r base.txt\ncoverage\n",
+        )
+        .unwrap();
+        std::fs::write(profiles.join("broken.txt"), "-r missing.txt\n").unwrap();
+
+        let dev = profile_requirements(root.path(), "dev").unwrap();
+        assert_eq!(
+            parse_requirements_file(&dev).unwrap(),
+            ["requests>=2.0", "rich", "pytest"]
+        );
+        // base.txt is read only once even though ci.txt reaches it twice
+        let ci = profile_requirements(root.path(), "ci").unwrap();
+        assert_eq!(
+            parse_requirements_file(&ci).unwrap(),
+            ["requests>=2.0", "rich", "pytest", "coverage"]
+        );
+        let broken = profile_requirements(root.path(), "broken").unwrap();
+        let error = parse_requirements_file(&broken).unwrap_err().to_string();
+        assert!(error.contains("missing.txt (included by"), "{}", error);
+
+        let error = profile_requirements(root.path(), "prod")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("available profiles: base, broken, ci, dev"),
+            "{}",
+            error
+        );
+
+        std::fs::write(
+            root.path().join(PROJECT_CONFIG_FILE),
+            "[profiles]\nprod = \"deploy/prod.txt\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            profile_requirements(root.path(), "prod").unwrap(),
+            root.path().join("deploy/prod.txt").display().to_string()
+        );
+    }
+}
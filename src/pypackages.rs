@@ -0,0 +1,170 @@
+//! Project-local packages in `__pypackages__` (PEP 582)
+//!
+//! Instead of going into a virtual environment, packages can be installed into
+//! `__pypackages__/<X.Y>/lib` next to the project, the way node_modules works.
+//! In this mode pip installs there with `--target`, and every interpreter ppm
+//! starts, including the programs of `ppm run`, finds them on `PYTHONPATH`.
+
+use crate::pip::{get_python_executable, get_python_version};
+use crate::{PackageError, ProjectConfig, Result};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+
+/// Directory of project-local packages, next to the project files
+pub const PYPACKAGES_DIR: &str = "__pypackages__";
+
+/// Variable telling ppm's own Python snippets to list only the local packages
+pub(crate) const LOCAL_PACKAGES_ENV: &str = "PPM_LOCAL_PACKAGES";
+
+/// The local packages directory in use, when the mode is on
+static LOCAL_PACKAGES: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Directory the packages of an interpreter version are installed into
+///
+/// # Arguments
+/// * `project` - The project directory
+/// * `python_version` - Version of the interpreter, e.g. "3.12.1"
+///
+/// # Returns
+/// * `PathBuf` - `<project>/__pypackages__/<X.Y>/lib`, e.g. `__pypackages__/3.12/lib`
+pub fn pypackages_lib(project: &Path, python_version: &str) -> PathBuf {
+    let minor: Vec<&str> = python_version.split('.').take(2).collect();
+    project
+        .join(PYPACKAGES_DIR)
+        .join(minor.join("."))
+        .join("lib")
+}
+
+/// Whether a project asks for local packages
+///
+/// It does when `ppm.toml` sets `pypackages = true` or a `__pypackages__`
+/// directory exists, which is what makes PEP 582 aware tools use it too.
+///
+/// # Arguments
+/// * `project` - The project directory
+pub fn local_packages_requested(project: &Path) -> bool {
+    let configured = ProjectConfig::load(project)
+        .ok()
+        .flatten()
+        .is_some_and(|config| config.pypackages);
+    configured || project.join(PYPACKAGES_DIR).is_dir()
+}
+
+/// Sets the local packages directory commands use, or turns the mode off
+///
+/// # Arguments
+/// * `lib` - The directory, see [`pypackages_lib`], or None for the interpreter's own environment
+pub fn set_local_packages(lib: Option<PathBuf>) {
+    *LOCAL_PACKAGES.lock().unwrap() = lib;
+}
+
+/// The local packages directory in use, if the mode is on
+pub fn local_packages() -> Option<PathBuf> {
+    LOCAL_PACKAGES.lock().unwrap().clone()
+}
+
+/// Turns on local packages for the selected interpreter
+///
+/// # Arguments
+/// * `project` - The project directory
+///
+/// # Returns
+/// * `Result<PathBuf>` - The directory packages now go to, created if needed,
+///   or an error if the interpreter could not be run
+pub fn enable_local_packages(project: &Path) -> Result<PathBuf> {
+    let python = get_python_executable()?;
+    let version = get_python_version(&python)?;
+    let lib = std::path::absolute(pypackages_lib(project, &version))?;
+    std::fs::create_dir_all(&lib)?;
+    set_local_packages(Some(lib.clone()));
+    Ok(lib)
+}
+
+/// Environment variables that point a command at local packages
+///
+/// `PYTHONPATH` puts the directory first on `sys.path`; `PIP_TARGET` and
+/// `PIP_UPGRADE` make `pip install` install there, replacing the versions
+/// already present. `PPM_LOCAL_PACKAGES` makes ppm's package listings ignore
+/// what the interpreter's own environment has.
+///
+/// # Arguments
+/// * `lib` - The local packages directory
+/// * `pythonpath` - The `PYTHONPATH` to extend, if any
+///
+/// # Returns
+/// * `Result<Vec<(&str, OsString)>>` - The variables, or an error if the
+///   directory cannot be part of a path list
+pub fn local_packages_env(
+    lib: &Path,
+    pythonpath: Option<&OsStr>,
+) -> Result<Vec<(&'static str, OsString)>> {
+    let mut dirs = vec![lib.to_path_buf()];
+    if let Some(pythonpath) = pythonpath {
+        dirs.extend(std::env::split_paths(pythonpath).filter(|dir| dir != lib));
+    }
+    let pythonpath = std::env::join_paths(dirs).map_err(|e| {
+        PackageError::IoError(std::io::Error::new(std::io::ErrorKind::InvalidInput, e))
+    })?;
+    Ok(vec![
+        ("PYTHONPATH", pythonpath),
+        ("PIP_TARGET", lib.as_os_str().to_os_string()),
+        ("PIP_UPGRADE", OsString::from("1")),
+        (LOCAL_PACKAGES_ENV, lib.as_os_str().to_os_string()),
+    ])
+}
+
+/// Points a command at the local packages, when the mode is on
+///
+/// Variables the command sets or removes itself are left alone, e.g. upgrading
+/// pip removes `PIP_TARGET` so pip stays in the interpreter's environment.
+pub(crate) fn apply_local_packages(command: &mut Command) {
+    let Some(lib) = local_packages() else {
+        return;
+    };
+    let Ok(env) = local_packages_env(&lib, std::env::var_os("PYTHONPATH").as_deref()) else {
+        return;
+    };
+    let explicit: Vec<OsString> = command
+        .get_envs()
+        .map(|(key, _)| key.to_os_string())
+        .collect();
+    for (key, value) in env {
+        if !explicit.iter().any(|explicit| explicit == key) {
+            command.env(key, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_local_packages_env() {
+        let project = Path::new("project");
+        let lib = pypackages_lib(project, "3.12.1");
+        assert_eq!(
+            lib,
+            Path::new("project")
+                .join("__pypackages__")
+                .join("3.12")
+                .join("lib")
+        );
+
+        let existing = std::env::join_paths([Path::new("shared"), &lib]).unwrap();
+        let env = local_packages_env(&lib, Some(&existing)).unwrap();
+        let pythonpath: Vec<PathBuf> = std::env::split_paths(&env[0].1).collect();
+        assert_eq!(env[0].0, "PYTHONPATH");
+        assert_eq!(pythonpath, [lib.clone(), PathBuf::from("shared")]);
+        assert_eq!(env[1], ("PIP_TARGET", lib.clone().into_os_string()));
+        assert_eq!(env[2], ("PIP_UPGRADE", OsString::from("1")));
+        assert_eq!(env[3], (LOCAL_PACKAGES_ENV, lib.clone().into_os_string()));
+
+        let dir = tempfile::tempdir().unwrap();
+        assert!(!local_packages_requested(dir.path()));
+        std::fs::create_dir(dir.path().join(PYPACKAGES_DIR)).unwrap();
+        assert!(local_packages_requested(dir.path()));
+    }
+}
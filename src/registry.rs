@@ -0,0 +1,656 @@
+//! The package registry recorded in `packages.json`
+
+use crate::graph::DependencyGraph;
+use crate::spec::canonicalize_name;
+use crate::{PackageError, Result};
+use serde::{Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// File the registry is kept in, relative to the project directory
+pub const REGISTRY_FILE: &str = "packages.json";
+
+/// Environment variable holding the key that signs the registry with an HMAC
+pub const REGISTRY_KEY_ENV: &str = "PPM_REGISTRY_KEY";
+
+/// Represents a Python package with its name and version
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Package {
+    /// Package name as it appears in PyPI
+    pub name: String,
+    /// Installed version of the package
+    pub version: String,
+    /// Whether the package was requested explicitly rather than pulled in as a dependency
+    #[serde(default = "default_explicit")]
+    pub explicit: bool,
+    /// Normalized names of the packages this package depends on
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    /// Whether the package is held at its current version during upgrades
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Where pip downloaded the package from, if known from pip's installation report
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin: Option<PackageOrigin>,
+}
+
+/// Download location of an installed package, taken from pip's `--report`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PackageOrigin {
+    /// URL of the wheel, sdist, directory or repository that was installed
+    pub url: String,
+    /// Archive hash such as "sha256=…", when pip downloaded an archive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Whether the package was requested by URL or path rather than by name
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub direct: bool,
+    /// Commit installed from a version control repository
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// Packages recorded before dependency tracking existed were all requested explicitly
+fn default_explicit() -> bool {
+    true
+}
+
+impl Package {
+    /// Creates a new Package instance
+    ///
+    /// # Arguments
+    /// * `name` - The package name
+    /// * `version` - The package version
+    ///
+    /// # Returns
+    /// A new Package instance
+    pub fn new(name: String, version: String) -> Self {
+        Self {
+            name,
+            version,
+            explicit: true,
+            dependencies: Vec::new(),
+            pinned: false,
+            origin: None,
+        }
+    }
+
+    /// Requirement spec reinstalling the commit a package was installed from,
+    /// if it came from a git repository
+    ///
+    /// # Returns
+    /// * `Option<String>` - A spec such as `pkg @ git+https://host/pkg.git@<commit>`
+    pub fn vcs_spec(&self) -> Option<String> {
+        let origin = self.origin.as_ref()?;
+        let commit = origin.commit.as_ref()?;
+        Some(format!(
+            "{} @ git+{}@{}",
+            self.name,
+            origin.url.trim_start_matches("git+"),
+            commit
+        ))
+    }
+}
+
+/// A named environment tracked next to the default one, e.g. "api" or "worker"
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct NamedEnvironment {
+    /// Virtual environment the packages are installed into, relative to the project directory
+    pub venv: PathBuf,
+    /// Map of package names to Package instances
+    #[serde(default)]
+    pub packages: HashMap<String, Package>,
+}
+
+/// Packages installed into a directory with `pip install --target`, e.g. for a zip deployment
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct VendoredSet {
+    /// Requirements file the directory was last synced to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requirements: Option<String>,
+    /// Map of package names to Package instances
+    #[serde(default)]
+    pub packages: HashMap<String, Package>,
+}
+
+/// Registry for tracking installed packages
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PackageRegistry {
+    /// Map of package names to Package instances
+    pub packages: HashMap<String, Package>,
+    /// Named environments, each with its own packages; the top-level packages
+    /// belong to the default environment
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub environments: BTreeMap<String, NamedEnvironment>,
+    /// Vendored directories by path relative to the project directory; they
+    /// belong to no environment
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub vendored: BTreeMap<String, VendoredSet>,
+}
+
+impl PackageRegistry {
+    /// Creates a new empty registry
+    pub fn new() -> Self {
+        Self {
+            packages: HashMap::new(),
+            environments: BTreeMap::new(),
+            vendored: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a package to the registry
+    ///
+    /// # Arguments
+    /// * `package` - The package to add
+    pub fn add_package(&mut self, package: Package) {
+        self.packages.insert(package.name.clone(), package);
+    }
+
+    /// Removes a package from the registry
+    ///
+    /// # Arguments
+    /// * `name` - The name of the package to remove
+    ///
+    /// # Returns
+    /// The removed package if it existed
+    pub fn remove_package(&mut self, name: &str) -> Option<Package> {
+        self.packages.remove(name)
+    }
+
+    /// Gets a package from the registry
+    ///
+    /// # Arguments
+    /// * `name` - The name of the package to retrieve
+    ///
+    /// # Returns
+    /// Reference to the package if it exists
+    pub fn get_package(&self, name: &str) -> Option<&Package> {
+        self.packages.get(name)
+    }
+
+    /// Checks if the registry is empty
+    pub fn is_empty(&self) -> bool {
+        self.packages.is_empty()
+    }
+
+    /// Finds the registry key of a package, ignoring case and separator differences
+    ///
+    /// # Arguments
+    /// * `name` - The package name as reported by pip or the user
+    ///
+    /// # Returns
+    /// The key under which the package is registered, if any
+    pub fn find_package_key(&self, name: &str) -> Option<String> {
+        if self.packages.contains_key(name) {
+            return Some(name.to_string());
+        }
+        let canonical = canonicalize_name(name);
+        self.packages
+            .keys()
+            .find(|key| canonicalize_name(key) == canonical)
+            .cloned()
+    }
+
+    /// Builds the dependency graph of the registered packages
+    ///
+    /// # Returns
+    /// A graph whose package names are the registry keys
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        DependencyGraph::from_registry(self)
+    }
+
+    /// Finds the registered packages that directly depend on a package
+    ///
+    /// # Arguments
+    /// * `name` - The package whose dependents should be found
+    ///
+    /// # Returns
+    /// Sorted registry keys of the direct dependents
+    pub fn dependents_of(&self, name: &str) -> Vec<String> {
+        self.dependency_graph().dependents(name)
+    }
+
+    /// Finds every registered package that directly or transitively depends on a package
+    ///
+    /// # Arguments
+    /// * `name` - The package whose dependent chain should be found
+    ///
+    /// # Returns
+    /// Registry keys of the dependents, ordered so that each package comes before
+    /// the packages it depends on
+    pub fn dependent_chain(&self, name: &str) -> Vec<String> {
+        self.dependency_graph().transitive_dependents(name)
+    }
+
+    /// Finds dependency packages that are no longer required by any explicit package
+    ///
+    /// # Returns
+    /// Sorted registry keys of the orphaned packages
+    pub fn orphaned_packages(&self) -> Vec<String> {
+        self.dependency_graph().orphans()
+    }
+}
+
+/// Named environment that [`load_packages`] and [`save_packages`] work on, set by `--env`
+static ACTIVE_ENVIRONMENT: Mutex<Option<String>> = Mutex::new(None);
+
+/// Makes the registry functions work on a named environment instead of the default one
+///
+/// # Arguments
+/// * `name` - Name of the environment, or None for the default environment
+pub fn set_active_environment(name: Option<String>) {
+    *ACTIVE_ENVIRONMENT.lock().unwrap() = name;
+}
+
+/// The named environment selected with `--env`, if any
+pub fn active_environment() -> Option<String> {
+    ACTIVE_ENVIRONMENT.lock().unwrap().clone()
+}
+
+/// Error for an environment the registry does not track
+pub(crate) fn unknown_environment(name: &str) -> PackageError {
+    PackageError::MetadataError(format!(
+        "No environment named {}; create it with `ppm env create {}`",
+        name, name
+    ))
+}
+
+/// Loads the package registry from the JSON file
+///
+/// Attempts to load the package registry from `packages.json` in the current directory,
+/// verifying its checksum with the key from `PPM_REGISTRY_KEY` if one is set.
+/// If the file doesn't exist, returns an empty registry. When a named
+/// environment is active, the registry holds only that environment's packages.
+///
+/// # Returns
+/// * `Result<PackageRegistry>` - Loaded registry, or [`PackageError::RegistryIntegrity`]
+///   if the file is corrupted or was changed outside ppm
+pub fn load_packages() -> Result<PackageRegistry> {
+    let path = Path::new(REGISTRY_FILE);
+    let mut registry = if path.exists() {
+        read_registry(path, registry_key().as_deref())?
+    } else {
+        PackageRegistry::new()
+    };
+    match active_environment() {
+        Some(name) => {
+            let environment = registry
+                .environments
+                .remove(&name)
+                .ok_or_else(|| unknown_environment(&name))?;
+            Ok(PackageRegistry {
+                packages: environment.packages,
+                ..PackageRegistry::new()
+            })
+        }
+        None => Ok(registry),
+    }
+}
+
+/// Saves the package registry to the JSON file
+///
+/// Serializes the current package registry to `packages.json` in the current directory,
+/// together with its checksum.
+/// Uses pretty printing for better readability. When a named environment is
+/// active, the registry replaces only that environment's packages.
+///
+/// # Arguments
+/// * `registry` - The registry to save
+///
+/// # Returns
+/// * `Result<()>` - Success or IO error
+pub fn save_packages(registry: &PackageRegistry) -> Result<()> {
+    let path = Path::new(REGISTRY_FILE);
+    let key = registry_key();
+    let Some(name) = active_environment() else {
+        return write_registry(path, registry, key.as_deref());
+    };
+
+    let mut stored = if path.exists() {
+        read_registry(path, key.as_deref())?
+    } else {
+        PackageRegistry::new()
+    };
+    stored
+        .environments
+        .get_mut(&name)
+        .ok_or_else(|| unknown_environment(&name))?
+        .packages = registry.packages.clone();
+    write_registry(path, &stored, key.as_deref())
+}
+
+/// Loads `packages.json` without verifying its checksum
+///
+/// Used to accept contents that failed the integrity check once they are trusted.
+///
+/// # Returns
+/// * `Result<PackageRegistry>` - The registry, or an error if the file is not valid JSON
+pub fn load_packages_unverified() -> Result<PackageRegistry> {
+    let path = Path::new(REGISTRY_FILE);
+    if !path.exists() {
+        return Ok(PackageRegistry::new());
+    }
+    let contents = std::fs::read_to_string(path)?;
+    serde_json::from_str(&contents).map_err(|e| {
+        PackageError::RegistryIntegrity(format!("{}: not valid JSON ({})", path.display(), e))
+    })
+}
+
+/// The registry signing key from `PPM_REGISTRY_KEY`, if set
+pub fn registry_key() -> Option<Vec<u8>> {
+    std::env::var_os(REGISTRY_KEY_ENV)
+        .filter(|key| !key.is_empty())
+        .map(|key| key.to_string_lossy().into_owned().into_bytes())
+}
+
+/// Checksum of the registry contents
+///
+/// Covers the packages, and the named environments when there are any,
+/// serialized compactly with the keys of every object sorted, so it does not
+/// depend on the formatting of the file or on map order.
+///
+/// # Arguments
+/// * `registry` - The registry to checksum
+/// * `key` - Signs the checksum as an HMAC when given
+///
+/// # Returns
+/// `sha256:<hex>`, or `hmac-sha256:<hex>` with a key
+pub fn registry_checksum(registry: &PackageRegistry, key: Option<&[u8]>) -> Result<String> {
+    let value = if registry.environments.is_empty() && registry.vendored.is_empty() {
+        serde_json::to_value(&registry.packages)?
+    } else {
+        serde_json::to_value(registry)?
+    };
+    let canonical = serde_json::to_string(&Canonical(&value))?;
+    let (algorithm, digest) = match key {
+        Some(key) => (
+            "hmac-sha256",
+            hmac_sha256::HMAC::mac(canonical.as_bytes(), key),
+        ),
+        None => ("sha256", hmac_sha256::Hash::hash(canonical.as_bytes())),
+    };
+    let hex: String = digest.iter().map(|byte| format!("{:02x}", byte)).collect();
+    Ok(format!("{}:{}", algorithm, hex))
+}
+
+/// A JSON value that serializes with the keys of every object sorted
+///
+/// serde_json keeps insertion order when its `preserve_order` feature is
+/// enabled anywhere in the build, so the order is fixed here instead.
+struct Canonical<'a>(&'a serde_json::Value);
+
+impl Serialize for Canonical<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match self.0 {
+            serde_json::Value::Object(map) => {
+                let sorted: BTreeMap<&String, Canonical> = map
+                    .iter()
+                    .map(|(key, value)| (key, Canonical(value)))
+                    .collect();
+                sorted.serialize(serializer)
+            }
+            serde_json::Value::Array(items) => serializer.collect_seq(items.iter().map(Canonical)),
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+/// Reads a registry file and verifies its checksum
+///
+/// A file without a checksum is refused like a tampered one, since anyone
+/// could have removed it; `ppm registry rehash` accepts such a file once it is
+/// trusted. With a key, the file must carry an HMAC made with it.
+///
+/// # Arguments
+/// * `path` - The registry file
+/// * `key` - Key the registry is signed with, if any
+///
+/// # Returns
+/// * `Result<PackageRegistry>` - The registry, or [`PackageError::RegistryIntegrity`]
+///   if it is not valid JSON or its checksum is missing or does not match
+pub fn read_registry(path: &Path, key: Option<&[u8]>) -> Result<PackageRegistry> {
+    let contents = std::fs::read_to_string(path)?;
+    let integrity_error =
+        |reason: String| PackageError::RegistryIntegrity(format!("{}: {}", path.display(), reason));
+    let mut document: serde_json::Value = serde_json::from_str(&contents)
+        .map_err(|e| integrity_error(format!("not valid JSON ({})", e)))?;
+    let stored = document
+        .as_object_mut()
+        .and_then(|object| object.remove("checksum"));
+    let registry: PackageRegistry = serde_json::from_value(document)
+        .map_err(|e| integrity_error(format!("not a package registry ({})", e)))?;
+
+    let stored = match stored {
+        Some(serde_json::Value::String(checksum)) => checksum,
+        Some(_) => return Err(integrity_error("malformed checksum".to_string())),
+        None if key.is_some() => {
+            return Err(integrity_error(format!(
+                "it is not signed, but {} is set",
+                REGISTRY_KEY_ENV
+            )))
+        }
+        None => {
+            return Err(integrity_error(
+                "it has no checksum; run `ppm registry rehash` to accept its contents".to_string(),
+            ))
+        }
+    };
+    if stored.starts_with("hmac-") && key.is_none() {
+        return Err(integrity_error(format!(
+            "it is signed with a key; set {} to verify it",
+            REGISTRY_KEY_ENV
+        )));
+    }
+    if !stored.starts_with("hmac-") && key.is_some() {
+        return Err(integrity_error(format!(
+            "it carries a plain checksum, but {} is set",
+            REGISTRY_KEY_ENV
+        )));
+    }
+    if stored != registry_checksum(&registry, key)? {
+        return Err(integrity_error(
+            "its contents do not match the checksum; it was changed outside ppm or is corrupted"
+                .to_string(),
+        ));
+    }
+    Ok(registry)
+}
+
+/// Writes a registry file with its checksum
+///
+/// The file is written next to its destination and then renamed over it, so
+/// an interruption never leaves a truncated registry behind.
+///
+/// # Arguments
+/// * `path` - The registry file
+/// * `registry` - The registry to save
+/// * `key` - Signs the checksum as an HMAC when given
+pub fn write_registry(path: &Path, registry: &PackageRegistry, key: Option<&[u8]>) -> Result<()> {
+    #[derive(Serialize)]
+    struct Stored<'a> {
+        #[serde(flatten)]
+        registry: &'a PackageRegistry,
+        checksum: String,
+    }
+
+    let stored = Stored {
+        registry,
+        checksum: registry_checksum(registry, key)?,
+    };
+    let partial = path.with_extension("json.tmp");
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    serde_json::to_writer_pretty(&mut writer, &stored)?;
+    writer.flush()?;
+    // The contents must be on disk before the rename makes them the registry
+    writer.get_ref().sync_all()?;
+    std::fs::rename(partial, path)?;
+    Ok(())
+}
+
+/// Orders a batch of packages so that dependents are removed before their dependencies
+pub(crate) fn order_for_removal(names: &[String], registry: &PackageRegistry) -> Vec<String> {
+    registry.dependency_graph().removal_order(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remove::{delete_package_with_dependents, DependentsPolicy};
+
+    #[test]
+    fn test_package_registry_operations() {
+        let mut registry = PackageRegistry::new();
+        let package = Package::new("test".to_string(), "1.0.0".to_string());
+
+        registry.add_package(package.clone());
+        assert_eq!(registry.get_package("test"), Some(&package));
+
+        let removed = registry.remove_package("test");
+        assert_eq!(removed, Some(package));
+        assert!(registry.is_empty());
+    }
+
+    #[test]
+    fn test_orphaned_packages() {
+        let mut registry = PackageRegistry::new();
+
+        let mut flask = Package::new("Flask".to_string(), "3.0.2".to_string());
+        flask.dependencies = vec!["jinja2".to_string()];
+        registry.add_package(flask);
+
+        let mut jinja = Package::new("Jinja2".to_string(), "3.1.4".to_string());
+        jinja.explicit = false;
+        jinja.dependencies = vec!["markupsafe".to_string()];
+        registry.add_package(jinja);
+
+        let mut markupsafe = Package::new("MarkupSafe".to_string(), "2.1.5".to_string());
+        markupsafe.explicit = false;
+        registry.add_package(markupsafe);
+
+        let mut leftover = Package::new("charset_normalizer".to_string(), "3.3.2".to_string());
+        leftover.explicit = false;
+        registry.add_package(leftover);
+
+        assert_eq!(registry.orphaned_packages(), vec!["charset_normalizer"]);
+
+        registry.remove_package("Flask");
+        assert_eq!(
+            registry.orphaned_packages(),
+            vec!["Jinja2", "MarkupSafe", "charset_normalizer"]
+        );
+    }
+
+    #[test]
+    fn test_registry_without_dependency_fields_loads_as_explicit() {
+        let json = r#"{"packages":{"requests":{"name":"requests","version":"2.32.3"}}}"#;
+        let registry: PackageRegistry = serde_json::from_str(json).unwrap();
+        let package = registry.get_package("requests").unwrap();
+
+        assert!(package.explicit);
+        assert!(package.dependencies.is_empty());
+        assert_eq!(
+            registry.find_package_key("Requests"),
+            Some("requests".to_string())
+        );
+    }
+
+    #[test]
+    fn test_dependent_chain() {
+        let mut registry = PackageRegistry::new();
+
+        let mut app = Package::new("app".to_string(), "1.0".to_string());
+        app.dependencies = vec!["flask".to_string()];
+        registry.add_package(app);
+
+        let mut flask = Package::new("flask".to_string(), "3.0.2".to_string());
+        flask.dependencies = vec!["werkzeug".to_string()];
+        registry.add_package(flask);
+
+        registry.add_package(Package::new("Werkzeug".to_string(), "3.0.1".to_string()));
+
+        assert_eq!(registry.dependents_of("werkzeug"), vec!["flask"]);
+        assert_eq!(registry.dependent_chain("werkzeug"), vec!["app", "flask"]);
+        assert!(registry.dependent_chain("app").is_empty());
+
+        let err =
+            delete_package_with_dependents("werkzeug", DependentsPolicy::Refuse, &mut registry)
+                .unwrap_err();
+        assert!(matches!(err, PackageError::RequiredByOthers(_, ref d) if d == &["flask"]));
+    }
+
+    #[test]
+    fn test_order_for_removal() {
+        let mut registry = PackageRegistry::new();
+        let mut flask = Package::new("flask".to_string(), "3.0.2".to_string());
+        flask.dependencies = vec!["werkzeug".to_string()];
+        registry.add_package(flask);
+        registry.add_package(Package::new("werkzeug".to_string(), "3.0.1".to_string()));
+
+        let names = vec!["werkzeug".to_string(), "flask".to_string()];
+        assert_eq!(
+            order_for_removal(&names, &registry),
+            vec!["flask", "werkzeug"]
+        );
+    }
+
+    #[test]
+    fn test_registry_checksum_detects_tampering() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("packages.json");
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("requests".to_string(), "2.32.3".to_string()));
+        registry.add_package(Package::new("idna".to_string(), "3.7".to_string()));
+
+        write_registry(&path, &registry, None).unwrap();
+        let contents = std::fs::read_to_string(&path).unwrap();
+        assert!(contents.contains("\"checksum\": \"sha256:"));
+        assert_eq!(
+            read_registry(&path, None).unwrap().packages,
+            registry.packages
+        );
+
+        // A changed version is caught instead of silently loaded
+        std::fs::write(&path, contents.replace("2.32.3", "2.0.0")).unwrap();
+        let error = read_registry(&path, None).unwrap_err();
+        assert_eq!(error.code(), "PPM0018");
+        assert!(error.to_string().contains("do not match the checksum"));
+
+        // So is a truncated file
+        std::fs::write(&path, &contents[..contents.len() / 2]).unwrap();
+        assert!(matches!(
+            read_registry(&path, None),
+            Err(PackageError::RegistryIntegrity(_))
+        ));
+
+        // With a key, only the holder of the key can produce a valid checksum
+        write_registry(&path, &registry, Some(b"secret")).unwrap();
+        assert!(read_registry(&path, Some(b"secret")).is_ok());
+        assert!(read_registry(&path, Some(b"other")).is_err());
+        assert!(read_registry(&path, None).is_err());
+        write_registry(&path, &registry, None).unwrap();
+        assert!(read_registry(&path, Some(b"secret")).is_err());
+
+        // The checksum does not depend on the order packages were added in
+        let mut reordered = PackageRegistry::new();
+        reordered.add_package(Package::new("idna".to_string(), "3.7".to_string()));
+        reordered.add_package(Package::new("requests".to_string(), "2.32.3".to_string()));
+        assert_eq!(
+            registry_checksum(&reordered, None).unwrap(),
+            registry_checksum(&registry, None).unwrap()
+        );
+        assert!(!path.with_extension("json.tmp").exists());
+
+        // Stripping the checksum does not get a changed registry past the check
+        let mut document: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        document.as_object_mut().unwrap().remove("checksum");
+        std::fs::write(&path, document.to_string().replace("2.32.3", "2.0.0")).unwrap();
+        let error = read_registry(&path, None).unwrap_err();
+        assert_eq!(error.code(), "PPM0018");
+        assert!(error.to_string().contains("no checksum"));
+        assert!(error.to_string().contains("ppm registry rehash"));
+    }
+}
@@ -0,0 +1,372 @@
+//! Release notes and changes between two versions of a package
+
+use crate::metadata::fetch_project_json;
+use crate::pip::get_python_executable;
+use crate::spec::{compare_versions, is_prerelease};
+use crate::upgrade::{find_changelog_url, fixed_vulnerabilities, Vulnerability};
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::cmp::Ordering;
+
+/// A release published between the two versions compared by [`release_changes`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReleaseNote {
+    /// The release
+    pub version: String,
+    /// Day its first file was uploaded, e.g. "2024-05-29"
+    pub date: Option<String>,
+    /// Why the release was yanked, empty if no reason was given
+    pub yanked: Option<String>,
+    /// The release's Requires-Python, when it differs from the release before it
+    pub requires_python: Option<String>,
+    /// Whether the release starts a new major version
+    pub major: bool,
+    /// The release's page on PyPI
+    pub url: String,
+}
+
+/// The releases of a package between two versions, see [`release_changes`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReleaseChanges {
+    /// Package name
+    pub name: String,
+    /// The version upgraded from
+    pub from: String,
+    /// The version upgraded to
+    pub to: String,
+    /// Releases after `from` up to and including `to`, oldest first
+    pub releases: Vec<ReleaseNote>,
+    /// Advisories affecting `from` that `to` fixes
+    pub fixed_vulnerabilities: Vec<Vulnerability>,
+    /// The project's changelog, if it links one
+    pub changelog_url: Option<String>,
+    /// The GitHub releases page of the project's repository, if it has one
+    pub releases_url: Option<String>,
+}
+
+/// Day the first file of a release was uploaded, e.g. "2024-05-29"
+///
+/// # Arguments
+/// * `files` - The release's entry in the `releases` table of a PyPI project document
+pub(crate) fn release_date(files: &serde_json::Value) -> Option<String> {
+    files
+        .as_array()?
+        .iter()
+        .filter_map(|file| {
+            file["upload_time_iso_8601"]
+                .as_str()
+                .or_else(|| file["upload_time"].as_str())
+        })
+        .min()
+        .map(|time| time.chars().take(10).collect())
+}
+
+/// Parses the range of `ppm changes`, e.g. "2.25.0..2.32.3"
+///
+/// Either end may be left out: a missing start stands for the installed
+/// version and a missing end for the latest release.
+///
+/// # Arguments
+/// * `range` - The range, `FROM..TO`
+///
+/// # Returns
+/// * `Result<(Option<String>, Option<String>)>` - The two ends, or an error if there is no `..`
+pub fn parse_change_range(range: &str) -> Result<(Option<String>, Option<String>)> {
+    let (from, to) = range.split_once("..").ok_or_else(|| {
+        PackageError::InvalidPackageSpec(format!(
+            "Expected a version range like 1.0..2.0, got '{}'",
+            range
+        ))
+    })?;
+    let end = |version: &str| {
+        let version = version.trim();
+        (!version.is_empty()).then(|| version.to_string())
+    };
+    Ok((end(from), end(to)))
+}
+
+/// Finds the GitHub releases page of a project from its PyPI `info` table
+fn github_releases_url(info: &serde_json::Value) -> Option<String> {
+    let mut urls: Vec<&str> = info["project_urls"]
+        .as_object()
+        .map(|urls| urls.values().filter_map(|url| url.as_str()).collect())
+        .unwrap_or_default();
+    urls.extend(info["home_page"].as_str());
+    urls.into_iter().find_map(|url| {
+        let path = url.split_once("github.com/")?.1;
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        let owner = segments.next()?;
+        let repo = segments.next()?.trim_end_matches(".git");
+        Some(format!("https://github.com/{}/{}/releases", owner, repo))
+    })
+}
+
+/// Summarizes the releases of a package between two versions
+///
+/// Pre-releases are left out unless one of the ends is a pre-release. A
+/// release is marked when it starts a new major version, changes the
+/// supported Python versions or was yanked, the usual sources of trouble in
+/// an upgrade.
+///
+/// # Arguments
+/// * `name` - The package name
+/// * `from` - The version upgraded from
+/// * `to` - The version upgraded to
+/// * `project` - The PyPI project document of the package
+///
+/// # Returns
+/// * `Result<ReleaseChanges>` - The releases, without advisories, or an error
+///   if `to` was never published or is not newer than `from`
+pub fn summarize_releases(
+    name: &str,
+    from: &str,
+    to: &str,
+    project: &serde_json::Value,
+) -> Result<ReleaseChanges> {
+    let releases = project["releases"].as_object().ok_or_else(|| {
+        PackageError::MetadataError(format!("No releases published for {}", name))
+    })?;
+    if !releases.contains_key(to) {
+        return Err(PackageError::MetadataError(format!(
+            "{} {} was never published on PyPI",
+            name, to
+        )));
+    }
+    if compare_versions(to, from) != Ordering::Greater {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "{} is not newer than {}",
+            to, from
+        )));
+    }
+
+    let requires_python = |files: &serde_json::Value| {
+        files.as_array().and_then(|files| {
+            files
+                .iter()
+                .find_map(|file| file["requires_python"].as_str())
+                .map(str::to_string)
+        })
+    };
+    let major = |version: &str| {
+        let digits: String = version.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse::<u64>().unwrap_or(0)
+    };
+
+    let allow_pre = is_prerelease(from) || is_prerelease(to);
+    let mut versions: Vec<&String> = releases
+        .keys()
+        .filter(|version| allow_pre || !is_prerelease(version))
+        .filter(|version| {
+            compare_versions(version, from) == Ordering::Greater
+                && compare_versions(version, to) != Ordering::Greater
+        })
+        .collect();
+    versions.sort_by(|a, b| compare_versions(a, b));
+
+    let mut previous_python = releases.get(from).and_then(requires_python);
+    let mut previous_major = major(from);
+    let mut notes = Vec::new();
+    for version in versions {
+        let files = &releases[version];
+        let file_list = files.as_array().map(Vec::as_slice).unwrap_or_default();
+        let date = release_date(files);
+        let yanked = (!file_list.is_empty()
+            && file_list
+                .iter()
+                .all(|file| file["yanked"].as_bool().unwrap_or(false)))
+        .then(|| {
+            file_list
+                .iter()
+                .find_map(|file| file["yanked_reason"].as_str())
+                .unwrap_or("")
+                .to_string()
+        });
+        let python = requires_python(files);
+        let changed_python = python != previous_python;
+        previous_python = python.clone();
+        let starts_major = major(version) > previous_major;
+        previous_major = previous_major.max(major(version));
+
+        notes.push(ReleaseNote {
+            version: version.clone(),
+            date,
+            yanked,
+            requires_python: changed_python.then(|| python.unwrap_or_else(|| "any".to_string())),
+            major: starts_major,
+            url: format!("https://pypi.org/project/{}/{}/", name, version),
+        });
+    }
+
+    let info = &project["info"];
+    Ok(ReleaseChanges {
+        name: info["name"].as_str().unwrap_or(name).to_string(),
+        from: from.to_string(),
+        to: to.to_string(),
+        releases: notes,
+        fixed_vulnerabilities: Vec::new(),
+        changelog_url: find_changelog_url(&info["project_urls"]),
+        releases_url: github_releases_url(info),
+    })
+}
+
+/// Looks up what changed in a package between two releases
+///
+/// # Arguments
+/// * `name` - The package name
+/// * `from` - The version upgraded from
+/// * `to` - The version upgraded to, or None for the latest release
+///
+/// # Returns
+/// * `Result<ReleaseChanges>` - The releases in between and the advisories
+///   the upgrade fixes, or an error if PyPI could not be reached
+pub fn release_changes(name: &str, from: &str, to: Option<&str>) -> Result<ReleaseChanges> {
+    let python = get_python_executable()?;
+    let project = fetch_project_json(&python, name)?;
+    let to = match to {
+        Some(to) => to.to_string(),
+        None => project["info"]["version"]
+            .as_str()
+            .ok_or_else(|| {
+                PackageError::MetadataError(format!("No version published for {}", name))
+            })?
+            .to_string(),
+    };
+    let mut changes = summarize_releases(name, from, &to, &project)?;
+    changes.fixed_vulnerabilities = fixed_vulnerabilities(&python, name, from, &to);
+    Ok(changes)
+}
+
+/// Renders the releases between two versions of a package
+///
+/// # Arguments
+/// * `changes` - The releases, see [`release_changes`]
+///
+/// # Returns
+/// * `String` - A headline, one line per release with what to watch out
+///   for, the advisories fixed and links to the release notes
+pub fn render_release_changes(changes: &ReleaseChanges) -> String {
+    let releases = &changes.releases;
+    let mut out = format!(
+        "{} {} -> {}: {} release{}",
+        changes.name,
+        changes.from,
+        changes.to,
+        releases.len(),
+        if releases.len() == 1 { "" } else { "s" }
+    );
+    let dates: Vec<&str> = releases.iter().filter_map(|r| r.date.as_deref()).collect();
+    if let (Some(first), Some(last)) = (dates.first(), dates.last()) {
+        if first == last {
+            out.push_str(&format!(", published {}", first));
+        } else {
+            out.push_str(&format!(", published {} to {}", first, last));
+        }
+    }
+    out.push('\n');
+
+    let width = releases.iter().map(|r| r.version.len()).max().unwrap_or(0);
+    for release in releases {
+        let mut notes = Vec::new();
+        if release.major {
+            notes.push("new major version".to_string());
+        }
+        if let Some(python) = &release.requires_python {
+            notes.push(format!("requires Python {}", python));
+        }
+        match release.yanked.as_deref() {
+            Some("") => notes.push("yanked".to_string()),
+            Some(reason) => notes.push(format!("yanked: {}", reason)),
+            None => {}
+        }
+        let line = format!(
+            "  {:<width$}  {:<10}  {}",
+            release.version,
+            release.date.as_deref().unwrap_or("-"),
+            notes.join("; "),
+            width = width
+        );
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    for vulnerability in &changes.fixed_vulnerabilities {
+        let summary = vulnerability.summary.lines().next().unwrap_or("").trim();
+        if summary.is_empty() {
+            out.push_str(&format!("Fixes {}\n", vulnerability.id));
+        } else {
+            out.push_str(&format!("Fixes {}: {}\n", vulnerability.id, summary));
+        }
+    }
+    if let Some(url) = &changes.changelog_url {
+        out.push_str(&format!("Changelog: {}\n", url));
+    }
+    if let Some(url) = &changes.releases_url {
+        out.push_str(&format!("Releases:  {}\n", url));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summarize_releases() {
+        assert_eq!(
+            parse_change_range("2.25.0..2.32.3").unwrap(),
+            (Some("2.25.0".to_string()), Some("2.32.3".to_string()))
+        );
+        assert_eq!(parse_change_range("..").unwrap(), (None, None));
+        assert!(parse_change_range("2.25.0").is_err());
+
+        let file = |time: &str, python: &str, yanked: Option<&str>| {
+            serde_json::json!({
+                "upload_time_iso_8601": time,
+                "requires_python": python,
+                "yanked": yanked.is_some(),
+                "yanked_reason": yanked,
+            })
+        };
+        let project = serde_json::json!({
+            "info": {
+                "name": "Demo",
+                "project_urls": {
+                    "Source": "https://github.com/org/demo.git",
+                    "Changelog": "https://demo.example/changes",
+                },
+            },
+            "releases": {
+                "1.9.0": [file("2023-01-01T00:00:00Z", ">=3.7", None)],
+                "1.10.0": [file("2023-06-01T10:00:00Z", ">=3.7", None)],
+                "2.0.0rc1": [file("2023-12-01T00:00:00Z", ">=3.8", None)],
+                "2.0.0": [file("2024-01-02T00:00:00Z", ">=3.8", Some("Broken import"))],
+                "2.0.1": [file("2024-01-05T00:00:00Z", ">=3.8", None)],
+                "2.1.0": [file("2024-03-01T00:00:00Z", ">=3.8", None)],
+            },
+        });
+
+        let changes = summarize_releases("demo", "1.9.0", "2.0.1", &project).unwrap();
+        let versions: Vec<&str> = changes
+            .releases
+            .iter()
+            .map(|r| r.version.as_str())
+            .collect();
+        assert_eq!(versions, ["1.10.0", "2.0.0", "2.0.1"]);
+        assert_eq!(
+            changes.releases_url.as_deref(),
+            Some("https://github.com/org/demo/releases")
+        );
+        assert_eq!(
+            render_release_changes(&changes),
+            "Demo 1.9.0 -> 2.0.1: 3 releases, published 2023-06-01 to 2024-01-05\n\
+             \x20 1.10.0  2023-06-01\n\
+             \x20 2.0.0   2024-01-02  new major version; requires Python >=3.8; yanked: Broken import\n\
+             \x20 2.0.1   2024-01-05\n\
+             Changelog: https://demo.example/changes\n\
+             Releases:  https://github.com/org/demo/releases\n"
+        );
+
+        assert!(summarize_releases("demo", "2.1.0", "2.0.1", &project).is_err());
+        assert!(summarize_releases("demo", "1.9.0", "3.0.0", &project).is_err());
+    }
+}
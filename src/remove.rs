@@ -0,0 +1,470 @@
+//! Removing packages and pruning the ones nothing needs
+
+use crate::diff::environment_package_set;
+use crate::pip::{get_python_executable, pip_error, run_pip};
+use crate::registry::{order_for_removal, Package, PackageRegistry};
+use crate::reporter::success_mark;
+use crate::session::record_removal;
+use crate::spec::{canonicalize_name, glob_match};
+use crate::stats::{record_timing, Phase};
+use crate::{PackageError, Result};
+use std::collections::HashSet;
+use std::process::Command;
+use std::time::Instant;
+
+/// Deletes a package using pip uninstall
+///
+/// Removes the specified package from the system and updates the registry.
+///
+/// # Arguments
+/// * `name` - Name of the package to delete
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or deletion error
+pub fn delete_package(name: &str, registry: &mut PackageRegistry) -> Result<()> {
+    if name.trim().is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "Package name cannot be empty".to_string(),
+        ));
+    }
+
+    let python = get_python_executable()?;
+
+    let started = Instant::now();
+    let mut command = Command::new(&python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("uninstall")
+        .arg(name)
+        .arg("-y");
+    let output = run_pip(command, name, None)?;
+    record_timing(name, Phase::Uninstall, started);
+
+    if !output.status.success() {
+        return Err(pip_error(name, &output, PackageError::UninstallationFailed));
+    }
+
+    let removed = registry
+        .find_package_key(name)
+        .and_then(|key| registry.remove_package(&key));
+    record_removal(name, removed.map(|p| p.version));
+    status!("{} Successfully removed package {}", success_mark(), name);
+    Ok(())
+}
+
+/// Deletes several packages, checking dependents against the whole batch
+///
+/// Packages that only depend on other members of the batch do not block the
+/// deletion. Members are removed dependents-first so each removal is valid on
+/// its own.
+///
+/// # Arguments
+/// * `names` - Names of the packages to delete
+/// * `policy` - What to do when packages outside the batch depend on them
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, a [`PackageError::RequiredByOthers`] refusal, or deletion error
+pub fn delete_packages(
+    names: &[String],
+    policy: DependentsPolicy,
+    registry: &mut PackageRegistry,
+) -> Result<()> {
+    if policy == DependentsPolicy::Refuse {
+        check_removal(names, registry)?;
+    }
+
+    let registered: HashSet<String> = names
+        .iter()
+        .filter(|n| registry.find_package_key(n).is_some())
+        .map(|n| canonicalize_name(n))
+        .collect();
+
+    for name in order_for_removal(names, registry) {
+        // An earlier cascade may already have removed this batch member
+        let removed_by_cascade = registered.contains(&canonicalize_name(&name))
+            && registry.find_package_key(&name).is_none();
+        if removed_by_cascade {
+            continue;
+        }
+        delete_package_with_dependents(&name, policy, registry)?;
+    }
+    Ok(())
+}
+
+/// What a delete removes, worked out before anything is uninstalled, see [`plan_removal`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RemovalPlan {
+    /// The packages asked for, with patterns expanded
+    pub packages: Vec<String>,
+    /// Registered packages removed along with them because they depend on
+    /// them, when dependents are cascaded
+    pub dependents: Vec<String>,
+}
+
+impl RemovalPlan {
+    /// Whether the removal is confirmed first
+    ///
+    /// Every removal is, a single package as much as a batch or a dependent
+    /// chain, since an uninstall cannot be undone; `--yes` answers in advance.
+    pub fn needs_confirmation(&self) -> bool {
+        !self.packages.is_empty() || !self.dependents.is_empty()
+    }
+}
+
+/// Works out which packages a delete removes
+///
+/// With [`DependentsPolicy::Refuse`] a batch that others depend on is refused
+/// here, so nobody is asked to confirm a doomed removal; with
+/// [`DependentsPolicy::Cascade`] the dependent chains of the batch are listed.
+///
+/// # Arguments
+/// * `names` - Names of the packages to delete, with patterns expanded
+/// * `policy` - What to do when other packages depend on them
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<RemovalPlan>` - The plan, or a [`PackageError::RequiredByOthers`] refusal
+pub fn plan_removal(
+    names: &[String],
+    policy: DependentsPolicy,
+    registry: &PackageRegistry,
+) -> Result<RemovalPlan> {
+    if policy == DependentsPolicy::Refuse {
+        check_removal(names, registry)?;
+    }
+    let mut seen: HashSet<String> = names.iter().map(|n| canonicalize_name(n)).collect();
+    let mut dependents = Vec::new();
+    if policy == DependentsPolicy::Cascade {
+        for name in names {
+            for dependent in registry.dependent_chain(name) {
+                if seen.insert(canonicalize_name(&dependent)) {
+                    dependents.push(dependent);
+                }
+            }
+        }
+    }
+    Ok(RemovalPlan {
+        packages: names.to_vec(),
+        dependents,
+    })
+}
+
+/// Checks that no package outside a batch depends on a member of the batch
+///
+/// # Arguments
+/// * `names` - Names of the packages about to be deleted
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or a [`PackageError::RequiredByOthers`] for the first blocked package
+pub fn check_removal(names: &[String], registry: &PackageRegistry) -> Result<()> {
+    let targets: HashSet<String> = names.iter().map(|n| canonicalize_name(n)).collect();
+
+    for name in names {
+        let outside: Vec<String> = registry
+            .dependents_of(name)
+            .into_iter()
+            .filter(|d| !targets.contains(&canonicalize_name(d)))
+            .collect();
+        if !outside.is_empty() {
+            return Err(PackageError::RequiredByOthers(name.clone(), outside));
+        }
+    }
+    Ok(())
+}
+
+/// Resolves package names and glob patterns against the registry
+///
+/// Plain names are passed through unchanged; patterns containing `*`, `?` or
+/// `[...]` expand to every matching registry entry.
+///
+/// # Arguments
+/// * `patterns` - Package names or glob patterns
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<Vec<String>>` - Matched names without duplicates, or
+///   [`PackageError::PackageNotFound`] for a pattern that matches nothing
+pub fn resolve_package_patterns(
+    patterns: &[String],
+    registry: &PackageRegistry,
+) -> Result<Vec<String>> {
+    let mut resolved: Vec<String> = Vec::new();
+    let mut seen: HashSet<String> = HashSet::new();
+
+    for pattern in patterns {
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err(PackageError::InvalidPackageSpec(
+                "Package name cannot be empty".to_string(),
+            ));
+        }
+
+        let matches: Vec<String> = if pattern.contains(['*', '?', '[']) {
+            let canonical_pattern = pattern.to_lowercase();
+            let mut matches: Vec<String> = registry
+                .packages
+                .keys()
+                .filter(|key| glob_match(&canonical_pattern, &key.to_lowercase()))
+                .cloned()
+                .collect();
+            if matches.is_empty() {
+                return Err(PackageError::PackageNotFound(pattern.to_string()));
+            }
+            matches.sort();
+            matches
+        } else {
+            vec![registry
+                .find_package_key(pattern)
+                .unwrap_or_else(|| pattern.to_string())]
+        };
+
+        for name in matches {
+            if seen.insert(canonicalize_name(&name)) {
+                resolved.push(name);
+            }
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// How to treat registered packages that depend on a package being deleted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DependentsPolicy {
+    /// Refuse to delete a package that others depend on
+    Refuse,
+    /// Delete the package together with its whole dependent chain
+    Cascade,
+    /// Delete the package and leave its dependents in place
+    Force,
+}
+
+/// Deletes a package after checking which registered packages depend on it
+///
+/// # Arguments
+/// * `name` - Name of the package to delete
+/// * `policy` - What to do when other packages depend on it
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success, a [`PackageError::RequiredByOthers`] refusal, or deletion error
+pub fn delete_package_with_dependents(
+    name: &str,
+    policy: DependentsPolicy,
+    registry: &mut PackageRegistry,
+) -> Result<()> {
+    let dependents = registry.dependent_chain(name);
+
+    if !dependents.is_empty() {
+        match policy {
+            DependentsPolicy::Refuse => {
+                return Err(PackageError::RequiredByOthers(
+                    name.to_string(),
+                    registry.dependents_of(name),
+                ));
+            }
+            DependentsPolicy::Cascade => {
+                status!("Also removing dependents: {}", dependents.join(", "));
+                for dependent in &dependents {
+                    delete_package(dependent, registry)?;
+                }
+            }
+            DependentsPolicy::Force => {
+                warning!(
+                    "{} is still required by: {}",
+                    name,
+                    registry.dependents_of(name).join(", ")
+                );
+            }
+        }
+    }
+
+    delete_package(name, registry)
+}
+
+/// Finds registry entries whose packages are no longer installed, e.g. after
+/// they were removed with pip directly
+///
+/// # Arguments
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<Vec<String>>` - Sorted registry keys of the stale entries, or an
+///   error if the installed distributions could not be listed
+pub fn stale_packages(registry: &PackageRegistry) -> Result<Vec<String>> {
+    let installed = environment_package_set(&get_python_executable()?)?;
+    let mut stale: Vec<String> = registry
+        .packages
+        .iter()
+        .filter(|(_, package)| !installed.contains_key(&canonicalize_name(&package.name)))
+        .map(|(key, _)| key.clone())
+        .collect();
+    stale.sort();
+    Ok(stale)
+}
+
+/// Removes registry entries without touching the environment
+///
+/// # Arguments
+/// * `names` - Registry keys, as returned by [`stale_packages`]
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// The entries that were removed
+pub fn prune_packages(names: &[String], registry: &mut PackageRegistry) -> Vec<Package> {
+    names
+        .iter()
+        .filter_map(|name| registry.remove_package(name))
+        .collect()
+}
+
+/// Uninstalls dependencies that are no longer required by any explicit package
+///
+/// # Arguments
+/// * `names` - Registry keys of the orphaned packages, as returned by
+///   [`PackageRegistry::orphaned_packages`]
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or uninstallation error
+pub fn remove_orphaned_packages(names: &[String], registry: &mut PackageRegistry) -> Result<()> {
+    if names.is_empty() {
+        return Ok(());
+    }
+
+    let python = get_python_executable()?;
+
+    let started = Instant::now();
+    let mut command = Command::new(&python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("uninstall")
+        .arg("-y")
+        .args(names);
+    let output = run_pip(command, "pip", None)?;
+    record_timing(&names.join(" "), Phase::Uninstall, started);
+
+    if !output.status.success() {
+        return Err(pip_error(
+            &names.join(" "),
+            &output,
+            PackageError::UninstallationFailed,
+        ));
+    }
+
+    for name in names {
+        let removed = registry.remove_package(name);
+        record_removal(name, removed.map(|p| p.version));
+        status!("{} Successfully removed package {}", success_mark(), name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::INSTALLED_DISTRIBUTIONS_SCRIPT;
+    use crate::session::{prompt_answer, PromptAnswer};
+    use crate::test_support::{python_runner, with_runner};
+
+    #[test]
+    fn test_resolve_package_patterns() {
+        let mut registry = PackageRegistry::new();
+        for name in ["boto3", "boto3-stubs", "botocore", "requests"] {
+            registry.add_package(Package::new(name.to_string(), "1.0".to_string()));
+        }
+
+        let patterns = vec![
+            "boto3*".to_string(),
+            "Requests".to_string(),
+            "boto3".to_string(),
+        ];
+        assert_eq!(
+            resolve_package_patterns(&patterns, &registry).unwrap(),
+            vec!["boto3", "boto3-stubs", "requests"]
+        );
+
+        let missing = vec!["numpy*".to_string()];
+        assert!(matches!(
+            resolve_package_patterns(&missing, &registry),
+            Err(PackageError::PackageNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_prune_stale_packages() {
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("Rich".to_string(), "13.7.0".to_string()));
+        registry.add_package(Package::new("requests".to_string(), "2.32.3".to_string()));
+        let runner = python_runner().respond(
+            INSTALLED_DISTRIBUTIONS_SCRIPT,
+            r#"{"rich": "13.7.0", "pip": "24.0"}"#,
+        );
+
+        let stale = with_runner(runner, |_| stale_packages(&registry)).unwrap();
+        assert_eq!(stale, ["requests"]);
+        let pruned = prune_packages(&stale, &mut registry);
+        assert_eq!(pruned[0].version, "2.32.3");
+        assert_eq!(registry.packages.keys().collect::<Vec<_>>(), ["Rich"]);
+
+        // An environment that cannot be listed prunes nothing
+        let result = with_runner(python_runner(), |_| stale_packages(&registry));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_plan_removal_lists_dependent_chains() {
+        let mut registry = PackageRegistry::new();
+        let mut app = Package::new("app".to_string(), "1.0".to_string());
+        app.dependencies = vec!["flask".to_string()];
+        let mut flask = Package::new("flask".to_string(), "3.0.3".to_string());
+        flask.dependencies = vec!["werkzeug".to_string()];
+        registry.add_package(app);
+        registry.add_package(flask);
+        registry.add_package(Package::new("werkzeug".to_string(), "3.0.3".to_string()));
+        let werkzeug = vec!["werkzeug".to_string()];
+
+        // A single package with a dependent chain is a batch to confirm
+        let plan = plan_removal(&werkzeug, DependentsPolicy::Cascade, &registry).unwrap();
+        assert_eq!(plan.dependents, ["app", "flask"]);
+        assert!(plan.needs_confirmation());
+
+        // Batch members are not listed again as dependents
+        let both = vec!["flask".to_string(), "werkzeug".to_string()];
+        let plan = plan_removal(&both, DependentsPolicy::Cascade, &registry).unwrap();
+        assert_eq!(plan.dependents, ["app"]);
+
+        let plan = plan_removal(&werkzeug, DependentsPolicy::Force, &registry).unwrap();
+        assert!(plan.dependents.is_empty());
+        assert!(matches!(
+            plan_removal(&werkzeug, DependentsPolicy::Refuse, &registry),
+            Err(PackageError::RequiredByOthers(name, _)) if name == "werkzeug"
+        ));
+    }
+
+    #[test]
+    fn test_single_package_removal_is_confirmed() {
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("requests".to_string(), "2.32.3".to_string()));
+        let plan = plan_removal(
+            &["requests".to_string()],
+            DependentsPolicy::Refuse,
+            &registry,
+        )
+        .unwrap();
+        assert!(plan.dependents.is_empty());
+        assert!(plan.needs_confirmation());
+
+        // So it goes through the prompt: --yes answers it, CI mode without --yes refuses
+        assert_eq!(prompt_answer(true, true, false), PromptAnswer::Yes);
+        assert!(matches!(
+            prompt_answer(false, true, true),
+            PromptAnswer::Refuse(_)
+        ));
+    }
+}
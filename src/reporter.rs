@@ -0,0 +1,409 @@
+//! Verbosity, the message reporter, logging and colors
+
+use crate::{PackageError, Result};
+use std::fmt;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering as AtomicOrdering};
+use std::sync::{Arc, Mutex};
+
+/// How much output is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    /// Only errors and a final result line (`-q`)
+    Quiet,
+    /// Status messages and summaries
+    Normal,
+    /// Also stream pip's output live (`-v`)
+    Verbose,
+    /// Also show every command that is run (`-vv`)
+    Debug,
+}
+
+/// Current [`Verbosity`], set from the `--quiet` and `--verbose` flags
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Sets how much output the library prints
+///
+/// # Arguments
+/// * `verbosity` - The new output level
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, AtomicOrdering::Relaxed);
+}
+
+/// Gets how much output the library prints
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(AtomicOrdering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Debug,
+    }
+}
+
+/// Whether the `--json` flag moved human-readable output to stderr
+static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables JSON output mode
+///
+/// In JSON mode, human-readable messages go to stderr so stdout only
+/// carries the JSON result document.
+///
+/// # Arguments
+/// * `enabled` - Whether JSON output mode is active
+pub fn set_json_output(enabled: bool) {
+    JSON_OUTPUT.store(enabled, AtomicOrdering::Relaxed);
+}
+
+/// Whether JSON output mode is active
+pub fn json_output() -> bool {
+    JSON_OUTPUT.load(AtomicOrdering::Relaxed)
+}
+
+/// Kind of a user-facing message, which decides whether and where a [`Reporter`] shows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageKind {
+    /// Progress of an operation, hidden by `--quiet`
+    Status,
+    /// Shown regardless of verbosity, such as the context of a confirmation prompt
+    Notice,
+    /// Extra detail, shown with `-v`
+    Detail,
+    /// Debugging detail, shown with `-vv`
+    Debug,
+    /// What a tool such as pip wrote to stderr, shown with `-v`
+    Diagnostic,
+    /// A problem that does not stop the operation, hidden by `--quiet`
+    Warning,
+    /// A failure, always shown
+    Error,
+}
+
+impl MessageKind {
+    /// Least verbosity at which messages of this kind are shown
+    pub fn min_verbosity(self) -> Verbosity {
+        match self {
+            MessageKind::Notice | MessageKind::Error => Verbosity::Quiet,
+            MessageKind::Status | MessageKind::Warning => Verbosity::Normal,
+            MessageKind::Detail | MessageKind::Diagnostic => Verbosity::Verbose,
+            MessageKind::Debug => Verbosity::Debug,
+        }
+    }
+
+    /// Whether messages of this kind belong on stderr
+    pub fn is_diagnostic(self) -> bool {
+        matches!(
+            self,
+            MessageKind::Diagnostic | MessageKind::Warning | MessageKind::Error
+        )
+    }
+
+    /// Name of the kind in JSON messages
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MessageKind::Status => "status",
+            MessageKind::Notice => "notice",
+            MessageKind::Detail => "detail",
+            MessageKind::Debug => "debug",
+            MessageKind::Diagnostic => "diagnostic",
+            MessageKind::Warning => "warning",
+            MessageKind::Error => "error",
+        }
+    }
+}
+
+/// Shows the library's user-facing output, keeping it out of the core logic
+///
+/// Messages are progress, hints, warnings and failures; output is the
+/// result of a command, such as a listing or a summary table. The reporter
+/// in use is chosen with [`set_reporter`]; without one, a reporter matching
+/// [`json_output`] and [`verbosity`] is used.
+pub trait Reporter: Send + Sync {
+    /// Shows a message, or drops it when its kind is hidden
+    ///
+    /// # Arguments
+    /// * `kind` - Kind of the message
+    /// * `text` - The message, without a trailing newline
+    fn message(&self, kind: MessageKind, text: &str);
+
+    /// Shows the result of a command
+    ///
+    /// # Arguments
+    /// * `text` - The result, written as is
+    fn output(&self, text: &str);
+}
+
+/// Reporter for terminals: messages on stdout, warnings and failures on stderr
+pub struct HumanReporter {
+    verbosity: Verbosity,
+}
+
+impl HumanReporter {
+    /// Creates a reporter showing the messages `verbosity` allows
+    pub fn new(verbosity: Verbosity) -> Self {
+        HumanReporter { verbosity }
+    }
+}
+
+impl Reporter for HumanReporter {
+    fn message(&self, kind: MessageKind, text: &str) {
+        if self.verbosity < kind.min_verbosity() {
+            return;
+        }
+        match kind {
+            MessageKind::Warning => eprintln!("{} {}", warning_label(), text),
+            _ if kind.is_diagnostic() => eprintln!("{}", text),
+            _ => println!("{}", text),
+        }
+    }
+
+    fn output(&self, text: &str) {
+        print!("{}", text);
+    }
+}
+
+/// Reporter for `--quiet`: only notices, failures and results are shown
+pub struct QuietReporter;
+
+impl Reporter for QuietReporter {
+    fn message(&self, kind: MessageKind, text: &str) {
+        HumanReporter::new(Verbosity::Quiet).message(kind, text);
+    }
+
+    fn output(&self, text: &str) {
+        print!("{}", text);
+    }
+}
+
+/// Reporter for `--json`: one JSON object per message on stderr
+///
+/// Stdout is left to the JSON result document, so results shown through
+/// [`Reporter::output`] are written to stderr as messages of level `output`.
+pub struct JsonReporter {
+    verbosity: Verbosity,
+}
+
+impl JsonReporter {
+    /// Creates a reporter showing the messages `verbosity` allows
+    pub fn new(verbosity: Verbosity) -> Self {
+        JsonReporter { verbosity }
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn message(&self, kind: MessageKind, text: &str) {
+        if self.verbosity >= kind.min_verbosity() {
+            eprintln!("{}", json_message(kind.as_str(), text));
+        }
+    }
+
+    fn output(&self, text: &str) {
+        eprintln!("{}", json_message("output", text.trim_end()));
+    }
+}
+
+/// Builds the JSON line of a message
+fn json_message(level: &str, text: &str) -> serde_json::Value {
+    serde_json::json!({ "level": level, "message": text })
+}
+
+/// Reporter of user-facing output; `None` means one matching the global settings
+pub(crate) static REPORTER: Mutex<Option<Arc<dyn Reporter>>> = Mutex::new(None);
+
+/// Shows all later user-facing output through `reporter`
+///
+/// # Arguments
+/// * `reporter` - The reporter to use from now on
+pub fn set_reporter(reporter: Arc<dyn Reporter>) {
+    *REPORTER.lock().unwrap() = Some(reporter);
+}
+
+/// Returns the reporter of user-facing output
+pub fn reporter() -> Arc<dyn Reporter> {
+    if let Some(reporter) = REPORTER.lock().unwrap().clone() {
+        return reporter;
+    }
+    match (json_output(), verbosity()) {
+        (true, verbosity) => Arc::new(JsonReporter::new(verbosity)),
+        (false, Verbosity::Quiet) => Arc::new(QuietReporter),
+        (false, verbosity) => Arc::new(HumanReporter::new(verbosity)),
+    }
+}
+
+/// Reports a message through the current reporter; used by the output macros
+#[doc(hidden)]
+pub fn report(kind: MessageKind, args: fmt::Arguments) {
+    reporter().message(kind, &args.to_string());
+}
+
+/// Environment variable holding the log filter, e.g. `PPM_LOG=debug`
+pub const LOG_ENV: &str = "PPM_LOG";
+
+/// Sets up structured logging
+///
+/// Logs go to `log_file` when given, otherwise to stderr when `PPM_LOG` is
+/// set; without either nothing is logged. `PPM_LOG` accepts the usual
+/// filter syntax (`debug`, `python_package_manager=trace`, ...) and
+/// defaults to `info`. At `debug` level the full output of every pip
+/// invocation is included.
+///
+/// # Arguments
+/// * `log_file` - Optional file to append the log to
+///
+/// # Returns
+/// * `Result<()>` - Success or error opening the log file or parsing `PPM_LOG`
+pub fn init_logging(log_file: Option<&Path>) -> Result<()> {
+    use tracing_subscriber::EnvFilter;
+
+    let filter = match std::env::var(LOG_ENV) {
+        Ok(directives) => EnvFilter::try_new(directives).map_err(|e| {
+            PackageError::InvalidPackageSpec(format!("Invalid {} filter: {}", LOG_ENV, e))
+        })?,
+        Err(_) if log_file.is_some() => EnvFilter::new("info"),
+        Err(_) => return Ok(()),
+    };
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            builder
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .init();
+        }
+        None => builder.with_writer(std::io::stderr).init(),
+    }
+    Ok(())
+}
+
+/// When colored output is used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ColorChoice {
+    /// Colors on terminals unless NO_COLOR is set
+    Auto,
+    /// Always emit colors, even into files and pipes
+    Always,
+    /// Never emit colors
+    Never,
+}
+
+/// Applies the color choice to stdout and stderr, including progress bars
+///
+/// # Arguments
+/// * `choice` - The color choice from the command line
+pub fn set_color_choice(choice: ColorChoice) {
+    let no_color = std::env::var_os("NO_COLOR");
+    console::set_colors_enabled(resolve_color(
+        choice,
+        no_color.as_deref(),
+        std::io::stdout().is_terminal(),
+    ));
+    console::set_colors_enabled_stderr(resolve_color(
+        choice,
+        no_color.as_deref(),
+        std::io::stderr().is_terminal(),
+    ));
+}
+
+/// Decides whether a stream gets colors
+///
+/// A non-empty NO_COLOR disables colors in `auto` mode, following
+/// <https://no-color.org>.
+fn resolve_color(choice: ColorChoice, no_color: Option<&std::ffi::OsStr>, terminal: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => terminal && no_color.is_none_or(|value| value.is_empty()),
+    }
+}
+
+/// Marker printed before a successful operation
+pub fn success_mark() -> String {
+    console::style("✓").green().to_string()
+}
+
+/// Label printed before warnings on stderr
+pub fn warning_label() -> String {
+    console::style("Warning:").yellow().for_stderr().to_string()
+}
+
+/// Label printed before errors on stderr
+pub fn error_label() -> String {
+    console::style("Error:")
+        .red()
+        .bold()
+        .for_stderr()
+        .to_string()
+}
+
+/// Whether pip's output is streamed live
+pub(crate) fn is_verbose() -> bool {
+    verbosity() >= Verbosity::Verbose
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inspect::print_package_sizes;
+    use crate::test_support::RecordingReporter;
+
+    #[test]
+    fn test_resolve_color() {
+        use std::ffi::OsStr;
+
+        assert!(resolve_color(ColorChoice::Auto, None, true));
+        assert!(!resolve_color(ColorChoice::Auto, None, false));
+        assert!(!resolve_color(
+            ColorChoice::Auto,
+            Some(OsStr::new("1")),
+            true
+        ));
+        assert!(resolve_color(ColorChoice::Auto, Some(OsStr::new("")), true));
+        assert!(resolve_color(
+            ColorChoice::Always,
+            Some(OsStr::new("1")),
+            false
+        ));
+        assert!(!resolve_color(ColorChoice::Never, None, true));
+    }
+
+    #[test]
+    fn test_reporter_receives_user_facing_output() {
+        let recorder = Arc::new(RecordingReporter::default());
+        set_reporter(recorder.clone());
+        status!("Installing {} package(s)...", 2);
+        warning!("Could not read {}", "ppm.toml");
+        print_package_sizes(&[]);
+        *REPORTER.lock().unwrap() = None;
+
+        // Other tests may report concurrently, so only look for these entries
+        let shown = recorder.shown.lock().unwrap();
+        for entry in [
+            (
+                Some(MessageKind::Status),
+                "Installing 2 package(s)...".to_string(),
+            ),
+            (
+                Some(MessageKind::Warning),
+                "Could not read ppm.toml".to_string(),
+            ),
+            (None, "No installed packages found\n".to_string()),
+        ] {
+            assert!(shown.contains(&entry), "missing {:?} in {:?}", entry, shown);
+        }
+
+        assert_eq!(MessageKind::Detail.min_verbosity(), Verbosity::Verbose);
+        assert_eq!(MessageKind::Error.min_verbosity(), Verbosity::Quiet);
+        assert!(MessageKind::Warning.is_diagnostic());
+        assert!(!MessageKind::Notice.is_diagnostic());
+        assert_eq!(
+            json_message("warning", "Could not read ppm.toml").to_string(),
+            r#"{"level":"warning","message":"Could not read ppm.toml"}"#
+        );
+    }
+}
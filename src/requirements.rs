@@ -0,0 +1,1465 @@
+//! Requirements, constraints and update files, and the dependency lists of pyproject.toml
+
+use crate::install::{install_hash_pinned, map_bounded, run_checkpointed};
+use crate::pip::{get_installed_versions, get_python_executable, get_python_version};
+use crate::registry::PackageRegistry;
+use crate::spec::{
+    canonicalize_name, parse_package_spec, requirement_name, VcsRequirement, SPECIFIER_CHARS,
+};
+use crate::state::unix_now;
+use crate::upgrade::resolve_latest_version;
+use crate::{
+    http_client, install_with_options, InstallCheckpoint, InstallOptions, InstallReport,
+    PackageError, Result,
+};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Reads `name==version` update pairs from a file
+///
+/// The file uses the requirements format: blank lines and `#` comments are ignored.
+///
+/// # Arguments
+/// * `path` - Path to the file
+///
+/// # Returns
+/// * `Result<Vec<(String, String)>>` - Pairs of package name and target version
+pub fn read_update_file(path: &str) -> Result<Vec<(String, String)>> {
+    if !Path::new(path).exists() {
+        return Err(PackageError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Update file not found: {}", path),
+        )));
+    }
+
+    let specs = parse_requirements_file(path)?;
+    specs
+        .iter()
+        .map(|spec| match parse_package_spec(spec)? {
+            (name, Some(version)) => Ok((name, version)),
+            (name, None) => Err(PackageError::InvalidPackageSpec(format!(
+                "Missing target version for {} in {}",
+                name, path
+            ))),
+        })
+        .collect()
+}
+
+/// Installs packages from a requirements file
+///
+/// Reads a requirements.txt file and installs all specified packages.
+///
+/// # Arguments
+/// * `path` - Path to the requirements file
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if the
+///   file could not be read or pip could not be run
+pub fn install_from_requirements(
+    path: &str,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    install_from_requirements_impl(path, &[], registry, false)
+}
+
+/// Installs packages from a requirements file in parallel
+///
+/// Reads a requirements.txt file and installs all specified packages in parallel.
+///
+/// # Arguments
+/// * `path` - Path to the requirements file
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if the
+///   file could not be read or pip could not be run
+pub fn install_from_requirements_parallel(
+    path: &str,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    install_from_requirements_impl(path, &[], registry, true)
+}
+
+/// Installs packages from a requirements file as described by an [`InstallOptions`]
+///
+/// Hash-pinned files are still installed in pip's `--require-hashes` mode, and
+/// the extra pip arguments are kept in the checkpoint so a resumed install
+/// uses them too. Dry runs and attestation checks install the listed specs
+/// with [`install_with_options`] instead.
+///
+/// # Arguments
+/// * `path` - Path to the requirements file
+/// * `options` - How to install the packages
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every package, or an error if the
+///   file could not be read or pip could not be run
+pub fn install_from_requirements_with_options(
+    path: &str,
+    options: &InstallOptions,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    if options.dry_run || options.verify_attestations {
+        let packages = parse_requirements_file(path)?;
+        return install_with_options(&packages, options, registry);
+    }
+    options.with_python(|| {
+        install_from_requirements_impl(path, &options.pip_args(), registry, options.parallel)
+    })
+}
+
+/// Reads version ceilings from a pip constraints file
+///
+/// Each line names a package followed by a specifier set, e.g. `numpy<2`.
+/// Blank lines, comments and entries without a specifier are ignored.
+///
+/// # Arguments
+/// * `path` - Path to the constraints file
+///
+/// # Returns
+/// * `Result<HashMap<String, String>>` - Specifier sets keyed by normalized package name
+pub fn read_constraints_file(path: &str) -> Result<HashMap<String, String>> {
+    if !Path::new(path).exists() {
+        return Err(PackageError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Constraints file not found: {}", path),
+        )));
+    }
+
+    let mut constraints = HashMap::new();
+    let reader = BufReader::new(File::open(path)?);
+    for line in reader.lines() {
+        let line = line?;
+        // Comments and environment markers do not affect the ceiling itself
+        let line = line.split(['#', ';']).next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        match line.find(SPECIFIER_CHARS) {
+            Some(index) if index > 0 => {
+                let specifier = line[index..].replace(' ', "");
+                constraints.insert(canonicalize_name(&line[..index]), specifier);
+            }
+            _ => warning!("Ignoring constraint without a version: {}", line),
+        }
+    }
+    Ok(constraints)
+}
+
+/// Implementation for installing from requirements files
+fn install_from_requirements_impl(
+    path: &str,
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+    parallel: bool,
+) -> Result<InstallReport> {
+    if !Path::new(path).exists() {
+        return Err(PackageError::IoError(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Requirements file not found: {}", path),
+        )));
+    }
+
+    let packages = parse_requirements_file(path)?;
+
+    if packages.is_empty() {
+        status!("No packages found in requirements file");
+        return Ok(InstallReport::new("Installation summary", "install"));
+    }
+
+    if is_hash_pinned(path)? {
+        // pip verifies the whole file in one run and installs nothing if any archive fails
+        status!(
+            "Installing {} hash-pinned packages from {}, verifying every archive",
+            packages.len(),
+            path
+        );
+        return install_hash_pinned(Path::new(path), &packages, pip_args, registry);
+    }
+
+    status!("Installing {} packages from {}", packages.len(), path);
+
+    let checkpoint = InstallCheckpoint {
+        requirements: path.to_string(),
+        started_at: unix_now(),
+        specs: packages.clone(),
+        completed: Vec::new(),
+        pip_args: pip_args.to_vec(),
+    };
+    run_checkpointed(checkpoint, &packages, registry, parallel)
+}
+
+/// Checks whether a requirements file pins archive hashes with `--hash` options
+fn is_hash_pinned(path: &str) -> Result<bool> {
+    Ok(std::fs::read_to_string(path)?
+        .lines()
+        .any(|line| !line.trim_start().starts_with('#') && line.contains("--hash")))
+}
+
+/// Removes the `--hash=<algorithm>:<digest>` options from a requirement line
+fn strip_hash_options(line: &str) -> String {
+    let mut words = Vec::new();
+    let mut tokens = line.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if token == "--hash" {
+            tokens.next();
+        } else if !token.starts_with("--hash=") {
+            words.push(token);
+        }
+    }
+    words.join(" ")
+}
+
+/// Parses a requirements file and returns package specifications
+///
+/// Lines ending in a backslash continue on the next line, and `--hash`
+/// options are dropped from the specs. The requirements of files included
+/// with `-r other.txt`, relative to the including file, are read in place,
+/// so layered files such as `requirements/dev.txt` including `base.txt`
+/// work; a file included twice is read once.
+///
+/// # Arguments
+/// * `path` - Path to the requirements file
+///
+/// # Returns
+/// * `Result<Vec<String>>` - The requirement lines, without blank lines and comments
+pub fn parse_requirements_file(path: &str) -> Result<Vec<String>> {
+    let mut packages = Vec::new();
+    parse_requirements_into(Path::new(path), &mut HashSet::new(), &mut packages)?;
+    Ok(packages)
+}
+
+/// Appends the requirements of a file and the files it includes
+fn parse_requirements_into(
+    path: &Path,
+    seen: &mut HashSet<PathBuf>,
+    packages: &mut Vec<String>,
+) -> Result<()> {
+    let file = File::open(path)?;
+    if !seen.insert(path.canonicalize()?) {
+        return Ok(());
+    }
+    let reader = BufReader::new(file);
+    let mut continued = String::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if let Some(head) = line.trim_end().strip_suffix('\\') {
+            continued.push_str(head);
+            continued.push(' ');
+            continue;
+        }
+        continued.push_str(&line);
+        let line = strip_hash_options(&std::mem::take(&mut continued));
+        let line = line.trim();
+
+        // Skip empty lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if let Some(include) = ["-r", "--requirement"].iter().find_map(|option| {
+            line.strip_prefix(option)
+                .filter(|rest| rest.starts_with([' ', '=']))
+                .map(|rest| rest.trim_start_matches([' ', '=']).trim())
+        }) {
+            let include = path.parent().unwrap_or(Path::new("")).join(include);
+            if !include.exists() {
+                return Err(PackageError::IoError(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!(
+                        "Requirements file not found: {} (included by {})",
+                        include.display(),
+                        path.display()
+                    ),
+                )));
+            }
+            parse_requirements_into(&include, seen, packages)?;
+            continue;
+        }
+
+        // Basic validation of package specification
+        if line.contains(' ') && !line.contains("==") {
+            warning!("Skipping potentially invalid line: {}", line);
+            continue;
+        }
+
+        packages.push(line.to_string());
+    }
+
+    Ok(())
+}
+
+/// Reads package specs separated by whitespace or newlines, e.g. from stdin
+///
+/// A word starting with `#` comments out the rest of its line. Specs cannot
+/// contain spaces, so direct references are written as `name@url`.
+///
+/// # Arguments
+/// * `reader` - Where to read the list from
+///
+/// # Returns
+/// * `Result<Vec<String>>` - The specs in order, or an error if reading failed
+pub fn read_package_list(mut reader: impl Read) -> Result<Vec<String>> {
+    let mut input = String::new();
+    reader.read_to_string(&mut input)?;
+    Ok(input
+        .lines()
+        .flat_map(|line| {
+            line.split_whitespace()
+                .take_while(|word| !word.starts_with('#'))
+        })
+        .map(str::to_string)
+        .collect())
+}
+
+/// A requirement whose pin [`pin_requirements`] changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct PinChange {
+    /// Package name as written in the file
+    pub name: String,
+    /// Version specifier it had before, e.g. ">=2.0", or empty for a bare name
+    pub previous: String,
+    /// Version it is pinned to now
+    pub version: String,
+}
+
+/// A requirements file with its entries pinned
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PinnedRequirements {
+    /// The rewritten file
+    pub contents: String,
+    /// Entries whose pin changed, in file order
+    pub changes: Vec<PinChange>,
+    /// Names of the entries left as they were, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Requirement of a single line that can be pinned
+struct PinTarget<'a> {
+    /// Byte range of the requirement within the line, without markers or options
+    range: std::ops::Range<usize>,
+    name: &'a str,
+    extras: &'a str,
+    specifier: &'a str,
+    /// Whether the line carries `--hash` options or continues on the next line
+    hashed: bool,
+}
+
+/// Finds the pinnable requirement of a line; options, comments and direct
+/// references such as `name @ https://…` or `./local` have none
+fn pin_target(line: &str) -> Option<PinTarget<'_>> {
+    let code = &line[..comment_start(line)];
+    let trimmed = code.trim_start();
+    if trimmed.is_empty() || trimmed.starts_with('-') {
+        return None;
+    }
+
+    let start = code.len() - trimmed.len();
+    let end = [trimmed.find(';'), trimmed.find(" -"), trimmed.find('\\')]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(trimmed.len());
+    let requirement = trimmed[..end].trim_end();
+    if requirement.contains(['@', '/']) {
+        return None;
+    }
+    let name = requirement_name(requirement);
+    if name.is_empty() {
+        return None;
+    }
+    let rest = requirement[name.len()..].trim_start();
+    let extras_len = if rest.starts_with('[') {
+        rest.find(']').map_or(0, |i| i + 1)
+    } else {
+        0
+    };
+    Some(PinTarget {
+        range: start..start + requirement.len(),
+        name,
+        extras: &rest[..extras_len],
+        specifier: rest[extras_len..].trim(),
+        hashed: code.contains("--hash") || code.trim_end().ends_with('\\'),
+    })
+}
+
+/// Names of the requirements of a file that [`pin_requirements`] can pin
+///
+/// # Arguments
+/// * `contents` - Contents of a requirements file
+pub fn pinnable_requirements(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter_map(pin_target)
+        .map(|target| target.name.to_string())
+        .collect()
+}
+
+/// Rewrites a requirements file so every entry is pinned with `==`
+///
+/// Comments, blank lines, options, ordering, extras and environment markers
+/// are kept as they are. Direct references are not touched, and neither are
+/// entries whose `--hash` options would no longer match the new version.
+///
+/// # Arguments
+/// * `contents` - Contents of a requirements file
+/// * `versions` - Versions to pin, keyed by normalized package name
+///
+/// # Returns
+/// The new contents, the pins that changed and the entries that were skipped
+pub fn pin_requirements(contents: &str, versions: &HashMap<String, String>) -> PinnedRequirements {
+    let mut pinned = PinnedRequirements::default();
+    for line in contents.split_inclusive('\n') {
+        let Some(target) = pin_target(line) else {
+            pinned.contents.push_str(line);
+            continue;
+        };
+        let Some(version) = versions.get(&canonicalize_name(target.name)) else {
+            pinned.skipped.push((
+                target.name.to_string(),
+                "no version to pin it to".to_string(),
+            ));
+            pinned.contents.push_str(line);
+            continue;
+        };
+        if target.specifier.strip_prefix("==").map(str::trim) == Some(version.as_str()) {
+            pinned.contents.push_str(line);
+            continue;
+        }
+        if target.hashed {
+            pinned.skipped.push((
+                target.name.to_string(),
+                "its hashes would no longer match; run `ppm lock` instead".to_string(),
+            ));
+            pinned.contents.push_str(line);
+            continue;
+        }
+
+        pinned.contents.push_str(&line[..target.range.start]);
+        pinned
+            .contents
+            .push_str(&format!("{}{}=={}", target.name, target.extras, version));
+        pinned.contents.push_str(&line[target.range.end..]);
+        pinned.changes.push(PinChange {
+            name: target.name.to_string(),
+            previous: target.specifier.to_string(),
+            version: version.clone(),
+        });
+    }
+    pinned
+}
+
+/// Pins every entry of a requirements file to the installed or latest version
+///
+/// # Arguments
+/// * `path` - Path to the requirements file
+/// * `latest` - Pin to the newest release that supports the interpreter instead
+///   of the installed version
+/// * `write` - Whether to save the file; without it only the result is returned
+///
+/// # Returns
+/// * `Result<PinnedRequirements>` - What was (or would be) changed, or an error
+///   if the file could not be read or written
+pub fn pin_requirements_file(path: &str, latest: bool, write: bool) -> Result<PinnedRequirements> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        PackageError::IoError(std::io::Error::new(
+            e.kind(),
+            format!("Requirements file not found: {}: {}", path, e),
+        ))
+    })?;
+    let names = pinnable_requirements(&contents);
+    let python = get_python_executable()?;
+
+    let versions: HashMap<String, String> = if latest {
+        let python_version = get_python_version(&python)?;
+        let resolved = map_bounded(&names, http_client().concurrency_limit(), |_, name| {
+            resolve_latest_version(&python, name, &python_version, None, false)
+        });
+        names
+            .iter()
+            .zip(resolved)
+            .filter_map(|(name, version)| match version {
+                Ok(version) => Some((canonicalize_name(name), version)),
+                Err(e) => {
+                    warning!("Could not find the latest release of {}: {}", name, e);
+                    None
+                }
+            })
+            .collect()
+    } else {
+        get_installed_versions(&python, &names)
+            .into_iter()
+            .map(|(name, version)| (canonicalize_name(&name), version))
+            .collect()
+    };
+
+    let mut pinned = pin_requirements(&contents, &versions);
+    if !latest {
+        for (name, reason) in &mut pinned.skipped {
+            if !versions.contains_key(&canonicalize_name(name)) {
+                *reason = "it is not installed".to_string();
+            }
+        }
+    }
+    if write && !pinned.changes.is_empty() {
+        std::fs::write(path, &pinned.contents)?;
+    }
+    Ok(pinned)
+}
+
+/// A requirement that [`bump_requirements`] or [`bump_pyproject`] rewrote
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpecEdit {
+    /// Line of the file, starting at 1
+    pub line: usize,
+    /// The requirement before the edit
+    pub before: String,
+    /// The requirement after the edit
+    pub after: String,
+}
+
+/// Specifier that moves a requirement to `version`
+///
+/// A single `==`, `>=`, `~=` or `===` clause keeps its operator; anything else,
+/// including no specifier at all, becomes an exact pin.
+fn bumped_specifier(specifier: &str, version: &str) -> String {
+    let operator = ["===", "==", ">=", "~="]
+        .into_iter()
+        .find(|op| specifier.starts_with(op) && !specifier.contains(','))
+        .unwrap_or("==");
+    format!("{}{}", operator, version)
+}
+
+/// Rewrites a requirement of package `canonical` to `version`
+///
+/// # Returns
+/// The new text and whether the line carries `--hash` options, or `None` if
+/// the requirement is for another package or already says the same
+fn bump_requirement(text: &str, canonical: &str, version: &str) -> Option<(String, bool)> {
+    let target = pin_target(text)?;
+    if canonicalize_name(target.name) != canonical {
+        return None;
+    }
+    let bumped = format!(
+        "{}{}{}{}",
+        &text[..target.range.start],
+        target.name,
+        target.extras,
+        bumped_specifier(target.specifier, version)
+    ) + &text[target.range.end..];
+    (bumped != text).then_some((bumped, target.hashed))
+}
+
+/// Moves one package of a requirements file to a new version
+///
+/// Entries with `--hash` options are left alone, as their hashes would no
+/// longer match.
+///
+/// # Arguments
+/// * `contents` - Contents of the requirements file
+/// * `name` - The package to bump
+/// * `version` - Its new version
+///
+/// # Returns
+/// The new contents, the edits made and the lines of hashed entries that were skipped
+pub fn bump_requirements(
+    contents: &str,
+    name: &str,
+    version: &str,
+) -> (String, Vec<SpecEdit>, Vec<usize>) {
+    let canonical = canonicalize_name(name);
+    let mut bumped = String::with_capacity(contents.len());
+    let mut edits = Vec::new();
+    let mut skipped = Vec::new();
+    for (index, line) in contents.split_inclusive('\n').enumerate() {
+        match bump_requirement(line, &canonical, version) {
+            Some((_, true)) => {
+                skipped.push(index + 1);
+                bumped.push_str(line);
+            }
+            Some((new_line, false)) => {
+                edits.push(SpecEdit {
+                    line: index + 1,
+                    before: line.trim().to_string(),
+                    after: new_line.trim().to_string(),
+                });
+                bumped.push_str(&new_line);
+            }
+            None => bumped.push_str(line),
+        }
+    }
+    (bumped, edits, skipped)
+}
+
+/// Length of a TOML string up to its closing quote; basic strings can escape quotes
+fn toml_string_len(body: &str, quote: char) -> Option<usize> {
+    let mut escaped = false;
+    for (i, c) in body.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quote == '"' => escaped = true,
+            c if c == quote => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Moves one package of a pyproject.toml to a new version
+///
+/// The `dependencies` of `[project]` and every list of
+/// `[project.optional-dependencies]` are edited as text, so the layout and
+/// comments of the file stay as they are.
+///
+/// # Arguments
+/// * `contents` - Contents of pyproject.toml
+/// * `name` - The package to bump
+/// * `version` - Its new version
+///
+/// # Returns
+/// The new contents and the edits made
+pub fn bump_pyproject(contents: &str, name: &str, version: &str) -> (String, Vec<SpecEdit>) {
+    let canonical = canonicalize_name(name);
+    let mut bumped = String::with_capacity(contents.len());
+    let mut edits = Vec::new();
+    let mut table = String::new();
+    let mut in_list = false;
+
+    for (index, line) in contents.split_inclusive('\n').enumerate() {
+        let trimmed = line.trim_start();
+        let mut scan_from = 0;
+        if !in_list {
+            if trimmed.starts_with('[') {
+                table = trimmed
+                    .trim_end()
+                    .trim_matches(['[', ']'])
+                    .trim()
+                    .to_string();
+            } else if let Some((key, _)) = trimmed.split_once('=') {
+                let key = key.trim();
+                in_list = (table == "project" && key == "dependencies")
+                    || table == "project.optional-dependencies";
+                scan_from = line.find('=').map_or(0, |i| i + 1);
+            }
+            if !in_list {
+                bumped.push_str(line);
+                continue;
+            }
+        }
+
+        // Walk the strings of the list, stopping at its closing bracket or a comment
+        let mut new_line = line[..scan_from].to_string();
+        let mut rest = &line[scan_from..];
+        while let Some(start) = rest.find(['"', '\'', ']', '#']) {
+            let quote = rest[start..].chars().next().unwrap_or('"');
+            if quote == ']' {
+                in_list = false;
+            }
+            if matches!(quote, ']' | '#') {
+                break;
+            }
+            let Some(len) = toml_string_len(&rest[start + 1..], quote) else {
+                break;
+            };
+            let text = &rest[start + 1..start + 1 + len];
+            new_line.push_str(&rest[..=start]);
+            match bump_requirement(text, &canonical, version) {
+                Some((requirement, _)) => {
+                    edits.push(SpecEdit {
+                        line: index + 1,
+                        before: text.to_string(),
+                        after: requirement.clone(),
+                    });
+                    new_line.push_str(&requirement);
+                }
+                None => new_line.push_str(text),
+            }
+            new_line.push(quote);
+            rest = &rest[start + len + 2..];
+        }
+        new_line.push_str(rest);
+        bumped.push_str(&new_line);
+    }
+    (bumped, edits)
+}
+
+/// Offset of the comment of a requirements line, or its length without one
+///
+/// pip starts a comment at a `#` at the start of the line or after whitespace.
+fn comment_start(line: &str) -> usize {
+    line.char_indices()
+        .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with(char::is_whitespace)))
+        .map_or(line.len(), |(i, _)| i)
+}
+
+/// Normalized name of the package a requirements line requires, if any
+fn requirement_line_name(line: &str) -> Option<String> {
+    let code = line[..comment_start(line)].trim();
+    if code.is_empty() || code.starts_with('-') {
+        return None;
+    }
+    let name = requirement_name(code);
+    (!name.is_empty()).then(|| canonicalize_name(name))
+}
+
+/// Entries of a requirements file, each spanning the lines it continues onto with `\`
+///
+/// # Returns
+/// The byte range of every entry and the normalized name of the package it requires, if any
+fn requirement_entries(contents: &str) -> Vec<(std::ops::Range<usize>, Option<String>)> {
+    let mut entries = Vec::new();
+    let mut start = 0;
+    let mut first: Option<&str> = None;
+    let mut offset = 0;
+    for line in contents.split_inclusive('\n') {
+        let first_line = *first.get_or_insert(line);
+        offset += line.len();
+        if line.trim_end().ends_with('\\') {
+            continue;
+        }
+        entries.push((start..offset, requirement_line_name(first_line)));
+        start = offset;
+        first = None;
+    }
+    if let Some(first_line) = first {
+        entries.push((start..offset, requirement_line_name(first_line)));
+    }
+    entries
+}
+
+/// The requirement of an entry without its comment, options or continuations
+fn entry_requirement(entry: &str) -> &str {
+    let line = entry.lines().next().unwrap_or_default();
+    let code = &line[..comment_start(line)];
+    code.split(" --hash")
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .trim_end_matches('\\')
+        .trim()
+}
+
+/// Declares a requirement in a requirements file
+///
+/// An entry for the same package is replaced, keeping its indentation and
+/// comment; its `--hash` options are dropped, as they would no longer match.
+/// Otherwise the requirement is appended.
+///
+/// # Arguments
+/// * `contents` - Contents of the requirements file
+/// * `spec` - The requirement, e.g. "requests>=2.31"
+///
+/// # Returns
+/// The new contents and the edit made, or `None` if the file already says the same
+pub fn add_requirement(contents: &str, spec: &str) -> (String, Option<SpecEdit>) {
+    let spec = spec.trim();
+    let canonical = canonicalize_name(requirement_name(spec));
+    let existing = requirement_entries(contents)
+        .into_iter()
+        .find(|(_, name)| name.as_deref() == Some(canonical.as_str()));
+
+    let Some((range, _)) = existing else {
+        let mut added = contents.to_string();
+        if !added.is_empty() && !added.ends_with('\n') {
+            added.push('\n');
+        }
+        added.push_str(spec);
+        added.push('\n');
+        let edit = SpecEdit {
+            line: added.lines().count(),
+            before: String::new(),
+            after: spec.to_string(),
+        };
+        return (added, Some(edit));
+    };
+
+    let entry = &contents[range.clone()];
+    let before = entry_requirement(entry);
+    let single_line = entry.trim_end().lines().count() == 1;
+    if before == spec && single_line && !entry.contains("--hash") {
+        return (contents.to_string(), None);
+    }
+    let first_line = entry.lines().next().unwrap_or_default();
+    let indent = &first_line[..first_line.len() - first_line.trim_start().len()];
+    let comment = first_line[comment_start(first_line)..].trim();
+    let mut replacement = format!("{}{}", indent, spec);
+    if single_line && !comment.is_empty() {
+        replacement.push_str("  ");
+        replacement.push_str(comment);
+    }
+    if entry.ends_with('\n') {
+        replacement.push('\n');
+    }
+    let edit = SpecEdit {
+        line: contents[..range.start].matches('\n').count() + 1,
+        before: before.to_string(),
+        after: spec.to_string(),
+    };
+    let mut replaced = contents.to_string();
+    replaced.replace_range(range, &replacement);
+    (replaced, Some(edit))
+}
+
+/// Removes every entry for a package from a requirements file
+///
+/// # Arguments
+/// * `contents` - Contents of the requirements file
+/// * `name` - The package to remove
+///
+/// # Returns
+/// The new contents and the entries removed
+pub fn remove_requirement(contents: &str, name: &str) -> (String, Vec<SpecEdit>) {
+    let canonical = canonicalize_name(name);
+    let mut kept = String::with_capacity(contents.len());
+    let mut edits = Vec::new();
+    for (range, entry_name) in requirement_entries(contents) {
+        let entry = &contents[range.clone()];
+        if entry_name.as_deref() == Some(canonical.as_str()) {
+            edits.push(SpecEdit {
+                line: contents[..range.start].matches('\n').count() + 1,
+                before: entry_requirement(entry).to_string(),
+                after: String::new(),
+            });
+        } else {
+            kept.push_str(entry);
+        }
+    }
+    (kept, edits)
+}
+
+/// A string in a dependency list of pyproject.toml
+#[derive(Debug)]
+struct TomlListItem {
+    /// Index of its line
+    line: usize,
+    /// Offset of its opening quote within the line
+    start: usize,
+    /// Offset just past its closing quote
+    end: usize,
+    /// The requirement it holds
+    text: String,
+}
+
+/// The `dependencies` of `[project]` or a list of `[project.optional-dependencies]`
+#[derive(Debug)]
+struct DependencyList {
+    /// The optional group, or None for the main dependencies
+    group: Option<String>,
+    /// Index of the line the list starts on
+    open_line: usize,
+    /// Line index and offset of the closing bracket
+    close: (usize, usize),
+    items: Vec<TomlListItem>,
+}
+
+/// Finds the dependency lists of pyproject.toml, given its lines
+fn dependency_lists(lines: &[String]) -> Vec<DependencyList> {
+    let mut lists = Vec::new();
+    let mut table = String::new();
+    let mut current: Option<DependencyList> = None;
+    for (index, line) in lines.iter().enumerate() {
+        let mut pos = 0;
+        if current.is_none() {
+            let trimmed = line.trim_start();
+            if trimmed.starts_with('[') {
+                table = trimmed
+                    .trim_end()
+                    .trim_matches(['[', ']'])
+                    .trim()
+                    .to_string();
+                continue;
+            }
+            let Some((key, _)) = trimmed.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().trim_matches(['"', '\'']);
+            let group = match table.as_str() {
+                "project" if key == "dependencies" => None,
+                "project.optional-dependencies" => Some(key.to_string()),
+                _ => continue,
+            };
+            let equals = line.find('=').unwrap_or(0);
+            let Some(open) = line[equals..].find('[') else {
+                continue;
+            };
+            current = Some(DependencyList {
+                group,
+                open_line: index,
+                close: (index, 0),
+                items: Vec::new(),
+            });
+            pos = equals + open + 1;
+        }
+
+        // Walk the strings of the list, stopping at its closing bracket or a comment
+        let Some(list) = current.as_mut() else {
+            continue;
+        };
+        let mut closed = false;
+        while let Some(found) = line[pos..].find(['"', '\'', ']', '#']) {
+            let start = pos + found;
+            let quote = line[start..].chars().next().unwrap_or('"');
+            if quote == ']' {
+                list.close = (index, start);
+                closed = true;
+            }
+            if matches!(quote, ']' | '#') {
+                break;
+            }
+            let Some(len) = toml_string_len(&line[start + 1..], quote) else {
+                break;
+            };
+            list.items.push(TomlListItem {
+                line: index,
+                start,
+                end: start + len + 2,
+                text: line[start + 1..start + 1 + len].to_string(),
+            });
+            pos = start + len + 2;
+        }
+        if closed {
+            lists.extend(current.take());
+        }
+    }
+    lists
+}
+
+/// Index of the line after the last key of a TOML table, if the table exists
+fn table_end(lines: &[String], name: &str) -> Option<usize> {
+    let header = lines.iter().position(|line| {
+        let trimmed = line.trim();
+        trimmed.starts_with('[')
+            && !trimmed.starts_with("[[")
+            && trimmed.trim_matches(['[', ']']).trim() == name
+    })?;
+    let mut end = header + 1;
+    for (index, line) in lines.iter().enumerate().skip(header + 1) {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with('[') && !line.starts_with(char::is_whitespace) {
+            break;
+        }
+        if !trimmed.trim().is_empty() {
+            end = index + 1;
+        }
+    }
+    Some(end)
+}
+
+/// A requirement as a TOML string, in single quotes if it holds a double quote
+fn toml_requirement(spec: &str) -> String {
+    if spec.contains(['"', '\\']) {
+        format!("'{}'", spec)
+    } else {
+        format!("\"{}\"", spec)
+    }
+}
+
+/// Inserts a line, ending the line before it first if it has no newline
+fn insert_line(lines: &mut Vec<String>, index: usize, line: String) {
+    if let Some(previous) = index.checked_sub(1).and_then(|i| lines.get_mut(i)) {
+        if !previous.ends_with('\n') {
+            previous.push('\n');
+        }
+    }
+    lines.insert(index, line);
+}
+
+/// Declares a dependency in pyproject.toml
+///
+/// The file is edited as text, so its layout and comments stay as they are. A
+/// requirement for the same package in the list is replaced; otherwise the
+/// requirement is added at the end of the list, which is created if needed.
+///
+/// # Arguments
+/// * `contents` - Contents of pyproject.toml
+/// * `spec` - The requirement, e.g. "requests>=2.31"
+/// * `group` - Group of `[project.optional-dependencies]` to add it to, or
+///   None for the `dependencies` of `[project]`
+///
+/// # Returns
+/// * `Result<(String, Option<SpecEdit>)>` - The new contents and the edit made,
+///   `None` if the list already says the same; or an error if the file has no
+///   `[project]` table
+pub fn add_pyproject_dependency(
+    contents: &str,
+    spec: &str,
+    group: Option<&str>,
+) -> Result<(String, Option<SpecEdit>)> {
+    let spec = spec.trim();
+    let canonical = canonicalize_name(requirement_name(spec));
+    let quoted = toml_requirement(spec);
+    let mut lines: Vec<String> = contents.split_inclusive('\n').map(str::to_string).collect();
+    let lists = dependency_lists(&lines);
+    let added = |line: usize| SpecEdit {
+        line: line + 1,
+        before: String::new(),
+        after: spec.to_string(),
+    };
+
+    let Some(list) = lists.iter().find(|list| list.group.as_deref() == group) else {
+        let (table, key) = match group {
+            None => ("project", "dependencies".to_string()),
+            Some(group)
+                if group
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "-_".contains(c)) =>
+            {
+                ("project.optional-dependencies", group.to_string())
+            }
+            Some(group) => ("project.optional-dependencies", format!("\"{}\"", group)),
+        };
+        let entry = format!("{} = [\n    {},\n]\n", key, quoted);
+        let line = match table_end(&lines, table) {
+            Some(end) => {
+                insert_line(&mut lines, end, entry);
+                end
+            }
+            None if group.is_some() && table_end(&lines, "project").is_some() => {
+                let end = lines.len();
+                insert_line(&mut lines, end, format!("\n[{}]\n{}", table, entry));
+                end + 2
+            }
+            None => {
+                return Err(PackageError::MetadataError(
+                    "pyproject.toml has no [project] table to declare dependencies in".to_string(),
+                ))
+            }
+        };
+        return Ok((lines.concat(), Some(added(line))));
+    };
+
+    if let Some(item) = list
+        .items
+        .iter()
+        .find(|item| canonicalize_name(requirement_name(&item.text)) == canonical)
+    {
+        if item.text == spec {
+            return Ok((contents.to_string(), None));
+        }
+        lines[item.line].replace_range(item.start..item.end, &quoted);
+        let edit = SpecEdit {
+            line: item.line + 1,
+            before: item.text.clone(),
+            after: spec.to_string(),
+        };
+        return Ok((lines.concat(), Some(edit)));
+    }
+
+    let (close_line, close) = list.close;
+    let before_close = lines[close_line][..close].trim_end().to_string();
+    if close_line == list.open_line || !before_close.trim().is_empty() {
+        // The bracket closes a line with other content: add the string in front of it
+        let separator = if before_close.ends_with('[') {
+            ""
+        } else if before_close.ends_with(',') {
+            " "
+        } else {
+            ", "
+        };
+        lines[close_line].replace_range(
+            before_close.len()..close,
+            &format!("{}{}", separator, quoted),
+        );
+        return Ok((lines.concat(), Some(added(close_line))));
+    }
+
+    // The bracket is on a line of its own: add a line above it
+    let indent = match list.items.last() {
+        Some(last) if last.line != list.open_line => {
+            let line = &lines[last.line];
+            line[..line.len() - line.trim_start().len()].to_string()
+        }
+        _ => "    ".to_string(),
+    };
+    if let Some(last) = list.items.last() {
+        if !lines[last.line][last.end..].trim_start().starts_with(',') {
+            lines[last.line].insert(last.end, ',');
+        }
+    }
+    lines.insert(close_line, format!("{}{},\n", indent, quoted));
+    Ok((lines.concat(), Some(added(close_line))))
+}
+
+/// Removes a package from every dependency list of pyproject.toml
+///
+/// A requirement on a line of its own takes the line with it; one sharing a
+/// line loses only itself and its comma.
+///
+/// # Arguments
+/// * `contents` - Contents of pyproject.toml
+/// * `name` - The package to remove
+///
+/// # Returns
+/// The new contents and the requirements removed
+pub fn remove_pyproject_dependency(contents: &str, name: &str) -> (String, Vec<SpecEdit>) {
+    let canonical = canonicalize_name(name);
+    let mut lines: Vec<String> = contents.split_inclusive('\n').map(str::to_string).collect();
+    let lists = dependency_lists(&lines);
+    let removed: Vec<&TomlListItem> = lists
+        .iter()
+        .flat_map(|list| &list.items)
+        .filter(|item| canonicalize_name(requirement_name(&item.text)) == canonical)
+        .collect();
+    let edits = removed
+        .iter()
+        .map(|item| SpecEdit {
+            line: item.line + 1,
+            before: item.text.clone(),
+            after: String::new(),
+        })
+        .collect();
+
+    // Last first, so earlier offsets and line indexes stay valid
+    for item in removed.iter().rev() {
+        let line = &lines[item.line];
+        let after = line[item.end..].trim_start();
+        let after_comma = after.strip_prefix(',').unwrap_or(after).trim_start();
+        if line[..item.start].trim().is_empty()
+            && (after_comma.is_empty() || after_comma.starts_with('#'))
+        {
+            lines.remove(item.line);
+            continue;
+        }
+        let end = if after.starts_with(',') {
+            line.len() - after_comma.len()
+        } else {
+            item.end
+        };
+        let start = match line[..item.start].trim_end().strip_suffix(',') {
+            Some(head) if end == item.end => head.len(),
+            _ => item.start,
+        };
+        lines[item.line].replace_range(start..end, "");
+    }
+    (lines.concat(), edits)
+}
+
+/// Formats a requirements file
+///
+/// Names are normalized as in PEP 503, extras and specifier clauses are sorted
+/// with the whitespace removed, markers and options get single spaces and
+/// entries are sorted by name. Options such as `-r base.txt` or `--index-url`
+/// stay at the top in their order; a comment block directly above a line moves
+/// with it, and one separated from the rest of the file by a blank line at the
+/// start stays there as a header. Hashes go on continuation lines of their own.
+///
+/// # Arguments
+/// * `contents` - Contents of a requirements file
+///
+/// # Returns
+/// The formatted file
+pub fn format_requirements(contents: &str) -> String {
+    let mut header = Vec::new();
+    let mut options: Vec<String> = Vec::new();
+    let mut entries: Vec<(String, Vec<String>)> = Vec::new();
+    let mut comments: Vec<String> = Vec::new();
+    let mut continued = String::new();
+
+    for line in contents.lines() {
+        if let Some(head) = line.trim_end().strip_suffix('\\') {
+            continued.push_str(head);
+            continued.push(' ');
+            continue;
+        }
+        continued.push_str(line);
+        let line = std::mem::take(&mut continued);
+        let line = line.trim();
+
+        if line.is_empty() {
+            if options.is_empty() && entries.is_empty() {
+                header.append(&mut comments);
+            }
+        } else if line.starts_with('#') {
+            comments.push(line.to_string());
+        } else if line.starts_with('-') {
+            options.append(&mut comments);
+            options.push(line.split_whitespace().collect::<Vec<_>>().join(" "));
+        } else {
+            let (key, entry) = format_requirement_line(line);
+            comments.push(entry);
+            entries.push((key, std::mem::take(&mut comments)));
+        }
+    }
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    let sections = [
+        header,
+        options,
+        entries.into_iter().flat_map(|(_, lines)| lines).collect(),
+    ];
+    let mut formatted = sections
+        .into_iter()
+        .filter(|lines| !lines.is_empty())
+        .map(|lines| lines.join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    for comment in comments {
+        formatted.push('\n');
+        formatted.push_str(&comment);
+    }
+    if !formatted.is_empty() {
+        formatted.push('\n');
+    }
+    formatted
+}
+
+/// Formats a single requirement and returns it with its sort key
+fn format_requirement_line(line: &str) -> (String, String) {
+    let comment_start = line
+        .char_indices()
+        .find(|&(i, c)| c == '#' && line[..i].ends_with(char::is_whitespace))
+        .map_or(line.len(), |(i, _)| i);
+    let comment = line[comment_start..].trim();
+    let code = &line[..comment_start];
+
+    let (requirement, options) = match code.find(" -") {
+        Some(index) => (&code[..index], &code[index + 1..]),
+        None => (code, ""),
+    };
+    let mut hashes = Vec::new();
+    let mut other = Vec::new();
+    let mut words = options.split_whitespace();
+    while let Some(word) = words.next() {
+        match word {
+            "--hash" => hashes.extend(words.next().map(|hash| format!("--hash={}", hash))),
+            _ if word.starts_with("--hash=") => hashes.push(word.to_string()),
+            _ => other.push(word),
+        }
+    }
+
+    let requirement = requirement.trim();
+    let mut formatted = format_requirement(requirement);
+    for option in other {
+        formatted.push(' ');
+        formatted.push_str(option);
+    }
+    for hash in hashes {
+        formatted.push_str(" \\\n    ");
+        formatted.push_str(&hash);
+    }
+    if !comment.is_empty() {
+        formatted.push_str("  ");
+        formatted.push_str(comment);
+    }
+    (canonicalize_name(requirement_name(requirement)), formatted)
+}
+
+/// Normalizes the name, extras, specifiers and markers of a requirement
+fn format_requirement(requirement: &str) -> String {
+    let (requirement, markers) = match requirement.split_once(';') {
+        Some((requirement, markers)) => (
+            requirement.trim(),
+            Some(markers.split_whitespace().collect::<Vec<_>>().join(" ")),
+        ),
+        None => (requirement, None),
+    };
+    let name = requirement_name(requirement);
+    let direct = VcsRequirement::parse(requirement).is_some() || requirement.contains("://");
+    if name.is_empty()
+        || requirement.starts_with(['.', '/'])
+        || (direct && !requirement.contains(" @"))
+    {
+        return requirement.to_string() + &markers.map_or(String::new(), |m| format!("; {}", m));
+    }
+
+    let rest = requirement[name.len()..].trim_start();
+    let (extras, rest) = match rest.strip_prefix('[').and_then(|rest| rest.split_once(']')) {
+        Some((extras, rest)) => {
+            let mut extras: Vec<String> = extras
+                .split(',')
+                .map(canonicalize_name)
+                .filter(|extra| !extra.is_empty())
+                .collect();
+            extras.sort();
+            extras.dedup();
+            (format!("[{}]", extras.join(",")), rest.trim_start())
+        }
+        None => (String::new(), rest),
+    };
+    let mut formatted = canonicalize_name(name) + &extras;
+    match rest.strip_prefix('@') {
+        Some(url) => {
+            formatted.push_str(" @ ");
+            formatted.push_str(url.trim());
+        }
+        None => {
+            let mut clauses: Vec<String> = rest
+                .split(',')
+                .map(|clause| clause.split_whitespace().collect::<String>())
+                .filter(|clause| !clause.is_empty())
+                .collect();
+            clauses.sort();
+            clauses.dedup();
+            formatted.push_str(&clauses.join(","));
+        }
+    }
+    if let Some(markers) = markers {
+        formatted.push_str("; ");
+        formatted.push_str(&markers);
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hash_pinned_requirements() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("requirements.txt");
+        std::fs::write(
+            &path,
+            "# pinned\nalpha==1.0 \\\n    --hash=sha256:aaaa \\\n    --hash=sha256:bbbb\n\
+             beta>=2 --hash sha256:cccc\n",
+        )
+        .unwrap();
+        let path = path.to_str().unwrap();
+
+        assert_eq!(
+            parse_requirements_file(path).unwrap(),
+            vec!["alpha==1.0", "beta>=2"]
+        );
+    }
+
+    #[test]
+    fn test_read_package_list() {
+        let input = "requests==2.31.0 rich\n# comment\n  numpy>=1.26  # pinned below 2\ntool@git+https://example.com/tool#egg=tool\n";
+        assert_eq!(
+            read_package_list(input.as_bytes()).unwrap(),
+            [
+                "requests==2.31.0",
+                "rich",
+                "numpy>=1.26",
+                "tool@git+https://example.com/tool#egg=tool"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pin_requirements() {
+        let contents = "# web stack\n\
+                        --index-url https://pypi.example.org/simple\n\
+                        requests[socks] >= 2.0 ; python_version >= \"3.8\"  # http\n\
+                        rich==13.7.0\n\
+                        numpy==1.26.0 \\\n    --hash=sha256:abc\n\
+                        tool @ https://example.com/tool.whl\n\
+                        missing\n";
+        let versions: HashMap<String, String> = [
+            ("requests", "2.32.3"),
+            ("rich", "13.7.0"),
+            ("numpy", "1.26.4"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_string(), version.to_string()))
+        .collect();
+        assert_eq!(
+            pinnable_requirements(contents),
+            ["requests", "rich", "numpy", "missing"]
+        );
+
+        let pinned = pin_requirements(contents, &versions);
+        assert_eq!(
+            pinned.contents,
+            contents.replace("requests[socks] >= 2.0 ;", "requests[socks]==2.32.3 ;")
+        );
+        assert_eq!(
+            pinned.changes,
+            [PinChange {
+                name: "requests".to_string(),
+                previous: ">= 2.0".to_string(),
+                version: "2.32.3".to_string(),
+            }]
+        );
+        let skipped: Vec<&str> = pinned
+            .skipped
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(skipped, ["numpy", "missing"]);
+    }
+
+    #[test]
+    fn test_bump_requirement_files() {
+        let requirements = "Requests>=2.0  # http\nrich\nrequests-toolbelt~=1.0\n";
+        let (bumped, edits, hashed) = bump_requirements(requirements, "requests", "2.32.3");
+        assert_eq!(
+            bumped,
+            "Requests>=2.32.3  # http\nrich\nrequests-toolbelt~=1.0\n"
+        );
+        assert_eq!(edits[0].line, 1);
+        assert_eq!(edits[0].after, "Requests>=2.32.3  # http");
+        assert!(hashed.is_empty());
+
+        let pyproject = "[project]\n\
+                         dependencies = [\n    \"requests>=2,<3\",  # http\n    \"rich\",\n]\n\
+                         [project.optional-dependencies]\n\
+                         socks = [\"requests[socks]==2.31.0; python_version > \\\"3.8\\\"\"]\n\
+                         [tool.other]\n\
+                         dependencies = [\"requests==1.0\"]\n";
+        let (bumped, edits) = bump_pyproject(pyproject, "requests", "2.32.3");
+        assert_eq!(
+            bumped,
+            pyproject
+                .replace("requests>=2,<3", "requests==2.32.3")
+                .replace("requests[socks]==2.31.0", "requests[socks]==2.32.3")
+        );
+        let lines: Vec<usize> = edits.iter().map(|edit| edit.line).collect();
+        assert_eq!(lines, [3, 7]);
+    }
+
+    #[test]
+    fn test_format_requirements() {
+        let contents = "# Project deps\n\
+                        # maintained by hand\n\
+                        \n\
+                        --index-url   https://pypi.example.org/simple\n\
+                        # shared pins\n\
+                        -r   base.txt\n\
+                        Rich [Jupyter , Extra_A] >= 13.0 ,< 14  # ui\n\
+                        requests==2.31.0 \\\n    --hash=sha256:abc\n\
+                        \n\
+                        # numeric\n\
+                        NumPy>=1.26,<2 ; python_version   >= \"3.9\"\n\
+                        Tool @ https://example.com/tool.whl\n\
+                        # dangling\n";
+        let formatted = format_requirements(contents);
+        assert_eq!(
+            formatted,
+            "# Project deps\n\
+             # maintained by hand\n\
+             \n\
+             --index-url https://pypi.example.org/simple\n\
+             # shared pins\n\
+             -r base.txt\n\
+             \n\
+             # numeric\n\
+             numpy<2,>=1.26; python_version >= \"3.9\"\n\
+             requests==2.31.0 \\\n    --hash=sha256:abc\n\
+             rich[extra-a,jupyter]<14,>=13.0  # ui\n\
+             tool @ https://example.com/tool.whl\n\
+             # dangling\n"
+        );
+        assert_eq!(format_requirements(&formatted), formatted);
+    }
+
+    #[test]
+    fn test_read_constraints_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(
+            &mut file,
+            b"# organization ceilings\nnumpy<2\nDjango >= 4.2, < 5.0 ; python_version >= '3.8'\nrequests\n",
+        )
+        .unwrap();
+
+        let constraints = read_constraints_file(file.path().to_str().unwrap()).unwrap();
+        assert_eq!(constraints.len(), 2);
+        assert_eq!(constraints["numpy"], "<2");
+        assert_eq!(constraints["django"], ">=4.2,<5.0");
+    }
+}
@@ -0,0 +1,600 @@
+//! A local PEP 503 simple index that proxies PyPI through ppm's caches
+//!
+//! `ppm serve` renders the `/simple/` pages from the cached PyPI JSON
+//! documents and serves the files they link to from a local artifact cache,
+//! downloading each file from PyPI the first time it is asked for. A team or
+//! CI fleet points pip at it with `--index-url http://<host>:<port>/simple/`
+//! and fetches every artifact from PyPI only once.
+
+use crate::http::http_client;
+use crate::metadata::fetch_pypi_json;
+use crate::pip::get_python_executable;
+use crate::spec::canonicalize_name;
+use crate::state::{response_cache_dir, STATE_DIR};
+use crate::{metadata_ttl, success_mark, HttpFailure, PackageError, Result};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Port `ppm serve` listens on unless told otherwise
+pub const DEFAULT_SERVE_PORT: u16 = 3141;
+
+/// Connections answered at once; further ones wait in a queue of the same size
+const SERVE_WORKERS: usize = 16;
+
+/// How long a connection may take to send a line or accept a write
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest request line or header line that is read
+const MAX_LINE_LENGTH: u64 = 8 * 1024;
+
+/// Most header lines read from one request
+const MAX_HEADERS: usize = 100;
+
+/// Directory of the distribution files downloaded through the proxy
+fn artifact_cache_dir() -> PathBuf {
+    Path::new(STATE_DIR).join("cache").join("files")
+}
+
+/// Body of a [`ProxyResponse`]
+#[derive(Debug, PartialEq)]
+enum Body {
+    Text(String),
+    File(PathBuf),
+}
+
+/// Answer to one request to the proxy
+#[derive(Debug, PartialEq)]
+struct ProxyResponse {
+    status: u16,
+    content_type: &'static str,
+    location: Option<String>,
+    body: Body,
+}
+
+impl ProxyResponse {
+    fn html(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/html; charset=utf-8",
+            location: None,
+            body: Body::Text(body),
+        }
+    }
+
+    fn file(path: PathBuf) -> Self {
+        Self {
+            status: 200,
+            content_type: "application/octet-stream",
+            location: None,
+            body: Body::File(path),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            location: None,
+            body: Body::Text(message.into() + "\n"),
+        }
+    }
+
+    fn redirect(location: String) -> Self {
+        Self {
+            location: Some(location),
+            ..Self::error(301, "Moved Permanently")
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            301 => "Moved Permanently",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Bad Gateway",
+        }
+    }
+}
+
+/// Simple index answering from ppm's metadata cache and artifact cache
+///
+/// ```ignore
+/// let proxy = IndexProxy::new(get_python_executable()?);
+/// proxy.serve("0.0.0.0", DEFAULT_SERVE_PORT)?;
+/// ```
+#[derive(Debug)]
+pub struct IndexProxy {
+    python: String,
+    files: PathBuf,
+    /// Project documents looked up by this proxy and when; only held to look
+    /// an entry up or store one, never during a fetch
+    metadata: Mutex<HashMap<String, (Instant, Arc<serde_json::Value>)>>,
+    downloads: AtomicUsize,
+}
+
+impl IndexProxy {
+    /// Creates a proxy that fetches through `python` and keeps files in `.ppm/cache/files`
+    pub fn new(python: impl Into<String>) -> Self {
+        Self {
+            python: python.into(),
+            files: artifact_cache_dir(),
+            metadata: Mutex::new(HashMap::new()),
+            downloads: AtomicUsize::new(0),
+        }
+    }
+
+    /// Listens for pip's requests until the process is stopped
+    ///
+    /// Connections are answered by a fixed pool of worker threads; while all
+    /// of them are busy and the queue is full, no more connections are accepted.
+    ///
+    /// # Arguments
+    /// * `host` - Address to bind, e.g. "127.0.0.1" or "0.0.0.0"
+    /// * `port` - Port to bind; 0 picks a free one
+    ///
+    /// # Returns
+    /// * `Result<()>` - An error if the address could not be bound
+    pub fn serve(self, host: &str, port: u16) -> Result<()> {
+        let listener = TcpListener::bind((host, port))?;
+        status!(
+            "{} Serving a simple index at http://{}/simple/ (Ctrl-C to stop)",
+            success_mark(),
+            listener.local_addr()?
+        );
+        let proxy = Arc::new(self);
+        let (sender, receiver) = sync_channel::<TcpStream>(SERVE_WORKERS);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..SERVE_WORKERS {
+            let proxy = Arc::clone(&proxy);
+            let receiver = Arc::clone(&receiver);
+            std::thread::spawn(move || proxy.work(&receiver));
+        }
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    if sender.send(stream).is_err() {
+                        break;
+                    }
+                }
+                Err(e) => warning!("Could not accept a connection: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Answers queued connections one after another until the queue is closed
+    fn work(&self, connections: &Mutex<Receiver<TcpStream>>) {
+        loop {
+            let next = connections.lock().unwrap_or_else(|e| e.into_inner()).recv();
+            let Ok(stream) = next else {
+                return;
+            };
+            if let Err(e) = self.answer(stream) {
+                debug_detail!("Connection failed: {}", e);
+            }
+        }
+    }
+
+    /// Reads one request from a connection and writes the response
+    fn answer(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        stream.set_read_timeout(Some(CONNECTION_TIMEOUT))?;
+        stream.set_write_timeout(Some(CONNECTION_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        read_limited_line(&mut reader, &mut request_line)?;
+        // Headers are not needed, but must be read before the connection is closed
+        let mut header = String::new();
+        for _ in 0..MAX_HEADERS {
+            header.clear();
+            if read_limited_line(&mut reader, &mut header)? <= 2 {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or_default();
+        let target = parts.next().unwrap_or("/");
+        let response = match method {
+            _ if !request_line.ends_with('\n') => {
+                ProxyResponse::error(400, "The request line is too long")
+            }
+            "GET" | "HEAD" => self.route(target),
+            _ => ProxyResponse::error(405, "Only GET and HEAD are supported"),
+        };
+        detail!("{} {} {}", method, target, response.status);
+
+        let body: Box<dyn Read> = match &response.body {
+            Body::Text(text) => Box::new(std::io::Cursor::new(text.clone().into_bytes())),
+            Body::File(path) => Box::new(File::open(path)?),
+        };
+        let length = match &response.body {
+            Body::Text(text) => text.len() as u64,
+            Body::File(path) => path.metadata()?.len(),
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            response.status,
+            response.reason(),
+            response.content_type,
+            length
+        );
+        if let Some(location) = &response.location {
+            head.push_str(&format!("Location: {}\r\n", location));
+        }
+        head.push_str("\r\n");
+        stream.write_all(head.as_bytes())?;
+        if method != "HEAD" {
+            std::io::copy(&mut body.take(length), &mut stream)?;
+        }
+        stream.flush()
+    }
+
+    /// Answers a request for a path of the index
+    fn route(&self, target: &str) -> ProxyResponse {
+        let path = percent_decode(target.split(['?', '#']).next().unwrap_or_default());
+        let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+        match segments.as_slice() {
+            [""] | ["simple"] => ProxyResponse::redirect("/simple/".to_string()),
+            ["simple", ""] => ProxyResponse::html(render_root_page(&cached_projects())),
+            ["simple", project] | ["simple", project, ""] => {
+                let canonical = canonicalize_name(project);
+                if segments.len() == 2 || *project != canonical {
+                    return ProxyResponse::redirect(format!("/simple/{}/", canonical));
+                }
+                match self.project_json(project) {
+                    Ok(metadata) => ProxyResponse::html(render_project_page(project, &metadata)),
+                    Err(e) => upstream_error(project, e),
+                }
+            }
+            ["files", project, filename] if is_plain_name(project) && is_plain_name(filename) => {
+                self.artifact(project, filename)
+            }
+            _ => ProxyResponse::error(404, "Not Found"),
+        }
+    }
+
+    /// The PyPI project document, from memory or the response cache while it is fresh
+    fn project_json(&self, project: &str) -> Result<Arc<serde_json::Value>> {
+        let remembered = self
+            .metadata
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(project)
+            .filter(|(fetched, _)| fetched.elapsed() < metadata_ttl())
+            .map(|(_, metadata)| Arc::clone(metadata));
+        if let Some(metadata) = remembered {
+            return Ok(metadata);
+        }
+
+        let metadata = Arc::new(fetch_pypi_json(&self.python, project, None)?);
+        self.metadata
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(project.to_string(), (Instant::now(), Arc::clone(&metadata)));
+        Ok(metadata)
+    }
+
+    /// Serves a distribution file, downloading and verifying it on the first request
+    fn artifact(&self, project: &str, filename: &str) -> ProxyResponse {
+        let path = self.files.join(canonicalize_name(project)).join(filename);
+        if path.is_file() {
+            return ProxyResponse::file(path);
+        }
+
+        let metadata = match self.project_json(project) {
+            Ok(metadata) => metadata,
+            Err(e) => return upstream_error(project, e),
+        };
+        let Some(file) = project_files(&metadata)
+            .into_iter()
+            .find(|file| file["filename"] == filename)
+        else {
+            return ProxyResponse::error(404, format!("{} has no file {}", project, filename));
+        };
+        let url = file["url"].as_str().unwrap_or_default();
+        let expected = file["digests"]["sha256"].as_str().unwrap_or_default();
+        match self.download(url, expected, &path) {
+            Ok(()) => ProxyResponse::file(path),
+            Err(e) => {
+                warning!("Could not fetch {}: {}", filename, e);
+                ProxyResponse::error(502, e.to_string())
+            }
+        }
+    }
+
+    /// Downloads a file into the artifact cache, keeping it only if its sha256 matches
+    fn download(&self, url: &str, sha256: &str, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Concurrent requests for the same file each download their own copy
+        let partial = path.with_extension(format!(
+            "part-{}-{}",
+            std::process::id(),
+            self.downloads.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = http_client()
+            .download(&self.python, url, &partial)
+            .and_then(|()| file_sha256(&partial))
+            .and_then(|actual| {
+                if actual != sha256 {
+                    return Err(PackageError::MetadataError(format!(
+                        "{} does not match its published sha256 (expected {}, got {})",
+                        url, sha256, actual
+                    )));
+                }
+                Ok(std::fs::rename(&partial, path)?)
+            });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&partial);
+        }
+        result
+    }
+}
+
+/// Reads a line of at most [`MAX_LINE_LENGTH`] bytes, the rest of a longer one
+/// being left for the next read
+fn read_limited_line(reader: &mut impl BufRead, line: &mut String) -> std::io::Result<usize> {
+    reader.take(MAX_LINE_LENGTH).read_line(line)
+}
+
+/// Turns a failed metadata lookup into a 404 for unknown projects, or a 502
+fn upstream_error(project: &str, error: PackageError) -> ProxyResponse {
+    if matches!(
+        error,
+        PackageError::HttpStatus(HttpFailure { status: 404, .. })
+    ) {
+        ProxyResponse::error(404, format!("{} is not on PyPI", project))
+    } else {
+        warning!("Could not look up {}: {}", project, error);
+        ProxyResponse::error(502, error.to_string())
+    }
+}
+
+/// Names of the projects whose metadata is cached, sorted
+fn cached_projects() -> Vec<String> {
+    let mut projects: Vec<String> = std::fs::read_dir(response_cache_dir())
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // Release documents are cached as `<name>@<version>.json`
+            let project = name.strip_suffix(".json")?;
+            (!project.contains('@')).then(|| project.to_string())
+        })
+        .collect();
+    projects.sort();
+    projects
+}
+
+/// Every file of every release in a PyPI project document
+fn project_files(metadata: &serde_json::Value) -> Vec<&serde_json::Value> {
+    let mut files: Vec<&serde_json::Value> = metadata["releases"]
+        .as_object()
+        .into_iter()
+        .flat_map(|releases| releases.values())
+        .filter_map(|files| files.as_array())
+        .flatten()
+        .collect();
+    files.sort_by_key(|file| file["filename"].as_str().unwrap_or_default());
+    files
+}
+
+/// Renders the `/simple/` page listing the cached projects
+fn render_root_page(projects: &[String]) -> String {
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta name=\"pypi:repository-version\" content=\"1.0\">\
+         <title>Simple index</title></head>\n<body>\n",
+    );
+    for project in projects {
+        let project = escape_html(project);
+        out.push_str(&format!("<a href=\"{0}/\">{0}</a><br/>\n", project));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Renders the PEP 503 page of a project, linking its files to the artifact cache
+///
+/// # Arguments
+/// * `project` - Normalized project name
+/// * `metadata` - The PyPI JSON document of the project
+///
+/// # Returns
+/// The HTML page, with each file's sha256, `Requires-Python` and yank reason
+pub fn render_project_page(project: &str, metadata: &serde_json::Value) -> String {
+    let project = escape_html(project);
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta name=\"pypi:repository-version\" content=\"1.0\">\
+         <title>Links for {0}</title></head>\n<body>\n<h1>Links for {0}</h1>\n",
+        project
+    );
+    for file in project_files(metadata) {
+        let Some(filename) = file["filename"].as_str() else {
+            continue;
+        };
+        let filename = escape_html(filename);
+        let mut anchor = format!("<a href=\"/files/{}/{}", project, filename);
+        if let Some(sha256) = file["digests"]["sha256"].as_str() {
+            anchor.push_str(&format!("#sha256={}", escape_html(sha256)));
+        }
+        anchor.push('"');
+        if let Some(requires) = file["requires_python"].as_str().filter(|r| !r.is_empty()) {
+            anchor.push_str(&format!(
+                " data-requires-python=\"{}\"",
+                escape_html(requires)
+            ));
+        }
+        if file["yanked"].as_bool() == Some(true) {
+            let reason = file["yanked_reason"].as_str().unwrap_or_default();
+            anchor.push_str(&format!(" data-yanked=\"{}\"", escape_html(reason)));
+        }
+        out.push_str(&format!("{}>{}</a><br/>\n", anchor, filename));
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Whether a path segment names a file or project rather than leaving its directory
+fn is_plain_name(segment: &str) -> bool {
+    !segment.is_empty() && segment != "." && segment != ".." && !segment.contains('\\')
+}
+
+/// Hex sha256 of a file, read in chunks
+pub(crate) fn file_sha256(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = hmac_sha256::Hash::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Decodes `%XX` escapes in a request path
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Escapes text for an HTML attribute or element
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Runs the caching index proxy in the current environment until stopped
+///
+/// # Arguments
+/// * `host` - Address to bind
+/// * `port` - Port to bind
+pub fn serve(host: &str, port: u16) -> Result<()> {
+    IndexProxy::new(get_python_executable()?).serve(host, port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_project_page() {
+        let metadata = serde_json::json!({
+            "releases": {
+                "1.0": [{
+                    "filename": "demo-1.0-py3-none-any.whl",
+                    "url": "https://files.pythonhosted.org/packages/demo-1.0-py3-none-any.whl",
+                    "digests": {"sha256": "abc123"},
+                    "requires_python": ">=3.8",
+                    "yanked": true,
+                    "yanked_reason": "broken \"wheel\"",
+                }],
+                "0.9": [{
+                    "filename": "demo-0.9.tar.gz",
+                    "digests": {"sha256": "def456"},
+                    "requires_python": null,
+                    "yanked": false,
+                }],
+            }
+        });
+        let page = render_project_page("demo", &metadata);
+        assert!(page.contains("<h1>Links for demo</h1>"));
+        assert!(page
+            .contains("<a href=\"/files/demo/demo-0.9.tar.gz#sha256=def456\">demo-0.9.tar.gz</a>"));
+        assert!(page.contains(
+            "<a href=\"/files/demo/demo-1.0-py3-none-any.whl#sha256=abc123\" \
+             data-requires-python=\"&gt;=3.8\" data-yanked=\"broken &quot;wheel&quot;\">"
+        ));
+        assert!(page.find("demo-0.9").unwrap() < page.find("demo-1.0").unwrap());
+    }
+
+    #[test]
+    fn test_proxy_routes() {
+        let proxy = IndexProxy::new("python3");
+        let location = |target: &str| proxy.route(target).location;
+        assert_eq!(location("/"), Some("/simple/".to_string()));
+        assert_eq!(location("/simple"), Some("/simple/".to_string()));
+        assert_eq!(
+            location("/simple/Foo_Bar/"),
+            Some("/simple/foo-bar/".to_string())
+        );
+        assert_eq!(
+            location("/simple/foo-bar?x=1"),
+            Some("/simple/foo-bar/".to_string())
+        );
+        assert_eq!(proxy.route("/files/demo/..").status, 404);
+        assert_eq!(proxy.route("/files/%2E%2E/x.whl").status, 404);
+        assert_eq!(proxy.route("/other").status, 404);
+        assert_eq!(percent_decode("demo-1.0%2Blocal.whl"), "demo-1.0+local.whl");
+
+        let missing = PackageError::HttpStatus(HttpFailure {
+            url: "https://pypi.org/pypi/nope/json".to_string(),
+            status: 404,
+            reason: "Not Found".to_string(),
+        });
+        assert_eq!(upstream_error("nope", missing).status, 404);
+    }
+
+    #[test]
+    fn test_proxy_caps_request_lines() {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(address).unwrap();
+            let target = "a".repeat(MAX_LINE_LENGTH as usize);
+            stream
+                .write_all(format!("GET /simple/{} HTTP/1.1\r\n\r\n", target).as_bytes())
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        });
+
+        let (stream, _) = listener.accept().unwrap();
+        IndexProxy::new("python3").answer(stream).unwrap();
+        let response = client.join().unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            "{}",
+            response
+        );
+        assert!(response.ends_with("The request line is too long\n"));
+    }
+}
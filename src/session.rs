@@ -0,0 +1,345 @@
+//! Process-wide settings of a run: interpreter override, CI mode, prompts and JSON events
+
+use crate::outcome::OperationOutcome;
+use crate::state::unix_now;
+use crate::PackageError;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Interpreter to use instead of the one found on PATH, e.g. a workspace member's venv
+pub(crate) static PYTHON_OVERRIDE: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    /// Interpreter of the current thread, taking precedence over [`PYTHON_OVERRIDE`];
+    /// matrix installs handle each interpreter on a thread of its own
+    pub(crate) static THREAD_PYTHON: std::cell::RefCell<Option<String>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Makes pip run with a specific interpreter instead of the one found on PATH
+///
+/// # Arguments
+/// * `python` - Path of the interpreter, or None to restore the default lookup
+pub fn set_python_override(python: Option<String>) {
+    *PYTHON_OVERRIDE.lock().unwrap() = python;
+}
+
+/// Whether CI mode disabled prompts and progress bars
+static CI_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Whether `--yes` answered confirmation prompts in advance
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+/// Sets whether confirmation prompts are answered with yes
+///
+/// # Arguments
+/// * `enabled` - Whether `--yes` was given
+pub fn set_assume_yes(enabled: bool) {
+    ASSUME_YES.store(enabled, AtomicOrdering::Relaxed);
+}
+
+/// Whether confirmation prompts are answered with yes
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(AtomicOrdering::Relaxed)
+}
+
+/// How a confirmation prompt is settled, see [`prompt_answer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptAnswer {
+    /// `--yes` answered it in advance
+    Yes,
+    /// The user is asked on the terminal
+    Ask,
+    /// Nobody can answer it, for the given reason
+    Refuse(&'static str),
+}
+
+/// Decides whether a confirmation prompt is answered, asked or refused
+///
+/// `--yes` answers every prompt in advance. Without it, CI mode and a stdin
+/// that is not a terminal refuse instead of waiting for an answer that never comes.
+///
+/// # Arguments
+/// * `assume_yes` - Whether `--yes` was given
+/// * `ci_mode` - Whether CI mode is on
+/// * `stdin_is_terminal` - Whether stdin is a terminal someone can answer on
+pub fn prompt_answer(assume_yes: bool, ci_mode: bool, stdin_is_terminal: bool) -> PromptAnswer {
+    if assume_yes {
+        PromptAnswer::Yes
+    } else if ci_mode {
+        PromptAnswer::Refuse("prompts are disabled in CI mode")
+    } else if !stdin_is_terminal {
+        PromptAnswer::Refuse("stdin is not a terminal")
+    } else {
+        PromptAnswer::Ask
+    }
+}
+
+/// Number of warnings printed during this run
+static WARNINGS_EMITTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Enables or disables CI mode
+///
+/// # Arguments
+/// * `enabled` - Whether prompts and progress bars are disabled
+pub fn set_ci_mode(enabled: bool) {
+    CI_MODE.store(enabled, AtomicOrdering::Relaxed);
+}
+
+/// Whether CI mode is enabled
+pub fn ci_mode() -> bool {
+    CI_MODE.load(AtomicOrdering::Relaxed)
+}
+
+/// Counts a warning; called by the [`warning!`] macro
+#[doc(hidden)]
+pub fn record_warning() {
+    WARNINGS_EMITTED.fetch_add(1, AtomicOrdering::Relaxed);
+}
+
+/// Number of warnings emitted so far in this run
+pub fn warnings_emitted() -> usize {
+    WARNINGS_EMITTED.load(AtomicOrdering::Relaxed)
+}
+
+/// How progress is reported while packages are processed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum ProgressMode {
+    /// Progress bars and spinners on the terminal
+    Bar,
+    /// One JSON event per line on stderr, for CI systems and wrappers
+    Json,
+}
+
+/// Whether `--progress json` replaced the progress bars with NDJSON events
+static JSON_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Selects how progress is reported
+///
+/// # Arguments
+/// * `mode` - The progress reporting mode
+pub fn set_progress_mode(mode: ProgressMode) {
+    JSON_PROGRESS.store(mode == ProgressMode::Json, AtomicOrdering::Relaxed);
+}
+
+/// Gets how progress is reported
+pub fn progress_mode() -> ProgressMode {
+    if JSON_PROGRESS.load(AtomicOrdering::Relaxed) {
+        ProgressMode::Json
+    } else {
+        ProgressMode::Bar
+    }
+}
+
+/// Writes a progress event as one line of JSON on stderr in `--progress json` mode
+pub(crate) fn emit_event(event: serde_json::Value) {
+    if progress_mode() == ProgressMode::Json {
+        eprintln!("{}", event);
+    }
+}
+
+/// Builds the `package_started` event
+pub(crate) fn package_started_event(name: &str, operation: &str) -> serde_json::Value {
+    serde_json::json!({
+        "event": "package_started",
+        "package": name,
+        "operation": operation,
+        "timestamp": unix_now(),
+    })
+}
+
+/// Builds the `package_installed` or `package_failed` event for a finished package
+pub(crate) fn package_finished_event(
+    name: &str,
+    operation: &str,
+    result: std::result::Result<&str, &str>,
+    elapsed: Duration,
+) -> serde_json::Value {
+    let mut event = serde_json::json!({
+        "package": name,
+        "operation": operation,
+        "duration_ms": elapsed.as_millis() as u64,
+        "timestamp": unix_now(),
+    });
+    match result {
+        Ok(version) => {
+            event["event"] = "package_installed".into();
+            // Unpinned parallel installs learn their version after the whole batch
+            if !version.is_empty() {
+                event["version"] = version.into();
+            }
+        }
+        Err(error) => {
+            event["event"] = "package_failed".into();
+            event["error"] = error_summary(error).into();
+        }
+    }
+    event
+}
+
+/// Emits the `run_summary` event at the end of an invocation
+///
+/// # Arguments
+/// * `command` - Name of the command that was run
+/// * `exit_code` - Exit code of the process
+/// * `elapsed` - Wall-clock duration of the whole run
+pub fn emit_run_summary(command: &str, exit_code: i32, elapsed: Duration) {
+    let report = RUN_REPORT.lock().unwrap();
+    let failed = report
+        .outcomes
+        .iter()
+        .filter(|o| o.status() == "failed")
+        .count();
+    emit_event(serde_json::json!({
+        "event": "run_summary",
+        "command": command,
+        "succeeded": report.outcomes.len() - failed,
+        "failed": failed,
+        "removed": report.removed.len(),
+        "exit_code": exit_code,
+        "duration_ms": elapsed.as_millis() as u64,
+        "timestamp": unix_now(),
+    }));
+}
+
+/// Last non-empty line of an error message, which is where pip puts the reason
+pub(crate) fn error_summary(error: &str) -> &str {
+    error
+        .lines()
+        .rev()
+        .find(|line| !line.trim().is_empty())
+        .map_or("unknown error", str::trim)
+}
+
+/// Results collected during the current invocation for the JSON result document
+#[derive(Debug, Default)]
+pub(crate) struct RunReport {
+    pub(crate) outcomes: Vec<OperationOutcome>,
+    /// Names of removed packages with the version they had, if known
+    pub(crate) removed: Vec<(String, Option<String>)>,
+    /// Id of the history entry reversed by this run
+    pub(crate) undoes: Option<u64>,
+    data: Option<serde_json::Value>,
+}
+
+/// Results of the current invocation, rendered by [`render_json_result`]
+pub(crate) static RUN_REPORT: Mutex<RunReport> = Mutex::new(RunReport {
+    outcomes: Vec::new(),
+    removed: Vec::new(),
+    undoes: None,
+    data: None,
+});
+
+/// Records a package removed during the current invocation
+pub(crate) fn record_removal(name: &str, version: Option<String>) {
+    RUN_REPORT
+        .lock()
+        .unwrap()
+        .removed
+        .push((name.to_string(), version));
+}
+
+/// Sets the command-specific payload of the JSON result document, e.g. a listing
+///
+/// # Arguments
+/// * `data` - The payload
+pub fn set_report_data(data: serde_json::Value) {
+    RUN_REPORT.lock().unwrap().data = Some(data);
+}
+
+/// Renders the JSON result document of the current invocation
+///
+/// # Arguments
+/// * `command` - Name of the command that was run
+/// * `exit_code` - Exit code of the process
+/// * `error` - Error that ended the command, if any
+///
+/// # Returns
+/// The pretty-printed document
+pub fn render_json_result(command: &str, exit_code: i32, error: Option<&PackageError>) -> String {
+    let report = RUN_REPORT.lock().unwrap();
+    let packages: Vec<serde_json::Value> = report
+        .outcomes
+        .iter()
+        .map(|outcome| {
+            serde_json::json!({
+                "name": outcome.name,
+                "spec": outcome.spec,
+                "previous_version": outcome.previous_version,
+                "new_version": outcome.new_version,
+                "status": outcome.status(),
+                "duration_ms": outcome.millis,
+                "index": outcome.index,
+                "error": outcome.error.as_deref().map(str::trim),
+            })
+        })
+        .collect();
+
+    let removed: Vec<&str> = report
+        .removed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    let document = serde_json::json!({
+        "command": command,
+        "success": exit_code == 0,
+        "exit_code": exit_code,
+        "error": error.map(|e| e.to_string()),
+        "error_code": error.map(PackageError::code),
+        "packages": packages,
+        "removed": removed,
+        "data": report.data,
+    });
+    serde_json::to_string_pretty(&document).unwrap_or_else(|_| "{}".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_json_result() {
+        set_report_data(serde_json::json!([{"name": "six", "version": "1.16.0"}]));
+        let document: serde_json::Value = serde_json::from_str(&render_json_result(
+            "list",
+            6,
+            Some(&PackageError::PackageNotFound("nope".to_string())),
+        ))
+        .unwrap();
+
+        assert_eq!(document["command"], "list");
+        assert_eq!(document["success"], false);
+        assert_eq!(document["exit_code"], 6);
+        assert_eq!(document["error"], "Package not found: nope");
+        assert_eq!(document["data"][0]["name"], "six");
+    }
+
+    #[test]
+    fn test_package_finished_event() {
+        let installed = package_finished_event(
+            "click",
+            "install",
+            Ok("8.1.7"),
+            Duration::from_millis(1_250),
+        );
+        assert_eq!(installed["event"], "package_installed");
+        assert_eq!(installed["version"], "8.1.7");
+        assert_eq!(installed["duration_ms"], 1_250);
+
+        let failed = package_finished_event(
+            "nosuchpkg",
+            "install",
+            Err("WARNING: Retrying\nERROR: No matching distribution found for nosuchpkg\n"),
+            Duration::from_millis(800),
+        );
+        assert_eq!(failed["event"], "package_failed");
+        assert_eq!(
+            failed["error"],
+            "ERROR: No matching distribution found for nosuchpkg"
+        );
+        assert!(failed.get("version").is_none());
+    }
+}
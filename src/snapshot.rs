@@ -0,0 +1,456 @@
+//! Named snapshots of the installed packages and restoring them
+
+use crate::diff::{DiffEntry, VersionChange};
+use crate::pip::{get_python_executable, get_python_version, pip_error, run_command, run_pip};
+use crate::registry::PackageRegistry;
+use crate::reporter::success_mark;
+use crate::session::record_removal;
+use crate::spec::canonicalize_name;
+use crate::state::{unix_now, STATE_DIR};
+use crate::stats::{record_timing, Phase};
+use crate::{PackageError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// An installed distribution captured by a snapshot
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotPackage {
+    /// Distribution name
+    pub name: String,
+    /// Installed version
+    pub version: String,
+    /// SHA-256 of the distribution's RECORD file, which lists the hash of every installed file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record_hash: Option<String>,
+}
+
+/// A restore point of the whole environment, stored in `.ppm/snapshots/<name>.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Snapshot name
+    pub name: String,
+    /// Unix timestamp of the capture
+    pub created_at: u64,
+    /// Version of the interpreter whose environment was captured
+    pub python_version: String,
+    /// Every installed distribution, sorted by name
+    pub packages: Vec<SnapshotPackage>,
+    /// The registry at the time of the capture
+    pub registry: PackageRegistry,
+}
+
+/// Directory holding the snapshots
+fn snapshots_dir() -> PathBuf {
+    Path::new(STATE_DIR).join("snapshots")
+}
+
+/// Resolves the file of a snapshot, rejecting names that are not plain file names
+fn snapshot_path(name: &str) -> Result<PathBuf> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "Invalid snapshot name \"{}\": use letters, digits, '-', '_' and '.'",
+            name
+        )));
+    }
+    Ok(snapshots_dir().join(format!("{}.json", name)))
+}
+
+/// Lists every installed distribution with the hash of its RECORD file
+///
+/// # Returns
+/// * `Result<Vec<SnapshotPackage>>` - Installed distributions sorted by normalized name
+pub fn installed_snapshot_packages() -> Result<Vec<SnapshotPackage>> {
+    let python = get_python_executable()?;
+    let output = run_command(Command::new(&python).arg("-c").arg(SNAPSHOT_SCRIPT))
+        .map_err(|_| PackageError::PythonNotFound)?;
+    if !output.status.success() {
+        return Err(PackageError::MetadataError(format!(
+            "Failed to list installed packages: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let rows: Vec<(String, String, Option<String>)> = serde_json::from_slice(&output.stdout)?;
+    let mut packages: Vec<SnapshotPackage> = rows
+        .into_iter()
+        .map(|(name, version, record_hash)| SnapshotPackage {
+            name,
+            version,
+            record_hash,
+        })
+        .collect();
+    packages.sort_by_key(|p| canonicalize_name(&p.name));
+    packages.dedup_by_key(|p| canonicalize_name(&p.name));
+    Ok(packages)
+}
+
+/// Captures the installed environment and the registry as a named snapshot
+///
+/// # Arguments
+/// * `name` - Snapshot name; an existing snapshot of that name is not overwritten
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<Snapshot>` - The saved snapshot
+pub fn create_snapshot(name: &str, registry: &PackageRegistry) -> Result<Snapshot> {
+    let path = snapshot_path(name)?;
+    if path.exists() {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "Snapshot {} already exists; delete it first to replace it",
+            name
+        )));
+    }
+
+    let snapshot = Snapshot {
+        name: name.to_string(),
+        created_at: unix_now(),
+        python_version: get_python_version(&get_python_executable()?)?,
+        packages: installed_snapshot_packages()?,
+        registry: registry.clone(),
+    };
+    std::fs::create_dir_all(snapshots_dir())?;
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, &snapshot)?;
+    Ok(snapshot)
+}
+
+/// Loads a snapshot by name
+///
+/// # Arguments
+/// * `name` - Snapshot name
+///
+/// # Returns
+/// * `Result<Snapshot>` - The snapshot, or [`PackageError::PackageNotFound`] if there is none of that name
+pub fn load_snapshot(name: &str) -> Result<Snapshot> {
+    let path = snapshot_path(name)?;
+    if !path.exists() {
+        return Err(PackageError::PackageNotFound(format!("snapshot {}", name)));
+    }
+    Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+}
+
+/// Lists the saved snapshots, oldest first
+///
+/// # Returns
+/// * `Result<Vec<Snapshot>>` - Readable snapshots; unreadable files are skipped with a warning
+pub fn list_snapshots() -> Result<Vec<Snapshot>> {
+    let entries = match std::fs::read_dir(snapshots_dir()) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut snapshots = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("json") {
+            continue;
+        }
+        match File::open(&path)
+            .map_err(PackageError::from)
+            .and_then(|file| Ok(serde_json::from_reader(BufReader::new(file))?))
+        {
+            Ok(snapshot) => snapshots.push(snapshot),
+            Err(e) => warning!("Skipping unreadable snapshot {}: {}", path.display(), e),
+        }
+    }
+    snapshots.sort_by(|a: &Snapshot, b| (a.created_at, &a.name).cmp(&(b.created_at, &b.name)));
+    Ok(snapshots)
+}
+
+/// Deletes a snapshot
+///
+/// # Arguments
+/// * `name` - Snapshot name
+///
+/// # Returns
+/// * `Result<()>` - Success or [`PackageError::PackageNotFound`] if there is no snapshot of that name
+pub fn delete_snapshot(name: &str) -> Result<()> {
+    let path = snapshot_path(name)?;
+    if !path.exists() {
+        return Err(PackageError::PackageNotFound(format!("snapshot {}", name)));
+    }
+    std::fs::remove_file(path)?;
+    Ok(())
+}
+
+/// Distributions a restore never uninstalls because ppm itself relies on them
+const RESTORE_PROTECTED: &[&str] = &["pip"];
+
+/// Changes that return an environment to a snapshot
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RestorePlan {
+    /// Packages to install at the snapshot version, either missing or at another version
+    pub install: Vec<VersionChange>,
+    /// Packages at the snapshot version whose installed files no longer match its hash
+    pub reinstall: Vec<DiffEntry>,
+    /// Packages installed since the snapshot was taken
+    pub uninstall: Vec<DiffEntry>,
+}
+
+impl RestorePlan {
+    /// Whether the environment already matches the snapshot
+    pub fn is_empty(&self) -> bool {
+        self.install.is_empty() && self.reinstall.is_empty() && self.uninstall.is_empty()
+    }
+}
+
+/// Computes the changes that return an environment to a snapshot
+///
+/// # Arguments
+/// * `snapshot` - The snapshot to restore
+/// * `current` - Distributions installed now, see [`installed_snapshot_packages`]
+///
+/// # Returns
+/// * `RestorePlan` - Installs (with `from` empty for missing packages), reinstalls and uninstalls
+pub fn plan_restore(snapshot: &Snapshot, current: &[SnapshotPackage]) -> RestorePlan {
+    let installed: HashMap<String, &SnapshotPackage> = current
+        .iter()
+        .map(|p| (canonicalize_name(&p.name), p))
+        .collect();
+    let wanted: HashSet<String> = snapshot
+        .packages
+        .iter()
+        .map(|p| canonicalize_name(&p.name))
+        .collect();
+
+    let mut plan = RestorePlan::default();
+    for package in &snapshot.packages {
+        match installed.get(&canonicalize_name(&package.name)) {
+            Some(now) if now.version == package.version => {
+                let rebuilt = matches!(
+                    (&now.record_hash, &package.record_hash),
+                    (Some(a), Some(b)) if a != b
+                );
+                if rebuilt {
+                    plan.reinstall.push(DiffEntry {
+                        name: package.name.clone(),
+                        version: package.version.clone(),
+                    });
+                }
+            }
+            now => plan.install.push(VersionChange {
+                name: package.name.clone(),
+                from: now.map(|p| p.version.clone()).unwrap_or_default(),
+                to: package.version.clone(),
+            }),
+        }
+    }
+    for package in current {
+        let key = canonicalize_name(&package.name);
+        if !wanted.contains(&key) && !RESTORE_PROTECTED.contains(&key.as_str()) {
+            plan.uninstall.push(DiffEntry {
+                name: package.name.clone(),
+                version: package.version.clone(),
+            });
+        }
+    }
+    plan
+}
+
+/// Renders a restore plan as `+`/`-`/`~`/`!` lines followed by a summary
+///
+/// # Arguments
+/// * `plan` - The plan to render
+///
+/// # Returns
+/// Uninstalls, version changes, installs and reinstalls, one per line
+pub fn render_restore_plan(plan: &RestorePlan) -> String {
+    if plan.is_empty() {
+        return "The environment already matches the snapshot\n".to_string();
+    }
+
+    let mut out = String::new();
+    for entry in &plan.uninstall {
+        let line = format!("- {} {}", entry.name, entry.version);
+        out.push_str(&format!("{}\n", console::style(line).red()));
+    }
+    for change in plan.install.iter().filter(|c| !c.from.is_empty()) {
+        let line = format!("~ {} {} -> {}", change.name, change.from, change.to);
+        out.push_str(&format!("{}\n", console::style(line).yellow()));
+    }
+    for change in plan.install.iter().filter(|c| c.from.is_empty()) {
+        let line = format!("+ {} {}", change.name, change.to);
+        out.push_str(&format!("{}\n", console::style(line).green()));
+    }
+    for entry in &plan.reinstall {
+        let line = format!(
+            "! {} {} (files changed, reinstalling)",
+            entry.name, entry.version
+        );
+        out.push_str(&format!("{}\n", console::style(line).yellow()));
+    }
+    out.push_str(&format!(
+        "\n{} to install, {} to reinstall, {} to uninstall\n",
+        plan.install.len(),
+        plan.reinstall.len(),
+        plan.uninstall.len()
+    ));
+    out
+}
+
+/// Applies a restore plan and puts back the registry recorded by the snapshot
+///
+/// Packages are installed with `--no-deps` because the snapshot already lists
+/// every distribution of the environment at its exact version.
+///
+/// # Arguments
+/// * `snapshot` - The snapshot being restored
+/// * `plan` - Changes computed by [`plan_restore`]
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or the first pip failure
+pub fn apply_restore(
+    snapshot: &Snapshot,
+    plan: &RestorePlan,
+    registry: &mut PackageRegistry,
+) -> Result<()> {
+    let python = get_python_executable()?;
+
+    if !plan.uninstall.is_empty() {
+        let names: Vec<&str> = plan.uninstall.iter().map(|e| e.name.as_str()).collect();
+        let started = Instant::now();
+        let mut command = Command::new(&python);
+        command
+            .arg("-m")
+            .arg("pip")
+            .arg("uninstall")
+            .arg("-y")
+            .args(&names);
+        let output = run_pip(command, "pip", None)?;
+        record_timing(&names.join(" "), Phase::Uninstall, started);
+        if !output.status.success() {
+            return Err(pip_error(
+                &names.join(" "),
+                &output,
+                PackageError::UninstallationFailed,
+            ));
+        }
+        for entry in &plan.uninstall {
+            record_removal(&entry.name, Some(entry.version.clone()));
+        }
+        status!("{} Removed {}", success_mark(), names.join(", "));
+    }
+
+    let batches = [
+        (
+            plan.install
+                .iter()
+                .map(|c| format!("{}=={}", c.name, c.to))
+                .collect::<Vec<_>>(),
+            false,
+        ),
+        (
+            plan.reinstall
+                .iter()
+                .map(|e| format!("{}=={}", e.name, e.version))
+                .collect(),
+            true,
+        ),
+    ];
+    for (specs, force) in batches {
+        if specs.is_empty() {
+            continue;
+        }
+        let started = Instant::now();
+        let mut command = Command::new(&python);
+        command.arg("-m").arg("pip").arg("install").arg("--no-deps");
+        if force {
+            command.arg("--force-reinstall");
+        }
+        command.args(&specs);
+        let output = run_pip(command, "pip", None)?;
+        record_timing(&specs.join(" "), Phase::Install, started);
+        if !output.status.success() {
+            return Err(pip_error(
+                &specs.join(" "),
+                &output,
+                PackageError::InstallationFailed,
+            ));
+        }
+        status!("{} Installed {}", success_mark(), specs.join(", "));
+    }
+
+    registry.packages = snapshot.registry.packages.clone();
+    Ok(())
+}
+
+/// Python snippet that prints `[name, version, sha256 of RECORD]` for every installed distribution
+const SNAPSHOT_SCRIPT: &str = r#"
+import hashlib, json, os
+from importlib import metadata
+local = os.environ.get("PPM_LOCAL_PACKAGES")
+rows = []
+for dist in metadata.distributions(**({"path": [local]} if local else {})):
+    record = dist.read_text("RECORD")
+    digest = hashlib.sha256(record.encode()).hexdigest() if record else None
+    rows.append([dist.metadata["Name"], dist.version, digest])
+print(json.dumps(rows))
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_restore() {
+        let package = |name: &str, version: &str, hash: &str| SnapshotPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            record_hash: Some(hash.to_string()),
+        };
+        let snapshot = Snapshot {
+            name: "before".to_string(),
+            created_at: 0,
+            python_version: "3.12.1".to_string(),
+            packages: vec![
+                package("Django", "4.2.11", "a"),
+                package("requests", "2.31.0", "b"),
+                package("six", "1.16.0", "c"),
+                package("pip", "24.0", "d"),
+            ],
+            registry: PackageRegistry::new(),
+        };
+        let current = vec![
+            package("django", "5.0.3", "x"),
+            package("requests", "2.31.0", "tampered"),
+            package("asgiref", "3.8.1", "y"),
+            package("pip", "24.0", "d"),
+            package("pip-audit", "2.7.0", "z"),
+        ];
+
+        let plan = plan_restore(&snapshot, &current);
+        let installs: Vec<(&str, &str, &str)> = plan
+            .install
+            .iter()
+            .map(|c| (c.name.as_str(), c.from.as_str(), c.to.as_str()))
+            .collect();
+        assert_eq!(
+            installs,
+            vec![("Django", "5.0.3", "4.2.11"), ("six", "", "1.16.0")]
+        );
+        assert_eq!(plan.reinstall.len(), 1);
+        assert_eq!(plan.reinstall[0].name, "requests");
+        let uninstalls: Vec<&str> = plan.uninstall.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(uninstalls, vec!["asgiref", "pip-audit"]);
+
+        let rendered = console::strip_ansi_codes(&render_restore_plan(&plan)).to_string();
+        assert!(rendered.contains("~ Django 5.0.3 -> 4.2.11\n"));
+        assert!(rendered.contains("+ six 1.16.0\n"));
+        assert!(rendered.ends_with("2 to install, 1 to reinstall, 2 to uninstall\n"));
+
+        assert!(snapshot_path("../escape").is_err());
+        assert!(snapshot_path("before-django-5").is_ok());
+    }
+}
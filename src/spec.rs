@@ -0,0 +1,498 @@
+//! Package names, requirement specifiers and version comparison
+
+use crate::{PackageError, Result};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+/// Normalizes a package name as described in PEP 503
+///
+/// # Arguments
+/// * `name` - The package name to normalize
+///
+/// # Returns
+/// The lowercase name with runs of `-`, `_` and `.` collapsed into a single `-`
+pub fn canonicalize_name(name: &str) -> String {
+    let mut canonical = String::with_capacity(name.len());
+    for c in name.trim().chars() {
+        if matches!(c, '-' | '_' | '.') {
+            if !canonical.ends_with('-') {
+                canonical.push('-');
+            }
+        } else {
+            canonical.extend(c.to_lowercase());
+        }
+    }
+    canonical
+}
+
+/// Name part of a requirement specifier such as `numpy[extra]>=1.26; python_version<"3.13"`
+pub fn requirement_name(spec: &str) -> &str {
+    if let Some(name) = vcs_url_name(spec) {
+        return name;
+    }
+    let end = spec
+        .find(|c: char| SPECIFIER_CHARS.contains(&c) || matches!(c, ';' | '[' | ' ' | '@' | '('))
+        .unwrap_or(spec.len());
+    spec[..end].trim()
+}
+
+/// Version control systems pip installs from, as the `git` in `git+https://…`
+const VCS_KINDS: [&str; 4] = ["git", "hg", "svn", "bzr"];
+
+/// A requirement installed from a version control repository, such as
+/// `git+https://github.com/org/pkg@main` or `pkg @ git+https://…@v1.2`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VcsRequirement {
+    /// Text before ` @ `, including any extras, when the spec names the package
+    pub name: Option<String>,
+    /// Version control system, e.g. "git"
+    pub kind: String,
+    /// Repository URL without the `git+` prefix, revision or fragment
+    pub url: String,
+    /// Branch, tag or commit after the last `@`
+    pub revision: Option<String>,
+    /// Fragment after `#`, such as `egg=pkg&subdirectory=src`
+    pub fragment: Option<String>,
+}
+
+impl VcsRequirement {
+    /// Parses a version control requirement
+    ///
+    /// # Arguments
+    /// * `spec` - Requirement string
+    ///
+    /// # Returns
+    /// * `Option<VcsRequirement>` - The parts of the spec, or None if it does
+    ///   not install from version control
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim();
+        let (name, location) = match spec.split_once('@') {
+            Some((name, location)) if !name.contains(':') => {
+                (Some(name.trim().to_string()), location.trim())
+            }
+            _ => (None, spec),
+        };
+        let (kind, rest) = location.split_once('+')?;
+        if !VCS_KINDS.contains(&kind) || !rest.contains("://") {
+            return None;
+        }
+        let (url, revision, fragment) = split_vcs_location(rest);
+        Some(VcsRequirement {
+            name,
+            kind: kind.to_string(),
+            url: url.to_string(),
+            revision: revision.map(str::to_string),
+            fragment: fragment.map(str::to_string),
+        })
+    }
+
+    /// Whether the revision is a full commit hash rather than a branch or tag
+    pub fn is_pinned(&self) -> bool {
+        self.revision.as_deref().is_some_and(|revision| {
+            matches!(revision.len(), 40 | 64) && revision.chars().all(|c| c.is_ascii_hexdigit())
+        })
+    }
+
+    /// The same requirement with its revision replaced by a commit
+    ///
+    /// # Arguments
+    /// * `commit` - Full commit hash to pin
+    pub fn at_commit(&self, commit: &str) -> String {
+        let mut location = format!("{}+{}@{}", self.kind, self.url, commit);
+        if let Some(fragment) = &self.fragment {
+            location.push('#');
+            location.push_str(fragment);
+        }
+        match &self.name {
+            Some(name) => format!("{} @ {}", name, location),
+            None => location,
+        }
+    }
+}
+
+/// Splits `https://host/repo.git@ref#egg=pkg` into its URL, revision and fragment
+///
+/// An `@` before the path, as in `ssh://git@host/repo`, belongs to the URL.
+fn split_vcs_location(location: &str) -> (&str, Option<&str>, Option<&str>) {
+    let (location, fragment) = match location.split_once('#') {
+        Some((location, fragment)) => (location, Some(fragment)),
+        None => (location, None),
+    };
+    let path_start = location.find("://").and_then(|scheme_end| {
+        location[scheme_end + 3..]
+            .find('/')
+            .map(|i| scheme_end + 3 + i)
+    });
+    match (location.rfind('@'), path_start) {
+        (Some(at), Some(path_start)) if at > path_start => {
+            (&location[..at], Some(&location[at + 1..]), fragment)
+        }
+        _ => (location, None, fragment),
+    }
+}
+
+/// Package name of a bare version control URL: its `#egg=` fragment, or else
+/// the last path segment without `.git`
+fn vcs_url_name(spec: &str) -> Option<&str> {
+    let spec = spec.trim();
+    let (kind, rest) = spec.split_once('+')?;
+    if !VCS_KINDS.contains(&kind) || !rest.contains("://") {
+        return None;
+    }
+    let (url, _, fragment) = split_vcs_location(rest);
+    let egg = fragment.and_then(|fragment| {
+        fragment
+            .split('&')
+            .find_map(|part| part.strip_prefix("egg="))
+    });
+    let name = egg.unwrap_or_else(|| {
+        let segment = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+        segment.strip_suffix(".git").unwrap_or(segment)
+    });
+    Some(name.trim())
+}
+
+/// Prepares package specifications for pip installation
+pub(crate) fn prepare_package_specs(packages: &[String]) -> Result<Vec<String>> {
+    packages
+        .iter()
+        .map(|pkg| {
+            let (name, version) = parse_package_spec(pkg)?;
+            Ok(version.map_or(name.clone(), |v| format!("{}=={}", name, v)))
+        })
+        .collect()
+}
+
+/// Checks whether an installed version satisfies a requirement spec
+///
+/// Specs with environment markers or direct URLs are never treated as satisfied.
+pub(crate) fn spec_is_satisfied(spec: &str, installed: &HashMap<String, String>) -> bool {
+    if spec.contains([';', '@']) {
+        return false;
+    }
+    let name = requirement_name(spec);
+    let Some(version) = installed.get(&canonicalize_name(name)) else {
+        return false;
+    };
+    let rest = spec[name.len()..].trim_start();
+    let specifiers = match rest.strip_prefix('[') {
+        Some(extras) => extras.split_once(']').map_or("", |(_, s)| s),
+        None => rest,
+    };
+    version_satisfies(version, specifiers)
+}
+
+/// Matches text against a glob pattern supporting `*`, `?` and `[...]` classes
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    fn match_class(class: &[char], c: char) -> bool {
+        let (negated, class) = match class.first() {
+            Some('!') | Some('^') => (true, &class[1..]),
+            _ => (false, class),
+        };
+        let mut i = 0;
+        let mut found = false;
+        while i < class.len() {
+            if i + 2 < class.len() && class[i + 1] == '-' {
+                found |= class[i] <= c && c <= class[i + 2];
+                i += 3;
+            } else {
+                found |= class[i] == c;
+                i += 1;
+            }
+        }
+        found != negated
+    }
+
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern.first() {
+            None => text.is_empty(),
+            Some('*') => (0..=text.len()).any(|skip| matches(&pattern[1..], &text[skip..])),
+            Some('?') => !text.is_empty() && matches(&pattern[1..], &text[1..]),
+            Some('[') => match pattern.iter().position(|&c| c == ']') {
+                Some(end) if end > 1 => {
+                    !text.is_empty()
+                        && match_class(&pattern[1..end], text[0])
+                        && matches(&pattern[end + 1..], &text[1..])
+                }
+                _ => text.first() == Some(&'[') && matches(&pattern[1..], &text[1..]),
+            },
+            Some(&c) => text.first() == Some(&c) && matches(&pattern[1..], &text[1..]),
+        }
+    }
+
+    matches(&pattern, &text)
+}
+
+/// Characters that start a version specifier operator
+pub(crate) const SPECIFIER_CHARS: [char; 5] = ['<', '>', '=', '!', '~'];
+
+/// Checks whether a version argument is a specifier set rather than an exact version
+pub(crate) fn is_version_specifier(version: &str) -> bool {
+    version.trim_start().starts_with(SPECIFIER_CHARS) || version.contains(['*', ','])
+}
+
+/// Normalizes a version argument so exact pins are stored without their operator
+///
+/// `==1.2.3` becomes `1.2.3`, while ranges and wildcards such as `==1.2.*` are
+/// kept (with an explicit operator) as specifier sets.
+pub(crate) fn normalize_update_version(version: &str) -> String {
+    let version = version.trim();
+    match version.strip_prefix("==") {
+        Some(exact) if !exact.contains(['*', ',']) => exact.trim().to_string(),
+        _ if version.starts_with(|c: char| c.is_ascii_digit()) && version.contains('*') => {
+            format!("=={}", version)
+        }
+        _ => version.to_string(),
+    }
+}
+
+/// Checks whether a version is a pre-release or development release
+pub fn is_prerelease(version: &str) -> bool {
+    split_version(version).1
+}
+
+/// Checks a version against a comma-separated PEP 440 specifier set
+///
+/// Supports `==`, `!=`, `<`, `<=`, `>`, `>=`, `~=` and `===`, including
+/// `.*` wildcards for `==` and `!=`. Unparseable clauses are treated as satisfied.
+pub fn version_satisfies(version: &str, specifiers: &str) -> bool {
+    specifiers
+        .split(',')
+        .map(str::trim)
+        .filter(|clause| !clause.is_empty())
+        .all(|clause| {
+            let operator_len = clause
+                .find(|c: char| !matches!(c, '=' | '!' | '<' | '>' | '~'))
+                .unwrap_or(clause.len());
+            let (operator, target) = clause.split_at(operator_len);
+            let target = target.trim();
+
+            if let Some(prefix) = target.strip_suffix(".*") {
+                let matches_prefix = compare_versions(
+                    &version
+                        .split('.')
+                        .take(prefix.split('.').count())
+                        .collect::<Vec<_>>()
+                        .join("."),
+                    prefix,
+                ) == Ordering::Equal;
+                return match operator {
+                    "==" => matches_prefix,
+                    "!=" => !matches_prefix,
+                    _ => true,
+                };
+            }
+
+            let ordering = compare_versions(version, target);
+            match operator {
+                "==" => ordering == Ordering::Equal,
+                "===" => version == target,
+                "!=" => ordering != Ordering::Equal,
+                "<" => ordering == Ordering::Less,
+                "<=" => ordering != Ordering::Greater,
+                ">" => ordering == Ordering::Greater,
+                ">=" => ordering != Ordering::Less,
+                "~=" => {
+                    let mut prefix: Vec<&str> = target.split('.').collect();
+                    if prefix.len() > 1 {
+                        prefix.pop();
+                    }
+                    ordering != Ordering::Less
+                        && version_satisfies(version, &format!("=={}.*", prefix.join(".")))
+                }
+                _ => true,
+            }
+        })
+}
+
+/// Splits a version into its numeric release segments and a pre-release flag
+///
+/// Trailing zero segments are dropped so that "1.0" and "1.0.0" compare equal.
+/// Local version labels are ignored and post-releases count as final releases.
+pub(crate) fn split_version(version: &str) -> (Vec<u64>, bool) {
+    let version = version.trim().trim_start_matches('v');
+    let version = version
+        .split_once('+')
+        .map_or(version, |(public, _)| public);
+    let mut release = Vec::new();
+    let mut suffix = "";
+    for part in version.split('.') {
+        let digits = part
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(part.len());
+        if digits > 0 {
+            release.push(part[..digits].parse().unwrap_or(0));
+        }
+        if digits < part.len() {
+            suffix = &part[digits..];
+            break;
+        }
+    }
+    while release.last() == Some(&0) {
+        release.pop();
+    }
+    let pre_release = !suffix.is_empty()
+        && !suffix
+            .trim_start_matches(['-', '_'])
+            .to_lowercase()
+            .starts_with("post");
+    (release, pre_release)
+}
+
+/// Compares two version strings
+///
+/// Release segments are compared numerically; when they are equal, a version
+/// carrying a pre-release or development suffix sorts before the final release.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (release_a, pre_a) = split_version(a);
+    let (release_b, pre_b) = split_version(b);
+    release_a.cmp(&release_b).then_with(|| pre_b.cmp(&pre_a))
+}
+
+/// Parses a package specification into name and optional version
+///
+/// Supports formats like "package" or "package==1.0.0"
+///
+/// # Arguments
+/// * `spec` - Package specification string
+///
+/// # Returns
+/// * `Result<(String, Option<String>)>` - Package name and optional version
+pub fn parse_package_spec(spec: &str) -> Result<(String, Option<String>)> {
+    let spec = spec.trim();
+
+    if spec.is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "Empty package specification".to_string(),
+        ));
+    }
+
+    let parts: Vec<&str> = spec.splitn(2, "==").collect();
+    match parts.as_slice() {
+        [name, version] => {
+            let name = name.trim();
+            let version = version.trim();
+
+            if name.is_empty() || version.is_empty() {
+                return Err(PackageError::InvalidPackageSpec(format!(
+                    "Invalid package specification: {}",
+                    spec
+                )));
+            }
+
+            Ok((name.to_string(), Some(version.to_string())))
+        }
+        [name] => {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(PackageError::InvalidPackageSpec(
+                    "Empty package name".to_string(),
+                ));
+            }
+            Ok((name.to_string(), None))
+        }
+        _ => Err(PackageError::InvalidPackageSpec(format!(
+            "Invalid package specification: {}",
+            spec
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_package_spec_with_version() {
+        let result = parse_package_spec("numpy==1.21.0").unwrap();
+        assert_eq!(result, ("numpy".to_string(), Some("1.21.0".to_string())));
+    }
+
+    #[test]
+    fn test_parse_package_spec_without_version() {
+        let result = parse_package_spec("requests").unwrap();
+        assert_eq!(result, ("requests".to_string(), None));
+    }
+
+    #[test]
+    fn test_parse_package_spec_invalid() {
+        assert!(parse_package_spec("").is_err());
+        assert!(parse_package_spec("==1.0.0").is_err());
+        assert!(parse_package_spec("package==").is_err());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("boto3*", "boto3"));
+        assert!(glob_match("boto3*", "boto3-stubs"));
+        assert!(!glob_match("boto3*", "botocore"));
+        assert!(glob_match("py?aml", "pyyaml"));
+        assert!(glob_match("[fd]jango*", "django-cors"));
+        assert!(!glob_match("[!fd]jango", "django"));
+    }
+
+    #[test]
+    fn test_version_satisfies() {
+        assert!(version_satisfies("3.11.7", ">=3.8"));
+        assert!(!version_satisfies("3.7.1", ">=3.8"));
+        assert!(version_satisfies("3.11.7", ">=3.8, !=3.9.*, <4"));
+        assert!(!version_satisfies("3.9.2", ">=3.8, !=3.9.*"));
+        assert!(version_satisfies("1.26.4", "~=1.26"));
+        assert!(!version_satisfies("2.0.0", "~=1.26"));
+        assert!(version_satisfies("1.4.2", "~=1.4.0"));
+        assert!(!version_satisfies("1.5.0", "~=1.4.0"));
+    }
+
+    #[test]
+    fn test_is_prerelease() {
+        assert!(is_prerelease("2.0.0rc1"));
+        assert!(is_prerelease("1.0a2"));
+        assert!(is_prerelease("1.0.dev3"));
+        assert!(!is_prerelease("2.32.3"));
+        assert!(!is_prerelease("1.0.post1"));
+    }
+
+    #[test]
+    fn test_vcs_requirement_parse() {
+        let vcs =
+            VcsRequirement::parse("git+ssh://git@github.com/org/tool.git@v1.2#egg=tool").unwrap();
+        assert_eq!(vcs.name, None);
+        assert_eq!(vcs.url, "ssh://git@github.com/org/tool.git");
+        assert_eq!(vcs.revision.as_deref(), Some("v1.2"));
+        assert!(!vcs.is_pinned());
+        assert_eq!(
+            vcs.at_commit(&"a".repeat(40)),
+            format!(
+                "git+ssh://git@github.com/org/tool.git@{}#egg=tool",
+                "a".repeat(40)
+            )
+        );
+
+        let named = VcsRequirement::parse("tool[cli] @ git+https://example.com/tool").unwrap();
+        assert_eq!(named.name.as_deref(), Some("tool[cli]"));
+        assert_eq!(named.revision, None);
+        assert!(VcsRequirement::parse("tool @ https://example.com/tool.whl").is_none());
+        assert!(VcsRequirement::parse("requests>=2").is_none());
+
+        assert_eq!(
+            requirement_name("git+https://github.com/org/my-tool.git@main"),
+            "my-tool"
+        );
+        assert_eq!(
+            requirement_name("git+https://example.com/repo#egg=tool"),
+            "tool"
+        );
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("2.32.3", "2.31.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.10", "1.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("2.0rc1", "2.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.0.post1", "1.0"), Ordering::Equal);
+    }
+}
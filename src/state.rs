@@ -0,0 +1,40 @@
+//! Locations of the local state kept in `.ppm` next to the registry
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Directory holding the tool's local state next to the registry
+pub(crate) const STATE_DIR: &str = ".ppm";
+
+/// Path of the metadata cache file
+pub(crate) fn metadata_cache_path() -> PathBuf {
+    Path::new(STATE_DIR).join("metadata.json")
+}
+
+/// Directory of cached PyPI responses, one file per project or release
+pub(crate) fn response_cache_dir() -> PathBuf {
+    Path::new(STATE_DIR).join("cache").join("pypi")
+}
+
+/// Directory of cached organization policy documents, see [`crate::policy::load_organization_policy`]
+pub(crate) fn policy_cache_dir() -> PathBuf {
+    Path::new(STATE_DIR).join("cache").join("policy")
+}
+
+/// Path of the append-only operation history, one JSON entry per line
+pub(crate) fn operation_history_path() -> PathBuf {
+    Path::new(STATE_DIR).join("history.jsonl")
+}
+
+/// Path of the stats history file
+pub(crate) fn stats_history_path() -> PathBuf {
+    Path::new(STATE_DIR).join("stats.json")
+}
+
+/// Current time as a Unix timestamp in seconds
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
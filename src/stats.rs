@@ -0,0 +1,322 @@
+//! Timing operations and the per-command run statistics
+
+use crate::state::{stats_history_path, unix_now};
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Kind of work measured for a package
+///
+/// pip resolves, downloads and installs within a single process, so those
+/// steps are measured together as one install, update or uninstall phase,
+/// except for parallel installs, which download up front. Resolution covers
+/// the PyPI lookups the tool performs itself.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// Looking up the version to install on PyPI
+    Resolve,
+    /// Downloading a package and its dependencies ahead of a parallel install
+    Download,
+    /// Downloading and installing a package with pip
+    Install,
+    /// Upgrading or downgrading a package with pip
+    Update,
+    /// Removing a package with pip
+    Uninstall,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let label = match self {
+            Phase::Resolve => "resolve",
+            Phase::Download => "download",
+            Phase::Install => "install",
+            Phase::Update => "update",
+            Phase::Uninstall => "uninstall",
+        };
+        write!(f, "{}", label)
+    }
+}
+
+/// Duration of one operation on one package (or one batch of packages)
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct OperationTiming {
+    /// Package name, or space-separated names for a batch handled by one pip call
+    pub target: String,
+    /// Kind of work that was measured
+    pub phase: Phase,
+    /// Wall-clock duration in milliseconds
+    pub millis: u64,
+}
+
+/// Timings collected during a single invocation of the tool
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct RunStats {
+    /// Command that was run, e.g. "install --parallel"
+    pub command: String,
+    /// Unix timestamp of the start of the run
+    pub started_at: u64,
+    /// Wall-clock duration of the whole run in milliseconds
+    pub total_millis: u64,
+    /// Individual operations performed during the run
+    pub timings: Vec<OperationTiming>,
+}
+
+/// History of recorded runs, stored in `.ppm/stats.json`
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct StatsHistory {
+    /// Recorded runs, oldest first
+    pub runs: Vec<RunStats>,
+}
+
+impl StatsHistory {
+    /// Loads the history from `.ppm/stats.json`, returning an empty history if it is missing or unreadable
+    pub fn load() -> Self {
+        File::open(stats_history_path())
+            .ok()
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).ok())
+            .unwrap_or_default()
+    }
+
+    /// Saves the history to `.ppm/stats.json`
+    pub fn save(&self) -> Result<()> {
+        let path = stats_history_path();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Appends a run, discarding the oldest runs beyond [`MAX_RECORDED_RUNS`]
+    ///
+    /// # Arguments
+    /// * `run` - The run to record
+    pub fn push(&mut self, run: RunStats) {
+        self.runs.push(run);
+        let excess = self.runs.len().saturating_sub(MAX_RECORDED_RUNS);
+        self.runs.drain(..excess);
+    }
+}
+
+/// Number of runs kept in the stats history
+pub const MAX_RECORDED_RUNS: usize = 100;
+
+/// Saves the operations timed during this invocation to the stats history
+///
+/// Runs that performed no timed operation (such as `list`) are not recorded.
+///
+/// # Arguments
+/// * `command` - Label of the command that was run, e.g. "install --parallel"
+/// * `elapsed` - Wall-clock duration of the whole run
+///
+/// # Returns
+/// * `Result<()>` - Success or error while saving the history
+pub fn record_run(command: &str, elapsed: Duration) -> Result<()> {
+    let timings = std::mem::take(&mut *RECORDED_TIMINGS.lock().unwrap());
+    if timings.is_empty() {
+        return Ok(());
+    }
+
+    let mut history = StatsHistory::load();
+    history.push(RunStats {
+        command: command.to_string(),
+        started_at: unix_now().saturating_sub(elapsed.as_secs()),
+        total_millis: elapsed.as_millis() as u64,
+        timings,
+    });
+    history.save()
+}
+
+/// Renders the last run and historical averages of a stats history
+///
+/// # Arguments
+/// * `history` - The recorded runs
+/// * `now` - Current Unix timestamp, used to show how long ago the last run was
+///
+/// # Returns
+/// The rendered report, terminated by a newline
+pub fn render_stats(history: &StatsHistory, now: u64) -> String {
+    let Some(last) = history.runs.last() else {
+        return "No operations recorded yet\n".to_string();
+    };
+
+    let mut out = format!(
+        "Last run: {} ({} ago), total {}\n",
+        last.command,
+        format_duration(now.saturating_sub(last.started_at) * 1000),
+        format_duration(last.total_millis)
+    );
+
+    let mut phase_totals: Vec<(Phase, u64)> = Vec::new();
+    for timing in &last.timings {
+        match phase_totals.iter_mut().find(|(p, _)| *p == timing.phase) {
+            Some((_, total)) => *total += timing.millis,
+            None => phase_totals.push((timing.phase, timing.millis)),
+        }
+    }
+    phase_totals.sort();
+    for (phase, millis) in &phase_totals {
+        out.push_str(&format!(
+            "  {:<10} {:>10}\n",
+            phase.to_string(),
+            format_duration(*millis)
+        ));
+    }
+
+    let mut slowest: Vec<&OperationTiming> = last.timings.iter().collect();
+    slowest.sort_by_key(|timing| std::cmp::Reverse(timing.millis));
+    out.push_str("  Slowest operations:\n");
+    for timing in slowest.iter().take(5) {
+        out.push_str(&format!(
+            "    {:<30} {:<10} {:>10}\n",
+            timing.target,
+            timing.phase.to_string(),
+            format_duration(timing.millis)
+        ));
+    }
+
+    // Average wall-clock time per command, which shows the benefit of --parallel
+    let mut commands: Vec<(&str, u64, u64)> = Vec::new();
+    for run in &history.runs {
+        match commands.iter_mut().find(|(c, _, _)| *c == run.command) {
+            Some((_, count, total)) => {
+                *count += 1;
+                *total += run.total_millis;
+            }
+            None => commands.push((&run.command, 1, run.total_millis)),
+        }
+    }
+    commands.sort();
+    out.push_str(&format!("\nHistory ({} runs):\n", history.runs.len()));
+    for (command, count, total) in &commands {
+        out.push_str(&format!(
+            "  {:<24} {:>4} run(s)  avg {:>10}\n",
+            command,
+            count,
+            format_duration(total / count)
+        ));
+    }
+
+    // Average time per package, to spot packages that are consistently slow
+    let mut packages: Vec<((&str, Phase), u64, u64)> = Vec::new();
+    for timing in history.runs.iter().flat_map(|run| &run.timings) {
+        let key = (timing.target.as_str(), timing.phase);
+        match packages.iter_mut().find(|(k, _, _)| *k == key) {
+            Some((_, count, total)) => {
+                *count += 1;
+                *total += timing.millis;
+            }
+            None => packages.push((key, 1, timing.millis)),
+        }
+    }
+    packages.sort_by(|a, b| (b.2 / b.1).cmp(&(a.2 / a.1)).then_with(|| a.0.cmp(&b.0)));
+    out.push_str("  Slowest packages on average:\n");
+    for ((target, phase), count, total) in packages.iter().take(5) {
+        out.push_str(&format!(
+            "    {:<30} {:<10} avg {:>10} ({} sample(s))\n",
+            target,
+            phase.to_string(),
+            format_duration(total / count),
+            count
+        ));
+    }
+
+    out
+}
+
+/// Formats a duration given in milliseconds
+///
+/// # Arguments
+/// * `millis` - Duration in milliseconds
+///
+/// # Returns
+/// A human-readable duration such as "850 ms", "12.3 s" or "2m 05s"
+pub fn format_duration(millis: u64) -> String {
+    if millis < 1000 {
+        format!("{} ms", millis)
+    } else if millis < 60_000 {
+        format!("{:.1} s", millis as f64 / 1000.0)
+    } else {
+        let secs = millis / 1000;
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    }
+}
+
+/// Operations timed during the current invocation, saved by [`record_run`]
+static RECORDED_TIMINGS: Mutex<Vec<OperationTiming>> = Mutex::new(Vec::new());
+
+/// Records how long an operation on a package took
+pub(crate) fn record_timing(target: &str, phase: Phase, started: Instant) {
+    RECORDED_TIMINGS.lock().unwrap().push(OperationTiming {
+        target: target.to_string(),
+        phase,
+        millis: started.elapsed().as_millis() as u64,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats_history_is_capped() {
+        let mut history = StatsHistory::default();
+        for i in 0..MAX_RECORDED_RUNS + 5 {
+            history.push(RunStats {
+                command: format!("install {}", i),
+                started_at: 0,
+                total_millis: 0,
+                timings: Vec::new(),
+            });
+        }
+        assert_eq!(history.runs.len(), MAX_RECORDED_RUNS);
+        assert_eq!(history.runs[0].command, "install 5");
+    }
+
+    #[test]
+    fn test_render_stats() {
+        let timing = |target: &str, phase, millis| OperationTiming {
+            target: target.to_string(),
+            phase,
+            millis,
+        };
+        let history = StatsHistory {
+            runs: vec![
+                RunStats {
+                    command: "install".to_string(),
+                    started_at: 1_000,
+                    total_millis: 9_000,
+                    timings: vec![timing("numpy", Phase::Install, 8_000)],
+                },
+                RunStats {
+                    command: "install --parallel".to_string(),
+                    started_at: 2_000,
+                    total_millis: 4_000,
+                    timings: vec![
+                        timing("numpy", Phase::Install, 3_800),
+                        timing("six", Phase::Install, 400),
+                    ],
+                },
+            ],
+        };
+
+        let report = render_stats(&history, 2_060);
+        assert!(report.starts_with("Last run: install --parallel (1m 00s ago), total 4.0 s\n"));
+        assert!(report.contains("  install         4.2 s\n"));
+        assert!(report.contains("install --parallel          1 run(s)  avg      4.0 s"));
+        assert!(report
+            .contains("numpy                          install    avg      5.9 s (2 sample(s))"));
+        assert_eq!(format_duration(850), "850 ms");
+        assert_eq!(
+            render_stats(&StatsHistory::default(), 0),
+            "No operations recorded yet\n"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+//! Suggestions for misspelled and look-alike package names
+
+use crate::registry::PackageRegistry;
+use crate::spec::{canonicalize_name, requirement_name};
+use std::collections::HashSet;
+
+/// Widely used PyPI packages checked when suggesting corrections for misspelled names
+pub(crate) const POPULAR_PACKAGES: &[&str] = &[
+    "aiohttp",
+    "alembic",
+    "anyio",
+    "apache-airflow",
+    "argparse",
+    "arrow",
+    "attrs",
+    "babel",
+    "beautifulsoup4",
+    "black",
+    "bleach",
+    "boto3",
+    "botocore",
+    "cachetools",
+    "celery",
+    "certifi",
+    "cffi",
+    "chardet",
+    "charset-normalizer",
+    "click",
+    "colorama",
+    "coverage",
+    "cryptography",
+    "cython",
+    "dask",
+    "decorator",
+    "django",
+    "djangorestframework",
+    "docker",
+    "docutils",
+    "elasticsearch",
+    "fastapi",
+    "filelock",
+    "flake8",
+    "flask",
+    "fsspec",
+    "gevent",
+    "gunicorn",
+    "h5py",
+    "httpx",
+    "idna",
+    "importlib-metadata",
+    "ipython",
+    "isort",
+    "jinja2",
+    "jmespath",
+    "joblib",
+    "jsonschema",
+    "jupyter",
+    "keras",
+    "kombu",
+    "lightgbm",
+    "lxml",
+    "markdown",
+    "markupsafe",
+    "matplotlib",
+    "mock",
+    "mypy",
+    "networkx",
+    "nltk",
+    "numba",
+    "numpy",
+    "oauthlib",
+    "openai",
+    "opencv-python",
+    "openpyxl",
+    "packaging",
+    "pandas",
+    "paramiko",
+    "pendulum",
+    "pillow",
+    "pip",
+    "platformdirs",
+    "plotly",
+    "pluggy",
+    "poetry",
+    "protobuf",
+    "psutil",
+    "psycopg2",
+    "psycopg2-binary",
+    "pyarrow",
+    "pycparser",
+    "pydantic",
+    "pygments",
+    "pyjwt",
+    "pylint",
+    "pymongo",
+    "pymysql",
+    "pyopenssl",
+    "pyparsing",
+    "pytest",
+    "pytest-cov",
+    "python-dateutil",
+    "python-dotenv",
+    "pytz",
+    "pyyaml",
+    "pyzmq",
+    "redis",
+    "regex",
+    "requests",
+    "requests-oauthlib",
+    "rich",
+    "s3transfer",
+    "scikit-learn",
+    "scipy",
+    "scrapy",
+    "seaborn",
+    "selenium",
+    "setuptools",
+    "simplejson",
+    "six",
+    "sqlalchemy",
+    "statsmodels",
+    "sympy",
+    "tensorflow",
+    "toml",
+    "tomli",
+    "torch",
+    "torchvision",
+    "tornado",
+    "tqdm",
+    "transformers",
+    "typer",
+    "typing-extensions",
+    "ujson",
+    "urllib3",
+    "uvicorn",
+    "virtualenv",
+    "websocket-client",
+    "werkzeug",
+    "wheel",
+    "wrapt",
+    "xgboost",
+    "xlrd",
+    "yarl",
+    "zipp",
+];
+
+/// Suggests package names close to a misspelled one
+///
+/// Candidates are compared by edit distance, counting a swap of two adjacent
+/// characters as one edit, after PEP 503 normalization.
+///
+/// # Arguments
+/// * `name` - The name that was not found
+/// * `candidates` - Extra names to consider besides the bundled popular packages,
+///   e.g. the registry's packages
+///
+/// # Returns
+/// * `Vec<String>` - Up to three closest names, nearest first
+pub fn suggest_package_names<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Vec<String> {
+    let target = canonicalize_name(name);
+    let max_distance = match target.chars().count() {
+        0..=3 => return Vec::new(),
+        4..=7 => 1,
+        8..=12 => 2,
+        _ => 3,
+    };
+
+    let mut seen = HashSet::new();
+    let mut scored: Vec<(usize, String)> = candidates
+        .into_iter()
+        .chain(POPULAR_PACKAGES.iter().copied())
+        .filter(|candidate| seen.insert(canonicalize_name(candidate)))
+        .filter_map(|candidate| {
+            let canonical = canonicalize_name(candidate);
+            if canonical == target {
+                return None;
+            }
+            let distance = edit_distance(&target, &canonical);
+            (distance <= max_distance).then(|| (distance, candidate.to_string()))
+        })
+        .collect();
+    scored.sort();
+    scored.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+/// Finds the popular package a name could be a typosquat of
+///
+/// Names of five or more characters that are one edit (two from nine
+/// characters on) away from a bundled popular package, without being that
+/// package, are suspicious: `reqeusts` and `numpyy` are, `numpy` and `boto`
+/// are not.
+///
+/// # Arguments
+/// * `name` - The package name
+///
+/// # Returns
+/// * `Option<&'static str>` - The nearest popular package, if the name is that close to one
+pub fn similar_popular_package(name: &str) -> Option<&'static str> {
+    let target = canonicalize_name(name);
+    let max_distance = match target.chars().count() {
+        0..=4 => return None,
+        5..=8 => 1,
+        _ => 2,
+    };
+    if POPULAR_PACKAGES.contains(&target.as_str()) {
+        return None;
+    }
+    POPULAR_PACKAGES
+        .iter()
+        .map(|popular| (edit_distance(&target, popular), *popular))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, popular)| popular)
+}
+
+/// Finds the packages about to be installed whose names imitate popular ones
+///
+/// Packages already in the registry were vetted when they were first
+/// installed and are not reported again.
+///
+/// # Arguments
+/// * `specs` - Requirement specifiers, e.g. "reqeusts>=2"
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Vec<(String, &'static str)>` - Each suspicious name with the popular package it resembles
+pub fn find_similar_names(
+    specs: &[String],
+    registry: &PackageRegistry,
+) -> Vec<(String, &'static str)> {
+    let mut found: Vec<(String, &'static str)> = Vec::new();
+    for spec in specs {
+        let name = requirement_name(spec.trim());
+        if name.is_empty()
+            || registry.find_package_key(name).is_some()
+            || found.iter().any(|(seen, _)| seen == name)
+        {
+            continue;
+        }
+        if let Some(popular) = similar_popular_package(name) {
+            found.push((name.to_string(), popular));
+        }
+    }
+    found
+}
+
+/// Optimal string alignment distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut rows = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in rows[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut best = (rows[i - 1][j] + 1)
+                .min(rows[i][j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                best = best.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = best;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// Formats name suggestions as a "did you mean" phrase
+///
+/// # Returns
+/// * `Option<String>` - e.g. "did you mean requests or httpx?", or None without suggestions
+pub fn did_you_mean(suggestions: &[String]) -> Option<String> {
+    match suggestions {
+        [] => None,
+        [only] => Some(format!("did you mean {}?", only)),
+        [init @ .., last] => Some(format!("did you mean {} or {}?", init.join(", "), last)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::diagnose_pip_output;
+    use crate::registry::Package;
+
+    #[test]
+    fn test_suggest_package_names() {
+        assert_eq!(suggest_package_names("resquests", []), vec!["requests"]);
+        assert_eq!(suggest_package_names("Numpi", []), vec!["numpy"]);
+        assert_eq!(suggest_package_names("reqeusts", []), vec!["requests"]);
+        assert_eq!(
+            suggest_package_names("my_intenal-lib", ["my-internal-lib"]),
+            vec!["my-internal-lib"]
+        );
+        assert!(suggest_package_names("requests", []).is_empty());
+        assert!(suggest_package_names("six", []).is_empty());
+        assert!(suggest_package_names("completely-unrelated", []).is_empty());
+
+        assert_eq!(
+            did_you_mean(&["a".to_string(), "b".to_string(), "c".to_string()]).unwrap(),
+            "did you mean a, b or c?"
+        );
+        let diagnostic =
+            diagnose_pip_output("ERROR: No matching distribution found for resquests").unwrap();
+        assert_eq!(
+            diagnostic.summary,
+            "No release matches resquests (did you mean requests?)"
+        );
+    }
+
+    #[test]
+    fn test_similar_popular_package() {
+        assert_eq!(similar_popular_package("reqeusts"), Some("requests"));
+        assert_eq!(similar_popular_package("Numpyy"), Some("numpy"));
+        assert_eq!(
+            similar_popular_package("python_dateutils"),
+            Some("python-dateutil")
+        );
+        assert_eq!(similar_popular_package("numpy"), None);
+        assert_eq!(similar_popular_package("boto"), None);
+        assert_eq!(similar_popular_package("my-internal-tool"), None);
+
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("Pyaml".to_string(), "24.4.0".to_string()));
+        let specs = [
+            "reqeusts>=2".to_string(),
+            "pyaml".to_string(),
+            "flask[async]".to_string(),
+            "reqeusts".to_string(),
+        ];
+        assert_eq!(
+            find_similar_names(&specs, &registry),
+            vec![("reqeusts".to_string(), "requests")]
+        );
+    }
+}
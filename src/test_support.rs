@@ -0,0 +1,42 @@
+//! Helpers shared by the unit tests
+
+use crate::pip::{set_command_runner, ScriptedRunner, COMMAND_RUNNER};
+use crate::reporter::{MessageKind, Reporter};
+use std::sync::{Arc, Mutex};
+
+/// Serializes the tests that install a command runner, which is process-wide
+pub(crate) static RUNNER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Runs `test` with `runner` answering every pip and interpreter invocation
+pub(crate) fn with_runner<T>(runner: ScriptedRunner, test: impl FnOnce(&ScriptedRunner) -> T) -> T {
+    let _guard = RUNNER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let runner = Arc::new(runner);
+    set_command_runner(runner.clone());
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| test(&runner)));
+    *COMMAND_RUNNER.lock().unwrap() = None;
+    result.unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+}
+
+/// A runner that finds an interpreter at /venv/bin/python
+pub(crate) fn python_runner() -> ScriptedRunner {
+    ScriptedRunner::new().respond("print(sys.executable)", "/venv/bin/python\n")
+}
+
+/// Reporter that records every message and output; `None` marks output
+#[derive(Default)]
+pub(crate) struct RecordingReporter {
+    pub(crate) shown: Mutex<Vec<(Option<MessageKind>, String)>>,
+}
+
+impl Reporter for RecordingReporter {
+    fn message(&self, kind: MessageKind, text: &str) {
+        self.shown
+            .lock()
+            .unwrap()
+            .push((Some(kind), text.to_string()));
+    }
+
+    fn output(&self, text: &str) {
+        self.shown.lock().unwrap().push((None, text.to_string()));
+    }
+}
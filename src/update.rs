@@ -0,0 +1,977 @@
+//! Updating, bumping and pinning installed packages
+
+use crate::install::{
+    dry_run_install, install_packages, par_items, track_dependencies, InstallOptions, TimedResult,
+};
+use crate::observer::{install_observer, observe_package_done, InstallObserver};
+use crate::outcome::{registered_version, InstallReport, OperationOutcome};
+use crate::pip::{
+    get_installed_version, get_python_executable, get_python_version, pip_error, run_pip,
+};
+use crate::registry::{Package, PackageRegistry};
+use crate::remove::{delete_packages, DependentsPolicy};
+use crate::reporter::success_mark;
+use crate::requirements::{
+    add_pyproject_dependency, add_requirement, bump_pyproject, bump_requirements,
+    remove_pyproject_dependency, remove_requirement, SpecEdit,
+};
+use crate::session::{emit_event, package_finished_event, package_started_event};
+use crate::spec::{
+    canonicalize_name, is_version_specifier, normalize_update_version, parse_package_spec,
+    requirement_name, split_version, VcsRequirement, SPECIFIER_CHARS,
+};
+use crate::stats::{record_timing, Phase};
+use crate::upgrade::{resolve_latest_version, UpgradePlan};
+use crate::{PackageError, Result};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Instant;
+
+/// Updates packages to specific versions as described by an [`InstallOptions`]
+///
+/// # Arguments
+/// * `updates` - Pairs of package name and target version
+/// * `options` - How to update them
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every update (none for a dry
+///   run), or an error if pip could not be run
+pub fn update_with_options(
+    updates: &[(String, String)],
+    options: &InstallOptions,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    options.with_python(|| {
+        let mut pip_args = options.pip_args();
+        if options.dry_run {
+            let specs: Vec<String> = updates
+                .iter()
+                .map(|(name, version)| format!("{}=={}", name, version))
+                .collect();
+            pip_args.push("--upgrade".to_string());
+            dry_run_install(&specs, &pip_args)?;
+            return Ok(InstallReport::new("Update summary", "update"));
+        }
+        let (title, verb) = ("Update summary", "update");
+        if options.parallel {
+            run_parallel_updates(updates, &pip_args, title, verb, registry)
+        } else {
+            run_sequential_updates(updates, &pip_args, title, verb, registry)
+        }
+    })
+}
+
+/// Updates a package to a specific version
+///
+/// Uses pip install --upgrade to update the package to the specified version.
+///
+/// # Arguments
+/// * `name` - Name of the package to update
+/// * `version` - Target version for the update
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of the update, or an error if pip could not be run
+pub fn update_package(
+    name: &str,
+    version: &str,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let updates = [(name.to_string(), version.to_string())];
+    run_sequential_updates(&updates, &[], "Update summary", "update", registry)
+}
+
+/// How far `upgrade` may move a package on its own, set by `update-policy` in ppm.toml
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdatePolicy {
+    /// Any newer release
+    #[default]
+    Major,
+    /// Releases with the same major version
+    Minor,
+    /// Releases with the same major and minor version
+    Patch,
+}
+
+impl UpdatePolicy {
+    /// Upper bound this policy puts on the releases following a version
+    ///
+    /// # Arguments
+    /// * `current` - The installed version
+    ///
+    /// # Returns
+    /// A specifier such as "<3" for the minor policy at 2.4.1, or None if any release is allowed
+    pub fn ceiling(self, current: &str) -> Option<String> {
+        if current == "unknown" {
+            return None;
+        }
+        let (release, _) = split_version(current);
+        let major = release.first().copied().unwrap_or(0);
+        let minor = release.get(1).copied().unwrap_or(0);
+        match self {
+            UpdatePolicy::Major => None,
+            UpdatePolicy::Minor => Some(format!("<{}", major + 1)),
+            UpdatePolicy::Patch => Some(format!("<{}.{}", major, minor + 1)),
+        }
+    }
+}
+
+impl fmt::Display for UpdatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UpdatePolicy::Major => "major",
+            UpdatePolicy::Minor => "minor",
+            UpdatePolicy::Patch => "patch",
+        })
+    }
+}
+
+/// The update policy of every package and the exceptions made for some of them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UpdatePolicies {
+    /// Policy of packages without one of their own
+    pub default: UpdatePolicy,
+    /// Policies keyed by normalized package name
+    pub packages: HashMap<String, UpdatePolicy>,
+}
+
+impl UpdatePolicies {
+    /// The policy that applies to a package
+    pub fn for_package(&self, name: &str) -> UpdatePolicy {
+        self.packages
+            .get(&canonicalize_name(name))
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// How far [`bump_package`] moves a package
+#[derive(Debug, Clone, PartialEq)]
+pub enum BumpTarget {
+    /// The newest release
+    Latest,
+    /// The newest release with the same major version
+    Minor,
+    /// The newest release with the same major and minor version
+    Patch,
+    /// An exact version
+    Version(String),
+}
+
+/// Outcome of [`bump_package`]
+#[derive(Debug)]
+pub struct BumpReport {
+    /// The package that was bumped
+    pub name: String,
+    /// Version installed before, if any
+    pub previous: Option<String>,
+    /// Version it was bumped to
+    pub version: String,
+    /// Requirements written to each file, empty if the install failed
+    pub edits: Vec<(PathBuf, SpecEdit)>,
+    /// Outcome of the install, empty if the version was installed already
+    pub install: InstallReport,
+}
+
+/// Moves a single dependency to a new version in its files and the environment
+///
+/// The target version is resolved first, then installed, and only when the
+/// install succeeds are the requirements of the files rewritten. A single
+/// `==`, `>=` or `~=` clause keeps its operator; anything else becomes a pin.
+///
+/// # Arguments
+/// * `name` - The package to bump
+/// * `target` - How far to move it
+/// * `files` - Requirements files and pyproject.toml files to edit
+/// * `allow_pre` - Whether pre-releases may be selected
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<BumpReport>` - The versions, file edits and install outcome, or an
+///   error if no version could be resolved or a file could not be read or written
+pub fn bump_package(
+    name: &str,
+    target: &BumpTarget,
+    files: &[PathBuf],
+    allow_pre: bool,
+    registry: &mut PackageRegistry,
+) -> Result<BumpReport> {
+    let python = get_python_executable()?;
+    let previous = registered_version(registry, name)
+        .filter(|version| version != "unknown")
+        .or_else(|| {
+            get_installed_version(&python, name)
+                .ok()
+                .filter(|version| version != "unknown")
+        });
+
+    let range = match (target, previous.as_deref()) {
+        (BumpTarget::Version(_) | BumpTarget::Latest, _) => None,
+        (_, None) => {
+            return Err(PackageError::PackageNotFound(format!(
+                "{} is not installed; pass a version to bump it to",
+                name
+            )))
+        }
+        (BumpTarget::Minor, Some(previous)) => UpdatePolicy::Minor
+            .ceiling(previous)
+            .map(|ceiling| format!(">={},{}", previous, ceiling)),
+        (BumpTarget::Patch, Some(previous)) => UpdatePolicy::Patch
+            .ceiling(previous)
+            .map(|ceiling| format!(">={},{}", previous, ceiling)),
+    };
+    let version = match target {
+        BumpTarget::Version(version) => version.clone(),
+        _ => {
+            let python_version = get_python_version(&python)?;
+            resolve_latest_version(&python, name, &python_version, range.as_deref(), allow_pre)?
+        }
+    };
+
+    let mut rewritten = Vec::new();
+    let mut edits = Vec::new();
+    for path in files {
+        let contents = std::fs::read_to_string(path)?;
+        let (bumped, file_edits) =
+            if path.file_name() == Some(std::ffi::OsStr::new("pyproject.toml")) {
+                bump_pyproject(&contents, name, &version)
+            } else {
+                let (bumped, file_edits, hashed) = bump_requirements(&contents, name, &version);
+                for line in hashed {
+                    warning!(
+                        "{}:{} pins {} with hashes; run `ppm lock` to update it",
+                        path.display(),
+                        line,
+                        name
+                    );
+                }
+                (bumped, file_edits)
+            };
+        if !file_edits.is_empty() {
+            rewritten.push((path, bumped));
+            edits.extend(file_edits.into_iter().map(|edit| (path.clone(), edit)));
+        }
+    }
+
+    let install = if previous.as_deref() == Some(version.as_str()) {
+        InstallReport::new("Bump summary", "bump")
+    } else {
+        let updates = [(name.to_string(), version.clone())];
+        run_sequential_updates(&updates, &[], "Bump summary", "bump", registry)?
+    };
+    if !install.is_success() {
+        edits.clear();
+    } else {
+        for (path, contents) in rewritten {
+            std::fs::write(path, contents)?;
+        }
+    }
+
+    Ok(BumpReport {
+        name: name.to_string(),
+        previous,
+        version,
+        edits,
+        install,
+    })
+}
+
+/// Outcome of [`add_dependencies`] or [`remove_dependencies`]
+#[derive(Debug)]
+pub struct DeclarationReport {
+    /// Requirements written to each file: `before` is empty for new ones and
+    /// `after` for removed ones; empty if the install or removal failed
+    pub edits: Vec<(PathBuf, SpecEdit)>,
+    /// Outcome of installing the added requirements, empty for removals
+    pub install: InstallReport,
+}
+
+/// Declares requirements in a dependency file and installs them
+///
+/// The requirements are installed first, and only when the install succeeds
+/// is the file rewritten, so it never declares what could not be installed.
+///
+/// # Arguments
+/// * `specs` - The requirements, e.g. "requests>=2.31"
+/// * `file` - pyproject.toml or a requirements file, created if missing
+/// * `group` - Group of `[project.optional-dependencies]` to declare them in,
+///   for pyproject.toml only
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<DeclarationReport>` - The file edits and install outcome, or an
+///   error if a spec is invalid or the file could not be read or written
+pub fn add_dependencies(
+    specs: &[String],
+    file: &Path,
+    group: Option<&str>,
+    registry: &mut PackageRegistry,
+) -> Result<DeclarationReport> {
+    let is_pyproject = file.file_name() == Some(std::ffi::OsStr::new("pyproject.toml"));
+    if group.is_some() && !is_pyproject {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "Optional dependency groups are declared in pyproject.toml, not {}",
+            file.display()
+        )));
+    }
+    let mut contents = if is_pyproject || file.exists() {
+        std::fs::read_to_string(file)?
+    } else {
+        String::new()
+    };
+
+    let mut edits = Vec::new();
+    for spec in specs {
+        let name = requirement_name(spec);
+        if name.is_empty() {
+            return Err(PackageError::InvalidPackageSpec(format!(
+                "Invalid package specification: {}",
+                spec
+            )));
+        }
+        let (declared, edit) = if is_pyproject {
+            // pyproject.toml takes PEP 508 requirements, which name the package of a URL
+            let spec = match VcsRequirement::parse(spec) {
+                Some(vcs) if vcs.name.is_none() => format!("{} @ {}", name, spec.trim()),
+                _ => spec.clone(),
+            };
+            add_pyproject_dependency(&contents, &spec, group)?
+        } else {
+            add_requirement(&contents, spec)
+        };
+        contents = declared;
+        edits.extend(edit.map(|edit| (file.to_path_buf(), edit)));
+    }
+
+    let install = install_packages(specs, registry)?;
+    if !install.is_success() {
+        edits.clear();
+    } else if !edits.is_empty() {
+        std::fs::write(file, contents)?;
+    }
+    Ok(DeclarationReport { edits, install })
+}
+
+/// Removes packages from the dependency files and uninstalls them
+///
+/// The packages are uninstalled first, and only when that succeeds are the
+/// files rewritten.
+///
+/// # Arguments
+/// * `names` - Names of the packages to remove
+/// * `files` - pyproject.toml and requirements files to remove them from
+/// * `policy` - What to do when other packages depend on them
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<DeclarationReport>` - The file edits, or an error if uninstalling
+///   failed or a file could not be read or written
+pub fn remove_dependencies(
+    names: &[String],
+    files: &[PathBuf],
+    policy: DependentsPolicy,
+    registry: &mut PackageRegistry,
+) -> Result<DeclarationReport> {
+    let mut rewritten = Vec::new();
+    let mut edits = Vec::new();
+    for path in files {
+        let mut contents = std::fs::read_to_string(path)?;
+        let mut file_edits = Vec::new();
+        for name in names {
+            let (removed, name_edits) =
+                if path.file_name() == Some(std::ffi::OsStr::new("pyproject.toml")) {
+                    remove_pyproject_dependency(&contents, name)
+                } else {
+                    remove_requirement(&contents, name)
+                };
+            contents = removed;
+            file_edits.extend(name_edits);
+        }
+        if !file_edits.is_empty() {
+            rewritten.push((path, contents));
+            edits.extend(file_edits.into_iter().map(|edit| (path.clone(), edit)));
+        }
+    }
+    for name in names {
+        let declared = edits.iter().any(|(_, edit)| {
+            canonicalize_name(requirement_name(&edit.before)) == canonicalize_name(name)
+        });
+        if !declared {
+            warning!("{} is not declared in any dependency file", name);
+        }
+    }
+
+    delete_packages(names, policy, registry)?;
+    for (path, contents) in rewritten {
+        std::fs::write(path, contents)?;
+    }
+    Ok(DeclarationReport {
+        edits,
+        install: InstallReport::new("Removal summary", "remove"),
+    })
+}
+
+/// Runs pip upgrades one at a time and records the results in the registry
+pub(crate) fn run_sequential_updates(
+    updates: &[(String, String)],
+    pip_args: &[String],
+    title: &str,
+    verb: &str,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let mut report = InstallReport::new(title, verb);
+    for (name, version) in updates {
+        report
+            .outcomes
+            .push(update_package_with_args(name, version, pip_args, registry)?);
+    }
+
+    let updated: Vec<String> = report
+        .outcomes
+        .iter()
+        .filter(|o| o.new_version.is_some())
+        .map(|o| o.name.clone())
+        .collect();
+    if !updated.is_empty() {
+        let python = get_python_executable()?;
+        report
+            .warnings
+            .extend(track_dependencies(&python, &updated, registry));
+    }
+    Ok(report)
+}
+
+/// Updates a package to a specific version, passing extra arguments to pip
+///
+/// A failing pip run is reported as an outcome with an error rather than as
+/// an `Err`, so callers can include it in their summary table.
+fn update_package_with_args(
+    name: &str,
+    version: &str,
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<OperationOutcome> {
+    if name.trim().is_empty() || version.trim().is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "Package name and version cannot be empty".to_string(),
+        ));
+    }
+
+    let python = get_python_executable()?;
+    let package_spec = format!("{}=={}", name, version);
+    let previous_version = registered_version(registry, name);
+    emit_event(package_started_event(name, "update"));
+
+    let started = Instant::now();
+    let mut command = Command::new(&python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("install")
+        .arg("--upgrade")
+        .arg(&package_spec)
+        .args(pip_args);
+    let output = run_pip(command, name, None)?;
+    record_timing(name, Phase::Update, started);
+    let millis = Some(started.elapsed().as_millis() as u64);
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr).to_string();
+        emit_event(package_finished_event(
+            name,
+            "update",
+            Err(&error),
+            started.elapsed(),
+        ));
+        return Ok(OperationOutcome {
+            name: name.to_string(),
+            spec: package_spec,
+            previous_version,
+            new_version: None,
+            error: Some(error),
+            millis,
+            satisfied: false,
+            index: None,
+        });
+    }
+
+    let installed_version = get_installed_version(&python, name)?;
+    emit_event(package_finished_event(
+        name,
+        "update",
+        Ok(&installed_version),
+        started.elapsed(),
+    ));
+    let explicit = registry.get_package(name).is_none_or(|p| p.explicit);
+    let mut package = Package::new(name.to_string(), installed_version.clone());
+    package.explicit = explicit;
+    registry.add_package(package);
+
+    Ok(OperationOutcome {
+        name: name.to_string(),
+        spec: package_spec,
+        previous_version,
+        new_version: Some(installed_version),
+        error: None,
+        millis,
+        satisfied: false,
+        index: None,
+    })
+}
+
+/// Updates several packages to specific versions in parallel
+///
+/// Runs one pip upgrade per package on the rayon thread pool with a shared
+/// progress bar, then records every successful update in the registry at once.
+///
+/// # Arguments
+/// * `updates` - Pairs of package name and target version
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every update, or an error if pip could not be run
+pub fn update_packages_parallel(
+    updates: &[(String, String)],
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    run_parallel_updates(updates, &[], "Update summary", "update", registry)
+}
+
+/// Applies an upgrade plan in parallel, one pip process per package
+///
+/// # Arguments
+/// * `plan` - Planned upgrades to apply
+/// * `constraints_file` - Constraints file forwarded to pip so dependencies stay capped too
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<InstallReport>` - The outcome of every upgrade, or an error if pip could not be run
+pub fn apply_upgrade_plan_parallel(
+    plan: &UpgradePlan,
+    constraints_file: Option<&Path>,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let pip_args: Vec<String> = constraints_file
+        .map(|path| vec!["-c".to_string(), path.display().to_string()])
+        .unwrap_or_default();
+    let updates: Vec<(String, String)> = plan
+        .upgrades
+        .iter()
+        .map(|entry| (entry.name.clone(), entry.target_version.clone()))
+        .collect();
+    run_parallel_updates(&updates, &pip_args, "Upgrade summary", "upgrade", registry)
+}
+
+/// Runs pip upgrades in parallel and records the results in the registry
+fn run_parallel_updates(
+    updates: &[(String, String)],
+    pip_args: &[String],
+    title: &str,
+    verb: &'static str,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let mut report = InstallReport::new(title, verb);
+    if updates.is_empty() {
+        return Ok(report);
+    }
+
+    let python = get_python_executable()?;
+    let observer = install_observer();
+    observer.on_start(updates.len(), verb);
+
+    let results: Vec<TimedResult> = par_items(updates)
+        .map(|(name, version)| {
+            let started = Instant::now();
+            observer.on_progress(name, "starting");
+            let result = update_single_package(&python, name, version, pip_args, observer.as_ref());
+            observe_package_done(observer.as_ref(), name, &result, started.elapsed());
+            (format!("{}=={}", name, version), result, started.elapsed())
+        })
+        .collect();
+
+    observer.on_finished();
+
+    report.outcomes = record_update_results(updates, results, registry);
+    let updated: Vec<String> = report
+        .outcomes
+        .iter()
+        .filter(|o| o.new_version.is_some())
+        .map(|o| o.name.clone())
+        .collect();
+    report
+        .warnings
+        .extend(track_dependencies(&python, &updated, registry));
+    Ok(report)
+}
+
+/// Registers the successful updates, keeping each package's explicit flag, and
+/// turns every result into an outcome
+pub(crate) fn record_update_results(
+    updates: &[(String, String)],
+    results: Vec<TimedResult>,
+    registry: &mut PackageRegistry,
+) -> Vec<OperationOutcome> {
+    let mut outcomes = Vec::with_capacity(results.len());
+
+    for ((name, _), (spec, result, elapsed)) in updates.iter().zip(results) {
+        let mut outcome = OperationOutcome {
+            name: name.clone(),
+            spec,
+            previous_version: registered_version(registry, name),
+            new_version: None,
+            error: None,
+            millis: Some(elapsed.as_millis() as u64),
+            satisfied: false,
+            index: None,
+        };
+        match result {
+            Ok((name, version)) => {
+                let explicit = registry.get_package(&name).is_none_or(|p| p.explicit);
+                let mut package = Package::new(name.clone(), version.clone());
+                package.explicit = explicit;
+                registry.add_package(package);
+                outcome.new_version = Some(version);
+            }
+            Err(error) => outcome.error = Some(error.to_string()),
+        }
+        outcomes.push(outcome);
+    }
+    outcomes
+}
+
+/// Parses the targets given to the update command
+///
+/// Accepts the two-argument form `name version` or `name "<2"` as well as any
+/// number of `name==version` pairs, `name~=1.26`-style ranges and bare names.
+/// The second element of each pair is either an exact version or a specifier
+/// set; bare names carry nothing and are resolved to the latest release by
+/// [`resolve_update_versions`].
+///
+/// # Arguments
+/// * `targets` - Raw command-line targets
+///
+/// # Returns
+/// * `Result<Vec<(String, Option<String>)>>` - Package names and optional version or range
+pub fn parse_update_targets(targets: &[String]) -> Result<Vec<(String, Option<String>)>> {
+    if let [name, version] = targets {
+        let version = version.trim();
+        let looks_like_version =
+            version.starts_with(|c: char| c.is_ascii_digit()) || is_version_specifier(version);
+        if !name.contains(SPECIFIER_CHARS) && looks_like_version {
+            let name = name.trim();
+            if name.is_empty() {
+                return Err(PackageError::InvalidPackageSpec(
+                    "Package name and version cannot be empty".to_string(),
+                ));
+            }
+            return Ok(vec![(
+                name.to_string(),
+                Some(normalize_update_version(version)),
+            )]);
+        }
+    }
+
+    targets
+        .iter()
+        .map(|target| {
+            let target = target.trim();
+            match target.find(SPECIFIER_CHARS) {
+                Some(index) => {
+                    let name = target[..index].trim();
+                    let version = target[index..].trim();
+                    if name.is_empty() || version.trim_start_matches(SPECIFIER_CHARS).is_empty() {
+                        return Err(PackageError::InvalidPackageSpec(format!(
+                            "Invalid package specification: {}",
+                            target
+                        )));
+                    }
+                    Ok((name.to_string(), Some(normalize_update_version(version))))
+                }
+                None => parse_package_spec(target),
+            }
+        })
+        .collect()
+}
+
+/// Resolves the target version of every update
+///
+/// Exact versions are kept as given. Packages with a version range or without
+/// a version are resolved to the newest compatible release on PyPI that
+/// matches, unless they are pinned in the registry.
+///
+/// # Arguments
+/// * `targets` - Package names and optional exact versions or ranges
+/// * `allow_pre` - Whether pre-releases may be selected
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<Vec<(String, String)>>` - Pairs of package name and exact target version
+pub fn resolve_update_versions(
+    targets: Vec<(String, Option<String>)>,
+    allow_pre: bool,
+    registry: &PackageRegistry,
+) -> Result<Vec<(String, String)>> {
+    let is_exact =
+        |version: &Option<String>| version.as_deref().is_some_and(|v| !is_version_specifier(v));
+    if targets.iter().all(|(_, version)| is_exact(version)) {
+        return Ok(targets
+            .into_iter()
+            .map(|(name, version)| (name, version.unwrap_or_default()))
+            .collect());
+    }
+
+    let python = get_python_executable()?;
+    let python_version = get_python_version(&python)?;
+
+    targets
+        .into_iter()
+        .map(|(name, version)| {
+            if is_exact(&version) {
+                return Ok((name, version.unwrap_or_default()));
+            }
+
+            if let Some(package) = registry
+                .find_package_key(&name)
+                .and_then(|key| registry.get_package(&key))
+                .filter(|p| p.pinned)
+            {
+                return Err(PackageError::InvalidPackageSpec(format!(
+                    "{} is pinned at version {} (run `unpin {}` first)",
+                    name, package.version, name
+                )));
+            }
+
+            let range = version.as_deref();
+            let started = Instant::now();
+            let resolved =
+                resolve_latest_version(&python, &name, &python_version, range, allow_pre)?;
+            record_timing(&name, Phase::Resolve, started);
+            match range {
+                Some(range) => status!("Resolved {}{} to version {}", name, range, resolved),
+                None => status!("Resolved {} to latest version {}", name, resolved),
+            }
+            Ok((name, resolved))
+        })
+        .collect()
+}
+
+/// Marks registered packages as pinned or unpinned
+///
+/// # Arguments
+/// * `names` - Names of the packages to change
+/// * `pinned` - Whether the packages should be pinned
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or [`PackageError::PackageNotFound`] for an unregistered package
+pub fn set_pinned(names: &[String], pinned: bool, registry: &mut PackageRegistry) -> Result<()> {
+    for name in names {
+        let key = registry
+            .find_package_key(name)
+            .ok_or_else(|| PackageError::PackageNotFound(name.clone()))?;
+        if let Some(package) = registry.packages.get_mut(&key) {
+            package.pinned = pinned;
+            if pinned {
+                status!(
+                    "{} Pinned {} at version {}",
+                    success_mark(),
+                    key,
+                    package.version
+                );
+            } else {
+                status!("{} Unpinned {}", success_mark(), key);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Upgrades a single package to a specific version and returns the installed version
+fn update_single_package(
+    python: &str,
+    name: &str,
+    version: &str,
+    pip_args: &[String],
+    observer: &dyn InstallObserver,
+) -> Result<(String, String)> {
+    let started = Instant::now();
+    let mut command = Command::new(python);
+    command
+        .arg("-m")
+        .arg("pip")
+        .arg("install")
+        .arg("--upgrade")
+        .arg(format!("{}=={}", name, version))
+        .args(pip_args);
+    let output = run_pip(command, name, Some(observer))?;
+    record_timing(name, Phase::Update, started);
+
+    if !output.status.success() {
+        return Err(pip_error(name, &output, |stderr| {
+            PackageError::InstallationFailed(format!("Failed to update {}: {}", name, stderr))
+        }));
+    }
+
+    // pip succeeded on an exact pin, so that is the installed version
+    Ok((name.to_string(), version.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::project::ProjectConfig;
+    use crate::spec::version_satisfies;
+
+    #[test]
+    fn test_parse_update_targets() {
+        let legacy = vec!["numpy".to_string(), "1.24.0".to_string()];
+        assert_eq!(
+            parse_update_targets(&legacy).unwrap(),
+            vec![("numpy".to_string(), Some("1.24.0".to_string()))]
+        );
+
+        let mixed = vec!["numpy==1.26.4".to_string(), "pandas".to_string()];
+        assert_eq!(
+            parse_update_targets(&mixed).unwrap(),
+            vec![
+                ("numpy".to_string(), Some("1.26.4".to_string())),
+                ("pandas".to_string(), None)
+            ]
+        );
+
+        let names = vec!["numpy".to_string(), "pandas".to_string()];
+        assert_eq!(
+            parse_update_targets(&names).unwrap()[1],
+            ("pandas".to_string(), None)
+        );
+    }
+
+    #[test]
+    fn test_parse_update_targets_with_ranges() {
+        let two_args = vec!["numpy".to_string(), "<2".to_string()];
+        assert_eq!(
+            parse_update_targets(&two_args).unwrap(),
+            vec![("numpy".to_string(), Some("<2".to_string()))]
+        );
+
+        let inline = vec![
+            "numpy~=1.26".to_string(),
+            "pandas>=2,<3".to_string(),
+            "requests==2.31.*".to_string(),
+            "flask==3.0.2".to_string(),
+        ];
+        let parsed = parse_update_targets(&inline).unwrap();
+        assert_eq!(parsed[0].1.as_deref(), Some("~=1.26"));
+        assert_eq!(parsed[1].1.as_deref(), Some(">=2,<3"));
+        assert_eq!(parsed[2].1.as_deref(), Some("==2.31.*"));
+        assert_eq!(parsed[3].1.as_deref(), Some("3.0.2"));
+
+        assert!(is_version_specifier("<2"));
+        assert!(!is_version_specifier("3.0.2"));
+        assert!(parse_update_targets(&["numpy<".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_set_pinned() {
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("numpy".to_string(), "1.26.4".to_string()));
+
+        set_pinned(&["NumPy".to_string()], true, &mut registry).unwrap();
+        assert!(registry.get_package("numpy").unwrap().pinned);
+
+        let targets = vec![("numpy".to_string(), None)];
+        assert!(resolve_update_versions(targets, false, &registry).is_err());
+
+        assert!(set_pinned(&["scipy".to_string()], true, &mut registry).is_err());
+    }
+
+    #[test]
+    fn test_declare_dependencies() {
+        let requirements = "rich  # ui\nrequests==2.31.0 \\\n    --hash=sha256:abc\nclick";
+        let (added, edit) = add_requirement(requirements, "httpx>=0.27");
+        assert_eq!(added, format!("{}\nhttpx>=0.27\n", requirements));
+        assert_eq!(edit.unwrap().line, 5);
+        let (changed, edit) = add_requirement(requirements, "Requests>=2.32");
+        assert_eq!(changed, "rich  # ui\nRequests>=2.32\nclick");
+        assert_eq!(edit.unwrap().before, "requests==2.31.0");
+        assert_eq!(add_requirement("rich\n", "rich").1, None);
+        let (removed, edits) = remove_requirement(requirements, "requests");
+        assert_eq!(removed, "rich  # ui\nclick");
+        assert_eq!(edits[0].line, 2);
+
+        let pyproject = "[project]\n\
+                         name = \"demo\"\n\
+                         dependencies = [\n    \"rich\",  # ui\n    \"click\"\n]\n\
+                         \n\
+                         [tool.other]\n\
+                         dependencies = []\n";
+        let (added, edit) = add_pyproject_dependency(pyproject, "httpx>=0.27", None).unwrap();
+        assert_eq!(
+            added,
+            pyproject.replace("\"click\"\n", "\"click\",\n    \"httpx>=0.27\",\n")
+        );
+        assert_eq!(edit.unwrap().line, 6);
+        let (changed, _) = add_pyproject_dependency(pyproject, "Rich>=13", None).unwrap();
+        assert_eq!(changed, pyproject.replace("\"rich\"", "\"Rich>=13\""));
+        let (grouped, edit) = add_pyproject_dependency(pyproject, "pytest", Some("test")).unwrap();
+        assert!(grouped.ends_with(
+            "dependencies = []\n\n[project.optional-dependencies]\ntest = [\n    \"pytest\",\n]\n"
+        ));
+        assert_eq!(edit.unwrap().line, 12);
+        let (inline, _) =
+            add_pyproject_dependency("[project]\ndependencies = [\"rich\"]\n", "click", None)
+                .unwrap();
+        assert_eq!(inline, "[project]\ndependencies = [\"rich\", \"click\"]\n");
+        let (created, _) =
+            add_pyproject_dependency("[project]\nname = \"demo\"\n\n[tool.x]\n", "click", None)
+                .unwrap();
+        assert_eq!(
+            created,
+            "[project]\nname = \"demo\"\ndependencies = [\n    \"click\",\n]\n\n[tool.x]\n"
+        );
+        assert!(add_pyproject_dependency("[tool.x]\n", "click", None).is_err());
+
+        let (removed, edits) = remove_pyproject_dependency(pyproject, "RICH");
+        assert_eq!(removed, pyproject.replace("    \"rich\",  # ui\n", ""));
+        assert_eq!(edits[0].before, "rich");
+        let (removed, _) =
+            remove_pyproject_dependency("[project]\ndependencies = [\"a\", \"b\", \"c\"]\n", "b");
+        assert_eq!(removed, "[project]\ndependencies = [\"a\", \"c\"]\n");
+        let (removed, _) =
+            remove_pyproject_dependency("[project]\ndependencies = [\"a\", \"b\"]\n", "b");
+        assert_eq!(removed, "[project]\ndependencies = [\"a\"]\n");
+    }
+
+    #[test]
+    fn test_update_policies() {
+        assert_eq!(UpdatePolicy::Major.ceiling("2.4.1"), None);
+        assert_eq!(UpdatePolicy::Minor.ceiling("2.4.1"), Some("<3".to_string()));
+        assert_eq!(
+            UpdatePolicy::Patch.ceiling("2.4.1"),
+            Some("<2.5".to_string())
+        );
+        assert_eq!(UpdatePolicy::Minor.ceiling("unknown"), None);
+        assert!(version_satisfies("2.9.0", "<3"));
+        assert!(!version_satisfies("3.0.0", "<3"));
+
+        let config: ProjectConfig =
+            toml::from_str("update-policy = \"minor\"\n[update-policies]\nDjango = \"patch\"\n")
+                .unwrap();
+        let policies = config.update_policies();
+        assert_eq!(policies.for_package("django"), UpdatePolicy::Patch);
+        assert_eq!(policies.for_package("requests"), UpdatePolicy::Minor);
+        assert_eq!(
+            ProjectConfig::default()
+                .update_policies()
+                .for_package("requests"),
+            UpdatePolicy::Major
+        );
+    }
+}
@@ -0,0 +1,1150 @@
+//! Planning upgrades and reporting on outdated packages
+
+use crate::http::http_client;
+use crate::install::map_bounded;
+use crate::metadata::{fetch_project_json, fetch_pypi_json};
+use crate::observer::install_observer;
+use crate::outcome::InstallReport;
+use crate::pip::{get_python_executable, get_python_version};
+use crate::registry::PackageRegistry;
+use crate::reporter::success_mark;
+use crate::spec::{
+    canonicalize_name, compare_versions, is_prerelease, version_satisfies, SPECIFIER_CHARS,
+};
+use crate::stats::{record_timing, Phase};
+use crate::update::{run_sequential_updates, UpdatePolicies, UpdatePolicy};
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Security advisory affecting a published release
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Vulnerability {
+    /// Advisory identifier (e.g. "PYSEC-2023-74" or "GHSA-...")
+    pub id: String,
+    /// Short description of the advisory
+    pub summary: String,
+    /// Versions in which the advisory is fixed
+    pub fixed_in: Vec<String>,
+}
+
+/// A planned upgrade of a single registered package
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UpgradePlanEntry {
+    /// Package name
+    pub name: String,
+    /// Version currently recorded in the registry
+    pub current_version: String,
+    /// Version the package will be upgraded to
+    pub target_version: String,
+    /// Newest published version, which differs from the target when capped by a constraint
+    pub latest_version: String,
+    /// Constraint that capped the target below the latest version, if any
+    pub constraint: Option<String>,
+    /// Link to the changelog or release notes of the target version
+    pub changelog_url: String,
+    /// Advisories affecting the current version that the target version fixes
+    pub fixed_vulnerabilities: Vec<Vulnerability>,
+}
+
+/// A registered package that upgrade planning kept at its current version
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HeldPackage {
+    /// Package name
+    pub name: String,
+    /// Version currently recorded in the registry
+    pub current_version: String,
+    /// Newest published version, if it could be determined
+    pub latest_version: Option<String>,
+    /// Why the package is held, e.g. "pin" or "constraint <2"
+    pub reason: String,
+}
+
+/// Outcome of upgrade planning
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UpgradePlan {
+    /// Packages that will be upgraded, sorted by name
+    pub upgrades: Vec<UpgradePlanEntry>,
+    /// Outdated packages that are held back, sorted by name
+    pub held: Vec<HeldPackage>,
+    /// Packages whose latest release could not be looked up, sorted by name
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unchecked: Vec<UncheckedPackage>,
+}
+
+impl UpgradePlan {
+    /// Whether applying the plan is confirmed first
+    ///
+    /// Upgrading several packages at once, or everything with `--all`, is
+    /// hard to undo.
+    ///
+    /// # Arguments
+    /// * `all` - Whether `--all` was given
+    pub fn needs_confirmation(&self, all: bool) -> bool {
+        !self.upgrades.is_empty() && (all || self.upgrades.len() > 1)
+    }
+}
+
+/// A package left out of an upgrade plan because looking it up failed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UncheckedPackage {
+    /// Package name
+    pub name: String,
+    /// Why the lookup failed
+    pub error: String,
+}
+
+/// Supported formats for upgrade reports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    /// Standalone HTML document suitable for attaching to a change ticket
+    Html,
+    /// The plan as JSON, for dashboards and scheduled jobs
+    Json,
+    /// Markdown tables suitable for a pull request description
+    Markdown,
+}
+
+/// Computes the pending upgrades for the given packages
+///
+/// Queries PyPI for the latest release of every package and keeps the ones
+/// whose latest version is newer than the version recorded in the registry.
+/// Pinned packages are held, and packages listed in `constraints` are only
+/// upgraded to the newest release the constraint allows, as are packages
+/// whose update policy rules out the latest release. Lookups run
+/// concurrently, up to the limit of the shared [`HttpClient`](crate::HttpClient). Packages whose
+/// metadata cannot be fetched are reported, listed as unchecked and left out,
+/// so one failing lookup does not spoil the plan for the others.
+///
+/// # Arguments
+/// * `names` - Names of the packages to check
+/// * `constraints` - Version ceilings keyed by normalized package name
+/// * `policies` - How far each package may be upgraded
+/// * `registry` - Reference to the package registry
+///
+/// # Returns
+/// * `Result<UpgradePlan>` - Planned upgrades and held packages
+pub fn plan_upgrades(
+    names: &[String],
+    constraints: &HashMap<String, String>,
+    policies: &UpdatePolicies,
+    registry: &PackageRegistry,
+) -> Result<UpgradePlan> {
+    let python = get_python_executable()?;
+    let restricted = policies.default != UpdatePolicy::Major
+        || policies
+            .packages
+            .values()
+            .any(|policy| *policy != UpdatePolicy::Major);
+    let python_version = if constraints.is_empty() && !restricted {
+        String::new()
+    } else {
+        get_python_version(&python)?
+    };
+    let mut plan = UpgradePlan::default();
+    let mut pending = Vec::new();
+
+    for name in names {
+        let package = registry
+            .get_package(name)
+            .ok_or_else(|| PackageError::PackageNotFound(name.clone()))?;
+        if package.pinned {
+            plan.held.push(HeldPackage {
+                name: name.clone(),
+                current_version: package.version.clone(),
+                latest_version: None,
+                reason: "pin".to_string(),
+            });
+        } else {
+            pending.push((name.as_str(), package.version.as_str()));
+        }
+    }
+
+    let observer = install_observer();
+    observer.on_start(pending.len(), "check");
+    let checked = map_bounded(
+        &pending,
+        http_client().concurrency_limit(),
+        |_, &(name, current_version)| {
+            observer.on_progress(name, "starting");
+            let constraint = constraints
+                .get(&canonicalize_name(name))
+                .map(String::as_str);
+            let started = Instant::now();
+            let planned = plan_single_upgrade(
+                &python,
+                &python_version,
+                name,
+                current_version,
+                constraint,
+                policies.for_package(name),
+            );
+            record_timing(name, Phase::Resolve, started);
+            let done = match &planned {
+                Ok(PlannedUpgrade::Upgrade(entry)) => Ok(entry.target_version.as_str()),
+                Ok(_) => Ok(current_version),
+                Err(_) => Err("lookup failed"),
+            };
+            observer.on_package_done(name, done, started.elapsed());
+            planned
+        },
+    );
+    observer.on_finished();
+
+    for ((name, _), planned) in pending.iter().zip(checked) {
+        match planned {
+            Ok(PlannedUpgrade::Upgrade(entry)) => plan.upgrades.push(entry),
+            Ok(PlannedUpgrade::Held(held)) => plan.held.push(held),
+            Ok(PlannedUpgrade::UpToDate) => {}
+            Err(e) => {
+                warning!("Skipping {}: {}", name, e);
+                plan.unchecked.push(UncheckedPackage {
+                    name: name.to_string(),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    if !plan.unchecked.is_empty() {
+        warning!(
+            "Could not check {} of {} package(s); the plan covers the rest",
+            plan.unchecked.len(),
+            pending.len()
+        );
+    }
+
+    plan.upgrades.sort_by(|a, b| a.name.cmp(&b.name));
+    plan.held.sort_by(|a, b| a.name.cmp(&b.name));
+    plan.unchecked.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(plan)
+}
+
+/// Applies a previously computed upgrade plan
+///
+/// # Arguments
+/// * `plan` - Planned upgrades to apply
+/// * `constraints_file` - Constraints file forwarded to pip so dependencies stay capped too
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<()>` - Success or error if any upgrade failed
+pub fn apply_upgrade_plan(
+    plan: &UpgradePlan,
+    constraints_file: Option<&Path>,
+    registry: &mut PackageRegistry,
+) -> Result<InstallReport> {
+    let pip_args: Vec<String> = constraints_file
+        .map(|path| vec!["-c".to_string(), path.display().to_string()])
+        .unwrap_or_default();
+    let updates: Vec<(String, String)> = plan
+        .upgrades
+        .iter()
+        .map(|entry| (entry.name.clone(), entry.target_version.clone()))
+        .collect();
+    run_sequential_updates(&updates, &pip_args, "Upgrade summary", "upgrade", registry)
+}
+
+/// Prints an upgrade plan as a list of version transitions
+///
+/// # Arguments
+/// * `plan` - Planned upgrades to print
+pub fn print_upgrade_plan(plan: &UpgradePlan) {
+    if !plan.held.is_empty() {
+        status!("Held back ({} total):", plan.held.len());
+        for held in &plan.held {
+            let latest = held
+                .latest_version
+                .as_ref()
+                .map(|v| format!(", latest {}", v))
+                .unwrap_or_default();
+            status!(
+                "  {} {} held by {}{}",
+                held.name,
+                held.current_version,
+                held.reason,
+                latest
+            );
+        }
+    }
+
+    if plan.upgrades.is_empty() {
+        if plan.unchecked.is_empty() {
+            status!("All packages are up to date");
+        } else {
+            status!("No upgrades found among the packages that could be checked");
+        }
+        return;
+    }
+
+    status!("Planned upgrades ({} total):", plan.upgrades.len());
+    for entry in &plan.upgrades {
+        let fixes = if entry.fixed_vulnerabilities.is_empty() {
+            String::new()
+        } else {
+            format!(
+                " (fixes {} vulnerabilit{})",
+                entry.fixed_vulnerabilities.len(),
+                if entry.fixed_vulnerabilities.len() == 1 {
+                    "y"
+                } else {
+                    "ies"
+                }
+            )
+        };
+        let capped = entry
+            .constraint
+            .as_ref()
+            .map(|c| {
+                format!(
+                    " (held by constraint {}, latest {})",
+                    c, entry.latest_version
+                )
+            })
+            .unwrap_or_default();
+        status!(
+            "  {} {} -> {}{}{}",
+            entry.name,
+            entry.current_version,
+            entry.target_version,
+            capped,
+            fixes
+        );
+    }
+}
+
+/// Renders the outdated packages of an upgrade plan as a table
+///
+/// Packages an upgrade would move are listed with the version it would reach
+/// and the advisories it fixes; held packages with what holds them.
+///
+/// # Arguments
+/// * `plan` - Upgrade plan of the packages to report
+///
+/// # Returns
+/// * `String` - The table, or a one-line note when nothing is outdated
+pub fn render_outdated(plan: &UpgradePlan) -> String {
+    if plan.upgrades.is_empty() && plan.held.is_empty() {
+        return "All packages are up to date\n".to_string();
+    }
+
+    let header = ["Package", "Current", "Latest", "Note"].map(String::from);
+    let mut rows: Vec<[String; 4]> = Vec::new();
+    for entry in &plan.upgrades {
+        let mut notes = Vec::new();
+        if entry.target_version != entry.latest_version {
+            notes.push(format!("upgrades to {}", entry.target_version));
+        }
+        if !entry.fixed_vulnerabilities.is_empty() {
+            let ids: Vec<&str> = entry
+                .fixed_vulnerabilities
+                .iter()
+                .map(|v| v.id.as_str())
+                .collect();
+            notes.push(format!("vulnerable: {}", ids.join(", ")));
+        }
+        rows.push([
+            entry.name.clone(),
+            entry.current_version.clone(),
+            entry.latest_version.clone(),
+            notes.join("; "),
+        ]);
+    }
+    for held in &plan.held {
+        rows.push([
+            held.name.clone(),
+            held.current_version.clone(),
+            held.latest_version
+                .clone()
+                .unwrap_or_else(|| "-".to_string()),
+            format!("held by {}", held.reason),
+        ]);
+    }
+    rows.sort_by_key(|row| row[0].to_lowercase());
+
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut out = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell))
+            .collect();
+        out.push_str(&format!("  {}\n", cells.join("  ").trim_end()));
+    }
+    out.push_str(&format!("\n{} outdated package(s)\n", rows.len()));
+    out
+}
+
+/// Builds one aligned "name  current → target" label per planned upgrade
+///
+/// # Arguments
+/// * `plan` - The upgrade plan to choose from
+///
+/// # Returns
+/// * `Vec<String>` - Labels in the order of `plan.upgrades`
+pub fn upgrade_choice_labels(plan: &UpgradePlan) -> Vec<String> {
+    let name_width = plan
+        .upgrades
+        .iter()
+        .map(|e| e.name.len())
+        .max()
+        .unwrap_or(0);
+    let current_width = plan
+        .upgrades
+        .iter()
+        .map(|e| e.current_version.len())
+        .max()
+        .unwrap_or(0);
+
+    plan.upgrades
+        .iter()
+        .map(|entry| {
+            let capped = if entry.constraint.is_some() {
+                format!("  (latest {})", entry.latest_version)
+            } else {
+                String::new()
+            };
+            format!(
+                "{:<name_width$}  {:>current_width$} → {}{}",
+                entry.name, entry.current_version, entry.target_version, capped
+            )
+        })
+        .collect()
+}
+
+/// Narrows an upgrade plan to the chosen entries
+///
+/// # Arguments
+/// * `plan` - The full upgrade plan
+/// * `chosen` - Indices into `plan.upgrades`
+///
+/// # Returns
+/// * `UpgradePlan` - Plan with only the chosen upgrades and the same held packages
+pub fn select_upgrades(plan: &UpgradePlan, chosen: &[usize]) -> UpgradePlan {
+    UpgradePlan {
+        upgrades: chosen
+            .iter()
+            .filter_map(|&i| plan.upgrades.get(i).cloned())
+            .collect(),
+        held: plan.held.clone(),
+        unchecked: plan.unchecked.clone(),
+    }
+}
+
+/// Parses a report specification of the form `FORMAT=PATH`
+///
+/// # Arguments
+/// * `spec` - Report specification, e.g. "html=report.html"
+///
+/// # Returns
+/// * `Result<(ReportFormat, PathBuf)>` - Report format and output path
+pub fn parse_report_spec(spec: &str) -> Result<(ReportFormat, PathBuf)> {
+    let (format, path) = spec.split_once('=').ok_or_else(|| {
+        PackageError::InvalidPackageSpec(format!(
+            "Report must be given as FORMAT=PATH, got: {}",
+            spec
+        ))
+    })?;
+
+    let path = path.trim();
+    if path.is_empty() {
+        return Err(PackageError::InvalidPackageSpec(
+            "Empty report path".to_string(),
+        ));
+    }
+
+    let format = match format.trim().to_lowercase().as_str() {
+        "html" => ReportFormat::Html,
+        "json" => ReportFormat::Json,
+        "md" | "markdown" => ReportFormat::Markdown,
+        other => {
+            return Err(PackageError::InvalidPackageSpec(format!(
+                "Unsupported report format: {}",
+                other
+            )))
+        }
+    };
+
+    Ok((format, PathBuf::from(path)))
+}
+
+/// Parses a report destination given either as `FORMAT=PATH` or as a path
+///
+/// A bare path picks the format from its extension, so `report.json` and
+/// `json=report.json` are the same report.
+///
+/// # Arguments
+/// * `spec` - Report destination, e.g. "report.json" or "html=out/report"
+///
+/// # Returns
+/// * `Result<(ReportFormat, PathBuf)>` - Report format and output path
+pub fn parse_report_path(spec: &str) -> Result<(ReportFormat, PathBuf)> {
+    if spec.contains('=') {
+        return parse_report_spec(spec);
+    }
+    let path = Path::new(spec.trim());
+    match path.extension().and_then(|e| e.to_str()) {
+        Some(extension) => parse_report_spec(&format!("{}={}", extension, path.display())),
+        None => Err(PackageError::InvalidPackageSpec(format!(
+            "Cannot tell the report format of {}; use FORMAT=PATH",
+            spec
+        ))),
+    }
+}
+
+/// Writes an upgrade plan as a change proposal report
+///
+/// # Arguments
+/// * `plan` - Planned upgrades to document
+/// * `format` - Output format of the report
+/// * `path` - Destination file
+///
+/// # Returns
+/// * `Result<()>` - Success or IO error
+pub fn write_upgrade_report(plan: &UpgradePlan, format: ReportFormat, path: &Path) -> Result<()> {
+    let content = match format {
+        ReportFormat::Html => render_upgrade_report_html(plan),
+        ReportFormat::Json => serde_json::to_string_pretty(plan)? + "\n",
+        ReportFormat::Markdown => render_upgrade_plan_markdown(plan),
+    };
+    std::fs::write(path, content)?;
+    status!(
+        "{} Wrote upgrade report to {}",
+        success_mark(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Resolves the newest release of a package that can be installed
+///
+/// Yanked releases, releases without files, releases whose `requires_python`
+/// excludes the interpreter, releases outside the requested range and (unless
+/// allowed or explicitly requested by the range) pre-releases are skipped.
+pub(crate) fn resolve_latest_version(
+    python: &str,
+    name: &str,
+    python_version: &str,
+    range: Option<&str>,
+    allow_pre: bool,
+) -> Result<String> {
+    let allow_pre = allow_pre
+        || range.is_some_and(|r| {
+            r.split(',')
+                .any(|clause| is_prerelease(clause.trim_start_matches(SPECIFIER_CHARS)))
+        });
+
+    let metadata = fetch_project_json(python, name)?;
+    let releases = metadata["releases"].as_object().ok_or_else(|| {
+        PackageError::MetadataError(format!("No releases published for {}", name))
+    })?;
+
+    releases
+        .iter()
+        .filter(|(version, _)| allow_pre || !is_prerelease(version))
+        .filter(|(version, _)| range.is_none_or(|r| version_satisfies(version, r)))
+        .filter(|(_, files)| release_is_installable(files, python_version))
+        .map(|(version, _)| version.clone())
+        .max_by(|a, b| compare_versions(a, b))
+        .ok_or_else(|| {
+            PackageError::MetadataError(format!(
+                "No compatible release of {}{} found for Python {}",
+                name,
+                range.unwrap_or(""),
+                python_version
+            ))
+        })
+}
+
+/// Checks whether a release has a non-yanked file usable on the given Python version
+fn release_is_installable(files: &serde_json::Value, python_version: &str) -> bool {
+    files.as_array().is_some_and(|files| {
+        files.iter().any(|file| {
+            !file["yanked"].as_bool().unwrap_or(false)
+                && file["requires_python"]
+                    .as_str()
+                    .is_none_or(|spec| version_satisfies(python_version, spec))
+        })
+    })
+}
+
+/// Planning outcome for a single package
+enum PlannedUpgrade {
+    Upgrade(UpgradePlanEntry),
+    Held(HeldPackage),
+    UpToDate,
+}
+
+/// Plans the upgrade of a single package, honoring an optional constraint and its update policy
+fn plan_single_upgrade(
+    python: &str,
+    python_version: &str,
+    name: &str,
+    current_version: &str,
+    constraint: Option<&str>,
+    policy: UpdatePolicy,
+) -> Result<PlannedUpgrade> {
+    let latest = fetch_project_json(python, name)?;
+    let latest_version = latest["info"]["version"]
+        .as_str()
+        .ok_or_else(|| PackageError::MetadataError(format!("No version published for {}", name)))?
+        .to_string();
+
+    let is_newer = |version: &str| {
+        current_version == "unknown"
+            || compare_versions(version, current_version) == Ordering::Greater
+    };
+    if !is_newer(&latest_version) {
+        return Ok(PlannedUpgrade::UpToDate);
+    }
+
+    // The policy ceiling joins the constraint, and is named when it is what holds the package back
+    let ceiling = policy.ceiling(current_version);
+    let held_by_policy = ceiling
+        .as_deref()
+        .is_some_and(|ceiling| !version_satisfies(&latest_version, ceiling));
+    let constraint = match (constraint, ceiling) {
+        (Some(constraint), Some(ceiling)) => Some(format!("{},{}", constraint, ceiling)),
+        (constraint, ceiling) => constraint.map(str::to_string).or(ceiling),
+    };
+    let described = |constraint: &str| {
+        if held_by_policy {
+            format!("{} (update-policy {})", constraint, policy)
+        } else {
+            constraint.to_string()
+        }
+    };
+
+    let target_version = match constraint.as_deref() {
+        Some(constraint) if !version_satisfies(&latest_version, constraint) => {
+            let allowed =
+                resolve_latest_version(python, name, python_version, Some(constraint), false)
+                    .ok()
+                    .filter(|v| is_newer(v));
+            match allowed {
+                Some(version) => version,
+                None => {
+                    return Ok(PlannedUpgrade::Held(HeldPackage {
+                        name: name.to_string(),
+                        current_version: current_version.to_string(),
+                        latest_version: Some(latest_version),
+                        reason: format!("constraint {}", described(constraint)),
+                    }))
+                }
+            }
+        }
+        _ => latest_version.clone(),
+    };
+    let capped_by = (target_version != latest_version)
+        .then(|| constraint.as_deref().map(described))
+        .flatten();
+
+    let changelog_url = find_changelog_url(&latest["info"]["project_urls"])
+        .unwrap_or_else(|| format!("https://pypi.org/project/{}/{}/", name, target_version));
+
+    let fixed_vulnerabilities = if current_version == "unknown" {
+        Vec::new()
+    } else {
+        fixed_vulnerabilities(python, name, current_version, &target_version)
+    };
+
+    Ok(PlannedUpgrade::Upgrade(UpgradePlanEntry {
+        name: name.to_string(),
+        current_version: current_version.to_string(),
+        target_version,
+        latest_version,
+        constraint: capped_by,
+        changelog_url,
+        fixed_vulnerabilities,
+    }))
+}
+
+/// Advisories affecting one release of a package that another release fixes
+///
+/// Advisories are published per release, so the older release's document is
+/// the one consulted; when it cannot be fetched, none are reported.
+pub(crate) fn fixed_vulnerabilities(
+    python: &str,
+    name: &str,
+    from: &str,
+    to: &str,
+) -> Vec<Vulnerability> {
+    fetch_pypi_json(python, name, Some(from))
+        .map(|release| parse_vulnerabilities(&release["vulnerabilities"]))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|v| {
+            v.fixed_in
+                .iter()
+                .any(|fixed| compare_versions(fixed, to) != Ordering::Greater)
+        })
+        .collect()
+}
+
+/// Picks the changelog link out of a PyPI `project_urls` table
+pub(crate) fn find_changelog_url(project_urls: &serde_json::Value) -> Option<String> {
+    const KEYWORDS: [&str; 5] = [
+        "changelog",
+        "change log",
+        "changes",
+        "release notes",
+        "history",
+    ];
+
+    project_urls.as_object()?.iter().find_map(|(label, url)| {
+        let label = label.to_lowercase();
+        if KEYWORDS.iter().any(|k| label.contains(k)) {
+            url.as_str().map(str::to_string)
+        } else {
+            None
+        }
+    })
+}
+
+/// Parses the `vulnerabilities` array of a PyPI release document
+fn parse_vulnerabilities(value: &serde_json::Value) -> Vec<Vulnerability> {
+    value
+        .as_array()
+        .map(|entries| {
+            entries
+                .iter()
+                .map(|entry| Vulnerability {
+                    id: entry["id"].as_str().unwrap_or("unknown").to_string(),
+                    summary: entry["summary"]
+                        .as_str()
+                        .or_else(|| entry["details"].as_str())
+                        .unwrap_or("")
+                        .to_string(),
+                    fixed_in: entry["fixed_in"]
+                        .as_array()
+                        .map(|versions| {
+                            versions
+                                .iter()
+                                .filter_map(|v| v.as_str().map(str::to_string))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Escapes text for inclusion in an HTML document
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders an upgrade plan as a standalone HTML change proposal
+fn render_upgrade_report_html(plan: &UpgradePlan) -> String {
+    let mut rows = String::new();
+    for entry in &plan.upgrades {
+        let fixes = if entry.fixed_vulnerabilities.is_empty() {
+            "None".to_string()
+        } else {
+            entry
+                .fixed_vulnerabilities
+                .iter()
+                .map(|v| {
+                    format!(
+                        "<strong>{}</strong> {}",
+                        escape_html(&v.id),
+                        escape_html(&v.summary)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("<br>")
+        };
+        let target = match &entry.constraint {
+            Some(constraint) => format!(
+                "{} (held by constraint {}, latest {})",
+                escape_html(&entry.target_version),
+                escape_html(constraint),
+                escape_html(&entry.latest_version)
+            ),
+            None => escape_html(&entry.target_version),
+        };
+        rows.push_str(&format!(
+            "<tr><td>{}</td><td>{}</td><td>{}</td><td><a href=\"{}\">{}</a></td><td>{}</td></tr>\n",
+            escape_html(&entry.name),
+            escape_html(&entry.current_version),
+            target,
+            escape_html(&entry.changelog_url),
+            escape_html(&entry.changelog_url),
+            fixes
+        ));
+    }
+
+    let held = if plan.held.is_empty() {
+        String::new()
+    } else {
+        let rows: String = plan
+            .held
+            .iter()
+            .map(|h| {
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    escape_html(&h.name),
+                    escape_html(&h.current_version),
+                    escape_html(h.latest_version.as_deref().unwrap_or("unknown")),
+                    escape_html(&h.reason)
+                )
+            })
+            .collect();
+        format!(
+            "<h2>Held back</h2>\n<table>\n<tr><th>Package</th><th>Current</th><th>Latest</th><th>Held by</th></tr>\n{}</table>\n",
+            rows
+        )
+    };
+
+    let vulnerability_count: usize = plan
+        .upgrades
+        .iter()
+        .map(|e| e.fixed_vulnerabilities.len())
+        .sum();
+
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Package upgrade proposal</title>\n\
+<style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}td,th{{border:1px solid #ccc;padding:4px 8px;text-align:left}}</style>\n\
+</head>\n<body>\n<h1>Package upgrade proposal</h1>\n\
+<p>{} package(s) will be upgraded, fixing {} known vulnerabilit{}.</p>\n\
+<table>\n<tr><th>Package</th><th>Current</th><th>Target</th><th>Changelog</th><th>Vulnerabilities fixed</th></tr>\n\
+{}</table>\n{}</body>\n</html>\n",
+        plan.upgrades.len(),
+        vulnerability_count,
+        if vulnerability_count == 1 { "y" } else { "ies" },
+        rows,
+        held
+    )
+}
+
+/// Escapes text for a cell of a markdown table
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Renders an upgrade plan as markdown, e.g. for a pull request description
+///
+/// Every upgrade links the release on PyPI and, when the project publishes
+/// one, its changelog; held and unchecked packages follow in sections of
+/// their own.
+///
+/// # Arguments
+/// * `plan` - The upgrade plan
+///
+/// # Returns
+/// * `String` - The markdown document
+pub fn render_upgrade_plan_markdown(plan: &UpgradePlan) -> String {
+    let mut out = String::from("## Dependency upgrades\n\n");
+    if plan.upgrades.is_empty() {
+        out.push_str("All packages are up to date.\n");
+    } else {
+        let vulnerability_count: usize = plan
+            .upgrades
+            .iter()
+            .map(|e| e.fixed_vulnerabilities.len())
+            .sum();
+        out.push_str(&format!(
+            "{} package(s) to upgrade, fixing {} known vulnerabilit{}.\n\n",
+            plan.upgrades.len(),
+            vulnerability_count,
+            if vulnerability_count == 1 { "y" } else { "ies" }
+        ));
+        out.push_str("| Package | Current | New | Links | Vulnerabilities fixed |\n");
+        out.push_str("| --- | --- | --- | --- | --- |\n");
+        for entry in &plan.upgrades {
+            let release = format!(
+                "https://pypi.org/project/{}/{}/",
+                entry.name, entry.target_version
+            );
+            let mut links = format!("[release]({})", release);
+            if !entry.changelog_url.is_empty() && entry.changelog_url != release {
+                links.push_str(&format!(" · [changelog]({})", entry.changelog_url));
+            }
+            let target = match &entry.constraint {
+                Some(constraint) => format!(
+                    "{} (held by {}, latest {})",
+                    entry.target_version, constraint, entry.latest_version
+                ),
+                None => entry.target_version.clone(),
+            };
+            let fixes = if entry.fixed_vulnerabilities.is_empty() {
+                "-".to_string()
+            } else {
+                entry
+                    .fixed_vulnerabilities
+                    .iter()
+                    .map(|v| v.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            out.push_str(&format!(
+                "| {} | {} | {} | {} | {} |\n",
+                escape_markdown_cell(&entry.name),
+                escape_markdown_cell(&entry.current_version),
+                escape_markdown_cell(&target),
+                escape_markdown_cell(&links),
+                escape_markdown_cell(&fixes)
+            ));
+        }
+    }
+
+    if !plan.held.is_empty() {
+        out.push_str("\n### Held back\n\n| Package | Current | Latest | Held by |\n");
+        out.push_str("| --- | --- | --- | --- |\n");
+        for held in &plan.held {
+            out.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                escape_markdown_cell(&held.name),
+                escape_markdown_cell(&held.current_version),
+                escape_markdown_cell(held.latest_version.as_deref().unwrap_or("unknown")),
+                escape_markdown_cell(&held.reason)
+            ));
+        }
+    }
+    if !plan.unchecked.is_empty() {
+        out.push_str("\n### Not checked\n\n");
+        for unchecked in &plan.unchecked {
+            out.push_str(&format!("- {}: {}\n", unchecked.name, unchecked.error));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::Package;
+    use crate::session::{prompt_answer, PromptAnswer};
+    use crate::test_support::{python_runner, with_runner};
+
+    #[test]
+    fn test_upgrade_choices() {
+        let entry =
+            |name: &str, current: &str, target: &str, constraint: Option<&str>| UpgradePlanEntry {
+                name: name.to_string(),
+                current_version: current.to_string(),
+                target_version: target.to_string(),
+                latest_version: "2.0.0".to_string(),
+                constraint: constraint.map(str::to_string),
+                changelog_url: String::new(),
+                fixed_vulnerabilities: Vec::new(),
+            };
+        let plan = UpgradePlan {
+            upgrades: vec![
+                entry("click", "8.1.6", "8.1.7", None),
+                entry("numpy", "1.24.0", "1.26.4", Some("<2")),
+            ],
+            held: Vec::new(),
+            unchecked: Vec::new(),
+        };
+
+        assert_eq!(
+            upgrade_choice_labels(&plan),
+            vec![
+                "click   8.1.6 → 8.1.7",
+                "numpy  1.24.0 → 1.26.4  (latest 2.0.0)",
+            ]
+        );
+        let chosen = select_upgrades(&plan, &[1]);
+        assert_eq!(chosen.upgrades.len(), 1);
+        assert_eq!(chosen.upgrades[0].name, "numpy");
+    }
+
+    #[test]
+    fn test_plan_upgrades_lists_failed_lookups() {
+        let runner = python_runner()
+            .fail("/pypi/flask/json", 1, "TimeoutError: timed out")
+            .fail("/pypi/click/json", 1, "HTTP Error 404: Not Found");
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("flask".to_string(), "3.0.0".to_string()));
+        registry.add_package(Package::new("click".to_string(), "8.1.7".to_string()));
+        let names = ["flask".to_string(), "click".to_string()];
+
+        let plan = with_runner(runner, |_| {
+            plan_upgrades(
+                &names,
+                &HashMap::new(),
+                &UpdatePolicies::default(),
+                &registry,
+            )
+        })
+        .unwrap();
+
+        assert!(plan.upgrades.is_empty());
+        let unchecked: Vec<&str> = plan.unchecked.iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(unchecked, ["click", "flask"]);
+        assert!(plan.unchecked[1].error.contains("timed out"));
+    }
+
+    #[test]
+    fn test_mass_upgrades_need_confirmation() {
+        assert_eq!(prompt_answer(true, true, false), PromptAnswer::Yes);
+        assert_eq!(
+            prompt_answer(false, true, true),
+            PromptAnswer::Refuse("prompts are disabled in CI mode")
+        );
+        assert_eq!(
+            prompt_answer(false, false, false),
+            PromptAnswer::Refuse("stdin is not a terminal")
+        );
+        assert_eq!(prompt_answer(false, false, true), PromptAnswer::Ask);
+
+        // Nothing to upgrade, because the lookup failed, needs no confirmation even with --all
+        let runner = python_runner().fail("/pypi/jinja2/json", 1, "TimeoutError: timed out");
+        let mut registry = PackageRegistry::new();
+        registry.add_package(Package::new("jinja2".to_string(), "3.1.2".to_string()));
+        let plan = with_runner(runner, |_| {
+            plan_upgrades(
+                &["jinja2".to_string()],
+                &HashMap::new(),
+                &UpdatePolicies::default(),
+                &registry,
+            )
+        })
+        .unwrap();
+        assert!(!plan.needs_confirmation(true));
+
+        let entry = |name: &str| UpgradePlanEntry {
+            name: name.to_string(),
+            current_version: "1.0".to_string(),
+            target_version: "2.0".to_string(),
+            latest_version: "2.0".to_string(),
+            constraint: None,
+            changelog_url: String::new(),
+            fixed_vulnerabilities: Vec::new(),
+        };
+        let single = UpgradePlan {
+            upgrades: vec![entry("flask")],
+            ..UpgradePlan::default()
+        };
+        assert!(!single.needs_confirmation(false));
+        assert!(single.needs_confirmation(true));
+        let several = UpgradePlan {
+            upgrades: vec![entry("flask"), entry("click")],
+            ..UpgradePlan::default()
+        };
+        assert!(several.needs_confirmation(false));
+    }
+
+    #[test]
+    fn test_parse_report_spec() {
+        let (format, path) = parse_report_spec("html=out/report.html").unwrap();
+        assert_eq!(format, ReportFormat::Html);
+        assert_eq!(path, PathBuf::from("out/report.html"));
+
+        assert!(parse_report_spec("report.html").is_err());
+        assert!(parse_report_spec("pdf=report.pdf").is_err());
+        assert!(parse_report_spec("html=").is_err());
+
+        let (format, path) = parse_report_path("out/report.json").unwrap();
+        assert_eq!(format, ReportFormat::Json);
+        assert_eq!(path, PathBuf::from("out/report.json"));
+        let (format, _) = parse_report_path("upgrades.md").unwrap();
+        assert_eq!(format, ReportFormat::Markdown);
+        assert!(parse_report_path("report").is_err());
+        assert!(parse_report_path("report.pdf").is_err());
+    }
+
+    #[test]
+    fn test_render_upgrade_report_html() {
+        let plan = UpgradePlan {
+            upgrades: vec![UpgradePlanEntry {
+                name: "requests".to_string(),
+                current_version: "2.25.0".to_string(),
+                target_version: "2.32.3".to_string(),
+                latest_version: "2.32.3".to_string(),
+                constraint: None,
+                changelog_url: "https://example.com/changes?a=1&b=2".to_string(),
+                fixed_vulnerabilities: vec![Vulnerability {
+                    id: "PYSEC-2023-74".to_string(),
+                    summary: "Leaks <Proxy-Authorization> header".to_string(),
+                    fixed_in: vec!["2.31.0".to_string()],
+                }],
+            }],
+            held: vec![HeldPackage {
+                name: "numpy".to_string(),
+                current_version: "1.26.4".to_string(),
+                latest_version: Some("2.0.0".to_string()),
+                reason: "constraint <2".to_string(),
+            }],
+            unchecked: Vec::new(),
+        };
+
+        let html = render_upgrade_report_html(&plan);
+        assert!(html.contains("<td>2.25.0</td><td>2.32.3</td>"));
+        assert!(html.contains("a=1&amp;b=2"));
+        assert!(html.contains("&lt;Proxy-Authorization&gt;"));
+        assert!(html.contains("fixing 1 known vulnerability"));
+        assert!(
+            html.contains("<td>numpy</td><td>1.26.4</td><td>2.0.0</td><td>constraint &lt;2</td>")
+        );
+
+        let markdown = render_upgrade_plan_markdown(&plan);
+        assert!(markdown.contains(
+            "| requests | 2.25.0 | 2.32.3 | [release](https://pypi.org/project/requests/2.32.3/) \
+             · [changelog](https://example.com/changes?a=1&b=2) | PYSEC-2023-74 |\n"
+        ));
+        assert!(markdown.contains("| numpy | 1.26.4 | 2.0.0 | constraint <2 |\n"));
+    }
+
+    #[test]
+    fn test_render_upgrade_plan_markdown() {
+        let entry = |name: &str, current: &str, target: &str| UpgradePlanEntry {
+            name: name.to_string(),
+            current_version: current.to_string(),
+            target_version: target.to_string(),
+            latest_version: target.to_string(),
+            constraint: None,
+            changelog_url: String::new(),
+            fixed_vulnerabilities: Vec::new(),
+        };
+        let plan = UpgradePlan {
+            upgrades: vec![
+                entry("flask", "2.3.3", "3.0.3"),
+                entry("odd|name", "1.0|local", "2.0|local"),
+            ],
+            ..UpgradePlan::default()
+        };
+
+        let markdown = render_upgrade_plan_markdown(&plan);
+        assert!(markdown.starts_with(
+            "## Dependency upgrades\n\n\
+             2 package(s) to upgrade, fixing 0 known vulnerabilities.\n\n\
+             | Package | Current | New | Links | Vulnerabilities fixed |\n\
+             | --- | --- | --- | --- | --- |\n"
+        ));
+        let rows: Vec<&str> = markdown
+            .lines()
+            .skip_while(|line| !line.starts_with("| ---"))
+            .skip(1)
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert!(rows[0].starts_with("| flask | 2.3.3 | 3.0.3 | "));
+        assert_eq!(
+            rows[1],
+            "| odd\\|name | 1.0\\|local | 2.0\\|local | \
+             [release](https://pypi.org/project/odd\\|name/2.0\\|local/) | - |"
+        );
+        // Every row has its five cells, whatever the names and versions hold
+        for row in rows {
+            assert_eq!(row.replace("\\|", "").matches('|').count(), 6, "{}", row);
+        }
+
+        assert_eq!(
+            render_upgrade_plan_markdown(&UpgradePlan::default()),
+            "## Dependency upgrades\n\nAll packages are up to date.\n"
+        );
+    }
+}
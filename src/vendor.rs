@@ -0,0 +1,471 @@
+//! Vendored `--target` directories for zip deployments such as AWS Lambda
+//!
+//! `ppm vendor-install` installs a requirements file into a plain directory
+//! with `pip install --target` and records what it put there in the
+//! `vendored` section of `packages.json`. Later runs sync the directory: pip
+//! replaces the packages it installs, and distributions the requirements no
+//! longer need are removed file by file using their `RECORD`.
+
+use crate::pip::{
+    get_python_executable, pip_error, request_pip_report, require_pip_feature, run_pip,
+    take_pip_report, PipFeature,
+};
+use crate::registry::{Package, PackageRegistry, VendoredSet};
+use crate::spec::canonicalize_name;
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+/// A distribution found in a vendored directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct VendoredDistribution {
+    /// Name from the distribution's metadata
+    pub name: String,
+    /// Version from the distribution's metadata
+    pub version: String,
+    /// Its `.dist-info` directory
+    pub dist_info: PathBuf,
+}
+
+/// What a vendor install changed in the directory
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct VendorSummary {
+    /// The vendored directory
+    pub dir: PathBuf,
+    /// Packages that were not there before, with their versions
+    pub added: Vec<(String, String)>,
+    /// Packages whose version changed: name, old version, new version
+    pub changed: Vec<(String, String, String)>,
+    /// Packages that were removed, with the versions they had
+    pub removed: Vec<(String, String)>,
+    /// Number of packages that kept their version
+    pub unchanged: usize,
+}
+
+/// Key of a vendored directory in the registry: the path without `.` components
+///
+/// # Arguments
+/// * `dir` - The directory as given, e.g. "./vendor"
+///
+/// # Returns
+/// * `String` - The key, e.g. "vendor"
+pub fn vendor_key(dir: &Path) -> String {
+    let path: PathBuf = dir
+        .components()
+        .filter(|component| !matches!(component, Component::CurDir))
+        .collect();
+    path.to_string_lossy().into_owned()
+}
+
+/// Reads the `Name` and `Version` headers of a distribution's `METADATA`
+fn parse_metadata_headers(metadata: &str) -> Option<(String, String)> {
+    let mut name = None;
+    let mut version = None;
+    for line in metadata.lines() {
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Name:") {
+            name = Some(value.trim().to_string());
+        } else if let Some(value) = line.strip_prefix("Version:") {
+            version = Some(value.trim().to_string());
+        }
+    }
+    Some((name?, version?))
+}
+
+/// Lists the distributions installed in a vendored directory
+///
+/// # Arguments
+/// * `dir` - The directory pip installed into with `--target`
+///
+/// # Returns
+/// * `Result<Vec<VendoredDistribution>>` - The distributions sorted by name,
+///   none if the directory does not exist
+pub fn vendored_distributions(dir: &Path) -> Result<Vec<VendoredDistribution>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut distributions = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "dist-info")
+        {
+            continue;
+        }
+        let Ok(metadata) = std::fs::read_to_string(path.join("METADATA")) else {
+            continue;
+        };
+        if let Some((name, version)) = parse_metadata_headers(&metadata) {
+            distributions.push(VendoredDistribution {
+                name,
+                version,
+                dist_info: path,
+            });
+        }
+    }
+    distributions.sort_by(|a, b| {
+        canonicalize_name(&a.name)
+            .cmp(&canonicalize_name(&b.name))
+            .then_with(|| a.dist_info.cmp(&b.dist_info))
+    });
+    Ok(distributions)
+}
+
+/// Parses a `RECORD` file into the paths it lists, relative to the install directory
+///
+/// Each line is `path,hash,size`; the path may be quoted as in CSV, and since
+/// hashes and sizes never contain commas, everything before the last two
+/// commas is the path.
+pub fn parse_record(record: &str) -> Vec<PathBuf> {
+    record
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.rsplitn(3, ',');
+            let (Some(_), Some(_), Some(path)) = (fields.next(), fields.next(), fields.next())
+            else {
+                return None;
+            };
+            let path = path
+                .strip_prefix('"')
+                .and_then(|path| path.strip_suffix('"'))
+                .map(|path| path.replace("\"\"", "\""))
+                .unwrap_or_else(|| path.to_string());
+            (!path.is_empty()).then(|| PathBuf::from(path))
+        })
+        .collect()
+}
+
+/// Resolves a `RECORD` path to a path inside the vendored directory
+///
+/// pip installs into a temporary `lib/python` directory and then moves its
+/// contents, and everything next to `lib` such as `bin`, into the target. The
+/// paths are relative to `lib/python`, so `../../bin/wheel` is `bin/wheel`.
+/// Paths that would leave the directory give None, so a damaged or malicious
+/// `RECORD` cannot make ppm delete anything outside it.
+fn vendored_path(record_path: &Path) -> Option<PathBuf> {
+    let mut parts = vec![OsStr::new("lib"), OsStr::new("python")];
+    for component in record_path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::ParentDir => {
+                parts.pop()?;
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let parts = match parts.as_slice() {
+        [lib, python, rest @ ..] if *lib == "lib" && *python == "python" => rest,
+        parts => parts,
+    };
+    (!parts.is_empty()).then(|| parts.iter().collect())
+}
+
+/// Files of a distribution, resolved inside the vendored directory
+fn distribution_files(dir: &Path, distribution: &VendoredDistribution) -> Vec<PathBuf> {
+    let record = std::fs::read_to_string(distribution.dist_info.join("RECORD")).unwrap_or_default();
+    parse_record(&record)
+        .iter()
+        .filter_map(|path| vendored_path(path))
+        .map(|path| dir.join(path))
+        .collect()
+}
+
+/// Removes distributions from a vendored directory
+///
+/// Files that a kept distribution also lists, e.g. because a newer version of
+/// the same package was installed over the old one, stay in place. Directories
+/// left empty are removed as well.
+///
+/// # Arguments
+/// * `dir` - The vendored directory
+/// * `remove` - The distributions to remove
+/// * `keep` - The distributions that stay
+///
+/// # Returns
+/// * `Result<()>` - Success, or the error of the first file that could not be removed
+pub fn remove_vendored(
+    dir: &Path,
+    remove: &[VendoredDistribution],
+    keep: &[VendoredDistribution],
+) -> Result<()> {
+    let kept: BTreeSet<PathBuf> = keep
+        .iter()
+        .flat_map(|distribution| distribution_files(dir, distribution))
+        .collect();
+    let mut parents = BTreeSet::new();
+    for distribution in remove {
+        for file in distribution_files(dir, distribution) {
+            if kept.contains(&file) || !file.is_file() {
+                continue;
+            }
+            std::fs::remove_file(&file)?;
+            if let Some(parent) = file.parent() {
+                parents.insert(parent.to_path_buf());
+            }
+        }
+        if distribution.dist_info.is_dir() {
+            std::fs::remove_dir_all(&distribution.dist_info)?;
+        }
+    }
+    // Deepest directories first, so emptied parents can go too
+    for parent in parents.iter().rev() {
+        for ancestor in parent.ancestors() {
+            if ancestor == dir || !ancestor.starts_with(dir) {
+                break;
+            }
+            if std::fs::remove_dir(ancestor).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Installs a requirements file into a vendored directory and syncs it
+///
+/// pip installs everything with `--target --upgrade`; distributions in the
+/// directory that it did not install this time are removed afterwards. With
+/// `clean`, every vendored distribution is removed first, and without a
+/// requirements file the directory is only cleaned and no longer tracked.
+///
+/// # Arguments
+/// * `requirements` - Requirements file to install; unless cleaning, defaults
+///   to the one the directory was last synced to
+/// * `dir` - The vendored directory, created if needed
+/// * `clean` - Remove what the directory holds before installing
+/// * `pip_args` - Extra arguments for pip, e.g. `--platform manylinux2014_x86_64 --only-binary=:all:`
+/// * `registry` - Mutable reference to the package registry
+///
+/// # Returns
+/// * `Result<VendorSummary>` - What changed, or an error if pip failed
+pub fn vendor_install(
+    requirements: Option<&str>,
+    dir: &Path,
+    clean: bool,
+    pip_args: &[String],
+    registry: &mut PackageRegistry,
+) -> Result<VendorSummary> {
+    let key = vendor_key(dir);
+    let requirements = requirements.map(str::to_string).or_else(|| {
+        (!clean)
+            .then(|| registry.vendored.get(&key)?.requirements.clone())
+            .flatten()
+    });
+    if requirements.is_none() && !clean {
+        return Err(PackageError::InvalidPackageSpec(format!(
+            "No requirements file given and {} was never vendored; pass -r FILE",
+            dir.display()
+        )));
+    }
+
+    let before = vendored_distributions(dir)?;
+    let mut summary = VendorSummary {
+        dir: dir.to_path_buf(),
+        ..VendorSummary::default()
+    };
+    if clean {
+        status!(
+            "Removing {} vendored packages from {}...",
+            before.len(),
+            dir.display()
+        );
+        remove_vendored(dir, &before, &[])?;
+    }
+    let Some(requirements) = requirements else {
+        summary.removed = before.into_iter().map(|d| (d.name, d.version)).collect();
+        registry.vendored.remove(&key);
+        return Ok(summary);
+    };
+
+    let python = get_python_executable()?;
+    require_pip_feature(&python, PipFeature::InstallReport, "Vendoring")?;
+    std::fs::create_dir_all(dir)?;
+    status!("Installing {} into {}...", requirements, dir.display());
+    let mut command = Command::new(&python);
+    command
+        .args(["-m", "pip", "install", "--target"])
+        .arg(dir)
+        .args(["--upgrade", "-r", &requirements])
+        .args(pip_args)
+        // The directory is given explicitly; local packages must not redirect it
+        .env_remove("PIP_TARGET");
+    let report = request_pip_report(&python, &mut command);
+    let output = run_pip(command, &requirements, None)?;
+    if !output.status.success() {
+        let _ = take_pip_report(report);
+        return Err(pip_error(
+            &requirements,
+            &output,
+            PackageError::InstallationFailed,
+        ));
+    }
+    let installs = take_pip_report(report);
+
+    // Old dist-info directories survive pip replacing a package with another version
+    let installed: HashMap<String, String> = installs
+        .iter()
+        .map(|install| (canonicalize_name(&install.name), install.version.clone()))
+        .collect();
+    let (keep, stale): (Vec<_>, Vec<_>) =
+        vendored_distributions(dir)?
+            .into_iter()
+            .partition(|distribution| {
+                installed.get(&canonicalize_name(&distribution.name)) == Some(&distribution.version)
+            });
+    remove_vendored(dir, &stale, &keep)?;
+
+    let previous: HashMap<String, String> = if clean {
+        HashMap::new()
+    } else {
+        before
+            .iter()
+            .map(|d| (canonicalize_name(&d.name), d.version.clone()))
+            .collect()
+    };
+    for install in &installs {
+        match previous.get(&canonicalize_name(&install.name)) {
+            None => summary
+                .added
+                .push((install.name.clone(), install.version.clone())),
+            Some(old) if *old != install.version => {
+                summary
+                    .changed
+                    .push((install.name.clone(), old.clone(), install.version.clone()))
+            }
+            Some(_) => summary.unchanged += 1,
+        }
+    }
+    summary.removed = before
+        .into_iter()
+        .filter(|d| !installed.contains_key(&canonicalize_name(&d.name)))
+        .map(|d| (d.name, d.version))
+        .collect();
+    summary.added.sort();
+    summary.changed.sort();
+
+    let packages = installs
+        .into_iter()
+        .map(|install| {
+            let mut package = Package::new(canonicalize_name(&install.name), install.version);
+            package.explicit = install.requested;
+            package.origin = install.origin;
+            (package.name.clone(), package)
+        })
+        .collect();
+    registry.vendored.insert(
+        key,
+        VendoredSet {
+            requirements: Some(requirements),
+            packages,
+        },
+    );
+    Ok(summary)
+}
+
+/// Renders what a vendor install changed
+///
+/// # Arguments
+/// * `summary` - The changes
+///
+/// # Returns
+/// * `String` - One line per changed package and a closing count
+pub fn render_vendor_summary(summary: &VendorSummary) -> String {
+    let mut out = String::new();
+    for (name, version) in &summary.added {
+        out.push_str(&format!("  + {} {}\n", name, version));
+    }
+    for (name, old, new) in &summary.changed {
+        out.push_str(&format!("  ~ {} {} -> {}\n", name, old, new));
+    }
+    for (name, version) in &summary.removed {
+        out.push_str(&format!("  - {} {}\n", name, version));
+    }
+    out.push_str(&format!(
+        "{}: {} added, {} changed, {} removed, {} unchanged\n",
+        summary.dir.display(),
+        summary.added.len(),
+        summary.changed.len(),
+        summary.removed.len(),
+        summary.unchanged
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vendor(dir: &Path, name: &str, version: &str, files: &[&str]) -> VendoredDistribution {
+        let dist_info = dir.join(format!("{}-{}.dist-info", name, version));
+        std::fs::create_dir_all(&dist_info).unwrap();
+        std::fs::write(
+            dist_info.join("METADATA"),
+            format!(
+                "Metadata-Version: 2.1\nName: {}\nVersion: {}\n\nDescription",
+                name, version
+            ),
+        )
+        .unwrap();
+        let mut record = String::new();
+        for file in files {
+            if let Some(path) = vendored_path(Path::new(file)) {
+                let path = dir.join(path);
+                std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+                std::fs::write(&path, "").unwrap();
+            }
+            record.push_str(&format!("{},sha256=abc,0\n", file));
+        }
+        record.push_str(&format!("{}-{}.dist-info/RECORD,,\n", name, version));
+        std::fs::write(dist_info.join("RECORD"), record).unwrap();
+        VendoredDistribution {
+            name: name.to_string(),
+            version: version.to_string(),
+            dist_info,
+        }
+    }
+
+    #[test]
+    fn test_remove_vendored() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("vendor");
+        let old_six = vendor(&dir, "six", "1.16.0", &["six.py"]);
+        let new_six = vendor(&dir, "six", "1.17.0", &["six.py"]);
+        let gone = vendor(
+            &dir,
+            "Gone_Pkg",
+            "2.0",
+            &[
+                "gone/__init__.py",
+                "gone/sub/mod.py",
+                "../../bin/gone",
+                "../../../outside.txt",
+            ],
+        );
+        std::fs::write(root.path().join("outside.txt"), "").unwrap();
+
+        let found = vendored_distributions(&dir).unwrap();
+        assert_eq!(found, [gone.clone(), old_six.clone(), new_six.clone()]);
+        assert_eq!(
+            parse_record("\"a,b.py\",sha256=x,1\nc.py,,\n"),
+            [PathBuf::from("a,b.py"), PathBuf::from("c.py")]
+        );
+
+        remove_vendored(&dir, &[old_six, gone], std::slice::from_ref(&new_six)).unwrap();
+        assert!(dir.join("six.py").exists());
+        assert!(new_six.dist_info.exists());
+        assert!(!dir.join("six-1.16.0.dist-info").exists());
+        assert!(!dir.join("gone").exists());
+        assert!(!dir.join("bin").exists());
+        assert!(!dir.join("Gone_Pkg-2.0.dist-info").exists());
+        assert!(root.path().join("outside.txt").exists());
+        assert_eq!(vendored_distributions(&dir).unwrap(), [new_six]);
+        assert_eq!(vendor_key(Path::new("./build/vendor")), "build/vendor");
+    }
+}
@@ -0,0 +1,274 @@
+//! Notifications about outdated and vulnerable packages
+//!
+//! A `[webhook]` table in `ppm.toml` names a URL that `ppm outdated` posts a
+//! summary to whenever it finds outdated packages or known vulnerabilities,
+//! so a scheduled run can alert the team. Slack and Microsoft Teams incoming
+//! webhooks get a chat message; any other URL gets a generic JSON document.
+
+use crate::http::http_client;
+use crate::pip::get_python_executable;
+use crate::{Result, UpgradePlan};
+use serde::{Deserialize, Serialize};
+
+/// Shape of the document posted to a webhook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// Slack incoming webhook message
+    Slack,
+    /// Microsoft Teams incoming webhook message card
+    Teams,
+    /// The summary as plain JSON, for other services and scripts
+    Generic,
+}
+
+/// The `[webhook]` table of `ppm.toml`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct WebhookConfig {
+    /// Where the summary is posted
+    pub url: String,
+    /// Payload shape; guessed from the URL when left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<WebhookFormat>,
+}
+
+impl WebhookConfig {
+    /// The configured format, or the one the URL's host calls for
+    pub fn format(&self) -> WebhookFormat {
+        if let Some(format) = self.format {
+            return format;
+        }
+        let host = self
+            .url
+            .split("://")
+            .nth(1)
+            .unwrap_or(&self.url)
+            .split(['/', ':'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+        if host == "hooks.slack.com" {
+            WebhookFormat::Slack
+        } else if host.ends_with(".webhook.office.com") || host.ends_with(".logic.azure.com") {
+            WebhookFormat::Teams
+        } else {
+            WebhookFormat::Generic
+        }
+    }
+}
+
+/// Counts of what an outdated check found, shared by every payload
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OutdatedSummary {
+    /// Project the check ran in
+    pub project: String,
+    /// Outdated packages, including held ones
+    pub outdated: usize,
+    /// Known vulnerabilities of the installed versions that an upgrade fixes
+    pub vulnerabilities: usize,
+    /// One line per outdated package, e.g. "django 4.2.1 -> 4.2.11 (fixes GHSA-...)"
+    pub packages: Vec<String>,
+}
+
+impl OutdatedSummary {
+    /// Summarizes an upgrade plan
+    ///
+    /// # Arguments
+    /// * `project` - Name of the project, shown in the message
+    /// * `plan` - The upgrade plan of every registered package
+    ///
+    /// # Returns
+    /// * `Option<OutdatedSummary>` - The summary, or None when nothing is outdated or vulnerable
+    pub fn from_plan(project: &str, plan: &UpgradePlan) -> Option<Self> {
+        let mut packages = Vec::new();
+        let mut vulnerabilities = 0;
+        for entry in &plan.upgrades {
+            vulnerabilities += entry.fixed_vulnerabilities.len();
+            let fixes = if entry.fixed_vulnerabilities.is_empty() {
+                String::new()
+            } else {
+                let ids: Vec<&str> = entry
+                    .fixed_vulnerabilities
+                    .iter()
+                    .map(|v| v.id.as_str())
+                    .collect();
+                format!(" (fixes {})", ids.join(", "))
+            };
+            packages.push(format!(
+                "{} {} -> {}{}",
+                entry.name, entry.current_version, entry.target_version, fixes
+            ));
+        }
+        for held in &plan.held {
+            let latest = held
+                .latest_version
+                .as_ref()
+                .map(|v| format!(", latest {}", v))
+                .unwrap_or_default();
+            packages.push(format!(
+                "{} {} (held by {}{})",
+                held.name, held.current_version, held.reason, latest
+            ));
+        }
+
+        if packages.is_empty() {
+            return None;
+        }
+        Some(Self {
+            project: project.to_string(),
+            outdated: packages.len(),
+            vulnerabilities,
+            packages,
+        })
+    }
+
+    /// One-line headline, e.g. "api: 3 outdated package(s), 2 known vulnerabilities"
+    pub fn title(&self) -> String {
+        let mut title = format!("{}: {} outdated package(s)", self.project, self.outdated);
+        if self.vulnerabilities > 0 {
+            title.push_str(&format!(
+                ", {} known vulnerabilit{}",
+                self.vulnerabilities,
+                if self.vulnerabilities == 1 {
+                    "y"
+                } else {
+                    "ies"
+                }
+            ));
+        }
+        title
+    }
+}
+
+/// Builds the document posted to a webhook
+///
+/// # Arguments
+/// * `format` - Shape the receiving service expects
+/// * `summary` - What the check found
+///
+/// # Returns
+/// * `serde_json::Value` - The request body
+pub fn webhook_payload(format: WebhookFormat, summary: &OutdatedSummary) -> serde_json::Value {
+    match format {
+        WebhookFormat::Slack => {
+            let lines: Vec<String> = summary
+                .packages
+                .iter()
+                .map(|line| format!("• {}", line))
+                .collect();
+            serde_json::json!({
+                "text": format!("*{}*\n{}", summary.title(), lines.join("\n")),
+            })
+        }
+        WebhookFormat::Teams => {
+            let lines: Vec<String> = summary
+                .packages
+                .iter()
+                .map(|line| format!("- {}", line))
+                .collect();
+            serde_json::json!({
+                "@type": "MessageCard",
+                "@context": "https://schema.org/extensions",
+                "summary": summary.title(),
+                "title": summary.title(),
+                "text": lines.join("\n\n"),
+            })
+        }
+        WebhookFormat::Generic => serde_json::json!({
+            "event": "outdated",
+            "summary": summary,
+        }),
+    }
+}
+
+/// Posts a summary to the configured webhook
+///
+/// # Arguments
+/// * `config` - The webhook settings of `ppm.toml`
+/// * `summary` - What the check found
+///
+/// # Returns
+/// * `Result<()>` - Success, or an error if the request failed
+pub fn send_webhook(config: &WebhookConfig, summary: &OutdatedSummary) -> Result<()> {
+    let python = get_python_executable()?;
+    http_client().post_json(
+        &python,
+        &config.url,
+        &webhook_payload(config.format(), summary),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HeldPackage, UpgradePlanEntry, Vulnerability};
+
+    #[test]
+    fn test_outdated_summary_payloads() {
+        assert!(OutdatedSummary::from_plan("api", &UpgradePlan::default()).is_none());
+
+        let plan = UpgradePlan {
+            upgrades: vec![UpgradePlanEntry {
+                name: "django".to_string(),
+                current_version: "4.2.1".to_string(),
+                target_version: "4.2.11".to_string(),
+                latest_version: "5.0.3".to_string(),
+                constraint: Some("<5".to_string()),
+                changelog_url: String::new(),
+                fixed_vulnerabilities: vec![Vulnerability {
+                    id: "GHSA-1234".to_string(),
+                    summary: "SQL injection".to_string(),
+                    fixed_in: vec!["4.2.2".to_string()],
+                }],
+            }],
+            held: vec![HeldPackage {
+                name: "numpy".to_string(),
+                current_version: "1.26.0".to_string(),
+                latest_version: Some("2.0.0".to_string()),
+                reason: "pin".to_string(),
+            }],
+            unchecked: Vec::new(),
+        };
+        let summary = OutdatedSummary::from_plan("api", &plan).unwrap();
+        assert_eq!(
+            summary.title(),
+            "api: 2 outdated package(s), 1 known vulnerability"
+        );
+        assert_eq!(
+            summary.packages,
+            [
+                "django 4.2.1 -> 4.2.11 (fixes GHSA-1234)",
+                "numpy 1.26.0 (held by pin, latest 2.0.0)"
+            ]
+        );
+
+        let slack = webhook_payload(WebhookFormat::Slack, &summary);
+        assert_eq!(
+            slack["text"],
+            "*api: 2 outdated package(s), 1 known vulnerability*\n\
+             • django 4.2.1 -> 4.2.11 (fixes GHSA-1234)\n\
+             • numpy 1.26.0 (held by pin, latest 2.0.0)"
+        );
+        let generic = webhook_payload(WebhookFormat::Generic, &summary);
+        assert_eq!(generic["summary"]["vulnerabilities"], 1);
+
+        let config = |url: &str| WebhookConfig {
+            url: url.to_string(),
+            format: None,
+        };
+        assert_eq!(
+            config("https://hooks.slack.com/services/T0/B0/x").format(),
+            WebhookFormat::Slack
+        );
+        assert_eq!(
+            config("https://contoso.webhook.office.com/webhookb2/x").format(),
+            WebhookFormat::Teams
+        );
+        assert_eq!(
+            config("http://localhost:8080/hook").format(),
+            WebhookFormat::Generic
+        );
+    }
+}
@@ -0,0 +1,302 @@
+//! Running an operation in every member of a workspace
+
+use crate::project::{create_virtualenv, venv_python, ProjectConfig, PROJECT_CONFIG_FILE};
+use crate::registry::{load_packages, save_packages, PackageRegistry};
+use crate::reporter::{json_output, reporter};
+use crate::session::{error_summary, set_python_override, set_report_data};
+use crate::stats::format_duration;
+use crate::{PackageError, Result};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// A sub-project of a workspace
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorkspaceMember {
+    /// Member directory
+    pub path: PathBuf,
+    /// The member's `ppm.toml`, or defaults named after the directory
+    pub config: ProjectConfig,
+}
+
+impl WorkspaceMember {
+    /// Requirements file of the member, relative to its directory
+    pub fn requirements(&self) -> &str {
+        self.config
+            .requirements
+            .as_deref()
+            .unwrap_or("requirements.txt")
+    }
+}
+
+/// Lists the members of the workspace rooted at a directory
+///
+/// # Arguments
+/// * `root` - Directory holding the workspace `ppm.toml`
+///
+/// # Returns
+/// * `Result<Vec<WorkspaceMember>>` - Members in configuration order, or an error
+///   if the directory is not a workspace root
+pub fn workspace_members(root: &Path) -> Result<Vec<WorkspaceMember>> {
+    let workspace = ProjectConfig::load(root)?
+        .and_then(|config| config.workspace)
+        .ok_or_else(|| {
+            PackageError::MetadataError(format!(
+                "{} has no [workspace] table listing members",
+                root.join(PROJECT_CONFIG_FILE).display()
+            ))
+        })?;
+
+    let mut paths = Vec::new();
+    for member in &workspace.members {
+        match member.strip_suffix("/*") {
+            Some(parent) => {
+                let mut children: Vec<PathBuf> = std::fs::read_dir(root.join(parent))?
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|path| path.is_dir())
+                    .collect();
+                children.sort();
+                paths.extend(children);
+            }
+            None => paths.push(root.join(member)),
+        }
+    }
+
+    paths
+        .into_iter()
+        .map(|path| {
+            if !path.is_dir() {
+                return Err(PackageError::MetadataError(format!(
+                    "Workspace member {} is not a directory",
+                    path.display()
+                )));
+            }
+            let mut config = ProjectConfig::load(&path)?.unwrap_or_default();
+            if config.name.is_empty() {
+                config.name = path
+                    .file_name()
+                    .map_or_else(String::new, |n| n.to_string_lossy().into_owned());
+            }
+            Ok(WorkspaceMember { path, config })
+        })
+        .collect()
+}
+
+/// Result of running an operation in one workspace member
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorkspaceMemberReport {
+    /// Member name
+    pub member: String,
+    /// Member directory
+    pub path: PathBuf,
+    /// Error message if the operation failed
+    pub error: Option<String>,
+    /// Packages in the member's registry afterwards
+    pub packages: usize,
+    /// Duration in milliseconds
+    pub millis: u64,
+}
+
+/// Switches into a workspace member and back out again when dropped
+///
+/// Dropping restores the working directory and clears the Python override,
+/// so every way out of a member, an error or a panic included, leaves the
+/// process where it started.
+struct MemberDirGuard {
+    original: PathBuf,
+}
+
+impl MemberDirGuard {
+    /// Makes `dir` the working directory until the guard is dropped
+    fn enter(dir: &Path) -> Result<Self> {
+        let original = std::env::current_dir()?;
+        std::env::set_current_dir(dir)?;
+        Ok(MemberDirGuard { original })
+    }
+}
+
+impl Drop for MemberDirGuard {
+    fn drop(&mut self) {
+        set_python_override(None);
+        if let Err(e) = std::env::set_current_dir(&self.original) {
+            warning!("Could not return to {}: {}", self.original.display(), e);
+        }
+    }
+}
+
+/// Runs an operation in every workspace member with its own registry and environment
+///
+/// For each member the working directory is switched to the member, so its
+/// `packages.json` is used, and pip runs in the member's virtual environment
+/// when `ppm.toml` names one (creating it if missing). The registry is saved
+/// only when the operation succeeds. The working directory is restored after
+/// each member, whether or not the operation succeeds.
+///
+/// # Arguments
+/// * `root` - Workspace root directory
+/// * `operation` - Work to do in each member
+///
+/// # Returns
+/// * `Result<Vec<WorkspaceMemberReport>>` - One report per member
+pub fn run_in_workspace<F>(root: &Path, mut operation: F) -> Result<Vec<WorkspaceMemberReport>>
+where
+    F: FnMut(&WorkspaceMember, &mut PackageRegistry) -> Result<()>,
+{
+    let members = workspace_members(root)?;
+    let mut reports = Vec::with_capacity(members.len());
+
+    for member in &members {
+        status!("\n==> {} ({})", member.config.name, member.path.display());
+        let started = Instant::now();
+        let result = (|| {
+            let _guard = MemberDirGuard::enter(&member.path)?;
+            if let Some(venv) = &member.config.venv {
+                if !venv.exists() {
+                    status!("Creating virtual environment in {}...", venv.display());
+                    create_virtualenv(venv)?;
+                }
+                set_python_override(Some(venv_python(&member.path.join(venv))));
+            }
+            let mut registry = load_packages()?;
+            operation(member, &mut registry)?;
+            save_packages(&registry)?;
+            Ok(registry.packages.len())
+        })();
+
+        reports.push(WorkspaceMemberReport {
+            member: member.config.name.clone(),
+            path: member.path.clone(),
+            error: result.as_ref().err().map(|e: &PackageError| e.to_string()),
+            packages: *result.as_ref().unwrap_or(&0),
+            millis: started.elapsed().as_millis() as u64,
+        });
+    }
+    Ok(reports)
+}
+
+/// Renders the consolidated report of a workspace operation
+///
+/// # Arguments
+/// * `reports` - One report per member
+///
+/// # Returns
+/// A table with one row per member, a success count and the failures
+pub fn render_workspace_report(reports: &[WorkspaceMemberReport]) -> String {
+    let member_width = reports
+        .iter()
+        .map(|r| r.member.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    let mut out = format!(
+        "\nWorkspace summary:\n  {:<member_width$}  {:<6}  {:>8}  {:>8}\n",
+        "Member", "Status", "Packages", "Duration"
+    );
+    for report in reports {
+        let (status, packages) = match report.error {
+            Some(_) => ("failed", "-".to_string()),
+            None => ("ok", report.packages.to_string()),
+        };
+        out.push_str(&format!(
+            "  {:<member_width$}  {:<6}  {:>8}  {:>8}\n",
+            report.member,
+            status,
+            packages,
+            format_duration(report.millis)
+        ));
+    }
+
+    let failed: Vec<&WorkspaceMemberReport> =
+        reports.iter().filter(|r| r.error.is_some()).collect();
+    out.push_str(&format!(
+        "\n{} succeeded, {} failed\n",
+        reports.len() - failed.len(),
+        failed.len()
+    ));
+    if !failed.is_empty() {
+        out.push_str("\nFailures:\n");
+        for report in failed {
+            let reason = report.error.as_deref().map_or("", error_summary);
+            out.push_str(&format!("  {}: {}\n", report.member, reason));
+        }
+    }
+    out
+}
+
+/// Prints a workspace report and turns failed members into an error
+///
+/// # Arguments
+/// * `reports` - One report per member
+/// * `verb` - What the operation did, e.g. "sync"
+///
+/// # Returns
+/// * `Result<()>` - Success, a partial failure, or a total failure
+pub fn summarize_workspace(reports: &[WorkspaceMemberReport], verb: &str) -> Result<()> {
+    if json_output() {
+        set_report_data(serde_json::json!({ "members": reports }));
+    }
+    reporter().output(&render_workspace_report(reports));
+
+    let failed = reports.iter().filter(|r| r.error.is_some()).count();
+    if failed == 0 {
+        Ok(())
+    } else if failed < reports.len() {
+        Err(PackageError::PartialFailure(
+            format!("workspace members failed to {}", verb),
+            failed,
+            reports.len(),
+        ))
+    } else {
+        Err(PackageError::InstallationFailed(format!(
+            "{} workspace members failed to {}",
+            failed, verb
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_workspace_members_and_report() {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::write(
+            root.path().join("ppm.toml"),
+            "[workspace]\nmembers = [\"tools\", \"services/*\"]\n",
+        )
+        .unwrap();
+        for dir in ["tools", "services/api", "services/worker"] {
+            std::fs::create_dir_all(root.path().join(dir)).unwrap();
+        }
+        std::fs::write(
+            root.path().join("services/api/ppm.toml"),
+            "name = \"api\"\nrequirements = \"requirements/prod.txt\"\n",
+        )
+        .unwrap();
+
+        let members = workspace_members(root.path()).unwrap();
+        let names: Vec<&str> = members.iter().map(|m| m.config.name.as_str()).collect();
+        assert_eq!(names, vec!["tools", "api", "worker"]);
+        assert_eq!(members[1].requirements(), "requirements/prod.txt");
+        assert_eq!(members[2].requirements(), "requirements.txt");
+        assert!(workspace_members(&root.path().join("tools")).is_err());
+
+        let report = |member: &str, error: Option<&str>| WorkspaceMemberReport {
+            member: member.to_string(),
+            path: PathBuf::from(member),
+            error: error.map(str::to_string),
+            packages: 3,
+            millis: 1200,
+        };
+        assert_eq!(
+            render_workspace_report(&[report("api", None), report("worker", Some("boom"))]),
+            "\nWorkspace summary:\n\
+             \x20 Member  Status  Packages  Duration\n\
+             \x20 api     ok             3     1.2 s\n\
+             \x20 worker  failed         -     1.2 s\n\
+             \n1 succeeded, 1 failed\n\
+             \nFailures:\n  worker: boom\n"
+        );
+    }
+}